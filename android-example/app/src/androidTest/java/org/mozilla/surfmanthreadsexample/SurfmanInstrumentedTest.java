@@ -26,6 +26,51 @@ public class SurfmanInstrumentedTest {
     private static native void testNewlyCreatedContextsAreNotCurrent();
     private static native void testSurfaceTextureBlitFramebuffer();
     private static native void testSurfaceTextureRightSideUp();
+    private static native void testSurfaceTextureMipmaps();
+    private static native void testLayeredSurfaces();
+    private static native void testCompositor();
+    private static native void testClearedSurfaceCreation();
+    private static native void testSurfaceFormatConversion();
+    private static native void testSurfaceOrigin();
+    private static native void testSurfaceAlphaMode();
+    private static native void testSurfaceColorspace();
+    private static native void testWidgetOutputInfoOfGenericSurface();
+    private static native void testSampleCountNegotiation();
+    private static native void testMultisampledContextSurfaceTexture();
+    private static native void testPresentToExternalFramebuffer();
+    private static native void testBindExternalFramebuffer();
+    private static native void testSurfaceFromTexture();
+    private static native void testSurfaceIntoPixels();
+    private static native void testDebugLabels();
+    private static native void testLifecycleObserver();
+    private static native void testDeviceEventHandler();
+    private static native void testMetricsSink();
+    private static native void testMemoryUsage();
+    private static native void testSurfaceLayoutRequirements();
+    private static native void testSurfaceSeed();
+    private static native void testSurfaceDestroyToken();
+    private static native void testSendableSurfaceTexture();
+    private static native void testDeviceCloneOnAnotherThread();
+    private static native void testSurfacePool();
+    private static native void testDeferredSurfaceDestruction();
+    private static native void testBatchSurfaceCreation();
+    private static native void testContextIdsPerConnection();
+    private static native void testContextDescriptorCache();
+    private static native void testContextDescriptorInterning();
+    private static native void testContextVersionRange();
+    private static native void testRequiredExtensions();
+    private static native void testNativeHandles();
+    private static native void testCreationTimings();
+    private static native void testFromCurrentContext();
+    private static native void testSurfaceCreationPreservesBindings();
+    private static native void testFlushContext();
+    private static native void testPresentWatchdog();
+    private static native void testSurfaceVisibility();
+    private static native void testSurfaceAttachmentState();
+    private static native void testMakeContextCurrentWithSurface();
+    private static native void testDisplays();
+    private static native void testAdapterForDisplay();
+    private static native void testInitWithConnection();
 
     static {
         System.loadLibrary("surfman_android_threads");
@@ -88,4 +133,229 @@ public class SurfmanInstrumentedTest {
     public void surfaceTextureRightSideUp() {
         testSurfaceTextureRightSideUp();
     }
+
+    @Test
+    public void surfaceTextureMipmaps() {
+        testSurfaceTextureMipmaps();
+    }
+
+    @Test
+    public void layeredSurfaces() {
+        testLayeredSurfaces();
+    }
+
+    @Test
+    public void compositor() {
+        testCompositor();
+    }
+
+    @Test
+    public void clearedSurfaceCreation() {
+        testClearedSurfaceCreation();
+    }
+
+    @Test
+    public void surfaceFormatConversion() {
+        testSurfaceFormatConversion();
+    }
+
+    @Test
+    public void surfaceOrigin() {
+        testSurfaceOrigin();
+    }
+
+    @Test
+    public void surfaceAlphaMode() {
+        testSurfaceAlphaMode();
+    }
+
+    @Test
+    public void surfaceColorspace() {
+        testSurfaceColorspace();
+    }
+
+    @Test
+    public void widgetOutputInfoOfGenericSurface() {
+        testWidgetOutputInfoOfGenericSurface();
+    }
+
+    @Test
+    public void sampleCountNegotiation() {
+        testSampleCountNegotiation();
+    }
+
+    @Test
+    public void multisampledContextSurfaceTexture() {
+        testMultisampledContextSurfaceTexture();
+    }
+
+    @Test
+    public void presentToExternalFramebuffer() {
+        testPresentToExternalFramebuffer();
+    }
+
+    @Test
+    public void bindExternalFramebuffer() {
+        testBindExternalFramebuffer();
+    }
+
+    @Test
+    public void surfaceFromTexture() {
+        testSurfaceFromTexture();
+    }
+
+    @Test
+    public void surfaceIntoPixels() {
+        testSurfaceIntoPixels();
+    }
+
+    @Test
+    public void debugLabels() {
+        testDebugLabels();
+    }
+
+    @Test
+    public void lifecycleObserver() {
+        testLifecycleObserver();
+    }
+
+    @Test
+    public void deviceEventHandler() {
+        testDeviceEventHandler();
+    }
+
+    @Test
+    public void metricsSink() {
+        testMetricsSink();
+    }
+
+    @Test
+    public void memoryUsage() {
+        testMemoryUsage();
+    }
+
+    @Test
+    public void surfaceLayoutRequirements() {
+        testSurfaceLayoutRequirements();
+    }
+
+    @Test
+    public void surfaceSeed() {
+        testSurfaceSeed();
+    }
+
+    @Test
+    public void surfaceDestroyToken() {
+        testSurfaceDestroyToken();
+    }
+
+    @Test
+    public void sendableSurfaceTexture() {
+        testSendableSurfaceTexture();
+    }
+
+    @Test
+    public void deviceCloneOnAnotherThread() {
+        testDeviceCloneOnAnotherThread();
+    }
+
+    @Test
+    public void surfacePool() {
+        testSurfacePool();
+    }
+
+    @Test
+    public void deferredSurfaceDestruction() {
+        testDeferredSurfaceDestruction();
+    }
+
+    @Test
+    public void batchSurfaceCreation() {
+        testBatchSurfaceCreation();
+    }
+
+    @Test
+    public void contextIdsPerConnection() {
+        testContextIdsPerConnection();
+    }
+
+    @Test
+    public void contextDescriptorCache() {
+        testContextDescriptorCache();
+    }
+
+    @Test
+    public void contextDescriptorInterning() {
+        testContextDescriptorInterning();
+    }
+
+    @Test
+    public void contextVersionRange() {
+        testContextVersionRange();
+    }
+
+    @Test
+    public void requiredExtensions() {
+        testRequiredExtensions();
+    }
+
+    @Test
+    public void nativeHandles() {
+        testNativeHandles();
+    }
+
+    @Test
+    public void creationTimings() {
+        testCreationTimings();
+    }
+
+    @Test
+    public void fromCurrentContext() {
+        testFromCurrentContext();
+    }
+
+    @Test
+    public void surfaceCreationPreservesBindings() {
+        testSurfaceCreationPreservesBindings();
+    }
+
+    @Test
+    public void flushContext() {
+        testFlushContext();
+    }
+
+    @Test
+    public void presentWatchdog() {
+        testPresentWatchdog();
+    }
+
+    @Test
+    public void surfaceVisibility() {
+        testSurfaceVisibility();
+    }
+
+    @Test
+    public void surfaceAttachmentState() {
+        testSurfaceAttachmentState();
+    }
+
+    @Test
+    public void makeContextCurrentWithSurface() {
+        testMakeContextCurrentWithSurface();
+    }
+
+    @Test
+    public void displays() {
+        testDisplays();
+    }
+
+    @Test
+    public void adapterForDisplay() {
+        testAdapterForDisplay();
+    }
+
+    @Test
+    public void initWithConnection() {
+        testInitWithConnection();
+    }
 }
@@ -151,6 +151,366 @@ pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInst
     tests::test_surface_texture_right_side_up();
 }
 
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSurfaceTextureMipmaps(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_surface_texture_mipmaps();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testLayeredSurfaces(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_layered_surfaces();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testCompositor(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_compositor();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testClearedSurfaceCreation(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_cleared_surface_creation();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSurfaceFormatConversion(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_surface_format_conversion();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSurfaceOrigin(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_surface_origin();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSurfaceAlphaMode(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_surface_alpha_mode();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSurfaceColorspace(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_surface_colorspace();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testWidgetOutputInfoOfGenericSurface(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_widget_output_info_of_generic_surface();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSampleCountNegotiation(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_sample_count_negotiation();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testMultisampledContextSurfaceTexture(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_multisampled_context_surface_texture();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testPresentToExternalFramebuffer(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_present_to_external_framebuffer();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testBindExternalFramebuffer(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_bind_external_framebuffer();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSurfaceFromTexture(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_surface_from_texture();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSurfaceIntoPixels(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_surface_into_pixels();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testDebugLabels(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_debug_labels();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testLifecycleObserver(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_lifecycle_observer();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testDeviceEventHandler(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_device_event_handler();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testMetricsSink(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_metrics_sink();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testMemoryUsage(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_memory_usage();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSurfaceLayoutRequirements(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_surface_layout_requirements();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSurfaceSeed(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_surface_seed();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSurfaceDestroyToken(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_surface_destroy_token();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSendableSurfaceTexture(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_sendable_surface_texture();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testDeviceCloneOnAnotherThread(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_device_clone_on_another_thread();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSurfacePool(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_surface_pool();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testDeferredSurfaceDestruction(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_deferred_surface_destruction();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testBatchSurfaceCreation(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_batch_surface_creation();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testContextIdsPerConnection(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_context_ids_per_connection();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testContextDescriptorCache(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_context_descriptor_cache();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testContextDescriptorInterning(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_context_descriptor_interning();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testContextVersionRange(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_context_version_range();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testRequiredExtensions(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_required_extensions();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testNativeHandles(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_native_handles();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testCreationTimings(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_creation_timings();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testFromCurrentContext(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_from_current_context();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSurfaceCreationPreservesBindings(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_surface_creation_preserves_bindings();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testFlushContext(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_flush_context();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testPresentWatchdog(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_present_watchdog();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSurfaceVisibility(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_surface_visibility();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSurfaceAttachmentState(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_surface_attachment_state();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testMakeContextCurrentWithSurface(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_make_context_current_with_surface();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testDisplays(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_displays();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testAdapterForDisplay(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_adapter_for_display();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testInitWithConnection(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_init_with_connection();
+}
+
 struct JavaResourceLoader {
     loader: GlobalRef,
     vm: JavaVM,
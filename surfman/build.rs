@@ -15,8 +15,11 @@ fn main() {
         windows: { target_os = "windows" },
         macos: { target_os = "macos" },
         android: { target_os = "android" },
-        // TODO: is `target_os = "linux"` the same as the following check?
-        linux: { all(unix, not(any(macos, android))) },
+        ohos: { target_env = "ohos" },
+        redox: { target_os = "redox" },
+        // Unlike `target_os = "linux"`, this also covers FreeBSD, OpenBSD, and the other BSDs, which
+        // use the same X11, Wayland, and EGL backends.
+        linux: { all(unix, not(any(macos, android, ohos, redox))) },
 
         // Features:
         // Here we collect the features that are only valid on certain platforms and
@@ -30,6 +33,7 @@ fn main() {
     }
 
     let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap();
+    let target_env = env::var("CARGO_CFG_TARGET_ENV").unwrap_or_default();
     let target_family = env::var("CARGO_CFG_TARGET_FAMILY").ok();
     let dest = PathBuf::from(&env::var("OUT_DIR").unwrap());
 
@@ -44,7 +48,7 @@ fn main() {
     }
 
     // Generate GL bindings.
-    if target_os == "android" {
+    if target_os == "android" || target_env == "ohos" {
         let mut file = File::create(&dest.join("gl_bindings.rs")).unwrap();
         let registry = Registry::new(Api::Gles2, (3, 0), Profile::Core, Fallbacks::All, []);
         registry.write_bindings(StructGenerator, &mut file).unwrap();
@@ -15,7 +15,7 @@ use std::mem;
 use std::path::Path;
 use std::slice;
 use surfman::{Connection, ContextAttributeFlags, ContextAttributes, GLApi, GLVersion};
-use surfman::{SurfaceAccess, SurfaceType};
+use surfman::{SurfaceAccess, SurfaceAlphaMode, SurfaceType, SurfaceUsage};
 
 mod common;
 
@@ -102,7 +102,10 @@ fn main() {
 
     let context_attributes = ContextAttributes {
         version: GLVersion::new(3, 3),
+        max_version: None,
         flags: ContextAttributeFlags::empty(),
+        required_extensions: vec![],
+        samples: 0,
     };
     let context_descriptor = device
         .create_context_descriptor(&context_attributes)
@@ -112,6 +115,8 @@ fn main() {
         .create_surface(
             &context,
             SurfaceAccess::GPUOnly,
+            SurfaceUsage::default(),
+            SurfaceAlphaMode::default(),
             SurfaceType::Generic {
                 size: Size2D::new(FRAMEBUFFER_WIDTH, FRAMEBUFFER_HEIGHT),
             },
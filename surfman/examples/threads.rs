@@ -8,7 +8,8 @@ use euclid::default::{Point2D, Rect, Size2D, Vector2D};
 use gl::types::{GLchar, GLenum, GLint, GLuint, GLvoid};
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::thread;
-use surfman::{declare_surfman, SurfaceAccess, SurfaceTexture, SurfaceType};
+use surfman::SurfaceUsage;
+use surfman::{declare_surfman, SurfaceAccess, SurfaceAlphaMode, SurfaceTexture, SurfaceType};
 use surfman::{Adapter, Connection, Context, ContextDescriptor, Device, GLApi, Surface};
 
 #[cfg(not(target_os = "android"))]
@@ -107,7 +108,10 @@ fn main() {
 
     let context_attributes = ContextAttributes {
         version: GLVersion::new(3, 0),
+        max_version: None,
         flags: ContextAttributeFlags::ALPHA,
+        required_extensions: vec![],
+        samples: 0,
     };
     let context_descriptor = device
         .create_context_descriptor(&context_attributes)
@@ -116,7 +120,13 @@ fn main() {
     let surface_type = SurfaceType::Widget { native_widget };
     let mut context = device.create_context(&context_descriptor, None).unwrap();
     let surface = device
-        .create_surface(&context, SurfaceAccess::GPUOnly, surface_type)
+        .create_surface(
+            &context,
+            SurfaceAccess::GPUOnly,
+            SurfaceUsage::default(),
+            SurfaceAlphaMode::Opaque,
+            surface_type,
+        )
         .unwrap();
     device
         .bind_surface_to_context(&mut context, surface)
@@ -405,7 +415,13 @@ fn worker_thread(
     let mut device = connection.create_device(&adapter).unwrap();
     let mut context = device.create_context(&context_descriptor, None).unwrap();
     let surface = device
-        .create_surface(&context, SurfaceAccess::GPUOnly, surface_type)
+        .create_surface(
+            &context,
+            SurfaceAccess::GPUOnly,
+            SurfaceUsage::default(),
+            SurfaceAlphaMode::default(),
+            surface_type,
+        )
         .unwrap();
     device
         .bind_surface_to_context(&mut context, surface)
@@ -440,7 +456,13 @@ fn worker_thread(
     let surface_type = SurfaceType::Generic { size };
     let surface = Some(
         device
-            .create_surface(&context, SurfaceAccess::GPUOnly, surface_type)
+            .create_surface(
+                &context,
+                SurfaceAccess::GPUOnly,
+                SurfaceUsage::default(),
+                SurfaceAlphaMode::default(),
+                surface_type,
+            )
             .unwrap(),
     );
     worker_to_main_sender
@@ -0,0 +1,224 @@
+// surfman/surfman/src/capture.rs
+//
+//! Descriptions of exported surfaces for screen-capture and streaming pipelines built on PipeWire
+//! or GStreamer.
+//!
+//! A surface exported with `Device::export_dma_buf()` is wrapped in a `CaptureBuffer`, which
+//! describes its format in the terms that PipeWire (`spa_video_format` plus a DRM modifier) and
+//! GStreamer (`video/x-raw(memory:DMABuf)` caps with a `drm-format` field) negotiate in, and its
+//! planes in the terms of `spa_data` and `GstVideoMeta`. The consumer reads the surface's storage
+//! directly; no pixels are copied.
+//!
+//! The format and modifier of an exported surface are chosen by the driver when the surface is
+//! created, so they should be offered to the consumer as the only choice, and the buffer should
+//! only be sent if the consumer accepts them; see `CaptureFormat::is_accepted_by()`.
+//!
+//! Surfman doesn't link to PipeWire or GStreamer; these are only the raw values to pass to them.
+
+use crate::platform::generic::egl::ffi::DRM_FORMAT_MOD_INVALID;
+use crate::platform::generic::egl::surface::EGLDmaBuf;
+use crate::Error;
+
+use euclid::default::Size2D;
+use std::os::unix::io::RawFd;
+
+/// `DRM_FORMAT_MOD_LINEAR`: the modifier of images stored row by row, with no tiling or
+/// compression.
+pub const DRM_FORMAT_MOD_LINEAR: u64 = 0;
+
+/// `SPA_DATA_DmaBuf`: the `spa_data` type of buffers backed by DMA-BUF file descriptors.
+pub const SPA_DATA_DMA_BUF: u32 = 3;
+
+// The DRM fourccs of the formats that surfaces may be exported in, along with the equivalent
+// `spa_video_format` and GStreamer video format. Both name formats by their byte order in memory,
+// while DRM fourccs name them by their bit order in a little-endian word.
+const FORMATS: [(u32, u32, &str); 4] = [
+    // DRM_FORMAT_ABGR8888, SPA_VIDEO_FORMAT_RGBA
+    (0x3432_4241, 11, "RGBA"),
+    // DRM_FORMAT_XBGR8888, SPA_VIDEO_FORMAT_RGBx
+    (0x3432_4258, 7, "RGBx"),
+    // DRM_FORMAT_ARGB8888, SPA_VIDEO_FORMAT_BGRA
+    (0x3432_5241, 12, "BGRA"),
+    // DRM_FORMAT_XRGB8888, SPA_VIDEO_FORMAT_BGRx
+    (0x3432_5258, 8, "BGRx"),
+];
+
+/// The format of an exported surface.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CaptureFormat {
+    /// The DRM fourcc code of the pixel format, such as `DRM_FORMAT_ABGR8888`.
+    pub fourcc: u32,
+    /// The DRM format modifier describing the memory layout of the image, or
+    /// `DRM_FORMAT_MOD_INVALID` if the driver didn't report one.
+    pub modifier: u64,
+    /// The size of the image, in pixels.
+    pub size: Size2D<i32>,
+}
+
+/// A plane of an exported surface.
+///
+/// For PipeWire, each plane fills one `spa_data` of type `SPA_DATA_DmaBuf`: `fd` and
+/// `buffer_size` go into `fd` and `maxsize`, with a `mapoffset` of 0, and `offset` and `stride`
+/// go into its `spa_chunk`. For GStreamer, each plane is a `GstMemory` wrapping `fd` with
+/// `gst_dmabuf_allocator_alloc()`, and `offset` and `stride` go into the `GstVideoMeta`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CapturePlane {
+    /// The DMA-BUF file descriptor holding this plane. It's owned by the `CaptureBuffer`;
+    /// duplicate it for consumers that take ownership, such as `gst_dmabuf_allocator_alloc()`.
+    pub fd: RawFd,
+    /// The offset of the plane within the buffer, in bytes.
+    pub offset: u32,
+    /// The number of bytes between the starts of consecutive rows of the plane.
+    pub stride: u32,
+    /// The size of the whole buffer that the file descriptor refers to, in bytes.
+    pub buffer_size: u64,
+}
+
+/// An exported surface, ready to be handed to a PipeWire stream or a GStreamer pipeline.
+pub struct CaptureBuffer {
+    format: CaptureFormat,
+    planes: Vec<CapturePlane>,
+    dma_buf: EGLDmaBuf,
+}
+
+impl CaptureFormat {
+    /// Returns the `spa_video_format` of this format, or `None` if PipeWire has no equivalent.
+    pub fn spa_video_format(&self) -> Option<u32> {
+        FORMATS
+            .iter()
+            .find(|&&(fourcc, _, _)| fourcc == self.fourcc)
+            .map(|&(_, spa_video_format, _)| spa_video_format)
+    }
+
+    /// Returns the value of the `SPA_FORMAT_VIDEO_modifier` property to offer, or `None` if the
+    /// modifier is implicit, in which case the property must be left out.
+    ///
+    /// The property should be offered with the `SPA_POD_PROP_FLAG_MANDATORY` flag, so that
+    /// consumers that can't import DMA-BUFs with this modifier reject the format.
+    pub fn spa_modifier(&self) -> Option<i64> {
+        if self.modifier == DRM_FORMAT_MOD_INVALID {
+            None
+        } else {
+            Some(self.modifier as i64)
+        }
+    }
+
+    /// Returns the GStreamer video format of this format, such as `"RGBA"`, or `None` if
+    /// GStreamer has no equivalent.
+    ///
+    /// This is the `format` field of caps for GStreamer versions before 1.24, which only
+    /// support linear DMA-BUFs.
+    pub fn gst_video_format(&self) -> Option<&'static str> {
+        FORMATS
+            .iter()
+            .find(|&&(fourcc, _, _)| fourcc == self.fourcc)
+            .map(|&(_, _, gst_video_format)| gst_video_format)
+    }
+
+    /// Returns the value of the `drm-format` caps field, such as `"AB24:0x0100000000000001"`, or
+    /// `None` if the modifier is implicit, which GStreamer can't express.
+    ///
+    /// Linear images have no modifier suffix, as GStreamer expects.
+    pub fn gst_drm_format(&self) -> Option<String> {
+        if self.modifier == DRM_FORMAT_MOD_INVALID {
+            return None;
+        }
+        let fourcc: String = self
+            .fourcc
+            .to_le_bytes()
+            .iter()
+            .map(|&byte| byte as char)
+            .collect();
+        if self.modifier == DRM_FORMAT_MOD_LINEAR {
+            Some(fourcc)
+        } else {
+            Some(format!("{}:0x{:016x}", fourcc, self.modifier))
+        }
+    }
+
+    /// Returns GStreamer caps describing this format, for GStreamer 1.24 or later, or `None` if
+    /// the modifier is implicit.
+    pub fn gst_caps(&self) -> Option<String> {
+        let drm_format = self.gst_drm_format()?;
+        Some(format!(
+            "video/x-raw(memory:DMABuf), format=(string)DMA_DRM, drm-format=(string){}, \
+             width=(int){}, height=(int){}",
+            drm_format, self.size.width, self.size.height
+        ))
+    }
+
+    /// Returns true if a consumer that can import the given fourcc with the given modifiers can
+    /// take this format.
+    ///
+    /// An implicit modifier is only accepted by consumers that list `DRM_FORMAT_MOD_INVALID`, as
+    /// PipeWire consumers do when they support implicit modifiers.
+    pub fn is_accepted_by(&self, fourcc: u32, modifiers: &[u64]) -> bool {
+        fourcc == self.fourcc && modifiers.contains(&self.modifier)
+    }
+}
+
+impl CaptureBuffer {
+    /// Wraps an exported surface of the given size.
+    ///
+    /// Fails with `Error::Failed` if the size of the DMA-BUFs can't be determined.
+    pub fn new(dma_buf: EGLDmaBuf, size: &Size2D<i32>) -> Result<CaptureBuffer, Error> {
+        let mut planes = Vec::with_capacity(dma_buf.planes.len());
+        for plane in &dma_buf.planes {
+            let buffer_size = unsafe { libc::lseek(plane.fd, 0, libc::SEEK_END) };
+            if buffer_size < 0 {
+                error!("Failed to determine the size of a DMA-BUF!");
+                return Err(Error::Failed);
+            }
+            planes.push(CapturePlane {
+                fd: plane.fd,
+                offset: plane.offset,
+                stride: plane.stride,
+                buffer_size: buffer_size as u64,
+            });
+        }
+
+        Ok(CaptureBuffer {
+            format: CaptureFormat {
+                fourcc: dma_buf.fourcc,
+                modifier: dma_buf.modifier,
+                size: *size,
+            },
+            planes,
+            dma_buf,
+        })
+    }
+
+    /// Returns the format of the buffer, to offer to the consumer.
+    #[inline]
+    pub fn format(&self) -> &CaptureFormat {
+        &self.format
+    }
+
+    /// Returns the planes of the buffer, in order.
+    #[inline]
+    pub fn planes(&self) -> &[CapturePlane] {
+        &self.planes
+    }
+
+    /// Returns the offsets and strides of the planes, as `gst_buffer_add_video_meta_full()`
+    /// takes them.
+    pub fn gst_video_meta_layout(&self) -> (Vec<usize>, Vec<i32>) {
+        let offsets = self
+            .planes
+            .iter()
+            .map(|plane| plane.offset as usize)
+            .collect();
+        let strides = self
+            .planes
+            .iter()
+            .map(|plane| plane.stride as i32)
+            .collect();
+        (offsets, strides)
+    }
+
+    /// Unwraps the exported surface, handing the file descriptors back to the caller.
+    #[inline]
+    pub fn into_dma_buf(self) -> EGLDmaBuf {
+        self.dma_buf
+    }
+}
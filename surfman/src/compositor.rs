@@ -0,0 +1,540 @@
+// surfman/surfman/src/compositor.rs
+//
+//! A simple compositor that stacks generic surfaces into the surface of a context.
+//!
+//! This is meant for embedders that only need to show a few layers, such as a UI over some
+//! content, and don't want to pull in a full renderer to do so.
+
+use crate::device::Device;
+use crate::gl;
+use crate::gl::types::{GLboolean, GLchar, GLenum, GLfloat, GLint, GLsizei, GLuint};
+use crate::gl_utils::BindingsGuard;
+use crate::{Error, GLApi, GLVersion, Gl, SurfaceOrigin};
+
+use euclid::default::{Size2D, Transform2D};
+use std::ffi::CString;
+use std::mem;
+use std::os::raw::c_void;
+use std::ptr;
+use std::thread;
+
+// Not in the OpenGL ES bindings, though no backend that uses them has rectangle textures.
+const GL_TEXTURE_BINDING_RECTANGLE: GLenum = 0x84f6;
+
+// A unit quad, drawn as a triangle strip.
+static QUAD_VERTICES: [f32; 8] = [0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 1.0];
+
+static VERTEX_SHADER: &str = "
+ATTRIBUTE vec2 aPosition;
+uniform mat3 uTransform;
+uniform vec2 uLayerSize;
+uniform vec2 uTargetSize;
+uniform vec2 uTexCoordOffset;
+uniform vec2 uTexCoordScale;
+VARYING vec2 vTexCoord;
+void main() {
+    vec3 position = uTransform * vec3(aPosition * uLayerSize, 1.0);
+    vTexCoord = uTexCoordOffset + aPosition * uTexCoordScale;
+    gl_Position = vec4(position.xy / uTargetSize * 2.0 - 1.0, 0.0, 1.0);
+}
+";
+
+static FRAGMENT_SHADER: &str = "
+uniform SAMPLER uTexture;
+uniform float uOpacity;
+VARYING vec2 vTexCoord;
+void main() {
+    FRAG_COLOR = TEXTURE(uTexture, vTexCoord) * uOpacity;
+}
+";
+
+/// One surface to be drawn by `Compositor::composite()`.
+pub struct CompositorLayer<'a, SurfaceTexture> {
+    /// A surface texture wrapping the generic surface to draw, created for the compositing
+    /// context.
+    pub surface_texture: &'a SurfaceTexture,
+    /// The size of the surface, in device pixels.
+    pub size: Size2D<i32>,
+    /// Which way up the contents of the surface are, as reported by `Device::surface_info()`.
+    /// Surfaces with their origin at the upper left corner are flipped so that they show upright.
+    pub origin: SurfaceOrigin,
+    /// The transform from the pixels of the surface to the pixels of the target, both with their
+    /// origin at the lower left corner as in OpenGL.
+    pub transform: Transform2D<f32>,
+    /// The opacity of the layer, from 0.0 to 1.0.
+    pub opacity: f32,
+}
+
+/// Draws generic surfaces into the surface bound to a context, stacked back to front.
+///
+/// The compositor belongs to the context that it was created with, and must be destroyed with
+/// `destroy()` while that context is still alive.
+pub struct Compositor {
+    gl: Gl,
+    program: GLuint,
+    vertex_buffer: GLuint,
+    vertex_array: GLuint,
+    texture_target: GLenum,
+    uniforms: Uniforms,
+    destroyed: bool,
+}
+
+struct Uniforms {
+    transform: GLint,
+    layer_size: GLint,
+    target_size: GLint,
+    tex_coord_offset: GLint,
+    tex_coord_scale: GLint,
+    texture: GLint,
+    opacity: GLint,
+}
+
+// The state outside of framebuffer and texture bindings that drawing changes, saved beforehand so
+// that it can be restored afterward.
+struct SavedState {
+    program: GLint,
+    viewport: [GLint; 4],
+    clear_color: [GLfloat; 4],
+    blend: GLboolean,
+    depth_test: GLboolean,
+    scissor_test: GLboolean,
+    blend_func: [GLint; 4],
+    active_texture: GLint,
+    array_buffer: GLint,
+    vertex_arrays: SavedVertexArrays,
+}
+
+enum SavedVertexArrays {
+    VertexArray(GLint),
+    // Without vertex array objects, the attribute that the compositor uses is set directly.
+    Attribute {
+        enabled: GLint,
+        buffer: GLint,
+        size: GLint,
+        kind: GLint,
+        normalized: GLint,
+        stride: GLint,
+        pointer: *mut c_void,
+    },
+}
+
+impl Compositor {
+    /// Creates a compositor that draws with the given context.
+    ///
+    /// The compositor samples layers through the texture target of `surface_gl_texture_target()`,
+    /// so it can only draw surface textures bound to that target. The context must be current.
+    pub fn new<D>(device: &D, context: &D::Context) -> Result<Compositor, Error>
+    where
+        D: Device,
+    {
+        let gl = Gl::load_with(|symbol| device.get_proc_address(context, symbol));
+        let texture_target = device.surface_gl_texture_target();
+        let version = GLVersion::current(&gl);
+
+        let (vertex_header, fragment_header) = match device.gl_api() {
+            GLApi::GLES => (
+                "#version 100\n#define ATTRIBUTE attribute\n#define VARYING varying\n",
+                "#version 100\nprecision mediump float;\n#define VARYING varying\n\
+                 #define FRAG_COLOR gl_FragColor\n#define SAMPLER sampler2D\n\
+                 #define TEXTURE texture2D\n",
+            ),
+            GLApi::GL if (version.major, version.minor) >= (3, 2) => (
+                "#version 150\n#define ATTRIBUTE in\n#define VARYING out\n",
+                if texture_target == gl::TEXTURE_2D {
+                    "#version 150\n#define VARYING in\nout vec4 oFragColor;\n\
+                     #define FRAG_COLOR oFragColor\n#define SAMPLER sampler2D\n\
+                     #define TEXTURE texture\n"
+                } else {
+                    "#version 150\n#define VARYING in\nout vec4 oFragColor;\n\
+                     #define FRAG_COLOR oFragColor\n#define SAMPLER sampler2DRect\n\
+                     #define TEXTURE texture\n"
+                },
+            ),
+            GLApi::GL => (
+                "#version 120\n#define ATTRIBUTE attribute\n#define VARYING varying\n",
+                if texture_target == gl::TEXTURE_2D {
+                    "#version 120\n#define VARYING varying\n#define FRAG_COLOR gl_FragColor\n\
+                     #define SAMPLER sampler2D\n#define TEXTURE texture2D\n"
+                } else {
+                    "#version 120\n#extension GL_ARB_texture_rectangle : require\n\
+                     #define VARYING varying\n#define FRAG_COLOR gl_FragColor\n\
+                     #define SAMPLER sampler2DRect\n#define TEXTURE texture2DRect\n"
+                },
+            ),
+        };
+
+        unsafe {
+            let vertex_shader =
+                compile_shader(&gl, gl::VERTEX_SHADER, vertex_header, VERTEX_SHADER)?;
+            let fragment_shader =
+                match compile_shader(&gl, gl::FRAGMENT_SHADER, fragment_header, FRAGMENT_SHADER) {
+                    Ok(fragment_shader) => fragment_shader,
+                    Err(err) => {
+                        gl.DeleteShader(vertex_shader);
+                        return Err(err);
+                    }
+                };
+
+            let program = gl.CreateProgram();
+            gl.AttachShader(program, vertex_shader);
+            gl.AttachShader(program, fragment_shader);
+            gl.BindAttribLocation(program, 0, b"aPosition\0".as_ptr() as *const GLchar);
+            gl.LinkProgram(program);
+            gl.DeleteShader(vertex_shader);
+            gl.DeleteShader(fragment_shader);
+
+            let mut link_status = 0;
+            gl.GetProgramiv(program, gl::LINK_STATUS, &mut link_status);
+            if link_status != gl::TRUE as GLint {
+                error!("Failed to link the compositor program!");
+                gl.DeleteProgram(program);
+                return Err(Error::Failed);
+            }
+
+            let uniforms = Uniforms {
+                transform: uniform_location(&gl, program, b"uTransform\0"),
+                layer_size: uniform_location(&gl, program, b"uLayerSize\0"),
+                target_size: uniform_location(&gl, program, b"uTargetSize\0"),
+                tex_coord_offset: uniform_location(&gl, program, b"uTexCoordOffset\0"),
+                tex_coord_scale: uniform_location(&gl, program, b"uTexCoordScale\0"),
+                texture: uniform_location(&gl, program, b"uTexture\0"),
+                opacity: uniform_location(&gl, program, b"uOpacity\0"),
+            };
+
+            let mut vertex_buffer = 0;
+            gl.GenBuffers(1, &mut vertex_buffer);
+            gl.BindBuffer(gl::ARRAY_BUFFER, vertex_buffer);
+            gl.BufferData(
+                gl::ARRAY_BUFFER,
+                mem::size_of_val(&QUAD_VERTICES) as isize,
+                QUAD_VERTICES.as_ptr() as *const _,
+                gl::STATIC_DRAW,
+            );
+            gl.BindBuffer(gl::ARRAY_BUFFER, 0);
+
+            // Core profiles can't draw without a vertex array object, so use one wherever they're
+            // available.
+            let mut vertex_array = 0;
+            if version.major >= 3 {
+                gl.GenVertexArrays(1, &mut vertex_array);
+                gl.BindVertexArray(vertex_array);
+                gl.BindBuffer(gl::ARRAY_BUFFER, vertex_buffer);
+                gl.VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, 0, ptr::null());
+                gl.EnableVertexAttribArray(0);
+                gl.BindVertexArray(0);
+                gl.BindBuffer(gl::ARRAY_BUFFER, 0);
+            }
+
+            Ok(Compositor {
+                gl,
+                program,
+                vertex_buffer,
+                vertex_array,
+                texture_target,
+                uniforms,
+                destroyed: false,
+            })
+        }
+    }
+
+    /// Draws the given layers, back to front, into the surface bound to the context.
+    ///
+    /// If `clear_color` is supplied, the surface is first cleared to that color; otherwise, the
+    /// layers are drawn over its existing contents. Layers are blended with premultiplied alpha,
+    /// and pixels that no layer covers are left alone.
+    ///
+    /// The surfaces of the layers must have been unbound from the contexts that rendered to them
+    /// before being wrapped in surface textures, which makes their rendering visible here. When
+    /// this returns, the drawing has been flushed, so the target surface can be presented right
+    /// away. The state of the context that drawing changes, such as its program, blending,
+    /// viewport, and bindings, is restored afterward.
+    ///
+    /// Layers whose surface textures are bound to another target than the compositor samples
+    /// from, such as those of layered surfaces or external textures, return
+    /// `Error::IncompatibleSurfaceTexture` without drawing anything.
+    pub fn composite<D>(
+        &mut self,
+        device: &D,
+        context: &D::Context,
+        layers: &[CompositorLayer<D::SurfaceTexture>],
+        clear_color: Option<[f32; 4]>,
+    ) -> Result<(), Error>
+    where
+        D: Device,
+    {
+        let target = match device.context_surface_info(context)? {
+            Some(target) => target,
+            None => return Err(Error::NoWidgetAttached),
+        };
+
+        if layers.iter().any(|layer| {
+            device.surface_texture_binding_target(layer.surface_texture) != self.texture_target
+        }) {
+            return Err(Error::IncompatibleSurfaceTexture);
+        }
+
+        let gl = &self.gl;
+        unsafe {
+            let saved_state = SavedState::new(gl, self.vertex_array != 0);
+            gl.ActiveTexture(gl::TEXTURE0);
+            let texture_binding = if self.texture_target == gl::TEXTURE_2D {
+                gl::TEXTURE_BINDING_2D
+            } else {
+                GL_TEXTURE_BINDING_RECTANGLE
+            };
+            let mut bindings =
+                BindingsGuard::new(gl).with_texture(self.texture_target, texture_binding);
+
+            bindings.bind_framebuffer(gl::FRAMEBUFFER, target.framebuffer_object);
+            gl.Viewport(0, 0, target.size.width, target.size.height);
+
+            if let Some([r, g, b, a]) = clear_color {
+                gl.ClearColor(r, g, b, a);
+                gl.Clear(gl::COLOR_BUFFER_BIT);
+            }
+
+            gl.UseProgram(self.program);
+            if self.vertex_array != 0 {
+                gl.BindVertexArray(self.vertex_array);
+            } else {
+                gl.BindBuffer(gl::ARRAY_BUFFER, self.vertex_buffer);
+                gl.VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, 0, ptr::null());
+                gl.EnableVertexAttribArray(0);
+            }
+
+            gl.Disable(gl::DEPTH_TEST);
+            gl.Disable(gl::SCISSOR_TEST);
+            gl.Enable(gl::BLEND);
+            gl.BlendFunc(gl::ONE, gl::ONE_MINUS_SRC_ALPHA);
+
+            gl.Uniform1i(self.uniforms.texture, 0);
+            gl.Uniform2f(
+                self.uniforms.target_size,
+                target.size.width as f32,
+                target.size.height as f32,
+            );
+
+            for layer in layers {
+                let transform = &layer.transform;
+                let matrix = [
+                    transform.m11,
+                    transform.m12,
+                    0.0,
+                    transform.m21,
+                    transform.m22,
+                    0.0,
+                    transform.m31,
+                    transform.m32,
+                    1.0,
+                ];
+                let (width, height) = (layer.size.width as f32, layer.size.height as f32);
+
+                // Rectangle textures are addressed in pixels rather than from 0 to 1.
+                let (s_scale, t_scale) = if self.texture_target == gl::TEXTURE_2D {
+                    (1.0, 1.0)
+                } else {
+                    (width, height)
+                };
+                let (t_offset, t_scale) = match layer.origin {
+                    SurfaceOrigin::BottomLeft => (0.0, t_scale),
+                    SurfaceOrigin::TopLeft => (t_scale, -t_scale),
+                };
+
+                bindings.bind_texture(device.surface_texture_object(layer.surface_texture));
+                gl.UniformMatrix3fv(self.uniforms.transform, 1, gl::FALSE, matrix.as_ptr());
+                gl.Uniform2f(self.uniforms.layer_size, width, height);
+                gl.Uniform2f(self.uniforms.tex_coord_offset, 0.0, t_offset);
+                gl.Uniform2f(self.uniforms.tex_coord_scale, s_scale, t_scale);
+                gl.Uniform1f(self.uniforms.opacity, layer.opacity.clamp(0.0, 1.0));
+                gl.DrawArrays(gl::TRIANGLE_STRIP, 0, 4);
+            }
+            gl.Flush();
+
+            drop(bindings);
+            saved_state.restore(gl);
+        }
+
+        Ok(())
+    }
+
+    /// Destroys the compositor.
+    ///
+    /// The context that the compositor was created with must be current.
+    pub fn destroy(&mut self) {
+        if self.destroyed {
+            return;
+        }
+
+        let gl = &self.gl;
+        unsafe {
+            if self.vertex_array != 0 {
+                gl.DeleteVertexArrays(1, &self.vertex_array);
+            }
+            gl.DeleteBuffers(1, &self.vertex_buffer);
+            gl.DeleteProgram(self.program);
+        }
+        self.destroyed = true;
+    }
+}
+
+impl Drop for Compositor {
+    fn drop(&mut self) {
+        if !self.destroyed && !thread::panicking() {
+            panic!("Should have destroyed the compositor first with `destroy()`!")
+        }
+    }
+}
+
+impl SavedState {
+    unsafe fn new(gl: &Gl, has_vertex_arrays: bool) -> SavedState {
+        let mut state = SavedState {
+            program: 0,
+            viewport: [0; 4],
+            clear_color: [0.0; 4],
+            blend: gl.IsEnabled(gl::BLEND),
+            depth_test: gl.IsEnabled(gl::DEPTH_TEST),
+            scissor_test: gl.IsEnabled(gl::SCISSOR_TEST),
+            blend_func: [0; 4],
+            active_texture: 0,
+            array_buffer: 0,
+            vertex_arrays: SavedVertexArrays::VertexArray(0),
+        };
+        gl.GetIntegerv(gl::CURRENT_PROGRAM, &mut state.program);
+        gl.GetIntegerv(gl::VIEWPORT, state.viewport.as_mut_ptr());
+        gl.GetFloatv(gl::COLOR_CLEAR_VALUE, state.clear_color.as_mut_ptr());
+        gl.GetIntegerv(gl::BLEND_SRC_RGB, &mut state.blend_func[0]);
+        gl.GetIntegerv(gl::BLEND_DST_RGB, &mut state.blend_func[1]);
+        gl.GetIntegerv(gl::BLEND_SRC_ALPHA, &mut state.blend_func[2]);
+        gl.GetIntegerv(gl::BLEND_DST_ALPHA, &mut state.blend_func[3]);
+        gl.GetIntegerv(gl::ACTIVE_TEXTURE, &mut state.active_texture);
+        gl.GetIntegerv(gl::ARRAY_BUFFER_BINDING, &mut state.array_buffer);
+
+        if has_vertex_arrays {
+            let mut vertex_array = 0;
+            gl.GetIntegerv(gl::VERTEX_ARRAY_BINDING, &mut vertex_array);
+            state.vertex_arrays = SavedVertexArrays::VertexArray(vertex_array);
+        } else {
+            let attribute = |parameter| {
+                let mut value = 0;
+                gl.GetVertexAttribiv(0, parameter, &mut value);
+                value
+            };
+            let (enabled, buffer) = (
+                attribute(gl::VERTEX_ATTRIB_ARRAY_ENABLED),
+                attribute(gl::VERTEX_ATTRIB_ARRAY_BUFFER_BINDING),
+            );
+            let (size, kind) = (
+                attribute(gl::VERTEX_ATTRIB_ARRAY_SIZE),
+                attribute(gl::VERTEX_ATTRIB_ARRAY_TYPE),
+            );
+            let (normalized, stride) = (
+                attribute(gl::VERTEX_ATTRIB_ARRAY_NORMALIZED),
+                attribute(gl::VERTEX_ATTRIB_ARRAY_STRIDE),
+            );
+            let mut pointer = ptr::null_mut();
+            gl.GetVertexAttribPointerv(0, gl::VERTEX_ATTRIB_ARRAY_POINTER, &mut pointer);
+            state.vertex_arrays = SavedVertexArrays::Attribute {
+                enabled,
+                buffer,
+                size,
+                kind,
+                normalized,
+                stride,
+                pointer,
+            };
+        }
+        state
+    }
+
+    unsafe fn restore(&self, gl: &Gl) {
+        match self.vertex_arrays {
+            SavedVertexArrays::VertexArray(vertex_array) => {
+                gl.BindVertexArray(vertex_array as GLuint)
+            }
+            SavedVertexArrays::Attribute {
+                enabled,
+                buffer,
+                size,
+                kind,
+                normalized,
+                stride,
+                pointer,
+            } => {
+                gl.BindBuffer(gl::ARRAY_BUFFER, buffer as GLuint);
+                gl.VertexAttribPointer(
+                    0,
+                    size,
+                    kind as GLenum,
+                    normalized as GLboolean,
+                    stride,
+                    pointer,
+                );
+                if enabled == 0 {
+                    gl.DisableVertexAttribArray(0);
+                }
+            }
+        }
+        gl.BindBuffer(gl::ARRAY_BUFFER, self.array_buffer as GLuint);
+        gl.ActiveTexture(self.active_texture as GLenum);
+        gl.BlendFuncSeparate(
+            self.blend_func[0] as GLenum,
+            self.blend_func[1] as GLenum,
+            self.blend_func[2] as GLenum,
+            self.blend_func[3] as GLenum,
+        );
+        set_capability(gl, gl::SCISSOR_TEST, self.scissor_test);
+        set_capability(gl, gl::DEPTH_TEST, self.depth_test);
+        set_capability(gl, gl::BLEND, self.blend);
+        let [r, g, b, a] = self.clear_color;
+        gl.ClearColor(r, g, b, a);
+        let [x, y, width, height] = self.viewport;
+        gl.Viewport(x, y, width, height);
+        gl.UseProgram(self.program as GLuint);
+    }
+}
+
+unsafe fn set_capability(gl: &Gl, capability: GLenum, enabled: GLboolean) {
+    if enabled == gl::TRUE {
+        gl.Enable(capability);
+    } else {
+        gl.Disable(capability);
+    }
+}
+
+unsafe fn compile_shader(
+    gl: &Gl,
+    kind: GLenum,
+    header: &str,
+    source: &str,
+) -> Result<GLuint, Error> {
+    let source = CString::new(format!("{}{}", header, source)).unwrap();
+    let shader = gl.CreateShader(kind);
+    gl.ShaderSource(shader, 1, &source.as_ptr(), ptr::null());
+    gl.CompileShader(shader);
+
+    let mut compile_status = 0;
+    gl.GetShaderiv(shader, gl::COMPILE_STATUS, &mut compile_status);
+    if compile_status != gl::TRUE as GLint {
+        let mut info_log_length = 0;
+        gl.GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut info_log_length);
+        let mut info_log = vec![0; info_log_length.max(1) as usize];
+        gl.GetShaderInfoLog(
+            shader,
+            info_log.len() as GLsizei,
+            ptr::null_mut(),
+            info_log.as_mut_ptr() as *mut GLchar,
+        );
+        error!(
+            "Failed to compile a compositor shader: {}",
+            String::from_utf8_lossy(&info_log)
+        );
+        gl.DeleteShader(shader);
+        return Err(Error::Failed);
+    }
+
+    Ok(shader)
+}
+
+unsafe fn uniform_location(gl: &Gl, program: GLuint, name: &[u8]) -> GLint {
+    gl.GetUniformLocation(program, name.as_ptr() as *const GLchar)
+}
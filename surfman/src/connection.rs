@@ -2,16 +2,107 @@
 //
 //! The abstract interface that all connections conform to.
 
+use crate::DisplayInfo;
 use crate::Error;
 use crate::GLApi;
 
 use euclid::default::Size2D;
 
-use std::os::raw::c_void;
+use std::os::raw::{c_ulong, c_void};
+#[cfg(unix)]
+use std::os::unix::io::RawFd;
 
 #[cfg(feature = "sm-winit")]
 use winit::window::Window;
 
+/// A handle to a display server or GPU, opened by one process and passed to another over IPC.
+///
+/// See `Connection::from_ipc_descriptor()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IpcDescriptor {
+    /// A socket connected to a Wayland compositor, as otherwise passed in `WAYLAND_SOCKET`.
+    ///
+    /// The Wayland backend accepts this.
+    #[cfg(unix)]
+    WaylandSocket(RawFd),
+    /// A DRM render node, such as `/dev/dri/renderD128`.
+    ///
+    /// The surfaceless backend accepts this, and renders on the node's GPU through GBM.
+    #[cfg(unix)]
+    DrmRenderNode(RawFd),
+    /// The locally unique identifier (LUID) of a DXGI adapter, with its `HighPart` in the upper
+    /// 32 bits.
+    ///
+    /// Direct3D devices can't be duplicated into another process, but a device can be created on
+    /// the same adapter without any access beyond that of a sandboxed process. The ANGLE backend
+    /// accepts this, and its hardware and low-power adapters are then always this adapter.
+    #[cfg(windows)]
+    DXGIAdapterLuid(u64),
+}
+
+/// A handle to a native window or view on any platform, from which a native widget can be
+/// created with `Connection::create_native_widget_from_handle()`.
+///
+/// Every variant exists on every platform, so that embedders supporting several platforms can
+/// build a handle in one place. Backends reject the kinds of handle they can't render to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NativeWidgetHandle {
+    /// An `NSView` on macOS.
+    NSView(*mut c_void),
+    /// A `CALayer` on macOS that backs an `NSView`, as returned by `-[NSView layer]`.
+    ///
+    /// Surfaces are attached to the view, which is found through the layer's delegate.
+    CALayer(*mut c_void),
+    /// A Win32 window, which can be a top-level window or a control.
+    HWND(*mut c_void),
+    /// An `ANativeWindow` on Android.
+    ANativeWindow(*mut c_void),
+    /// An `OHNativeWindow` on OpenHarmony.
+    OHNativeWindow(*mut c_void),
+    /// A Wayland `wl_surface`.
+    WaylandSurface {
+        /// The `wl_display` that the surface was created on.
+        display: *mut c_void,
+        /// The `wl_surface` itself.
+        surface: *mut c_void,
+        /// The size of the surface, in device pixels.
+        ///
+        /// Wayland doesn't track the size of surfaces, so the caller has to supply it.
+        size: Size2D<i32>,
+    },
+    /// An X11 window ID.
+    X11Window(c_ulong),
+}
+
+/// The raw display connection underneath a connection, as returned by
+/// `Connection::native_display()`, for calling platform APIs that surfman doesn't wrap.
+///
+/// Like `NativeWidgetHandle`, every variant exists on every platform. The pointers are borrowed
+/// from the connection: they are valid for as long as it is alive, and must not be closed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NativeDisplayHandle {
+    /// An `EGLDisplay`, with the surfaceless backend.
+    EGLDisplay(*mut c_void),
+    /// An Xlib `Display`, along with the `EGLDisplay` created on it, which is null with GLX.
+    Xlib {
+        /// The Xlib `Display`.
+        display: *mut c_void,
+        /// The `EGLDisplay`, or null.
+        egl_display: *mut c_void,
+    },
+    /// A Wayland `wl_display`, along with the `EGLDisplay` created on it.
+    Wayland {
+        /// The `wl_display`, which is null if the connection wraps a bare EGL display.
+        display: *mut c_void,
+        /// The `EGLDisplay`.
+        egl_display: *mut c_void,
+    },
+    /// There is no display connection, as with CGL, WGL, ANGLE, OSMesa, Android, and OpenHarmony.
+    ///
+    /// Use `Device::native_device_handle()` for the display or GPU that the device renders with.
+    None,
+}
+
 /// Methods relating to display server connections.
 pub trait Connection: Sized {
     /// The adapter type associated with this connection.
@@ -28,12 +119,35 @@ pub trait Connection: Sized {
     /// Connects to the default display.
     fn new() -> Result<Self, Error>;
 
+    /// Connects through a handle that another process opened and passed over IPC.
+    ///
+    /// This lets sandboxed GPU processes render without access to the filesystem or the display
+    /// server. On success, the connection takes ownership of any file descriptor in the
+    /// descriptor. Backends that don't accept the kind of descriptor given return
+    /// `UnsupportedOnThisPlatform` and leave it open.
+    ///
+    /// # Safety
+    ///
+    /// Any file descriptor in the descriptor must be open, and nothing else may use or close it
+    /// once a backend has taken ownership of it.
+    unsafe fn from_ipc_descriptor(descriptor: IpcDescriptor) -> Result<Self, Error>;
+
     /// Returns the native connection corresponding to this connection.
     fn native_connection(&self) -> Self::NativeConnection;
 
+    /// Returns the raw display connection underneath this connection, for calling platform APIs
+    /// that surfman doesn't wrap.
+    fn native_display(&self) -> NativeDisplayHandle;
+
     /// Returns the OpenGL API flavor that this connection supports (OpenGL or OpenGL ES).
     fn gl_api(&self) -> GLApi;
 
+    /// Returns the displays connected to the system.
+    ///
+    /// Backends that don't display anything, such as OSMesa and surfaceless EGL, return an empty
+    /// list. Backends that can't enumerate displays return `UnsupportedOnThisPlatform`.
+    fn displays(&self) -> Result<Vec<DisplayInfo>, Error>;
+
     /// Returns the "best" adapter on this system, preferring high-performance hardware adapters.
     ///
     /// This is an alias for `Connection::create_hardware_adapter()`.
@@ -48,10 +162,23 @@ pub trait Connection: Sized {
     /// Returns the "best" adapter on this system, preferring software adapters.
     fn create_software_adapter(&self) -> Result<Self::Adapter, Error>;
 
+    /// Returns the adapter for the GPU that drives the given display, as returned by
+    /// `Connection::displays()`.
+    ///
+    /// On systems with several GPUs, rendering with a device opened on this adapter avoids copying
+    /// each frame between GPUs when presenting to a widget on that display. Returns
+    /// `NoAdapterFound` if the GPU driving the display can't be determined.
+    fn create_adapter_for_display(&self, display: &DisplayInfo) -> Result<Self::Adapter, Error>;
+
     /// Opens a device.
     fn create_device(&self, adapter: &Self::Adapter) -> Result<Self::Device, Error>;
 
     /// Wraps an existing native device type in a device.
+    ///
+    /// # Safety
+    ///
+    /// The native device must be valid, and must stay alive as long as the returned device unless
+    /// the backend documents that it's retained.
     unsafe fn create_device_from_native_device(
         &self,
         native_device: Self::NativeDevice,
@@ -71,12 +198,31 @@ pub trait Connection: Sized {
     ) -> Result<Self::NativeWidget, Error>;
 
     /// Creates a native widget from a raw pointer
+    ///
+    /// # Safety
+    ///
+    /// The pointer must refer to a live window or view of the kind that the backend expects, which
+    /// must outlive the native widget and any surfaces created from it.
     unsafe fn create_native_widget_from_ptr(
         &self,
         raw: *mut c_void,
         size: Size2D<i32>,
     ) -> Self::NativeWidget;
 
+    /// Creates a native widget from a handle to a native window or view.
+    ///
+    /// If this connection can't render to the kind of window the handle refers to, an
+    /// `IncompatibleNativeWidget` error is returned.
+    ///
+    /// # Safety
+    ///
+    /// The handle must refer to a live window or view, which must outlive the native widget and
+    /// any surfaces created from it.
+    unsafe fn create_native_widget_from_handle(
+        &self,
+        handle: NativeWidgetHandle,
+    ) -> Result<Self::NativeWidget, Error>;
+
     /// Create a native widget type from the given `raw_window_handle::RawWindowHandle`.
     #[cfg(feature = "sm-raw-window-handle")]
     fn create_native_widget_from_rwh(
@@ -5,23 +5,88 @@
 #![allow(unused_imports)]
 
 use crate::gl;
-use crate::gl::types::GLuint;
+use crate::gl::types::{GLenum, GLint, GLuint};
 use crate::info::GLVersion;
-use crate::Gl;
+use crate::{Error, GLApi, Gl};
 
+use std::collections::HashMap;
 use std::ffi::CStr;
+use std::hash::Hash;
 use std::os::raw::c_char;
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 
 /// A unique ID among all currently-allocated contexts.
 ///
-/// If you destroy a context, subsequently-allocated contexts might reuse the same ID.
+/// The upper 16 bits hold a tag identifying the connection the context was created from, and the
+/// rest count the contexts created from that connection. IDs are never reused while that
+/// connection, or any device opened on it, is alive. Once they have all been dropped, the tag may
+/// be handed to a new connection, whose contexts may then get the same IDs again.
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub struct ContextID(pub u64);
 
+const CONNECTION_TAG_SHIFT: u32 = 48;
+const CONNECTION_TAG_COUNT: u64 = 1 << 16;
+
 lazy_static! {
-    #[doc(hidden)]
-    pub static ref CREATE_CONTEXT_MUTEX: Mutex<ContextID> = Mutex::new(ContextID(0));
+    static ref CONNECTION_TAGS: Mutex<ConnectionTags> = Mutex::new(ConnectionTags {
+        next: 0,
+        free: vec![],
+    });
+}
+
+struct ConnectionTags {
+    next: u64,
+    free: Vec<u64>,
+}
+
+// Hands out the IDs of the contexts created from one connection, including from the devices
+// opened on it, which share it.
+//
+// Each connection counts its contexts separately, under its own tag, so that creating contexts
+// on unrelated connections doesn't contend on a lock. The global lock is only taken to hand out
+// and give back tags, when connections are opened and dropped.
+#[derive(Clone)]
+pub(crate) struct ContextIDAllocator(Arc<ConnectionContextIDs>);
+
+struct ConnectionContextIDs {
+    tag: u64,
+    next: AtomicU64,
+}
+
+impl ContextIDAllocator {
+    /// Fails with `Error::ConnectionFailed` if every connection tag is held by a live connection.
+    pub(crate) fn new() -> Result<ContextIDAllocator, Error> {
+        let mut tags = CONNECTION_TAGS.lock().unwrap();
+        let tag = match tags.free.pop() {
+            Some(tag) => tag,
+            None if tags.next < CONNECTION_TAG_COUNT => {
+                tags.next += 1;
+                tags.next - 1
+            }
+            None => {
+                error!("Too many live connections to tag their context IDs apart!");
+                return Err(Error::ConnectionFailed);
+            }
+        };
+        Ok(ContextIDAllocator(Arc::new(ConnectionContextIDs {
+            tag,
+            next: AtomicU64::new(tag << CONNECTION_TAG_SHIFT),
+        })))
+    }
+
+    #[inline]
+    pub(crate) fn next(&self) -> ContextID {
+        ContextID(self.0.next.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl Drop for ConnectionContextIDs {
+    fn drop(&mut self) {
+        if let Ok(mut tags) = CONNECTION_TAGS.lock() {
+            tags.free.push(self.tag);
+        }
+    }
 }
 
 bitflags! {
@@ -45,39 +110,244 @@ bitflags! {
         /// The OpenGL compatibility profile will be used. If this is not present, the core profile
         /// is used.
         const COMPATIBILITY_PROFILE = 0x08;
+        /// A debug context will be requested, which reports errors and performance warnings in
+        /// more detail through `GL_KHR_debug`. Backends that can't create debug contexts, such as
+        /// CGL and OSMesa, ignore this flag.
+        const DEBUG                 = 0x10;
+        /// Widget surfaces created for this context will have separate left and right back
+        /// buffers, for driving quad-buffered stereo displays. Select the buffer to draw to with
+        /// `glDrawBuffer(GL_BACK_LEFT)` or `glDrawBuffer(GL_BACK_RIGHT)`.
+        ///
+        /// Only the GLX and WGL backends support this, and only with drivers that offer stereo
+        /// pixel formats; elsewhere, asking for it returns `Error::UnsupportedOnThisPlatform`.
+        /// With GLX, stereo contexts can't render to generic surfaces.
+        const STEREO                = 0x20;
+        /// The window-system framebuffers of widget surfaces created for this context will have
+        /// 16-bit floating-point color channels, so that linear HDR rendering can go straight to
+        /// them without an extra resolve pass. Generic surfaces keep 8-bit channels.
+        ///
+        /// This requires `EGL_EXT_pixel_format_float` on EGL backends and
+        /// `WGL_ARB_pixel_format_float` on WGL; without them, asking for it returns
+        /// `Error::RequiredExtensionUnavailable`. The GLX, CGL, and OSMesa backends return
+        /// `Error::UnsupportedOnThisPlatform`.
+        const FLOAT_COLOR           = 0x40;
     }
 }
 
 /// Attributes that control aspects of a context and/or surfaces created from that context.
 ///
 /// Similar to: https://www.khronos.org/registry/webgl/specs/latest/1.0/#WEBGLCONTEXTATTRIBUTES
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub struct ContextAttributes {
     /// The OpenGL or OpenGL ES version that this context supports.
     ///
     /// Keep in mind that OpenGL and OpenGL ES have different version numbering schemes. Before
     /// filling in this field, check the result of `Device::gl_api()`.
+    ///
+    /// With `max_version`, this is the lowest version that will do.
     pub version: GLVersion,
+    /// The highest version to ask for, if the application can make use of versions above
+    /// `version`.
+    ///
+    /// Contexts are then created with the highest version from `max_version` down to `version`
+    /// that the driver supports, for example OpenGL ES 3.2, 3.1, and then 3.0 for `version` 3.0 and
+    /// `max_version` 3.2. The version actually obtained is reported by
+    /// `Device::context_descriptor_attributes()` on the descriptor of the context. `None`, or a
+    /// version no higher than `version`, asks for `version` alone.
+    pub max_version: Option<GLVersion>,
     /// Various flags.
     pub flags: ContextAttributeFlags,
+    /// OpenGL extensions that the application can't do without, such as
+    /// `"GL_EXT_color_buffer_float"`.
+    ///
+    /// `Device::create_context()` checks that the new context supports each of them, and fails
+    /// with `Error::MissingExtensions`, listing the ones that it lacks, if it doesn't. This way an
+    /// unsupported driver is caught up front instead of partway through rendering.
+    pub required_extensions: Vec<String>,
+    /// The number of samples per pixel that the window-system framebuffers of widget surfaces
+    /// created for this context will have, for multisample antialiasing. 0 and 1 both mean no
+    /// multisampling. Generic surfaces are always single-sampled, so their surface textures need
+    /// no resolve; with GLX and ANGLE, they're allocated from a single-sampled config with the
+    /// same channel sizes. CGL and OSMesa never multisample.
+    ///
+    /// If the requested count isn't available, the next lower one of 8, 4, 2, and 1 that is
+    /// available is used instead. The count actually obtained is reported by
+    /// `Device::context_descriptor_attributes()` and `SurfaceInfo::samples`.
+    pub samples: u32,
 }
 
 impl ContextAttributes {
-    #[allow(dead_code)]
-    pub(crate) fn zeroed() -> ContextAttributes {
-        ContextAttributes {
-            version: GLVersion::new(0, 0),
-            flags: ContextAttributeFlags::empty(),
+    // The highest version to try when creating a context, which is never lower than `version`.
+    pub(crate) fn highest_version(&self) -> GLVersion {
+        self.max_version
+            .map_or(self.version, |max_version| max_version.max(self.version))
+    }
+
+    // The sample counts to try when choosing a config or pixel format, in order of preference:
+    // the requested count, and then each lower power of two down to 1. Only the EGL, GLX, and WGL
+    // backends choose configs by sample count.
+    #[cfg(any(android, linux, ohos, windows))]
+    pub(crate) fn sample_counts(&self) -> impl Iterator<Item = u32> {
+        let samples = self.samples.max(1);
+        Some(samples).into_iter().chain(
+            [8, 4, 2, 1]
+                .iter()
+                .cloned()
+                .filter(move |&count| count < samples),
+        )
+    }
+}
+
+/// A small ID that a device hands out for each distinct context descriptor interned with
+/// `Device::intern_context_descriptor()`.
+///
+/// Equal descriptors get the same ID from a device and its clones, so IDs can key caches of
+/// contexts and pipeline state in place of the descriptors themselves. IDs from unrelated devices
+/// aren't comparable.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
+pub struct ContextDescriptorID(pub u32);
+
+// The versions to try when creating a context, in order of preference: each version of the API
+// from `max_version` down to `version`.
+pub(crate) fn gl_versions(
+    gl_api: GLApi,
+    version: GLVersion,
+    max_version: GLVersion,
+) -> Vec<GLVersion> {
+    let mut versions = vec![];
+    let mut next_version = max_version;
+    while next_version > version {
+        versions.push(next_version);
+        next_version = if next_version.minor > 0 {
+            GLVersion::new(next_version.major, next_version.minor - 1)
+        } else if next_version.major > 1 {
+            let major = next_version.major - 1;
+            GLVersion::new(major, highest_minor_version(gl_api, major))
+        } else {
+            break;
+        };
+    }
+    versions.push(version);
+    versions
+}
+
+// The last minor version of each major version of OpenGL and OpenGL ES.
+fn highest_minor_version(gl_api: GLApi, major: u8) -> u8 {
+    match (gl_api, major) {
+        (GLApi::GL, 1) => 5,
+        (GLApi::GL, 2) => 1,
+        (GLApi::GL, 3) => 3,
+        (GLApi::GL, 4) => 6,
+        (GLApi::GLES, 1) => 1,
+        (GLApi::GLES, 2) => 0,
+        (GLApi::GLES, 3) => 2,
+        _ => 9,
+    }
+}
+
+// The context descriptors a device has created, keyed by the attributes they were created with,
+// so that asking for the same attributes again skips choosing a config or pixel format, along
+// with the IDs of the descriptors that have been interned.
+//
+// A device only ever sees a handful of distinct attributes, so they're searched linearly. The
+// cache is shared with clones of the device.
+pub(crate) struct ContextDescriptorCache<D>(Arc<Mutex<ContextDescriptors<D>>>);
+
+struct ContextDescriptors<D> {
+    by_attributes: Vec<(ContextAttributes, D)>,
+    interned: HashMap<D, ContextDescriptorID>,
+}
+
+impl<D> Clone for ContextDescriptorCache<D> {
+    #[inline]
+    fn clone(&self) -> ContextDescriptorCache<D> {
+        ContextDescriptorCache(self.0.clone())
+    }
+}
+
+impl<D> ContextDescriptorCache<D>
+where
+    D: Clone,
+{
+    pub(crate) fn new() -> ContextDescriptorCache<D> {
+        ContextDescriptorCache(Arc::new(Mutex::new(ContextDescriptors {
+            by_attributes: vec![],
+            interned: HashMap::new(),
+        })))
+    }
+
+    // Returns a copy of the descriptor cached for the given attributes, or creates and caches one
+    // if there's none. Failures aren't cached.
+    pub(crate) fn get_or_insert_with<F>(
+        &self,
+        attributes: &ContextAttributes,
+        create: F,
+    ) -> Result<D, Error>
+    where
+        F: FnOnce() -> Result<D, Error>,
+    {
+        if let Some(descriptor) = self.get(attributes) {
+            return Ok(descriptor);
+        }
+
+        // Don't hold the lock while choosing, which can take a while.
+        let descriptor = create()?;
+        let by_attributes = &mut self.0.lock().unwrap().by_attributes;
+        if !by_attributes.iter().any(|entry| entry.0 == *attributes) {
+            by_attributes.push((attributes.clone(), descriptor.clone()));
+        }
+        Ok(descriptor)
+    }
+
+    fn get(&self, attributes: &ContextAttributes) -> Option<D> {
+        let descriptors = self.0.lock().unwrap();
+        descriptors
+            .by_attributes
+            .iter()
+            .find(|entry| entry.0 == *attributes)
+            .map(|entry| entry.1.clone())
+    }
+}
+
+impl<D> ContextDescriptorCache<D>
+where
+    D: Clone + Eq + Hash,
+{
+    // Returns the ID of the descriptor, assigning the next one if no equal descriptor has been
+    // interned yet. Interned descriptors are kept for the life of the device.
+    pub(crate) fn intern(&self, descriptor: &D) -> ContextDescriptorID {
+        let interned = &mut self.0.lock().unwrap().interned;
+        if let Some(&id) = interned.get(descriptor) {
+            return id;
         }
+        let id = ContextDescriptorID(interned.len() as u32);
+        interned.insert(descriptor.clone(), id);
+        id
+    }
+}
+
+// From OpenGL 4.3 and OpenGL ES 3.2, which the bindings predate.
+#[cfg(any(android, linux, ohos, windows))]
+const GL_CONTEXT_FLAGS: GLenum = 0x821e;
+#[cfg(any(android, linux, ohos, windows))]
+const GL_CONTEXT_FLAG_DEBUG_BIT: GLint = 0x2;
+
+#[cfg(any(android, linux, ohos, windows))]
+pub(crate) fn current_context_is_debug(gl: &Gl) -> bool {
+    unsafe {
+        // Versions without `GL_CONTEXT_FLAGS` have no debug contexts either.
+        let mut context_flags = 0;
+        gl.GetIntegerv(GL_CONTEXT_FLAGS, &mut context_flags);
+        gl.GetError() == gl::NO_ERROR && (context_flags & GL_CONTEXT_FLAG_DEBUG_BIT) != 0
     }
 }
 
-#[cfg(target_os = "android")]
+#[cfg(any(target_os = "android", target_env = "ohos"))]
 pub(crate) fn current_context_uses_compatibility_profile(_gl: &Gl) -> bool {
     false
 }
 
-#[cfg(not(target_os = "android"))]
+#[cfg(not(any(target_os = "android", target_env = "ohos")))]
 #[allow(dead_code)]
 pub(crate) fn current_context_uses_compatibility_profile(gl: &Gl) -> bool {
     unsafe {
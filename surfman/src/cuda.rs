@@ -0,0 +1,260 @@
+// surfman/surfman/src/cuda.rs
+//
+//! Registration of surface textures with the CUDA runtime, for pipelines that post-process
+//! rendered frames with CUDA.
+//!
+//! A surface texture is registered with `cudaGraphicsGLRegisterImage()`, after which CUDA can map
+//! it and read from its storage as a CUDA array, without copying it through the CPU. The CUDA
+//! runtime (`libcudart`) is loaded at runtime, so that applications built with this feature still
+//! run on machines without it; registration then fails with `UnsupportedOnThisPlatform`.
+//!
+//! This is only available on Linux.
+
+use crate::device::Device;
+use crate::gl;
+use crate::Error;
+
+use std::mem;
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+use std::ptr;
+use std::thread;
+
+// `cudaError_t`. 0 is `cudaSuccess`.
+type CudaError = c_int;
+
+type GraphicsGLRegisterImageFn = unsafe extern "C" fn(
+    resource: *mut *mut c_void,
+    image: c_uint,
+    target: c_uint,
+    flags: c_uint,
+) -> CudaError;
+type GraphicsUnregisterResourceFn = unsafe extern "C" fn(resource: *mut c_void) -> CudaError;
+type GraphicsMapResourcesFn = unsafe extern "C" fn(
+    count: c_int,
+    resources: *mut *mut c_void,
+    stream: *mut c_void,
+) -> CudaError;
+type GraphicsUnmapResourcesFn = unsafe extern "C" fn(
+    count: c_int,
+    resources: *mut *mut c_void,
+    stream: *mut c_void,
+) -> CudaError;
+type GraphicsSubResourceGetMappedArrayFn = unsafe extern "C" fn(
+    array: *mut *mut c_void,
+    resource: *mut c_void,
+    array_index: c_uint,
+    mip_level: c_uint,
+) -> CudaError;
+
+#[allow(non_snake_case)]
+struct CudaFunctions {
+    GraphicsGLRegisterImage: GraphicsGLRegisterImageFn,
+    GraphicsUnregisterResource: GraphicsUnregisterResourceFn,
+    GraphicsMapResources: GraphicsMapResourcesFn,
+    GraphicsUnmapResources: GraphicsUnmapResourcesFn,
+    GraphicsSubResourceGetMappedArray: GraphicsSubResourceGetMappedArrayFn,
+}
+
+lazy_static! {
+    static ref CUDA_FUNCTIONS: Option<CudaFunctions> = unsafe {
+        let library_names: [&[u8]; 3] = [
+            b"libcudart.so.12\0",
+            b"libcudart.so.11.0\0",
+            b"libcudart.so\0",
+        ];
+        let library = library_names
+            .iter()
+            .map(|name| libc::dlopen(name.as_ptr() as *const c_char, libc::RTLD_LAZY))
+            .find(|library| !library.is_null())?;
+        let get = |name: &'static [u8]| libc::dlsym(library, name.as_ptr() as *const c_char);
+        let symbols = [
+            get(b"cudaGraphicsGLRegisterImage\0"),
+            get(b"cudaGraphicsUnregisterResource\0"),
+            get(b"cudaGraphicsMapResources\0"),
+            get(b"cudaGraphicsUnmapResources\0"),
+            get(b"cudaGraphicsSubResourceGetMappedArray\0"),
+        ];
+        if symbols.iter().any(|symbol| symbol.is_null()) {
+            return None;
+        }
+        Some(CudaFunctions {
+            GraphicsGLRegisterImage: mem::transmute::<*mut c_void, GraphicsGLRegisterImageFn>(
+                symbols[0],
+            ),
+            GraphicsUnregisterResource: mem::transmute::<*mut c_void, GraphicsUnregisterResourceFn>(
+                symbols[1],
+            ),
+            GraphicsMapResources: mem::transmute::<*mut c_void, GraphicsMapResourcesFn>(symbols[2]),
+            GraphicsUnmapResources: mem::transmute::<*mut c_void, GraphicsUnmapResourcesFn>(
+                symbols[3],
+            ),
+            GraphicsSubResourceGetMappedArray: mem::transmute::<
+                *mut c_void,
+                GraphicsSubResourceGetMappedArrayFn,
+            >(symbols[4]),
+        })
+    };
+}
+
+bitflags! {
+    /// How CUDA will access a registered surface texture.
+    ///
+    /// These are the `cudaGraphicsRegisterFlags` passed to `cudaGraphicsGLRegisterImage()`.
+    pub struct CudaRegisterFlags: c_uint {
+        /// CUDA will only read from the texture.
+        const READ_ONLY = 0x01;
+        /// CUDA will overwrite the whole texture, discarding its previous contents.
+        const WRITE_DISCARD = 0x02;
+        /// CUDA will bind the texture to a surface reference.
+        const SURFACE_LOAD_STORE = 0x04;
+        /// CUDA will perform texture gather operations on the texture.
+        const TEXTURE_GATHER = 0x08;
+    }
+}
+
+/// A surface texture registered with the CUDA runtime as a graphics resource.
+///
+/// Map the resource with `map()` to access its storage from CUDA, and unmap it with `unmap()`
+/// before rendering with it again. It must be unregistered with `unregister()` before the surface
+/// texture is destroyed.
+pub struct CudaResource {
+    resource: *mut c_void,
+    mapped: bool,
+    unregistered: bool,
+}
+
+impl Drop for CudaResource {
+    fn drop(&mut self) {
+        if !self.unregistered && !thread::panicking() {
+            panic!("Should have unregistered the CUDA resource first with `unregister()`!")
+        }
+    }
+}
+
+/// Registers a surface texture with the CUDA runtime.
+///
+/// Surface textures are meant to be read from, so `flags` should usually be `READ_ONLY`.
+///
+/// CUDA can only register 2D, rectangle, and 2D array textures. Other surface textures, such as
+/// external textures, return `Error::IncompatibleSurfaceTexture`.
+///
+/// # Safety
+///
+/// The context that the surface texture belongs to must be current, and the CUDA context that the
+/// resource will be used with must be current on this thread.
+pub unsafe fn register_surface_texture<D>(
+    device: &D,
+    surface_texture: &D::SurfaceTexture,
+    flags: CudaRegisterFlags,
+) -> Result<CudaResource, Error>
+where
+    D: Device,
+{
+    let cuda_functions = match *CUDA_FUNCTIONS {
+        Some(ref cuda_functions) => cuda_functions,
+        None => return Err(Error::UnsupportedOnThisPlatform),
+    };
+
+    let target = device.surface_texture_binding_target(surface_texture);
+    match target {
+        gl::TEXTURE_2D | gl::TEXTURE_RECTANGLE | gl::TEXTURE_2D_ARRAY => {}
+        _ => return Err(Error::IncompatibleSurfaceTexture),
+    }
+
+    let mut resource = ptr::null_mut();
+    let result = (cuda_functions.GraphicsGLRegisterImage)(
+        &mut resource,
+        device.surface_texture_object(surface_texture),
+        target,
+        flags.bits(),
+    );
+    if result != 0 {
+        error!("cudaGraphicsGLRegisterImage() failed: {}", result);
+        return Err(Error::Failed);
+    }
+
+    Ok(CudaResource {
+        resource,
+        mapped: false,
+        unregistered: false,
+    })
+}
+
+impl CudaResource {
+    /// Returns the `cudaGraphicsResource_t` handle, for use with other CUDA bindings.
+    #[inline]
+    pub fn as_raw(&self) -> *mut c_void {
+        self.resource
+    }
+
+    /// Maps the resource for access by CUDA, ordered on the given stream (or the default stream
+    /// if null), and returns the `cudaArray_t` that holds the contents of the surface texture.
+    ///
+    /// OpenGL must not use the surface texture while it's mapped.
+    ///
+    /// # Safety
+    ///
+    /// The CUDA context that the resource was registered with must be current, and `stream` must
+    /// be a stream of it or null.
+    pub unsafe fn map(&mut self, stream: *mut c_void) -> Result<*mut c_void, Error> {
+        let cuda_functions = CUDA_FUNCTIONS.as_ref().unwrap();
+        if self.mapped {
+            return Err(Error::Failed);
+        }
+
+        let result = (cuda_functions.GraphicsMapResources)(1, &mut self.resource, stream);
+        if result != 0 {
+            error!("cudaGraphicsMapResources() failed: {}", result);
+            return Err(Error::Failed);
+        }
+        self.mapped = true;
+
+        let mut array = ptr::null_mut();
+        let result =
+            (cuda_functions.GraphicsSubResourceGetMappedArray)(&mut array, self.resource, 0, 0);
+        if result != 0 {
+            error!("cudaGraphicsSubResourceGetMappedArray() failed: {}", result);
+            return Err(Error::Failed);
+        }
+        Ok(array)
+    }
+
+    /// Unmaps the resource, ordered on the given stream, handing the surface texture back to
+    /// OpenGL. The array returned by `map()` must not be used afterward.
+    ///
+    /// # Safety
+    ///
+    /// The same as for `map()`.
+    pub unsafe fn unmap(&mut self, stream: *mut c_void) -> Result<(), Error> {
+        let cuda_functions = CUDA_FUNCTIONS.as_ref().unwrap();
+        if !self.mapped {
+            return Ok(());
+        }
+
+        let result = (cuda_functions.GraphicsUnmapResources)(1, &mut self.resource, stream);
+        if result != 0 {
+            error!("cudaGraphicsUnmapResources() failed: {}", result);
+            return Err(Error::Failed);
+        }
+        self.mapped = false;
+        Ok(())
+    }
+
+    /// Unregisters the resource from the CUDA runtime, unmapping it first if it's mapped.
+    ///
+    /// # Safety
+    ///
+    /// The CUDA context that the resource was registered with must be current.
+    pub unsafe fn unregister(mut self) -> Result<(), Error> {
+        let cuda_functions = CUDA_FUNCTIONS.as_ref().unwrap();
+        let unmapped = self.unmap(ptr::null_mut());
+        let result = (cuda_functions.GraphicsUnregisterResource)(self.resource);
+        self.unregistered = true;
+        unmapped?;
+        if result != 0 {
+            error!("cudaGraphicsUnregisterResource() failed: {}", result);
+            return Err(Error::Failed);
+        }
+        Ok(())
+    }
+}
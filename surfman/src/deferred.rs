@@ -0,0 +1,62 @@
+// surfman/surfman/src/deferred.rs
+//
+//! The queue of surfaces waiting to be destroyed once their contexts are available.
+
+use crate::context::ContextID;
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+// The surfaces are boxed as `Any` because the queue is shared by all backends. Each backend only
+// takes out the surfaces of its own type.
+type DeferredSurfaces = HashMap<ContextID, Vec<Box<dyn Any + Send>>>;
+
+// Checked before taking the lock, so that contexts with nothing queued pay a single atomic load.
+static DEFERRED_SURFACE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+    static ref DEFERRED_SURFACES: Mutex<DeferredSurfaces> = Mutex::new(HashMap::new());
+}
+
+pub(crate) fn defer_destroy_surface<S>(context_id: ContextID, surface: S)
+where
+    S: Any + Send,
+{
+    let mut deferred_surfaces = DEFERRED_SURFACES.lock().unwrap();
+    deferred_surfaces
+        .entry(context_id)
+        .or_default()
+        .push(Box::new(surface));
+    DEFERRED_SURFACE_COUNT.fetch_add(1, Ordering::Release);
+}
+
+// Removes and returns the queued surfaces of the given context that have type `S`.
+pub(crate) fn take_deferred_surfaces<S>(context_id: ContextID) -> Vec<S>
+where
+    S: Any,
+{
+    if DEFERRED_SURFACE_COUNT.load(Ordering::Acquire) == 0 {
+        return vec![];
+    }
+
+    let mut deferred_surfaces = DEFERRED_SURFACES.lock().unwrap();
+    let queue = match deferred_surfaces.remove(&context_id) {
+        None => return vec![],
+        Some(queue) => queue,
+    };
+
+    let (mut surfaces, mut others) = (vec![], vec![]);
+    for surface in queue {
+        match surface.downcast::<S>() {
+            Ok(surface) => surfaces.push(*surface),
+            Err(other) => others.push(other),
+        }
+    }
+    if !others.is_empty() {
+        deferred_surfaces.insert(context_id, others);
+    }
+    DEFERRED_SURFACE_COUNT.fetch_sub(surfaces.len(), Ordering::Release);
+    surfaces
+}
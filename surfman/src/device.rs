@@ -3,16 +3,69 @@
 //! The abstract interface that all devices conform to.
 
 use super::connection::Connection as ConnectionInterface;
-use crate::gl::types::{GLenum, GLuint};
-use crate::{ContextAttributes, ContextID, Error, GLApi, SurfaceAccess, SurfaceInfo, SurfaceType};
-use euclid::default::Size2D;
+use crate::deferred;
+use crate::gl;
+use crate::gl::types::{GLboolean, GLenum, GLfloat, GLint, GLuint};
+use crate::label;
+use crate::SurfaceLayoutRequirements;
+use crate::SurfaceType;
+use crate::WidgetOutputInfo;
+use crate::{
+    Colorspace, ContextAttributes, ContextDescriptorID, ContextID, Error, GLApi,
+    PresentationStatistics, SurfaceAccess, SurfaceUsage, SurfaceVisibility,
+};
+use crate::{
+    CreateSurfacesResult, CreationTimings, DeviceEventHandler, Gl, LifecycleObserver,
+    SurfaceAlphaMode, SurfaceFormat, SurfaceInfo, SurfaceOrigin,
+};
+use euclid::default::{Rect, Size2D};
 
+#[cfg(feature = "sm-snapshot")]
+use std::fs::File;
+use std::hash::Hash;
+#[cfg(feature = "sm-snapshot")]
+use std::io::BufWriter;
 use std::os::raw::c_void;
+#[cfg(feature = "sm-snapshot")]
+use std::path::Path;
+use std::sync::Arc;
 
-/// A thread-local handle to a device.
+/// The raw display or GPU device underneath a device, as returned by
+/// `Device::native_device_handle()`, for calling platform APIs that surfman doesn't wrap.
+///
+/// Like `NativeWidgetHandle`, every variant exists on every platform. The pointers are borrowed
+/// from the device: they are valid for as long as it is alive, and must not be released.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NativeDeviceHandle {
+    /// An `EGLDisplay`, which EGL treats as the device, with Android, OpenHarmony, and the EGL
+    /// backends on Unix.
+    EGLDisplay(*mut c_void),
+    /// A Direct3D 11 `ID3D11Device`, along with the `EGLDisplay` that ANGLE created on it, which
+    /// is null with WGL.
+    D3D11Device {
+        /// The `ID3D11Device`.
+        device: *mut c_void,
+        /// The `EGLDisplay`, or null.
+        egl_display: *mut c_void,
+    },
+    /// An `id<MTLDevice>` on macOS, for the GPU that CGL renders with.
+    ///
+    /// Metal devices are owned by the system, and live as long as the process does.
+    MTLDevice(*mut c_void),
+    /// There is no device object, as with GLX and OSMesa.
+    ///
+    /// With GLX, the display is returned by `Connection::native_display()`.
+    None,
+}
+
+/// A handle to a device.
 ///
 /// Devices contain most of the relevant surface management methods.
-pub trait Device: Sized
+///
+/// Cloning a device is cheap: clones share its native handles, memory usage count, and context
+/// descriptor cache. Every backend's device can be sent to other threads, so each rendering thread
+/// can own a clone rather than funneling its surface operations through a single thread.
+pub trait Device: Sized + Clone
 where
     Self::Connection: ConnectionInterface,
 {
@@ -21,19 +74,29 @@ where
     /// The context type associated with this device.
     type Context;
     /// The context descriptor type associated with this device.
-    type ContextDescriptor;
+    ///
+    /// Descriptors compare equal when contexts created from them would be configured the same way,
+    /// so they can key caches.
+    type ContextDescriptor: Clone + Eq + Hash;
     /// The native context type associated with this device.
     type NativeContext;
     /// The surface type associated with this device.
     type Surface;
     /// The surface texture type associated with this device.
     type SurfaceTexture;
+    /// The type of the tokens that surfaces can be turned into, to be destroyed later without
+    /// their contexts.
+    type SurfaceDestroyToken;
 
     // device.rs
 
     /// Returns the native device associated with this device.
     fn native_device(&self) -> <Self::Connection as ConnectionInterface>::NativeDevice;
 
+    /// Returns the raw display or GPU device underneath this device, for calling platform APIs
+    /// that surfman doesn't wrap.
+    fn native_device_handle(&self) -> NativeDeviceHandle;
+
     /// Returns the display server connection that this device was created with.
     fn connection(&self) -> Self::Connection;
 
@@ -43,6 +106,43 @@ where
     /// Returns the OpenGL API flavor that this device supports (OpenGL or OpenGL ES).
     fn gl_api(&self) -> GLApi;
 
+    /// Installs an observer that is notified when contexts and surfaces of this device are
+    /// created, destroyed, resized, or presented, replacing any previous one.
+    ///
+    /// Pass `None` to remove the observer.
+    fn set_lifecycle_observer(&mut self, observer: Option<Arc<dyn LifecycleObserver>>);
+
+    /// Returns the lifecycle observer installed on this device, if any.
+    fn lifecycle_observer(&self) -> Option<Arc<dyn LifecycleObserver>>;
+
+    /// Installs a handler that is told when the GPU of this device is removed or reset, or the
+    /// displays are reconfigured, replacing any previous one.
+    ///
+    /// Pass `None` to remove the handler. Events are delivered asynchronously, on a thread that
+    /// surfman starts for the handler, so the handler must hand them over to the threads that own
+    /// the affected contexts. Removals are detected where the platform reports them (Direct3D 11
+    /// on Windows). Resets are detected when making a context current or presenting through this
+    /// trait fails because the context was lost. Display changes are detected by checking
+    /// `Connection::displays()` once a second, so they aren't reported on platforms that can't
+    /// list their displays. The same failure may be reported more than once, as a `Reset`
+    /// followed by a `Removed`, for instance.
+    fn set_device_event_handler(&mut self, handler: Option<DeviceEventHandler>);
+
+    /// Returns the device event handler installed on this device, if any.
+    fn device_event_handler(&self) -> Option<DeviceEventHandler>;
+
+    /// Returns the GPU memory, in bytes, held by the surfaces of this device.
+    ///
+    /// This is the sum of `surface_memory_usage()` over the surfaces currently allocated on this
+    /// device, so that memory can be attributed to the device's owner and trimmed under pressure.
+    fn memory_usage(&self) -> usize;
+
+    /// Returns how long opening this device, and creating its context descriptors and contexts,
+    /// took, to find out where slow startup spends its time.
+    ///
+    /// Clones of a device share its timings.
+    fn creation_timings(&self) -> CreationTimings;
+
     // context.rs
 
     /// Creates a context descriptor with the given attributes.
@@ -53,10 +153,25 @@ where
         attributes: &ContextAttributes,
     ) -> Result<Self::ContextDescriptor, Error>;
 
+    /// Returns a small ID for the given context descriptor, which is the same for every equal
+    /// descriptor.
+    ///
+    /// The IDs are shared with clones of this device, and the descriptors behind them are kept
+    /// for as long as it lives. Higher-level code can key its caches of contexts and pipeline
+    /// state by them instead of keeping its own table of descriptors.
+    fn intern_context_descriptor(
+        &self,
+        context_descriptor: &Self::ContextDescriptor,
+    ) -> ContextDescriptorID;
+
     /// Creates a new OpenGL context.
     ///
     /// The context initially has no surface attached. Until a surface is bound to it, rendering
     /// commands will fail or have no effect.
+    ///
+    /// If the descriptor was created with `required_extensions`, the new context is briefly made
+    /// current to check that it supports them, after which the previously current context is
+    /// restored. If it lacks any, it's destroyed again and `Error::MissingExtensions` is returned.
     fn create_context(
         &mut self,
         descriptor: &Self::ContextDescriptor,
@@ -64,11 +179,33 @@ where
     ) -> Result<Self::Context, Error>;
 
     /// Wraps a native context object in an OpenGL context.
+    ///
+    /// # Safety
+    ///
+    /// The native context must be valid, and must stay alive as long as the returned context unless
+    /// the backend documents that it's retained.
     unsafe fn create_context_from_native_context(
         &self,
         native_context: Self::NativeContext,
     ) -> Result<Self::Context, Error>;
 
+    /// Wraps the OpenGL context that is current on this thread, along with the display or device
+    /// that it was created on, in a new device and context.
+    ///
+    /// This lets an application that set up OpenGL on its own move to surfman a piece at a time.
+    /// As with `create_context_from_native_context()`, the context keeps rendering to whatever it
+    /// was current with, so surfaces can't be bound to it, and destroying it leaves the native
+    /// context alone.
+    ///
+    /// If no context is current, this returns a `NoCurrentContext` error. With the multi backend,
+    /// the default backend is tried first.
+    ///
+    /// # Safety
+    ///
+    /// Except where a backend says otherwise, neither the native context nor its display is
+    /// retained, so both must outlive the returned device and context.
+    unsafe fn from_current_context() -> Result<(Self, Self::Context), Error>;
+
     /// Destroys a context.
     ///
     /// The context must have been created on this device.
@@ -82,6 +219,23 @@ where
     /// After calling this function, it is valid to use OpenGL rendering commands.
     fn make_context_current(&self, context: &Self::Context) -> Result<(), Error>;
 
+    /// Makes the context the current OpenGL context for this thread, rendering to the given
+    /// surface instead of any surface bound to it.
+    ///
+    /// This lets one context render to the widget surfaces of several windows without binding
+    /// and unbinding them every frame, and without a context per window. The surface stays with
+    /// the caller: render to the framebuffer object from `surface_info()`, then pass the surface
+    /// to `present_surface()`. The surface must have been created with this context, or an
+    /// `IncompatibleSurface` error is returned.
+    ///
+    /// The surface stays the rendering target until the context is made current again with
+    /// `make_context_current()`, which switches back to the surface bound to the context, if any.
+    fn make_context_current_with_surface(
+        &self,
+        context: &Self::Context,
+        surface: &Self::Surface,
+    ) -> Result<(), Error>;
+
     /// Removes the current OpenGL context from this thread.
     ///
     /// After calling this function, OpenGL rendering commands will fail until a new context is
@@ -103,6 +257,15 @@ where
     /// load OpenGL function pointers.
     fn get_proc_address(&self, context: &Self::Context, symbol_name: &str) -> *const c_void;
 
+    /// Calls the given function with the OpenGL functions that surfman uses with this context.
+    ///
+    /// The functions are loaded once by the backend rather than on every call, and are only
+    /// valid while the context is current.
+    #[doc(hidden)]
+    fn with_gl_functions<F, R>(&self, context: &Self::Context, callback: F) -> R
+    where
+        F: FnOnce(&Gl) -> R;
+
     /// Attaches a surface to a context for rendering.
     ///
     /// This function takes ownership of the surface. The surface must have been created with this
@@ -123,11 +286,44 @@ where
     ///
     /// Any pending OpenGL commands targeting this surface will be automatically flushed, so the
     /// surface is safe to read from immediately when this function returns.
+    ///
+    /// If the context was rendering to a framebuffer object given to
+    /// `bind_external_framebuffer()`, that framebuffer object is detached and `None` is returned.
     fn unbind_surface_from_context(
         &self,
         context: &mut Self::Context,
     ) -> Result<Option<Self::Surface>, Error>;
 
+    /// Makes a context render to a framebuffer object that the caller created and owns, in place
+    /// of a surface.
+    ///
+    /// Afterward, `context_surface_info()` reports the framebuffer object and size, so code that
+    /// renders to whatever the context's surface is draws into it. The framebuffer object must
+    /// belong to this context or one that shares with it, and must stay alive until it's detached
+    /// again with `unbind_surface_from_context()`; surfman never deletes it.
+    ///
+    /// If a surface is already bound, a `SurfaceAlreadyBound` error is returned. If the context
+    /// already renders to an external render target, including another framebuffer object given
+    /// to this function, an `ExternalRenderTarget` error is returned.
+    fn bind_external_framebuffer(
+        &self,
+        context: &mut Self::Context,
+        framebuffer_object: GLuint,
+        size: Size2D<i32>,
+    ) -> Result<(), Error>;
+
+    /// Makes the rendering submitted so far to a surface visible to everything else that reads
+    /// it: surface textures in other contexts, other threads and processes, and other APIs.
+    ///
+    /// This flushes the context and waits on a fence for the rendering to finish, or does whatever
+    /// else the backend needs, so that the surface is safe to read from when this function
+    /// returns. It's meant for surfaces that are rendered to through their framebuffer objects
+    /// while they aren't bound; `unbind_surface_from_context()` already flushes bound ones.
+    ///
+    /// The surface must have been created with this context, or an `IncompatibleSurface` error is
+    /// returned.
+    fn flush_context(&self, context: &Self::Context, surface: &Self::Surface) -> Result<(), Error>;
+
     /// Returns a unique ID representing a context.
     ///
     /// This ID is unique to all currently-allocated contexts. If you destroy a context and create
@@ -139,22 +335,228 @@ where
     /// This includes, most notably, the OpenGL framebuffer object needed to render to the surface.
     fn context_surface_info(&self, context: &Self::Context) -> Result<Option<SurfaceInfo>, Error>;
 
+    /// Returns true if a surface is bound to the given context.
+    ///
+    /// Contexts that render to an external framebuffer have no surface bound.
+    fn context_has_surface(&self, context: &Self::Context) -> bool {
+        match self.context_surface_info(context) {
+            Ok(Some(_)) => true,
+            Ok(None) | Err(_) => false,
+        }
+    }
+
     /// Returns the native context associated with the given context.
     fn native_context(&self, context: &Self::Context) -> Self::NativeContext;
 
+    /// Attaches a debug name to a context.
+    ///
+    /// OpenGL has no object name for a context, so the label is only recorded by surfman and
+    /// returned from `context_label()`. It's forgotten when the context is destroyed.
+    fn set_context_label(&self, context: &Self::Context, label: &str) -> Result<(), Error> {
+        label::set_context_label(self.context_id(context), label);
+        Ok(())
+    }
+
+    /// Returns the debug name most recently given to a context with `set_context_label()`, if
+    /// any.
+    fn context_label(&self, context: &Self::Context) -> Option<String> {
+        label::context_label(self.context_id(context))
+    }
+
     // surface.rs
 
     /// Creates either a generic or a widget surface, depending on the supplied surface type.
     ///
     /// Only the given context may ever render to the surface, but generic surfaces can be wrapped
     /// up in a `SurfaceTexture` for reading by other contexts.
+    ///
+    /// The usage describes how the surface will be used, so that the backend can allocate it
+    /// accordingly. Pass `SurfaceUsage::default()` if there's nothing more specific to say.
+    ///
+    /// The alpha mode says how the alpha channel of the contents is to be interpreted. It is
+    /// reported in `SurfaceInfo`, and widget surfaces are composited accordingly where the window
+    /// system allows it. Widget surfaces can't use `SurfaceAlphaMode::Straight`; asking for it
+    /// returns `Error::UnsupportedOnThisPlatform`.
     fn create_surface(
         &mut self,
         context: &Self::Context,
         surface_access: SurfaceAccess,
+        surface_usage: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
         surface_type: SurfaceType<<Self::Connection as ConnectionInterface>::NativeWidget>,
     ) -> Result<Self::Surface, Error>;
 
+    /// Creates `count` generic surfaces of the given size, as if by calling `create_surface()`
+    /// that many times.
+    ///
+    /// Backends share the work common to all of the surfaces, such as looking up the context's
+    /// configuration and making it current, across the whole batch, which makes this cheaper than
+    /// a loop when prewarming pools or allocating the tiles of a tiled renderer.
+    ///
+    /// If one of the surfaces fails to be created, the error is returned along with the surfaces
+    /// that were created before it, which the caller must destroy.
+    fn create_surfaces(
+        &mut self,
+        context: &Self::Context,
+        surface_access: SurfaceAccess,
+        surface_usage: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
+        size: &Size2D<i32>,
+        count: usize,
+    ) -> CreateSurfacesResult<Self::Surface>;
+
+    /// Creates a generic surface with the given number of layers, each of the given size.
+    ///
+    /// Surface textures of a layered surface are bound to `GL_TEXTURE_2D_ARRAY`, as
+    /// `surface_texture_binding_target()` reports, so one surface can hold many tiles, or the
+    /// layers of a layered compositor. Rendering to the surface draws into the layer chosen with
+    /// `set_surface_layer()`, initially the first.
+    ///
+    /// `layers` must be at least 1, or this returns `Error::InvalidSurfaceLayer`. Layered surfaces
+    /// are supported on Android and by the EGL backends on Linux (surfaceless, Wayland, and X11);
+    /// the other backends return `UnsupportedOnThisPlatform`. On Android the layers share one
+    /// hardware buffer. The Linux backends can't share arrays of images through EGL, so there
+    /// surface textures of layered surfaces hold a copy of the layers, made when they're created.
+    fn create_layered_surface(
+        &mut self,
+        _: &Self::Context,
+        _: SurfaceAccess,
+        _: SurfaceUsage,
+        _: &Size2D<i32>,
+        _: u32,
+    ) -> Result<Self::Surface, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Chooses the layer of a layered surface that rendering to the surface draws into.
+    ///
+    /// The supplied context must be the context the surface is associated with, or this returns
+    /// an `IncompatibleSurface` error. Asking for a layer past the last returns
+    /// `Error::InvalidSurfaceLayer`. Backends without layered surfaces return
+    /// `UnsupportedOnThisPlatform`.
+    fn set_surface_layer(
+        &self,
+        _: &Self::Context,
+        _: &mut Self::Surface,
+        _: u32,
+    ) -> Result<(), Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Returns the number of layers of a surface.
+    ///
+    /// This is 1 for all surfaces except those created with `create_layered_surface()`.
+    fn surface_layers(&self, _: &Self::Surface) -> u32 {
+        1
+    }
+
+    /// Creates either a generic or a widget surface like `create_surface()`, and clears its
+    /// contents to the given color before returning it.
+    ///
+    /// The contents of a new surface are otherwise undefined, so a widget surface presented before
+    /// the first full draw may show garbage. Pass `[0.0; 4]` to zero the surface. Only the color
+    /// buffer is cleared.
+    ///
+    /// To clear it, the surface is briefly bound to the context in place of the surface that's
+    /// attached to it, which is then reattached. This leaves the context current.
+    fn create_cleared_surface(
+        &mut self,
+        context: &mut Self::Context,
+        surface_access: SurfaceAccess,
+        surface_usage: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
+        surface_type: SurfaceType<<Self::Connection as ConnectionInterface>::NativeWidget>,
+        clear_color: [f32; 4],
+    ) -> Result<Self::Surface, Error> {
+        let mut surface = self.create_surface(
+            context,
+            surface_access,
+            surface_usage,
+            surface_alpha_mode,
+            surface_type,
+        )?;
+        let old_surface = match self.unbind_surface_from_context(context) {
+            Ok(old_surface) => old_surface,
+            Err(err) => {
+                self.destroy_surface(context, &mut surface)?;
+                return Err(err);
+            }
+        };
+
+        let result = match self.bind_surface_to_context(context, surface) {
+            Ok(()) => {
+                let cleared = self
+                    .make_context_current(context)
+                    .and_then(|()| self.context_surface_info(context));
+                if let Ok(Some(ref surface_info)) = cleared {
+                    self.with_gl_functions(context, |gl| {
+                        clear_framebuffer(gl, surface_info.framebuffer_object, clear_color)
+                    });
+                }
+                match (self.unbind_surface_from_context(context), cleared) {
+                    (Ok(Some(surface)), Ok(_)) => Ok(surface),
+                    (Ok(Some(mut surface)), Err(err)) => {
+                        drop(self.destroy_surface(context, &mut surface));
+                        Err(err)
+                    }
+                    (Ok(None), _) => Err(Error::Failed),
+                    (Err(err), _) => Err(err),
+                }
+            }
+            Err((err, mut surface)) => {
+                drop(self.destroy_surface(context, &mut surface));
+                Err(err)
+            }
+        };
+
+        if let Some(old_surface) = old_surface {
+            if let Err((err, mut old_surface)) = self.bind_surface_to_context(context, old_surface)
+            {
+                drop(self.destroy_surface(context, &mut old_surface));
+                if let Ok(mut surface) = result {
+                    drop(self.destroy_surface(context, &mut surface));
+                }
+                return Err(err);
+            }
+        }
+
+        result
+    }
+
+    /// Creates a new generic surface with a copy of the contents of the given one, stored with
+    /// its color channels in the given order.
+    ///
+    /// The channel order of a surface's storage depends on the platform and is reported by the
+    /// `format` field of `SurfaceInfo`. Converting a surface before handing its storage to
+    /// another API spares consumers from swizzling. Shaders sampling the new surface see the
+    /// swizzled contents if its format differs from the platform's native one.
+    ///
+    /// The surface must be a generic surface belonging to the given context, which is left
+    /// current. The new surface is GPU-only.
+    fn convert_surface_format(
+        &mut self,
+        context: &mut Self::Context,
+        surface: &Self::Surface,
+        format: SurfaceFormat,
+    ) -> Result<Self::Surface, Error>;
+
+    /// Wraps an existing OpenGL texture of the given context in a new generic surface, so that
+    /// textures produced outside surfman can be shared or presented like any other surface.
+    ///
+    /// The texture must be a complete `GL_TEXTURE_2D` of the given size, with RGBA contents and
+    /// no mip levels beyond the first. Rendering to the surface draws into the texture's storage.
+    /// The texture stays owned by the caller, but must not be redefined, with `glTexImage2D()`
+    /// for instance, while the surface is alive. The given context is left current.
+    ///
+    /// Backends whose surfaces can't share storage with an OpenGL texture return an
+    /// `UnsupportedOnThisPlatform` error.
+    fn create_surface_from_texture(
+        &mut self,
+        context: &mut Self::Context,
+        size: &Size2D<i32>,
+        texture_object: GLuint,
+    ) -> Result<Self::Surface, Error>;
+
     /// Creates a surface texture from an existing generic surface for use with the given context.
     ///
     /// The surface texture is local to the supplied context and takes ownership of the surface.
@@ -164,6 +566,9 @@ where
     /// with.* This allows you to render to a surface in one context and sample from that surface
     /// in another context.
     ///
+    /// Generic surfaces are never multisampled, whatever `ContextAttributes::samples` asks for,
+    /// so the texture always holds single-sampled pixels and no resolve is needed.
+    ///
     /// Calling this method on a widget surface returns a `WidgetAttached` error.
     fn create_surface_texture(
         &self,
@@ -184,6 +589,186 @@ where
         surface: &mut Self::Surface,
     ) -> Result<(), Error>;
 
+    /// Gives up a surface, returning a token that `destroy_token()` can destroy later, on any
+    /// thread and without the surface's context.
+    ///
+    /// The OpenGL objects that the surface owns in its context, such as its framebuffer, aren't
+    /// part of the token. They're freed when the context is destroyed.
+    fn surface_into_destroy_token(&self, surface: Self::Surface) -> Self::SurfaceDestroyToken;
+
+    /// Destroys the native resources of a surface given up with `surface_into_destroy_token()`.
+    ///
+    /// Unlike `destroy_surface()`, this doesn't need the context that the surface belonged to.
+    fn destroy_token(&self, token: Self::SurfaceDestroyToken) -> Result<(), Error>;
+
+    /// Queues a surface to be destroyed once its context is available, for threads that can't
+    /// make that context current.
+    ///
+    /// Any device of the same backend can queue the surface, on any thread. It's destroyed the
+    /// next time its context is made current with `make_context_current()` or destroyed, or when
+    /// `destroy_deferred_surfaces()` is called with the context. Checking the queue costs a single
+    /// atomic load while it's empty.
+    fn defer_destroy_surface(&self, surface: Self::Surface)
+    where
+        Self::Surface: Send + 'static,
+    {
+        deferred::defer_destroy_surface(self.surface_info(&surface).context_id, surface);
+    }
+
+    /// Destroys the surfaces queued for the given context with `defer_destroy_surface()`.
+    ///
+    /// If a surface fails to be destroyed, it and the surfaces after it stay queued.
+    fn destroy_deferred_surfaces(&self, context: &mut Self::Context) -> Result<(), Error>
+    where
+        Self::Surface: Send + 'static,
+    {
+        let context_id = self.context_id(context);
+        let mut surfaces =
+            deferred::take_deferred_surfaces::<Self::Surface>(context_id).into_iter();
+        while let Some(mut surface) = surfaces.next() {
+            if let Err(err) = self.destroy_surface(context, &mut surface) {
+                deferred::defer_destroy_surface(context_id, surface);
+                for surface in surfaces {
+                    deferred::defer_destroy_surface(context_id, surface);
+                }
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads back the contents of a surface and destroys it, returning its pixels along with the
+    /// number of bytes per row and the order of the color channels.
+    ///
+    /// Rows run from top to bottom and are packed tightly, so the stride is always four bytes per
+    /// pixel. The channels are in the order of the surface's storage where OpenGL can read that
+    /// order back, and RGBA otherwise. This is meant for tests and screenshots, since it stalls
+    /// until rendering to the surface is done.
+    ///
+    /// To read it, the surface is briefly bound to the context in place of the surface that's
+    /// attached to it, which is then reattached. This leaves the context current. The surface is
+    /// destroyed even if reading it fails.
+    fn surface_into_pixels(
+        &self,
+        context: &mut Self::Context,
+        mut surface: Self::Surface,
+    ) -> Result<(Vec<u8>, usize, SurfaceFormat), Error> {
+        let surface_info = self.surface_info(&surface);
+        let format = match surface_info.format {
+            SurfaceFormat::BGRA8 if self.gl_api() == GLApi::GL => SurfaceFormat::BGRA8,
+            _ => SurfaceFormat::RGBA8,
+        };
+
+        let old_surface = match self.unbind_surface_from_context(context) {
+            Ok(old_surface) => old_surface,
+            Err(err) => {
+                self.destroy_surface(context, &mut surface)?;
+                return Err(err);
+            }
+        };
+
+        let result = match self.bind_surface_to_context(context, surface) {
+            Ok(()) => {
+                let pixels = self
+                    .make_context_current(context)
+                    .and_then(|()| self.context_surface_info(context))
+                    .and_then(|surface_info| match surface_info {
+                        Some(surface_info) => Ok(self.with_gl_functions(context, |gl| {
+                            read_framebuffer_pixels(
+                                gl,
+                                surface_info.framebuffer_object,
+                                &surface_info.size,
+                                format,
+                            )
+                        })),
+                        None => Err(Error::Failed),
+                    });
+                match self.unbind_surface_from_context(context) {
+                    Ok(Some(mut surface)) => {
+                        let destroyed = self.destroy_surface(context, &mut surface);
+                        pixels.and_then(|pixels| destroyed.map(|()| pixels))
+                    }
+                    Ok(None) => Err(Error::Failed),
+                    Err(err) => Err(err),
+                }
+            }
+            Err((err, mut surface)) => {
+                drop(self.destroy_surface(context, &mut surface));
+                Err(err)
+            }
+        };
+
+        if let Some(old_surface) = old_surface {
+            if let Err((err, mut old_surface)) = self.bind_surface_to_context(context, old_surface)
+            {
+                drop(self.destroy_surface(context, &mut old_surface));
+                return Err(err);
+            }
+        }
+
+        let stride = surface_info.size.width as usize * 4;
+        result.map(|pixels| (pixels, stride, format))
+    }
+
+    /// Writes the contents of a surface to a PNG file at the given path, for debugging.
+    ///
+    /// The surface must belong to the given context, which is left current. Widget surfaces can
+    /// only be read while they're attached to it; otherwise, this returns a `WidgetAttached`
+    /// error. The same goes for generic surfaces on backends where they have no framebuffer object
+    /// of their own (GLX, ANGLE, and OSMesa), for which this returns `UnsupportedOnThisPlatform`
+    /// instead; `surface_into_pixels()` can read those. If the file can't be written, this returns
+    /// `Error::Failed`.
+    #[cfg(feature = "sm-snapshot")]
+    fn write_surface_png<P>(
+        &self,
+        context: &Self::Context,
+        surface: &Self::Surface,
+        path: P,
+    ) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let surface_info = self.surface_info(surface);
+        if surface_info.context_id != self.context_id(context) {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        // The framebuffer object of a widget surface may be the default framebuffer, which only
+        // refers to the surface while it's attached. Some backends render generic surfaces through
+        // the default framebuffer too.
+        let attached = match self.context_surface_info(context)? {
+            Some(context_surface_info) => context_surface_info.id == surface_info.id,
+            None => false,
+        };
+        if surface_info.framebuffer_object == 0 && !attached {
+            return match self.widget_output_info(surface) {
+                Err(Error::NoWidgetAttached) => Err(Error::UnsupportedOnThisPlatform),
+                _ => Err(Error::WidgetAttached),
+            };
+        }
+
+        self.make_context_current(context)?;
+        let pixels = self.with_gl_functions(context, |gl| {
+            read_framebuffer_pixels(
+                gl,
+                surface_info.framebuffer_object,
+                &surface_info.size,
+                SurfaceFormat::RGBA8,
+            )
+        });
+
+        let file = File::create(path).map_err(|_| Error::Failed)?;
+        let mut encoder = png::Encoder::new(
+            BufWriter::new(file),
+            surface_info.size.width as u32,
+            surface_info.size.height as u32,
+        );
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(|_| Error::Failed)?;
+        writer.write_image_data(&pixels).map_err(|_| Error::Failed)
+    }
+
     /// Destroys a surface texture and returns the underlying surface.
     ///
     /// The supplied context must be the same context the surface texture was created with, or an
@@ -199,14 +784,54 @@ where
 
     /// Returns the OpenGL texture target needed to read from this surface texture.
     ///
-    /// This will be `GL_TEXTURE_2D` or `GL_TEXTURE_RECTANGLE`, depending on platform.
+    /// This will be `GL_TEXTURE_2D` or `GL_TEXTURE_RECTANGLE`, depending on platform. Some surface
+    /// textures, such as those of layered surfaces, are bound to another target, which
+    /// `surface_texture_binding_target()` returns.
     fn surface_gl_texture_target(&self) -> GLenum;
 
+    /// Returns the OpenGL texture target that the given surface texture is bound to.
+    ///
+    /// This is `GL_TEXTURE_2D_ARRAY` for surface textures of layered surfaces, and
+    /// `surface_gl_texture_target()` for most others.
+    fn surface_texture_binding_target(&self, _: &Self::SurfaceTexture) -> GLenum {
+        self.surface_gl_texture_target()
+    }
+
+    /// Returns the number of layers of the surface that a surface texture wraps.
+    ///
+    /// This is 1 for all surface textures except those of surfaces created with
+    /// `create_layered_surface()`.
+    fn surface_texture_layers(&self, _: &Self::SurfaceTexture) -> u32 {
+        1
+    }
+
+    /// Fills in the mip chain of a surface texture from the current contents of its surface, so
+    /// that it can be sampled with mipmapping at scales well below its size.
+    ///
+    /// Call this after each frame rendered to the surface that will be sampled this way. The
+    /// texture's minification filter is switched to `GL_LINEAR_MIPMAP_LINEAR`.
+    ///
+    /// The given context must be the one the surface texture was created for, and is left current.
+    /// Where surface textures share the surface's storage, the mip chain has to live in that
+    /// storage, so this returns an `UnsupportedOnThisPlatform` error unless the surface was created
+    /// with `SurfaceUsage::MIPMAPPED` on a backend that can allocate it. Backends whose surface
+    /// textures can't be mipmapped at all, such as rectangle textures or textures bound to EGL
+    /// images, always return that error. OSMesa surface textures are copies of their surfaces, so
+    /// they can always be mipmapped.
+    fn generate_surface_mipmaps(
+        &self,
+        context: &mut Self::Context,
+        surface_texture: &Self::SurfaceTexture,
+    ) -> Result<(), Error>;
+
     /// Displays the contents of a widget surface on screen.
     ///
     /// Widget surfaces are internally double-buffered, so changes to them don't show up in their
     /// associated widgets until this method is called.
     ///
+    /// Multisampled widget surfaces are resolved by the window system as their buffers are
+    /// swapped, so what is displayed is always single-sampled.
+    ///
     /// The supplied context must match the context the surface was created with, or an
     /// `IncompatibleSurface` error is returned.
     fn present_surface(
@@ -215,6 +840,150 @@ where
         surface: &mut Self::Surface,
     ) -> Result<(), Error>;
 
+    /// Displays the contents of a widget surface on screen, hinting that only the given
+    /// rectangles have changed since the last present.
+    ///
+    /// The rectangles are in device pixels, with the origin at the lower left corner of the
+    /// surface as in OpenGL. An empty slice means that the whole surface changed. Where the
+    /// platform supports it (e.g. `EGL_KHR_swap_buffers_with_damage`), the compositor only
+    /// updates the damaged area; elsewhere, this is equivalent to `present_surface()`.
+    fn present_surface_with_damage(
+        &self,
+        context: &Self::Context,
+        surface: &mut Self::Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error>;
+
+    /// Displays the `source` rectangle of a widget surface in the `destination` rectangle of its
+    /// widget, scaling it to fit.
+    ///
+    /// This allows content rendered at a fixed resolution to be letterboxed or scaled into a
+    /// widget of a different size without an extra blit pass on the caller's side. Both rectangles
+    /// are in device pixels, with the origin at the lower left corner as in OpenGL, and must be
+    /// nonempty. The parts of the widget outside `destination` are transparent black. Where the
+    /// platform compositor can crop and scale buffers (e.g. `ASurfaceControl`), it does the work;
+    /// otherwise the back buffer is rescaled in place before swapping, so its contents are
+    /// undefined afterward.
+    ///
+    /// The viewport applies only to this present; `present_surface()` shows the whole surface
+    /// again.
+    fn present_surface_with_viewport(
+        &self,
+        context: &Self::Context,
+        surface: &mut Self::Surface,
+        source: &Rect<i32>,
+        destination: &Rect<i32>,
+    ) -> Result<(), Error>;
+
+    /// Blits a generic surface into a framebuffer that surfman doesn't own, such as the default
+    /// framebuffer of a window whose swap chain is managed by glutin or a game engine.
+    ///
+    /// The whole surface is scaled into `viewport` with linear filtering, leaving the rest of the
+    /// target framebuffer alone. The viewport is in device pixels, with the origin at the lower
+    /// left corner as in OpenGL. The target must be a framebuffer of the given context: for
+    /// example, 0 for the default framebuffer of a context wrapped with
+    /// `create_context_from_native_context()`. Swapping it onto the screen is up to its owner.
+    ///
+    /// The surface must belong to the given context, which is left current, or this returns an
+    /// `IncompatibleSurface` error. Surfaces without a framebuffer object of their own to read
+    /// from, which are widget surfaces and the generic surfaces of the GLX, ANGLE, and OSMesa
+    /// backends, return `UnsupportedOnThisPlatform`.
+    fn present_to_external_framebuffer(
+        &self,
+        context: &Self::Context,
+        surface: &Self::Surface,
+        target_framebuffer_object: GLuint,
+        viewport: &Rect<i32>,
+    ) -> Result<(), Error> {
+        let surface_info = self.surface_info(surface);
+        if surface_info.context_id != self.context_id(context) {
+            return Err(Error::IncompatibleSurface);
+        }
+        if surface_info.framebuffer_object == 0 {
+            return Err(Error::UnsupportedOnThisPlatform);
+        }
+
+        self.make_context_current(context)?;
+        self.with_gl_functions(context, |gl| {
+            blit_framebuffer(
+                gl,
+                surface_info.framebuffer_object,
+                &surface_info.size,
+                target_framebuffer_object,
+                viewport,
+            )
+        });
+        Ok(())
+    }
+
+    /// Declares which parts of a widget surface will be redrawn before it is next presented.
+    ///
+    /// This must be called before any rendering to the frame. Afterward, the contents of the
+    /// surface outside the region are undefined if the platform supports partial updates
+    /// (`EGL_KHR_partial_update`), which lets tiled renderers skip loading them. Elsewhere, this
+    /// does nothing. An empty slice means that the whole surface will be redrawn.
+    ///
+    /// Use `surface_buffer_age()` to find out which region needs redrawing.
+    fn set_surface_damage_region(
+        &self,
+        context: &Self::Context,
+        surface: &mut Self::Surface,
+        region: &[Rect<i32>],
+    ) -> Result<(), Error>;
+
+    /// Returns the number of presents ago that the current back buffer of a widget surface was
+    /// displayed.
+    ///
+    /// For example, 1 means that the back buffer holds the last frame presented, and 2 means the
+    /// frame before that. 0 means that its contents are undefined and the whole surface must be
+    /// redrawn, which is also returned when the platform doesn't report buffer ages.
+    fn surface_buffer_age(
+        &self,
+        context: &Self::Context,
+        surface: &Self::Surface,
+    ) -> Result<u32, Error>;
+
+    /// Sets whether presenting a widget surface preserves its contents.
+    ///
+    /// Widget surfaces are created without preservation, so their contents are undefined after
+    /// `present_surface()`. With it, applications that only repaint the dirty regions of each
+    /// frame can draw over the previous one, at the cost of a copy per present on some hardware.
+    /// Where the platform or the surface's configuration can't preserve contents, this returns
+    /// `Error::UnsupportedOnThisPlatform`.
+    fn set_surface_preserve_buffer(
+        &self,
+        context: &Self::Context,
+        surface: &mut Self::Surface,
+        preserve: bool,
+    ) -> Result<(), Error>;
+
+    /// Sets which way up the contents of a surface are, as reported by `surface_info()`.
+    ///
+    /// Renderers that draw with the origin at the upper left can set `TopLeft` instead of
+    /// flipping each frame themselves. Widget surfaces are then displayed flipped, which takes
+    /// effect at the next present. Where the platform can't flip a widget surface, this returns
+    /// `Error::UnsupportedOnThisPlatform`; generic surfaces only record the origin for the
+    /// consumers of their contents.
+    fn set_surface_origin(
+        &self,
+        surface: &mut Self::Surface,
+        origin: SurfaceOrigin,
+    ) -> Result<(), Error>;
+
+    /// Sets the color space that the contents of a surface are in, as reported by
+    /// `surface_info()`.
+    ///
+    /// Widget surfaces are tagged with the color space when they are presented, so that the
+    /// compositor converts wide-gamut contents for the display instead of taking them to be sRGB.
+    /// Where the platform can't tag a widget surface, this returns
+    /// `Error::UnsupportedOnThisPlatform` for anything but `SRGB`; generic surfaces only record
+    /// the color space for the consumers of their contents.
+    fn set_surface_colorspace(
+        &self,
+        surface: &mut Self::Surface,
+        colorspace: Colorspace,
+    ) -> Result<(), Error>;
+
     /// Resizes a widget surface.
     fn resize_surface(
         &self,
@@ -231,8 +1000,238 @@ where
     /// 0, the default framebuffer, depending on platform.
     fn surface_info(&self, surface: &Self::Surface) -> SurfaceInfo;
 
+    /// Returns true if the surface is bound to its context.
+    ///
+    /// Binding a surface moves it into its context until it's unbound, so this is false for every
+    /// surface that the caller holds. Swap-chain code can assert it before handing a surface to
+    /// `bind_surface_to_context()` or `destroy_surface()`.
+    fn surface_is_bound(&self, surface: &Self::Surface) -> bool {
+        self.surface_info(surface).bound
+    }
+
+    /// Returns the GPU memory, in bytes, held by the given surface.
+    ///
+    /// Where the platform reports the size of the allocation (`IOSurfaceGetAllocSize()` on macOS,
+    /// `AHardwareBuffer_describe()` on Android), this includes row padding and any extra layers.
+    /// Elsewhere, this is an estimate that counts four bytes per pixel of color storage.
+    fn surface_memory_usage(&self, surface: &Self::Surface) -> usize;
+
+    /// Returns a counter that goes up whenever the given surface is modified.
+    ///
+    /// Consumers of a surface shared with another process can compare it with the value seen last
+    /// time to find out cheaply whether the surface has been written to since, and skip
+    /// recompositing it if not. On macOS, this is the `IOSurfaceGetSeed()` seed, which counts
+    /// writes from every process. Where the platform keeps no such counter, this returns
+    /// `Error::UnsupportedOnThisPlatform`.
+    fn surface_seed(&self, surface: &Self::Surface) -> Result<u32, Error>;
+
+    /// Returns the stride, row alignment, and total size of the memory that a generic surface of
+    /// the given size, format, and access would be allocated with, without creating one.
+    ///
+    /// Callers that generate pixel data on the CPU can use this to lay it out to match before any
+    /// surface exists. Where the platform chooses the layout (`IOSurface`s on macOS,
+    /// `AHardwareBuffer`s on Android), rows may be padded. Elsewhere, rows are packed tightly at
+    /// four bytes per pixel, as `lock_surface_data()` and readbacks see them.
+    fn surface_layout_requirements(
+        &self,
+        size: &Size2D<i32>,
+        format: SurfaceFormat,
+        access: SurfaceAccess,
+    ) -> Result<SurfaceLayoutRequirements, Error>;
+
+    /// Returns timing information about the most recently displayed frame of a widget surface.
+    ///
+    /// This returns `None` if no frame has been displayed yet, if the surface is not a widget
+    /// surface, or if the platform doesn't report presentation timing.
+    fn surface_presentation_statistics(
+        &self,
+        surface: &Self::Surface,
+    ) -> Option<PresentationStatistics>;
+
+    /// Returns whether a widget surface can currently be seen on screen.
+    ///
+    /// This returns `SurfaceVisibility::Unknown` for generic surfaces and on platforms that don't
+    /// report visibility. Some platforms only learn about visibility as frames are presented, so
+    /// keep presenting occasionally while a surface is occluded to find out when it becomes visible
+    /// again.
+    fn surface_visibility(&self, surface: &Self::Surface) -> SurfaceVisibility;
+
+    /// Returns the color space, bit depth, and HDR capability of the display that a widget surface
+    /// is shown on, for choosing a surface format and tone mapping.
+    ///
+    /// Platforms that don't report some of these describe the display as 8-bit sRGB without HDR.
+    /// The display can change as the widget is moved, so check again when it is. This returns
+    /// `Error::NoWidgetAttached` for generic surfaces.
+    fn widget_output_info(&self, surface: &Self::Surface) -> Result<WidgetOutputInfo, Error>;
+
     /// Returns the OpenGL texture object containing the contents of this surface.
     ///
     /// It is only legal to read from, not write to, this texture object.
     fn surface_texture_object(&self, surface_texture: &Self::SurfaceTexture) -> GLuint;
+
+    /// Attaches a debug name to a surface, so that it shows up under that name in GPU captures
+    /// from tools like RenderDoc.
+    ///
+    /// The label is recorded by surfman and returned from `surface_label()`. If the driver
+    /// supports `GL_KHR_debug`, it's also given with `glObjectLabel()` to the surface's
+    /// framebuffer object and the textures and renderbuffers attached to it. On Windows, the
+    /// Direct3D texture behind a generic surface is named too, and on macOS, the `IOSurface`s
+    /// behind the surface; elsewhere, and for widget surfaces rendered through the default
+    /// framebuffer, only surfman sees the label. The label is forgotten when the surface is
+    /// destroyed.
+    ///
+    /// The surface must belong to the given context. The context that was current before is made
+    /// current again afterward.
+    fn set_surface_label(
+        &self,
+        context: &Self::Context,
+        surface: &Self::Surface,
+        label: &str,
+    ) -> Result<(), Error> {
+        let surface_info = self.surface_info(surface);
+        if surface_info.context_id != self.context_id(context) {
+            return Err(Error::IncompatibleSurface);
+        }
+        label::set_surface_label(surface_info.id, label);
+        Ok(())
+    }
+
+    /// Returns the debug name most recently given to a surface with `set_surface_label()`, if
+    /// any.
+    fn surface_label(&self, surface: &Self::Surface) -> Option<String> {
+        label::surface_label(self.surface_info(surface).id)
+    }
+}
+
+// From `GL_EXT_bgra`, which is core in desktop OpenGL.
+const GL_BGRA: GLenum = 0x80e1;
+
+// The pixel pack parameters that `glReadPixels()` honors, and the values that pack the rows of
+// four-byte pixels tightly.
+const TIGHT_PACK_PARAMETERS: [(GLenum, GLint); 4] = [
+    (gl::PACK_ALIGNMENT, 4),
+    (gl::PACK_ROW_LENGTH, 0),
+    (gl::PACK_SKIP_PIXELS, 0),
+    (gl::PACK_SKIP_ROWS, 0),
+];
+
+// Reads back the color buffer of the given framebuffer, top row first, leaving the rest of the GL
+// state as it was.
+fn read_framebuffer_pixels(
+    gl: &Gl,
+    framebuffer_object: GLuint,
+    size: &Size2D<i32>,
+    format: SurfaceFormat,
+) -> Vec<u8> {
+    let stride = size.width as usize * 4;
+    let height = size.height as usize;
+    let mut pixels = vec![0; stride * height];
+    unsafe {
+        let (mut old_framebuffer_object, mut old_pack_buffer) = (0, 0);
+        gl.GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut old_framebuffer_object);
+        gl.GetIntegerv(gl::PIXEL_PACK_BUFFER_BINDING, &mut old_pack_buffer);
+        // The caller's pack parameters would otherwise pad or offset the rows in `pixels`.
+        let mut old_pack_parameters = [0; 4];
+        for (index, &(parameter, value)) in TIGHT_PACK_PARAMETERS.iter().enumerate() {
+            gl.GetIntegerv(parameter, &mut old_pack_parameters[index]);
+            gl.PixelStorei(parameter, value);
+        }
+
+        gl.BindFramebuffer(gl::READ_FRAMEBUFFER, framebuffer_object);
+        gl.BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+        let gl_format = match format {
+            SurfaceFormat::RGBA8 => gl::RGBA,
+            SurfaceFormat::BGRA8 => GL_BGRA,
+        };
+        gl.ReadPixels(
+            0,
+            0,
+            size.width,
+            size.height,
+            gl_format,
+            gl::UNSIGNED_BYTE,
+            pixels.as_mut_ptr() as *mut c_void,
+        );
+
+        for (index, &(parameter, _)) in TIGHT_PACK_PARAMETERS.iter().enumerate() {
+            gl.PixelStorei(parameter, old_pack_parameters[index]);
+        }
+        gl.BindBuffer(gl::PIXEL_PACK_BUFFER, old_pack_buffer as GLuint);
+        gl.BindFramebuffer(gl::READ_FRAMEBUFFER, old_framebuffer_object as GLuint);
+    }
+
+    // OpenGL reads the bottom row first.
+    for row in 0..(height / 2) {
+        let (upper, lower) = pixels.split_at_mut((height - row - 1) * stride);
+        upper[(row * stride)..((row + 1) * stride)].swap_with_slice(&mut lower[..stride]);
+    }
+    pixels
+}
+
+// Scales the color buffer of one framebuffer into a rectangle of another with linear filtering,
+// leaving the rest of the GL state as it was.
+fn blit_framebuffer(
+    gl: &Gl,
+    source_framebuffer_object: GLuint,
+    source_size: &Size2D<i32>,
+    destination_framebuffer_object: GLuint,
+    destination: &Rect<i32>,
+) {
+    unsafe {
+        let (mut old_read_framebuffer, mut old_draw_framebuffer) = (0, 0);
+        gl.GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut old_read_framebuffer);
+        gl.GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut old_draw_framebuffer);
+        // Of the per-fragment operations, only the scissor test applies to blits.
+        let scissor_test = gl.IsEnabled(gl::SCISSOR_TEST);
+
+        gl.BindFramebuffer(gl::READ_FRAMEBUFFER, source_framebuffer_object);
+        gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, destination_framebuffer_object);
+        gl.Disable(gl::SCISSOR_TEST);
+        gl.BlitFramebuffer(
+            0,
+            0,
+            source_size.width,
+            source_size.height,
+            destination.origin.x,
+            destination.origin.y,
+            destination.max_x(),
+            destination.max_y(),
+            gl::COLOR_BUFFER_BIT,
+            gl::LINEAR,
+        );
+
+        if scissor_test == gl::TRUE {
+            gl.Enable(gl::SCISSOR_TEST);
+        }
+        gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, old_draw_framebuffer as GLuint);
+        gl.BindFramebuffer(gl::READ_FRAMEBUFFER, old_read_framebuffer as GLuint);
+    }
+}
+
+// Clears the color buffer of the given framebuffer, leaving the rest of the GL state as it was.
+fn clear_framebuffer(gl: &Gl, framebuffer_object: GLuint, color: [f32; 4]) {
+    unsafe {
+        let mut old_framebuffer_object = 0;
+        let mut old_clear_color: [GLfloat; 4] = [0.0; 4];
+        let mut old_color_mask: [GLboolean; 4] = [gl::TRUE; 4];
+        gl.GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut old_framebuffer_object);
+        gl.GetFloatv(gl::COLOR_CLEAR_VALUE, old_clear_color.as_mut_ptr());
+        gl.GetBooleanv(gl::COLOR_WRITEMASK, old_color_mask.as_mut_ptr());
+        let scissor_test = gl.IsEnabled(gl::SCISSOR_TEST);
+
+        gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, framebuffer_object);
+        gl.Disable(gl::SCISSOR_TEST);
+        gl.ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
+        gl.ClearColor(color[0], color[1], color[2], color[3]);
+        gl.Clear(gl::COLOR_BUFFER_BIT);
+
+        let [r, g, b, a] = old_clear_color;
+        gl.ClearColor(r, g, b, a);
+        let [r, g, b, a] = old_color_mask;
+        gl.ColorMask(r, g, b, a);
+        if scissor_test == gl::TRUE {
+            gl.Enable(gl::SCISSOR_TEST);
+        }
+        gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, old_framebuffer_object as GLuint);
+    }
 }
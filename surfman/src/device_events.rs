@@ -0,0 +1,127 @@
+// surfman/surfman/src/device_events.rs
+//
+//! Notifications about the GPU and displays that a device's resources depend on.
+
+#[cfg(any(linux, macos, windows))]
+use crate::DisplayInfo;
+use crate::{Error, WindowingApiError};
+
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// How often the delivery thread checks on the GPU and the displays.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Something that happened to the GPU or the displays of a device, after which its contexts and
+/// surfaces may have to be rebuilt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeviceEvent {
+    /// The GPU was removed, as happens when an external GPU is unplugged or its driver is updated.
+    ///
+    /// Every context and surface of the device is lost. A new device must be opened, possibly on
+    /// another adapter.
+    Removed,
+    /// The GPU was reset after a hang or a fault, and the contents of contexts were lost.
+    ///
+    /// Contexts and their surfaces must be recreated. The device may still be usable, except on
+    /// Direct3D 11, where it has to be opened again.
+    Reset,
+    /// A display was connected or disconnected, or one changed its resolution, refresh rate, or
+    /// position on the desktop.
+    ///
+    /// Widget surfaces may have to be resized, and `Device::widget_output_info()` queried again.
+    DisplayReconfigured,
+}
+
+/// A function that receives the events of the devices it's installed on with
+/// `Device::set_device_event_handler()`.
+pub type DeviceEventHandler = Arc<dyn Fn(DeviceEvent) + Send + Sync>;
+
+// Delivers device events to the installed handler.
+//
+// Installing a handler starts a thread that calls it, so that handlers run asynchronously and can
+// call back into surfman without deadlocking. The same thread polls for events that the platform
+// doesn't push to us. Clones share the handler, as clones of a device do.
+#[derive(Clone, Default)]
+pub(crate) struct DeviceEventDispatcher(Arc<Mutex<DispatcherState>>);
+
+#[derive(Default)]
+struct DispatcherState {
+    handler: Option<DeviceEventHandler>,
+    // Dropping the sender stops the delivery thread.
+    sender: Option<Sender<DeviceEvent>>,
+}
+
+impl DeviceEventDispatcher {
+    // Replaces the handler. While a handler is installed, `poll` is called on the delivery thread
+    // every `POLL_INTERVAL`, and the events it returns are delivered.
+    pub(crate) fn set_handler<F>(&self, handler: Option<DeviceEventHandler>, mut poll: F)
+    where
+        F: FnMut() -> Option<DeviceEvent> + Send + 'static,
+    {
+        let mut state = self.0.lock().unwrap();
+        state.sender = handler.clone().map(|handler| {
+            let (sender, receiver) = mpsc::channel();
+            thread::Builder::new()
+                .name("surfman device events".to_owned())
+                .spawn(move || loop {
+                    match receiver.recv_timeout(POLL_INTERVAL) {
+                        Ok(event) => handler(event),
+                        Err(RecvTimeoutError::Timeout) => {
+                            if let Some(event) = poll() {
+                                handler(event)
+                            }
+                        }
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                })
+                .unwrap();
+            sender
+        });
+        state.handler = handler;
+    }
+
+    pub(crate) fn handler(&self) -> Option<DeviceEventHandler> {
+        self.0.lock().unwrap().handler.clone()
+    }
+
+    // Queues an event for delivery, if a handler is installed.
+    pub(crate) fn post(&self, event: DeviceEvent) {
+        if let Some(ref sender) = self.0.lock().unwrap().sender {
+            // Sending can only fail if the handler panicked and took the delivery thread with it.
+            let _ = sender.send(event);
+        }
+    }
+
+    // Reports a reset if the result of a call shows that the context was lost.
+    pub(crate) fn check<T>(&self, result: Result<T, Error>) -> Result<T, Error> {
+        match result {
+            Err(Error::MakeCurrentFailed(WindowingApiError::ContextLost))
+            | Err(Error::PresentFailed(WindowingApiError::ContextLost)) => {
+                self.post(DeviceEvent::Reset)
+            }
+            _ => {}
+        }
+        result
+    }
+}
+
+// Returns a poll function that reports `DisplayReconfigured` whenever the list of displays
+// returned by `displays` changes. Platforms that can't list their displays never report it.
+#[cfg(any(linux, macos, windows))]
+pub(crate) fn display_poller<F>(mut displays: F) -> impl FnMut() -> Option<DeviceEvent>
+where
+    F: FnMut() -> Result<Vec<DisplayInfo>, Error>,
+{
+    let mut last_displays = displays().ok();
+    move || {
+        let current_displays = displays().ok();
+        if current_displays.is_none() || current_displays == last_displays {
+            return None;
+        }
+        last_displays = current_displays;
+        Some(DeviceEvent::DisplayReconfigured)
+    }
+}
@@ -0,0 +1,72 @@
+// surfman/surfman/src/display.rs
+//
+//! Information about the displays connected to the system.
+
+use euclid::default::{Point2D, Size2D};
+
+/// A color space that a display shows content in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Colorspace {
+    /// The sRGB color space, which most displays use and which content is assumed to be in.
+    SRGB,
+    /// The Display P3 color space, which covers a wider gamut than sRGB.
+    DisplayP3,
+    /// The ITU-R BT.2020 color space of HDR displays.
+    BT2020,
+}
+
+/// A display connected to the system, as returned by `Connection::displays()`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DisplayInfo {
+    /// The platform's identifier for the display.
+    ///
+    /// This is the RandR output on X11, the name of the `wl_output` global on Wayland, the
+    /// `CGDirectDisplayID` on macOS, and the `HMONITOR` on Windows.
+    pub id: u64,
+    /// The name of the display, such as `DP-1`, or an empty string if the platform doesn't report
+    /// one.
+    pub name: String,
+    /// The position of the top-left corner of the display on the desktop, in device pixels.
+    pub origin: Point2D<i32>,
+    /// The size of the display, in device pixels.
+    pub size: Size2D<i32>,
+    /// The number of times per second that the display refreshes, if known.
+    ///
+    /// This is `None` for displays with a variable refresh rate when the platform doesn't report
+    /// their maximum.
+    pub refresh_rate: Option<f64>,
+    /// The ratio of device pixels to logical pixels that content on the display is shown at.
+    pub scale_factor: f64,
+    /// The color space of the display, if known.
+    pub colorspace: Option<Colorspace>,
+}
+
+/// What a widget surface is being shown on, as returned by `Device::widget_output_info()`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WidgetOutputInfo {
+    /// The `id` of the display that the widget is on, as listed by `Connection::displays()`, if
+    /// known.
+    ///
+    /// A widget that spans several displays is reported as being on the one that holds most of it.
+    pub display_id: Option<u64>,
+    /// The color space that the display shows content in.
+    ///
+    /// Displays whose color space the platform doesn't report are taken to be sRGB.
+    pub colorspace: Colorspace,
+    /// The number of bits per color channel that the display is driven at, such as 8 or 10.
+    pub bits_per_component: u32,
+    /// Whether the display is currently able to show high dynamic range content.
+    pub hdr: bool,
+}
+
+impl WidgetOutputInfo {
+    // What is reported for displays that the platform tells us nothing about.
+    pub(crate) fn unknown(display_id: Option<u64>) -> WidgetOutputInfo {
+        WidgetOutputInfo {
+            display_id,
+            colorspace: Colorspace::SRGB,
+            bits_per_component: 8,
+            hdr: false,
+        }
+    }
+}
@@ -35,6 +35,10 @@ pub enum Error {
     NoGLLibraryFound,
     /// An extension necessary for this library to function isn't supported.
     RequiredExtensionUnavailable,
+    /// The context lacks OpenGL extensions listed in `ContextAttributes::required_extensions`.
+    ///
+    /// These are the ones that are missing.
+    MissingExtensions(Vec<String>),
     /// Looking up an OpenGL function address failed.
     GLFunctionNotFound,
     /// This context renders to an externally-managed render target.
@@ -53,6 +57,10 @@ pub enum Error {
     SurfaceTextureCreationFailed(WindowingApiError),
     /// The system couldn't present a widget surface.
     PresentFailed(WindowingApiError),
+    /// A present or fence wait ran past the timeout of the installed `PresentWatchdog`.
+    ///
+    /// The call did finish, late, so what it did has taken effect.
+    PresentTimedOut,
     /// A context couldn't be created because there is no current context.
     NoCurrentContext,
     /// The current connection couldn't be fetched because there is no current connection.
@@ -94,6 +102,9 @@ pub enum Error {
     IncompatibleNativeContext,
     /// The native device does not match the supplied connection.
     IncompatibleNativeDevice,
+    /// A layered surface was asked for with no layers, or a layer past the last layer of a surface
+    /// was chosen.
+    InvalidSurfaceLayer,
 }
 
 /// Abstraction of the errors that EGL, CGL, GLX, CGL, etc. return.
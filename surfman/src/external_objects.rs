@@ -0,0 +1,477 @@
+// surfman/surfman/src/external_objects.rs
+//
+//! Sharing images and synchronization with Vulkan through the `GL_EXT_external_objects` family
+//! of extensions, for native OpenGL contexts that can't use the EGL image and DMA-BUF paths.
+//!
+//! OpenGL can only import memory and semaphores, not export them, so the Vulkan side allocates
+//! an image with exportable memory (`VK_KHR_external_memory_fd` on Linux,
+//! `VK_KHR_external_memory_win32` on Windows) and hands its handle to
+//! `ExternalObjects::import_memory_texture()`. The resulting texture shares storage with the
+//! Vulkan image: the contents of a surface are copied into it with
+//! `ExternalObjects::copy_from_bound_surface()`, or it can be rendered to directly. Access is
+//! ordered with semaphores exported by Vulkan and imported with
+//! `ExternalObjects::import_semaphore()`.
+//!
+//! This is only available on Linux and Windows.
+
+use crate::device::Device;
+use crate::gl;
+use crate::gl::types::{GLenum, GLint, GLsizei, GLuint, GLuint64};
+use crate::gl_utils;
+use crate::{Error, Gl};
+
+use euclid::default::Size2D;
+use std::mem;
+use std::os::raw::c_void;
+#[cfg(linux)]
+use std::os::unix::io::RawFd;
+use std::ptr;
+use std::thread;
+#[cfg(windows)]
+use winapi::um::winnt::HANDLE;
+
+// `GL_DEDICATED_MEMORY_OBJECT_EXT`.
+const DEDICATED_MEMORY_OBJECT: GLenum = 0x9581;
+// `GL_HANDLE_TYPE_OPAQUE_FD_EXT`.
+#[cfg(linux)]
+const HANDLE_TYPE_OPAQUE_FD: GLenum = 0x9586;
+// `GL_HANDLE_TYPE_OPAQUE_WIN32_EXT`.
+#[cfg(windows)]
+const HANDLE_TYPE_OPAQUE_WIN32: GLenum = 0x9587;
+
+/// A handle to memory or a semaphore exported by Vulkan: an opaque file descriptor on Linux.
+///
+/// Importing a file descriptor transfers its ownership to OpenGL.
+#[cfg(linux)]
+pub type ExternalHandle = RawFd;
+
+/// A handle to memory or a semaphore exported by Vulkan: an opaque Win32 handle on Windows.
+///
+/// Importing a handle doesn't transfer its ownership; the caller must still close it.
+#[cfg(windows)]
+pub type ExternalHandle = HANDLE;
+
+/// The layout that a texture is in when a semaphore is waited on or signaled, which must match
+/// the `VkImageLayout` that the Vulkan side uses in its matching signal or wait.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextureLayout {
+    /// `VK_IMAGE_LAYOUT_UNDEFINED`: the contents don't need to be preserved.
+    Undefined,
+    /// `VK_IMAGE_LAYOUT_GENERAL`.
+    General,
+    /// `VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL`.
+    ColorAttachment,
+    /// `VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL`.
+    ShaderReadOnly,
+    /// `VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL`.
+    TransferSource,
+    /// `VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL`.
+    TransferDestination,
+}
+
+type CreateMemoryObjectsFn = unsafe extern "system" fn(n: GLsizei, memory_objects: *mut GLuint);
+type DeleteMemoryObjectsFn = unsafe extern "system" fn(n: GLsizei, memory_objects: *const GLuint);
+type MemoryObjectParameterivFn =
+    unsafe extern "system" fn(memory_object: GLuint, pname: GLenum, params: *const GLint);
+type TexStorageMem2DFn = unsafe extern "system" fn(
+    target: GLenum,
+    levels: GLsizei,
+    internal_format: GLenum,
+    width: GLsizei,
+    height: GLsizei,
+    memory: GLuint,
+    offset: GLuint64,
+);
+type GenSemaphoresFn = unsafe extern "system" fn(n: GLsizei, semaphores: *mut GLuint);
+type DeleteSemaphoresFn = unsafe extern "system" fn(n: GLsizei, semaphores: *const GLuint);
+type SemaphoreOperationFn = unsafe extern "system" fn(
+    semaphore: GLuint,
+    num_buffer_barriers: GLuint,
+    buffers: *const GLuint,
+    num_texture_barriers: GLuint,
+    textures: *const GLuint,
+    layouts: *const GLenum,
+);
+#[cfg(linux)]
+type ImportMemoryFn =
+    unsafe extern "system" fn(memory: GLuint, size: GLuint64, handle_type: GLenum, fd: GLint);
+#[cfg(windows)]
+type ImportMemoryFn =
+    unsafe extern "system" fn(memory: GLuint, size: GLuint64, handle_type: GLenum, handle: HANDLE);
+#[cfg(linux)]
+type ImportSemaphoreFn =
+    unsafe extern "system" fn(semaphore: GLuint, handle_type: GLenum, fd: GLint);
+#[cfg(windows)]
+type ImportSemaphoreFn =
+    unsafe extern "system" fn(semaphore: GLuint, handle_type: GLenum, handle: HANDLE);
+
+/// The `GL_EXT_external_objects` entry points of one context.
+///
+/// These can only be used while that context is current.
+pub struct ExternalObjects {
+    gl: Gl,
+    create_memory_objects: CreateMemoryObjectsFn,
+    delete_memory_objects: DeleteMemoryObjectsFn,
+    memory_object_parameteriv: MemoryObjectParameterivFn,
+    tex_storage_mem_2d: TexStorageMem2DFn,
+    import_memory: ImportMemoryFn,
+    gen_semaphores: GenSemaphoresFn,
+    delete_semaphores: DeleteSemaphoresFn,
+    import_semaphore: ImportSemaphoreFn,
+    wait_semaphore: SemaphoreOperationFn,
+    signal_semaphore: SemaphoreOperationFn,
+}
+
+/// A texture whose storage is memory imported from Vulkan.
+///
+/// It must be destroyed with `ExternalObjects::destroy_memory_texture()`, or a panic will occur.
+pub struct ExternalMemoryTexture {
+    memory_object: GLuint,
+    texture_object: GLuint,
+    size: Size2D<i32>,
+}
+
+/// A semaphore imported from Vulkan.
+///
+/// It must be destroyed with `ExternalObjects::destroy_semaphore()`, or a panic will occur.
+pub struct ExternalSemaphore {
+    semaphore: GLuint,
+}
+
+impl Drop for ExternalMemoryTexture {
+    fn drop(&mut self) {
+        if self.memory_object != 0 && !thread::panicking() {
+            panic!("Should have destroyed the texture first with `destroy_memory_texture()`!")
+        }
+    }
+}
+
+impl Drop for ExternalSemaphore {
+    fn drop(&mut self) {
+        if self.semaphore != 0 && !thread::panicking() {
+            panic!("Should have destroyed the semaphore first with `destroy_semaphore()`!")
+        }
+    }
+}
+
+impl ExternalObjects {
+    /// Loads the entry points of the given context, which must be current.
+    ///
+    /// Returns `Error::UnsupportedOnThisPlatform` unless the context supports
+    /// `GL_EXT_memory_object` and `GL_EXT_semaphore`, along with their `_fd` variants on Linux or
+    /// their `_win32` variants on Windows.
+    pub fn new<D>(device: &D, context: &D::Context) -> Result<ExternalObjects, Error>
+    where
+        D: Device,
+    {
+        let gl = Gl::load_with(|symbol| device.get_proc_address(context, symbol));
+
+        #[cfg(linux)]
+        let (handle_extensions, import_memory, import_semaphore): ([&[u8]; 2], _, _) = (
+            [b"GL_EXT_memory_object_fd", b"GL_EXT_semaphore_fd"],
+            "glImportMemoryFdEXT",
+            "glImportSemaphoreFdEXT",
+        );
+        #[cfg(windows)]
+        let (handle_extensions, import_memory, import_semaphore): ([&[u8]; 2], _, _) = (
+            [b"GL_EXT_memory_object_win32", b"GL_EXT_semaphore_win32"],
+            "glImportMemoryWin32HandleEXT",
+            "glImportSemaphoreWin32HandleEXT",
+        );
+
+        let extensions: [&[u8]; 2] = [b"GL_EXT_memory_object", b"GL_EXT_semaphore"];
+        if !extensions
+            .iter()
+            .chain(handle_extensions.iter())
+            .all(|extension| gl_utils::context_has_extension(&gl, extension))
+        {
+            return Err(Error::UnsupportedOnThisPlatform);
+        }
+
+        let symbol_names = [
+            "glCreateMemoryObjectsEXT",
+            "glDeleteMemoryObjectsEXT",
+            "glMemoryObjectParameterivEXT",
+            "glTexStorageMem2DEXT",
+            import_memory,
+            "glGenSemaphoresEXT",
+            "glDeleteSemaphoresEXT",
+            import_semaphore,
+            "glWaitSemaphoreEXT",
+            "glSignalSemaphoreEXT",
+        ];
+        let mut symbols = [ptr::null(); 10];
+        for (symbol, symbol_name) in symbols.iter_mut().zip(symbol_names.iter()) {
+            *symbol = device.get_proc_address(context, symbol_name);
+            if symbol.is_null() {
+                return Err(Error::UnsupportedOnThisPlatform);
+            }
+        }
+
+        unsafe {
+            Ok(ExternalObjects {
+                gl,
+                create_memory_objects: mem::transmute::<*const c_void, CreateMemoryObjectsFn>(
+                    symbols[0],
+                ),
+                delete_memory_objects: mem::transmute::<*const c_void, DeleteMemoryObjectsFn>(
+                    symbols[1],
+                ),
+                memory_object_parameteriv: mem::transmute::<*const c_void, MemoryObjectParameterivFn>(
+                    symbols[2],
+                ),
+                tex_storage_mem_2d: mem::transmute::<*const c_void, TexStorageMem2DFn>(symbols[3]),
+                import_memory: mem::transmute::<*const c_void, ImportMemoryFn>(symbols[4]),
+                gen_semaphores: mem::transmute::<*const c_void, GenSemaphoresFn>(symbols[5]),
+                delete_semaphores: mem::transmute::<*const c_void, DeleteSemaphoresFn>(symbols[6]),
+                import_semaphore: mem::transmute::<*const c_void, ImportSemaphoreFn>(symbols[7]),
+                wait_semaphore: mem::transmute::<*const c_void, SemaphoreOperationFn>(symbols[8]),
+                signal_semaphore: mem::transmute::<*const c_void, SemaphoreOperationFn>(symbols[9]),
+            })
+        }
+    }
+
+    /// Imports memory exported by Vulkan as the storage of a new `GL_TEXTURE_2D` texture of the
+    /// given size, in the `GL_RGBA8` format.
+    ///
+    /// `allocation_size` is the `allocationSize` of the `VkMemoryAllocateInfo` that the memory was
+    /// allocated with, and `dedicated` must be true if it was a dedicated allocation. The Vulkan
+    /// image must be a single-level `VK_FORMAT_R8G8B8A8_UNORM` image of the same size, with
+    /// optimal tiling.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must be a valid handle to memory that satisfies the above.
+    pub unsafe fn import_memory_texture(
+        &self,
+        handle: ExternalHandle,
+        allocation_size: u64,
+        size: &Size2D<i32>,
+        dedicated: bool,
+    ) -> Result<ExternalMemoryTexture, Error> {
+        let gl = &self.gl;
+        while gl.GetError() != gl::NO_ERROR {}
+
+        let mut memory_object = 0;
+        (self.create_memory_objects)(1, &mut memory_object);
+        let dedicated = dedicated as GLint;
+        (self.memory_object_parameteriv)(memory_object, DEDICATED_MEMORY_OBJECT, &dedicated);
+        #[cfg(linux)]
+        (self.import_memory)(
+            memory_object,
+            allocation_size,
+            HANDLE_TYPE_OPAQUE_FD,
+            handle,
+        );
+        #[cfg(windows)]
+        (self.import_memory)(
+            memory_object,
+            allocation_size,
+            HANDLE_TYPE_OPAQUE_WIN32,
+            handle,
+        );
+        if gl.GetError() != gl::NO_ERROR {
+            error!("Failed to import external memory!");
+            (self.delete_memory_objects)(1, &memory_object);
+            return Err(Error::Failed);
+        }
+
+        let mut texture_object = 0;
+        gl.GenTextures(1, &mut texture_object);
+        let mut bindings =
+            gl_utils::BindingsGuard::for_texture(gl, gl::TEXTURE_2D, gl::TEXTURE_BINDING_2D);
+        bindings.bind_texture(texture_object);
+        (self.tex_storage_mem_2d)(
+            gl::TEXTURE_2D,
+            1,
+            gl::RGBA8,
+            size.width,
+            size.height,
+            memory_object,
+            0,
+        );
+        gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+        gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
+        drop(bindings);
+
+        if gl.GetError() != gl::NO_ERROR {
+            error!("Failed to create a texture from external memory!");
+            gl.DeleteTextures(1, &texture_object);
+            (self.delete_memory_objects)(1, &memory_object);
+            return Err(Error::Failed);
+        }
+
+        Ok(ExternalMemoryTexture {
+            memory_object,
+            texture_object,
+            size: *size,
+        })
+    }
+
+    /// Imports a semaphore exported by Vulkan.
+    ///
+    /// On Linux, the semaphore must have been exported with
+    /// `VK_EXTERNAL_SEMAPHORE_HANDLE_TYPE_OPAQUE_FD_BIT`; on Windows, with
+    /// `VK_EXTERNAL_SEMAPHORE_HANDLE_TYPE_OPAQUE_WIN32_BIT`.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must be a valid handle to a binary semaphore.
+    pub unsafe fn import_semaphore(
+        &self,
+        handle: ExternalHandle,
+    ) -> Result<ExternalSemaphore, Error> {
+        let gl = &self.gl;
+        while gl.GetError() != gl::NO_ERROR {}
+
+        let mut semaphore = 0;
+        (self.gen_semaphores)(1, &mut semaphore);
+        #[cfg(linux)]
+        (self.import_semaphore)(semaphore, HANDLE_TYPE_OPAQUE_FD, handle);
+        #[cfg(windows)]
+        (self.import_semaphore)(semaphore, HANDLE_TYPE_OPAQUE_WIN32, handle);
+        if gl.GetError() != gl::NO_ERROR {
+            error!("Failed to import an external semaphore!");
+            (self.delete_semaphores)(1, &semaphore);
+            return Err(Error::Failed);
+        }
+
+        Ok(ExternalSemaphore { semaphore })
+    }
+
+    /// Makes subsequent OpenGL commands wait until Vulkan signals the semaphore, and transitions
+    /// the given textures out of the layouts that Vulkan left them in.
+    pub fn wait_semaphore(
+        &self,
+        semaphore: &ExternalSemaphore,
+        textures: &[(&ExternalMemoryTexture, TextureLayout)],
+    ) {
+        let (texture_objects, layouts) = texture_barriers(textures);
+        unsafe {
+            (self.wait_semaphore)(
+                semaphore.semaphore,
+                0,
+                ptr::null(),
+                texture_objects.len() as GLuint,
+                texture_objects.as_ptr(),
+                layouts.as_ptr(),
+            );
+        }
+    }
+
+    /// Signals the semaphore once previous OpenGL commands have completed, transitioning the
+    /// given textures to the layouts that Vulkan expects them in.
+    ///
+    /// The commands are flushed, so that Vulkan can wait on the semaphore right away.
+    pub fn signal_semaphore(
+        &self,
+        semaphore: &ExternalSemaphore,
+        textures: &[(&ExternalMemoryTexture, TextureLayout)],
+    ) {
+        let (texture_objects, layouts) = texture_barriers(textures);
+        unsafe {
+            (self.signal_semaphore)(
+                semaphore.semaphore,
+                0,
+                ptr::null(),
+                texture_objects.len() as GLuint,
+                texture_objects.as_ptr(),
+                layouts.as_ptr(),
+            );
+            self.gl.Flush();
+        }
+    }
+
+    /// Copies the contents of the surface bound to the given context into a texture of the same
+    /// size.
+    ///
+    /// The surface's origin is at the lower left corner, so Vulkan sees the copy upside down.
+    /// Returns `Error::Failed` if no surface is bound or the sizes differ.
+    pub fn copy_from_bound_surface<D>(
+        &self,
+        device: &D,
+        context: &D::Context,
+        texture: &ExternalMemoryTexture,
+    ) -> Result<(), Error>
+    where
+        D: Device,
+    {
+        let surface_info = match device.context_surface_info(context)? {
+            Some(surface_info) => surface_info,
+            None => return Err(Error::Failed),
+        };
+        if surface_info.size != texture.size {
+            return Err(Error::Failed);
+        }
+
+        let gl = &self.gl;
+        let mut bindings = gl_utils::BindingsGuard::new(gl);
+        let framebuffer_object =
+            bindings.create_and_bind_framebuffer(gl::TEXTURE_2D, texture.texture_object);
+        gl_utils::copy_framebuffer(
+            gl,
+            surface_info.framebuffer_object,
+            framebuffer_object,
+            &texture.size,
+            false,
+        );
+        drop(bindings);
+        gl_utils::destroy_framebuffer(gl, framebuffer_object);
+        Ok(())
+    }
+
+    /// Destroys a texture created with `import_memory_texture()`, releasing the imported memory.
+    pub fn destroy_memory_texture(&self, mut texture: ExternalMemoryTexture) {
+        unsafe {
+            self.gl.DeleteTextures(1, &texture.texture_object);
+            (self.delete_memory_objects)(1, &texture.memory_object);
+        }
+        texture.texture_object = 0;
+        texture.memory_object = 0;
+    }
+
+    /// Destroys a semaphore created with `import_semaphore()`.
+    pub fn destroy_semaphore(&self, mut semaphore: ExternalSemaphore) {
+        unsafe {
+            (self.delete_semaphores)(1, &semaphore.semaphore);
+        }
+        semaphore.semaphore = 0;
+    }
+}
+
+impl ExternalMemoryTexture {
+    /// Returns the OpenGL texture object, whose target is `GL_TEXTURE_2D`.
+    #[inline]
+    pub fn texture_object(&self) -> GLuint {
+        self.texture_object
+    }
+
+    /// Returns the size of the texture, in pixels.
+    #[inline]
+    pub fn size(&self) -> Size2D<i32> {
+        self.size
+    }
+}
+
+impl TextureLayout {
+    fn gl_layout(self) -> GLenum {
+        match self {
+            TextureLayout::Undefined => gl::NONE,
+            // `GL_LAYOUT_GENERAL_EXT`, and so on.
+            TextureLayout::General => 0x958d,
+            TextureLayout::ColorAttachment => 0x958e,
+            TextureLayout::ShaderReadOnly => 0x9591,
+            TextureLayout::TransferSource => 0x9592,
+            TextureLayout::TransferDestination => 0x9593,
+        }
+    }
+}
+
+fn texture_barriers(
+    textures: &[(&ExternalMemoryTexture, TextureLayout)],
+) -> (Vec<GLuint>, Vec<GLenum>) {
+    textures
+        .iter()
+        .map(|&(texture, layout)| (texture.texture_object, layout.gl_layout()))
+        .unzip()
+}
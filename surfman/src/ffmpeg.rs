@@ -0,0 +1,291 @@
+// surfman/surfman/src/ffmpeg.rs
+//
+//! Conversions between surfman surfaces and FFmpeg hardware frames, for encoding rendered frames
+//! and displaying decoded ones without copying them through the CPU.
+//!
+//! * On Linux, surfaces are exchanged with VA-API through DRM PRIME frames. A surface exported
+//!   with `Device::export_dma_buf()` becomes an `AVDRMFrameDescriptor` with
+//!   `drm_frame_descriptor()`, which goes in `data[0]` of an `AV_PIX_FMT_DRM_PRIME` frame that
+//!   `av_hwframe_map()` maps to VA-API for encoding. In the other direction, a decoded VA-API
+//!   frame mapped to DRM PRIME is turned back into a `DmaBuf` with
+//!   `dma_buf_from_drm_frame_descriptor()` and sampled with
+//!   `Device::create_surface_texture_from_dma_buf()`.
+//!
+//! * On macOS, surfaces are exchanged with VideoToolbox as `CVPixelBuffer`s, which go in
+//!   `data[3]` of `AV_PIX_FMT_VIDEOTOOLBOX` frames. Both wrap `IOSurface`s, so
+//!   `pixel_buffer_from_native_surface()` and `native_surface_from_pixel_buffer()` convert
+//!   between them without copying.
+//!
+//! * On Windows, surfaces are exchanged with D3D11VA as Direct3D 11 textures, which go in
+//!   `data[0]` of `AV_PIX_FMT_D3D11` frames, with the array slice in `data[1]`. The texture of a
+//!   surface comes from `Device::surface_d3d11_texture()`, and a decoded texture is sampled with
+//!   `Device::create_surface_texture_from_texture()` on ANGLE.
+//!
+//! Decoders produce YUV frames, which OpenGL can only sample through `GL_TEXTURE_EXTERNAL_OES`;
+//! convert them to RGB on the GPU first, e.g. with FFmpeg's `scale_vaapi=format=bgr0` filter.
+//!
+//! Surfman doesn't link to FFmpeg; these are only the raw values to pass to it.
+
+#[cfg(linux)]
+use crate::platform::generic::egl::surface::{EGLDmaBuf, EGLDmaBufPlane};
+#[cfg(macos)]
+use crate::platform::macos::system::surface::NativeSurface;
+#[cfg(any(linux, macos))]
+use crate::Error;
+
+#[cfg(macos)]
+use io_surface::IOSurfaceRef;
+#[cfg(linux)]
+use std::os::raw::c_int;
+#[cfg(macos)]
+use std::os::raw::c_void;
+#[cfg(macos)]
+use std::ptr;
+#[cfg(windows)]
+use winapi::um::d3d11::ID3D11Texture2D;
+
+/// `AV_DRM_MAX_PLANES`: the maximum number of objects, layers, and planes in a DRM PRIME frame.
+#[cfg(linux)]
+pub const AV_DRM_MAX_PLANES: usize = 4;
+
+/// A DMA-BUF object of a DRM PRIME frame, laid out as FFmpeg's `AVDRMObjectDescriptor`.
+#[cfg(linux)]
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AVDRMObjectDescriptor {
+    /// The DMA-BUF file descriptor.
+    pub fd: c_int,
+    /// The size of the buffer, in bytes.
+    pub size: usize,
+    /// The DRM format modifier of the buffer.
+    pub format_modifier: u64,
+}
+
+/// A plane of a layer of a DRM PRIME frame, laid out as FFmpeg's `AVDRMPlaneDescriptor`.
+#[cfg(linux)]
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AVDRMPlaneDescriptor {
+    /// The index of the object holding the plane.
+    pub object_index: c_int,
+    /// The offset of the plane within its object, in bytes.
+    pub offset: isize,
+    /// The number of bytes between the starts of consecutive rows of the plane.
+    pub pitch: isize,
+}
+
+/// A layer of a DRM PRIME frame, laid out as FFmpeg's `AVDRMLayerDescriptor`.
+#[cfg(linux)]
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AVDRMLayerDescriptor {
+    /// The DRM fourcc code of the layer.
+    pub format: u32,
+    /// The number of planes in the layer.
+    pub nb_planes: c_int,
+    /// The planes of the layer.
+    pub planes: [AVDRMPlaneDescriptor; AV_DRM_MAX_PLANES],
+}
+
+/// A DRM PRIME frame, laid out as FFmpeg's `AVDRMFrameDescriptor`.
+#[cfg(linux)]
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AVDRMFrameDescriptor {
+    /// The number of DMA-BUF objects in the frame.
+    pub nb_objects: c_int,
+    /// The DMA-BUF objects of the frame.
+    pub objects: [AVDRMObjectDescriptor; AV_DRM_MAX_PLANES],
+    /// The number of layers in the frame.
+    pub nb_layers: c_int,
+    /// The layers of the frame.
+    pub layers: [AVDRMLayerDescriptor; AV_DRM_MAX_PLANES],
+}
+
+/// Describes an exported surface as a DRM PRIME frame.
+///
+/// The descriptor borrows the file descriptors of `dma_buf`, which must stay open until FFmpeg is
+/// done with the frame.
+#[cfg(linux)]
+pub fn drm_frame_descriptor(dma_buf: &EGLDmaBuf) -> Result<AVDRMFrameDescriptor, Error> {
+    if dma_buf.planes.is_empty() || dma_buf.planes.len() > AV_DRM_MAX_PLANES {
+        return Err(Error::Failed);
+    }
+
+    let mut descriptor = AVDRMFrameDescriptor {
+        nb_objects: dma_buf.planes.len() as c_int,
+        nb_layers: 1,
+        ..AVDRMFrameDescriptor::default()
+    };
+    descriptor.layers[0].format = dma_buf.fourcc;
+    descriptor.layers[0].nb_planes = dma_buf.planes.len() as c_int;
+
+    // Each plane of an exported surface has its own file descriptor, so each becomes an object.
+    for (plane_index, plane) in dma_buf.planes.iter().enumerate() {
+        let size = unsafe { libc::lseek(plane.fd, 0, libc::SEEK_END) };
+        if size < 0 {
+            error!("Failed to determine the size of a DMA-BUF!");
+            return Err(Error::Failed);
+        }
+        descriptor.objects[plane_index] = AVDRMObjectDescriptor {
+            fd: plane.fd,
+            size: size as usize,
+            format_modifier: dma_buf.modifier,
+        };
+        descriptor.layers[0].planes[plane_index] = AVDRMPlaneDescriptor {
+            object_index: plane_index as c_int,
+            offset: plane.offset as isize,
+            pitch: plane.stride as isize,
+        };
+    }
+    Ok(descriptor)
+}
+
+/// Duplicates the file descriptors of a DRM PRIME frame, such as a decoded VA-API frame mapped
+/// with `av_hwframe_map()`, into a `DmaBuf` that can be imported with
+/// `Device::create_surface_texture_from_dma_buf()`.
+///
+/// The frame must have a single layer, as frames whose planes are split across layers can't be
+/// imported as one image. The returned `DmaBuf` owns its file descriptors, so it stays valid after
+/// FFmpeg releases the frame.
+///
+/// # Safety
+///
+/// The file descriptors in `descriptor` must be open.
+#[cfg(linux)]
+pub unsafe fn dma_buf_from_drm_frame_descriptor(
+    descriptor: &AVDRMFrameDescriptor,
+) -> Result<EGLDmaBuf, Error> {
+    if descriptor.nb_layers != 1 {
+        error!("Only single-layer DRM PRIME frames can be imported!");
+        return Err(Error::Failed);
+    }
+    let layer = &descriptor.layers[0];
+    if layer.nb_planes <= 0 || layer.nb_planes as usize > AV_DRM_MAX_PLANES {
+        return Err(Error::Failed);
+    }
+
+    let mut dma_buf = EGLDmaBuf {
+        fourcc: layer.format,
+        modifier: 0,
+        planes: Vec::with_capacity(layer.nb_planes as usize),
+    };
+    for plane in &layer.planes[0..(layer.nb_planes as usize)] {
+        if plane.object_index < 0 || plane.object_index >= descriptor.nb_objects {
+            return Err(Error::Failed);
+        }
+        let object = &descriptor.objects[plane.object_index as usize];
+        dma_buf.modifier = object.format_modifier;
+        let fd = libc::dup(object.fd);
+        if fd < 0 {
+            return Err(Error::Failed);
+        }
+        dma_buf.planes.push(EGLDmaBufPlane {
+            fd,
+            offset: plane.offset as u32,
+            stride: plane.pitch as u32,
+        });
+    }
+    Ok(dma_buf)
+}
+
+/// A Core Video pixel buffer, as found in `data[3]` of `AV_PIX_FMT_VIDEOTOOLBOX` frames.
+#[cfg(macos)]
+pub type CVPixelBufferRef = *mut c_void;
+
+#[cfg(macos)]
+#[link(name = "CoreVideo", kind = "framework")]
+extern "C" {
+    fn CVPixelBufferCreateWithIOSurface(
+        allocator: *const c_void,
+        surface: IOSurfaceRef,
+        pixel_buffer_attributes: *const c_void,
+        pixel_buffer_out: *mut CVPixelBufferRef,
+    ) -> i32;
+    fn CVPixelBufferGetIOSurface(pixel_buffer: CVPixelBufferRef) -> IOSurfaceRef;
+}
+
+#[cfg(macos)]
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFRetain(object: *const c_void) -> *const c_void;
+}
+
+/// Wraps the `IOSurface` of a surface in a pixel buffer that VideoToolbox can encode.
+///
+/// The pixel buffer shares storage with the surface. It's returned retained, and must be released
+/// with `CVPixelBufferRelease()`.
+///
+/// # Safety
+///
+/// `native_surface` must wrap a valid `IOSurface`.
+#[cfg(macos)]
+pub unsafe fn pixel_buffer_from_native_surface(
+    native_surface: &NativeSurface,
+) -> Result<CVPixelBufferRef, Error> {
+    let mut pixel_buffer = ptr::null_mut();
+    let result = CVPixelBufferCreateWithIOSurface(
+        ptr::null(),
+        native_surface.0,
+        ptr::null(),
+        &mut pixel_buffer,
+    );
+    if result != 0 || pixel_buffer.is_null() {
+        error!("CVPixelBufferCreateWithIOSurface() failed: {}", result);
+        return Err(Error::Failed);
+    }
+    Ok(pixel_buffer)
+}
+
+/// Returns the `IOSurface` backing a pixel buffer, such as one decoded by VideoToolbox, or `None`
+/// if the pixel buffer isn't backed by an `IOSurface`.
+///
+/// The `IOSurface` is retained, as `Device::native_surface()` does, and can be bound to an OpenGL
+/// rectangle texture with `CGLTexImageIOSurface2D()`.
+///
+/// # Safety
+///
+/// `pixel_buffer` must be a valid pixel buffer.
+#[cfg(macos)]
+pub unsafe fn native_surface_from_pixel_buffer(
+    pixel_buffer: CVPixelBufferRef,
+) -> Option<NativeSurface> {
+    let io_surface = CVPixelBufferGetIOSurface(pixel_buffer);
+    if io_surface.is_null() {
+        return None;
+    }
+    CFRetain(io_surface as *const c_void);
+    Some(NativeSurface(io_surface))
+}
+
+/// A Direct3D 11 texture slice, as found in `data[0]` and `data[1]` of `AV_PIX_FMT_D3D11`
+/// frames.
+#[cfg(windows)]
+#[derive(Clone, Copy, Debug)]
+pub struct D3D11Frame {
+    /// The texture, which may be a texture array.
+    pub texture: *mut ID3D11Texture2D,
+    /// The index of the slice of the texture array that holds the frame.
+    pub array_index: usize,
+}
+
+#[cfg(windows)]
+impl D3D11Frame {
+    /// Reads the texture slice out of the `data` pointers of a frame.
+    ///
+    /// Decoders output slices of texture arrays, which can't be wrapped in surface textures
+    /// directly; copy the slice to a texture of its own with `CopySubresourceRegion()` first.
+    #[inline]
+    pub fn from_frame_data(data: &[*mut u8]) -> D3D11Frame {
+        D3D11Frame {
+            texture: data[0] as *mut ID3D11Texture2D,
+            array_index: data[1] as usize,
+        }
+    }
+
+    /// Returns the `data` pointers of a frame holding this texture slice.
+    #[inline]
+    pub fn to_frame_data(&self) -> [*mut u8; 2] {
+        [self.texture as *mut u8, self.array_index as *mut u8]
+    }
+}
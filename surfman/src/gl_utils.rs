@@ -3,10 +3,28 @@
 //! Various OpenGL utilities used by the different backends.
 
 use crate::gl;
-use crate::gl::types::{GLenum, GLint, GLuint};
+use crate::gl::types::{GLboolean, GLchar, GLenum, GLint, GLsizei, GLuint};
 use crate::Gl;
+#[cfg(linux)]
+use crate::SurfaceOrigin;
 
-#[allow(dead_code)]
+use std::ffi::CStr;
+use std::os::raw::c_char;
+
+#[cfg(any(android, angle, linux, ohos))]
+use crate::gl::types::GLfloat;
+#[cfg(any(android, angle, linux, ohos))]
+use euclid::default::Rect;
+#[cfg(any(android, angle, linux, macos, ohos))]
+use euclid::default::Size2D;
+#[cfg(linux)]
+use std::os::raw::c_void;
+#[cfg(any(android, angle, linux, macos, ohos))]
+use std::ptr;
+#[cfg(linux)]
+use std::slice;
+
+#[cfg(any(android, angle, linux, macos, ohos))]
 pub(crate) fn create_and_bind_framebuffer(
     gl: &Gl,
     texture_target: GLenum,
@@ -27,6 +45,7 @@ pub(crate) fn create_and_bind_framebuffer(
     }
 }
 
+#[cfg(not(redox))]
 pub(crate) fn unbind_framebuffer_if_necessary(gl: &Gl, framebuffer_object: GLuint) {
     unsafe {
         // Unbind the framebuffer if it's bound.
@@ -42,10 +61,585 @@ pub(crate) fn unbind_framebuffer_if_necessary(gl: &Gl, framebuffer_object: GLuin
     }
 }
 
-#[allow(dead_code)]
+// Remembers the framebuffer bindings of the current context, and optionally the binding of one
+// texture target, and restores them when dropped. Surfman's internal GL calls that bind objects of
+// their own (creating and presenting surfaces, for instance) run under one of these, so that
+// callers don't find their bindings changed afterward.
+//
+// The guard keeps track of what's bound while it's alive, so binding an object that's already
+// bound is skipped, as is restoring bindings that never changed. For that to work, all binding
+// changes of the tracked targets must go through the guard while it's alive.
+//
+// Nothing is remembered once the guard is dropped, so redundant binds are only skipped within one
+// surfman call, not across bind/present cycles. Between calls, the caller is free to change the
+// bindings without telling surfman, so a cache kept per context couldn't be trusted without
+// querying the bindings again, which is what the next guard does anyway.
+pub(crate) struct BindingsGuard<'a> {
+    gl: &'a Gl,
+    framebuffers: Option<FramebufferBindings>,
+    texture: Option<(GLenum, Binding)>,
+}
+
+#[derive(Clone, Copy)]
+struct FramebufferBindings {
+    draw: Binding,
+    read: Binding,
+}
+
+// The object bound to one target: the one that was bound when the guard was made, and the one
+// that's bound now.
+#[derive(Clone, Copy)]
+struct Binding {
+    saved: GLuint,
+    current: GLuint,
+}
+
+impl<'a> BindingsGuard<'a> {
+    pub(crate) fn new(gl: &'a Gl) -> BindingsGuard<'a> {
+        unsafe {
+            let (mut draw_framebuffer, mut read_framebuffer) = (0, 0);
+            gl.GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut draw_framebuffer);
+            gl.GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut read_framebuffer);
+            BindingsGuard {
+                gl,
+                framebuffers: Some(FramebufferBindings {
+                    draw: Binding::new(draw_framebuffer as GLuint),
+                    read: Binding::new(read_framebuffer as GLuint),
+                }),
+                texture: None,
+            }
+        }
+    }
+
+    // Remembers only the texture bound to `target`, for calls that don't touch framebuffers.
+    pub(crate) fn for_texture(gl: &'a Gl, target: GLenum, binding: GLenum) -> BindingsGuard<'a> {
+        BindingsGuard {
+            gl,
+            framebuffers: None,
+            texture: None,
+        }
+        .with_texture(target, binding)
+    }
+
+    // Also remembers the texture bound to `target` in the active texture unit. `binding` is the
+    // query that returns it, e.g. `GL_TEXTURE_BINDING_2D` for `GL_TEXTURE_2D`.
+    pub(crate) fn with_texture(mut self, target: GLenum, binding: GLenum) -> BindingsGuard<'a> {
+        unsafe {
+            let mut texture = 0;
+            self.gl.GetIntegerv(binding, &mut texture);
+            self.texture = Some((target, Binding::new(texture as GLuint)));
+        }
+        self
+    }
+
+    // Binds the framebuffer to `GL_FRAMEBUFFER`, `GL_DRAW_FRAMEBUFFER`, or `GL_READ_FRAMEBUFFER`,
+    // unless it's already bound there.
+    pub(crate) fn bind_framebuffer(&mut self, target: GLenum, framebuffer_object: GLuint) {
+        let (draw, read) = match target {
+            gl::DRAW_FRAMEBUFFER => (true, false),
+            gl::READ_FRAMEBUFFER => (false, true),
+            _ => (true, true),
+        };
+        let framebuffers = self
+            .framebuffers
+            .as_mut()
+            .expect("The framebuffer bindings aren't being tracked!");
+        if (!draw || framebuffers.draw.current == framebuffer_object)
+            && (!read || framebuffers.read.current == framebuffer_object)
+        {
+            return;
+        }
+
+        unsafe {
+            self.gl.BindFramebuffer(target, framebuffer_object);
+        }
+        if draw {
+            framebuffers.draw.current = framebuffer_object;
+        }
+        if read {
+            framebuffers.read.current = framebuffer_object;
+        }
+    }
+
+    // Binds the texture to the target given to `with_texture()`, unless it's already bound there.
+    pub(crate) fn bind_texture(&mut self, texture_object: GLuint) {
+        let (target, ref mut binding) = *self
+            .texture
+            .as_mut()
+            .expect("The texture binding isn't being tracked!");
+        if binding.current != texture_object {
+            unsafe {
+                self.gl.BindTexture(target, texture_object);
+            }
+            binding.current = texture_object;
+        }
+    }
+
+    // Like `create_and_bind_framebuffer()`, but binds the new framebuffer through this guard.
+    #[cfg(any(android, angle, linux, ohos))]
+    pub(crate) fn create_and_bind_framebuffer(
+        &mut self,
+        texture_target: GLenum,
+        texture_object: GLuint,
+    ) -> GLuint {
+        unsafe {
+            let mut framebuffer_object = 0;
+            self.gl.GenFramebuffers(1, &mut framebuffer_object);
+            self.bind_framebuffer(gl::FRAMEBUFFER, framebuffer_object);
+            self.gl.FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                texture_target,
+                texture_object,
+                0,
+            );
+            framebuffer_object
+        }
+    }
+}
+
+impl<'a> Drop for BindingsGuard<'a> {
+    fn drop(&mut self) {
+        // Objects deleted in the meantime were unbound when they were, and must not be rebound.
+        let gl = self.gl;
+        unsafe {
+            if let Some(FramebufferBindings { draw, read }) = self.framebuffers {
+                if draw.is_dirty() && draw.saved_is(|framebuffer| gl.IsFramebuffer(framebuffer)) {
+                    gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, draw.saved);
+                }
+                if read.is_dirty() && read.saved_is(|framebuffer| gl.IsFramebuffer(framebuffer)) {
+                    gl.BindFramebuffer(gl::READ_FRAMEBUFFER, read.saved);
+                }
+            }
+            if let Some((target, texture)) = self.texture {
+                if texture.is_dirty() && texture.saved_is(|texture| gl.IsTexture(texture)) {
+                    gl.BindTexture(target, texture.saved);
+                }
+            }
+        }
+    }
+}
+
+impl Binding {
+    fn new(saved: GLuint) -> Binding {
+        Binding {
+            saved,
+            current: saved,
+        }
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.current != self.saved
+    }
+
+    // Returns true if the saved object is 0 or still an object of its kind, according to `is`.
+    fn saved_is<F>(&self, is: F) -> bool
+    where
+        F: FnOnce(GLuint) -> GLboolean,
+    {
+        self.saved == 0 || is(self.saved) == gl::TRUE
+    }
+}
+
+// `glObjectLabel()`, from `GL_KHR_debug`, which is core in OpenGL 4.3 and OpenGL ES 3.2.
+pub(crate) type ObjectLabelFn = unsafe extern "system" fn(GLenum, GLuint, GLsizei, *const GLchar);
+
+// Gives the label to a framebuffer object and to the textures or renderbuffers attached to it, so
+// that captures show it on the buffers the contents actually live in too.
+pub(crate) unsafe fn label_framebuffer(
+    gl: &Gl,
+    object_label: ObjectLabelFn,
+    framebuffer_object: GLuint,
+    label: &str,
+) {
+    let (length, label) = (label.len() as GLsizei, label.as_ptr() as *const GLchar);
+    object_label(gl::FRAMEBUFFER, framebuffer_object, length, label);
+
+    let mut bindings = BindingsGuard::new(gl);
+    bindings.bind_framebuffer(gl::READ_FRAMEBUFFER, framebuffer_object);
+    for &attachment in &[
+        gl::COLOR_ATTACHMENT0,
+        gl::DEPTH_ATTACHMENT,
+        gl::STENCIL_ATTACHMENT,
+    ] {
+        let (mut object_type, mut object) = (0, 0);
+        gl.GetFramebufferAttachmentParameteriv(
+            gl::READ_FRAMEBUFFER,
+            attachment,
+            gl::FRAMEBUFFER_ATTACHMENT_OBJECT_TYPE,
+            &mut object_type,
+        );
+        let identifier = match object_type as GLenum {
+            gl::TEXTURE => gl::TEXTURE,
+            gl::RENDERBUFFER => gl::RENDERBUFFER,
+            _ => continue,
+        };
+        gl.GetFramebufferAttachmentParameteriv(
+            gl::READ_FRAMEBUFFER,
+            attachment,
+            gl::FRAMEBUFFER_ATTACHMENT_OBJECT_NAME,
+            &mut object,
+        );
+        object_label(identifier, object as GLuint, length, label);
+    }
+}
+
+// Returns true if the current context advertises the given OpenGL extension. This uses
+// `glGetStringi()`, so it requires OpenGL 3.0 or OpenGL ES 3.0.
+pub(crate) fn context_has_extension(gl: &Gl, extension_name: &[u8]) -> bool {
+    unsafe {
+        let mut extension_count = 0;
+        gl.GetIntegerv(gl::NUM_EXTENSIONS, &mut extension_count);
+        (0..(extension_count as GLuint)).any(|extension_index| {
+            let extension = gl.GetStringi(gl::EXTENSIONS, extension_index) as *const c_char;
+            !extension.is_null() && CStr::from_ptr(extension).to_bytes() == extension_name
+        })
+    }
+}
+
+// Returns the extensions among `extension_names` that the current context doesn't advertise.
+// Versions without `glGetStringi()` have their extension string read instead.
+pub(crate) fn missing_extensions(gl: &Gl, extension_names: &[String]) -> Vec<String> {
+    let mut extensions = vec![];
+    unsafe {
+        let mut extension_count = 0;
+        if gl.GetStringi.is_loaded() {
+            gl.GetIntegerv(gl::NUM_EXTENSIONS, &mut extension_count);
+        }
+        if gl.GetError() == gl::NO_ERROR && extension_count > 0 {
+            for extension_index in 0..(extension_count as GLuint) {
+                let extension = gl.GetStringi(gl::EXTENSIONS, extension_index) as *const c_char;
+                if !extension.is_null() {
+                    extensions.push(CStr::from_ptr(extension).to_string_lossy().into_owned());
+                }
+            }
+        } else {
+            let extension_string = gl.GetString(gl::EXTENSIONS) as *const c_char;
+            if !extension_string.is_null() {
+                let extension_string = CStr::from_ptr(extension_string).to_string_lossy();
+                extensions.extend(extension_string.split_whitespace().map(str::to_owned));
+            }
+        }
+    }
+
+    extension_names
+        .iter()
+        .filter(|extension_name| !extensions.contains(extension_name))
+        .cloned()
+        .collect()
+}
+
+// Flushes the commands submitted so far and blocks until they have finished, so that readers
+// outside the context see their results. A fence only waits for the commands before it, so it's
+// preferred to `glFinish()` when the context has `glFenceSync()`.
+pub(crate) fn finish_with_fence(gl: &Gl) {
+    unsafe {
+        if gl.FenceSync.is_loaded() {
+            let sync = gl.FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+            if !sync.is_null() {
+                gl.ClientWaitSync(sync, gl::SYNC_FLUSH_COMMANDS_BIT, gl::TIMEOUT_IGNORED);
+                gl.DeleteSync(sync);
+                return;
+            }
+        }
+        gl.Finish();
+    }
+}
+
+// Reads a framebuffer back into CPU memory as 32-bit BGRA pixels with the top row first, which is
+// the layout that window systems take for presenting pixels without a GPU. The rows are `stride`
+// bytes apart, which must be a multiple of 4. `source_origin` says which way up the framebuffer
+// contents are.
+#[cfg(linux)]
+pub(crate) unsafe fn read_pixels_top_down(
+    gl: &Gl,
+    framebuffer_object: GLuint,
+    size: &Size2D<i32>,
+    source_origin: SurfaceOrigin,
+    stride: usize,
+    pixels: *mut u8,
+) {
+    let (mut old_read_framebuffer, mut old_pack_buffer) = (0, 0);
+    let (mut old_pack_alignment, mut old_pack_row_length) = (0, 0);
+    gl.GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut old_read_framebuffer);
+    gl.GetIntegerv(gl::PIXEL_PACK_BUFFER_BINDING, &mut old_pack_buffer);
+    gl.GetIntegerv(gl::PACK_ALIGNMENT, &mut old_pack_alignment);
+    gl.GetIntegerv(gl::PACK_ROW_LENGTH, &mut old_pack_row_length);
+
+    gl.BindFramebuffer(gl::READ_FRAMEBUFFER, framebuffer_object);
+    gl.BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+    gl.PixelStorei(gl::PACK_ALIGNMENT, 4);
+    gl.PixelStorei(gl::PACK_ROW_LENGTH, (stride / 4) as GLint);
+    gl.ReadPixels(
+        0,
+        0,
+        size.width,
+        size.height,
+        gl::BGRA,
+        gl::UNSIGNED_INT_8_8_8_8_REV,
+        pixels as *mut c_void,
+    );
+
+    gl.PixelStorei(gl::PACK_ROW_LENGTH, old_pack_row_length);
+    gl.PixelStorei(gl::PACK_ALIGNMENT, old_pack_alignment);
+    gl.BindBuffer(gl::PIXEL_PACK_BUFFER, old_pack_buffer as GLuint);
+    gl.BindFramebuffer(gl::READ_FRAMEBUFFER, old_read_framebuffer as GLuint);
+
+    // OpenGL reads the bottom row first.
+    if source_origin == SurfaceOrigin::TopLeft {
+        return;
+    }
+    let height = size.height as usize;
+    let pixels = slice::from_raw_parts_mut(pixels, stride * height);
+    for y in 0..(height / 2) {
+        let (top, bottom) = pixels.split_at_mut((height - y - 1) * stride);
+        top[(y * stride)..((y + 1) * stride)].swap_with_slice(&mut bottom[0..stride]);
+    }
+}
+
+#[cfg(not(redox))]
 pub(crate) fn destroy_framebuffer(gl: &Gl, framebuffer_object: GLuint) {
     unbind_framebuffer_if_necessary(gl, framebuffer_object);
     unsafe {
         gl.DeleteFramebuffers(1, &framebuffer_object);
     }
 }
+
+// Scales the `source` rectangle of a framebuffer into its `destination` rectangle with linear
+// filtering, clearing the rest of it to transparent black. This is how surfaces are presented with
+// a viewport on platforms whose compositors can't crop and scale them. The contents pass through a
+// temporary texture, since a framebuffer can't be blitted onto itself.
+#[cfg(any(android, angle, linux, ohos))]
+pub(crate) fn blit_viewport(
+    gl: &Gl,
+    framebuffer_object: GLuint,
+    source: &Rect<i32>,
+    destination: &Rect<i32>,
+) {
+    unsafe {
+        let (mut old_draw_framebuffer, mut old_read_framebuffer) = (0, 0);
+        let mut old_texture = 0;
+        let mut old_clear_color: [GLfloat; 4] = [0.0; 4];
+        let mut old_color_mask: [GLboolean; 4] = [gl::TRUE; 4];
+        gl.GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut old_draw_framebuffer);
+        gl.GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut old_read_framebuffer);
+        gl.GetIntegerv(gl::TEXTURE_BINDING_2D, &mut old_texture);
+        gl.GetFloatv(gl::COLOR_CLEAR_VALUE, old_clear_color.as_mut_ptr());
+        gl.GetBooleanv(gl::COLOR_WRITEMASK, old_color_mask.as_mut_ptr());
+        let scissor_test = gl.IsEnabled(gl::SCISSOR_TEST);
+
+        let mut texture_object = 0;
+        gl.GenTextures(1, &mut texture_object);
+        gl.BindTexture(gl::TEXTURE_2D, texture_object);
+        gl.TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            gl::RGBA8 as GLint,
+            source.size.width,
+            source.size.height,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            ptr::null(),
+        );
+        let temporary_framebuffer_object =
+            create_and_bind_framebuffer(gl, gl::TEXTURE_2D, texture_object);
+
+        gl.Disable(gl::SCISSOR_TEST);
+        gl.ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
+
+        gl.BindFramebuffer(gl::READ_FRAMEBUFFER, framebuffer_object);
+        gl.BlitFramebuffer(
+            source.origin.x,
+            source.origin.y,
+            source.max_x(),
+            source.max_y(),
+            0,
+            0,
+            source.size.width,
+            source.size.height,
+            gl::COLOR_BUFFER_BIT,
+            gl::NEAREST,
+        );
+
+        gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, framebuffer_object);
+        gl.ClearColor(0.0, 0.0, 0.0, 0.0);
+        gl.Clear(gl::COLOR_BUFFER_BIT);
+
+        gl.BindFramebuffer(gl::READ_FRAMEBUFFER, temporary_framebuffer_object);
+        gl.BlitFramebuffer(
+            0,
+            0,
+            source.size.width,
+            source.size.height,
+            destination.origin.x,
+            destination.origin.y,
+            destination.max_x(),
+            destination.max_y(),
+            gl::COLOR_BUFFER_BIT,
+            gl::LINEAR,
+        );
+
+        gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, old_draw_framebuffer as GLuint);
+        gl.BindFramebuffer(gl::READ_FRAMEBUFFER, old_read_framebuffer as GLuint);
+        gl.DeleteFramebuffers(1, &temporary_framebuffer_object);
+        gl.BindTexture(gl::TEXTURE_2D, old_texture as GLuint);
+        gl.DeleteTextures(1, &texture_object);
+
+        let [r, g, b, a] = old_clear_color;
+        gl.ClearColor(r, g, b, a);
+        let [r, g, b, a] = old_color_mask;
+        gl.ColorMask(r, g, b, a);
+        if scissor_test == gl::TRUE {
+            gl.Enable(gl::SCISSOR_TEST);
+        }
+    }
+}
+
+// Copies the contents of one framebuffer into another of the same size, swapping the red and
+// blue channels if asked. Desktop OpenGL swaps them as it reads the pixels back into a pixel
+// buffer object, so they never leave the GPU; OpenGL ES can only read back RGBA, so there they're
+// swapped on the CPU.
+#[cfg(any(android, angle, linux, macos, ohos))]
+pub(crate) fn copy_framebuffer(
+    gl: &Gl,
+    read_framebuffer_object: GLuint,
+    draw_framebuffer_object: GLuint,
+    size: &Size2D<i32>,
+    swap_red_blue: bool,
+) {
+    unsafe {
+        let (mut old_draw_framebuffer, mut old_read_framebuffer) = (0, 0);
+        gl.GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut old_draw_framebuffer);
+        gl.GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut old_read_framebuffer);
+        let scissor_test = gl.IsEnabled(gl::SCISSOR_TEST);
+        gl.Disable(gl::SCISSOR_TEST);
+
+        let mut read_framebuffer_object = read_framebuffer_object;
+        let mut texture_object = 0;
+        if swap_red_blue {
+            let (mut old_texture, mut old_pack_buffer, mut old_unpack_buffer) = (0, 0, 0);
+            gl.GetIntegerv(gl::TEXTURE_BINDING_2D, &mut old_texture);
+            gl.GetIntegerv(gl::PIXEL_PACK_BUFFER_BINDING, &mut old_pack_buffer);
+            gl.GetIntegerv(gl::PIXEL_UNPACK_BUFFER_BINDING, &mut old_unpack_buffer);
+
+            gl.GenTextures(1, &mut texture_object);
+            gl.BindTexture(gl::TEXTURE_2D, texture_object);
+            gl.BindFramebuffer(gl::READ_FRAMEBUFFER, read_framebuffer_object);
+
+            #[cfg(not(any(android, ohos)))]
+            {
+                let mut pixel_buffer = 0;
+                gl.GenBuffers(1, &mut pixel_buffer);
+                gl.BindBuffer(gl::PIXEL_PACK_BUFFER, pixel_buffer);
+                gl.BufferData(
+                    gl::PIXEL_PACK_BUFFER,
+                    size.width as isize * size.height as isize * 4,
+                    ptr::null(),
+                    gl::STREAM_COPY,
+                );
+                gl.ReadPixels(
+                    0,
+                    0,
+                    size.width,
+                    size.height,
+                    gl::BGRA,
+                    gl::UNSIGNED_BYTE,
+                    ptr::null_mut(),
+                );
+                gl.BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+                gl.BindBuffer(gl::PIXEL_UNPACK_BUFFER, pixel_buffer);
+                gl.TexImage2D(
+                    gl::TEXTURE_2D,
+                    0,
+                    gl::RGBA8 as GLint,
+                    size.width,
+                    size.height,
+                    0,
+                    gl::RGBA,
+                    gl::UNSIGNED_BYTE,
+                    ptr::null(),
+                );
+                gl.BindBuffer(gl::PIXEL_UNPACK_BUFFER, 0);
+                gl.DeleteBuffers(1, &pixel_buffer);
+            }
+
+            #[cfg(any(android, ohos))]
+            {
+                let mut pixels = vec![0u8; size.width as usize * size.height as usize * 4];
+                gl.BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+                gl.BindBuffer(gl::PIXEL_UNPACK_BUFFER, 0);
+                gl.ReadPixels(
+                    0,
+                    0,
+                    size.width,
+                    size.height,
+                    gl::RGBA,
+                    gl::UNSIGNED_BYTE,
+                    pixels.as_mut_ptr() as *mut _,
+                );
+                for pixel in pixels.chunks_mut(4) {
+                    pixel.swap(0, 2);
+                }
+                gl.TexImage2D(
+                    gl::TEXTURE_2D,
+                    0,
+                    gl::RGBA8 as GLint,
+                    size.width,
+                    size.height,
+                    0,
+                    gl::RGBA,
+                    gl::UNSIGNED_BYTE,
+                    pixels.as_ptr() as *const _,
+                );
+            }
+
+            gl.BindTexture(gl::TEXTURE_2D, old_texture as GLuint);
+            gl.BindBuffer(gl::PIXEL_PACK_BUFFER, old_pack_buffer as GLuint);
+            gl.BindBuffer(gl::PIXEL_UNPACK_BUFFER, old_unpack_buffer as GLuint);
+            read_framebuffer_object =
+                create_and_bind_framebuffer(gl, gl::TEXTURE_2D, texture_object);
+        }
+
+        gl.BindFramebuffer(gl::READ_FRAMEBUFFER, read_framebuffer_object);
+        gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, draw_framebuffer_object);
+        gl.BlitFramebuffer(
+            0,
+            0,
+            size.width,
+            size.height,
+            0,
+            0,
+            size.width,
+            size.height,
+            gl::COLOR_BUFFER_BIT,
+            gl::NEAREST,
+        );
+
+        gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, old_draw_framebuffer as GLuint);
+        gl.BindFramebuffer(gl::READ_FRAMEBUFFER, old_read_framebuffer as GLuint);
+        if swap_red_blue {
+            gl.DeleteFramebuffers(1, &read_framebuffer_object);
+            gl.DeleteTextures(1, &texture_object);
+        }
+        if scissor_test == gl::TRUE {
+            gl.Enable(gl::SCISSOR_TEST);
+        }
+    }
+}
+
+// Fills in the mip chain of a 2D texture from its base level and switches its minification filter
+// over to sampling from it, leaving the texture binding as it was.
+#[cfg(any(android, linux, redox))]
+pub(crate) fn generate_mipmaps(gl: &Gl, texture_object: GLuint) {
+    unsafe {
+        let mut bindings = BindingsGuard::for_texture(gl, gl::TEXTURE_2D, gl::TEXTURE_BINDING_2D);
+        bindings.bind_texture(texture_object);
+        gl.GenerateMipmap(gl::TEXTURE_2D);
+        gl.TexParameteri(
+            gl::TEXTURE_2D,
+            gl::TEXTURE_MIN_FILTER,
+            gl::LINEAR_MIPMAP_LINEAR as GLint,
+        );
+    }
+}
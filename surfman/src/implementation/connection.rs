@@ -6,17 +6,57 @@
 use super::super::connection::{Connection, NativeConnection};
 use super::super::device::{Adapter, Device, NativeDevice};
 use super::super::surface::NativeWidget;
-use crate::connection::Connection as ConnectionInterface;
+use crate::connection::NativeWidgetHandle;
+use crate::connection::{Connection as ConnectionInterface, IpcDescriptor, NativeDisplayHandle};
 use crate::info::GLApi;
-use crate::Error;
+use crate::timings;
+use crate::{DisplayInfo, Error};
 
 use euclid::default::Size2D;
 
 use std::os::raw::c_void;
+use std::time::Duration;
 
 #[cfg(feature = "sm-winit")]
 use winit::window::Window;
 
+// As with devices, the backends implement device creation as `*_impl()` methods, which these wrap
+// in order to time it.
+impl Connection {
+    /// Opens the hardware device corresponding to the given adapter.
+    ///
+    /// Device handles are local to a single thread.
+    pub fn create_device(&self, adapter: &Adapter) -> Result<Device, Error> {
+        let (result, elapsed) = timings::time(|| self.create_device_impl(adapter));
+        record_device_open(&result, elapsed);
+        result
+    }
+
+    /// Wraps an existing native device type in a device.
+    ///
+    /// # Safety
+    ///
+    /// The native device must be valid, and must stay alive as long as the returned device unless
+    /// the backend documents that it's retained.
+    pub unsafe fn create_device_from_native_device(
+        &self,
+        native_device: NativeDevice,
+    ) -> Result<Device, Error> {
+        let (result, elapsed) =
+            timings::time(|| self.create_device_from_native_device_impl(native_device));
+        record_device_open(&result, elapsed);
+        result
+    }
+}
+
+fn record_device_open(result: &Result<Device, Error>, elapsed: Duration) {
+    if let Ok(ref device) = *result {
+        device
+            .creation_timings_recorder()
+            .record(|timings| timings.device_open = elapsed);
+    }
+}
+
 #[deny(unconditional_recursion)]
 impl ConnectionInterface for Connection {
     type Adapter = Adapter;
@@ -30,16 +70,31 @@ impl ConnectionInterface for Connection {
         Connection::new()
     }
 
+    #[inline]
+    unsafe fn from_ipc_descriptor(descriptor: IpcDescriptor) -> Result<Connection, Error> {
+        Connection::from_ipc_descriptor(descriptor)
+    }
+
     #[inline]
     fn native_connection(&self) -> Self::NativeConnection {
         Connection::native_connection(self)
     }
 
+    #[inline]
+    fn native_display(&self) -> NativeDisplayHandle {
+        Connection::native_display(self)
+    }
+
     #[inline]
     fn gl_api(&self) -> GLApi {
         Connection::gl_api(self)
     }
 
+    #[inline]
+    fn displays(&self) -> Result<Vec<DisplayInfo>, Error> {
+        Connection::displays(self)
+    }
+
     #[inline]
     fn create_adapter(&self) -> Result<Adapter, Error> {
         Connection::create_adapter(self)
@@ -60,6 +115,11 @@ impl ConnectionInterface for Connection {
         Connection::create_software_adapter(self)
     }
 
+    #[inline]
+    fn create_adapter_for_display(&self, display: &DisplayInfo) -> Result<Adapter, Error> {
+        Connection::create_adapter_for_display(self, display)
+    }
+
     #[inline]
     fn create_device(&self, adapter: &Adapter) -> Result<Device, Error> {
         Connection::create_device(self, adapter)
@@ -97,6 +157,14 @@ impl ConnectionInterface for Connection {
         Connection::create_native_widget_from_ptr(self, raw, size)
     }
 
+    #[inline]
+    unsafe fn create_native_widget_from_handle(
+        &self,
+        handle: NativeWidgetHandle,
+    ) -> Result<NativeWidget, Error> {
+        Connection::create_native_widget_from_handle(self, handle)
+    }
+
     #[inline]
     #[cfg(feature = "sm-raw-window-handle")]
     fn create_native_widget_from_rwh(
@@ -6,14 +6,505 @@
 use super::super::connection::Connection;
 use super::super::context::{Context, ContextDescriptor, NativeContext};
 use super::super::device::{Adapter, Device};
-use super::super::surface::{NativeWidget, Surface, SurfaceTexture};
+use super::super::surface::{NativeWidget, Surface, SurfaceDestroyToken, SurfaceTexture};
 use crate::connection::Connection as ConnectionInterface;
+use crate::deferred;
 use crate::device::Device as DeviceInterface;
 use crate::gl::types::{GLenum, GLuint};
-use crate::{ContextAttributes, ContextID, Error, GLApi, SurfaceAccess, SurfaceInfo, SurfaceType};
-use euclid::default::Size2D;
+use crate::gl_utils;
+use crate::label;
+use crate::lifecycle;
+use crate::metrics::{self, Metric};
+use crate::timings;
+use crate::watchdog::{self, WatchedOperation};
+use crate::{
+    Colorspace, ContextAttributes, ContextID, CreateSurfacesResult, Error, GLApi, Gl,
+    PresentationStatistics, SurfaceAccess, SurfaceAlphaMode, SurfaceUsage, SurfaceVisibility,
+};
+use crate::{ContextDescriptorID, DeviceEventHandler, SurfaceLayoutRequirements, WidgetOutputInfo};
+use crate::{CreationTimings, NativeDeviceHandle};
+use crate::{
+    LifecycleEvent, LifecycleObserver, SurfaceFormat, SurfaceID, SurfaceInfo, SurfaceOrigin,
+    SurfaceType,
+};
+use euclid::default::{Rect, Size2D};
 
+use std::mem;
 use std::os::raw::c_void;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+// The backends implement their raw operations as `*_impl()` methods. The public methods wrapping
+// them here do the bookkeeping that every backend shares, so that it happens whether the methods
+// are called on the device directly or through the `Device` trait.
+impl Device {
+    /// Creates a context descriptor with the given attributes.
+    ///
+    /// Context descriptors are local to this device.
+    pub fn create_context_descriptor(
+        &self,
+        attributes: &ContextAttributes,
+    ) -> Result<ContextDescriptor, Error> {
+        let (result, elapsed) = timings::time(|| self.create_context_descriptor_impl(attributes));
+        self.creation_timings_recorder().record(|timings| {
+            timings.config_selection += elapsed;
+            if result.is_ok() {
+                timings.context_descriptors_created += 1;
+            }
+        });
+        result
+    }
+
+    /// Creates a new OpenGL context.
+    ///
+    /// The context initially has no surface attached. Until a surface is bound to it, rendering
+    /// commands will fail or have no effect.
+    ///
+    /// If the descriptor was created with `required_extensions`, the new context is briefly made
+    /// current to check that it supports them. If it lacks any, it's destroyed again and
+    /// `Error::MissingExtensions` is returned.
+    pub fn create_context(
+        &mut self,
+        descriptor: &ContextDescriptor,
+        share_with: Option<&Context>,
+    ) -> Result<Context, Error> {
+        let (result, elapsed) = timings::time(|| {
+            let mut context = self.create_context_impl(descriptor, share_with)?;
+            let required_extensions = self
+                .context_descriptor_attributes(descriptor)
+                .required_extensions;
+            if !required_extensions.is_empty() {
+                if let Err(err) = check_required_extensions(self, &context, &required_extensions) {
+                    // The context is unusable either way.
+                    let _ = self.destroy_context_impl(&mut context);
+                    return Err(err);
+                }
+            }
+            Ok(context)
+        });
+        self.creation_timings_recorder().record(|timings| {
+            timings.context_creation += elapsed;
+            if result.is_ok() {
+                timings.contexts_created += 1;
+            }
+        });
+        let context = result?;
+        lifecycle::notify(self.lifecycle_observer(), || {
+            LifecycleEvent::ContextCreated {
+                context_id: self.context_id(&context),
+            }
+        });
+        Ok(context)
+    }
+
+    /// Wraps a native context object in an OpenGL context.
+    ///
+    /// The native context isn't destroyed when the context is, unless the backend documents
+    /// otherwise for its `NativeContext`.
+    ///
+    /// # Safety
+    ///
+    /// The native context must be valid, and must stay alive as long as the returned context unless
+    /// the backend documents that it's retained.
+    pub unsafe fn create_context_from_native_context(
+        &self,
+        native_context: NativeContext,
+    ) -> Result<Context, Error> {
+        let context = self.create_context_from_native_context_impl(native_context)?;
+        lifecycle::notify(self.lifecycle_observer(), || {
+            LifecycleEvent::ContextCreated {
+                context_id: self.context_id(&context),
+            }
+        });
+        Ok(context)
+    }
+
+    /// Destroys a context, along with the surface bound to it and the surfaces queued for it with
+    /// `defer_destroy_surface()`.
+    ///
+    /// The context must have been created on this device. Queued surfaces that fail to be
+    /// destroyed don't stop the context from being destroyed: they're given up instead, as with
+    /// `surface_into_destroy_token()`, and the errors are logged.
+    pub fn destroy_context(&self, context: &mut Context) -> Result<(), Error> {
+        let context_id = self.context_id(context);
+        for mut surface in deferred::take_deferred_surfaces::<Surface>(context_id) {
+            if let Err(err) = destroy_surface(self, context, &mut surface) {
+                error!("Failed to destroy a deferred surface: {:?}", err);
+                let token = self.surface_into_destroy_token(surface);
+                if let Err(err) = self.destroy_token(token) {
+                    error!("Failed to destroy a deferred surface's token: {:?}", err);
+                }
+            }
+        }
+        self.destroy_context_impl(context)?;
+        label::forget_context_label(context_id);
+        lifecycle::notify(self.lifecycle_observer(), || {
+            LifecycleEvent::ContextDestroyed { context_id }
+        });
+        Ok(())
+    }
+
+    /// Makes the context the current OpenGL context for this thread.
+    ///
+    /// After calling this function, it is valid to use OpenGL rendering commands. Surfaces queued
+    /// for the context with `defer_destroy_surface()` are destroyed.
+    pub fn make_context_current(&self, context: &Context) -> Result<(), Error> {
+        let result = self.make_context_current_impl(context);
+        self.device_event_dispatcher().check(result)?;
+        if metrics::enabled() {
+            metrics::record(Metric::MakeCurrentCalls, 1);
+        }
+        if let Err(err) = destroy_deferred_surfaces(self, context) {
+            error!("Failed to destroy deferred surfaces: {:?}", err);
+        }
+        Ok(())
+    }
+
+    /// Makes the context the current OpenGL context for this thread, rendering to the given
+    /// surface instead of any surface bound to it.
+    ///
+    /// The surface must have been created with this context, or an `IncompatibleSurface` error is
+    /// returned. It stays the rendering target until the context is made current again with
+    /// `make_context_current()`.
+    pub fn make_context_current_with_surface(
+        &self,
+        context: &Context,
+        surface: &Surface,
+    ) -> Result<(), Error> {
+        let result = self.make_context_current_with_surface_impl(context, surface);
+        self.device_event_dispatcher().check(result)?;
+        if metrics::enabled() {
+            metrics::record(Metric::MakeCurrentCalls, 1);
+        }
+        Ok(())
+    }
+
+    /// Attaches a surface to a context for rendering.
+    ///
+    /// This function takes ownership of the surface. The surface must have been created with this
+    /// context, or an `IncompatibleSurface` error is returned. If a surface is already bound, a
+    /// `SurfaceAlreadyBound` error is returned. If an error is returned, the surface is returned
+    /// alongside it.
+    pub fn bind_surface_to_context(
+        &self,
+        context: &mut Context,
+        surface: Surface,
+    ) -> Result<(), (Error, Surface)> {
+        self.bind_surface_to_context_impl(context, surface)
+    }
+
+    /// Removes and returns any attached surface from this context.
+    ///
+    /// Any pending OpenGL commands targeting this surface will be automatically flushed, so the
+    /// surface is safe to read from immediately when this function returns.
+    pub fn unbind_surface_from_context(
+        &self,
+        context: &mut Context,
+    ) -> Result<Option<Surface>, Error> {
+        self.unbind_surface_from_context_impl(context)
+    }
+
+    /// Makes the rendering submitted so far to a surface visible to everything else that reads
+    /// it, waiting for it to finish if need be.
+    ///
+    /// The surface must have been created with this context, or an `IncompatibleSurface` error is
+    /// returned.
+    pub fn flush_context(&self, context: &Context, surface: &Surface) -> Result<(), Error> {
+        let surface_id = self.surface_info(surface).id;
+        watchdog::watch(WatchedOperation::FenceWait, surface_id, || {
+            self.flush_context_impl(context, surface)
+        })
+    }
+
+    /// Creates either a generic or a widget surface, depending on the supplied surface type.
+    ///
+    /// Only the given context may ever render to the surface, but generic surfaces can be wrapped
+    /// up in a `SurfaceTexture` for reading by other contexts.
+    pub fn create_surface(
+        &mut self,
+        context: &Context,
+        surface_access: SurfaceAccess,
+        surface_usage: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
+        surface_type: SurfaceType<NativeWidget>,
+    ) -> Result<Surface, Error> {
+        let surface = self.create_surface_impl(
+            context,
+            surface_access,
+            surface_usage,
+            surface_alpha_mode,
+            surface_type,
+        )?;
+        self.note_surface_created(&surface);
+        Ok(surface)
+    }
+
+    /// Creates `count` generic surfaces of the given size, as if by calling `create_surface()`
+    /// that many times, but sharing the work common to all of them.
+    ///
+    /// If one of the surfaces fails to be created, the error is returned along with the surfaces
+    /// that were created before it, which the caller must destroy.
+    pub fn create_surfaces(
+        &mut self,
+        context: &Context,
+        surface_access: SurfaceAccess,
+        surface_usage: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
+        size: &Size2D<i32>,
+        count: usize,
+    ) -> CreateSurfacesResult<Surface> {
+        let result = self.create_surfaces_impl(
+            context,
+            surface_access,
+            surface_usage,
+            surface_alpha_mode,
+            size,
+            count,
+        );
+        // Surfaces handed back with an error are still allocated, so they're accounted for too.
+        let surfaces = match result {
+            Ok(ref surfaces) | Err((_, ref surfaces)) => surfaces,
+        };
+        for surface in surfaces {
+            self.note_surface_created(surface);
+        }
+        result
+    }
+
+    /// Creates a new generic surface with a copy of the contents of the given one, stored with
+    /// its color channels in the given order.
+    ///
+    /// The surface must be a generic surface belonging to the given context, which is left
+    /// current. The new surface is GPU-only.
+    pub fn convert_surface_format(
+        &mut self,
+        context: &mut Context,
+        surface: &Surface,
+        format: SurfaceFormat,
+    ) -> Result<Surface, Error> {
+        let surface = self.convert_surface_format_impl(context, surface, format)?;
+        self.note_surface_created(&surface);
+        Ok(surface)
+    }
+
+    /// Wraps an existing OpenGL texture of the given context in a new generic surface.
+    ///
+    /// The texture must be a complete `GL_TEXTURE_2D` of the given size, which stays owned by
+    /// the caller and must not be redefined while the surface is alive. Backends whose surfaces
+    /// can't share storage with an OpenGL texture return an `UnsupportedOnThisPlatform` error.
+    pub fn create_surface_from_texture(
+        &mut self,
+        context: &mut Context,
+        size: &Size2D<i32>,
+        texture_object: GLuint,
+    ) -> Result<Surface, Error> {
+        let surface = self.create_surface_from_texture_impl(context, size, texture_object)?;
+        self.note_surface_created(&surface);
+        Ok(surface)
+    }
+
+    /// Destroys a surface.
+    ///
+    /// The supplied context must be the context the surface is associated with, or this returns
+    /// an `IncompatibleSurface` error.
+    ///
+    /// You must explicitly call this method to dispose of a surface. Otherwise, a panic occurs in
+    /// the `drop` method.
+    pub fn destroy_surface(
+        &self,
+        context: &mut Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        destroy_surface(self, context, surface)
+    }
+
+    /// Gives up a surface, returning a token that `destroy_token()` can destroy later, on any
+    /// thread and without the surface's context.
+    ///
+    /// The OpenGL objects that the surface owns in its context, such as its framebuffer, aren't
+    /// part of the token. They're freed when the context is destroyed.
+    pub fn surface_into_destroy_token(&self, surface: Surface) -> SurfaceDestroyToken {
+        // The surface is gone as far as anyone can tell, even though its resources aren't freed
+        // until the token is destroyed.
+        self.note_surface_destroyed(
+            &self.surface_info(&surface),
+            self.surface_memory_usage(&surface),
+        );
+        surface.into_destroy_token()
+    }
+
+    /// Displays the contents of a widget surface on screen.
+    ///
+    /// The supplied context must match the context the surface was created with, or an
+    /// `IncompatibleSurface` error is returned.
+    pub fn present_surface(&self, context: &Context, surface: &mut Surface) -> Result<(), Error> {
+        let surface_id = self.surface_info(surface).id;
+        let result = watchdog::watch(WatchedOperation::Present, surface_id, || {
+            self.present_surface_impl(context, surface)
+        });
+        self.note_surface_presented(result, surface_id)
+    }
+
+    /// Displays the contents of a widget surface on screen, hinting that only the given
+    /// rectangles, in device pixels from the lower left corner, have changed since the last
+    /// present.
+    pub fn present_surface_with_damage(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        let surface_id = self.surface_info(surface).id;
+        let result = watchdog::watch(WatchedOperation::Present, surface_id, || {
+            self.present_surface_with_damage_impl(context, surface, damage)
+        });
+        self.note_surface_presented(result, surface_id)
+    }
+
+    /// Displays the `source` rectangle of a widget surface in the `destination` rectangle of its
+    /// widget, scaling it to fit.
+    ///
+    /// Both rectangles are in device pixels, with the origin at the lower left corner, and must be
+    /// nonempty.
+    pub fn present_surface_with_viewport(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        source: &Rect<i32>,
+        destination: &Rect<i32>,
+    ) -> Result<(), Error> {
+        let surface_id = self.surface_info(surface).id;
+        let result = watchdog::watch(WatchedOperation::Present, surface_id, || {
+            self.present_surface_with_viewport_impl(context, surface, source, destination)
+        });
+        self.note_surface_presented(result, surface_id)
+    }
+
+    /// Resizes a widget surface.
+    pub fn resize_surface(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        size: Size2D<i32>,
+    ) -> Result<(), Error> {
+        let old_size = self.surface_info(surface).size;
+        let old_memory_usage = self.surface_memory_usage(surface);
+        self.resize_surface_impl(context, surface, size)?;
+        let memory_usage_counter = self.memory_usage_counter();
+        memory_usage_counter.fetch_sub(old_memory_usage, Ordering::Relaxed);
+        memory_usage_counter.fetch_add(self.surface_memory_usage(surface), Ordering::Relaxed);
+        if metrics::enabled() {
+            let delta = metrics::surface_bytes(&size) - metrics::surface_bytes(&old_size);
+            metrics::record(Metric::SurfaceBytesAllocated, delta);
+        }
+        lifecycle::notify(self.lifecycle_observer(), || {
+            LifecycleEvent::SurfaceResized {
+                surface_id: self.surface_info(surface).id,
+                size,
+            }
+        });
+        Ok(())
+    }
+
+    /// Attaches a debug name to a surface, so that it shows up under that name in GPU captures.
+    ///
+    /// Besides being recorded for `surface_label()`, the name is given to the surface's
+    /// framebuffer object and its attachments if the driver supports `GL_KHR_debug`, and to the
+    /// native buffer behind the surface where the platform has a way to name one. The context that
+    /// was current before is made current again afterward.
+    pub fn set_surface_label(
+        &self,
+        context: &Context,
+        surface: &Surface,
+        label: &str,
+    ) -> Result<(), Error> {
+        let surface_info = self.surface_info(surface);
+        if surface_info.context_id != self.context_id(context) {
+            return Err(Error::IncompatibleSurface);
+        }
+        label::set_surface_label(surface_info.id, label);
+        #[cfg(any(windows, macos))]
+        self.set_surface_native_label(surface, label);
+        if surface_info.framebuffer_object == 0 {
+            return Ok(());
+        }
+
+        let _guard = self.temporarily_make_context_current(context)?;
+        if !self.with_gl_functions(context, |gl| {
+            gl_utils::context_has_extension(gl, b"GL_KHR_debug")
+        }) {
+            return Ok(());
+        }
+
+        // The extension suffixes its entry points on OpenGL ES.
+        let object_label = self.get_proc_address(
+            context,
+            match self.gl_api() {
+                GLApi::GL => "glObjectLabel",
+                GLApi::GLES => "glObjectLabelKHR",
+            },
+        );
+        if object_label.is_null() {
+            return Ok(());
+        }
+        self.with_gl_functions(context, |gl| unsafe {
+            gl_utils::label_framebuffer(
+                gl,
+                mem::transmute::<*const c_void, gl_utils::ObjectLabelFn>(object_label),
+                surface_info.framebuffer_object,
+                label,
+            )
+        });
+        Ok(())
+    }
+
+    // Accounts for a surface that the backend has just created.
+    pub(crate) fn note_surface_created(&self, surface: &Surface) {
+        let surface_info = self.surface_info(surface);
+        self.memory_usage_counter()
+            .fetch_add(self.surface_memory_usage(surface), Ordering::Relaxed);
+        if metrics::enabled() {
+            metrics::record_surface_allocation(&surface_info.size, 1);
+        }
+        lifecycle::notify(self.lifecycle_observer(), || {
+            LifecycleEvent::SurfaceCreated {
+                surface_id: surface_info.id,
+                context_id: surface_info.context_id,
+                size: surface_info.size,
+            }
+        });
+    }
+
+    // Accounts for a surface that has been destroyed or given up. Its info and memory usage have
+    // to be fetched beforehand.
+    fn note_surface_destroyed(&self, surface_info: &SurfaceInfo, memory_usage: usize) {
+        let surface_id = surface_info.id;
+        self.memory_usage_counter()
+            .fetch_sub(memory_usage, Ordering::Relaxed);
+        label::forget_surface_label(surface_id);
+        if metrics::enabled() {
+            metrics::record_surface_allocation(&surface_info.size, -1);
+        }
+        lifecycle::notify(self.lifecycle_observer(), || {
+            LifecycleEvent::SurfaceDestroyed { surface_id }
+        });
+    }
+
+    fn note_surface_presented(
+        &self,
+        result: Result<(), Error>,
+        surface_id: SurfaceID,
+    ) -> Result<(), Error> {
+        self.device_event_dispatcher().check(result)?;
+        if metrics::enabled() {
+            metrics::record(Metric::Presents, 1);
+        }
+        lifecycle::notify(self.lifecycle_observer(), || {
+            LifecycleEvent::SurfacePresented { surface_id }
+        });
+        Ok(())
+    }
+}
 
 #[deny(unconditional_recursion)]
 impl DeviceInterface for Device {
@@ -23,6 +514,7 @@ impl DeviceInterface for Device {
     type NativeContext = NativeContext;
     type Surface = Surface;
     type SurfaceTexture = SurfaceTexture;
+    type SurfaceDestroyToken = SurfaceDestroyToken;
 
     // device.rs
 
@@ -32,6 +524,11 @@ impl DeviceInterface for Device {
         Device::native_device(self)
     }
 
+    #[inline]
+    fn native_device_handle(&self) -> NativeDeviceHandle {
+        Device::native_device_handle(self)
+    }
+
     #[inline]
     fn connection(&self) -> Connection {
         Device::connection(self)
@@ -47,6 +544,36 @@ impl DeviceInterface for Device {
         Device::gl_api(self)
     }
 
+    #[inline]
+    fn set_lifecycle_observer(&mut self, observer: Option<Arc<dyn LifecycleObserver>>) {
+        Device::set_lifecycle_observer(self, observer)
+    }
+
+    #[inline]
+    fn lifecycle_observer(&self) -> Option<Arc<dyn LifecycleObserver>> {
+        Device::lifecycle_observer(self)
+    }
+
+    #[inline]
+    fn set_device_event_handler(&mut self, handler: Option<DeviceEventHandler>) {
+        Device::set_device_event_handler(self, handler)
+    }
+
+    #[inline]
+    fn device_event_handler(&self) -> Option<DeviceEventHandler> {
+        Device::device_event_handler(self)
+    }
+
+    #[inline]
+    fn memory_usage(&self) -> usize {
+        Device::memory_usage(self)
+    }
+
+    #[inline]
+    fn creation_timings(&self) -> CreationTimings {
+        Device::creation_timings(self)
+    }
+
     // context.rs
 
     #[inline]
@@ -57,6 +584,14 @@ impl DeviceInterface for Device {
         Device::create_context_descriptor(self, attributes)
     }
 
+    #[inline]
+    fn intern_context_descriptor(
+        &self,
+        context_descriptor: &Self::ContextDescriptor,
+    ) -> ContextDescriptorID {
+        Device::intern_context_descriptor(self, context_descriptor)
+    }
+
     #[inline]
     fn create_context(
         &mut self,
@@ -74,6 +609,11 @@ impl DeviceInterface for Device {
         Device::create_context_from_native_context(self, native_context)
     }
 
+    #[inline]
+    unsafe fn from_current_context() -> Result<(Device, Context), Error> {
+        Device::from_current_context()
+    }
+
     #[inline]
     fn destroy_context(&self, context: &mut Self::Context) -> Result<(), Error> {
         Device::destroy_context(self, context)
@@ -89,6 +629,15 @@ impl DeviceInterface for Device {
         Device::make_context_current(self, context)
     }
 
+    #[inline]
+    fn make_context_current_with_surface(
+        &self,
+        context: &Self::Context,
+        surface: &Self::Surface,
+    ) -> Result<(), Error> {
+        Device::make_context_current_with_surface(self, context, surface)
+    }
+
     #[inline]
     fn make_no_context_current(&self) -> Result<(), Error> {
         Device::make_no_context_current(self)
@@ -107,6 +656,14 @@ impl DeviceInterface for Device {
         Device::get_proc_address(self, context, symbol_name)
     }
 
+    #[inline]
+    fn with_gl_functions<F, R>(&self, context: &Self::Context, callback: F) -> R
+    where
+        F: FnOnce(&Gl) -> R,
+    {
+        Device::with_gl_functions(self, context, callback)
+    }
+
     #[inline]
     fn bind_surface_to_context(
         &self,
@@ -124,6 +681,21 @@ impl DeviceInterface for Device {
         Device::unbind_surface_from_context(self, context)
     }
 
+    #[inline]
+    fn bind_external_framebuffer(
+        &self,
+        context: &mut Self::Context,
+        framebuffer_object: GLuint,
+        size: Size2D<i32>,
+    ) -> Result<(), Error> {
+        Device::bind_external_framebuffer(self, context, framebuffer_object, size)
+    }
+
+    #[inline]
+    fn flush_context(&self, context: &Self::Context, surface: &Self::Surface) -> Result<(), Error> {
+        Device::flush_context(self, context, surface)
+    }
+
     #[inline]
     fn context_id(&self, context: &Self::Context) -> ContextID {
         Device::context_id(self, context)
@@ -146,9 +718,89 @@ impl DeviceInterface for Device {
         &mut self,
         context: &Self::Context,
         surface_access: SurfaceAccess,
+        surface_usage: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
         surface_type: SurfaceType<NativeWidget>,
     ) -> Result<Self::Surface, Error> {
-        Device::create_surface(self, context, surface_access, surface_type)
+        Device::create_surface(
+            self,
+            context,
+            surface_access,
+            surface_usage,
+            surface_alpha_mode,
+            surface_type,
+        )
+    }
+
+    #[inline]
+    fn create_surfaces(
+        &mut self,
+        context: &Self::Context,
+        surface_access: SurfaceAccess,
+        surface_usage: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
+        size: &Size2D<i32>,
+        count: usize,
+    ) -> CreateSurfacesResult<Self::Surface> {
+        Device::create_surfaces(
+            self,
+            context,
+            surface_access,
+            surface_usage,
+            surface_alpha_mode,
+            size,
+            count,
+        )
+    }
+
+    #[cfg(any(android, linux))]
+    #[inline]
+    fn create_layered_surface(
+        &mut self,
+        context: &Self::Context,
+        surface_access: SurfaceAccess,
+        surface_usage: SurfaceUsage,
+        size: &Size2D<i32>,
+        layers: u32,
+    ) -> Result<Self::Surface, Error> {
+        Device::create_layered_surface(self, context, surface_access, surface_usage, size, layers)
+    }
+
+    #[cfg(any(android, linux))]
+    #[inline]
+    fn set_surface_layer(
+        &self,
+        context: &Self::Context,
+        surface: &mut Self::Surface,
+        layer: u32,
+    ) -> Result<(), Error> {
+        Device::set_surface_layer(self, context, surface, layer)
+    }
+
+    #[cfg(any(android, linux))]
+    #[inline]
+    fn surface_layers(&self, surface: &Self::Surface) -> u32 {
+        Device::surface_layers(self, surface)
+    }
+
+    #[inline]
+    fn convert_surface_format(
+        &mut self,
+        context: &mut Self::Context,
+        surface: &Self::Surface,
+        format: SurfaceFormat,
+    ) -> Result<Self::Surface, Error> {
+        Device::convert_surface_format(self, context, surface, format)
+    }
+
+    #[inline]
+    fn create_surface_from_texture(
+        &mut self,
+        context: &mut Self::Context,
+        size: &Size2D<i32>,
+        texture_object: GLuint,
+    ) -> Result<Self::Surface, Error> {
+        Device::create_surface_from_texture(self, context, size, texture_object)
     }
 
     #[inline]
@@ -169,6 +821,21 @@ impl DeviceInterface for Device {
         Device::destroy_surface(self, context, surface)
     }
 
+    #[inline]
+    fn surface_into_destroy_token(&self, surface: Surface) -> SurfaceDestroyToken {
+        Device::surface_into_destroy_token(self, surface)
+    }
+
+    #[inline]
+    fn destroy_token(&self, token: SurfaceDestroyToken) -> Result<(), Error> {
+        Device::destroy_token(self, token)
+    }
+
+    #[inline]
+    fn destroy_deferred_surfaces(&self, context: &mut Self::Context) -> Result<(), Error> {
+        destroy_deferred_surfaces(self, context)
+    }
+
     #[inline]
     fn destroy_surface_texture(
         &self,
@@ -183,6 +850,27 @@ impl DeviceInterface for Device {
         Device::surface_gl_texture_target(self)
     }
 
+    #[cfg(any(android, linux))]
+    #[inline]
+    fn surface_texture_binding_target(&self, surface_texture: &Self::SurfaceTexture) -> GLenum {
+        Device::surface_texture_binding_target(self, surface_texture)
+    }
+
+    #[cfg(any(android, linux))]
+    #[inline]
+    fn surface_texture_layers(&self, surface_texture: &Self::SurfaceTexture) -> u32 {
+        Device::surface_texture_layers(self, surface_texture)
+    }
+
+    #[inline]
+    fn generate_surface_mipmaps(
+        &self,
+        context: &mut Self::Context,
+        surface_texture: &Self::SurfaceTexture,
+    ) -> Result<(), Error> {
+        Device::generate_surface_mipmaps(self, context, surface_texture)
+    }
+
     #[inline]
     fn present_surface(
         &self,
@@ -192,6 +880,74 @@ impl DeviceInterface for Device {
         Device::present_surface(self, context, surface)
     }
 
+    #[inline]
+    fn present_surface_with_damage(
+        &self,
+        context: &Self::Context,
+        surface: &mut Self::Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        Device::present_surface_with_damage(self, context, surface, damage)
+    }
+
+    #[inline]
+    fn present_surface_with_viewport(
+        &self,
+        context: &Self::Context,
+        surface: &mut Self::Surface,
+        source: &Rect<i32>,
+        destination: &Rect<i32>,
+    ) -> Result<(), Error> {
+        Device::present_surface_with_viewport(self, context, surface, source, destination)
+    }
+
+    #[inline]
+    fn set_surface_damage_region(
+        &self,
+        context: &Self::Context,
+        surface: &mut Self::Surface,
+        region: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        Device::set_surface_damage_region(self, context, surface, region)
+    }
+
+    #[inline]
+    fn surface_buffer_age(
+        &self,
+        context: &Self::Context,
+        surface: &Self::Surface,
+    ) -> Result<u32, Error> {
+        Device::surface_buffer_age(self, context, surface)
+    }
+
+    #[inline]
+    fn set_surface_preserve_buffer(
+        &self,
+        context: &Self::Context,
+        surface: &mut Self::Surface,
+        preserve: bool,
+    ) -> Result<(), Error> {
+        Device::set_surface_preserve_buffer(self, context, surface, preserve)
+    }
+
+    #[inline]
+    fn set_surface_origin(
+        &self,
+        surface: &mut Self::Surface,
+        origin: SurfaceOrigin,
+    ) -> Result<(), Error> {
+        Device::set_surface_origin(self, surface, origin)
+    }
+
+    #[inline]
+    fn set_surface_colorspace(
+        &self,
+        surface: &mut Self::Surface,
+        colorspace: Colorspace,
+    ) -> Result<(), Error> {
+        Device::set_surface_colorspace(self, surface, colorspace)
+    }
+
     #[inline]
     fn resize_surface(
         &self,
@@ -207,8 +963,100 @@ impl DeviceInterface for Device {
         Device::surface_info(self, surface)
     }
 
+    #[inline]
+    fn surface_memory_usage(&self, surface: &Self::Surface) -> usize {
+        Device::surface_memory_usage(self, surface)
+    }
+
+    #[inline]
+    fn surface_seed(&self, surface: &Self::Surface) -> Result<u32, Error> {
+        Device::surface_seed(self, surface)
+    }
+
+    #[inline]
+    fn surface_layout_requirements(
+        &self,
+        size: &Size2D<i32>,
+        format: SurfaceFormat,
+        access: SurfaceAccess,
+    ) -> Result<SurfaceLayoutRequirements, Error> {
+        Device::surface_layout_requirements(self, size, format, access)
+    }
+
+    #[inline]
+    fn surface_presentation_statistics(
+        &self,
+        surface: &Self::Surface,
+    ) -> Option<PresentationStatistics> {
+        Device::surface_presentation_statistics(self, surface)
+    }
+
+    #[inline]
+    fn surface_visibility(&self, surface: &Self::Surface) -> SurfaceVisibility {
+        Device::surface_visibility(self, surface)
+    }
+
+    #[inline]
+    fn widget_output_info(&self, surface: &Self::Surface) -> Result<WidgetOutputInfo, Error> {
+        Device::widget_output_info(self, surface)
+    }
+
     #[inline]
     fn surface_texture_object(&self, surface_texture: &Self::SurfaceTexture) -> GLuint {
         Device::surface_texture_object(self, surface_texture)
     }
+
+    #[inline]
+    fn set_surface_label(
+        &self,
+        context: &Self::Context,
+        surface: &Self::Surface,
+        label: &str,
+    ) -> Result<(), Error> {
+        Device::set_surface_label(self, context, surface, label)
+    }
+}
+
+// Destroys a surface with a context that only has to be borrowed immutably, so that surfaces
+// queued for the context can be destroyed when it's made current.
+fn destroy_surface(device: &Device, context: &Context, surface: &mut Surface) -> Result<(), Error> {
+    let surface_info = device.surface_info(surface);
+    let memory_usage = device.surface_memory_usage(surface);
+    device.destroy_surface_impl(context, surface)?;
+    device.note_surface_destroyed(&surface_info, memory_usage);
+    Ok(())
+}
+
+// Destroys the surfaces queued for the given context with `defer_destroy_surface()`. If one fails
+// to be destroyed, it and the surfaces after it stay queued.
+fn destroy_deferred_surfaces(device: &Device, context: &Context) -> Result<(), Error> {
+    let context_id = device.context_id(context);
+    let mut surfaces = deferred::take_deferred_surfaces::<Surface>(context_id).into_iter();
+    while let Some(mut surface) = surfaces.next() {
+        if let Err(err) = destroy_surface(device, context, &mut surface) {
+            deferred::defer_destroy_surface(context_id, surface);
+            for surface in surfaces {
+                deferred::defer_destroy_surface(context_id, surface);
+            }
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+// Fails with `MissingExtensions` if the context lacks any of the given extensions. The context
+// that was current before is made current again afterward.
+fn check_required_extensions(
+    device: &Device,
+    context: &Context,
+    required_extensions: &[String],
+) -> Result<(), Error> {
+    let _guard = device.temporarily_make_context_current(context)?;
+    let gl = Gl::load_with(|symbol| device.get_proc_address(context, symbol));
+    let missing_extensions = gl_utils::missing_extensions(&gl, required_extensions);
+    if missing_extensions.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::MissingExtensions(missing_extensions))
+    }
 }
@@ -5,3 +5,5 @@
 
 mod connection;
 mod device;
+#[cfg(feature = "sm-opencl")]
+mod opencl;
@@ -0,0 +1,23 @@
+// surfman/surfman/src/implementation/opencl.rs
+//
+//! This is an included private module that automatically produces the implementation of the
+//! `GLSharing` trait for a backend.
+
+use super::super::context::Context;
+use super::super::device::Device;
+use super::super::surface::Surface;
+use crate::opencl::{GLSharing, GLSharingProperties, SharedEGLImage};
+use crate::Error;
+
+#[deny(unconditional_recursion)]
+impl GLSharing for Device {
+    #[inline]
+    fn gl_sharing_properties(&self, context: &Context) -> Result<GLSharingProperties, Error> {
+        Device::gl_sharing_properties(self, context)
+    }
+
+    #[inline]
+    fn surface_egl_image(&self, surface: &Surface) -> Result<SharedEGLImage, Error> {
+        Device::surface_egl_image(self, surface)
+    }
+}
@@ -0,0 +1,145 @@
+// surfman/surfman/src/init.rs
+//
+//! One-call creation of a connection, device, and context with reasonable defaults.
+
+use crate::connection::Connection as ConnectionAPI;
+use crate::device::Device as DeviceAPI;
+use crate::{Connection, Context, Device};
+use crate::{ContextAttributeFlags, ContextAttributes, Error, GLVersion};
+use crate::{SurfaceAccess, SurfaceAlphaMode, SurfaceType, SurfaceUsage};
+
+use euclid::default::Size2D;
+
+/// Which kind of adapter `init()` tries first.
+///
+/// If a device can't be opened on the preferred adapter, the others are tried in the order
+/// high-performance, low-power, software.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AdapterPreference {
+    /// A high-performance hardware adapter, such as a discrete GPU.
+    HighPerformance,
+    /// A low-power hardware adapter, such as an integrated GPU.
+    LowPower,
+    /// A software adapter.
+    Software,
+}
+
+/// Options for `init()`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InitOptions {
+    /// The attributes of the context to create.
+    pub context_attributes: ContextAttributes,
+    /// Which kind of adapter to try first.
+    pub adapter_preference: AdapterPreference,
+    /// If set, a generic surface of this size is bound to the context, and the context is made
+    /// current.
+    pub surface_size: Option<Size2D<i32>>,
+}
+
+impl Default for InitOptions {
+    /// OpenGL 3.0 (or OpenGL ES 3.0) with an alpha channel and a depth buffer, like the default
+    /// WebGL 2 context, on a low-power adapter, with no surface.
+    #[inline]
+    fn default() -> InitOptions {
+        InitOptions {
+            context_attributes: ContextAttributes {
+                version: GLVersion::new(3, 0),
+                max_version: None,
+                flags: ContextAttributeFlags::ALPHA | ContextAttributeFlags::DEPTH,
+                required_extensions: vec![],
+                samples: 0,
+            },
+            adapter_preference: AdapterPreference::LowPower,
+            surface_size: None,
+        }
+    }
+}
+
+/// Connects to the default display, opens a device, and creates a context.
+///
+/// This does what nearly every `surfman` application does at startup. Applications that need
+/// more control, such as to connect to a particular window, can do the same with
+/// `init_with_connection()`.
+pub fn init(options: InitOptions) -> Result<(Connection, Device, Context), Error> {
+    init_with_connection(Connection::new()?, options)
+}
+
+/// Opens a device on the given connection, and creates a context.
+///
+/// The adapter is chosen according to `options.adapter_preference`, falling back to the other
+/// kinds of adapter if a device can't be opened on it. If all of them fail, the error from the
+/// preferred adapter is returned.
+pub fn init_with_connection<C, D>(
+    connection: C,
+    options: InitOptions,
+) -> Result<(C, D, D::Context), Error>
+where
+    C: ConnectionAPI<Device = D>,
+    D: DeviceAPI<Connection = C>,
+{
+    let mut device = open_device(&connection, options.adapter_preference)?;
+
+    let context_descriptor = device.create_context_descriptor(&options.context_attributes)?;
+    let mut context = device.create_context(&context_descriptor, None)?;
+
+    if let Some(size) = options.surface_size {
+        if let Err(err) = bind_surface(&mut device, &mut context, size) {
+            device.destroy_context(&mut context)?;
+            return Err(err);
+        }
+    }
+
+    Ok((connection, device, context))
+}
+
+fn open_device<C, D>(connection: &C, adapter_preference: AdapterPreference) -> Result<D, Error>
+where
+    C: ConnectionAPI<Device = D>,
+    D: DeviceAPI<Connection = C>,
+{
+    let mut adapter_preferences = vec![adapter_preference];
+    for &fallback in &[
+        AdapterPreference::HighPerformance,
+        AdapterPreference::LowPower,
+        AdapterPreference::Software,
+    ] {
+        if fallback != adapter_preference {
+            adapter_preferences.push(fallback);
+        }
+    }
+
+    let mut first_error = None;
+    for adapter_preference in adapter_preferences {
+        let adapter = match adapter_preference {
+            AdapterPreference::HighPerformance => connection.create_hardware_adapter(),
+            AdapterPreference::LowPower => connection.create_low_power_adapter(),
+            AdapterPreference::Software => connection.create_software_adapter(),
+        };
+        match adapter.and_then(|adapter| connection.create_device(&adapter)) {
+            Ok(device) => return Ok(device),
+            Err(err) => {
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+    Err(first_error.unwrap())
+}
+
+fn bind_surface<D>(device: &mut D, context: &mut D::Context, size: Size2D<i32>) -> Result<(), Error>
+where
+    D: DeviceAPI,
+    D::Connection: ConnectionAPI,
+{
+    let surface = device.create_surface(
+        context,
+        SurfaceAccess::GPUOnly,
+        SurfaceUsage::default(),
+        SurfaceAlphaMode::default(),
+        SurfaceType::Generic { size },
+    )?;
+    if let Err((err, mut surface)) = device.bind_surface_to_context(context, surface) {
+        device.destroy_surface(context, &mut surface)?;
+        return Err(err);
+    }
+    device.make_context_current(context)
+}
@@ -0,0 +1,44 @@
+// surfman/surfman/src/label.rs
+//
+//! Storage for the debug labels attached to contexts and surfaces.
+
+use crate::context::ContextID;
+use crate::surface::SurfaceID;
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+lazy_static! {
+    static ref CONTEXT_LABELS: Mutex<HashMap<ContextID, String>> = Mutex::new(HashMap::new());
+    static ref SURFACE_LABELS: Mutex<HashMap<SurfaceID, String>> = Mutex::new(HashMap::new());
+}
+
+pub(crate) fn set_context_label(context_id: ContextID, label: &str) {
+    CONTEXT_LABELS
+        .lock()
+        .unwrap()
+        .insert(context_id, label.to_owned());
+}
+
+pub(crate) fn context_label(context_id: ContextID) -> Option<String> {
+    CONTEXT_LABELS.lock().unwrap().get(&context_id).cloned()
+}
+
+pub(crate) fn forget_context_label(context_id: ContextID) {
+    CONTEXT_LABELS.lock().unwrap().remove(&context_id);
+}
+
+pub(crate) fn set_surface_label(surface_id: SurfaceID, label: &str) {
+    SURFACE_LABELS
+        .lock()
+        .unwrap()
+        .insert(surface_id, label.to_owned());
+}
+
+pub(crate) fn surface_label(surface_id: SurfaceID) -> Option<String> {
+    SURFACE_LABELS.lock().unwrap().get(&surface_id).cloned()
+}
+
+pub(crate) fn forget_surface_label(surface_id: SurfaceID) {
+    SURFACE_LABELS.lock().unwrap().remove(&surface_id);
+}
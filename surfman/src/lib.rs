@@ -26,7 +26,7 @@ pub mod platform;
 pub use platform::default::connection::{Connection, NativeConnection};
 pub use platform::default::context::{Context, ContextDescriptor, NativeContext};
 pub use platform::default::device::{Adapter, Device, NativeDevice};
-pub use platform::default::surface::{NativeWidget, Surface, SurfaceTexture};
+pub use platform::default::surface::{NativeWidget, Surface, SurfaceDestroyToken, SurfaceTexture};
 
 // TODO(pcwalton): Fill this in with other OS's.
 #[cfg(target_os = "macos")]
@@ -36,29 +36,91 @@ pub use platform::system::device::{Adapter as SystemAdapter, Device as SystemDev
 #[cfg(target_os = "macos")]
 pub use platform::system::surface::Surface as SystemSurface;
 
+#[cfg(all(feature = "sm-capture", linux))]
+pub mod capture;
+pub mod compositor;
 pub mod connection;
+#[cfg(all(feature = "sm-cuda", target_os = "linux", not(target_env = "ohos")))]
+pub mod cuda;
 pub mod device;
+#[cfg(all(feature = "sm-external-objects", any(linux, windows)))]
+pub mod external_objects;
+#[cfg(all(feature = "sm-ffmpeg", any(linux, macos, windows)))]
+pub mod ffmpeg;
+#[cfg(feature = "sm-opencl")]
+pub mod opencl;
+pub mod pool;
+#[cfg(feature = "sm-skia")]
+pub mod skia;
+#[cfg(all(
+    feature = "sm-wgpu",
+    any(
+        target_os = "android",
+        all(target_os = "linux", not(target_env = "ohos"))
+    )
+))]
+pub mod wgpu;
 
 pub mod error;
 pub use crate::error::{Error, WindowingApiError};
 
+pub use crate::connection::{IpcDescriptor, NativeDisplayHandle, NativeWidgetHandle};
+pub use crate::device::NativeDeviceHandle;
+
 mod context;
-pub use crate::context::{ContextAttributeFlags, ContextAttributes, ContextID};
+pub use crate::context::{
+    ContextAttributeFlags, ContextAttributes, ContextDescriptorID, ContextID,
+};
+
+mod device_events;
+pub use crate::device_events::{DeviceEvent, DeviceEventHandler};
+
+mod display;
+pub use crate::display::{Colorspace, DisplayInfo, WidgetOutputInfo};
 
 mod info;
 pub use crate::info::{GLApi, GLVersion};
 
+mod init;
+pub use crate::init::{init, init_with_connection, AdapterPreference, InitOptions};
+
+mod lifecycle;
+pub use crate::lifecycle::{LifecycleEvent, LifecycleObserver};
+
+mod metrics;
+mod overrides;
+pub use crate::metrics::{set_metrics_sink, Metric, MetricsSink};
+
+mod sendable;
+pub use crate::sendable::SendableSurfaceTexture;
+
 mod surface;
-pub use crate::surface::{SurfaceAccess, SurfaceID, SurfaceInfo, SurfaceType, SystemSurfaceInfo};
+pub use crate::surface::{
+    CreateSurfacesResult, PresentationStatistics, SurfaceAccess, SurfaceAlphaMode, SurfaceFormat,
+    SurfaceID, SurfaceInfo, SurfaceLayoutRequirements, SurfaceOrigin, SurfaceTransform,
+    SurfaceType, SurfaceUsage, SurfaceVisibility, SystemSurfaceInfo,
+};
+
+mod timings;
+pub use crate::timings::CreationTimings;
+
+mod watchdog;
+pub use crate::watchdog::{
+    set_present_watchdog, PresentTimeout, PresentTimeoutHandler, PresentWatchdog, WatchedOperation,
+};
 
 pub mod macros;
 
-#[cfg(not(target_os = "android"))]
+#[cfg(not(any(target_os = "android", target_env = "ohos")))]
 pub(crate) use crate::gl::Gl;
-#[cfg(target_os = "android")]
+#[cfg(any(target_os = "android", target_env = "ohos"))]
 pub(crate) use crate::gl::Gles2 as Gl;
 
+mod deferred;
 mod gl_utils;
+mod label;
+// OSMesa allocates depth and stencil buffers itself, so Redox has no use for these.
+#[cfg_attr(redox, allow(dead_code))]
 mod renderbuffers;
 
 mod gl {
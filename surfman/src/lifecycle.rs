@@ -0,0 +1,71 @@
+// surfman/surfman/src/lifecycle.rs
+//
+//! Notifications about the creation and destruction of contexts and surfaces.
+
+use crate::context::ContextID;
+use crate::surface::SurfaceID;
+
+use euclid::default::Size2D;
+use std::sync::Arc;
+
+/// Something that happened to a context or surface of a device.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LifecycleEvent {
+    /// A context was created, either from scratch or by wrapping a native context.
+    ContextCreated {
+        /// The ID of the new context.
+        context_id: ContextID,
+    },
+    /// A context was destroyed.
+    ContextDestroyed {
+        /// The ID of the destroyed context.
+        context_id: ContextID,
+    },
+    /// A surface was created.
+    SurfaceCreated {
+        /// The ID of the new surface.
+        surface_id: SurfaceID,
+        /// The ID of the context the surface belongs to.
+        context_id: ContextID,
+        /// The size of the new surface, in device pixels.
+        size: Size2D<i32>,
+    },
+    /// A surface was destroyed.
+    SurfaceDestroyed {
+        /// The ID of the destroyed surface.
+        surface_id: SurfaceID,
+    },
+    /// A surface was resized.
+    SurfaceResized {
+        /// The ID of the resized surface.
+        surface_id: SurfaceID,
+        /// The new size of the surface, in device pixels.
+        size: Size2D<i32>,
+    },
+    /// The contents of a widget surface were presented.
+    SurfacePresented {
+        /// The ID of the presented surface.
+        surface_id: SurfaceID,
+    },
+}
+
+/// Receives the lifecycle events of the devices it's installed on with
+/// `Device::set_lifecycle_observer()`.
+///
+/// Events are delivered synchronously, on the thread of the device, right after the call that
+/// caused them succeeds.
+pub trait LifecycleObserver: Send + Sync {
+    /// Called for each event.
+    fn on_event(&self, event: &LifecycleEvent);
+}
+
+// Delivers an event to the given observer, if there is one. The event is only built if it's
+// going to be delivered.
+pub(crate) fn notify<F>(observer: Option<Arc<dyn LifecycleObserver>>, event: F)
+where
+    F: FnOnce() -> LifecycleEvent,
+{
+    if let Some(observer) = observer {
+        observer.on_event(&event());
+    }
+}
@@ -0,0 +1,77 @@
+// surfman/surfman/src/metrics.rs
+//
+//! Counters that embedders can feed into their monitoring systems.
+
+use euclid::default::Size2D;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// A quantity tracked by surfman and reported to the installed `MetricsSink`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Metric {
+    /// The number of surfaces that are currently allocated.
+    LiveSurfaces,
+    /// The number of bytes of color storage held by allocated surfaces.
+    ///
+    /// This is an estimate from the surfaces' sizes at four bytes per pixel. It doesn't account
+    /// for depth and stencil buffers, driver padding, or the extra buffers of widget surfaces.
+    SurfaceBytesAllocated,
+    /// The number of times widget surfaces have been presented.
+    ///
+    /// This only ever increases; sample it periodically to derive presents per second.
+    Presents,
+    /// The number of times a context has been made current.
+    ///
+    /// This only ever increases.
+    MakeCurrentCalls,
+}
+
+/// Receives changes to surfman's metrics.
+///
+/// Install one with `set_metrics_sink()`. Changes are reported as deltas, synchronously, on the
+/// thread of the device that caused them, so implementations should be cheap and thread-safe;
+/// typically they add the delta to an atomic counter or gauge.
+pub trait MetricsSink: Send + Sync {
+    /// Called when the given metric changes by `delta`.
+    fn record(&self, metric: Metric, delta: i64);
+}
+
+// Checked by callers before they gather metrics, so that tracking costs a single atomic load when
+// no sink is installed.
+static METRICS_SINK_INSTALLED: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    static ref METRICS_SINK: RwLock<Option<Arc<dyn MetricsSink>>> = RwLock::new(None);
+}
+
+/// Installs the sink that receives surfman's metrics for the whole process, replacing any
+/// previous one.
+///
+/// Pass `None` to remove the sink. By default no sink is installed and metrics aren't tracked.
+pub fn set_metrics_sink(sink: Option<Arc<dyn MetricsSink>>) {
+    let mut metrics_sink = METRICS_SINK.write().unwrap();
+    METRICS_SINK_INSTALLED.store(sink.is_some(), Ordering::Release);
+    *metrics_sink = sink;
+}
+
+#[inline]
+pub(crate) fn enabled() -> bool {
+    METRICS_SINK_INSTALLED.load(Ordering::Acquire)
+}
+
+pub(crate) fn record(metric: Metric, delta: i64) {
+    if let Some(ref sink) = *METRICS_SINK.read().unwrap() {
+        sink.record(metric, delta);
+    }
+}
+
+// Records the allocation (for a `count` of 1) or release (for -1) of a surface of the given size.
+pub(crate) fn record_surface_allocation(size: &Size2D<i32>, count: i64) {
+    record(Metric::LiveSurfaces, count);
+    record(Metric::SurfaceBytesAllocated, count * surface_bytes(size));
+}
+
+#[inline]
+pub(crate) fn surface_bytes(size: &Size2D<i32>) -> i64 {
+    size.width as i64 * size.height as i64 * 4
+}
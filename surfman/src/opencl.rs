@@ -0,0 +1,153 @@
+// surfman/surfman/src/opencl.rs
+//
+//! The handles that OpenCL needs to share objects with surfman contexts.
+//!
+//! An OpenCL context created with the properties from `GLSharing::gl_sharing_properties()` shares
+//! objects with the given surfman context through `cl_khr_gl_sharing`, so that surface textures
+//! can be wrapped in OpenCL images with `clCreateFromGLTexture()` using the values from
+//! `shared_texture()`. On EGL backends, surfaces can instead be wrapped directly through
+//! `cl_khr_egl_image` with `clCreateFromEGLImageKHR()`, using the values from
+//! `GLSharing::surface_egl_image()`.
+//!
+//! Surfman doesn't link to OpenCL; these are only the raw values to pass to it.
+
+use crate::device::Device;
+use crate::gl;
+use crate::gl::types::{GLenum, GLint, GLuint};
+use crate::Error;
+
+use std::os::raw::c_void;
+
+// Not in the OpenGL ES bindings, though no backend that uses them has rectangle textures.
+const GL_TEXTURE_RECTANGLE: GLenum = 0x84f5;
+
+/// The `cl_context_properties` value type, which is `intptr_t`.
+pub type CLContextProperties = isize;
+
+/// `CL_GL_CONTEXT_KHR`: the OpenGL context to share objects with.
+pub const CL_GL_CONTEXT_KHR: CLContextProperties = 0x2008;
+/// `CL_EGL_DISPLAY_KHR`: the EGL display that the OpenGL context belongs to.
+pub const CL_EGL_DISPLAY_KHR: CLContextProperties = 0x2009;
+/// `CL_GLX_DISPLAY_KHR`: the X display that the GLX context belongs to.
+pub const CL_GLX_DISPLAY_KHR: CLContextProperties = 0x200A;
+/// `CL_WGL_HDC_KHR`: the device context that the WGL context is current with.
+pub const CL_WGL_HDC_KHR: CLContextProperties = 0x200B;
+/// `CL_CGL_SHAREGROUP_KHR`: the share group of the CGL context.
+pub const CL_CGL_SHAREGROUP_KHR: CLContextProperties = 0x200C;
+
+/// The display that an OpenGL context belongs to, in the form that its windowing API expects.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GLSharingDisplay {
+    /// An `EGLDisplay`.
+    EGL(*const c_void),
+    /// An Xlib `Display *`.
+    GLX(*mut c_void),
+    /// A Win32 `HDC`.
+    WGL(*mut c_void),
+    /// A `CGLShareGroupObj`. OpenCL finds the context through its share group, so
+    /// `GLSharingProperties::gl_context` isn't passed for CGL.
+    CGLShareGroup(*mut c_void),
+}
+
+/// What OpenCL needs to share objects with an OpenGL context through `cl_khr_gl_sharing`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GLSharingProperties {
+    /// The native context: an `EGLContext`, `GLXContext`, `HGLRC`, or `CGLContextObj`.
+    pub gl_context: *mut c_void,
+    /// The display that the context belongs to.
+    pub display: GLSharingDisplay,
+}
+
+/// What OpenCL needs to wrap a surface texture in an image with `clCreateFromGLTexture()`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SharedTexture {
+    /// The OpenGL texture target: `GL_TEXTURE_2D`, `GL_TEXTURE_RECTANGLE`, or
+    /// `GL_TEXTURE_2D_ARRAY`.
+    pub target: GLenum,
+    /// The mipmap level to wrap, which is always 0.
+    pub mip_level: GLint,
+    /// The OpenGL texture object.
+    pub texture_object: GLuint,
+}
+
+/// What OpenCL needs to wrap a surface in an image with `clCreateFromEGLImageKHR()`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SharedEGLImage {
+    /// The `EGLDisplay` that the image belongs to.
+    pub egl_display: *const c_void,
+    /// The `EGLImageKHR` holding the contents of the surface.
+    pub egl_image: *mut c_void,
+}
+
+/// Devices whose contexts OpenCL can share objects with.
+pub trait GLSharing: Device {
+    /// Returns what OpenCL needs to share objects with the given context.
+    ///
+    /// On WGL, the device context is that of the surface currently bound to the context, so the
+    /// properties must be fetched again if another widget surface is bound. OSMesa contexts can't
+    /// be shared with OpenCL; this returns `Error::UnsupportedOnThisPlatform` for them.
+    fn gl_sharing_properties(&self, context: &Self::Context) -> Result<GLSharingProperties, Error>;
+
+    /// Returns the EGL image holding the contents of the given surface.
+    ///
+    /// The image is owned by the surface and is destroyed along with it. This returns
+    /// `Error::UnsupportedOnThisPlatform` on backends other than EGL, and `Error::WidgetAttached`
+    /// for widget surfaces, which have no EGL image.
+    fn surface_egl_image(&self, surface: &Self::Surface) -> Result<SharedEGLImage, Error>;
+}
+
+impl GLSharingProperties {
+    /// Returns the `cl_context_properties` entries that share objects with the context.
+    ///
+    /// The caller must add `CL_CONTEXT_PLATFORM` and the terminating zero before passing these to
+    /// `clCreateContext()`.
+    pub fn context_properties(&self) -> Vec<CLContextProperties> {
+        match self.display {
+            GLSharingDisplay::EGL(egl_display) => vec![
+                CL_GL_CONTEXT_KHR,
+                self.gl_context as CLContextProperties,
+                CL_EGL_DISPLAY_KHR,
+                egl_display as CLContextProperties,
+            ],
+            GLSharingDisplay::GLX(x11_display) => vec![
+                CL_GL_CONTEXT_KHR,
+                self.gl_context as CLContextProperties,
+                CL_GLX_DISPLAY_KHR,
+                x11_display as CLContextProperties,
+            ],
+            GLSharingDisplay::WGL(dc) => vec![
+                CL_GL_CONTEXT_KHR,
+                self.gl_context as CLContextProperties,
+                CL_WGL_HDC_KHR,
+                dc as CLContextProperties,
+            ],
+            GLSharingDisplay::CGLShareGroup(share_group) => {
+                vec![CL_CGL_SHAREGROUP_KHR, share_group as CLContextProperties]
+            }
+        }
+    }
+}
+
+/// Describes a surface texture for `clCreateFromGLTexture()`.
+///
+/// OpenCL can only share 2D, rectangle, and 2D array textures. Other surface textures, such as
+/// external textures, return `Error::IncompatibleSurfaceTexture`.
+pub fn shared_texture<D>(
+    device: &D,
+    surface_texture: &D::SurfaceTexture,
+) -> Result<SharedTexture, Error>
+where
+    D: Device,
+{
+    let target = device.surface_texture_binding_target(surface_texture);
+    match target {
+        gl::TEXTURE_2D | GL_TEXTURE_RECTANGLE | gl::TEXTURE_2D_ARRAY => {}
+        _ => return Err(Error::IncompatibleSurfaceTexture),
+    }
+
+    Ok(SharedTexture {
+        target,
+        mip_level: 0,
+        texture_object: device.surface_texture_object(surface_texture),
+    })
+}
@@ -0,0 +1,74 @@
+// surfman/surfman/src/overrides.rs
+//
+//! Environment variables that override what the application asks for.
+//!
+//! These let users and bug reporters change how `surfman` renders without rebuilding the
+//! application:
+//!
+//! * `SURFMAN_GL_VERSION=<major>.<minor>` replaces the GL version in context attributes, and
+//!   pins it to that version alone if they ask for a range.
+//!
+//! * `SURFMAN_FORCE_SOFTWARE=1` makes the hardware and low-power adapters software adapters.
+//!
+//! * `SURFMAN_DEBUG_CONTEXT=1` requests debug contexts, and `SURFMAN_DEBUG_CONTEXT=0` keeps them
+//!   from being requested.
+
+use crate::{ContextAttributeFlags, ContextAttributes, GLVersion};
+
+use std::env;
+
+static GL_VERSION_ENV_VAR: &'static str = "SURFMAN_GL_VERSION";
+#[cfg(any(angle, linux))]
+static FORCE_SOFTWARE_ENV_VAR: &'static str = "SURFMAN_FORCE_SOFTWARE";
+static DEBUG_CONTEXT_ENV_VAR: &'static str = "SURFMAN_DEBUG_CONTEXT";
+
+// Applies `SURFMAN_GL_VERSION` and `SURFMAN_DEBUG_CONTEXT` to the attributes passed to
+// `create_context_descriptor()`.
+pub(crate) fn context_attributes(attributes: &ContextAttributes) -> ContextAttributes {
+    let mut attributes = attributes.clone();
+
+    if let Ok(value) = env::var(GL_VERSION_ENV_VAR) {
+        match parse_gl_version(&value) {
+            Some(version) => {
+                attributes.version = version;
+                attributes.max_version = None;
+            }
+            None => warn!("Ignoring malformed {}: {:?}", GL_VERSION_ENV_VAR, value),
+        }
+    }
+    if let Some(debug) = env_flag(DEBUG_CONTEXT_ENV_VAR) {
+        attributes.flags.set(ContextAttributeFlags::DEBUG, debug);
+    }
+
+    attributes
+}
+
+// Whether `SURFMAN_FORCE_SOFTWARE` is set. Backends whose software adapter is their low-power
+// adapter don't consult this.
+#[cfg(any(angle, linux))]
+pub(crate) fn software_rendering_forced() -> bool {
+    env_flag(FORCE_SOFTWARE_ENV_VAR).unwrap_or(false)
+}
+
+fn parse_gl_version(value: &str) -> Option<GLVersion> {
+    let mut components = value.trim().splitn(2, '.');
+    let major = components.next()?.parse().ok()?;
+    let minor = match components.next() {
+        Some(minor) => minor.parse().ok()?,
+        None => 0,
+    };
+    Some(GLVersion::new(major, minor))
+}
+
+// Reads a boolean environment variable, returning `None` if it's unset or not a boolean.
+fn env_flag(name: &str) -> Option<bool> {
+    let value = env::var(name).ok()?;
+    match &*value.trim().to_ascii_lowercase() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" | "" => Some(false),
+        _ => {
+            warn!("Ignoring malformed {}: {:?}", name, value);
+            None
+        }
+    }
+}
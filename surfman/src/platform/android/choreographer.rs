@@ -0,0 +1,403 @@
+// surfman/surfman/src/platform/android/choreographer.rs
+//
+//! Vertical sync notifications from `AChoreographer`, for pacing rendering to the display.
+//!
+//! `AChoreographer` delivers its callbacks on the `ALooper` of the thread that it was obtained
+//! on, which the rendering thread usually doesn't run. So the first listener starts a thread that
+//! does nothing but run a looper and forward each vsync to the listeners registered at the time.
+//! On Android 13 (API 33) and later, each vsync also carries the frame timelines that
+//! SurfaceFlinger predicts, so that a renderer can pick the presentation time it targets.
+
+use super::device::Device;
+use super::ffi::{AChoreographer, AChoreographerFrameCallbackData};
+use super::ffi::{ALooper, ALooper_pollOnce, ALooper_prepare, ALooper_wake};
+use crate::Error;
+
+use std::mem;
+use std::os::raw::{c_char, c_int, c_long, c_void};
+use std::ptr;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+type FrameCallback = unsafe extern "C" fn(frame_time_nanos: c_long, data: *mut c_void);
+type FrameCallback64 = unsafe extern "C" fn(frame_time_nanos: i64, data: *mut c_void);
+type VsyncCallback =
+    unsafe extern "C" fn(callback_data: *const AChoreographerFrameCallbackData, data: *mut c_void);
+
+type Listener = Arc<Mutex<dyn FnMut(&VsyncTiming) + Send>>;
+
+// `AChoreographer` arrived in API 24, and most of its functions later still, so these are looked
+// up at runtime rather than linked.
+#[allow(non_snake_case)]
+struct ChoreographerFunctions {
+    GetInstance: unsafe extern "C" fn() -> *mut AChoreographer,
+    // Deprecated in API 29, since `long` truncates the frame time on 32-bit devices.
+    PostFrameCallback: unsafe extern "C" fn(
+        choreographer: *mut AChoreographer,
+        callback: FrameCallback,
+        data: *mut c_void,
+    ),
+    PostFrameCallback64: Option<
+        unsafe extern "C" fn(
+            choreographer: *mut AChoreographer,
+            callback: FrameCallback64,
+            data: *mut c_void,
+        ),
+    >,
+    FrameTimeline: Option<FrameTimelineFunctions>,
+}
+
+// The frame timeline API, available from API 33.
+#[allow(non_snake_case)]
+struct FrameTimelineFunctions {
+    PostVsyncCallback: unsafe extern "C" fn(
+        choreographer: *mut AChoreographer,
+        callback: VsyncCallback,
+        data: *mut c_void,
+    ) -> c_int,
+    GetFrameTimeNanos:
+        unsafe extern "C" fn(callback_data: *const AChoreographerFrameCallbackData) -> i64,
+    GetFrameTimelinesLength:
+        unsafe extern "C" fn(callback_data: *const AChoreographerFrameCallbackData) -> usize,
+    GetPreferredFrameTimelineIndex:
+        unsafe extern "C" fn(callback_data: *const AChoreographerFrameCallbackData) -> usize,
+    GetFrameTimelineVsyncId: unsafe extern "C" fn(
+        callback_data: *const AChoreographerFrameCallbackData,
+        index: usize,
+    ) -> i64,
+    GetFrameTimelineExpectedPresentationTimeNanos: unsafe extern "C" fn(
+        callback_data: *const AChoreographerFrameCallbackData,
+        index: usize,
+    ) -> i64,
+    GetFrameTimelineDeadlineNanos: unsafe extern "C" fn(
+        callback_data: *const AChoreographerFrameCallbackData,
+        index: usize,
+    ) -> i64,
+}
+
+lazy_static! {
+    static ref CHOREOGRAPHER_FUNCTIONS: Option<ChoreographerFunctions> = unsafe {
+        let library = libc::dlopen(
+            b"libandroid.so\0".as_ptr() as *const c_char,
+            libc::RTLD_LAZY,
+        );
+        if library.is_null() {
+            return None;
+        }
+        let get = |name: &'static [u8]| libc::dlsym(library, name.as_ptr() as *const c_char);
+
+        let symbols = [
+            get(b"AChoreographer_getInstance\0"),
+            get(b"AChoreographer_postFrameCallback\0"),
+        ];
+        if symbols.iter().any(|symbol| symbol.is_null()) {
+            return None;
+        }
+
+        let frame_callback_64 = get(b"AChoreographer_postFrameCallback64\0");
+
+        let frame_timeline_symbols = [
+            get(b"AChoreographer_postVsyncCallback\0"),
+            get(b"AChoreographerFrameCallbackData_getFrameTimeNanos\0"),
+            get(b"AChoreographerFrameCallbackData_getFrameTimelinesLength\0"),
+            get(b"AChoreographerFrameCallbackData_getPreferredFrameTimelineIndex\0"),
+            get(b"AChoreographerFrameCallbackData_getFrameTimelineVsyncId\0"),
+            get(b"AChoreographerFrameCallbackData_getFrameTimelineExpectedPresentationTimeNanos\0"),
+            get(b"AChoreographerFrameCallbackData_getFrameTimelineDeadlineNanos\0"),
+        ];
+        let frame_timeline = if frame_timeline_symbols
+            .iter()
+            .any(|symbol| symbol.is_null())
+        {
+            None
+        } else {
+            Some(FrameTimelineFunctions {
+                PostVsyncCallback: mem::transmute(frame_timeline_symbols[0]),
+                GetFrameTimeNanos: mem::transmute(frame_timeline_symbols[1]),
+                GetFrameTimelinesLength: mem::transmute(frame_timeline_symbols[2]),
+                GetPreferredFrameTimelineIndex: mem::transmute(frame_timeline_symbols[3]),
+                GetFrameTimelineVsyncId: mem::transmute(frame_timeline_symbols[4]),
+                GetFrameTimelineExpectedPresentationTimeNanos: mem::transmute(
+                    frame_timeline_symbols[5],
+                ),
+                GetFrameTimelineDeadlineNanos: mem::transmute(frame_timeline_symbols[6]),
+            })
+        };
+
+        Some(ChoreographerFunctions {
+            GetInstance: mem::transmute(symbols[0]),
+            PostFrameCallback: mem::transmute(symbols[1]),
+            PostFrameCallback64: if frame_callback_64.is_null() {
+                None
+            } else {
+                Some(mem::transmute(frame_callback_64))
+            },
+            FrameTimeline: frame_timeline,
+        })
+    };
+
+    // Started when the first listener is added, and kept running for the life of the process.
+    static ref CHOREOGRAPHER_THREAD: Option<ChoreographerThread> = ChoreographerThread::spawn();
+}
+
+/// The timing of a vertical sync of the display, as reported by `AChoreographer`.
+///
+/// All times are measured on the system monotonic clock (`CLOCK_MONOTONIC`).
+#[derive(Clone, Debug)]
+pub struct VsyncTiming {
+    /// The time at which the vsync occurred.
+    pub frame_time: Duration,
+    /// The presentation times that the system predicts frames started at this vsync can hit,
+    /// ordered from the earliest to the latest.
+    ///
+    /// This is empty before Android 13 (API 33).
+    pub frame_timelines: Vec<FrameTimeline>,
+    /// The index into `frame_timelines` of the timeline that the system recommends targeting.
+    ///
+    /// This is `None` if `frame_timelines` is empty.
+    pub preferred_frame_timeline: Option<usize>,
+}
+
+/// A presentation time that a frame can target, along with the deadline for submitting it.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameTimeline {
+    /// The identifier of the vsync that this timeline belongs to.
+    pub vsync_id: i64,
+    /// The time at which a frame targeting this timeline is expected to appear on screen.
+    pub expected_presentation_time: Duration,
+    /// The time by which rendering of the frame must be submitted to hit
+    /// `expected_presentation_time`.
+    pub deadline: Duration,
+}
+
+/// A registration of a function to be called at every vertical sync.
+///
+/// The function stops being called when this is dropped.
+pub struct VsyncListener {
+    id: u64,
+}
+
+struct ChoreographerThread {
+    looper: LooperPtr,
+    state: Arc<ListenerState>,
+}
+
+// The listeners, shared between the choreographer thread and the threads adding and removing
+// them.
+struct ListenerState {
+    listeners: Mutex<Listeners>,
+}
+
+struct Listeners {
+    next_id: u64,
+    entries: Vec<(u64, Listener)>,
+    // Whether a callback has been posted to the choreographer and hasn't run yet.
+    callback_pending: bool,
+}
+
+struct LooperPtr(*mut ALooper);
+
+unsafe impl Send for LooperPtr {}
+unsafe impl Sync for LooperPtr {}
+
+impl Device {
+    /// Calls the given function at every vertical sync of the display, until the returned
+    /// listener is dropped.
+    ///
+    /// The function runs on a thread that surfman starts for `AChoreographer`, not on the thread
+    /// that added it, so it should do no more than hand the timing to the rendering thread. From
+    /// Android 13 (API 33) on, the timing includes the frame timelines, whose expected
+    /// presentation times can be targeted directly instead of guessed from the frame time.
+    ///
+    /// If this release of Android doesn't have `AChoreographer` (API 24), this returns
+    /// `Error::UnsupportedOnThisPlatform`.
+    pub fn add_vsync_listener<F>(&self, listener: F) -> Result<VsyncListener, Error>
+    where
+        F: FnMut(&VsyncTiming) + Send + 'static,
+    {
+        let thread = match *CHOREOGRAPHER_THREAD {
+            Some(ref thread) => thread,
+            None => return Err(Error::UnsupportedOnThisPlatform),
+        };
+
+        let mut listeners = thread.state.listeners.lock().unwrap();
+        let id = listeners.next_id;
+        listeners.next_id += 1;
+        listeners.entries.push((id, Arc::new(Mutex::new(listener))));
+
+        // Wake the choreographer thread so that it posts a callback for the next vsync.
+        if !listeners.callback_pending {
+            unsafe { ALooper_wake(thread.looper.0) }
+        }
+
+        Ok(VsyncListener { id })
+    }
+}
+
+impl Drop for VsyncListener {
+    fn drop(&mut self) {
+        // A listener can only have been created if the thread is running.
+        if let Some(ref thread) = *CHOREOGRAPHER_THREAD {
+            let mut listeners = thread.state.listeners.lock().unwrap();
+            listeners.entries.retain(|&(id, _)| id != self.id);
+        }
+    }
+}
+
+impl ChoreographerThread {
+    fn spawn() -> Option<ChoreographerThread> {
+        let functions = CHOREOGRAPHER_FUNCTIONS.as_ref()?;
+        let state = Arc::new(ListenerState {
+            listeners: Mutex::new(Listeners {
+                next_id: 0,
+                entries: vec![],
+                callback_pending: false,
+            }),
+        });
+
+        let (looper_sender, looper_receiver) = mpsc::channel();
+        let thread_state = state.clone();
+        thread::Builder::new()
+            .name("surfman choreographer".to_owned())
+            .spawn(move || unsafe { run(functions, thread_state, looper_sender) })
+            .ok()?;
+
+        let looper = looper_receiver.recv().ok()??;
+        Some(ChoreographerThread { looper, state })
+    }
+}
+
+// The body of the choreographer thread.
+//
+// The thread holds a reference to the listener state for as long as it runs, which is what keeps
+// the pointer passed to the callbacks valid.
+unsafe fn run(
+    functions: &'static ChoreographerFunctions,
+    state: Arc<ListenerState>,
+    looper_sender: mpsc::Sender<Option<LooperPtr>>,
+) {
+    let looper = ALooper_prepare(0);
+    let choreographer = (functions.GetInstance)();
+    if looper.is_null() || choreographer.is_null() {
+        drop(looper_sender.send(None));
+        return;
+    }
+    drop(looper_sender.send(Some(LooperPtr(looper))));
+
+    let data = Arc::as_ptr(&state) as *mut c_void;
+    loop {
+        {
+            let mut listeners = state.listeners.lock().unwrap();
+            if !listeners.entries.is_empty() && !listeners.callback_pending {
+                post_callback(functions, choreographer, data);
+                listeners.callback_pending = true;
+            }
+        }
+
+        // Returns once the callback has run or another thread has woken the looper.
+        ALooper_pollOnce(-1, ptr::null_mut(), ptr::null_mut(), ptr::null_mut());
+    }
+}
+
+// Posts a callback for the next vsync, using the newest API available.
+unsafe fn post_callback(
+    functions: &ChoreographerFunctions,
+    choreographer: *mut AChoreographer,
+    data: *mut c_void,
+) {
+    if let Some(ref frame_timeline) = functions.FrameTimeline {
+        (frame_timeline.PostVsyncCallback)(choreographer, on_vsync, data);
+    } else if let Some(post_frame_callback_64) = functions.PostFrameCallback64 {
+        post_frame_callback_64(choreographer, on_frame_64, data);
+    } else {
+        (functions.PostFrameCallback)(choreographer, on_frame, data);
+    }
+}
+
+unsafe extern "C" fn on_vsync(
+    callback_data: *const AChoreographerFrameCallbackData,
+    data: *mut c_void,
+) {
+    let frame_timeline = match *CHOREOGRAPHER_FUNCTIONS {
+        Some(ChoreographerFunctions {
+            FrameTimeline: Some(ref frame_timeline),
+            ..
+        }) => frame_timeline,
+        _ => unreachable!(),
+    };
+
+    let frame_timeline_count = (frame_timeline.GetFrameTimelinesLength)(callback_data);
+    let frame_timelines = (0..frame_timeline_count)
+        .map(|index| FrameTimeline {
+            vsync_id: (frame_timeline.GetFrameTimelineVsyncId)(callback_data, index),
+            expected_presentation_time: nanos_to_duration((frame_timeline
+                .GetFrameTimelineExpectedPresentationTimeNanos)(
+                callback_data, index
+            )),
+            deadline: nanos_to_duration((frame_timeline.GetFrameTimelineDeadlineNanos)(
+                callback_data,
+                index,
+            )),
+        })
+        .collect();
+    let preferred_frame_timeline = if frame_timeline_count == 0 {
+        None
+    } else {
+        Some((frame_timeline.GetPreferredFrameTimelineIndex)(
+            callback_data,
+        ))
+    };
+
+    dispatch(
+        data,
+        &VsyncTiming {
+            frame_time: nanos_to_duration((frame_timeline.GetFrameTimeNanos)(callback_data)),
+            frame_timelines,
+            preferred_frame_timeline,
+        },
+    )
+}
+
+unsafe extern "C" fn on_frame_64(frame_time_nanos: i64, data: *mut c_void) {
+    dispatch(data, &VsyncTiming::from_frame_time(frame_time_nanos))
+}
+
+unsafe extern "C" fn on_frame(frame_time_nanos: c_long, data: *mut c_void) {
+    dispatch(data, &VsyncTiming::from_frame_time(frame_time_nanos as i64))
+}
+
+// Calls every listener with the given timing.
+//
+// The listeners are called without the list locked, so that they can add and remove listeners.
+unsafe fn dispatch(data: *mut c_void, timing: &VsyncTiming) {
+    let state = &*(data as *const ListenerState);
+    let listeners: Vec<Listener> = {
+        let mut listeners = state.listeners.lock().unwrap();
+        listeners.callback_pending = false;
+        listeners
+            .entries
+            .iter()
+            .map(|&(_, ref listener)| listener.clone())
+            .collect()
+    };
+
+    for listener in listeners {
+        (&mut *listener.lock().unwrap())(timing);
+    }
+}
+
+impl VsyncTiming {
+    fn from_frame_time(frame_time_nanos: i64) -> VsyncTiming {
+        VsyncTiming {
+            frame_time: nanos_to_duration(frame_time_nanos),
+            frame_timelines: vec![],
+            preferred_frame_timeline: None,
+        }
+    }
+}
+
+fn nanos_to_duration(nanos: i64) -> Duration {
+    Duration::from_nanos(nanos.max(0) as u64)
+}
@@ -7,19 +7,26 @@
 use super::device::{Adapter, Device, NativeDevice};
 use super::ffi::ANativeWindow;
 use super::surface::NativeWidget;
-use crate::Error;
+use crate::context::{ContextDescriptorCache, ContextIDAllocator};
+use crate::device_events::DeviceEventDispatcher;
+use crate::timings::CreationTimingsRecorder;
 use crate::GLApi;
+use crate::{DisplayInfo, Error, IpcDescriptor, NativeDisplayHandle, NativeWidgetHandle};
 
 use euclid::default::Size2D;
 
 use std::os::raw::c_void;
+use std::sync::atomic::AtomicUsize;
+use std::sync::Arc;
 
 #[cfg(feature = "sm-winit")]
 use winit::window::Window;
 
 /// A connection to the display server.
 #[derive(Clone)]
-pub struct Connection;
+pub struct Connection {
+    pub(crate) context_ids: ContextIDAllocator,
+}
 
 /// An empty placeholder for native connections.
 #[derive(Clone)]
@@ -29,10 +36,27 @@ impl Connection {
     /// Connects to the default display.
     #[inline]
     pub fn new() -> Result<Connection, Error> {
-        Ok(Connection)
+        Ok(Connection {
+            context_ids: ContextIDAllocator::new()?,
+        })
+    }
+
+    /// Returns `UnsupportedOnThisPlatform`, because EGL displays can be opened from
+    /// within the sandbox.
+    ///
+    /// # Safety
+    ///
+    /// The descriptor is never used, so there are no requirements on it.
+    #[inline]
+    pub unsafe fn from_ipc_descriptor(_: IpcDescriptor) -> Result<Connection, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
     }
 
     /// An alias for `Connection::new()`, present for consistency with other backends.
+    ///
+    /// # Safety
+    ///
+    /// The native connection carries no handles, so there are no requirements on it.
     #[inline]
     pub unsafe fn from_native_connection(_: NativeConnection) -> Result<Connection, Error> {
         Connection::new()
@@ -44,12 +68,30 @@ impl Connection {
         NativeConnection
     }
 
+    /// Returns the raw display connection underneath this connection.
+    ///
+    /// Android has no display connection, so this returns `NativeDisplayHandle::None`. The
+    /// device's EGL display is returned by `Device::native_device_handle()`.
+    #[inline]
+    pub fn native_display(&self) -> NativeDisplayHandle {
+        NativeDisplayHandle::None
+    }
+
     /// Returns the OpenGL API flavor that this connection supports (OpenGL or OpenGL ES).
     #[inline]
     pub fn gl_api(&self) -> GLApi {
         GLApi::GLES
     }
 
+    /// Returns the displays connected to the system.
+    ///
+    /// Android only describes its displays through the Java `DisplayManager` API, so this always
+    /// returns `UnsupportedOnThisPlatform`.
+    #[inline]
+    pub fn displays(&self) -> Result<Vec<DisplayInfo>, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
     /// Returns the "best" adapter on this system.
     ///
     /// This is an alias for `Connection::create_hardware_adapter()`.
@@ -76,12 +118,20 @@ impl Connection {
         Ok(Adapter)
     }
 
+    /// Returns the adapter for the GPU that drives the given display.
+    ///
+    /// Android devices have a single GPU, so this is the same as the default adapter.
+    #[inline]
+    pub fn create_adapter_for_display(&self, _: &DisplayInfo) -> Result<Adapter, Error> {
+        Ok(Adapter)
+    }
+
     /// Opens the hardware device corresponding to the given adapter.
     ///
-    /// Device handles are local to a single thread.
+    /// Device handles can be cloned and sent to other threads.
     #[inline]
-    pub fn create_device(&self, _: &Adapter) -> Result<Device, Error> {
-        Device::new()
+    pub(crate) fn create_device_impl(&self, _: &Adapter) -> Result<Device, Error> {
+        Device::new(self)
     }
 
     /// Wraps an Android `EGLDisplay` in a device and returns it.
@@ -90,13 +140,19 @@ impl Connection {
     /// Therefore, it is the caller's responsibility to keep it alive as long as this `Device`
     /// remains alive.
     #[inline]
-    pub unsafe fn create_device_from_native_device(
+    pub(crate) unsafe fn create_device_from_native_device_impl(
         &self,
         native_device: NativeDevice,
     ) -> Result<Device, Error> {
         Ok(Device {
             egl_display: native_device.0,
-            display_is_owned: false,
+            _display_owner: None,
+            lifecycle_observer: None,
+            device_event_dispatcher: DeviceEventDispatcher::default(),
+            creation_timings: CreationTimingsRecorder::default(),
+            memory_usage: Arc::new(AtomicUsize::new(0)),
+            context_ids: self.context_ids.clone(),
+            context_descriptors: ContextDescriptorCache::new(),
         })
     }
 
@@ -104,7 +160,9 @@ impl Connection {
     #[cfg(feature = "sm-winit")]
     #[inline]
     pub fn from_winit_window(_: &Window) -> Result<Connection, Error> {
-        Ok(Connection)
+        Ok(Connection {
+            context_ids: ContextIDAllocator::new()?,
+        })
     }
 
     /// Creates a native widget type from the given `winit` window.
@@ -121,12 +179,19 @@ impl Connection {
         match window.raw_window_handle() {
             Android(handle) => Ok(NativeWidget {
                 native_window: handle.a_native_window as *mut _,
+                surface_control: false,
+                pre_rotation: false,
             }),
             _ => Err(Error::IncompatibleNativeWidget),
         }
     }
 
     /// Create a native widget from a raw pointer
+    ///
+    /// # Safety
+    ///
+    /// The pointer must be to a live `ANativeWindow`, which must outlive the native widget and any
+    /// surfaces created from it.
     pub unsafe fn create_native_widget_from_ptr(
         &self,
         raw: *mut c_void,
@@ -134,6 +199,30 @@ impl Connection {
     ) -> NativeWidget {
         NativeWidget {
             native_window: raw as *mut ANativeWindow,
+            surface_control: false,
+            pre_rotation: false,
+        }
+    }
+
+    /// Creates a native widget from a handle to a native window or view.
+    ///
+    /// Only `ANativeWindow` handles are accepted.
+    ///
+    /// # Safety
+    ///
+    /// The handle must refer to a live window or view, which must outlive the native widget and any
+    /// surfaces created from it.
+    pub unsafe fn create_native_widget_from_handle(
+        &self,
+        handle: NativeWidgetHandle,
+    ) -> Result<NativeWidget, Error> {
+        match handle {
+            NativeWidgetHandle::ANativeWindow(native_window) => Ok(NativeWidget {
+                native_window: native_window as *mut ANativeWindow,
+                surface_control: false,
+                pre_rotation: false,
+            }),
+            _ => Err(Error::IncompatibleNativeWidget),
         }
     }
 
@@ -149,6 +238,8 @@ impl Connection {
         match raw_handle {
             Android(handle) => Ok(NativeWidget {
                 native_window: handle.a_native_window as *mut _,
+                surface_control: false,
+                pre_rotation: false,
             }),
             _ => Err(Error::IncompatibleNativeWidget),
         }
@@ -2,18 +2,26 @@
 //
 //! OpenGL rendering contexts.
 
-use super::device::Device;
+use super::connection::{Connection, NativeConnection};
+use super::device::{Device, NativeDevice};
 use super::surface::{Surface, SurfaceObjects};
-use crate::context::{ContextID, CREATE_CONTEXT_MUTEX};
+use crate::context::ContextID;
 use crate::egl;
 use crate::egl::types::{EGLConfig, EGLContext, EGLSurface, EGLint};
+use crate::gl::types::GLuint;
+use crate::gl_utils;
+#[cfg(feature = "sm-opencl")]
+use crate::opencl::{GLSharingDisplay, GLSharingProperties};
+use crate::overrides;
 use crate::platform::generic::egl::context::{self, CurrentContextGuard};
-use crate::platform::generic::egl::device::EGL_FUNCTIONS;
+use crate::platform::generic::egl::device::{self as egl_device, EGL_FUNCTIONS};
 use crate::platform::generic::egl::error::ToWindowingApiError;
+use crate::platform::generic::egl::ffi::EGL_MUTABLE_RENDER_BUFFER_BIT_KHR;
 use crate::platform::generic::egl::surface::ExternalEGLSurfaces;
-use crate::surface::Framebuffer;
-use crate::{ContextAttributes, Error, Gl, SurfaceInfo};
+use crate::surface::{ExternalFramebuffer, Framebuffer};
+use crate::{ContextAttributes, ContextDescriptorID, Error, GLVersion, Gl, SurfaceInfo};
 
+use euclid::default::Size2D;
 use std::mem;
 use std::os::raw::c_void;
 use std::thread;
@@ -61,24 +69,82 @@ impl Drop for Context {
 impl Device {
     /// Creates a context descriptor with the given attributes.
     ///
-    /// Context descriptors are local to this device.
+    /// Context descriptors are local to this device. Any OpenGL ES version up to 3.2 can be
+    /// requested; anything newer returns `Error::UnsupportedGLVersion`.
+    ///
+    /// Descriptors are cached by this device, so asking again for the same attributes returns a
+    /// copy of the earlier descriptor without choosing an EGL config again.
     #[inline]
-    pub fn create_context_descriptor(
+    pub(crate) fn create_context_descriptor_impl(
         &self,
         attributes: &ContextAttributes,
     ) -> Result<ContextDescriptor, Error> {
+        let attributes = &overrides::context_attributes(attributes);
+        self.context_descriptors
+            .get_or_insert_with(attributes, || self.choose_context_descriptor(attributes))
+    }
+
+    /// Returns a small ID for the given context descriptor, which is the same for every equal
+    /// descriptor.
+    ///
+    /// The IDs are shared with clones of this device.
+    #[inline]
+    pub fn intern_context_descriptor(
+        &self,
+        context_descriptor: &ContextDescriptor,
+    ) -> ContextDescriptorID {
+        self.context_descriptors.intern(context_descriptor)
+    }
+
+    fn choose_context_descriptor(
+        &self,
+        attributes: &ContextAttributes,
+    ) -> Result<ContextDescriptor, Error> {
+        let version = attributes.version;
+        if version.major > 3 || version.major == 3 && version.minor > 2 {
+            return Err(Error::UnsupportedGLVersion);
+        }
+
+        // Only configs that are renderable with OpenGL ES 3.x can have contexts of those versions.
+        let renderable_type = if version.major >= 3 {
+            egl::OPENGL_ES3_BIT
+        } else {
+            egl::OPENGL_ES2_BIT
+        };
+
+        let config_attributes = |surface_type: EGLint| {
+            [
+                egl::COLOR_BUFFER_TYPE as EGLint,
+                egl::RGB_BUFFER as EGLint,
+                egl::SURFACE_TYPE as EGLint,
+                surface_type,
+                egl::RENDERABLE_TYPE as EGLint,
+                renderable_type as EGLint,
+            ]
+        };
+
         unsafe {
+            // Widget surfaces can only be switched to single-buffered rendering if their config
+            // allows it, so prefer such configs where that's supported.
+            if egl_device::display_has_extension(self.egl_display, b"EGL_KHR_mutable_render_buffer")
+            {
+                let surface_type = egl::PBUFFER_BIT as EGLint
+                    | egl::WINDOW_BIT as EGLint
+                    | EGL_MUTABLE_RENDER_BUFFER_BIT_KHR;
+                match ContextDescriptor::new(
+                    self.egl_display,
+                    attributes,
+                    &config_attributes(surface_type),
+                ) {
+                    Err(Error::NoPixelFormatFound) => {}
+                    result => return result,
+                }
+            }
+
             ContextDescriptor::new(
                 self.egl_display,
                 attributes,
-                &[
-                    egl::COLOR_BUFFER_TYPE as EGLint,
-                    egl::RGB_BUFFER as EGLint,
-                    egl::SURFACE_TYPE as EGLint,
-                    egl::PBUFFER_BIT as EGLint,
-                    egl::RENDERABLE_TYPE as EGLint,
-                    egl::OPENGL_ES2_BIT as EGLint,
-                ],
+                &config_attributes(egl::PBUFFER_BIT as EGLint),
             )
         }
     }
@@ -87,13 +153,14 @@ impl Device {
     ///
     /// The context initially has no surface attached. Until a surface is bound to it, rendering
     /// commands will fail or have no effect.
-    pub fn create_context(
+    ///
+    /// If the driver can only provide an older OpenGL ES version than the descriptor asks for,
+    /// this returns `Error::UnsupportedGLVersion`.
+    pub(crate) fn create_context_impl(
         &mut self,
         descriptor: &ContextDescriptor,
         share_with: Option<&Context>,
     ) -> Result<Context, Error> {
-        let mut next_context_id = CREATE_CONTEXT_MUTEX.lock().unwrap();
-
         let egl_display = self.egl_display;
 
         unsafe {
@@ -110,14 +177,28 @@ impl Device {
             let pbuffer = context::create_dummy_pbuffer(egl_display, egl_context);
 
             // Wrap up the EGL context.
-            let context = Context {
+            let mut context = Context {
                 egl_context,
-                id: *next_context_id,
+                id: self.context_ids.next(),
                 pbuffer,
                 framebuffer: Framebuffer::None,
                 context_is_owned: true,
             };
-            next_context_id.0 += 1;
+
+            // Drivers without `EGL_KHR_create_context` may ignore the minor version and hand back
+            // an older context than was asked for. Fail rather than silently downgrade.
+            let gl_version = {
+                let _guard = self.temporarily_make_context_current(&context)?;
+                GL_FUNCTIONS.with(|gl| GLVersion::current(gl))
+            };
+            let requested_version = descriptor.gl_version;
+            if (gl_version.major, gl_version.minor)
+                < (requested_version.major, requested_version.minor)
+            {
+                self.destroy_context_impl(&mut context)?;
+                return Err(Error::UnsupportedGLVersion);
+            }
+
             Ok(context)
         }
     }
@@ -127,19 +208,17 @@ impl Device {
     /// The underlying `EGLContext` is not retained, as there is no way to do this in the EGL API.
     /// Therefore, it is the caller's responsibility to keep it alive as long as this `Context`
     /// remains alive.
-    pub unsafe fn create_context_from_native_context(
+    pub(crate) unsafe fn create_context_from_native_context_impl(
         &self,
         native_context: NativeContext,
     ) -> Result<Context, Error> {
-        let mut next_context_id = CREATE_CONTEXT_MUTEX.lock().unwrap();
-
         // Create a dummy pbuffer.
         let pbuffer = context::create_dummy_pbuffer(self.egl_display, native_context.egl_context);
 
         // Create the context.
         let context = Context {
             egl_context: native_context.egl_context,
-            id: *next_context_id,
+            id: self.context_ids.next(),
             pbuffer,
             framebuffer: Framebuffer::External(ExternalEGLSurfaces {
                 draw: native_context.egl_draw_surface,
@@ -147,15 +226,31 @@ impl Device {
             }),
             context_is_owned: false,
         };
-        next_context_id.0 += 1;
 
         Ok(context)
     }
 
+    /// Wraps the EGL context that is current on this thread, along with its display, in a new
+    /// device and context.
+    ///
+    /// If no context is current, this returns a `NoCurrentContext` error.
+    ///
+    /// # Safety
+    ///
+    /// Neither is retained, so the caller must keep both alive as long as the device and context
+    /// are.
+    pub unsafe fn from_current_context() -> Result<(Device, Context), Error> {
+        let native_context = NativeContext::current()?;
+        let connection = Connection::from_native_connection(NativeConnection::current()?)?;
+        let device = connection.create_device_from_native_device(NativeDevice::current())?;
+        let context = device.create_context_from_native_context(native_context)?;
+        Ok((device, context))
+    }
+
     /// Destroys a context.
     ///
     /// The context must have been created on this device.
-    pub fn destroy_context(&self, context: &mut Context) -> Result<(), Error> {
+    pub(crate) fn destroy_context_impl(&self, context: &mut Context) -> Result<(), Error> {
         if context.egl_context == egl::NO_CONTEXT {
             return Ok(());
         }
@@ -201,14 +296,14 @@ impl Device {
     /// Makes the context the current OpenGL context for this thread.
     ///
     /// After calling this function, it is valid to use OpenGL rendering commands.
-    pub fn make_context_current(&self, context: &Context) -> Result<(), Error> {
+    pub(crate) fn make_context_current_impl(&self, context: &Context) -> Result<(), Error> {
         unsafe {
             let egl_display = self.egl_display;
             let egl_context = context.egl_context;
 
             let (egl_draw_surface, egl_read_surface) = match context.framebuffer {
                 Framebuffer::Surface(Surface {
-                    objects: SurfaceObjects::Window { egl_surface },
+                    objects: SurfaceObjects::Window { egl_surface, .. },
                     ..
                 }) => (egl_surface, egl_surface),
                 Framebuffer::External(ExternalEGLSurfaces { draw, read }) => (draw, read),
@@ -216,7 +311,20 @@ impl Device {
                     objects: SurfaceObjects::HardwareBuffer { .. },
                     ..
                 })
-                | Framebuffer::None => (context.pbuffer, context.pbuffer),
+                | Framebuffer::Surface(Surface {
+                    objects: SurfaceObjects::SurfaceControl { .. },
+                    ..
+                })
+                | Framebuffer::Surface(Surface {
+                    objects: SurfaceObjects::Suspended,
+                    ..
+                })
+                | Framebuffer::Surface(Surface {
+                    objects: SurfaceObjects::ImportedHardwareBuffer { .. },
+                    ..
+                })
+                | Framebuffer::None
+                | Framebuffer::ExternalFramebuffer(_) => (context.pbuffer, context.pbuffer),
             };
 
             EGL_FUNCTIONS.with(|egl| {
@@ -231,6 +339,47 @@ impl Device {
         }
     }
 
+    /// Makes the context the current OpenGL context for this thread, rendering to the given
+    /// surface instead of any surface bound to it.
+    ///
+    /// The surface must have been created with this context, or an `IncompatibleSurface` error is
+    /// returned.
+    pub(crate) fn make_context_current_with_surface_impl(
+        &self,
+        context: &Context,
+        surface: &Surface,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        unsafe {
+            // Surfaces other than window surfaces are rendered to through their framebuffer
+            // objects, so the context's pbuffer stands in for them.
+            let egl_surface = match surface.objects {
+                SurfaceObjects::Window { egl_surface, .. } => egl_surface,
+                SurfaceObjects::HardwareBuffer { .. }
+                | SurfaceObjects::SurfaceControl { .. }
+                | SurfaceObjects::Suspended
+                | SurfaceObjects::ImportedHardwareBuffer { .. } => context.pbuffer,
+            };
+
+            EGL_FUNCTIONS.with(|egl| {
+                let result = egl.MakeCurrent(
+                    self.egl_display,
+                    egl_surface,
+                    egl_surface,
+                    context.egl_context,
+                );
+                if result == egl::FALSE {
+                    let err = egl.GetError().to_windowing_api_error();
+                    return Err(Error::MakeCurrentFailed(err));
+                }
+                Ok(())
+            })
+        }
+    }
+
     /// Removes the current OpenGL context from this thread.
     ///
     /// After calling this function, OpenGL rendering commands will fail until a new context is
@@ -249,7 +398,7 @@ impl Device {
     /// `unbind_surface_from_context`.
     ///
     /// If an error is returned, the surface is returned alongside it.
-    pub fn bind_surface_to_context(
+    pub(crate) fn bind_surface_to_context_impl(
         &self,
         context: &mut Context,
         new_surface: Surface,
@@ -259,7 +408,9 @@ impl Device {
         }
 
         match context.framebuffer {
-            Framebuffer::External { .. } => return Err((Error::ExternalRenderTarget, new_surface)),
+            Framebuffer::External { .. } | Framebuffer::ExternalFramebuffer(_) => {
+                return Err((Error::ExternalRenderTarget, new_surface))
+            }
             Framebuffer::Surface(_) => return Err((Error::SurfaceAlreadyBound, new_surface)),
             Framebuffer::None => {}
         }
@@ -272,12 +423,16 @@ impl Device {
     ///
     /// Any pending OpenGL commands targeting this surface will be automatically flushed, so the
     /// surface is safe to read from immediately when this function returns.
-    pub fn unbind_surface_from_context(
+    pub(crate) fn unbind_surface_from_context_impl(
         &self,
         context: &mut Context,
     ) -> Result<Option<Surface>, Error> {
         match context.framebuffer {
             Framebuffer::External { .. } => return Err(Error::ExternalRenderTarget),
+            Framebuffer::ExternalFramebuffer(_) => {
+                context.framebuffer = Framebuffer::None;
+                return Ok(None);
+            }
             Framebuffer::None => return Ok(None),
             Framebuffer::Surface(_) => {}
         }
@@ -292,10 +447,60 @@ impl Device {
 
         match mem::replace(&mut context.framebuffer, Framebuffer::None) {
             Framebuffer::Surface(surface) => return Ok(Some(surface)),
-            Framebuffer::External { .. } | Framebuffer::None => unreachable!(),
+            Framebuffer::External { .. }
+            | Framebuffer::ExternalFramebuffer(_)
+            | Framebuffer::None => unreachable!(),
         }
     }
 
+    /// Makes a context render to a framebuffer object that the caller created and owns, in place
+    /// of a surface.
+    ///
+    /// The framebuffer object must stay alive until it's detached again with
+    /// `unbind_surface_from_context()`.
+    pub fn bind_external_framebuffer(
+        &self,
+        context: &mut Context,
+        framebuffer_object: GLuint,
+        size: Size2D<i32>,
+    ) -> Result<(), Error> {
+        match context.framebuffer {
+            Framebuffer::None => {}
+            Framebuffer::External(_) | Framebuffer::ExternalFramebuffer(_) => {
+                return Err(Error::ExternalRenderTarget)
+            }
+            Framebuffer::Surface(_) => return Err(Error::SurfaceAlreadyBound),
+        }
+
+        // The context stays current on its pbuffer, as it does when nothing is bound.
+        context.framebuffer =
+            Framebuffer::ExternalFramebuffer(ExternalFramebuffer::new(framebuffer_object, size));
+        Ok(())
+    }
+
+    /// Makes the rendering submitted so far to a surface visible to everything else that reads
+    /// it.
+    ///
+    /// This flushes the context and waits on a fence for the rendering to finish, so that readers
+    /// that aren't implicitly synchronized with it, such as other APIs importing the surface's
+    /// memory, see all of it.
+    ///
+    /// The surface must have been created with the given context, or an `IncompatibleSurface`
+    /// error is returned.
+    pub(crate) fn flush_context_impl(
+        &self,
+        context: &Context,
+        surface: &Surface,
+    ) -> Result<(), Error> {
+        if surface.context_id != context.id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        let _guard = self.temporarily_make_context_current(context)?;
+        GL_FUNCTIONS.with(gl_utils::finish_with_fence);
+        Ok(())
+    }
+
     /// Returns the attributes that the context descriptor was created with.
     pub fn context_descriptor_attributes(
         &self,
@@ -316,11 +521,17 @@ impl Device {
         context::get_proc_address(symbol_name)
     }
 
-    pub(crate) fn context_to_egl_config(
-        &self,
-        context: &Context,
-    ) -> EGLConfig {
-        unsafe { context::egl_config_from_id(self.egl_display, context::get_context_attr(self.egl_display, context.egl_context, egl::CONFIG_ID as EGLint)) }
+    pub(crate) fn context_to_egl_config(&self, context: &Context) -> EGLConfig {
+        unsafe {
+            context::egl_config_from_id(
+                self.egl_display,
+                context::get_context_attr(
+                    self.egl_display,
+                    context.egl_context,
+                    egl::CONFIG_ID as EGLint,
+                ),
+            )
+        }
     }
 
     pub(crate) fn temporarily_make_context_current(
@@ -328,10 +539,43 @@ impl Device {
         context: &Context,
     ) -> Result<CurrentContextGuard, Error> {
         let guard = CurrentContextGuard::new();
-        self.make_context_current(context)?;
+        self.make_context_current_impl(context)?;
         Ok(guard)
     }
 
+    // Calls the given function with the OpenGL functions, which are loaded once per thread and
+    // shared by all contexts.
+    #[inline]
+    pub(crate) fn with_gl_functions<F, R>(&self, _: &Context, callback: F) -> R
+    where
+        F: FnOnce(&Gl) -> R,
+    {
+        GL_FUNCTIONS.with(|gl| callback(gl))
+    }
+
+    /// Returns true if the context supports the Android Extension Pack, which adds tessellation
+    /// and geometry shaders, ASTC textures, and more to OpenGL ES 3.1.
+    ///
+    /// The extension pack is part of OpenGL ES 3.2, so this is always true for contexts of that
+    /// version; for OpenGL ES 3.1 contexts, it depends on `GL_ANDROID_extension_pack_es31a`.
+    pub fn context_supports_extension_pack(&self, context: &Context) -> Result<bool, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        GL_FUNCTIONS.with(|gl| {
+            let gl_version = GLVersion::current(gl);
+            if (gl_version.major, gl_version.minor) >= (3, 2) {
+                return Ok(true);
+            }
+            if (gl_version.major, gl_version.minor) < (3, 1) {
+                return Ok(false);
+            }
+
+            Ok(gl_utils::context_has_extension(
+                gl,
+                b"GL_ANDROID_extension_pack_es31a",
+            ))
+        })
+    }
+
     /// Returns a unique ID representing a context.
     ///
     /// This ID is unique to all currently-allocated contexts. If you destroy a context and create
@@ -348,7 +592,13 @@ impl Device {
         match context.framebuffer {
             Framebuffer::None => Ok(None),
             Framebuffer::External { .. } => Err(Error::ExternalRenderTarget),
-            Framebuffer::Surface(ref surface) => Ok(Some(self.surface_info(surface))),
+            Framebuffer::ExternalFramebuffer(ref framebuffer) => {
+                Ok(Some(framebuffer.info(context.id)))
+            }
+            Framebuffer::Surface(ref surface) => Ok(Some(SurfaceInfo {
+                bound: true,
+                ..self.surface_info(surface)
+            })),
         }
     }
 
@@ -356,7 +606,7 @@ impl Device {
     pub fn native_context(&self, context: &Context) -> NativeContext {
         let (egl_draw_surface, egl_read_surface) = match context.framebuffer {
             Framebuffer::Surface(Surface {
-                objects: SurfaceObjects::Window { egl_surface },
+                objects: SurfaceObjects::Window { egl_surface, .. },
                 ..
             }) => (egl_surface, egl_surface),
             Framebuffer::External(ExternalEGLSurfaces { draw, read }) => (draw, read),
@@ -364,7 +614,20 @@ impl Device {
                 objects: SurfaceObjects::HardwareBuffer { .. },
                 ..
             })
-            | Framebuffer::None => (context.pbuffer, context.pbuffer),
+            | Framebuffer::Surface(Surface {
+                objects: SurfaceObjects::SurfaceControl { .. },
+                ..
+            })
+            | Framebuffer::Surface(Surface {
+                objects: SurfaceObjects::Suspended,
+                ..
+            })
+            | Framebuffer::Surface(Surface {
+                objects: SurfaceObjects::ImportedHardwareBuffer { .. },
+                ..
+            })
+            | Framebuffer::None
+            | Framebuffer::ExternalFramebuffer(_) => (context.pbuffer, context.pbuffer),
         };
 
         NativeContext {
@@ -374,3 +637,15 @@ impl Device {
         }
     }
 }
+
+#[cfg(feature = "sm-opencl")]
+impl Device {
+    /// Returns what OpenCL needs to share objects with the given context through
+    /// `cl_khr_gl_sharing`.
+    pub fn gl_sharing_properties(&self, context: &Context) -> Result<GLSharingProperties, Error> {
+        Ok(GLSharingProperties {
+            gl_context: context.egl_context as *mut c_void,
+            display: GLSharingDisplay::EGL(self.egl_display),
+        })
+    }
+}
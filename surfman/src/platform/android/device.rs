@@ -1,43 +1,50 @@
 // surfman/surfman/src/platform/android/device.rs
 //
-//! A thread-local handle to the device.
+//! A handle to the device.
 
 use super::connection::Connection;
+use super::context::ContextDescriptor;
+use crate::context::{ContextDescriptorCache, ContextIDAllocator};
+use crate::device_events::DeviceEventDispatcher;
 use crate::egl;
 use crate::egl::types::EGLDisplay;
-use crate::platform::generic::egl::device::EGL_FUNCTIONS;
-use crate::{Error, GLApi};
+use crate::platform::generic::egl::device::{OwnedEGLDisplay, EGL_FUNCTIONS};
+use crate::timings::{CreationTimings, CreationTimingsRecorder};
+use crate::{DeviceEventHandler, Error, GLApi, LifecycleObserver, NativeDeviceHandle};
+
+use std::os::raw::c_void;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 /// Represents a hardware display adapter that can be used for rendering (including the CPU).
 ///
-/// Adapters can be sent between threads. To render with an adapter, open a thread-local `Device`.
+/// Adapters can be sent between threads. To render with an adapter, open a `Device`.
 #[derive(Clone, Debug)]
 pub struct Adapter;
 
-/// A thread-local handle to a device.
+/// A handle to a device.
 ///
-/// Devices contain most of the relevant surface management methods.
+/// Devices contain most of the relevant surface management methods. Clones share the device's
+/// EGL display and can be sent to other threads, so each rendering thread can own a handle.
+#[derive(Clone)]
 pub struct Device {
     pub(crate) egl_display: EGLDisplay,
-    pub(crate) display_is_owned: bool,
+    // Present if the device initialized the display and must terminate it.
+    pub(crate) _display_owner: Option<Arc<OwnedEGLDisplay>>,
+    pub(crate) lifecycle_observer: Option<Arc<dyn LifecycleObserver>>,
+    pub(crate) device_event_dispatcher: DeviceEventDispatcher,
+    pub(crate) creation_timings: CreationTimingsRecorder,
+    pub(crate) memory_usage: Arc<AtomicUsize>,
+    pub(crate) context_ids: ContextIDAllocator,
+    pub(crate) context_descriptors: ContextDescriptorCache<ContextDescriptor>,
 }
 
 /// Wrapper for an `EGLDisplay`.
 #[derive(Clone, Copy)]
 pub struct NativeDevice(pub EGLDisplay);
 
-impl Drop for Device {
-    fn drop(&mut self) {
-        EGL_FUNCTIONS.with(|egl| unsafe {
-            if !self.display_is_owned {
-                return;
-            }
-            let result = egl.Terminate(self.egl_display);
-            assert_ne!(result, egl::FALSE);
-            self.egl_display = egl::NO_DISPLAY;
-        })
-    }
-}
+// EGL displays are process-wide, and EGL is thread-safe.
+unsafe impl Send for Device {}
 
 impl NativeDevice {
     /// Returns the current EGL display.
@@ -50,7 +57,7 @@ impl NativeDevice {
 
 impl Device {
     #[inline]
-    pub(crate) fn new() -> Result<Device, Error> {
+    pub(crate) fn new(connection: &Connection) -> Result<Device, Error> {
         EGL_FUNCTIONS.with(|egl| {
             unsafe {
                 let egl_display = egl.GetDisplay(egl::DEFAULT_DISPLAY);
@@ -63,7 +70,13 @@ impl Device {
 
                 Ok(Device {
                     egl_display,
-                    display_is_owned: true,
+                    _display_owner: Some(Arc::new(OwnedEGLDisplay(egl_display))),
+                    lifecycle_observer: None,
+                    device_event_dispatcher: DeviceEventDispatcher::default(),
+                    creation_timings: CreationTimingsRecorder::default(),
+                    memory_usage: Arc::new(AtomicUsize::new(0)),
+                    context_ids: connection.context_ids.clone(),
+                    context_descriptors: ContextDescriptorCache::new(),
                 })
             }
         })
@@ -75,10 +88,18 @@ impl Device {
         NativeDevice(self.egl_display)
     }
 
+    /// Returns the raw display underneath this device: its EGL display.
+    #[inline]
+    pub fn native_device_handle(&self) -> NativeDeviceHandle {
+        NativeDeviceHandle::EGLDisplay(self.egl_display as *mut c_void)
+    }
+
     /// Returns the display server connection that this device was created with.
     #[inline]
     pub fn connection(&self) -> Connection {
-        Connection
+        Connection {
+            context_ids: self.context_ids.clone(),
+        }
     }
 
     /// Returns the adapter that this device was created with.
@@ -92,4 +113,60 @@ impl Device {
     pub fn gl_api(&self) -> GLApi {
         GLApi::GLES
     }
+
+    /// Installs an observer that is notified when contexts and surfaces of this device are
+    /// created, destroyed, resized, or presented, replacing any previous one.
+    #[inline]
+    pub fn set_lifecycle_observer(&mut self, observer: Option<Arc<dyn LifecycleObserver>>) {
+        self.lifecycle_observer = observer;
+    }
+
+    /// Returns the lifecycle observer installed on this device, if any.
+    #[inline]
+    pub fn lifecycle_observer(&self) -> Option<Arc<dyn LifecycleObserver>> {
+        self.lifecycle_observer.clone()
+    }
+
+    /// Installs a handler that is told when the GPU of this device is removed or reset, or the
+    /// displays are reconfigured, replacing any previous one.
+    ///
+    /// This backend can't list displays, so only resets are reported.
+    #[inline]
+    pub fn set_device_event_handler(&mut self, handler: Option<DeviceEventHandler>) {
+        self.device_event_dispatcher.set_handler(handler, || None);
+    }
+
+    /// Returns the device event handler installed on this device, if any.
+    #[inline]
+    pub fn device_event_handler(&self) -> Option<DeviceEventHandler> {
+        self.device_event_dispatcher.handler()
+    }
+
+    #[inline]
+    pub(crate) fn device_event_dispatcher(&self) -> &DeviceEventDispatcher {
+        &self.device_event_dispatcher
+    }
+
+    /// Returns the GPU memory, in bytes, held by the surfaces of this device.
+    #[inline]
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub(crate) fn memory_usage_counter(&self) -> &AtomicUsize {
+        &self.memory_usage
+    }
+
+    /// Returns how long opening this device, and creating its context descriptors and contexts,
+    /// took.
+    #[inline]
+    pub fn creation_timings(&self) -> CreationTimings {
+        self.creation_timings.get()
+    }
+
+    #[inline]
+    pub(crate) fn creation_timings_recorder(&self) -> &CreationTimingsRecorder {
+        &self.creation_timings
+    }
 }
@@ -1,13 +1,21 @@
 // surfman/surfman/src/platform/android/ffi.rs
 
-use std::os::raw::c_int;
+use std::os::raw::{c_int, c_void};
 
 pub(crate) const AHARDWAREBUFFER_FORMAT_R8G8B8A8_UNORM: u32 = 1;
+pub(crate) const AHARDWAREBUFFER_FORMAT_R16G16B16A16_FLOAT: i32 = 0x16;
+pub(crate) const AHARDWAREBUFFER_FORMAT_R10G10B10A2_UNORM: i32 = 0x2b;
 
-pub(crate) const AHARDWAREBUFFER_USAGE_CPU_READ_NEVER: u64 = 0;
-pub(crate) const AHARDWAREBUFFER_USAGE_CPU_WRITE_NEVER: u64 = 0 << 4;
+pub(crate) const AHARDWAREBUFFER_USAGE_CPU_READ_RARELY: u64 = 2;
+pub(crate) const AHARDWAREBUFFER_USAGE_CPU_READ_OFTEN: u64 = 3;
+pub(crate) const AHARDWAREBUFFER_USAGE_CPU_WRITE_RARELY: u64 = 2 << 4;
+pub(crate) const AHARDWAREBUFFER_USAGE_CPU_WRITE_OFTEN: u64 = 3 << 4;
 pub(crate) const AHARDWAREBUFFER_USAGE_GPU_SAMPLED_IMAGE: u64 = 1 << 8;
 pub(crate) const AHARDWAREBUFFER_USAGE_GPU_FRAMEBUFFER: u64 = 1 << 9;
+pub(crate) const AHARDWAREBUFFER_USAGE_COMPOSER_OVERLAY: u64 = 1 << 11;
+pub(crate) const AHARDWAREBUFFER_USAGE_PROTECTED_CONTENT: u64 = 1 << 14;
+pub(crate) const AHARDWAREBUFFER_USAGE_VIDEO_ENCODE: u64 = 1 << 16;
+pub(crate) const AHARDWAREBUFFER_USAGE_GPU_MIPMAP_COMPLETE: u64 = 1 << 26;
 
 #[repr(C)]
 pub struct AHardwareBuffer {
@@ -31,14 +39,95 @@ pub struct ANativeWindow {
     opaque: i32,
 }
 
+#[repr(C)]
+pub(crate) struct ASurfaceControl {
+    opaque: i32,
+}
+
+#[repr(C)]
+pub(crate) struct ASurfaceTransaction {
+    opaque: i32,
+}
+
+#[repr(C)]
+pub(crate) struct ASurfaceTransactionStats {
+    opaque: i32,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub(crate) struct ARect {
+    pub(crate) left: i32,
+    pub(crate) top: i32,
+    pub(crate) right: i32,
+    pub(crate) bottom: i32,
+}
+
+#[repr(C)]
+pub(crate) struct ALooper {
+    opaque: i32,
+}
+
+#[repr(C)]
+pub(crate) struct AChoreographer {
+    opaque: i32,
+}
+
+#[repr(C)]
+pub(crate) struct AChoreographerFrameCallbackData {
+    opaque: i32,
+}
+
+pub(crate) const ANATIVEWINDOW_TRANSFORM_IDENTITY: i32 = 0x00;
+pub(crate) const ANATIVEWINDOW_TRANSFORM_MIRROR_VERTICAL: i32 = 0x02;
+pub(crate) const ANATIVEWINDOW_TRANSFORM_ROTATE_90: i32 = 0x04;
+pub(crate) const ANATIVEWINDOW_TRANSFORM_ROTATE_180: i32 = 0x03;
+pub(crate) const ANATIVEWINDOW_TRANSFORM_ROTATE_270: i32 = 0x07;
+
+// The `ANativeWindow_query()` key for the transform that the compositor would apply to buffers.
+pub(crate) const NATIVE_WINDOW_TRANSFORM_HINT: c_int = 8;
+
+pub(crate) const ASURFACE_TRANSACTION_VISIBILITY_SHOW: i8 = 1;
+pub(crate) const ASURFACE_TRANSACTION_TRANSPARENCY_TRANSLUCENT: i8 = 1;
+pub(crate) const ASURFACE_TRANSACTION_TRANSPARENCY_OPAQUE: i8 = 2;
+
+pub(crate) const ADATASPACE_SRGB: i32 = 142671872;
+pub(crate) const ADATASPACE_DISPLAY_P3: i32 = 143261696;
+pub(crate) const ADATASPACE_BT2020_PQ: i32 = 163971072;
+
 #[link(name = "android")]
 extern "C" {
     pub(crate) fn AHardwareBuffer_allocate(
         desc: *const AHardwareBuffer_Desc,
         outBuffer: *mut *mut AHardwareBuffer,
     ) -> c_int;
+    pub(crate) fn AHardwareBuffer_acquire(buffer: *mut AHardwareBuffer);
+    pub(crate) fn AHardwareBuffer_describe(
+        buffer: *const AHardwareBuffer,
+        outDesc: *mut AHardwareBuffer_Desc,
+    );
     pub(crate) fn AHardwareBuffer_release(buffer: *mut AHardwareBuffer);
 
     pub(crate) fn ANativeWindow_getWidth(window: *mut ANativeWindow) -> i32;
     pub(crate) fn ANativeWindow_getHeight(window: *mut ANativeWindow) -> i32;
+    pub(crate) fn ANativeWindow_getFormat(window: *mut ANativeWindow) -> i32;
+    pub(crate) fn ANativeWindow_setBuffersGeometry(
+        window: *mut ANativeWindow,
+        width: i32,
+        height: i32,
+        format: i32,
+    ) -> i32;
+    pub(crate) fn ANativeWindow_setBuffersTransform(
+        window: *mut ANativeWindow,
+        transform: i32,
+    ) -> i32;
+
+    pub(crate) fn ALooper_prepare(opts: c_int) -> *mut ALooper;
+    pub(crate) fn ALooper_pollOnce(
+        timeoutMillis: c_int,
+        outFd: *mut c_int,
+        outEvents: *mut c_int,
+        outData: *mut *mut c_void,
+    ) -> c_int;
+    pub(crate) fn ALooper_wake(looper: *mut ALooper);
 }
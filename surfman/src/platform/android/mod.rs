@@ -2,12 +2,14 @@
 //
 //! Bindings to EGL on Android.
 
+pub mod choreographer;
 pub mod connection;
 pub mod context;
 pub mod device;
 pub mod surface;
 
 mod ffi;
+mod surface_control;
 
 #[path = "../../implementation/mod.rs"]
 mod implementation;
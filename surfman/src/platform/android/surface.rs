@@ -4,31 +4,56 @@
 
 use super::context::{Context, GL_FUNCTIONS};
 use super::device::Device;
+use super::ffi::NATIVE_WINDOW_TRANSFORM_HINT;
 use super::ffi::{AHardwareBuffer, AHardwareBuffer_Desc, AHARDWAREBUFFER_USAGE_GPU_SAMPLED_IMAGE};
+use super::ffi::{AHardwareBuffer_acquire, AHardwareBuffer_describe};
 use super::ffi::{AHardwareBuffer_allocate, AHardwareBuffer_release, ANativeWindow};
-use super::ffi::{ANativeWindow_getHeight, ANativeWindow_getWidth};
-use super::ffi::{AHARDWAREBUFFER_FORMAT_R8G8B8A8_UNORM, AHARDWAREBUFFER_USAGE_CPU_READ_NEVER};
-use super::ffi::{AHARDWAREBUFFER_USAGE_CPU_WRITE_NEVER, AHARDWAREBUFFER_USAGE_GPU_FRAMEBUFFER};
+use super::ffi::{ANativeWindow_getFormat, ANativeWindow_getHeight, ANativeWindow_getWidth};
+use super::ffi::{ANativeWindow_setBuffersGeometry, ANativeWindow_setBuffersTransform};
+use super::ffi::{ADATASPACE_BT2020_PQ, ADATASPACE_DISPLAY_P3, ADATASPACE_SRGB};
+use super::ffi::{
+    AHARDWAREBUFFER_FORMAT_R10G10B10A2_UNORM, AHARDWAREBUFFER_FORMAT_R16G16B16A16_FLOAT,
+};
+use super::ffi::{AHARDWAREBUFFER_FORMAT_R8G8B8A8_UNORM, AHARDWAREBUFFER_USAGE_COMPOSER_OVERLAY};
+use super::ffi::{AHARDWAREBUFFER_USAGE_CPU_READ_OFTEN, AHARDWAREBUFFER_USAGE_CPU_READ_RARELY};
+use super::ffi::{AHARDWAREBUFFER_USAGE_CPU_WRITE_OFTEN, AHARDWAREBUFFER_USAGE_CPU_WRITE_RARELY};
+use super::ffi::{
+    AHARDWAREBUFFER_USAGE_GPU_FRAMEBUFFER, AHARDWAREBUFFER_USAGE_GPU_MIPMAP_COMPLETE,
+};
+use super::ffi::{AHARDWAREBUFFER_USAGE_PROTECTED_CONTENT, AHARDWAREBUFFER_USAGE_VIDEO_ENCODE};
+use super::ffi::{ANATIVEWINDOW_TRANSFORM_IDENTITY, ANATIVEWINDOW_TRANSFORM_ROTATE_180};
+use super::ffi::{ANATIVEWINDOW_TRANSFORM_ROTATE_270, ANATIVEWINDOW_TRANSFORM_ROTATE_90};
+use super::surface_control::{self, Swapchain, SwapchainBuffer};
 use crate::context::ContextID;
 use crate::egl;
 use crate::egl::types::{EGLSurface, EGLint};
 use crate::gl;
-use crate::gl::types::{GLenum, GLuint};
+use crate::gl::types::{GLenum, GLint, GLuint};
 use crate::gl_utils;
+#[cfg(feature = "sm-opencl")]
+use crate::opencl::SharedEGLImage;
 use crate::platform::generic;
-use crate::platform::generic::egl::device::EGL_FUNCTIONS;
+use crate::platform::generic::egl::device::{self as egl_device, EGL_FUNCTIONS};
+use crate::platform::generic::egl::error::ToWindowingApiError;
 use crate::platform::generic::egl::ffi::EGLImageKHR;
 use crate::platform::generic::egl::ffi::EGL_EXTENSION_FUNCTIONS;
 use crate::platform::generic::egl::ffi::EGL_IMAGE_PRESERVED_KHR;
+use crate::platform::generic::egl::ffi::EGL_MUTABLE_RENDER_BUFFER_BIT_KHR;
 use crate::platform::generic::egl::ffi::EGL_NATIVE_BUFFER_ANDROID;
 use crate::platform::generic::egl::ffi::EGL_NO_IMAGE_KHR;
 use crate::renderbuffers::Renderbuffers;
-use crate::{Error, SurfaceAccess, SurfaceID, SurfaceInfo, SurfaceType, WindowingApiError};
+use crate::surface::{estimate_memory_usage, SurfaceLayoutRequirements};
+use crate::{
+    Colorspace, CreateSurfacesResult, Error, Gl, PresentationStatistics, SurfaceAccess,
+    SurfaceAlphaMode, SurfaceFormat, SurfaceID, SurfaceInfo, SurfaceOrigin, SurfaceTransform,
+    SurfaceType, SurfaceUsage, SurfaceVisibility, WidgetOutputInfo, WindowingApiError,
+};
 
-use euclid::default::Size2D;
+use euclid::default::{Rect, Size2D};
 use std::fmt::{self, Debug, Formatter};
 use std::marker::PhantomData;
-use std::os::raw::c_void;
+use std::mem;
+use std::os::raw::{c_char, c_int, c_void};
 use std::ptr;
 use std::thread;
 
@@ -36,6 +61,13 @@ pub use crate::platform::generic::egl::context::ContextDescriptor;
 
 const SURFACE_GL_TEXTURE_TARGET: GLenum = gl::TEXTURE_2D;
 
+// From `GL_OES_EGL_image_external`.
+const GL_TEXTURE_EXTERNAL_OES: GLenum = 0x8d65;
+const GL_TEXTURE_BINDING_EXTERNAL_OES: GLenum = 0x8d67;
+
+// Binding a layered hardware buffer to a `GL_TEXTURE_2D_ARRAY` requires this.
+const EGL_IMAGE_ARRAY_EXTENSION: &[u8] = b"GL_EXT_EGL_image_array";
+
 /// Represents a hardware buffer of pixels that can be rendered to via the CPU or GPU and either
 /// displayed in a native widget or bound to a texture for reading.
 ///
@@ -53,9 +85,23 @@ const SURFACE_GL_TEXTURE_TARGET: GLenum = gl::TEXTURE_2D;
 ///
 /// Surfaces must be destroyed with the `destroy_surface()` method, or a panic will occur.
 pub struct Surface {
+    pub(crate) id: SurfaceID,
     pub(crate) context_id: ContextID,
     pub(crate) size: Size2D<i32>,
     pub(crate) objects: SurfaceObjects,
+    // The rotation that rendering applies to the contents, if pre-rotation was requested.
+    pub(crate) transform: SurfaceTransform,
+    pub(crate) pre_rotation: bool,
+    // The order of the channels in memory. See `SurfaceInfo::format`.
+    pub(crate) format: SurfaceFormat,
+    // Which way up the contents are. See `SurfaceInfo::origin`.
+    pub(crate) origin: SurfaceOrigin,
+    // How the alpha channel is interpreted. See `SurfaceInfo::alpha_mode`.
+    pub(crate) alpha_mode: SurfaceAlphaMode,
+    // The color space of the contents. See `SurfaceInfo::colorspace`.
+    pub(crate) colorspace: Colorspace,
+    // The number of samples per pixel. See `SurfaceInfo::samples`.
+    pub(crate) samples: u32,
     pub(crate) destroyed: bool,
 }
 
@@ -72,6 +118,7 @@ pub struct SurfaceTexture {
     pub(crate) surface: Surface,
     pub(crate) local_egl_image: EGLImageKHR,
     pub(crate) texture_object: GLuint,
+    pub(crate) texture_target: GLenum,
     pub(crate) phantom: PhantomData<*const ()>,
 }
 
@@ -82,9 +129,22 @@ pub(crate) enum SurfaceObjects {
         framebuffer_object: GLuint,
         texture_object: GLuint,
         renderbuffers: Renderbuffers,
+        layers: u32,
     },
     Window {
         egl_surface: EGLSurface,
+        native_window: *mut ANativeWindow,
+    },
+    SurfaceControl {
+        swapchain: Swapchain,
+        native_window: *mut ANativeWindow,
+    },
+    // A widget surface whose window has gone away. See `suspend_widget_surface()`.
+    Suspended,
+    // A hardware buffer allocated by someone else, which can only be read from. See
+    // `create_surface_texture_from_hardware_buffer()`.
+    ImportedHardwareBuffer {
+        hardware_buffer: *mut AHardwareBuffer,
     },
 }
 
@@ -110,9 +170,131 @@ impl Debug for SurfaceTexture {
     }
 }
 
+bitflags! {
+    /// How the hardware buffer behind a generic surface will be used, which lets the allocator
+    /// place it in the memory that suits that use best.
+    ///
+    /// These map directly onto the `AHARDWAREBUFFER_USAGE_*` flags. Generic surfaces are always
+    /// allocated with `GPU_FRAMEBUFFER` and `GPU_SAMPLED_IMAGE`, since surfman renders to them and
+    /// samples them as surface textures.
+    pub struct HardwareBufferUsage: u64 {
+        /// The CPU will read the buffer occasionally.
+        const CPU_READ_RARELY     = AHARDWAREBUFFER_USAGE_CPU_READ_RARELY;
+        /// The CPU will read the buffer often, so it should be cached for reading.
+        const CPU_READ_OFTEN      = AHARDWAREBUFFER_USAGE_CPU_READ_OFTEN;
+        /// The CPU will write the buffer occasionally.
+        const CPU_WRITE_RARELY    = AHARDWAREBUFFER_USAGE_CPU_WRITE_RARELY;
+        /// The CPU will write the buffer often.
+        const CPU_WRITE_OFTEN     = AHARDWAREBUFFER_USAGE_CPU_WRITE_OFTEN;
+        /// The GPU will sample the buffer as a texture.
+        const GPU_SAMPLED_IMAGE   = AHARDWAREBUFFER_USAGE_GPU_SAMPLED_IMAGE;
+        /// The GPU will render to the buffer.
+        const GPU_FRAMEBUFFER     = AHARDWAREBUFFER_USAGE_GPU_FRAMEBUFFER;
+        /// The buffer will be handed to SurfaceFlinger, which may scan it out as a hardware
+        /// overlay.
+        const COMPOSER_OVERLAY    = AHARDWAREBUFFER_USAGE_COMPOSER_OVERLAY;
+        /// The buffer holds protected content, which only a secure path may read.
+        const PROTECTED_CONTENT   = AHARDWAREBUFFER_USAGE_PROTECTED_CONTENT;
+        /// The buffer will be read by a hardware video encoder.
+        const VIDEO_ENCODE        = AHARDWAREBUFFER_USAGE_VIDEO_ENCODE;
+        /// The buffer has a full mip chain, which the GPU fills in.
+        const GPU_MIPMAP_COMPLETE = AHARDWAREBUFFER_USAGE_GPU_MIPMAP_COMPLETE;
+    }
+}
+
+impl From<SurfaceAccess> for HardwareBufferUsage {
+    fn from(access: SurfaceAccess) -> HardwareBufferUsage {
+        match access {
+            SurfaceAccess::GPUOnly => HardwareBufferUsage::empty(),
+            SurfaceAccess::GPUCPU => {
+                HardwareBufferUsage::CPU_READ_OFTEN | HardwareBufferUsage::CPU_WRITE_OFTEN
+            }
+            SurfaceAccess::GPUCPUWriteCombined => {
+                HardwareBufferUsage::CPU_READ_RARELY | HardwareBufferUsage::CPU_WRITE_OFTEN
+            }
+        }
+    }
+}
+
+// Hardware buffers have no usage flag for video decoders, so `VIDEO_DECODE` isn't mapped.
+impl From<SurfaceUsage> for HardwareBufferUsage {
+    fn from(surface_usage: SurfaceUsage) -> HardwareBufferUsage {
+        let mut usage = HardwareBufferUsage::empty();
+        if surface_usage.contains(SurfaceUsage::RENDER_TARGET) {
+            usage |= HardwareBufferUsage::GPU_FRAMEBUFFER;
+        }
+        if surface_usage.contains(SurfaceUsage::TEXTURE_SOURCE) {
+            usage |= HardwareBufferUsage::GPU_SAMPLED_IMAGE;
+        }
+        if surface_usage.contains(SurfaceUsage::CPU_READ) {
+            usage |= HardwareBufferUsage::CPU_READ_OFTEN;
+        }
+        if surface_usage.contains(SurfaceUsage::CPU_WRITE) {
+            usage |= HardwareBufferUsage::CPU_WRITE_OFTEN;
+        }
+        if surface_usage.contains(SurfaceUsage::SCANOUT) {
+            usage |= HardwareBufferUsage::COMPOSER_OVERLAY;
+        }
+        if surface_usage.contains(SurfaceUsage::MIPMAPPED) {
+            usage |= HardwareBufferUsage::GPU_MIPMAP_COMPLETE;
+        }
+        // There are no usage flags that ask for a linear or uncompressed layout directly, but
+        // allocators only tile and compress buffers that the CPU never maps. The "rarely" flags
+        // leave the caching of the buffer as `SurfaceAccess` and the other usage flags request it.
+        if surface_usage.intersects(SurfaceUsage::LINEAR | SurfaceUsage::UNCOMPRESSED) {
+            usage |= HardwareBufferUsage::CPU_READ_RARELY | HardwareBufferUsage::CPU_WRITE_RARELY;
+        }
+        usage
+    }
+}
+
 /// An Android native window.
 pub struct NativeWidget {
     pub(crate) native_window: *mut ANativeWindow,
+    pub(crate) surface_control: bool,
+    pub(crate) pre_rotation: bool,
+}
+
+impl NativeWidget {
+    /// Requests that widget surfaces created from this native widget be presented with
+    /// `ASurfaceControl` instead of `eglSwapBuffers()`.
+    ///
+    /// The surface then renders into a ring of hardware buffers that are handed to SurfaceFlinger
+    /// in transactions carrying explicit fences, and each buffer is reused only once SurfaceFlinger
+    /// releases it. This avoids the buffer queue of the window, lowering latency.
+    ///
+    /// This needs Android 10 (API 29) or later; on older releases, it has no effect. The window
+    /// should not be rendered to by anything else while such a surface exists.
+    #[inline]
+    pub fn with_surface_control(mut self, enabled: bool) -> NativeWidget {
+        self.surface_control = enabled;
+        self
+    }
+
+    /// Requests that widget surfaces created from this native widget render their contents
+    /// pre-rotated to the orientation of the display.
+    ///
+    /// When the display is rotated relative to the natural orientation of the window,
+    /// SurfaceFlinger otherwise has to rotate every frame, which usually means composing it on the
+    /// GPU instead of scanning it out directly. With pre-rotation, the surface is allocated in the
+    /// orientation of the display instead, and the rotation that rendering must apply is reported
+    /// in the `transform` of `SurfaceInfo`. Without it, `transform` is always `Identity`.
+    ///
+    /// The rotation is picked up when the surface is created, and, for surfaces presented with
+    /// `ASurfaceControl`, when it is resized.
+    #[inline]
+    pub fn with_pre_rotation(mut self, enabled: bool) -> NativeWidget {
+        self.pre_rotation = enabled;
+        self
+    }
+
+    /// Returns the rotation that SurfaceFlinger applies when showing the contents of this window,
+    /// which is the transform hint of the window.
+    ///
+    /// This is `Identity` if the hint can't be queried.
+    pub fn display_transform(&self) -> SurfaceTransform {
+        unsafe { query_transform_hint(self.native_window) }
+    }
 }
 
 impl Device {
@@ -120,62 +302,251 @@ impl Device {
     ///
     /// Only the given context may ever render to the surface, but generic surfaces can be wrapped
     /// up in a `SurfaceTexture` for reading by other contexts.
-    pub fn create_surface(
+    ///
+    /// On Android, the access pattern and usage pick the usage flags of the hardware buffer behind
+    /// a generic surface; see `create_surface_with_usage()` for finer control.
+    ///
+    /// SurfaceFlinger ignores the alpha channel of opaque widget surfaces, and blends the others
+    /// as premultiplied. Widget surfaces with straight alpha are unsupported.
+    pub(crate) fn create_surface_impl(
+        &mut self,
+        context: &Context,
+        surface_access: SurfaceAccess,
+        surface_usage: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
+        surface_type: SurfaceType<NativeWidget>,
+    ) -> Result<Surface, Error> {
+        let usage = HardwareBufferUsage::from(surface_access) | surface_usage.into();
+        self.create_surface_with_usage_impl(context, usage, surface_alpha_mode, surface_type)
+    }
+
+    /// Creates `count` generic surfaces of the given size.
+    ///
+    /// The access pattern and usage pick the usage flags of the hardware buffers as in
+    /// `create_surface()`. The context is made current once for the whole batch. If one of the
+    /// surfaces fails to be created, the error is returned along with the surfaces that were
+    /// created before it.
+    pub(crate) fn create_surfaces_impl(
+        &mut self,
+        context: &Context,
+        surface_access: SurfaceAccess,
+        surface_usage: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
+        size: &Size2D<i32>,
+        count: usize,
+    ) -> CreateSurfacesResult<Surface> {
+        let _guard = match self.temporarily_make_context_current(context) {
+            Ok(guard) => guard,
+            Err(err) => return Err((err, vec![])),
+        };
+        let usage = HardwareBufferUsage::from(surface_access) | surface_usage.into();
+
+        let mut surfaces = Vec::with_capacity(count);
+        for _ in 0..count {
+            match self.create_generic_surface(context, size, 1, usage) {
+                Ok(mut surface) => {
+                    surface.alpha_mode = surface_alpha_mode;
+                    surfaces.push(surface)
+                }
+                Err(err) => return Err((err, surfaces)),
+            }
+        }
+        Ok(surfaces)
+    }
+
+    /// Creates either a generic or a widget surface, allocating the hardware buffer behind a
+    /// generic surface with the given usage.
+    ///
+    /// Describing the actual use of the surface, for example that SurfaceFlinger will show it as
+    /// an overlay or that a video encoder will read it, lets gralloc place the buffer optimally.
+    /// The buffers of widget surfaces are allocated by the window or for composition, so the usage
+    /// is ignored for them. If the device can't allocate a buffer with the requested usage, this
+    /// returns `Error::SurfaceCreationFailed`.
+    pub fn create_surface_with_usage(
+        &mut self,
+        context: &Context,
+        usage: HardwareBufferUsage,
+        alpha_mode: SurfaceAlphaMode,
+        surface_type: SurfaceType<NativeWidget>,
+    ) -> Result<Surface, Error> {
+        let surface =
+            self.create_surface_with_usage_impl(context, usage, alpha_mode, surface_type)?;
+        self.note_surface_created(&surface);
+        Ok(surface)
+    }
+
+    fn create_surface_with_usage_impl(
         &mut self,
         context: &Context,
-        _: SurfaceAccess,
+        usage: HardwareBufferUsage,
+        alpha_mode: SurfaceAlphaMode,
         surface_type: SurfaceType<NativeWidget>,
     ) -> Result<Surface, Error> {
         match surface_type {
-            SurfaceType::Generic { size } => self.create_generic_surface(context, &size),
+            SurfaceType::Generic { size } => {
+                let mut surface = self.create_generic_surface(context, &size, 1, usage)?;
+                surface.alpha_mode = alpha_mode;
+                Ok(surface)
+            }
+            // SurfaceFlinger only composites premultiplied contents.
+            SurfaceType::Widget { .. } if alpha_mode == SurfaceAlphaMode::Straight => {
+                Err(Error::UnsupportedOnThisPlatform)
+            }
             SurfaceType::Widget { native_widget } => unsafe {
-                self.create_window_surface(context, native_widget.native_window)
+                if native_widget.surface_control && surface_control::is_available() {
+                    self.create_surface_control_surface(context, &native_widget, alpha_mode)
+                } else {
+                    self.create_window_surface(context, &native_widget, alpha_mode)
+                }
             },
         }
     }
 
+    /// Creates a generic surface backed by a hardware buffer with the given number of layers, each
+    /// of the given size.
+    ///
+    /// Surface textures of a layered surface are bound to `GL_TEXTURE_2D_ARRAY`, so one allocation
+    /// can hold many tiles, or the layers of a layered compositor. Rendering to the surface draws
+    /// into the layer chosen with `set_surface_layer()`, initially the first.
+    ///
+    /// The access pattern and usage pick the usage flags of the hardware buffer as in
+    /// `create_surface()`. This requires the `GL_EXT_EGL_image_array` extension, and `layers` must
+    /// be at least 1, or this returns `Error::InvalidSurfaceLayer`.
+    pub fn create_layered_surface(
+        &mut self,
+        context: &Context,
+        surface_access: SurfaceAccess,
+        surface_usage: SurfaceUsage,
+        size: &Size2D<i32>,
+        layers: u32,
+    ) -> Result<Surface, Error> {
+        if layers == 0 {
+            return Err(Error::InvalidSurfaceLayer);
+        }
+        let usage = HardwareBufferUsage::from(surface_access) | surface_usage.into();
+        let surface = self.create_generic_surface(context, size, layers, usage)?;
+        self.note_surface_created(&surface);
+        Ok(surface)
+    }
+
+    /// Chooses the layer of a layered surface that rendering to the surface draws into.
+    ///
+    /// The supplied context must be the context the surface is associated with, or this returns
+    /// an `IncompatibleSurface` error. Surfaces not created with `create_layered_surface()` have
+    /// only the first layer, and asking for a layer past the last returns
+    /// `Error::InvalidSurfaceLayer`.
+    pub fn set_surface_layer(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        layer: u32,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        match surface.objects {
+            SurfaceObjects::HardwareBuffer {
+                framebuffer_object,
+                texture_object,
+                layers,
+                ..
+            } if layer < layers => {
+                if layers == 1 {
+                    return Ok(());
+                }
+                let _guard = self.temporarily_make_context_current(context)?;
+                GL_FUNCTIONS.with(|gl| unsafe {
+                    let mut bindings = gl_utils::BindingsGuard::new(gl);
+                    bindings.bind_framebuffer(gl::DRAW_FRAMEBUFFER, framebuffer_object);
+                    gl.FramebufferTextureLayer(
+                        gl::DRAW_FRAMEBUFFER,
+                        gl::COLOR_ATTACHMENT0,
+                        texture_object,
+                        0,
+                        layer as GLint,
+                    );
+                });
+                Ok(())
+            }
+            SurfaceObjects::ImportedHardwareBuffer { .. } if layer == 0 => Ok(()),
+            SurfaceObjects::HardwareBuffer { .. }
+            | SurfaceObjects::ImportedHardwareBuffer { .. } => Err(Error::InvalidSurfaceLayer),
+            SurfaceObjects::Window { .. }
+            | SurfaceObjects::SurfaceControl { .. }
+            | SurfaceObjects::Suspended => Err(Error::WidgetAttached),
+        }
+    }
+
+    /// Returns the number of layers in the hardware buffer behind a generic surface.
+    ///
+    /// This is 1 for all surfaces except those created with `create_layered_surface()`.
+    pub fn surface_layers(&self, surface: &Surface) -> u32 {
+        match surface.objects {
+            SurfaceObjects::HardwareBuffer { layers, .. } => layers,
+            _ => 1,
+        }
+    }
+
     fn create_generic_surface(
         &mut self,
         context: &Context,
         size: &Size2D<i32>,
+        layers: u32,
+        usage: HardwareBufferUsage,
     ) -> Result<Surface, Error> {
         let _guard = self.temporarily_make_context_current(context)?;
 
         GL_FUNCTIONS.with(|gl| {
             unsafe {
-                // Create a native hardware buffer.
-                let hardware_buffer_desc = AHardwareBuffer_Desc {
-                    format: AHARDWAREBUFFER_FORMAT_R8G8B8A8_UNORM,
-                    height: size.height as u32,
-                    width: size.width as u32,
-                    layers: 1,
-                    rfu0: 0,
-                    rfu1: 0,
-                    stride: 10,
-                    usage: AHARDWAREBUFFER_USAGE_CPU_READ_NEVER
-                        | AHARDWAREBUFFER_USAGE_CPU_WRITE_NEVER
-                        | AHARDWAREBUFFER_USAGE_GPU_FRAMEBUFFER
-                        | AHARDWAREBUFFER_USAGE_GPU_SAMPLED_IMAGE,
-                };
-                let mut hardware_buffer = ptr::null_mut();
-                let result = AHardwareBuffer_allocate(&hardware_buffer_desc, &mut hardware_buffer);
-                if result != 0 {
-                    return Err(Error::SurfaceCreationFailed(WindowingApiError::Failed));
+                if layers > 1 && !gl_utils::context_has_extension(gl, EGL_IMAGE_ARRAY_EXTENSION) {
+                    return Err(Error::RequiredExtensionUnavailable);
                 }
 
-                // Create an EGL image, and bind it to a texture.
-                let egl_image = self.create_egl_image(context, hardware_buffer);
+                // Create a native hardware buffer.
+                let hardware_buffer = allocate_hardware_buffer(
+                    size,
+                    layers,
+                    usage | HardwareBufferUsage::GPU_SAMPLED_IMAGE,
+                )?;
 
-                // Initialize and bind the image to the texture.
-                let texture_object =
-                    generic::egl::surface::bind_egl_image_to_gl_texture(gl, egl_image);
+                // Create an EGL image, and bind it to a texture.
+                let egl_image = match self.create_egl_image(context, hardware_buffer) {
+                    Ok(egl_image) => egl_image,
+                    Err(err) => {
+                        AHardwareBuffer_release(hardware_buffer);
+                        return Err(Error::SurfaceCreationFailed(err));
+                    }
+                };
 
-                // Create the framebuffer, and bind the texture to it.
-                let framebuffer_object = gl_utils::create_and_bind_framebuffer(
-                    gl,
-                    SURFACE_GL_TEXTURE_TARGET,
-                    texture_object,
-                );
+                // Initialize and bind the image to the texture, and create the framebuffer,
+                // bound to the texture. The caller's bindings are put back afterward.
+                let mut bindings = gl_utils::BindingsGuard::new(gl);
+                let (texture_object, framebuffer_object) = if layers > 1 {
+                    let texture_object = bind_egl_image_to_texture(
+                        gl,
+                        egl_image,
+                        gl::TEXTURE_2D_ARRAY,
+                        gl::TEXTURE_BINDING_2D_ARRAY,
+                    );
+                    let mut framebuffer_object = 0;
+                    gl.GenFramebuffers(1, &mut framebuffer_object);
+                    bindings.bind_framebuffer(gl::FRAMEBUFFER, framebuffer_object);
+                    gl.FramebufferTextureLayer(
+                        gl::FRAMEBUFFER,
+                        gl::COLOR_ATTACHMENT0,
+                        texture_object,
+                        0,
+                        0,
+                    );
+                    (texture_object, framebuffer_object)
+                } else {
+                    let texture_object =
+                        generic::egl::surface::bind_egl_image_to_gl_texture(gl, egl_image);
+                    let framebuffer_object = bindings
+                        .create_and_bind_framebuffer(SURFACE_GL_TEXTURE_TARGET, texture_object);
+                    (texture_object, framebuffer_object)
+                };
 
                 // Bind renderbuffers as appropriate.
                 let context_descriptor = self.context_descriptor(context);
@@ -189,6 +560,7 @@ impl Device {
                 );
 
                 Ok(Surface {
+                    id: SurfaceID::next(),
                     size: *size,
                     context_id: context.id,
                     objects: SurfaceObjects::HardwareBuffer {
@@ -197,7 +569,15 @@ impl Device {
                         framebuffer_object,
                         texture_object,
                         renderbuffers,
+                        layers,
                     },
+                    transform: SurfaceTransform::Identity,
+                    pre_rotation: false,
+                    format: SurfaceFormat::RGBA8,
+                    origin: SurfaceOrigin::BottomLeft,
+                    alpha_mode: SurfaceAlphaMode::Premultiplied,
+                    colorspace: Colorspace::SRGB,
+                    samples: 1,
                     destroyed: false,
                 })
             }
@@ -207,29 +587,220 @@ impl Device {
     unsafe fn create_window_surface(
         &mut self,
         context: &Context,
-        native_window: *mut ANativeWindow,
+        native_widget: &NativeWidget,
+        alpha_mode: SurfaceAlphaMode,
     ) -> Result<Surface, Error> {
-        let width = ANativeWindow_getWidth(native_window);
-        let height = ANativeWindow_getHeight(native_window);
+        let (egl_surface, size, transform) =
+            self.create_egl_window_surface(context, native_widget, alpha_mode)?;
+        Ok(Surface {
+            id: SurfaceID::next(),
+            context_id: context.id,
+            size,
+            objects: SurfaceObjects::Window {
+                egl_surface,
+                native_window: native_widget.native_window,
+            },
+            transform,
+            pre_rotation: native_widget.pre_rotation,
+            format: SurfaceFormat::RGBA8,
+            origin: SurfaceOrigin::BottomLeft,
+            alpha_mode,
+            colorspace: Colorspace::SRGB,
+            samples: generic::egl::context::get_config_samples(
+                self.egl_display,
+                self.context_to_egl_config(context),
+            ),
+            destroyed: false,
+        })
+    }
 
+    // Creates an `EGLSurface` for the window of the given widget at its current size. Returns the
+    // surface along with its size and the rotation that rendering to it must apply.
+    unsafe fn create_egl_window_surface(
+        &self,
+        context: &Context,
+        native_widget: &NativeWidget,
+        alpha_mode: SurfaceAlphaMode,
+    ) -> Result<(EGLSurface, Size2D<i32>, SurfaceTransform), Error> {
+        let native_window = native_widget.native_window;
+        let (size, transform) = widget_size_and_transform(native_widget);
+
+        // With pre-rotation, the window's buffers take on the orientation of the display, and
+        // SurfaceFlinger is told that their contents are already rotated.
+        if native_widget.pre_rotation {
+            ANativeWindow_setBuffersGeometry(native_window, size.width, size.height, 0);
+            ANativeWindow_setBuffersTransform(native_window, native_transform(transform.inverse()));
+        }
+
+        let attributes =
+            generic::egl::surface::window_surface_attributes(self.egl_display, alpha_mode);
         EGL_FUNCTIONS.with(|egl| {
             let egl_surface = egl.CreateWindowSurface(
                 self.egl_display,
                 self.context_to_egl_config(context),
                 native_window as *const c_void,
-                ptr::null(),
+                attributes.as_ptr(),
             );
-            assert_ne!(egl_surface, egl::NO_SURFACE);
+            if egl_surface == egl::NO_SURFACE {
+                let err = egl.GetError().to_windowing_api_error();
+                return Err(Error::SurfaceCreationFailed(err));
+            }
+            Ok((egl_surface, size, transform))
+        })
+    }
+
+    unsafe fn create_surface_control_surface(
+        &mut self,
+        context: &Context,
+        native_widget: &NativeWidget,
+        alpha_mode: SurfaceAlphaMode,
+    ) -> Result<Surface, Error> {
+        let native_window = native_widget.native_window;
+        let (size, transform) = widget_size_and_transform(native_widget);
+
+        let _guard = self.temporarily_make_context_current(context)?;
+        let context_descriptor = self.context_descriptor(context);
+        let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+
+        GL_FUNCTIONS.with(|gl| {
+            let renderbuffers = Renderbuffers::new(gl, &size, &context_attributes);
+            let swapchain = Swapchain::new(
+                gl,
+                self.egl_display,
+                native_window,
+                &size,
+                native_transform(transform.inverse()),
+                alpha_mode == SurfaceAlphaMode::Opaque,
+                renderbuffers,
+                |size| self.create_swapchain_buffer(context, gl, size),
+            )?;
 
             Ok(Surface {
+                id: SurfaceID::next(),
                 context_id: context.id,
-                size: Size2D::new(width, height),
-                objects: SurfaceObjects::Window { egl_surface },
+                size,
+                objects: SurfaceObjects::SurfaceControl {
+                    swapchain,
+                    native_window,
+                },
+                transform,
+                pre_rotation: native_widget.pre_rotation,
+                format: SurfaceFormat::RGBA8,
+                origin: SurfaceOrigin::BottomLeft,
+                alpha_mode,
+                colorspace: Colorspace::SRGB,
+                samples: 1,
                 destroyed: false,
             })
         })
     }
 
+    // Allocates a buffer for an `ASurfaceControl` widget surface, which SurfaceFlinger may scan
+    // out directly.
+    unsafe fn create_swapchain_buffer(
+        &self,
+        context: &Context,
+        gl: &Gl,
+        size: &Size2D<i32>,
+    ) -> Result<SwapchainBuffer, Error> {
+        let hardware_buffer = allocate_hardware_buffer(
+            size,
+            1,
+            HardwareBufferUsage::COMPOSER_OVERLAY | HardwareBufferUsage::GPU_SAMPLED_IMAGE,
+        )?;
+        let egl_image = match self.create_egl_image(context, hardware_buffer) {
+            Ok(egl_image) => egl_image,
+            Err(err) => {
+                AHardwareBuffer_release(hardware_buffer);
+                return Err(Error::SurfaceCreationFailed(err));
+            }
+        };
+        let texture_object = generic::egl::surface::bind_egl_image_to_gl_texture(gl, egl_image);
+        Ok(SwapchainBuffer {
+            hardware_buffer,
+            egl_image,
+            texture_object,
+        })
+    }
+
+    /// Creates a new generic surface with a copy of the contents of the given one, stored with
+    /// its color channels in the given order.
+    ///
+    /// Surfaces on this backend are RGBA in memory. Calling this method on a widget surface
+    /// returns a `WidgetAttached` error.
+    pub(crate) fn convert_surface_format_impl(
+        &mut self,
+        context: &mut Context,
+        surface: &Surface,
+        format: SurfaceFormat,
+    ) -> Result<Surface, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        let read_framebuffer_object = match surface.objects {
+            SurfaceObjects::HardwareBuffer {
+                framebuffer_object,
+                layers: 1,
+                ..
+            } => framebuffer_object,
+            // Only the selected layer of a layered surface can be read through its framebuffer.
+            SurfaceObjects::HardwareBuffer { .. } => return Err(Error::UnsupportedOnThisPlatform),
+            // Imported buffers are only ever read through textures, so they have no framebuffer.
+            SurfaceObjects::ImportedHardwareBuffer { .. } => {
+                return Err(Error::UnsupportedOnThisPlatform)
+            }
+            SurfaceObjects::Window { .. }
+            | SurfaceObjects::SurfaceControl { .. }
+            | SurfaceObjects::Suspended => return Err(Error::WidgetAttached),
+        };
+
+        let usage =
+            HardwareBufferUsage::from(SurfaceAccess::GPUOnly) | SurfaceUsage::default().into();
+        let mut new_surface = self.create_generic_surface(context, &surface.size, 1, usage)?;
+        new_surface.format = format;
+        new_surface.origin = surface.origin;
+        new_surface.alpha_mode = surface.alpha_mode;
+        new_surface.colorspace = surface.colorspace;
+        let draw_framebuffer_object = match new_surface.objects {
+            SurfaceObjects::HardwareBuffer {
+                framebuffer_object, ..
+            } => framebuffer_object,
+            _ => unreachable!(),
+        };
+
+        match self.temporarily_make_context_current(context) {
+            Ok(_guard) => {
+                GL_FUNCTIONS.with(|gl| {
+                    gl_utils::copy_framebuffer(
+                        gl,
+                        read_framebuffer_object,
+                        draw_framebuffer_object,
+                        &surface.size,
+                        surface.format != format,
+                    )
+                });
+                Ok(new_surface)
+            }
+            Err(err) => {
+                self.destroy_surface_impl(context, &mut new_surface)?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Wraps an existing OpenGL texture of the given context in a new generic surface.
+    ///
+    /// Generic surfaces on this backend are hardware buffers, which can't be made from a texture, so this always returns an `UnsupportedOnThisPlatform` error.
+    #[inline]
+    pub(crate) fn create_surface_from_texture_impl(
+        &mut self,
+        _: &mut Context,
+        _: &Size2D<i32>,
+        _: GLuint,
+    ) -> Result<Surface, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
     /// Creates a surface texture from an existing generic surface for use with the given context.
     ///
     /// The surface texture is local to the supplied context and takes ownership of the surface.
@@ -247,25 +818,126 @@ impl Device {
     ) -> Result<SurfaceTexture, (Error, Surface)> {
         unsafe {
             match surface.objects {
-                SurfaceObjects::Window { .. } => return Err((Error::WidgetAttached, surface)),
+                SurfaceObjects::Window { .. }
+                | SurfaceObjects::SurfaceControl { .. }
+                | SurfaceObjects::Suspended => return Err((Error::WidgetAttached, surface)),
                 SurfaceObjects::HardwareBuffer {
                     hardware_buffer, ..
-                } => GL_FUNCTIONS.with(|gl| {
-                    let _guard = match self.temporarily_make_context_current(context) {
-                        Ok(guard) => guard,
-                        Err(err) => return Err((err, surface)),
-                    };
+                }
+                | SurfaceObjects::ImportedHardwareBuffer { hardware_buffer } => {
+                    GL_FUNCTIONS.with(|gl| {
+                        let _guard = match self.temporarily_make_context_current(context) {
+                            Ok(guard) => guard,
+                            Err(err) => return Err((err, surface)),
+                        };
 
-                    let local_egl_image = self.create_egl_image(context, hardware_buffer);
-                    let texture_object =
-                        generic::egl::surface::bind_egl_image_to_gl_texture(gl, local_egl_image);
-                    Ok(SurfaceTexture {
-                        surface,
-                        local_egl_image,
-                        texture_object,
-                        phantom: PhantomData,
+                        let local_egl_image = match self.create_egl_image(context, hardware_buffer)
+                        {
+                            Ok(egl_image) => egl_image,
+                            Err(err) => {
+                                return Err((Error::SurfaceTextureCreationFailed(err), surface))
+                            }
+                        };
+
+                        // Buffers from elsewhere may be in formats, like YUV, that can only be
+                        // sampled as external textures.
+                        let (texture_object, texture_target) = match surface.objects {
+                            SurfaceObjects::ImportedHardwareBuffer { .. } => (
+                                bind_egl_image_to_texture(
+                                    gl,
+                                    local_egl_image,
+                                    GL_TEXTURE_EXTERNAL_OES,
+                                    GL_TEXTURE_BINDING_EXTERNAL_OES,
+                                ),
+                                GL_TEXTURE_EXTERNAL_OES,
+                            ),
+                            SurfaceObjects::HardwareBuffer { layers, .. } if layers > 1 => (
+                                bind_egl_image_to_texture(
+                                    gl,
+                                    local_egl_image,
+                                    gl::TEXTURE_2D_ARRAY,
+                                    gl::TEXTURE_BINDING_2D_ARRAY,
+                                ),
+                                gl::TEXTURE_2D_ARRAY,
+                            ),
+                            _ => (
+                                generic::egl::surface::bind_egl_image_to_gl_texture(
+                                    gl,
+                                    local_egl_image,
+                                ),
+                                SURFACE_GL_TEXTURE_TARGET,
+                            ),
+                        };
+
+                        Ok(SurfaceTexture {
+                            surface,
+                            local_egl_image,
+                            texture_object,
+                            texture_target,
+                            phantom: PhantomData,
+                        })
                     })
-                }),
+                }
+            }
+        }
+    }
+
+    /// Creates a surface texture that samples from an existing hardware buffer, such as one
+    /// from a camera, a video decoder, or another process.
+    ///
+    /// The texture is bound to `GL_TEXTURE_EXTERNAL_OES`, which handles any format the buffer
+    /// may be in, YUV included, so shaders must sample it with a `samplerExternalOES`. Use
+    /// `surface_texture_binding_target()` and `surface_texture_shader_extension()` to find out what a
+    /// surface texture needs. The texture reflects later changes to the buffer's contents.
+    ///
+    /// The surface texture holds a reference to the buffer. Destroying the surface texture
+    /// returns a surface wrapping the buffer, which must then be destroyed with
+    /// `destroy_surface()` to drop that reference.
+    ///
+    /// This requires the `EGL_ANDROID_get_native_client_buffer` extension.
+    pub unsafe fn create_surface_texture_from_hardware_buffer(
+        &mut self,
+        context: &mut Context,
+        hardware_buffer: *mut AHardwareBuffer,
+    ) -> Result<SurfaceTexture, Error> {
+        if EGL_EXTENSION_FUNCTIONS
+            .GetNativeClientBufferANDROID
+            .is_none()
+        {
+            return Err(Error::RequiredExtensionUnavailable);
+        }
+
+        let mut hardware_buffer_desc = mem::zeroed();
+        AHardwareBuffer_describe(hardware_buffer, &mut hardware_buffer_desc);
+        let size = Size2D::new(
+            hardware_buffer_desc.width as i32,
+            hardware_buffer_desc.height as i32,
+        );
+
+        AHardwareBuffer_acquire(hardware_buffer);
+        let surface = Surface {
+            id: SurfaceID::next(),
+            context_id: context.id,
+            size,
+            objects: SurfaceObjects::ImportedHardwareBuffer { hardware_buffer },
+            transform: SurfaceTransform::Identity,
+            pre_rotation: false,
+            format: SurfaceFormat::RGBA8,
+            origin: SurfaceOrigin::BottomLeft,
+            alpha_mode: SurfaceAlphaMode::Premultiplied,
+            colorspace: Colorspace::SRGB,
+            samples: 1,
+            destroyed: false,
+        };
+        // The caller destroys the surface with `destroy_surface()`, which accounts for it as it
+        // would for any other.
+        self.note_surface_created(&surface);
+
+        match self.create_surface_texture(context, surface) {
+            Ok(surface_texture) => Ok(surface_texture),
+            Err((err, mut surface)) => {
+                self.destroy_surface(context, &mut surface)?;
+                Err(err)
             }
         }
     }
@@ -277,30 +949,547 @@ impl Device {
     ///
     /// The supplied context must match the context the surface was created with, or an
     /// `IncompatibleSurface` error is returned.
-    pub fn present_surface(&self, context: &Context, surface: &mut Surface) -> Result<(), Error> {
+    #[inline]
+    pub(crate) fn present_surface_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        self.present_surface_with_damage_impl(context, surface, &[])
+    }
+
+    /// Displays the contents of a widget surface on screen, hinting that only the given
+    /// rectangles have changed since the last present.
+    ///
+    /// The rectangles are in device pixels, with the origin at the lower left corner of the
+    /// surface. An empty slice means that the whole surface changed. The damage is passed to
+    /// `eglSwapBuffersWithDamageKHR()`, or to the transaction for surfaces presented with
+    /// `ASurfaceControl`, and SurfaceFlinger uses it to limit composition.
+    pub(crate) fn present_surface_with_damage_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
         if context.id != surface.context_id {
             return Err(Error::IncompatibleSurface);
         }
 
-        EGL_FUNCTIONS.with(|egl| unsafe {
-            match surface.objects {
-                SurfaceObjects::Window { egl_surface } => {
-                    egl.SwapBuffers(self.egl_display, egl_surface);
-                    Ok(())
+        match surface.objects {
+            SurfaceObjects::Window {
+                egl_surface,
+                native_window,
+            } => unsafe {
+                reapply_window_colorspace(native_window, surface.colorspace);
+                generic::egl::surface::swap_buffers_with_damage(
+                    self.egl_display,
+                    egl_surface,
+                    damage,
+                );
+                Ok(())
+            },
+            SurfaceObjects::SurfaceControl {
+                ref mut swapchain, ..
+            } => {
+                let _guard = self.temporarily_make_context_current(context)?;
+                GL_FUNCTIONS.with(|gl| unsafe { swapchain.present(gl, self.egl_display, damage) });
+                Ok(())
+            }
+            SurfaceObjects::HardwareBuffer { .. }
+            | SurfaceObjects::Suspended
+            | SurfaceObjects::ImportedHardwareBuffer { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Displays the `source` rectangle of a widget surface in the `destination` rectangle of its
+    /// widget, scaling it to fit.
+    ///
+    /// The rectangles are in device pixels, with the origin at the lower left corner. For surfaces
+    /// presented with `ASurfaceControl`, SurfaceFlinger crops and scales the buffer itself, and the
+    /// destination is in the coordinates of the window, before any pre-rotation. Otherwise, the
+    /// back buffer is rescaled in place before `eglSwapBuffers()`.
+    pub(crate) fn present_surface_with_viewport_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        source: &Rect<i32>,
+        destination: &Rect<i32>,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        match surface.objects {
+            SurfaceObjects::Window {
+                egl_surface,
+                native_window,
+            } => GL_FUNCTIONS.with(|gl| unsafe {
+                reapply_window_colorspace(native_window, surface.colorspace);
+                generic::egl::surface::swap_buffers_with_viewport(
+                    gl,
+                    self.egl_display,
+                    egl_surface,
+                    context.egl_context,
+                    source,
+                    destination,
+                )
+            }),
+            SurfaceObjects::SurfaceControl {
+                ref mut swapchain,
+                native_window,
+            } => {
+                if source.is_empty() || destination.is_empty() {
+                    return Err(Error::Failed);
                 }
-                SurfaceObjects::HardwareBuffer { .. } => Err(Error::NoWidgetAttached),
+
+                let window_size = unsafe {
+                    Size2D::new(
+                        ANativeWindow_getWidth(native_window),
+                        ANativeWindow_getHeight(native_window),
+                    )
+                };
+                swapchain.set_viewport(source, destination, &window_size);
+
+                let _guard = self.temporarily_make_context_current(context)?;
+                GL_FUNCTIONS.with(|gl| unsafe { swapchain.present(gl, self.egl_display, &[]) });
+                Ok(())
             }
-        })
+            SurfaceObjects::HardwareBuffer { .. }
+            | SurfaceObjects::Suspended
+            | SurfaceObjects::ImportedHardwareBuffer { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Declares which parts of a widget surface will be redrawn before it is next presented.
+    ///
+    /// This must be called while the surface is bound to the current context, before any
+    /// rendering to the frame. With `EGL_KHR_partial_update`, the contents of the surface outside
+    /// the region are undefined afterward, which spares tiled GPUs from loading them. An empty
+    /// slice means that the whole surface will be redrawn.
+    pub fn set_surface_damage_region(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        region: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        match surface.objects {
+            SurfaceObjects::Window { egl_surface, .. } => unsafe {
+                generic::egl::surface::set_damage_region(self.egl_display, egl_surface, region)
+            },
+            // The buffers belong to surfman, so their contents are always preserved.
+            SurfaceObjects::SurfaceControl { .. } => Ok(()),
+            SurfaceObjects::HardwareBuffer { .. }
+            | SurfaceObjects::Suspended
+            | SurfaceObjects::ImportedHardwareBuffer { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Sets the time at which the next frame presented to a widget surface should be shown.
+    ///
+    /// The time is in nanoseconds on the system monotonic clock (`CLOCK_MONOTONIC`), for example
+    /// the presentation timestamp of a video frame. The compositor holds the frame back until then,
+    /// rather than showing it at the next vertical sync. It applies only to the next present.
+    ///
+    /// For surfaces presented with `eglSwapBuffers()`, this requires
+    /// `EGL_ANDROID_presentation_time`; without it, `Error::RequiredExtensionUnavailable` is
+    /// returned.
+    pub fn set_presentation_time(&self, surface: &Surface, nanos: i64) -> Result<(), Error> {
+        match surface.objects {
+            SurfaceObjects::Window { egl_surface, .. } => unsafe {
+                let presentation_time = match EGL_EXTENSION_FUNCTIONS.PresentationTimeANDROID {
+                    Some(presentation_time)
+                        if egl_device::display_has_extension(
+                            self.egl_display,
+                            b"EGL_ANDROID_presentation_time",
+                        ) =>
+                    {
+                        presentation_time
+                    }
+                    _ => return Err(Error::RequiredExtensionUnavailable),
+                };
+                if presentation_time(self.egl_display, egl_surface, nanos) == egl::FALSE {
+                    return Err(Error::Failed);
+                }
+                Ok(())
+            },
+            SurfaceObjects::SurfaceControl { ref swapchain, .. } => {
+                swapchain.set_presentation_time(nanos);
+                Ok(())
+            }
+            SurfaceObjects::HardwareBuffer { .. }
+            | SurfaceObjects::Suspended
+            | SurfaceObjects::ImportedHardwareBuffer { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Switches a widget surface between double-buffered and single-buffered rendering.
+    ///
+    /// A single-buffered surface renders straight to the buffer on screen, which cuts a frame or
+    /// more of latency for things like stylus drawing and VR, at the cost of possible tearing.
+    /// The switch takes effect at the next present. While single-buffered, presenting the surface
+    /// just flushes the rendering so far to the display, and the contents of the surface are kept
+    /// from one frame to the next.
+    ///
+    /// This requires `EGL_KHR_mutable_render_buffer`, and a context whose config supports it,
+    /// which surfman prefers when the extension is available. If either is missing, this returns
+    /// `Error::RequiredExtensionUnavailable`. Surfaces presented with `ASurfaceControl` can't be
+    /// single-buffered, and return `Error::UnsupportedOnThisPlatform`.
+    pub fn set_surface_single_buffered(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        single_buffered: bool,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        let egl_surface = match surface.objects {
+            SurfaceObjects::Window { egl_surface, .. } => egl_surface,
+            SurfaceObjects::SurfaceControl { .. } => return Err(Error::UnsupportedOnThisPlatform),
+            SurfaceObjects::HardwareBuffer { .. }
+            | SurfaceObjects::Suspended
+            | SurfaceObjects::ImportedHardwareBuffer { .. } => return Err(Error::NoWidgetAttached),
+        };
+
+        unsafe {
+            if !egl_device::display_has_extension(
+                self.egl_display,
+                b"EGL_KHR_mutable_render_buffer",
+            ) {
+                return Err(Error::RequiredExtensionUnavailable);
+            }
+
+            let egl_config = self.context_to_egl_config(context);
+            let surface_type = generic::egl::context::get_config_attr(
+                self.egl_display,
+                egl_config,
+                egl::SURFACE_TYPE as EGLint,
+            );
+            if surface_type & EGL_MUTABLE_RENDER_BUFFER_BIT_KHR == 0 {
+                return Err(Error::RequiredExtensionUnavailable);
+            }
+
+            let render_buffer = if single_buffered {
+                egl::SINGLE_BUFFER
+            } else {
+                egl::BACK_BUFFER
+            };
+            EGL_FUNCTIONS.with(|egl| {
+                if egl.SurfaceAttrib(
+                    self.egl_display,
+                    egl_surface,
+                    egl::RENDER_BUFFER as EGLint,
+                    render_buffer as EGLint,
+                ) == egl::FALSE
+                {
+                    return Err(Error::Failed);
+                }
+                Ok(())
+            })
+        }
+    }
+
+    /// Returns the number of presents ago that the current back buffer of a widget surface was
+    /// displayed, or 0 if its contents are undefined.
+    ///
+    /// The surface must be bound to the current context.
+    pub fn surface_buffer_age(&self, context: &Context, surface: &Surface) -> Result<u32, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        match surface.objects {
+            SurfaceObjects::Window { egl_surface, .. } => unsafe {
+                Ok(generic::egl::surface::query_buffer_age(
+                    self.egl_display,
+                    egl_surface,
+                ))
+            },
+            SurfaceObjects::SurfaceControl { ref swapchain, .. } => Ok(swapchain.buffer_age()),
+            SurfaceObjects::HardwareBuffer { .. }
+            | SurfaceObjects::Suspended
+            | SurfaceObjects::ImportedHardwareBuffer { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Sets whether presenting a widget surface preserves its contents.
+    ///
+    /// Widget surfaces are created without preservation, so their contents are undefined after
+    /// `present_surface()`. With it, applications that only repaint the dirty regions of each
+    /// frame can draw over the previous one, at the cost of a copy per present on some hardware.
+    ///
+    /// If the config of the context doesn't allow preservation, this returns
+    /// `Error::UnsupportedOnThisPlatform`, as it does for surfaces presented with `ASurfaceControl`,
+    /// whose buffers rotate through SurfaceFlinger.
+    pub fn set_surface_preserve_buffer(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        preserve: bool,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        match surface.objects {
+            SurfaceObjects::Window { egl_surface, .. } => unsafe {
+                generic::egl::surface::set_swap_behavior(self.egl_display, egl_surface, preserve)
+            },
+            SurfaceObjects::SurfaceControl { .. } => Err(Error::UnsupportedOnThisPlatform),
+            SurfaceObjects::HardwareBuffer { .. }
+            | SurfaceObjects::Suspended
+            | SurfaceObjects::ImportedHardwareBuffer { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Sets which way up the contents of a surface are.
+    ///
+    /// Surfaces presented with `ASurfaceControl` are flipped by SurfaceFlinger, starting with the
+    /// next present. Surfaces presented with `eglSwapBuffers()` can only be bottom-left, and
+    /// setting `SurfaceOrigin::TopLeft` on them returns `Error::UnsupportedOnThisPlatform`. A
+    /// suspended surface keeps its origin only if it is resumed with `ASurfaceControl`.
+    pub fn set_surface_origin(
+        &self,
+        surface: &mut Surface,
+        origin: SurfaceOrigin,
+    ) -> Result<(), Error> {
+        match surface.objects {
+            SurfaceObjects::SurfaceControl {
+                ref mut swapchain, ..
+            } => swapchain.set_top_down(origin == SurfaceOrigin::TopLeft),
+            SurfaceObjects::Window { .. } if origin == SurfaceOrigin::TopLeft => {
+                return Err(Error::UnsupportedOnThisPlatform)
+            }
+            _ => {}
+        }
+        surface.origin = origin;
+        Ok(())
+    }
+
+    /// Sets the color space that the contents of a surface are in.
+    ///
+    /// Widget surfaces tag their buffers with the matching data space, so that SurfaceFlinger
+    /// converts their contents for the display. Windows presented with `eglSwapBuffers()` can only
+    /// be tagged from Android 9 (API 28) on; before that, setting any color space but `SRGB` on
+    /// them returns `Error::UnsupportedOnThisPlatform`.
+    pub fn set_surface_colorspace(
+        &self,
+        surface: &mut Surface,
+        colorspace: Colorspace,
+    ) -> Result<(), Error> {
+        match surface.objects {
+            SurfaceObjects::SurfaceControl {
+                ref mut swapchain, ..
+            } => swapchain.set_dataspace(dataspace(colorspace)),
+            SurfaceObjects::Window { native_window, .. } => unsafe {
+                if !set_window_colorspace(native_window, colorspace)
+                    && colorspace != Colorspace::SRGB
+                {
+                    return Err(Error::UnsupportedOnThisPlatform);
+                }
+            },
+            _ => {}
+        }
+        surface.colorspace = colorspace;
+        Ok(())
     }
 
     /// Resizes a widget surface.
-    pub fn resize_surface(
+    ///
+    /// Surfaces presented with `eglSwapBuffers()` always match the size of their window, so the
+    /// size argument is ignored for them: the dimensions of the window are queried again, and the
+    /// `EGLSurface` is recreated if they have changed. Surfaces presented with `ASurfaceControl`
+    /// reallocate their buffers at the given size.
+    ///
+    /// Widget surfaces that were asked to pre-rotate their contents also pick up the rotation of
+    /// the display again, and are reallocated in its orientation. The size reported in
+    /// `SurfaceInfo` may then have its width and height swapped relative to the widget.
+    ///
+    /// If a new `EGLSurface` can't be created, the surface is left suspended, as if by
+    /// `suspend_widget_surface()`.
+    pub(crate) fn resize_surface_impl(
         &self,
-        _context: &Context,
+        context: &Context,
         surface: &mut Surface,
         size: Size2D<i32>,
     ) -> Result<(), Error> {
-        surface.size = size;
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        match surface.objects {
+            SurfaceObjects::Window {
+                egl_surface,
+                native_window,
+            } => unsafe {
+                let native_widget = NativeWidget {
+                    native_window,
+                    surface_control: false,
+                    pre_rotation: surface.pre_rotation,
+                };
+                let (window_size, transform) = widget_size_and_transform(&native_widget);
+                if window_size == surface.size && transform == surface.transform {
+                    return Ok(());
+                }
+
+                // There can only be one `EGLSurface` for a window at a time.
+                EGL_FUNCTIONS.with(|egl| egl.DestroySurface(self.egl_display, egl_surface));
+                surface.objects = SurfaceObjects::Suspended;
+
+                let (egl_surface, size, transform) =
+                    self.create_egl_window_surface(context, &native_widget, surface.alpha_mode)?;
+                surface.objects = SurfaceObjects::Window {
+                    egl_surface,
+                    native_window,
+                };
+                surface.size = size;
+                surface.transform = transform;
+                Ok(())
+            },
+            SurfaceObjects::SurfaceControl {
+                ref mut swapchain,
+                native_window,
+            } => {
+                let transform = if surface.pre_rotation {
+                    unsafe { query_transform_hint(native_window) }
+                } else {
+                    SurfaceTransform::Identity
+                };
+                let size = if transform.swaps_dimensions() {
+                    Size2D::new(size.height, size.width)
+                } else {
+                    size
+                };
+
+                if size != surface.size || transform != surface.transform {
+                    let _guard = self.temporarily_make_context_current(context)?;
+                    let context_descriptor = self.context_descriptor(context);
+                    let context_attributes =
+                        self.context_descriptor_attributes(&context_descriptor);
+                    GL_FUNCTIONS.with(|gl| unsafe {
+                        let renderbuffers = Renderbuffers::new(gl, &size, &context_attributes);
+                        swapchain.resize(
+                            gl,
+                            self.egl_display,
+                            &size,
+                            native_transform(transform.inverse()),
+                            renderbuffers,
+                            |size| self.create_swapchain_buffer(context, gl, size),
+                        )
+                    })?;
+                }
+
+                surface.size = size;
+                surface.transform = transform;
+                Ok(())
+            }
+            SurfaceObjects::HardwareBuffer { .. } => {
+                surface.size = size;
+                Ok(())
+            }
+            SurfaceObjects::Suspended | SurfaceObjects::ImportedHardwareBuffer { .. } => {
+                Err(Error::NoWidgetAttached)
+            }
+        }
+    }
+
+    /// Releases the native window of a widget surface, keeping the surface itself alive.
+    ///
+    /// Android destroys the window of an activity when it goes to the background, at which point
+    /// the `EGLSurface` or `ASurfaceControl` made from it must be gone, but the context and its
+    /// resources can survive. Call this from `onNativeWindowDestroyed` (or `surfaceDestroyed`),
+    /// and `resume_widget_surface()` once the new window arrives. In between, presenting the
+    /// surface returns `Error::NoWidgetAttached`, and binding it to a context renders nowhere.
+    ///
+    /// The supplied context must be the context the surface is associated with, or this returns
+    /// an `IncompatibleSurface` error. If the surface isn't an active widget surface, this returns
+    /// `Error::NoWidgetAttached`.
+    pub fn suspend_widget_surface(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        match surface.objects {
+            SurfaceObjects::Window { egl_surface, .. } => EGL_FUNCTIONS.with(|egl| unsafe {
+                egl.DestroySurface(self.egl_display, egl_surface);
+            }),
+            SurfaceObjects::SurfaceControl {
+                ref mut swapchain, ..
+            } => {
+                let _guard = self.temporarily_make_context_current(context)?;
+                GL_FUNCTIONS.with(|gl| unsafe { swapchain.destroy(gl, self.egl_display) });
+            }
+            SurfaceObjects::HardwareBuffer { .. }
+            | SurfaceObjects::Suspended
+            | SurfaceObjects::ImportedHardwareBuffer { .. } => return Err(Error::NoWidgetAttached),
+        }
+
+        surface.objects = SurfaceObjects::Suspended;
+        Ok(())
+    }
+
+    /// Attaches a widget surface that was suspended with `suspend_widget_surface()` to a new
+    /// native window, typically the one passed to `onNativeWindowCreated`.
+    ///
+    /// The surface takes on the size of the new window, and the presentation options of the new
+    /// native widget. Its contents are undefined until it is next rendered to.
+    ///
+    /// The supplied context must be the context the surface is associated with, or this returns
+    /// an `IncompatibleSurface` error. If the surface isn't suspended, this returns
+    /// `Error::WidgetAttached`.
+    pub fn resume_widget_surface(
+        &mut self,
+        context: &Context,
+        surface: &mut Surface,
+        native_widget: NativeWidget,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        match surface.objects {
+            SurfaceObjects::Suspended => {}
+            _ => return Err(Error::WidgetAttached),
+        }
+
+        let mut new_surface = unsafe {
+            if native_widget.surface_control && surface_control::is_available() {
+                self.create_surface_control_surface(context, &native_widget, surface.alpha_mode)?
+            } else {
+                self.create_window_surface(context, &native_widget, surface.alpha_mode)?
+            }
+        };
+
+        surface.size = new_surface.size;
+        surface.transform = new_surface.transform;
+        surface.pre_rotation = new_surface.pre_rotation;
+        surface.objects = mem::replace(&mut new_surface.objects, SurfaceObjects::Suspended);
+        new_surface.destroyed = true;
+
+        // Windows presented with `eglSwapBuffers()` can't be flipped.
+        match surface.objects {
+            SurfaceObjects::SurfaceControl {
+                ref mut swapchain, ..
+            } => {
+                swapchain.set_top_down(surface.origin == SurfaceOrigin::TopLeft);
+                swapchain.set_dataspace(dataspace(surface.colorspace));
+            }
+            SurfaceObjects::Window { native_window, .. } => unsafe {
+                surface.origin = SurfaceOrigin::BottomLeft;
+                if !set_window_colorspace(native_window, surface.colorspace) {
+                    surface.colorspace = Colorspace::SRGB;
+                }
+            },
+            _ => surface.origin = SurfaceOrigin::BottomLeft,
+        }
         Ok(())
     }
 
@@ -309,7 +1498,7 @@ impl Device {
         &self,
         _: &Context,
         hardware_buffer: *mut AHardwareBuffer,
-    ) -> EGLImageKHR {
+    ) -> Result<EGLImageKHR, WindowingApiError> {
         // Get the native client buffer.
         let eglGetNativeClientBufferANDROID =
             EGL_EXTENSION_FUNCTIONS.GetNativeClientBufferANDROID.expect(
@@ -318,7 +1507,9 @@ impl Device {
             );
         let client_buffer =
             eglGetNativeClientBufferANDROID(hardware_buffer as *const AHardwareBuffer as *const _);
-        assert!(!client_buffer.is_null());
+        if client_buffer.is_null() {
+            return Err(WindowingApiError::BadParameter);
+        }
 
         // Create the EGL image.
         let egl_image_attributes = [
@@ -334,8 +1525,10 @@ impl Device {
             client_buffer,
             egl_image_attributes.as_ptr(),
         );
-        assert_ne!(egl_image, EGL_NO_IMAGE_KHR);
-        egl_image
+        if egl_image == EGL_NO_IMAGE_KHR {
+            return Err(EGL_FUNCTIONS.with(|egl| egl.GetError().to_windowing_api_error()));
+        }
+        Ok(egl_image)
     }
 
     /// Destroys a surface.
@@ -345,9 +1538,9 @@ impl Device {
     ///
     /// You must explicitly call this method to dispose of a surface. Otherwise, a panic occurs in
     /// the `drop` method.
-    pub fn destroy_surface(
+    pub(crate) fn destroy_surface_impl(
         &self,
-        context: &mut Context,
+        context: &Context,
         surface: &mut Surface,
     ) -> Result<(), Error> {
         if context.id != surface.context_id {
@@ -362,10 +1555,10 @@ impl Device {
                     ref mut framebuffer_object,
                     ref mut texture_object,
                     ref mut renderbuffers,
+                    ..
                 } => {
                     GL_FUNCTIONS.with(|gl| {
-                        gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
-                        gl.DeleteFramebuffers(1, framebuffer_object);
+                        gl_utils::destroy_framebuffer(gl, *framebuffer_object);
                         *framebuffer_object = 0;
 
                         renderbuffers.destroy(gl);
@@ -385,10 +1578,21 @@ impl Device {
                 }
                 SurfaceObjects::Window {
                     ref mut egl_surface,
+                    ..
                 } => EGL_FUNCTIONS.with(|egl| {
                     egl.DestroySurface(self.egl_display, *egl_surface);
                     *egl_surface = egl::NO_SURFACE;
                 }),
+                SurfaceObjects::SurfaceControl {
+                    ref mut swapchain, ..
+                } => GL_FUNCTIONS.with(|gl| swapchain.destroy(gl, self.egl_display)),
+                SurfaceObjects::ImportedHardwareBuffer {
+                    ref mut hardware_buffer,
+                } => {
+                    AHardwareBuffer_release(*hardware_buffer);
+                    *hardware_buffer = ptr::null_mut();
+                }
+                SurfaceObjects::Suspended => {}
             }
         }
 
@@ -427,6 +1631,37 @@ impl Device {
         })
     }
 
+    /// Destroys the native resources of a surface given up with `surface_into_destroy_token()`.
+    ///
+    /// Unlike `destroy_surface()`, this doesn't need the context that the surface belonged to.
+    pub fn destroy_token(&self, mut token: SurfaceDestroyToken) -> Result<(), Error> {
+        unsafe {
+            match token.objects {
+                SurfaceObjects::HardwareBuffer {
+                    hardware_buffer,
+                    egl_image,
+                    ..
+                } => {
+                    let result =
+                        (EGL_EXTENSION_FUNCTIONS.DestroyImageKHR)(self.egl_display, egl_image);
+                    assert_ne!(result, egl::FALSE);
+                    AHardwareBuffer_release(hardware_buffer);
+                }
+                SurfaceObjects::Window { egl_surface, .. } => EGL_FUNCTIONS.with(|egl| {
+                    egl.DestroySurface(self.egl_display, egl_surface);
+                }),
+                SurfaceObjects::SurfaceControl {
+                    ref mut swapchain, ..
+                } => swapchain.release(self.egl_display),
+                SurfaceObjects::ImportedHardwareBuffer { hardware_buffer } => {
+                    AHardwareBuffer_release(hardware_buffer)
+                }
+                SurfaceObjects::Suspended => {}
+            }
+        }
+        Ok(())
+    }
+
     /// Returns a pointer to the underlying surface data for reading or writing by the CPU.
     #[inline]
     pub fn lock_surface_data<'s>(&self, _: &'s mut Surface) -> Result<SurfaceDataGuard<'s>, Error> {
@@ -442,6 +1677,77 @@ impl Device {
         SURFACE_GL_TEXTURE_TARGET
     }
 
+    /// Fills in the mip chain of a surface texture from the current contents of its surface, so
+    /// that it can be sampled with mipmapping at scales well below its size.
+    ///
+    /// The mip chain is kept in the surface's hardware buffer, so the surface must have been
+    /// created with `SurfaceUsage::MIPMAPPED`. For other surfaces, and for surface textures of
+    /// imported hardware buffers, which are external textures, this returns an
+    /// `UnsupportedOnThisPlatform` error.
+    pub fn generate_surface_mipmaps(
+        &self,
+        context: &mut Context,
+        surface_texture: &SurfaceTexture,
+    ) -> Result<(), Error> {
+        if surface_texture.texture_target != gl::TEXTURE_2D {
+            return Err(Error::UnsupportedOnThisPlatform);
+        }
+        match surface_texture.surface.objects {
+            SurfaceObjects::HardwareBuffer {
+                hardware_buffer, ..
+            } => unsafe {
+                let mut hardware_buffer_desc: AHardwareBuffer_Desc = mem::zeroed();
+                AHardwareBuffer_describe(hardware_buffer, &mut hardware_buffer_desc);
+                if hardware_buffer_desc.usage & AHARDWAREBUFFER_USAGE_GPU_MIPMAP_COMPLETE == 0 {
+                    return Err(Error::UnsupportedOnThisPlatform);
+                }
+            },
+            _ => return Err(Error::UnsupportedOnThisPlatform),
+        }
+
+        let _guard = self.temporarily_make_context_current(context)?;
+        GL_FUNCTIONS.with(|gl| {
+            gl_utils::generate_mipmaps(gl, surface_texture.texture_object);
+            Ok(())
+        })
+    }
+
+    /// Returns the OpenGL texture target that the given surface texture is bound to.
+    ///
+    /// This is `GL_TEXTURE_EXTERNAL_OES` for surface textures made with
+    /// `create_surface_texture_from_hardware_buffer()`, `GL_TEXTURE_2D_ARRAY` for those of layered
+    /// surfaces, and `surface_gl_texture_target()` otherwise.
+    #[inline]
+    pub fn surface_texture_binding_target(&self, surface_texture: &SurfaceTexture) -> GLenum {
+        surface_texture.texture_target
+    }
+
+    /// Returns the number of layers of the surface that a surface texture wraps.
+    ///
+    /// This is 1 for all surface textures except those of surfaces created with
+    /// `create_layered_surface()`.
+    #[inline]
+    pub fn surface_texture_layers(&self, surface_texture: &SurfaceTexture) -> u32 {
+        self.surface_layers(&surface_texture.surface)
+    }
+
+    /// Returns the GLSL extension that shaders must enable to sample from the given surface
+    /// texture, if any.
+    ///
+    /// For external textures, this is `GL_OES_EGL_image_external_essl3`, for shaders written in
+    /// GLSL ES 3.00; shaders written in GLSL ES 1.00 enable `GL_OES_EGL_image_external` instead.
+    #[inline]
+    pub fn surface_texture_shader_extension(
+        &self,
+        surface_texture: &SurfaceTexture,
+    ) -> Option<&'static str> {
+        if surface_texture.texture_target == GL_TEXTURE_EXTERNAL_OES {
+            Some("GL_OES_EGL_image_external_essl3")
+        } else {
+            None
+        }
+    }
+
     /// Returns various information about the surface, including the framebuffer object needed to
     /// render to this surface.
     ///
@@ -457,11 +1763,124 @@ impl Device {
                 SurfaceObjects::HardwareBuffer {
                     framebuffer_object, ..
                 } => framebuffer_object,
-                SurfaceObjects::Window { .. } => 0,
+                SurfaceObjects::SurfaceControl { ref swapchain, .. } => {
+                    swapchain.framebuffer_object
+                }
+                SurfaceObjects::Window { .. }
+                | SurfaceObjects::Suspended
+                | SurfaceObjects::ImportedHardwareBuffer { .. } => 0,
+            },
+            scale_factor: 1.0,
+            transform: surface.transform,
+            origin: surface.origin,
+            format: surface.format,
+            alpha_mode: surface.alpha_mode,
+            colorspace: surface.colorspace,
+            samples: surface.samples,
+            bound: false,
+        }
+    }
+
+    /// Returns the GPU memory, in bytes, held by the given surface.
+    ///
+    /// For surfaces backed by hardware buffers, this is computed from the buffer's stride, height,
+    /// and layer count as reported by `AHardwareBuffer_describe()`. The buffers of window surfaces
+    /// belong to the system, so for those this is an estimate that counts four bytes per pixel of
+    /// color storage.
+    pub fn surface_memory_usage(&self, surface: &Surface) -> usize {
+        match surface.objects {
+            SurfaceObjects::HardwareBuffer {
+                hardware_buffer, ..
+            }
+            | SurfaceObjects::ImportedHardwareBuffer { hardware_buffer } => unsafe {
+                let mut hardware_buffer_desc: AHardwareBuffer_Desc = mem::zeroed();
+                AHardwareBuffer_describe(hardware_buffer, &mut hardware_buffer_desc);
+                hardware_buffer_desc.stride as usize
+                    * hardware_buffer_desc.height as usize
+                    * hardware_buffer_desc.layers as usize
+                    * 4
             },
+            SurfaceObjects::Window { .. }
+            | SurfaceObjects::SurfaceControl { .. }
+            | SurfaceObjects::Suspended => estimate_memory_usage(&surface.size),
         }
     }
 
+    /// Returns `Error::UnsupportedOnThisPlatform`, as hardware buffers have no modification
+    /// counter.
+    #[inline]
+    pub fn surface_seed(&self, _: &Surface) -> Result<u32, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Returns the stride, row alignment, and total size of the memory that a generic surface of
+    /// the given size and access would be allocated with, without creating one.
+    ///
+    /// Gralloc only reveals the stride it picks once a buffer exists, so this allocates a
+    /// throwaway hardware buffer with the same usage as a generic surface and describes it. The
+    /// row alignment is the largest power of two, up to a page, that the stride is a multiple of.
+    pub fn surface_layout_requirements(
+        &self,
+        size: &Size2D<i32>,
+        _: SurfaceFormat,
+        access: SurfaceAccess,
+    ) -> Result<SurfaceLayoutRequirements, Error> {
+        let usage = HardwareBufferUsage::from(access) | SurfaceUsage::default().into();
+        unsafe {
+            let hardware_buffer =
+                allocate_hardware_buffer(size, 1, usage | HardwareBufferUsage::GPU_SAMPLED_IMAGE)?;
+            let mut hardware_buffer_desc: AHardwareBuffer_Desc = mem::zeroed();
+            AHardwareBuffer_describe(hardware_buffer, &mut hardware_buffer_desc);
+            AHardwareBuffer_release(hardware_buffer);
+
+            let stride = hardware_buffer_desc.stride as usize * 4;
+            Ok(SurfaceLayoutRequirements {
+                stride,
+                alignment: 1 << stride.trailing_zeros().min(12),
+                size: stride * hardware_buffer_desc.height as usize,
+            })
+        }
+    }
+
+    /// Returns timing information about the most recently displayed frame of a widget surface.
+    ///
+    /// Presentation timing isn't available on this backend, so this always returns `None`.
+    #[inline]
+    pub fn surface_presentation_statistics(&self, _: &Surface) -> Option<PresentationStatistics> {
+        None
+    }
+
+    /// Returns whether a widget surface can currently be seen on screen.
+    ///
+    /// Android doesn't report whether a native window is visible, so this always returns
+    /// `Unknown`. Stop rendering while the activity is stopped instead.
+    #[inline]
+    pub fn surface_visibility(&self, _: &Surface) -> SurfaceVisibility {
+        SurfaceVisibility::Unknown
+    }
+
+    /// Returns what a widget surface is shown on.
+    ///
+    /// Android only describes its displays through the Java `Display` API, so the display, its
+    /// color space, and its HDR capability aren't reported here. The bit depth is that of the
+    /// window's buffers, which is what reaches SurfaceFlinger.
+    pub fn widget_output_info(&self, surface: &Surface) -> Result<WidgetOutputInfo, Error> {
+        let mut output_info = WidgetOutputInfo::unknown(None);
+        match surface.objects {
+            SurfaceObjects::Window { native_window, .. } => unsafe {
+                output_info.bits_per_component = match ANativeWindow_getFormat(native_window) {
+                    AHARDWAREBUFFER_FORMAT_R10G10B10A2_UNORM => 10,
+                    AHARDWAREBUFFER_FORMAT_R16G16B16A16_FLOAT => 16,
+                    _ => 8,
+                };
+            },
+            SurfaceObjects::SurfaceControl { .. } | SurfaceObjects::Suspended => {}
+            SurfaceObjects::HardwareBuffer { .. }
+            | SurfaceObjects::ImportedHardwareBuffer { .. } => return Err(Error::NoWidgetAttached),
+        }
+        Ok(output_info)
+    }
+
     /// Returns the OpenGL texture object containing the contents of this surface.
     ///
     /// It is only legal to read from, not write to, this texture object.
@@ -475,20 +1894,271 @@ impl NativeWidget {
     /// Creates a native widget type from an Android `NativeWindow`.
     #[inline]
     pub unsafe fn from_native_window(native_window: *mut ANativeWindow) -> NativeWidget {
-        NativeWidget { native_window }
+        NativeWidget {
+            native_window,
+            surface_control: false,
+            pre_rotation: false,
+        }
     }
 }
 
 impl Surface {
     fn id(&self) -> SurfaceID {
-        match self.objects {
-            SurfaceObjects::HardwareBuffer { egl_image, .. } => SurfaceID(egl_image as usize),
-            SurfaceObjects::Window { egl_surface } => SurfaceID(egl_surface as usize),
+        self.id
+    }
+
+    /// Gives up this surface, returning a token that can be destroyed later with
+    /// `Device::destroy_token()`, on any thread and without the surface's context.
+    ///
+    /// The OpenGL objects that the surface owns in its context, such as its framebuffer, aren't
+    /// part of the token. They're freed when the context is destroyed.
+    pub(crate) fn into_destroy_token(mut self) -> SurfaceDestroyToken {
+        self.destroyed = true;
+        let mut objects = mem::replace(&mut self.objects, SurfaceObjects::Suspended);
+        match objects {
+            SurfaceObjects::HardwareBuffer {
+                ref mut framebuffer_object,
+                ref mut texture_object,
+                ref mut renderbuffers,
+                ..
+            } => {
+                *framebuffer_object = 0;
+                *texture_object = 0;
+                renderbuffers.abandon();
+            }
+            SurfaceObjects::SurfaceControl {
+                ref mut swapchain, ..
+            } => swapchain.abandon_gl_objects(),
+            SurfaceObjects::Window { .. }
+            | SurfaceObjects::Suspended
+            | SurfaceObjects::ImportedHardwareBuffer { .. } => {}
         }
+        SurfaceDestroyToken { objects }
     }
 }
 
+/// The native resources of a surface given up with `Device::surface_into_destroy_token()`, which
+/// can be destroyed later without the surface's context.
+///
+/// Tokens can be sent to other threads, and must be destroyed with `Device::destroy_token()`, or
+/// their resources leak.
+pub struct SurfaceDestroyToken {
+    objects: SurfaceObjects,
+}
+
+unsafe impl Send for SurfaceDestroyToken {}
+
 /// Represents the CPU view of the pixel data of this surface.
 pub struct SurfaceDataGuard<'a> {
     phantom: PhantomData<&'a ()>,
 }
+
+// Like `bind_egl_image_to_gl_texture()`, but for other texture targets, such as
+// `GL_TEXTURE_EXTERNAL_OES` or `GL_TEXTURE_2D_ARRAY`.
+unsafe fn bind_egl_image_to_texture(
+    gl: &Gl,
+    egl_image: EGLImageKHR,
+    texture_target: GLenum,
+    texture_binding_target: GLenum,
+) -> GLuint {
+    let mut texture = 0;
+    gl.GenTextures(1, &mut texture);
+    debug_assert_ne!(texture, 0);
+
+    let mut bindings =
+        gl_utils::BindingsGuard::for_texture(gl, texture_target, texture_binding_target);
+    bindings.bind_texture(texture);
+    (EGL_EXTENSION_FUNCTIONS.ImageTargetTexture2DOES)(texture_target, egl_image);
+    gl.TexParameteri(texture_target, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+    gl.TexParameteri(texture_target, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
+    gl.TexParameteri(
+        texture_target,
+        gl::TEXTURE_WRAP_S,
+        gl::CLAMP_TO_EDGE as GLint,
+    );
+    gl.TexParameteri(
+        texture_target,
+        gl::TEXTURE_WRAP_T,
+        gl::CLAMP_TO_EDGE as GLint,
+    );
+    drop(bindings);
+
+    debug_assert_eq!(gl.GetError(), gl::NO_ERROR);
+    texture
+}
+
+// `ANativeWindow_query()` isn't part of the NDK, but libnativewindow exports it, and it's the only
+// way to learn the transform hint of a window.
+lazy_static! {
+    static ref NATIVE_WINDOW_QUERY: Option<
+        unsafe extern "C" fn(window: *const ANativeWindow, what: c_int, value: *mut c_int) -> c_int,
+    > = unsafe {
+        let library = libc::dlopen(
+            b"libnativewindow.so\0".as_ptr() as *const c_char,
+            libc::RTLD_LAZY,
+        );
+        if library.is_null() {
+            return None;
+        }
+        let symbol = libc::dlsym(library, b"ANativeWindow_query\0".as_ptr() as *const c_char);
+        if symbol.is_null() {
+            None
+        } else {
+            Some(mem::transmute(symbol))
+        }
+    };
+}
+
+// `ANativeWindow_setBuffersDataSpace()` only exists from API 28 on.
+type SetBuffersDataSpaceFn =
+    unsafe extern "C" fn(window: *mut ANativeWindow, dataspace: i32) -> i32;
+
+lazy_static! {
+    static ref NATIVE_WINDOW_SET_BUFFERS_DATA_SPACE: Option<SetBuffersDataSpaceFn> = unsafe {
+        let library = libc::dlopen(
+            b"libnativewindow.so\0".as_ptr() as *const c_char,
+            libc::RTLD_LAZY,
+        );
+        if library.is_null() {
+            return None;
+        }
+        let symbol = libc::dlsym(
+            library,
+            b"ANativeWindow_setBuffersDataSpace\0".as_ptr() as *const c_char,
+        );
+        if symbol.is_null() {
+            None
+        } else {
+            Some(mem::transmute(symbol))
+        }
+    };
+}
+
+// Returns the `ADATASPACE_*` value for a color space.
+fn dataspace(colorspace: Colorspace) -> i32 {
+    match colorspace {
+        Colorspace::SRGB => ADATASPACE_SRGB,
+        Colorspace::DisplayP3 => ADATASPACE_DISPLAY_P3,
+        Colorspace::BT2020 => ADATASPACE_BT2020_PQ,
+    }
+}
+
+// Tags the buffers that EGL queues to the window with the color space. Returns false if this
+// release of Android can't.
+unsafe fn set_window_colorspace(native_window: *mut ANativeWindow, colorspace: Colorspace) -> bool {
+    match *NATIVE_WINDOW_SET_BUFFERS_DATA_SPACE {
+        Some(set_buffers_data_space) => {
+            set_buffers_data_space(native_window, dataspace(colorspace)) == 0
+        }
+        None => false,
+    }
+}
+
+// EGL sets the data space of the window itself when it creates the `EGLSurface`, so wide-gamut
+// windows are tagged again before every swap in case the `EGLSurface` was recreated since.
+unsafe fn reapply_window_colorspace(native_window: *mut ANativeWindow, colorspace: Colorspace) {
+    if colorspace != Colorspace::SRGB {
+        set_window_colorspace(native_window, colorspace);
+    }
+}
+
+// Returns the rotation that SurfaceFlinger would apply to the buffers of the window.
+unsafe fn query_transform_hint(native_window: *mut ANativeWindow) -> SurfaceTransform {
+    let query = match *NATIVE_WINDOW_QUERY {
+        Some(query) => query,
+        None => return SurfaceTransform::Identity,
+    };
+    let mut transform_hint = ANATIVEWINDOW_TRANSFORM_IDENTITY;
+    if query(
+        native_window,
+        NATIVE_WINDOW_TRANSFORM_HINT,
+        &mut transform_hint,
+    ) != 0
+    {
+        return SurfaceTransform::Identity;
+    }
+    match transform_hint {
+        ANATIVEWINDOW_TRANSFORM_ROTATE_90 => SurfaceTransform::Rotate90,
+        ANATIVEWINDOW_TRANSFORM_ROTATE_180 => SurfaceTransform::Rotate180,
+        ANATIVEWINDOW_TRANSFORM_ROTATE_270 => SurfaceTransform::Rotate270,
+        // Flips aren't used for display rotation.
+        _ => SurfaceTransform::Identity,
+    }
+}
+
+fn native_transform(transform: SurfaceTransform) -> i32 {
+    match transform {
+        SurfaceTransform::Identity => ANATIVEWINDOW_TRANSFORM_IDENTITY,
+        SurfaceTransform::Rotate90 => ANATIVEWINDOW_TRANSFORM_ROTATE_90,
+        SurfaceTransform::Rotate180 => ANATIVEWINDOW_TRANSFORM_ROTATE_180,
+        SurfaceTransform::Rotate270 => ANATIVEWINDOW_TRANSFORM_ROTATE_270,
+    }
+}
+
+// Returns the size of the buffers for a widget surface, and the rotation that rendering to them
+// must apply, taking pre-rotation into account.
+unsafe fn widget_size_and_transform(
+    native_widget: &NativeWidget,
+) -> (Size2D<i32>, SurfaceTransform) {
+    let native_window = native_widget.native_window;
+    let size = Size2D::new(
+        ANativeWindow_getWidth(native_window),
+        ANativeWindow_getHeight(native_window),
+    );
+    if !native_widget.pre_rotation {
+        return (size, SurfaceTransform::Identity);
+    }
+    let transform = query_transform_hint(native_window);
+    if transform.swaps_dimensions() {
+        (Size2D::new(size.height, size.width), transform)
+    } else {
+        (size, transform)
+    }
+}
+
+// Allocates an RGBA hardware buffer with the given number of layers that the GPU can render to,
+// with the given extra usage.
+unsafe fn allocate_hardware_buffer(
+    size: &Size2D<i32>,
+    layers: u32,
+    usage: HardwareBufferUsage,
+) -> Result<*mut AHardwareBuffer, Error> {
+    let hardware_buffer_desc = AHardwareBuffer_Desc {
+        format: AHARDWAREBUFFER_FORMAT_R8G8B8A8_UNORM,
+        height: size.height as u32,
+        width: size.width as u32,
+        layers,
+        rfu0: 0,
+        rfu1: 0,
+        stride: 10,
+        usage: (usage | HardwareBufferUsage::GPU_FRAMEBUFFER).bits(),
+    };
+    let mut hardware_buffer = ptr::null_mut();
+    let result = AHardwareBuffer_allocate(&hardware_buffer_desc, &mut hardware_buffer);
+    if result != 0 {
+        return Err(Error::SurfaceCreationFailed(WindowingApiError::Failed));
+    }
+    Ok(hardware_buffer)
+}
+
+#[cfg(feature = "sm-opencl")]
+impl Device {
+    /// Returns the EGL image holding the contents of the given surface, for OpenCL to wrap with
+    /// `cl_khr_egl_image`.
+    ///
+    /// The image is owned by the surface and is destroyed along with it. Widget surfaces have no
+    /// EGL image; this returns `Error::WidgetAttached` for them, and
+    /// imported hardware buffers have none either.
+    pub fn surface_egl_image(&self, surface: &Surface) -> Result<SharedEGLImage, Error> {
+        match surface.objects {
+            SurfaceObjects::HardwareBuffer { egl_image, .. } => Ok(SharedEGLImage {
+                egl_display: self.egl_display,
+                egl_image: egl_image as *mut c_void,
+            }),
+            SurfaceObjects::ImportedHardwareBuffer { .. } => Err(Error::UnsupportedOnThisPlatform),
+            SurfaceObjects::Window { .. }
+            | SurfaceObjects::SurfaceControl { .. }
+            | SurfaceObjects::Suspended => Err(Error::WidgetAttached),
+        }
+    }
+}
@@ -0,0 +1,804 @@
+// surfman/surfman/src/platform/android/surface_control.rs
+//
+//! Presentation of widget surfaces with `ASurfaceControl`, available from Android 10 (API 29).
+//!
+//! Instead of rendering to an `EGLSurface` for the window, these widget surfaces render into a
+//! small ring of hardware buffers and hand each finished one to SurfaceFlinger in an
+//! `ASurfaceTransaction`, along with a native fence that signals when the rendering is done.
+//! SurfaceFlinger reports back with a release fence for the buffer that the new one replaced, so
+//! a buffer is only rendered to again once the compositor is finished with it, without the
+//! implicit synchronization and queueing of `eglSwapBuffers()`.
+
+use super::ffi::{AHardwareBuffer, AHardwareBuffer_release, ANativeWindow, ARect};
+use super::ffi::{ASurfaceControl, ASurfaceTransaction, ASurfaceTransactionStats};
+use super::ffi::{ADATASPACE_SRGB, ASURFACE_TRANSACTION_VISIBILITY_SHOW};
+use super::ffi::{ANATIVEWINDOW_TRANSFORM_MIRROR_VERTICAL, ANATIVEWINDOW_TRANSFORM_ROTATE_90};
+use super::ffi::{
+    ASURFACE_TRANSACTION_TRANSPARENCY_OPAQUE, ASURFACE_TRANSACTION_TRANSPARENCY_TRANSLUCENT,
+};
+use crate::egl;
+use crate::egl::types::EGLDisplay;
+use crate::gl;
+use crate::gl::types::GLuint;
+use crate::gl_utils;
+use crate::platform::generic::egl::device;
+use crate::platform::generic::egl::ffi::{EGLImageKHR, EGL_EXTENSION_FUNCTIONS, EGL_NO_IMAGE_KHR};
+use crate::renderbuffers::Renderbuffers;
+use crate::{Error, Gl, WindowingApiError};
+
+use euclid::default::{Rect, Size2D};
+use std::cell::Cell;
+use std::mem;
+use std::os::raw::{c_char, c_int, c_void};
+use std::os::unix::io::RawFd;
+use std::ptr;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+// The number of buffers that a widget surface cycles through.
+const SWAPCHAIN_LENGTH: usize = 3;
+
+// How long to wait for SurfaceFlinger to release a buffer before rendering to it anyway. This
+// bounds the stall when the compositor holds on to buffers, e.g. while the activity is paused.
+const RELEASE_TIMEOUT: Duration = Duration::from_millis(100);
+
+type OnComplete = unsafe extern "C" fn(context: *mut c_void, stats: *mut ASurfaceTransactionStats);
+
+// These only exist from API 29 on, so they're looked up at runtime rather than linked, and older
+// releases fall back to `eglSwapBuffers()`.
+#[allow(non_snake_case)]
+struct SurfaceControlFunctions {
+    CreateFromWindow: unsafe extern "C" fn(
+        parent: *mut ANativeWindow,
+        debug_name: *const c_char,
+    ) -> *mut ASurfaceControl,
+    Release: unsafe extern "C" fn(surface_control: *mut ASurfaceControl),
+    TransactionCreate: unsafe extern "C" fn() -> *mut ASurfaceTransaction,
+    TransactionDelete: unsafe extern "C" fn(transaction: *mut ASurfaceTransaction),
+    TransactionApply: unsafe extern "C" fn(transaction: *mut ASurfaceTransaction),
+    TransactionReparent: unsafe extern "C" fn(
+        transaction: *mut ASurfaceTransaction,
+        surface_control: *mut ASurfaceControl,
+        new_parent: *mut ASurfaceControl,
+    ),
+    TransactionSetBuffer: unsafe extern "C" fn(
+        transaction: *mut ASurfaceTransaction,
+        surface_control: *mut ASurfaceControl,
+        buffer: *mut AHardwareBuffer,
+        acquire_fence_fd: c_int,
+    ),
+    TransactionSetBufferTransform: unsafe extern "C" fn(
+        transaction: *mut ASurfaceTransaction,
+        surface_control: *mut ASurfaceControl,
+        transform: i32,
+    ),
+    TransactionSetDamageRegion: unsafe extern "C" fn(
+        transaction: *mut ASurfaceTransaction,
+        surface_control: *mut ASurfaceControl,
+        rects: *const ARect,
+        count: u32,
+    ),
+    TransactionSetGeometry: unsafe extern "C" fn(
+        transaction: *mut ASurfaceTransaction,
+        surface_control: *mut ASurfaceControl,
+        source: *const ARect,
+        destination: *const ARect,
+        transform: i32,
+    ),
+    TransactionSetVisibility: unsafe extern "C" fn(
+        transaction: *mut ASurfaceTransaction,
+        surface_control: *mut ASurfaceControl,
+        visibility: i8,
+    ),
+    TransactionSetBufferTransparency: unsafe extern "C" fn(
+        transaction: *mut ASurfaceTransaction,
+        surface_control: *mut ASurfaceControl,
+        transparency: i8,
+    ),
+    TransactionSetBufferDataSpace: unsafe extern "C" fn(
+        transaction: *mut ASurfaceTransaction,
+        surface_control: *mut ASurfaceControl,
+        dataspace: i32,
+    ),
+    TransactionSetDesiredPresentTime:
+        unsafe extern "C" fn(transaction: *mut ASurfaceTransaction, desired_present_time: i64),
+    TransactionSetOnComplete: unsafe extern "C" fn(
+        transaction: *mut ASurfaceTransaction,
+        context: *mut c_void,
+        func: OnComplete,
+    ),
+    StatsGetASurfaceControls: unsafe extern "C" fn(
+        stats: *mut ASurfaceTransactionStats,
+        surface_controls: *mut *mut *mut ASurfaceControl,
+        count: *mut usize,
+    ),
+    StatsReleaseASurfaceControls: unsafe extern "C" fn(surface_controls: *mut *mut ASurfaceControl),
+    StatsGetPreviousReleaseFenceFd: unsafe extern "C" fn(
+        stats: *mut ASurfaceTransactionStats,
+        surface_control: *mut ASurfaceControl,
+    ) -> c_int,
+}
+
+lazy_static! {
+    static ref SURFACE_CONTROL_FUNCTIONS: Option<SurfaceControlFunctions> = unsafe {
+        let library = libc::dlopen(
+            b"libandroid.so\0".as_ptr() as *const c_char,
+            libc::RTLD_LAZY,
+        );
+        if library.is_null() {
+            return None;
+        }
+        let get = |name: &'static [u8]| libc::dlsym(library, name.as_ptr() as *const c_char);
+        let symbols = [
+            get(b"ASurfaceControl_createFromWindow\0"),
+            get(b"ASurfaceControl_release\0"),
+            get(b"ASurfaceTransaction_create\0"),
+            get(b"ASurfaceTransaction_delete\0"),
+            get(b"ASurfaceTransaction_apply\0"),
+            get(b"ASurfaceTransaction_reparent\0"),
+            get(b"ASurfaceTransaction_setBuffer\0"),
+            get(b"ASurfaceTransaction_setBufferTransform\0"),
+            get(b"ASurfaceTransaction_setDamageRegion\0"),
+            get(b"ASurfaceTransaction_setGeometry\0"),
+            get(b"ASurfaceTransaction_setVisibility\0"),
+            get(b"ASurfaceTransaction_setDesiredPresentTime\0"),
+            get(b"ASurfaceTransaction_setOnComplete\0"),
+            get(b"ASurfaceTransactionStats_getASurfaceControls\0"),
+            get(b"ASurfaceTransactionStats_releaseASurfaceControls\0"),
+            get(b"ASurfaceTransactionStats_getPreviousReleaseFenceFd\0"),
+            get(b"ASurfaceTransaction_setBufferTransparency\0"),
+            get(b"ASurfaceTransaction_setBufferDataSpace\0"),
+        ];
+        if symbols.iter().any(|symbol| symbol.is_null()) {
+            return None;
+        }
+        Some(SurfaceControlFunctions {
+            CreateFromWindow: mem::transmute(symbols[0]),
+            Release: mem::transmute(symbols[1]),
+            TransactionCreate: mem::transmute(symbols[2]),
+            TransactionDelete: mem::transmute(symbols[3]),
+            TransactionApply: mem::transmute(symbols[4]),
+            TransactionReparent: mem::transmute(symbols[5]),
+            TransactionSetBuffer: mem::transmute(symbols[6]),
+            TransactionSetBufferTransform: mem::transmute(symbols[7]),
+            TransactionSetDamageRegion: mem::transmute(symbols[8]),
+            TransactionSetGeometry: mem::transmute(symbols[9]),
+            TransactionSetVisibility: mem::transmute(symbols[10]),
+            TransactionSetDesiredPresentTime: mem::transmute(symbols[11]),
+            TransactionSetOnComplete: mem::transmute(symbols[12]),
+            StatsGetASurfaceControls: mem::transmute(symbols[13]),
+            StatsReleaseASurfaceControls: mem::transmute(symbols[14]),
+            StatsGetPreviousReleaseFenceFd: mem::transmute(symbols[15]),
+            TransactionSetBufferTransparency: mem::transmute(symbols[16]),
+            TransactionSetBufferDataSpace: mem::transmute(symbols[17]),
+        })
+    };
+}
+
+// Returns true if this release of Android supports `ASurfaceControl`.
+pub(crate) fn is_available() -> bool {
+    SURFACE_CONTROL_FUNCTIONS.is_some()
+}
+
+// One of the hardware buffers that a widget surface renders into.
+pub(crate) struct SwapchainBuffer {
+    pub(crate) hardware_buffer: *mut AHardwareBuffer,
+    pub(crate) egl_image: EGLImageKHR,
+    pub(crate) texture_object: GLuint,
+}
+
+pub(crate) struct Swapchain {
+    functions: &'static SurfaceControlFunctions,
+    pub(crate) surface_control: *mut ASurfaceControl,
+    pub(crate) framebuffer_object: GLuint,
+    buffers: Vec<SwapchainBuffer>,
+    renderbuffers: Renderbuffers,
+    // The buffer being rendered to.
+    back_buffer: usize,
+    // The buffer that SurfaceFlinger is showing, if any.
+    front_buffer: Option<usize>,
+    presents_since_allocation: usize,
+    // The time at which the next buffer should be shown, on `CLOCK_MONOTONIC`, if any.
+    desired_present_time: Cell<Option<i64>>,
+    // The transform that undoes the pre-rotation of the contents, if any.
+    buffer_transform: i32,
+    // OpenGL stores the bottom row first, while SurfaceFlinger expects the top row first, so this
+    // is `ANATIVEWINDOW_TRANSFORM_MIRROR_VERTICAL` unless the contents are rendered top-down.
+    vertical_flip: i32,
+    // Whether SurfaceFlinger may skip blending the buffers, as an
+    // `ASURFACE_TRANSACTION_TRANSPARENCY_*` value.
+    transparency: i8,
+    // The `ADATASPACE_*` value that the buffers are tagged with.
+    dataspace: i32,
+    // Whether the data space has to be sent again with the next buffer.
+    dataspace_changed: bool,
+    // Whether the buffer transform has to be sent again with the next buffer.
+    transform_changed: bool,
+    size: Size2D<i32>,
+    // The source and destination rectangles for the next presented buffer, if any.
+    pending_geometry: Cell<Option<(ARect, ARect)>>,
+    // Whether the last presented buffer was cropped or scaled.
+    has_geometry: bool,
+    native_fences: bool,
+    release_state: Arc<ReleaseState>,
+}
+
+// Tracks which buffers SurfaceFlinger has given back. This is shared with the completion
+// callbacks, which run on a binder thread.
+struct ReleaseState {
+    status: Mutex<ReleaseStatus>,
+    released: Condvar,
+}
+
+struct ReleaseStatus {
+    // Incremented whenever the buffers are reallocated, so that callbacks for transactions that
+    // carried the old buffers are ignored.
+    generation: u64,
+    buffers: Vec<BufferStatus>,
+}
+
+enum BufferStatus {
+    // The buffer can be rendered to once the fence, if any, signals.
+    Free { release_fence: Option<RawFd> },
+    // The buffer has been handed to SurfaceFlinger.
+    Queued,
+}
+
+// Passed to `on_transaction_complete()`.
+struct TransactionContext {
+    release_state: Arc<ReleaseState>,
+    generation: u64,
+    // The buffer that this transaction replaced on screen.
+    replaced_buffer: Option<usize>,
+}
+
+impl Swapchain {
+    // Creates a child surface of the given window and allocates its buffers with
+    // `allocate_buffer`. SurfaceFlinger applies `buffer_transform` to the buffers when showing
+    // them, so that pre-rotated contents appear upright. If `opaque` is set, SurfaceFlinger ignores
+    // the alpha channel of the buffers.
+    //
+    // The context must be current. The renderbuffers are destroyed if this fails.
+    pub(crate) unsafe fn new<F>(
+        gl: &Gl,
+        egl_display: EGLDisplay,
+        native_window: *mut ANativeWindow,
+        size: &Size2D<i32>,
+        buffer_transform: i32,
+        opaque: bool,
+        mut renderbuffers: Renderbuffers,
+        allocate_buffer: F,
+    ) -> Result<Swapchain, Error>
+    where
+        F: FnMut(&Size2D<i32>) -> Result<SwapchainBuffer, Error>,
+    {
+        let functions = match *SURFACE_CONTROL_FUNCTIONS {
+            Some(ref functions) => functions,
+            None => {
+                renderbuffers.destroy(gl);
+                return Err(Error::UnsupportedOnThisPlatform);
+            }
+        };
+
+        let buffers = match allocate_buffers(gl, egl_display, size, allocate_buffer) {
+            Ok(buffers) => buffers,
+            Err(err) => {
+                renderbuffers.destroy(gl);
+                return Err(err);
+            }
+        };
+
+        let surface_control =
+            (functions.CreateFromWindow)(native_window, b"surfman\0".as_ptr() as *const c_char);
+        if surface_control.is_null() {
+            renderbuffers.destroy(gl);
+            for mut buffer in buffers {
+                buffer.destroy(gl, egl_display);
+            }
+            return Err(Error::SurfaceCreationFailed(
+                WindowingApiError::BadNativeWindow,
+            ));
+        }
+
+        let mut bindings = gl_utils::BindingsGuard::new(gl);
+        let framebuffer_object =
+            bindings.create_and_bind_framebuffer(gl::TEXTURE_2D, buffers[0].texture_object);
+        renderbuffers.bind_to_current_framebuffer(gl);
+        drop(bindings);
+
+        Ok(Swapchain {
+            functions,
+            surface_control,
+            framebuffer_object,
+            release_state: Arc::new(ReleaseState {
+                status: Mutex::new(ReleaseStatus {
+                    generation: 0,
+                    buffers: buffers.iter().map(|_| BufferStatus::free()).collect(),
+                }),
+                released: Condvar::new(),
+            }),
+            buffers,
+            renderbuffers,
+            back_buffer: 0,
+            front_buffer: None,
+            presents_since_allocation: 0,
+            desired_present_time: Cell::new(None),
+            buffer_transform,
+            vertical_flip: ANATIVEWINDOW_TRANSFORM_MIRROR_VERTICAL,
+            transparency: if opaque {
+                ASURFACE_TRANSACTION_TRANSPARENCY_OPAQUE
+            } else {
+                ASURFACE_TRANSACTION_TRANSPARENCY_TRANSLUCENT
+            },
+            dataspace: ADATASPACE_SRGB,
+            dataspace_changed: false,
+            transform_changed: false,
+            size: *size,
+            pending_geometry: Cell::new(None),
+            has_geometry: false,
+            native_fences: device::supports_native_fences(egl_display),
+        })
+    }
+
+    // Hands the back buffer to SurfaceFlinger, then switches rendering to the next buffer once
+    // SurfaceFlinger has released it.
+    //
+    // The context must be current. The damage rectangles have their origin at the lower left.
+    pub(crate) unsafe fn present(
+        &mut self,
+        gl: &Gl,
+        egl_display: EGLDisplay,
+        damage: &[Rect<i32>],
+    ) {
+        let functions = self.functions;
+
+        // SurfaceFlinger waits for this before reading the buffer, so the GPU doesn't have to
+        // finish first unless there's no way to export a fence.
+        let acquire_fence = device::create_native_fence(egl_display, self.native_fences);
+        if acquire_fence.is_none() {
+            gl.Finish();
+        }
+
+        let transaction = (functions.TransactionCreate)();
+        (functions.TransactionSetBuffer)(
+            transaction,
+            self.surface_control,
+            self.buffers[self.back_buffer].hardware_buffer,
+            acquire_fence.unwrap_or(-1),
+        );
+
+        if self.front_buffer.is_none() || self.transform_changed {
+            // Flips are applied before rotations, so the flip just toggles the vertical flip bit.
+            (functions.TransactionSetBufferTransform)(
+                transaction,
+                self.surface_control,
+                self.buffer_transform ^ self.vertical_flip,
+            );
+            self.transform_changed = false;
+        }
+        if self.front_buffer.is_none() || self.dataspace_changed {
+            (functions.TransactionSetBufferDataSpace)(
+                transaction,
+                self.surface_control,
+                self.dataspace,
+            );
+            self.dataspace_changed = false;
+        }
+        if self.front_buffer.is_none() {
+            (functions.TransactionSetBufferTransparency)(
+                transaction,
+                self.surface_control,
+                self.transparency,
+            );
+            (functions.TransactionSetVisibility)(
+                transaction,
+                self.surface_control,
+                ASURFACE_TRANSACTION_VISIBILITY_SHOW,
+            );
+        }
+
+        // The geometry has to be reset once after a cropped frame, since SurfaceFlinger keeps it.
+        match self.pending_geometry.take() {
+            Some((source, destination)) => {
+                self.set_geometry(transaction, &source, &destination);
+                self.has_geometry = true;
+            }
+            None if self.has_geometry => {
+                let (source, destination) = self.full_geometry();
+                self.set_geometry(transaction, &source, &destination);
+                self.has_geometry = false;
+            }
+            None => {}
+        }
+
+        if let Some(desired_present_time) = self.desired_present_time.take() {
+            (functions.TransactionSetDesiredPresentTime)(transaction, desired_present_time);
+        }
+
+        // The damage is in buffer space, before the vertical flip, so the rectangles can be
+        // passed along unchanged.
+        if !damage.is_empty() {
+            let rects: Vec<ARect> = damage
+                .iter()
+                .map(|rect| ARect {
+                    left: rect.origin.x,
+                    top: rect.origin.y,
+                    right: rect.max_x(),
+                    bottom: rect.max_y(),
+                })
+                .collect();
+            (functions.TransactionSetDamageRegion)(
+                transaction,
+                self.surface_control,
+                rects.as_ptr(),
+                rects.len() as u32,
+            );
+        }
+
+        let generation = {
+            let mut status = self.release_state.status.lock().unwrap();
+            status.buffers[self.back_buffer] = BufferStatus::Queued;
+            status.generation
+        };
+        let context = Box::new(TransactionContext {
+            release_state: self.release_state.clone(),
+            generation,
+            replaced_buffer: self.front_buffer,
+        });
+        (functions.TransactionSetOnComplete)(
+            transaction,
+            Box::into_raw(context) as *mut c_void,
+            on_transaction_complete,
+        );
+        (functions.TransactionApply)(transaction);
+        (functions.TransactionDelete)(transaction);
+
+        self.front_buffer = Some(self.back_buffer);
+        self.back_buffer = (self.back_buffer + 1) % self.buffers.len();
+        self.presents_since_allocation += 1;
+
+        self.release_state.wait_for_release(self.back_buffer);
+        self.attach_back_buffer(gl);
+    }
+
+    // Sets the time at which the next presented buffer should be shown.
+    pub(crate) fn set_presentation_time(&self, nanos: i64) {
+        self.desired_present_time.set(Some(nanos));
+    }
+
+    // Shows only the `source` rectangle of the next presented buffer, scaled into the
+    // `destination` rectangle of a window of the given size. Both rectangles have their origin at
+    // the lower left; the source is in buffer space and the destination in window space.
+    pub(crate) fn set_viewport(
+        &self,
+        source: &Rect<i32>,
+        destination: &Rect<i32>,
+        window_size: &Size2D<i32>,
+    ) {
+        // Like the damage, the source is in buffer space before the vertical flip. The
+        // destination is in the window, whose origin is at the upper left.
+        let source = ARect {
+            left: source.origin.x,
+            top: source.origin.y,
+            right: source.max_x(),
+            bottom: source.max_y(),
+        };
+        let destination = ARect {
+            left: destination.origin.x,
+            top: window_size.height - destination.max_y(),
+            right: destination.max_x(),
+            bottom: window_size.height - destination.origin.y,
+        };
+        self.pending_geometry.set(Some((source, destination)));
+    }
+
+    // The geometry that shows the whole buffer over the whole window.
+    fn full_geometry(&self) -> (ARect, ARect) {
+        let source = ARect {
+            left: 0,
+            top: 0,
+            right: self.size.width,
+            bottom: self.size.height,
+        };
+        let mut destination = source;
+        if self.buffer_transform & ANATIVEWINDOW_TRANSFORM_ROTATE_90 != 0 {
+            mem::swap(&mut destination.right, &mut destination.bottom);
+        }
+        (source, destination)
+    }
+
+    unsafe fn set_geometry(
+        &self,
+        transaction: *mut ASurfaceTransaction,
+        source: &ARect,
+        destination: &ARect,
+    ) {
+        // This also sets the buffer transform, so the vertical flip has to be included again.
+        (self.functions.TransactionSetGeometry)(
+            transaction,
+            self.surface_control,
+            source,
+            destination,
+            self.buffer_transform ^ self.vertical_flip,
+        );
+    }
+
+    // Sets whether the contents are rendered with their top row first. This takes effect with the
+    // next present.
+    pub(crate) fn set_top_down(&mut self, top_down: bool) {
+        let vertical_flip = if top_down {
+            0
+        } else {
+            ANATIVEWINDOW_TRANSFORM_MIRROR_VERTICAL
+        };
+        if vertical_flip != self.vertical_flip {
+            self.vertical_flip = vertical_flip;
+            self.transform_changed = true;
+        }
+    }
+
+    // Tags the buffers with the color space of their contents, as an `ADATASPACE_*` value, starting
+    // with the next presented buffer.
+    pub(crate) fn set_dataspace(&mut self, dataspace: i32) {
+        if dataspace != self.dataspace {
+            self.dataspace = dataspace;
+            self.dataspace_changed = true;
+        }
+    }
+
+    // Returns the number of presents since the back buffer was last shown, or 0 if its contents
+    // are undefined.
+    pub(crate) fn buffer_age(&self) -> u32 {
+        if self.presents_since_allocation >= self.buffers.len() {
+            self.buffers.len() as u32
+        } else {
+            0
+        }
+    }
+
+    // Reallocates the buffers at the new size, to be shown with the given transform. The context
+    // must be current.
+    pub(crate) unsafe fn resize<F>(
+        &mut self,
+        gl: &Gl,
+        egl_display: EGLDisplay,
+        size: &Size2D<i32>,
+        buffer_transform: i32,
+        renderbuffers: Renderbuffers,
+        allocate_buffer: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(&Size2D<i32>) -> Result<SwapchainBuffer, Error>,
+    {
+        let mut renderbuffers = renderbuffers;
+        let buffers = match allocate_buffers(gl, egl_display, size, allocate_buffer) {
+            Ok(buffers) => buffers,
+            Err(err) => {
+                renderbuffers.destroy(gl);
+                return Err(err);
+            }
+        };
+
+        // SurfaceFlinger keeps its own references to the buffers it still holds.
+        for mut buffer in mem::replace(&mut self.buffers, buffers) {
+            buffer.destroy(gl, egl_display);
+        }
+        mem::replace(&mut self.renderbuffers, renderbuffers).destroy(gl);
+        self.release_state.reset(self.buffers.len());
+
+        self.back_buffer = 0;
+        self.front_buffer = None;
+        self.presents_since_allocation = 0;
+        self.buffer_transform = buffer_transform;
+        self.size = *size;
+        self.attach_back_buffer(gl);
+        Ok(())
+    }
+
+    // Removes the child surface from the window and frees the buffers.
+    pub(crate) unsafe fn destroy(&mut self, gl: &Gl, egl_display: EGLDisplay) {
+        gl_utils::destroy_framebuffer(gl, self.framebuffer_object);
+        self.framebuffer_object = 0;
+        self.renderbuffers.destroy(gl);
+
+        for buffer in &mut self.buffers {
+            gl.DeleteTextures(1, &buffer.texture_object);
+            buffer.texture_object = 0;
+        }
+        self.release(egl_display);
+    }
+
+    // Forgets the objects that belong to the context, for when it isn't available. They're freed
+    // along with the context.
+    pub(crate) fn abandon_gl_objects(&mut self) {
+        self.framebuffer_object = 0;
+        self.renderbuffers.abandon();
+        for buffer in &mut self.buffers {
+            buffer.texture_object = 0;
+        }
+    }
+
+    // Removes the child surface from the window and frees what's left of the buffers once the
+    // objects that belong to the context are gone.
+    pub(crate) unsafe fn release(&mut self, egl_display: EGLDisplay) {
+        let functions = self.functions;
+        if !self.surface_control.is_null() {
+            let transaction = (functions.TransactionCreate)();
+            (functions.TransactionReparent)(transaction, self.surface_control, ptr::null_mut());
+            (functions.TransactionApply)(transaction);
+            (functions.TransactionDelete)(transaction);
+            (functions.Release)(self.surface_control);
+            self.surface_control = ptr::null_mut();
+        }
+
+        for mut buffer in self.buffers.drain(..) {
+            buffer.release(egl_display);
+        }
+        self.release_state.reset(0);
+    }
+
+    unsafe fn attach_back_buffer(&self, gl: &Gl) {
+        let mut bindings = gl_utils::BindingsGuard::new(gl);
+        bindings.bind_framebuffer(gl::FRAMEBUFFER, self.framebuffer_object);
+        gl.FramebufferTexture2D(
+            gl::FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            gl::TEXTURE_2D,
+            self.buffers[self.back_buffer].texture_object,
+            0,
+        );
+        self.renderbuffers.bind_to_current_framebuffer(gl);
+    }
+}
+
+impl SwapchainBuffer {
+    unsafe fn destroy(&mut self, gl: &Gl, egl_display: EGLDisplay) {
+        gl.DeleteTextures(1, &self.texture_object);
+        self.texture_object = 0;
+        self.release(egl_display);
+    }
+
+    unsafe fn release(&mut self, egl_display: EGLDisplay) {
+        let result = (EGL_EXTENSION_FUNCTIONS.DestroyImageKHR)(egl_display, self.egl_image);
+        assert_ne!(result, egl::FALSE);
+        self.egl_image = EGL_NO_IMAGE_KHR;
+
+        AHardwareBuffer_release(self.hardware_buffer);
+        self.hardware_buffer = ptr::null_mut();
+    }
+}
+
+impl BufferStatus {
+    fn free() -> BufferStatus {
+        BufferStatus::Free {
+            release_fence: None,
+        }
+    }
+}
+
+impl ReleaseState {
+    // Blocks until SurfaceFlinger has released the given buffer and finished reading from it, or
+    // until the timeout expires.
+    fn wait_for_release(&self, buffer_index: usize) {
+        let deadline = Instant::now() + RELEASE_TIMEOUT;
+        let mut status = self.status.lock().unwrap();
+        let release_fence = loop {
+            if let BufferStatus::Free {
+                ref mut release_fence,
+            } = status.buffers[buffer_index]
+            {
+                break release_fence.take();
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                break None;
+            }
+            status = self
+                .released
+                .wait_timeout(status, deadline - now)
+                .unwrap()
+                .0;
+        };
+        drop(status);
+
+        if let Some(release_fence) = release_fence {
+            unsafe {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                let mut poll_fd = libc::pollfd {
+                    fd: release_fence,
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                libc::poll(&mut poll_fd, 1, timeout.as_millis() as c_int);
+                libc::close(release_fence);
+            }
+        }
+    }
+
+    fn release(&self, generation: u64, buffer_index: Option<usize>, release_fence: Option<RawFd>) {
+        let mut status = self.status.lock().unwrap();
+        match buffer_index {
+            Some(buffer_index) if status.generation == generation => {
+                status.buffers[buffer_index] = BufferStatus::Free { release_fence };
+                self.released.notify_all();
+            }
+            _ => {
+                if let Some(release_fence) = release_fence {
+                    unsafe {
+                        libc::close(release_fence);
+                    }
+                }
+            }
+        }
+    }
+
+    fn reset(&self, buffer_count: usize) {
+        let mut status = self.status.lock().unwrap();
+        status.generation += 1;
+        for buffer in status.buffers.drain(..) {
+            if let BufferStatus::Free {
+                release_fence: Some(release_fence),
+            } = buffer
+            {
+                unsafe {
+                    libc::close(release_fence);
+                }
+            }
+        }
+        status.buffers = (0..buffer_count).map(|_| BufferStatus::free()).collect();
+        self.released.notify_all();
+    }
+}
+
+unsafe fn allocate_buffers<F>(
+    gl: &Gl,
+    egl_display: EGLDisplay,
+    size: &Size2D<i32>,
+    mut allocate_buffer: F,
+) -> Result<Vec<SwapchainBuffer>, Error>
+where
+    F: FnMut(&Size2D<i32>) -> Result<SwapchainBuffer, Error>,
+{
+    let mut buffers = Vec::with_capacity(SWAPCHAIN_LENGTH);
+    for _ in 0..SWAPCHAIN_LENGTH {
+        match allocate_buffer(size) {
+            Ok(buffer) => buffers.push(buffer),
+            Err(err) => {
+                for mut buffer in buffers {
+                    buffer.destroy(gl, egl_display);
+                }
+                return Err(err);
+            }
+        }
+    }
+    Ok(buffers)
+}
+
+unsafe extern "C" fn on_transaction_complete(
+    context: *mut c_void,
+    stats: *mut ASurfaceTransactionStats,
+) {
+    let context = Box::from_raw(context as *mut TransactionContext);
+    let functions = match *SURFACE_CONTROL_FUNCTIONS {
+        Some(ref functions) => functions,
+        None => return,
+    };
+
+    // The transaction only touched one surface, whose previous buffer is the one it replaced.
+    let mut release_fence = None;
+    let (mut surface_controls, mut surface_control_count) = (ptr::null_mut(), 0);
+    (functions.StatsGetASurfaceControls)(stats, &mut surface_controls, &mut surface_control_count);
+    if !surface_controls.is_null() {
+        if surface_control_count > 0 {
+            let fence = (functions.StatsGetPreviousReleaseFenceFd)(stats, *surface_controls);
+            if fence >= 0 {
+                release_fence = Some(fence);
+            }
+        }
+        (functions.StatsReleaseASurfaceControls)(surface_controls);
+    }
+
+    context
+        .release_state
+        .release(context.generation, context.replaced_buffer, release_fence);
+}
@@ -2,17 +2,20 @@
 //
 //! Functionality common to backends using EGL contexts.
 
-use super::device::EGL_FUNCTIONS;
+use super::device::{self, EGL_FUNCTIONS};
 use super::error::ToWindowingApiError;
 use super::ffi::EGL_CONTEXT_OPENGL_PROFILE_MASK;
+use super::ffi::{EGL_COLOR_COMPONENT_TYPE_EXT, EGL_COLOR_COMPONENT_TYPE_FLOAT_EXT};
 use super::ffi::{EGL_CONTEXT_MINOR_VERSION_KHR, EGL_CONTEXT_OPENGL_COMPATIBILITY_PROFILE_BIT};
 use super::surface::{EGLBackedSurface, ExternalEGLSurfaces};
-use crate::context::{self, CREATE_CONTEXT_MUTEX};
+use crate::context::{self, ContextIDAllocator};
 use crate::egl;
 use crate::egl::types::{EGLConfig, EGLContext, EGLDisplay, EGLSurface, EGLint};
-use crate::surface::Framebuffer;
+use crate::gl::types::GLuint;
+use crate::surface::{ExternalFramebuffer, Framebuffer};
 use crate::{ContextAttributeFlags, ContextAttributes, ContextID, Error, GLApi, GLVersion};
 use crate::{Gl, SurfaceInfo};
+use euclid::default::Size2D;
 
 use std::ffi::CString;
 use std::mem;
@@ -23,6 +26,7 @@ use std::thread;
 #[allow(dead_code)]
 const DUMMY_PBUFFER_SIZE: EGLint = 16;
 const RGB_CHANNEL_BIT_DEPTH: EGLint = 8;
+const FLOAT_CHANNEL_BIT_DEPTH: EGLint = 16;
 
 pub(crate) struct EGLBackedContext {
     pub(crate) egl_context: EGLContext,
@@ -44,12 +48,18 @@ pub struct NativeContext {
 
 /// Information needed to create a context. Some APIs call this a "config" or a "pixel format".
 ///
-/// These are local to a device.
-#[derive(Clone)]
+/// These are local to a device. Descriptors compare equal when they name the same EGL config and
+/// request the same version, profile, and extensions.
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct ContextDescriptor {
     pub(crate) egl_config_id: EGLint,
     pub(crate) gl_version: GLVersion,
+    // The highest version to try when creating contexts, which is `gl_version` unless a range was
+    // asked for.
+    pub(crate) max_gl_version: GLVersion,
     pub(crate) compatibility_profile: bool,
+    pub(crate) debug: bool,
+    pub(crate) required_extensions: Vec<String>,
 }
 
 #[must_use]
@@ -90,9 +100,8 @@ impl EGLBackedContext {
         descriptor: &ContextDescriptor,
         share_with: Option<&EGLBackedContext>,
         gl_api: GLApi,
+        context_ids: &ContextIDAllocator,
     ) -> Result<EGLBackedContext, Error> {
-        let mut next_context_id = CREATE_CONTEXT_MUTEX.lock().unwrap();
-
         // Create the context.
         let egl_context = create_context(
             egl_display,
@@ -104,27 +113,26 @@ impl EGLBackedContext {
         // Wrap and return it.
         let context = EGLBackedContext {
             egl_context,
-            id: *next_context_id,
+            id: context_ids.next(),
             framebuffer: Framebuffer::None,
             context_is_owned: true,
         };
-        next_context_id.0 += 1;
         Ok(context)
     }
 
-    pub(crate) unsafe fn from_native_context(native_context: NativeContext) -> EGLBackedContext {
-        let mut next_context_id = CREATE_CONTEXT_MUTEX.lock().unwrap();
-        let context = EGLBackedContext {
+    pub(crate) unsafe fn from_native_context(
+        native_context: NativeContext,
+        context_ids: &ContextIDAllocator,
+    ) -> EGLBackedContext {
+        EGLBackedContext {
             egl_context: native_context.egl_context,
-            id: *next_context_id,
+            id: context_ids.next(),
             framebuffer: Framebuffer::External(ExternalEGLSurfaces {
                 draw: native_context.egl_draw_surface,
                 read: native_context.egl_read_surface,
             }),
             context_is_owned: false,
-        };
-        next_context_id.0 += 1;
-        context
+        }
     }
 
     pub(crate) unsafe fn destroy(&mut self, egl_display: EGLDisplay) {
@@ -149,7 +157,9 @@ impl EGLBackedContext {
         let egl_surfaces = match self.framebuffer {
             Framebuffer::Surface(ref surface) => surface.egl_surfaces(),
             Framebuffer::External(ref surfaces) => (*surfaces).clone(),
-            Framebuffer::None => ExternalEGLSurfaces::default(),
+            Framebuffer::None | Framebuffer::ExternalFramebuffer(_) => {
+                ExternalEGLSurfaces::default()
+            }
         };
 
         NativeContext {
@@ -163,7 +173,9 @@ impl EGLBackedContext {
         let egl_surfaces = match self.framebuffer {
             Framebuffer::Surface(ref surface) => surface.egl_surfaces(),
             Framebuffer::External(ref surfaces) => (*surfaces).clone(),
-            Framebuffer::None => ExternalEGLSurfaces::default(),
+            Framebuffer::None | Framebuffer::ExternalFramebuffer(_) => {
+                ExternalEGLSurfaces::default()
+            }
         };
 
         EGL_FUNCTIONS.with(|egl| {
@@ -181,6 +193,33 @@ impl EGLBackedContext {
         })
     }
 
+    pub(crate) unsafe fn make_current_with_surface(
+        &self,
+        egl_display: EGLDisplay,
+        surface: &EGLBackedSurface,
+    ) -> Result<(), Error> {
+        if self.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        // Generic surfaces have no EGL surface and are rendered to through their framebuffer
+        // objects, so the context is made current without one.
+        let egl_surfaces = surface.egl_surfaces();
+        EGL_FUNCTIONS.with(|egl| {
+            let result = egl.MakeCurrent(
+                egl_display,
+                egl_surfaces.draw,
+                egl_surfaces.read,
+                self.egl_context,
+            );
+            if result == egl::FALSE {
+                let err = egl.GetError().to_windowing_api_error();
+                return Err(Error::MakeCurrentFailed(err));
+            }
+            Ok(())
+        })
+    }
+
     #[inline]
     pub(crate) fn is_current(&self) -> bool {
         unsafe { EGL_FUNCTIONS.with(|egl| egl.GetCurrentContext() == self.egl_context) }
@@ -197,7 +236,9 @@ impl EGLBackedContext {
 
         match self.framebuffer {
             Framebuffer::None => self.framebuffer = Framebuffer::Surface(surface),
-            Framebuffer::External(_) => return Err((Error::ExternalRenderTarget, surface)),
+            Framebuffer::External(_) | Framebuffer::ExternalFramebuffer(_) => {
+                return Err((Error::ExternalRenderTarget, surface))
+            }
             Framebuffer::Surface(_) => return Err((Error::SurfaceAlreadyBound, surface)),
         }
 
@@ -218,11 +259,17 @@ impl EGLBackedContext {
             Framebuffer::None => return Ok(None),
             Framebuffer::Surface(_) => {}
             Framebuffer::External(_) => return Err(Error::ExternalRenderTarget),
+            Framebuffer::ExternalFramebuffer(_) => {
+                self.framebuffer = Framebuffer::None;
+                return Ok(None);
+            }
         }
 
         let surface = match mem::replace(&mut self.framebuffer, Framebuffer::None) {
             Framebuffer::Surface(surface) => surface,
-            Framebuffer::None | Framebuffer::External(_) => unreachable!(),
+            Framebuffer::None | Framebuffer::External(_) | Framebuffer::ExternalFramebuffer(_) => {
+                unreachable!()
+            }
         };
 
         // If we're current, we stay current, but with no surface attached.
@@ -231,11 +278,45 @@ impl EGLBackedContext {
         Ok(Some(surface))
     }
 
+    pub(crate) fn bound_surface(&self) -> Option<&EGLBackedSurface> {
+        match self.framebuffer {
+            Framebuffer::Surface(ref surface) => Some(surface),
+            Framebuffer::None | Framebuffer::External(_) | Framebuffer::ExternalFramebuffer(_) => {
+                None
+            }
+        }
+    }
+
+    pub(crate) fn bind_external_framebuffer(
+        &mut self,
+        framebuffer_object: GLuint,
+        size: Size2D<i32>,
+    ) -> Result<(), Error> {
+        match self.framebuffer {
+            Framebuffer::None => {}
+            Framebuffer::External(_) | Framebuffer::ExternalFramebuffer(_) => {
+                return Err(Error::ExternalRenderTarget)
+            }
+            Framebuffer::Surface(_) => return Err(Error::SurfaceAlreadyBound),
+        }
+
+        // The context stays current without a surface, as it does when nothing is bound.
+        self.framebuffer =
+            Framebuffer::ExternalFramebuffer(ExternalFramebuffer::new(framebuffer_object, size));
+        Ok(())
+    }
+
     pub(crate) fn surface_info(&self) -> Result<Option<SurfaceInfo>, Error> {
         match self.framebuffer {
             Framebuffer::None => Ok(None),
             Framebuffer::External(_) => Err(Error::ExternalRenderTarget),
-            Framebuffer::Surface(ref surface) => Ok(Some(surface.info())),
+            Framebuffer::ExternalFramebuffer(ref framebuffer) => {
+                Ok(Some(framebuffer.info(self.id)))
+            }
+            Framebuffer::Surface(ref surface) => Ok(Some(SurfaceInfo {
+                bound: true,
+                ..surface.info()
+            })),
         }
     }
 }
@@ -261,6 +342,16 @@ impl NativeContext {
 }
 
 impl ContextDescriptor {
+    /// Returns the `EGL_CONFIG_ID` of the EGL config that contexts created from this descriptor
+    /// use.
+    ///
+    /// Libraries that create their own contexts can look the config up on the same display with
+    /// `eglChooseConfig()` and an `EGL_CONFIG_ID` attribute, to match surfman's configuration.
+    #[inline]
+    pub fn egl_config_id(&self) -> EGLint {
+        self.egl_config_id
+    }
+
     pub(crate) unsafe fn new(
         egl_display: EGLDisplay,
         attributes: &ContextAttributes,
@@ -268,8 +359,19 @@ impl ContextDescriptor {
     ) -> Result<ContextDescriptor, Error> {
         let flags = attributes.flags;
 
+        let float_color = flags.contains(ContextAttributeFlags::FLOAT_COLOR);
+        if float_color && !device::display_has_extension(egl_display, b"EGL_EXT_pixel_format_float")
+        {
+            return Err(Error::RequiredExtensionUnavailable);
+        }
+        let channel_bit_depth = if float_color {
+            FLOAT_CHANNEL_BIT_DEPTH
+        } else {
+            RGB_CHANNEL_BIT_DEPTH
+        };
+
         let alpha_size = if flags.contains(ContextAttributeFlags::ALPHA) {
-            8
+            channel_bit_depth
         } else {
             0
         };
@@ -295,88 +397,86 @@ impl ContextDescriptor {
             return Err(Error::UnsupportedGLProfile);
         }
 
+        // EGL configs have no stereo attribute.
+        if flags.contains(ContextAttributeFlags::STEREO) {
+            return Err(Error::UnsupportedOnThisPlatform);
+        }
+
         // Create required config attributes.
         //
         // We check these separately because `eglChooseConfig` on its own might give us 32-bit
         // color when 24-bit color is requested, and that can break code.
-        let required_config_attributes = [
+        let mut required_config_attributes = vec![
             egl::RED_SIZE as EGLint,
-            RGB_CHANNEL_BIT_DEPTH,
+            channel_bit_depth,
             egl::GREEN_SIZE as EGLint,
-            RGB_CHANNEL_BIT_DEPTH,
+            channel_bit_depth,
             egl::BLUE_SIZE as EGLint,
-            RGB_CHANNEL_BIT_DEPTH,
+            channel_bit_depth,
         ];
+        if float_color {
+            required_config_attributes.extend_from_slice(&[
+                EGL_COLOR_COMPONENT_TYPE_EXT as EGLint,
+                EGL_COLOR_COMPONENT_TYPE_FLOAT_EXT as EGLint,
+            ]);
+        }
 
-        // Create config attributes.
-        let mut requested_config_attributes = required_config_attributes.to_vec();
-        requested_config_attributes.extend_from_slice(&[
-            egl::ALPHA_SIZE as EGLint,
-            alpha_size,
-            egl::DEPTH_SIZE as EGLint,
-            depth_size,
-            egl::STENCIL_SIZE as EGLint,
-            stencil_size,
-        ]);
-        requested_config_attributes.extend_from_slice(extra_config_attributes);
-        requested_config_attributes.extend_from_slice(&[egl::NONE as EGLint, 0, 0, 0]);
-
-        EGL_FUNCTIONS.with(|egl| {
-            // See how many applicable configs there are.
-            let mut config_count = 0;
-            let result = egl.ChooseConfig(
-                egl_display,
-                requested_config_attributes.as_ptr(),
-                ptr::null_mut(),
-                0,
-                &mut config_count,
-            );
-            if result == egl::FALSE {
-                let err = egl.GetError().to_windowing_api_error();
-                return Err(Error::PixelFormatSelectionFailed(err));
-            }
-            if config_count == 0 {
-                return Err(Error::NoPixelFormatFound);
+        // Try each sample count in turn, settling for fewer samples if the requested count isn't
+        // available.
+        let mut egl_config = None;
+        for samples in attributes.sample_counts() {
+            let mut required_config_attributes = required_config_attributes.clone();
+            if samples > 1 {
+                required_config_attributes.extend_from_slice(&[
+                    egl::SAMPLE_BUFFERS as EGLint,
+                    1,
+                    egl::SAMPLES as EGLint,
+                    samples as EGLint,
+                ]);
             }
 
-            // Enumerate all those configs.
-            let mut configs = vec![ptr::null(); config_count as usize];
-            let mut real_config_count = config_count;
-            let result = egl.ChooseConfig(
+            // Create config attributes.
+            let mut requested_config_attributes = required_config_attributes.clone();
+            requested_config_attributes.extend_from_slice(&[
+                egl::ALPHA_SIZE as EGLint,
+                alpha_size,
+                egl::DEPTH_SIZE as EGLint,
+                depth_size,
+                egl::STENCIL_SIZE as EGLint,
+                stencil_size,
+            ]);
+            requested_config_attributes.extend_from_slice(extra_config_attributes);
+            requested_config_attributes.extend_from_slice(&[egl::NONE as EGLint, 0, 0, 0]);
+
+            match choose_config(
                 egl_display,
-                requested_config_attributes.as_ptr(),
-                configs.as_mut_ptr(),
-                config_count,
-                &mut real_config_count,
-            );
-            if result == egl::FALSE {
-                let err = egl.GetError().to_windowing_api_error();
-                return Err(Error::PixelFormatSelectionFailed(err));
+                &requested_config_attributes,
+                &required_config_attributes,
+            ) {
+                Ok(config) => {
+                    egl_config = Some(config);
+                    break;
+                }
+                Err(Error::NoPixelFormatFound) => {}
+                Err(err) => return Err(err),
             }
+        }
+        let egl_config = match egl_config {
+            None => return Err(Error::NoPixelFormatFound),
+            Some(egl_config) => egl_config,
+        };
 
-            // Sanitize configs.
-            let egl_config = configs
-                .into_iter()
-                .filter(|&egl_config| {
-                    required_config_attributes
-                        .chunks(2)
-                        .all(|pair| get_config_attr(egl_display, egl_config, pair[0]) == pair[1])
-                })
-                .next();
-            let egl_config = match egl_config {
-                None => return Err(Error::NoPixelFormatFound),
-                Some(egl_config) => egl_config,
-            };
-
-            // Get the config ID and version.
-            let egl_config_id = get_config_attr(egl_display, egl_config, egl::CONFIG_ID as EGLint);
-            let gl_version = attributes.version;
-
-            Ok(ContextDescriptor {
-                egl_config_id,
-                gl_version,
-                compatibility_profile,
-            })
+        // Get the config ID and version.
+        let egl_config_id = get_config_attr(egl_display, egl_config, egl::CONFIG_ID as EGLint);
+        let gl_version = attributes.version;
+
+        Ok(ContextDescriptor {
+            egl_config_id,
+            gl_version,
+            max_gl_version: attributes.highest_version(),
+            compatibility_profile,
+            debug: flags.contains(ContextAttributeFlags::DEBUG),
+            required_extensions: attributes.required_extensions.clone(),
         })
     }
 
@@ -392,11 +492,15 @@ impl ContextDescriptor {
             egl.MakeCurrent(egl_display, egl::NO_SURFACE, egl::NO_SURFACE, egl_context);
             let gl_version = GLVersion::current(gl);
             let compatibility_profile = context::current_context_uses_compatibility_profile(gl);
+            let debug = context::current_context_is_debug(gl);
 
             ContextDescriptor {
                 egl_config_id,
                 gl_version,
+                max_gl_version: gl_version,
                 compatibility_profile,
+                debug,
+                required_extensions: vec![],
             }
         })
     }
@@ -440,15 +544,32 @@ impl ContextDescriptor {
         attribute_flags.set(ContextAttributeFlags::DEPTH, depth_size != 0);
         attribute_flags.set(ContextAttributeFlags::STENCIL, stencil_size != 0);
 
+        // Without the extension, every config has fixed-point color.
+        if device::display_has_extension(egl_display, b"EGL_EXT_pixel_format_float") {
+            let component_type = get_config_attr(
+                egl_display,
+                egl_config,
+                EGL_COLOR_COMPONENT_TYPE_EXT as EGLint,
+            );
+            attribute_flags.set(
+                ContextAttributeFlags::FLOAT_COLOR,
+                component_type == EGL_COLOR_COMPONENT_TYPE_FLOAT_EXT as EGLint,
+            );
+        }
+
         attribute_flags.set(
             ContextAttributeFlags::COMPATIBILITY_PROFILE,
             self.compatibility_profile,
         );
+        attribute_flags.set(ContextAttributeFlags::DEBUG, self.debug);
 
         // Create appropriate context attributes.
         ContextAttributes {
             flags: attribute_flags,
             version: self.gl_version,
+            max_version: Some(self.max_gl_version).filter(|&version| version > self.gl_version),
+            required_extensions: self.required_extensions.clone(),
+            samples: get_config_samples(egl_display, egl_config),
         }
     }
 }
@@ -482,11 +603,45 @@ pub(crate) unsafe fn create_context(
 
     let egl_config = egl_config_from_id(egl_display, descriptor.egl_config_id);
 
+    // Try the highest version first. Drivers reject versions they don't support with
+    // `EGL_BAD_MATCH` or `EGL_BAD_ATTRIBUTE`, so move on to the next one after any failure, and
+    // report the failure of the lowest.
+    let gl_versions =
+        context::gl_versions(gl_api, descriptor.gl_version, descriptor.max_gl_version);
+    let (&lowest_gl_version, higher_gl_versions) = gl_versions.split_last().unwrap();
+    for &gl_version in higher_gl_versions {
+        let result = create_context_with_version(
+            egl_display,
+            egl_config,
+            descriptor,
+            gl_version,
+            share_with,
+        );
+        if result.is_ok() {
+            return result;
+        }
+    }
+    create_context_with_version(
+        egl_display,
+        egl_config,
+        descriptor,
+        lowest_gl_version,
+        share_with,
+    )
+}
+
+unsafe fn create_context_with_version(
+    egl_display: EGLDisplay,
+    egl_config: EGLConfig,
+    descriptor: &ContextDescriptor,
+    gl_version: GLVersion,
+    share_with: EGLContext,
+) -> Result<EGLContext, Error> {
     let mut egl_context_attributes = vec![
         egl::CONTEXT_CLIENT_VERSION as EGLint,
-        descriptor.gl_version.major as EGLint,
+        gl_version.major as EGLint,
         EGL_CONTEXT_MINOR_VERSION_KHR as EGLint,
-        descriptor.gl_version.minor as EGLint,
+        gl_version.minor as EGLint,
     ];
 
     // D3D11 ANGLE doesn't seem happy if EGL_CONTEXT_OPENGL_PROFILE_MASK is set
@@ -498,6 +653,10 @@ pub(crate) unsafe fn create_context(
         ]);
     }
 
+    if descriptor.debug {
+        egl_context_attributes.extend(&[egl::CONTEXT_OPENGL_DEBUG as EGLint, egl::TRUE as EGLint]);
+    }
+
     // Include some extra zeroes to work around broken implementations.
     //
     // FIXME(pcwalton): Which implementations are those? (This is copied from Gecko.)
@@ -549,6 +708,103 @@ pub(crate) unsafe fn get_config_attr(
     })
 }
 
+// Returns the number of samples per pixel of window surfaces created with the given config.
+// Single-sampled configs have 0 samples, which we report as 1.
+pub(crate) unsafe fn get_config_samples(egl_display: EGLDisplay, egl_config: EGLConfig) -> u32 {
+    get_config_attr(egl_display, egl_config, egl::SAMPLES as EGLint).max(1) as u32
+}
+
+// Returns the first config matching the requested attributes whose values of the required
+// attributes are exactly those given.
+unsafe fn choose_config(
+    egl_display: EGLDisplay,
+    requested_config_attributes: &[EGLint],
+    required_config_attributes: &[EGLint],
+) -> Result<EGLConfig, Error> {
+    EGL_FUNCTIONS.with(|egl| {
+        // See how many applicable configs there are.
+        let mut config_count = 0;
+        let result = egl.ChooseConfig(
+            egl_display,
+            requested_config_attributes.as_ptr(),
+            ptr::null_mut(),
+            0,
+            &mut config_count,
+        );
+        if result == egl::FALSE {
+            let err = egl.GetError().to_windowing_api_error();
+            return Err(Error::PixelFormatSelectionFailed(err));
+        }
+        if config_count == 0 {
+            return Err(Error::NoPixelFormatFound);
+        }
+
+        // Enumerate all those configs.
+        let mut configs = vec![ptr::null(); config_count as usize];
+        let mut real_config_count = config_count;
+        let result = egl.ChooseConfig(
+            egl_display,
+            requested_config_attributes.as_ptr(),
+            configs.as_mut_ptr(),
+            config_count,
+            &mut real_config_count,
+        );
+        if result == egl::FALSE {
+            let err = egl.GetError().to_windowing_api_error();
+            return Err(Error::PixelFormatSelectionFailed(err));
+        }
+
+        // Sanitize configs.
+        configs
+            .into_iter()
+            .find(|&egl_config| {
+                required_config_attributes
+                    .chunks(2)
+                    .all(|pair| get_config_attr(egl_display, egl_config, pair[0]) == pair[1])
+            })
+            .ok_or(Error::NoPixelFormatFound)
+    })
+}
+
+// Returns a single-sampled config with the same color, depth, and stencil sizes as the given one,
+// or the given config itself if it isn't multisampled. Generic surfaces are allocated from it, so
+// that their contents can be sampled and shared without a multisample resolve.
+#[cfg(angle)]
+pub(crate) unsafe fn single_sampled_config(
+    egl_display: EGLDisplay,
+    egl_config: EGLConfig,
+    extra_config_attributes: &[EGLint],
+) -> Result<EGLConfig, Error> {
+    if get_config_samples(egl_display, egl_config) == 1 {
+        return Ok(egl_config);
+    }
+
+    let mut required_config_attributes = vec![];
+    for &attr in &[
+        egl::RED_SIZE,
+        egl::GREEN_SIZE,
+        egl::BLUE_SIZE,
+        egl::ALPHA_SIZE,
+        egl::DEPTH_SIZE,
+        egl::STENCIL_SIZE,
+    ] {
+        let attr = attr as EGLint;
+        required_config_attributes
+            .extend_from_slice(&[attr, get_config_attr(egl_display, egl_config, attr)]);
+    }
+    required_config_attributes.extend_from_slice(&[egl::SAMPLE_BUFFERS as EGLint, 0]);
+
+    let mut requested_config_attributes = required_config_attributes.clone();
+    requested_config_attributes.extend_from_slice(extra_config_attributes);
+    requested_config_attributes.extend_from_slice(&[egl::NONE as EGLint, 0, 0, 0]);
+
+    choose_config(
+        egl_display,
+        &requested_config_attributes,
+        &required_config_attributes,
+    )
+}
+
 pub(crate) unsafe fn get_context_attr(
     egl_display: EGLDisplay,
     egl_context: EGLContext,
@@ -2,11 +2,34 @@
 //
 //! Functionality common to backends using EGL displays.
 
+use crate::egl;
+#[cfg(any(android, linux))]
+use crate::egl::types::EGLAttrib;
+use crate::egl::types::{EGLDisplay, EGLint};
 use crate::egl::Egl;
+#[cfg(any(android, linux))]
+use crate::platform::generic::egl::ffi::EGL_EXTENSION_FUNCTIONS;
+#[cfg(any(android, linux))]
+use crate::platform::generic::egl::ffi::EGL_NO_NATIVE_FENCE_FD_ANDROID;
+#[cfg(any(android, linux))]
+use crate::platform::generic::egl::ffi::EGL_SYNC_NATIVE_FENCE_ANDROID;
+#[cfg(any(android, linux))]
+use crate::platform::generic::egl::ffi::EGL_SYNC_NATIVE_FENCE_FD_ANDROID;
+use crate::Error;
 
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
 use std::mem;
 use std::os::raw::{c_char, c_void};
+#[cfg(any(android, linux))]
+use std::os::unix::io::RawFd;
+use std::path::Path;
+#[cfg(any(android, linux))]
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+#[cfg(not(target_os = "windows"))]
+use std::os::unix::ffi::OsStrExt;
 
 #[cfg(not(target_os = "windows"))]
 use libc::{dlopen, dlsym, RTLD_LAZY};
@@ -21,9 +44,19 @@ thread_local! {
     pub static EGL_FUNCTIONS: Egl = Egl::load_with(get_proc_address);
 }
 
+lazy_static! {
+    // An EGL implementation that the application supplied to use instead of the system's.
+    static ref SOFTWARE_EGL_LIBRARY: Mutex<Option<EGLLibraryWrapper>> = Mutex::new(None);
+}
+
+static EGL_LIBRARY_LOADED: AtomicBool = AtomicBool::new(false);
+
 #[cfg(target_os = "windows")]
 lazy_static! {
     static ref EGL_LIBRARY: EGLLibraryWrapper = {
+        if let Some(library) = take_software_egl_library() {
+            return library;
+        }
         unsafe {
             let module = libloaderapi::LoadLibraryA(&b"libEGL.dll\0"[0] as *const u8 as LPCSTR);
             EGLLibraryWrapper(module)
@@ -34,6 +67,9 @@ lazy_static! {
 #[cfg(not(any(target_os = "windows", target_os = "macos")))]
 lazy_static! {
     static ref EGL_LIBRARY: EGLLibraryWrapper = {
+        if let Some(library) = take_software_egl_library() {
+            return library;
+        }
         unsafe {
             EGLLibraryWrapper(dlopen(
                 &b"libEGL.so\0"[0] as *const u8 as *const _,
@@ -51,6 +87,62 @@ struct EGLLibraryWrapper(*mut c_void);
 unsafe impl Send for EGLLibraryWrapper {}
 unsafe impl Sync for EGLLibraryWrapper {}
 
+/// Loads the EGL implementation at the given path, such as SwiftShader or ANGLE's null backend,
+/// for the EGL backends to use instead of the system's `libEGL`.
+///
+/// This gives applications that bundle such a library a conformant OpenGL ES software renderer
+/// that goes through the same code paths as hardware rendering, for use when they would
+/// otherwise call `create_software_adapter()`. EGL is loaded only once per process, so this must
+/// be called before the first connection is opened, and every adapter renders with the library
+/// afterward. `Adapter::vendor()` reports it on backends that support that.
+///
+/// If EGL has already been loaded, this returns `Error::Failed`. If the library can't be loaded,
+/// this returns `Error::NoGLLibraryFound`.
+pub fn set_software_egl_library(path: &Path) -> Result<(), Error> {
+    let mut software_egl_library = SOFTWARE_EGL_LIBRARY.lock().unwrap();
+    if EGL_LIBRARY_LOADED.load(Ordering::SeqCst) {
+        return Err(Error::Failed);
+    }
+
+    let library = unsafe { load_library(path) };
+    match library {
+        Some(library) => {
+            *software_egl_library = Some(library);
+            Ok(())
+        }
+        None => Err(Error::NoGLLibraryFound),
+    }
+}
+
+// Called when EGL is first loaded. Any library set afterward would be ignored, so this also
+// closes the window for `set_software_egl_library()`.
+fn take_software_egl_library() -> Option<EGLLibraryWrapper> {
+    EGL_LIBRARY_LOADED.store(true, Ordering::SeqCst);
+    SOFTWARE_EGL_LIBRARY.lock().unwrap().take()
+}
+
+#[cfg(target_os = "windows")]
+unsafe fn load_library(path: &Path) -> Option<EGLLibraryWrapper> {
+    let path = CString::new(path.to_str()?).ok()?;
+    let module = libloaderapi::LoadLibraryA(path.as_ptr() as LPCSTR);
+    if module.is_null() {
+        None
+    } else {
+        Some(EGLLibraryWrapper(module))
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+unsafe fn load_library(path: &Path) -> Option<EGLLibraryWrapper> {
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let library = dlopen(path.as_ptr(), RTLD_LAZY);
+    if library.is_null() {
+        None
+    } else {
+        Some(EGLLibraryWrapper(library))
+    }
+}
+
 #[cfg(target_os = "windows")]
 fn get_proc_address(symbol_name: &str) -> *const c_void {
     unsafe {
@@ -73,3 +165,162 @@ pub(crate) unsafe fn lookup_egl_extension(name: &'static [u8]) -> *mut c_void {
     EGL_FUNCTIONS
         .with(|egl| mem::transmute(egl.GetProcAddress(&name[0] as *const u8 as *const c_char)))
 }
+
+// Returns true if the given EGL display supports the named extension.
+pub(crate) unsafe fn display_has_extension(egl_display: EGLDisplay, extension: &[u8]) -> bool {
+    EGL_FUNCTIONS.with(|egl| {
+        let extensions = egl.QueryString(egl_display, egl::EXTENSIONS as EGLint);
+        !extensions.is_null()
+            && CStr::from_ptr(extensions)
+                .to_bytes()
+                .split(|&byte| byte == b' ')
+                .any(|name| name == extension)
+    })
+}
+
+// Returns the `EGL_VENDOR` string of the given initialized display.
+//
+// Under libglvnd, this identifies the vendor library that the display was dispatched to, for
+// example "NVIDIA" or "Mesa Project".
+pub(crate) unsafe fn query_display_vendor(egl_display: EGLDisplay) -> Option<String> {
+    EGL_FUNCTIONS.with(|egl| {
+        let vendor = egl.QueryString(egl_display, egl::VENDOR as EGLint);
+        if vendor.is_null() {
+            return None;
+        }
+        Some(CStr::from_ptr(vendor).to_string_lossy().into_owned())
+    })
+}
+
+// Returns the DRM format modifiers that the given EGL display can import DMA-BUFs of the given
+// fourcc with, excluding those that are only usable with `GL_TEXTURE_EXTERNAL_OES`.
+//
+// This requires `EGL_EXT_image_dma_buf_import_modifiers`; without it, the list is empty.
+#[cfg(any(android, linux))]
+pub(crate) unsafe fn query_dma_buf_modifiers(egl_display: EGLDisplay, fourcc: u32) -> Vec<u64> {
+    let query_modifiers = match EGL_EXTENSION_FUNCTIONS.QueryDmaBufModifiersEXT {
+        Some(query_modifiers)
+            if display_has_extension(egl_display, b"EGL_EXT_image_dma_buf_import_modifiers") =>
+        {
+            query_modifiers
+        }
+        _ => return vec![],
+    };
+
+    let mut modifier_count = 0;
+    let result = query_modifiers(
+        egl_display,
+        fourcc as EGLint,
+        0,
+        ptr::null_mut(),
+        ptr::null_mut(),
+        &mut modifier_count,
+    );
+    if result == egl::FALSE || modifier_count <= 0 {
+        return vec![];
+    }
+
+    let mut modifiers = vec![0; modifier_count as usize];
+    let mut external_only = vec![egl::FALSE; modifier_count as usize];
+    let result = query_modifiers(
+        egl_display,
+        fourcc as EGLint,
+        modifier_count,
+        modifiers.as_mut_ptr(),
+        external_only.as_mut_ptr(),
+        &mut modifier_count,
+    );
+    if result == egl::FALSE {
+        return vec![];
+    }
+
+    modifiers
+        .into_iter()
+        .zip(external_only)
+        .take(modifier_count as usize)
+        .filter(|&(_, external_only)| external_only == egl::FALSE)
+        .map(|(modifier, _)| modifier)
+        .collect()
+}
+
+// Returns true if native fences can be exported from the given display as sync files.
+#[cfg(any(android, linux))]
+pub(crate) unsafe fn supports_native_fences(egl_display: EGLDisplay) -> bool {
+    if EGL_EXTENSION_FUNCTIONS.DupNativeFenceFDANDROID.is_none() {
+        return false;
+    }
+    display_has_extension(egl_display, b"EGL_ANDROID_native_fence_sync")
+}
+
+// Creates a sync file that signals when the rendering submitted so far has finished.
+#[cfg(any(android, linux))]
+pub(crate) unsafe fn create_native_fence(
+    egl_display: EGLDisplay,
+    native_fences: bool,
+) -> Option<RawFd> {
+    let dup_native_fence_fd = match EGL_EXTENSION_FUNCTIONS.DupNativeFenceFDANDROID {
+        Some(dup_native_fence_fd) if native_fences => dup_native_fence_fd,
+        _ => return None,
+    };
+
+    EGL_FUNCTIONS.with(|egl| {
+        let attributes = [
+            EGL_SYNC_NATIVE_FENCE_FD_ANDROID as EGLAttrib,
+            EGL_NO_NATIVE_FENCE_FD_ANDROID as EGLAttrib,
+            egl::NONE as EGLAttrib,
+        ];
+        let sync = egl.CreateSync(
+            egl_display,
+            EGL_SYNC_NATIVE_FENCE_ANDROID,
+            attributes.as_ptr(),
+        );
+        if sync == egl::NO_SYNC {
+            return None;
+        }
+
+        // The fence only gets a file descriptor once it has been flushed to the GPU.
+        egl.ClientWaitSync(egl_display, sync, egl::SYNC_FLUSH_COMMANDS_BIT as EGLint, 0);
+        let fence_fd = dup_native_fence_fd(egl_display, sync);
+        egl.DestroySync(egl_display, sync);
+
+        if fence_fd == EGL_NO_NATIVE_FENCE_FD_ANDROID {
+            None
+        } else {
+            Some(fence_fd)
+        }
+    })
+}
+
+// Blocks until the rendering submitted so far has finished.
+#[cfg(any(android, linux))]
+pub(crate) unsafe fn finish_rendering(egl_display: EGLDisplay) {
+    EGL_FUNCTIONS.with(|egl| {
+        let attributes = [egl::NONE as EGLAttrib];
+        let sync = egl.CreateSync(egl_display, egl::SYNC_FENCE, attributes.as_ptr());
+        if sync == egl::NO_SYNC {
+            return;
+        }
+        egl.ClientWaitSync(
+            egl_display,
+            sync,
+            egl::SYNC_FLUSH_COMMANDS_BIT as EGLint,
+            egl::FOREVER,
+        );
+        egl.DestroySync(egl_display, sync);
+    })
+}
+
+// An EGL display that the device initialized, and that is terminated once the last clone of the
+// device is dropped.
+#[cfg(any(android, angle, ohos))]
+pub(crate) struct OwnedEGLDisplay(pub(crate) EGLDisplay);
+
+#[cfg(any(android, angle, ohos))]
+impl Drop for OwnedEGLDisplay {
+    fn drop(&mut self) {
+        EGL_FUNCTIONS.with(|egl| unsafe {
+            let result = egl.Terminate(self.0);
+            assert_ne!(result, egl::FALSE);
+        })
+    }
+}
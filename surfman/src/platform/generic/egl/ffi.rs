@@ -5,9 +5,12 @@
 #![allow(dead_code)]
 
 use crate::egl::types::{EGLAttrib, EGLBoolean, EGLContext, EGLDeviceEXT, EGLDisplay, EGLSurface};
+use crate::egl::types::{EGLSync, EGLuint64KHR};
 use crate::egl::types::{EGLenum, EGLint};
+use crate::platform::generic::egl::device::lookup_egl_extension;
 
-use std::os::raw::c_void;
+use std::mem;
+use std::os::raw::{c_char, c_int, c_void};
 
 pub enum EGLClientBufferOpaque {}
 pub type EGLClientBuffer = *mut EGLClientBufferOpaque;
@@ -15,83 +18,243 @@ pub type EGLClientBuffer = *mut EGLClientBufferOpaque;
 pub enum EGLImageKHROpaque {}
 pub type EGLImageKHR = *mut EGLImageKHROpaque;
 
+pub const EGL_NATIVE_PIXMAP_KHR: EGLenum = 0x30b0;
 pub const EGL_GL_TEXTURE_2D_KHR: EGLenum = 0x30b1;
 pub const EGL_IMAGE_PRESERVED_KHR: EGLenum = 0x30d2;
 pub const EGL_CONTEXT_MINOR_VERSION_KHR: EGLenum = 0x30fb;
 pub const EGL_CONTEXT_OPENGL_PROFILE_MASK: EGLenum = 0x30fd;
+pub const EGL_DRM_DEVICE_FILE_EXT: EGLenum = 0x3233;
+pub const EGL_BUFFER_AGE_KHR: EGLenum = 0x313d;
 pub const EGL_PLATFORM_DEVICE_EXT: EGLenum = 0x313f;
 pub const EGL_NATIVE_BUFFER_ANDROID: EGLenum = 0x3140;
+pub const EGL_SYNC_NATIVE_FENCE_ANDROID: EGLenum = 0x3144;
+pub const EGL_SYNC_NATIVE_FENCE_FD_ANDROID: EGLenum = 0x3145;
 pub const EGL_PLATFORM_X11_KHR: EGLenum = 0x31d5;
+pub const EGL_PLATFORM_GBM_KHR: EGLenum = 0x31d7;
 pub const EGL_PLATFORM_WAYLAND_KHR: EGLenum = 0x31d8;
 pub const EGL_PLATFORM_SURFACELESS_MESA: EGLenum = 0x31dd;
+pub const EGL_PRESENT_OPAQUE_EXT: EGLenum = 0x31df;
 pub const EGL_D3D_TEXTURE_2D_SHARE_HANDLE_ANGLE: EGLenum = 0x3200;
+pub const EGL_LINUX_DMA_BUF_EXT: EGLenum = 0x3270;
+pub const EGL_LINUX_DRM_FOURCC_EXT: EGLenum = 0x3271;
 pub const EGL_BAD_DEVICE_EXT: EGLenum = 0x322b;
 pub const EGL_DEVICE_EXT: EGLenum = 0x322c;
+pub const EGL_COLOR_COMPONENT_TYPE_EXT: EGLenum = 0x3339;
+pub const EGL_COLOR_COMPONENT_TYPE_FLOAT_EXT: EGLenum = 0x333b;
+pub const EGL_DRM_RENDER_NODE_FILE_EXT: EGLenum = 0x3377;
 pub const EGL_D3D11_DEVICE_ANGLE: EGLenum = 0x33a1;
 pub const EGL_DXGI_KEYED_MUTEX_ANGLE: EGLenum = 0x33a2;
 pub const EGL_D3D_TEXTURE_ANGLE: EGLenum = 0x33a3;
+pub const EGL_DIRECT_COMPOSITION_ANGLE: EGLenum = 0x33a5;
+pub const EGL_D3D11_TEXTURE_ANGLE: EGLenum = 0x3484;
+
+// The `EGL_DMA_BUF_PLANE{n}_{FD,OFFSET,PITCH,MODIFIER_LO,MODIFIER_HI}_EXT` attributes, by plane.
+pub const EGL_DMA_BUF_PLANE_FD_EXT: [EGLenum; 4] = [0x3272, 0x3275, 0x3278, 0x3440];
+pub const EGL_DMA_BUF_PLANE_OFFSET_EXT: [EGLenum; 4] = [0x3273, 0x3276, 0x3279, 0x3441];
+pub const EGL_DMA_BUF_PLANE_PITCH_EXT: [EGLenum; 4] = [0x3274, 0x3277, 0x327a, 0x3442];
+pub const EGL_DMA_BUF_PLANE_MODIFIER_LO_EXT: [EGLenum; 4] = [0x3443, 0x3445, 0x3447, 0x3449];
+pub const EGL_DMA_BUF_PLANE_MODIFIER_HI_EXT: [EGLenum; 4] = [0x3444, 0x3446, 0x3448, 0x344a];
+
+// The modifier that stands for "whatever layout the driver picked implicitly".
+pub const DRM_FORMAT_MOD_INVALID: u64 = 0x00ff_ffff_ffff_ffff;
+// The modifier of images stored row by row, with no tiling or compression.
+pub const DRM_FORMAT_MOD_LINEAR: u64 = 0;
+
+// The DRM fourccs whose channels are stored in blue, green, red order.
+pub const DRM_FORMAT_ARGB8888: u32 = 0x3432_5241;
+pub const DRM_FORMAT_XRGB8888: u32 = 0x3432_5258;
 
 pub const EGL_NO_DEVICE_EXT: EGLDeviceEXT = 0 as EGLDeviceEXT;
 pub const EGL_NO_IMAGE_KHR: EGLImageKHR = 0 as EGLImageKHR;
 
+pub const EGL_NO_NATIVE_FENCE_FD_ANDROID: EGLint = -1;
+
+pub const EGL_MUTABLE_RENDER_BUFFER_BIT_KHR: EGLint = 0x1000;
+
 pub const EGL_CONTEXT_OPENGL_CORE_PROFILE_BIT: EGLint = 1;
 pub const EGL_CONTEXT_OPENGL_COMPATIBILITY_PROFILE_BIT: EGLint = 2;
 
+type CreateImageKHRFn = extern "C" fn(
+    dpy: EGLDisplay,
+    ctx: EGLContext,
+    target: EGLenum,
+    buffer: EGLClientBuffer,
+    attrib_list: *const EGLint,
+) -> EGLImageKHR;
+type DestroyImageKHRFn = extern "C" fn(dpy: EGLDisplay, image: EGLImageKHR) -> EGLBoolean;
+type ImageTargetTexture2DOESFn = extern "C" fn(target: EGLenum, image: EGLImageKHR);
+type CreateDeviceANGLEFn = extern "C" fn(
+    device_type: EGLint,
+    native_device: *mut c_void,
+    attrib_list: *const EGLAttrib,
+) -> EGLDeviceEXT;
+type DupNativeFenceFDANDROIDFn = extern "C" fn(dpy: EGLDisplay, sync: EGLSync) -> EGLint;
+type ExportDMABUFImageMESAFn = extern "C" fn(
+    dpy: EGLDisplay,
+    image: EGLImageKHR,
+    fds: *mut c_int,
+    strides: *mut EGLint,
+    offsets: *mut EGLint,
+) -> EGLBoolean;
+type ExportDMABUFImageQueryMESAFn = extern "C" fn(
+    dpy: EGLDisplay,
+    image: EGLImageKHR,
+    fourcc: *mut c_int,
+    num_planes: *mut c_int,
+    modifiers: *mut EGLuint64KHR,
+) -> EGLBoolean;
+type GetNativeClientBufferANDROIDFn = extern "C" fn(buffer: *const c_void) -> EGLClientBuffer;
+type PresentationTimeANDROIDFn =
+    extern "C" fn(dpy: EGLDisplay, surface: EGLSurface, time: i64) -> EGLBoolean;
+type QueryDeviceAttribEXTFn =
+    extern "C" fn(device: EGLDeviceEXT, attribute: EGLint, value: *mut EGLAttrib) -> EGLBoolean;
+type QueryDeviceStringEXTFn = extern "C" fn(device: EGLDeviceEXT, name: EGLint) -> *const c_char;
+type QueryDmaBufFormatsEXTFn = extern "C" fn(
+    dpy: EGLDisplay,
+    max_formats: EGLint,
+    formats: *mut EGLint,
+    num_formats: *mut EGLint,
+) -> EGLBoolean;
+type QueryDmaBufModifiersEXTFn = extern "C" fn(
+    dpy: EGLDisplay,
+    format: EGLint,
+    max_modifiers: EGLint,
+    modifiers: *mut EGLuint64KHR,
+    external_only: *mut EGLBoolean,
+    num_modifiers: *mut EGLint,
+) -> EGLBoolean;
+type QueryDevicesEXTFn = extern "C" fn(
+    max_devices: EGLint,
+    devices: *mut EGLDeviceEXT,
+    num_devices: *mut EGLint,
+) -> EGLBoolean;
+type QueryDisplayAttribEXTFn =
+    extern "C" fn(dpy: EGLDisplay, attribute: EGLint, value: *mut EGLAttrib) -> EGLBoolean;
+type QuerySurfacePointerANGLEFn = extern "C" fn(
+    dpy: EGLDisplay,
+    surface: EGLSurface,
+    attribute: EGLint,
+    value: *mut *mut c_void,
+) -> EGLBoolean;
+type SetDamageRegionKHRFn = extern "C" fn(
+    dpy: EGLDisplay,
+    surface: EGLSurface,
+    rects: *mut EGLint,
+    n_rects: EGLint,
+) -> EGLBoolean;
+type SwapBuffersWithDamageEXTFn = extern "C" fn(
+    dpy: EGLDisplay,
+    surface: EGLSurface,
+    rects: *const EGLint,
+    n_rects: EGLint,
+) -> EGLBoolean;
+type SwapBuffersWithDamageKHRFn = extern "C" fn(
+    dpy: EGLDisplay,
+    surface: EGLSurface,
+    rects: *const EGLint,
+    n_rects: EGLint,
+) -> EGLBoolean;
+
 #[allow(non_snake_case)]
 pub(crate) struct EGLExtensionFunctions {
     // Ubiquitous extensions assumed to be present
-    pub(crate) CreateImageKHR: extern "C" fn(
-        dpy: EGLDisplay,
-        ctx: EGLContext,
-        target: EGLenum,
-        buffer: EGLClientBuffer,
-        attrib_list: *const EGLint,
-    ) -> EGLImageKHR,
-    pub(crate) DestroyImageKHR: extern "C" fn(dpy: EGLDisplay, image: EGLImageKHR) -> EGLBoolean,
-    pub(crate) ImageTargetTexture2DOES: extern "C" fn(target: EGLenum, image: EGLImageKHR),
+    pub(crate) CreateImageKHR: CreateImageKHRFn,
+    pub(crate) DestroyImageKHR: DestroyImageKHRFn,
+    pub(crate) ImageTargetTexture2DOES: ImageTargetTexture2DOESFn,
 
     // Optional extensions
-    pub(crate) CreateDeviceANGLE: Option<
-        extern "C" fn(
-            device_type: EGLint,
-            native_device: *mut c_void,
-            attrib_list: *const EGLAttrib,
-        ) -> EGLDeviceEXT,
-    >,
-    pub(crate) GetNativeClientBufferANDROID:
-        Option<extern "C" fn(buffer: *const c_void) -> EGLClientBuffer>,
-    pub(crate) QueryDeviceAttribEXT: Option<
-        extern "C" fn(device: EGLDeviceEXT, attribute: EGLint, value: *mut EGLAttrib) -> EGLBoolean,
-    >,
-    pub(crate) QueryDisplayAttribEXT: Option<
-        extern "C" fn(dpy: EGLDisplay, attribute: EGLint, value: *mut EGLAttrib) -> EGLBoolean,
-    >,
-    pub(crate) QuerySurfacePointerANGLE: Option<
-        extern "C" fn(
-            dpy: EGLDisplay,
-            surface: EGLSurface,
-            attribute: EGLint,
-            value: *mut *mut c_void,
-        ) -> EGLBoolean,
-    >,
+    pub(crate) CreateDeviceANGLE: Option<CreateDeviceANGLEFn>,
+    pub(crate) DupNativeFenceFDANDROID: Option<DupNativeFenceFDANDROIDFn>,
+    pub(crate) ExportDMABUFImageMESA: Option<ExportDMABUFImageMESAFn>,
+    pub(crate) ExportDMABUFImageQueryMESA: Option<ExportDMABUFImageQueryMESAFn>,
+    pub(crate) GetNativeClientBufferANDROID: Option<GetNativeClientBufferANDROIDFn>,
+    pub(crate) PresentationTimeANDROID: Option<PresentationTimeANDROIDFn>,
+    pub(crate) QueryDeviceAttribEXT: Option<QueryDeviceAttribEXTFn>,
+    pub(crate) QueryDeviceStringEXT: Option<QueryDeviceStringEXTFn>,
+    pub(crate) QueryDmaBufFormatsEXT: Option<QueryDmaBufFormatsEXTFn>,
+    pub(crate) QueryDmaBufModifiersEXT: Option<QueryDmaBufModifiersEXTFn>,
+    pub(crate) QueryDevicesEXT: Option<QueryDevicesEXTFn>,
+    pub(crate) QueryDisplayAttribEXT: Option<QueryDisplayAttribEXTFn>,
+    pub(crate) QuerySurfacePointerANGLE: Option<QuerySurfacePointerANGLEFn>,
+    pub(crate) SetDamageRegionKHR: Option<SetDamageRegionKHRFn>,
+    pub(crate) SwapBuffersWithDamageEXT: Option<SwapBuffersWithDamageEXTFn>,
+    pub(crate) SwapBuffersWithDamageKHR: Option<SwapBuffersWithDamageKHRFn>,
+}
+
+macro_rules! load_extension {
+    ($name:expr, $ty:ty) => {
+        mem::transmute::<*mut c_void, $ty>(lookup_egl_extension(concat!($name, "\0").as_bytes()))
+    };
 }
 
 lazy_static! {
-    pub(crate) static ref EGL_EXTENSION_FUNCTIONS: EGLExtensionFunctions = {
-        use crate::platform::generic::egl::device::lookup_egl_extension as get;
-        use std::mem::transmute as cast;
-        unsafe {
-            EGLExtensionFunctions {
-                CreateImageKHR: cast(get(b"eglCreateImageKHR\0")),
-                DestroyImageKHR: cast(get(b"eglDestroyImageKHR\0")),
-                ImageTargetTexture2DOES: cast(get(b"glEGLImageTargetTexture2DOES\0")),
-
-                CreateDeviceANGLE: cast(get(b"eglCreateDeviceANGLE\0")),
-                GetNativeClientBufferANDROID: cast(get(b"eglGetNativeClientBufferANDROID\0")),
-                QueryDeviceAttribEXT: cast(get(b"eglQueryDeviceAttribEXT\0")),
-                QueryDisplayAttribEXT: cast(get(b"eglQueryDisplayAttribEXT\0")),
-                QuerySurfacePointerANGLE: cast(get(b"eglQuerySurfacePointerANGLE\0")),
-            }
+    pub(crate) static ref EGL_EXTENSION_FUNCTIONS: EGLExtensionFunctions = unsafe {
+        EGLExtensionFunctions {
+            CreateImageKHR: load_extension!("eglCreateImageKHR", CreateImageKHRFn),
+            DestroyImageKHR: load_extension!("eglDestroyImageKHR", DestroyImageKHRFn),
+            ImageTargetTexture2DOES: load_extension!(
+                "glEGLImageTargetTexture2DOES",
+                ImageTargetTexture2DOESFn
+            ),
+
+            CreateDeviceANGLE: load_extension!("eglCreateDeviceANGLE", Option<CreateDeviceANGLEFn>),
+            DupNativeFenceFDANDROID: load_extension!(
+                "eglDupNativeFenceFDANDROID",
+                Option<DupNativeFenceFDANDROIDFn>
+            ),
+            ExportDMABUFImageMESA: load_extension!(
+                "eglExportDMABUFImageMESA",
+                Option<ExportDMABUFImageMESAFn>
+            ),
+            ExportDMABUFImageQueryMESA: load_extension!(
+                "eglExportDMABUFImageQueryMESA",
+                Option<ExportDMABUFImageQueryMESAFn>
+            ),
+            GetNativeClientBufferANDROID: load_extension!(
+                "eglGetNativeClientBufferANDROID",
+                Option<GetNativeClientBufferANDROIDFn>
+            ),
+            PresentationTimeANDROID: load_extension!(
+                "eglPresentationTimeANDROID",
+                Option<PresentationTimeANDROIDFn>
+            ),
+            QueryDeviceAttribEXT: load_extension!(
+                "eglQueryDeviceAttribEXT",
+                Option<QueryDeviceAttribEXTFn>
+            ),
+            QueryDeviceStringEXT: load_extension!(
+                "eglQueryDeviceStringEXT",
+                Option<QueryDeviceStringEXTFn>
+            ),
+            QueryDmaBufFormatsEXT: load_extension!(
+                "eglQueryDmaBufFormatsEXT",
+                Option<QueryDmaBufFormatsEXTFn>
+            ),
+            QueryDmaBufModifiersEXT: load_extension!(
+                "eglQueryDmaBufModifiersEXT",
+                Option<QueryDmaBufModifiersEXTFn>
+            ),
+            QueryDevicesEXT: load_extension!("eglQueryDevicesEXT", Option<QueryDevicesEXTFn>),
+            QueryDisplayAttribEXT: load_extension!(
+                "eglQueryDisplayAttribEXT",
+                Option<QueryDisplayAttribEXTFn>
+            ),
+            QuerySurfacePointerANGLE: load_extension!(
+                "eglQuerySurfacePointerANGLE",
+                Option<QuerySurfacePointerANGLEFn>
+            ),
+            SetDamageRegionKHR: load_extension!(
+                "eglSetDamageRegionKHR",
+                Option<SetDamageRegionKHRFn>
+            ),
+            SwapBuffersWithDamageEXT: load_extension!(
+                "eglSwapBuffersWithDamageEXT",
+                Option<SwapBuffersWithDamageEXTFn>
+            ),
+            SwapBuffersWithDamageKHR: load_extension!(
+                "eglSwapBuffersWithDamageKHR",
+                Option<SwapBuffersWithDamageKHRFn>
+            ),
         }
     };
 }
@@ -2,29 +2,50 @@
 //
 //! Functionality common to backends using EGL surfaces.
 
-use super::context::CurrentContextGuard;
-use super::device::EGL_FUNCTIONS;
+use super::context::{self, CurrentContextGuard};
+use super::device::{display_has_extension, EGL_FUNCTIONS};
 use crate::egl;
-use crate::egl::types::{EGLAttrib, EGLConfig, EGLContext, EGLDisplay, EGLSurface, EGLint};
+use crate::egl::types::EGLint;
+use crate::egl::types::{EGLAttrib, EGLBoolean, EGLConfig, EGLContext, EGLDisplay, EGLSurface};
 use crate::gl;
-use crate::gl::types::{GLint, GLuint};
+use crate::gl::types::{GLenum, GLint, GLsizei, GLuint};
 use crate::gl_utils;
 use crate::platform::generic::egl::error::ToWindowingApiError;
 use crate::platform::generic::egl::ffi::EGLClientBuffer;
 use crate::platform::generic::egl::ffi::EGLImageKHR;
+use crate::platform::generic::egl::ffi::EGL_BUFFER_AGE_KHR;
+#[cfg(linux)]
+use crate::platform::generic::egl::ffi::EGL_DMA_BUF_PLANE_MODIFIER_HI_EXT;
+#[cfg(linux)]
+use crate::platform::generic::egl::ffi::EGL_DMA_BUF_PLANE_MODIFIER_LO_EXT;
 use crate::platform::generic::egl::ffi::EGL_EXTENSION_FUNCTIONS;
 use crate::platform::generic::egl::ffi::EGL_GL_TEXTURE_2D_KHR;
 use crate::platform::generic::egl::ffi::EGL_IMAGE_PRESERVED_KHR;
 use crate::platform::generic::egl::ffi::EGL_NO_IMAGE_KHR;
+use crate::platform::generic::egl::ffi::EGL_PRESENT_OPAQUE_EXT;
+#[cfg(linux)]
+use crate::platform::generic::egl::ffi::{DRM_FORMAT_ARGB8888, DRM_FORMAT_XRGB8888};
+#[cfg(linux)]
+use crate::platform::generic::egl::ffi::{DRM_FORMAT_MOD_INVALID, EGL_LINUX_DMA_BUF_EXT};
+#[cfg(linux)]
+use crate::platform::generic::egl::ffi::{EGL_DMA_BUF_PLANE_FD_EXT, EGL_DMA_BUF_PLANE_OFFSET_EXT};
+#[cfg(linux)]
+use crate::platform::generic::egl::ffi::{EGL_DMA_BUF_PLANE_PITCH_EXT, EGL_LINUX_DRM_FOURCC_EXT};
 use crate::renderbuffers::Renderbuffers;
 use crate::Gl;
-use crate::{ContextAttributes, ContextID, Error, SurfaceID, SurfaceInfo};
+#[cfg(linux)]
+use crate::WindowingApiError;
+use crate::{Colorspace, SurfaceAlphaMode, SurfaceOrigin, SurfaceTransform};
+use crate::{ContextAttributes, ContextID, Error, SurfaceFormat, SurfaceID, SurfaceInfo};
 
-use euclid::default::Size2D;
+use euclid::default::{Rect, Size2D};
 use std::fmt::{self, Debug, Formatter};
+use std::iter;
 use std::marker::PhantomData;
 use std::mem;
 use std::os::raw::c_void;
+#[cfg(any(android, linux))]
+use std::os::unix::io::RawFd;
 use std::ptr;
 
 #[allow(dead_code)]
@@ -35,9 +56,20 @@ pub(crate) struct ExternalEGLSurfaces {
 }
 
 pub struct EGLBackedSurface {
+    pub(crate) id: SurfaceID,
     pub(crate) context_id: ContextID,
     pub(crate) size: Size2D<i32>,
     pub(crate) objects: EGLSurfaceObjects,
+    // The order of the channels in memory. See `SurfaceInfo::format`.
+    pub(crate) format: SurfaceFormat,
+    // Which way up the contents are. See `SurfaceInfo::origin`.
+    pub(crate) origin: SurfaceOrigin,
+    // How the alpha channel is interpreted. See `SurfaceInfo::alpha_mode`.
+    pub(crate) alpha_mode: SurfaceAlphaMode,
+    // The color space of the contents. See `SurfaceInfo::colorspace`.
+    pub(crate) colorspace: Colorspace,
+    // The number of samples per pixel. See `SurfaceInfo::samples`.
+    pub(crate) samples: u32,
     pub(crate) destroyed: bool,
 }
 
@@ -56,6 +88,9 @@ pub(crate) enum EGLSurfaceObjects {
         framebuffer_object: GLuint,
         texture_object: GLuint,
         renderbuffers: Renderbuffers,
+        // The layers after the first of a surface made with `new_generic_layered()`. EGL can only
+        // share single images, so each layer is an image of its own.
+        extra_layers: Vec<EGLImageLayer>,
     },
     Window {
         native_window: *const c_void,
@@ -63,9 +98,58 @@ pub(crate) enum EGLSurfaceObjects {
     },
 }
 
+pub(crate) struct EGLImageLayer {
+    egl_image: EGLImageKHR,
+    texture_object: GLuint,
+}
+
+/// The DMA-BUF planes that make up the image of a generic surface.
+///
+/// The file descriptors are owned, and closed when this is dropped. To keep one open, take its
+/// plane out of `planes` first.
+#[cfg(any(android, linux))]
+pub struct EGLDmaBuf {
+    /// The DRM fourcc code of the pixel format, such as `DRM_FORMAT_ABGR8888`.
+    pub fourcc: u32,
+    /// The DRM format modifier describing the memory layout (tiling, compression) of the image.
+    pub modifier: u64,
+    /// The planes of the image, in order.
+    pub planes: Vec<EGLDmaBufPlane>,
+}
+
+/// A single plane of an exported DMA-BUF image.
+#[cfg(any(android, linux))]
+pub struct EGLDmaBufPlane {
+    /// The DMA-BUF file descriptor holding this plane.
+    pub fd: RawFd,
+    /// The offset of the plane within the buffer, in bytes.
+    pub offset: u32,
+    /// The number of bytes between the starts of consecutive rows of the plane.
+    pub stride: u32,
+}
+
+// The parts of an `EGLBackedSurface` that don't belong to its context, and so can be destroyed
+// without it.
+pub(crate) struct EGLSurfaceDestroyToken {
+    pub(crate) id: SurfaceID,
+    objects: EGLDestroyTokenObjects,
+}
+
+enum EGLDestroyTokenObjects {
+    Images(Vec<EGLImageKHR>),
+    Window {
+        native_window: *const c_void,
+        egl_surface: EGLSurface,
+    },
+}
+
+unsafe impl Send for EGLSurfaceDestroyToken {}
+
 pub(crate) struct EGLSurfaceTexture {
     pub(crate) surface: EGLBackedSurface,
     pub(crate) texture_object: GLuint,
+    // `GL_TEXTURE_2D_ARRAY` for layered surfaces, and `GL_TEXTURE_2D` otherwise.
+    pub(crate) texture_target: GLenum,
     pub(crate) phantom: PhantomData<*const ()>,
 }
 
@@ -84,59 +168,44 @@ impl EGLBackedSurface {
         context_attributes: &ContextAttributes,
         size: &Size2D<i32>,
     ) -> EGLBackedSurface {
-        let egl_image_attribs = [
-            EGL_IMAGE_PRESERVED_KHR as EGLint,
-            egl::FALSE as EGLint,
-            egl::NONE as EGLint,
-            0,
-        ];
+        EGLBackedSurface::new_generic_layered(
+            gl,
+            egl_display,
+            egl_context,
+            context_id,
+            context_attributes,
+            size,
+            1,
+        )
+    }
 
+    // Creates a generic surface with the given number of layers, which must be at least 1.
+    // Rendering draws into the first until another is chosen with `set_layer()`.
+    pub(crate) fn new_generic_layered(
+        gl: &Gl,
+        egl_display: EGLDisplay,
+        egl_context: EGLContext,
+        context_id: ContextID,
+        context_attributes: &ContextAttributes,
+        size: &Size2D<i32>,
+        layers: u32,
+    ) -> EGLBackedSurface {
+        debug_assert!(layers > 0);
         unsafe {
-            // Create our texture.
-            let mut texture_object = 0;
-            gl.GenTextures(1, &mut texture_object);
-            // Save the current texture binding
-            let mut old_texture_object = 0;
-            gl.GetIntegerv(gl::TEXTURE_BINDING_2D, &mut old_texture_object);
-            gl.BindTexture(gl::TEXTURE_2D, texture_object);
-            // Unbind PIXEL_UNPACK_BUFFER, because if it is bound,
-            // it can cause errors in glTexImage2D.
-            // TODO: should this be inside a check for GL 2.0?
-            let mut unpack_buffer = 0;
-            gl.GetIntegerv(gl::PIXEL_UNPACK_BUFFER_BINDING, &mut unpack_buffer);
-            if unpack_buffer != 0 {
-                gl.BindBuffer(gl::PIXEL_UNPACK_BUFFER, 0);
-            }
-            gl.TexImage2D(
-                gl::TEXTURE_2D,
-                0,
-                gl::RGBA as GLint,
-                size.width,
-                size.height,
-                0,
-                gl::RGBA,
-                gl::UNSIGNED_BYTE,
-                ptr::null(),
-            );
-            // Restore the old bindings
-            gl.BindTexture(gl::TEXTURE_2D, old_texture_object as _);
-            if unpack_buffer != 0 {
-                gl.BindBuffer(gl::PIXEL_UNPACK_BUFFER, unpack_buffer as _);
-            }
-
-            // Create our image.
-            let egl_client_buffer = texture_object as usize as EGLClientBuffer;
-            let egl_image = (EGL_EXTENSION_FUNCTIONS.CreateImageKHR)(
-                egl_display,
-                egl_context,
-                EGL_GL_TEXTURE_2D_KHR,
-                egl_client_buffer,
-                egl_image_attribs.as_ptr(),
-            );
+            // Create our textures and images. The caller's bindings are put back afterward.
+            let mut bindings = gl_utils::BindingsGuard::new(gl)
+                .with_texture(gl::TEXTURE_2D, gl::TEXTURE_BINDING_2D);
+            let EGLImageLayer {
+                egl_image,
+                texture_object,
+            } = EGLImageLayer::new(gl, &mut bindings, egl_display, egl_context, size);
+            let extra_layers = (1..layers)
+                .map(|_| EGLImageLayer::new(gl, &mut bindings, egl_display, egl_context, size))
+                .collect();
 
-            // Create the framebuffer, and bind the texture to it.
+            // Create the framebuffer, and bind the first texture to it.
             let framebuffer_object =
-                gl_utils::create_and_bind_framebuffer(gl, gl::TEXTURE_2D, texture_object);
+                bindings.create_and_bind_framebuffer(gl::TEXTURE_2D, texture_object);
 
             // Bind renderbuffers as appropriate.
             let renderbuffers = Renderbuffers::new(gl, size, context_attributes);
@@ -148,6 +217,47 @@ impl EGLBackedSurface {
             );
 
             EGLBackedSurface {
+                id: SurfaceID::next(),
+                context_id,
+                size: *size,
+                objects: EGLSurfaceObjects::TextureImage {
+                    egl_image,
+                    framebuffer_object,
+                    texture_object,
+                    renderbuffers,
+                    extra_layers,
+                },
+                format: SurfaceFormat::RGBA8,
+                origin: SurfaceOrigin::BottomLeft,
+                alpha_mode: SurfaceAlphaMode::Premultiplied,
+                colorspace: Colorspace::SRGB,
+                samples: 1,
+                destroyed: false,
+            }
+        }
+    }
+
+    // Wraps an existing EGL image in a generic surface, which takes ownership of it. The format is
+    // the channel order of the image's storage.
+    pub(crate) fn new_from_egl_image(
+        gl: &Gl,
+        egl_image: EGLImageKHR,
+        format: SurfaceFormat,
+        context_id: ContextID,
+        context_attributes: &ContextAttributes,
+        size: &Size2D<i32>,
+    ) -> EGLBackedSurface {
+        unsafe {
+            let texture_object = bind_egl_image_to_gl_texture(gl, egl_image);
+            let mut bindings = gl_utils::BindingsGuard::new(gl);
+            let framebuffer_object =
+                bindings.create_and_bind_framebuffer(gl::TEXTURE_2D, texture_object);
+
+            let renderbuffers = Renderbuffers::new(gl, size, context_attributes);
+            renderbuffers.bind_to_current_framebuffer(gl);
+
+            EGLBackedSurface {
+                id: SurfaceID::next(),
                 context_id,
                 size: *size,
                 objects: EGLSurfaceObjects::TextureImage {
@@ -155,21 +265,101 @@ impl EGLBackedSurface {
                     framebuffer_object,
                     texture_object,
                     renderbuffers,
+                    extra_layers: vec![],
                 },
+                format,
+                origin: SurfaceOrigin::BottomLeft,
+                alpha_mode: SurfaceAlphaMode::Premultiplied,
+                colorspace: Colorspace::SRGB,
+                samples: 1,
                 destroyed: false,
             }
         }
     }
 
+    // Imports a DMA-BUF as a generic surface, which holds its own references to the buffers.
+    #[cfg(linux)]
+    pub(crate) unsafe fn new_from_dma_buf(
+        gl: &Gl,
+        egl_display: EGLDisplay,
+        dma_buf: &EGLDmaBuf,
+        context_id: ContextID,
+        context_attributes: &ContextAttributes,
+        size: &Size2D<i32>,
+    ) -> Result<EGLBackedSurface, Error> {
+        let egl_image = create_egl_image_from_dma_buf(egl_display, dma_buf, size)?;
+        let format = match dma_buf.fourcc {
+            DRM_FORMAT_ARGB8888 | DRM_FORMAT_XRGB8888 => SurfaceFormat::BGRA8,
+            _ => SurfaceFormat::RGBA8,
+        };
+        Ok(EGLBackedSurface::new_from_egl_image(
+            gl,
+            egl_image,
+            format,
+            context_id,
+            context_attributes,
+            size,
+        ))
+    }
+
+    // Wraps an existing texture of the current context in a generic surface, through an EGL image
+    // made from it. The surface renders to and shares the texture's storage, but the texture
+    // itself stays owned by the caller.
+    pub(crate) fn new_from_texture(
+        gl: &Gl,
+        egl_display: EGLDisplay,
+        egl_context: EGLContext,
+        context_id: ContextID,
+        context_attributes: &ContextAttributes,
+        size: &Size2D<i32>,
+        texture_object: GLuint,
+    ) -> Result<EGLBackedSurface, Error> {
+        let egl_image_attribs = [
+            EGL_IMAGE_PRESERVED_KHR as EGLint,
+            egl::TRUE as EGLint,
+            egl::NONE as EGLint,
+            0,
+        ];
+
+        unsafe {
+            let egl_image = (EGL_EXTENSION_FUNCTIONS.CreateImageKHR)(
+                egl_display,
+                egl_context,
+                EGL_GL_TEXTURE_2D_KHR,
+                texture_object as usize as EGLClientBuffer,
+                egl_image_attribs.as_ptr(),
+            );
+            if egl_image == EGL_NO_IMAGE_KHR {
+                let windowing_api_error =
+                    EGL_FUNCTIONS.with(|egl| egl.GetError().to_windowing_api_error());
+                return Err(Error::SurfaceCreationFailed(windowing_api_error));
+            }
+
+            Ok(EGLBackedSurface::new_from_egl_image(
+                gl,
+                egl_image,
+                SurfaceFormat::RGBA8,
+                context_id,
+                context_attributes,
+                size,
+            ))
+        }
+    }
+
     pub(crate) fn new_window(
         egl_display: EGLDisplay,
         egl_config: EGLConfig,
         native_window: *mut c_void,
         context_id: ContextID,
         size: &Size2D<i32>,
+        alpha_mode: SurfaceAlphaMode,
     ) -> EGLBackedSurface {
         EGL_FUNCTIONS.with(|egl| unsafe {
-            let window_surface_attribs = [egl::NONE as EGLAttrib];
+            let window_surface_attribs: Vec<EGLAttrib> =
+                window_surface_attributes(egl_display, alpha_mode)
+                    .into_iter()
+                    .map(|attribute| attribute as EGLAttrib)
+                    .collect();
             let egl_surface = egl.CreatePlatformWindowSurface(
                 egl_display,
                 egl_config,
@@ -177,14 +367,22 @@ impl EGLBackedSurface {
                 window_surface_attribs.as_ptr(),
             );
             assert_ne!(egl_surface, egl::NO_SURFACE);
+            let samples = context::get_config_samples(egl_display, egl_config);
 
             EGLBackedSurface {
+                id: SurfaceID::next(),
                 context_id,
                 size: *size,
                 objects: EGLSurfaceObjects::Window {
                     native_window,
                     egl_surface,
                 },
+                // X11 and Wayland windows are XRGB8888 or ARGB8888, which are BGRA in memory.
+                format: SurfaceFormat::BGRA8,
+                origin: SurfaceOrigin::BottomLeft,
+                alpha_mode,
+                colorspace: Colorspace::SRGB,
+                samples,
                 destroyed: false,
             }
         })
@@ -195,19 +393,135 @@ impl EGLBackedSurface {
         gl: &Gl,
     ) -> Result<EGLSurfaceTexture, (Error, EGLBackedSurface)> {
         unsafe {
-            let egl_image = match self.objects {
-                EGLSurfaceObjects::TextureImage { egl_image, .. } => egl_image,
+            let (texture_object, texture_target) = match self.objects {
+                EGLSurfaceObjects::TextureImage {
+                    egl_image,
+                    ref extra_layers,
+                    ..
+                } if extra_layers.is_empty() => {
+                    (bind_egl_image_to_gl_texture(gl, egl_image), gl::TEXTURE_2D)
+                }
+                EGLSurfaceObjects::TextureImage {
+                    egl_image,
+                    ref extra_layers,
+                    ..
+                } => {
+                    let egl_images: Vec<EGLImageKHR> = iter::once(egl_image)
+                        .chain(extra_layers.iter().map(|layer| layer.egl_image))
+                        .collect();
+                    let texture_object =
+                        copy_egl_images_to_texture_array(gl, &egl_images, &self.size);
+                    (texture_object, gl::TEXTURE_2D_ARRAY)
+                }
                 EGLSurfaceObjects::Window { .. } => return Err((Error::WidgetAttached, self)),
             };
-            let texture_object = bind_egl_image_to_gl_texture(gl, egl_image);
             Ok(EGLSurfaceTexture {
                 surface: self,
                 texture_object,
+                texture_target,
                 phantom: PhantomData,
             })
         }
     }
 
+    // Exports the image of a generic surface as DMA-BUF file descriptors.
+    //
+    // This requires `EGL_MESA_image_dma_buf_export`.
+    #[cfg(any(android, linux))]
+    #[cfg(feature = "sm-opencl")]
+    pub(crate) fn egl_image(&self) -> Result<EGLImageKHR, Error> {
+        match self.objects {
+            EGLSurfaceObjects::TextureImage { egl_image, .. } => Ok(egl_image),
+            EGLSurfaceObjects::Window { .. } => Err(Error::WidgetAttached),
+        }
+    }
+
+    pub(crate) fn export_dma_buf(&self, egl_display: EGLDisplay) -> Result<EGLDmaBuf, Error> {
+        let egl_image = match self.objects {
+            EGLSurfaceObjects::TextureImage {
+                egl_image,
+                ref extra_layers,
+                ..
+            } if extra_layers.is_empty() => egl_image,
+            // Each layer of a layered surface is an image of its own.
+            EGLSurfaceObjects::TextureImage { .. } => return Err(Error::UnsupportedOnThisPlatform),
+            EGLSurfaceObjects::Window { .. } => return Err(Error::WidgetAttached),
+        };
+        let (export_query, export) = match (
+            EGL_EXTENSION_FUNCTIONS.ExportDMABUFImageQueryMESA,
+            EGL_EXTENSION_FUNCTIONS.ExportDMABUFImageMESA,
+        ) {
+            (Some(export_query), Some(export)) => (export_query, export),
+            _ => return Err(Error::RequiredExtensionUnavailable),
+        };
+
+        let (mut fourcc, mut plane_count) = (0, 0);
+        let result = export_query(
+            egl_display,
+            egl_image,
+            &mut fourcc,
+            &mut plane_count,
+            ptr::null_mut(),
+        );
+        if result == egl::FALSE || plane_count <= 0 {
+            return Err(Error::Failed);
+        }
+
+        let plane_count = plane_count as usize;
+        let mut modifiers = vec![0; plane_count];
+        let result = export_query(
+            egl_display,
+            egl_image,
+            &mut fourcc,
+            &mut 0,
+            modifiers.as_mut_ptr(),
+        );
+        if result == egl::FALSE {
+            return Err(Error::Failed);
+        }
+
+        let mut fds = vec![-1; plane_count];
+        let mut strides = vec![0; plane_count];
+        let mut offsets = vec![0; plane_count];
+        let result = export(
+            egl_display,
+            egl_image,
+            fds.as_mut_ptr(),
+            strides.as_mut_ptr(),
+            offsets.as_mut_ptr(),
+        );
+        if result == egl::FALSE {
+            return Err(Error::Failed);
+        }
+
+        let mut dma_buf = EGLDmaBuf {
+            fourcc: fourcc as u32,
+            modifier: modifiers[0],
+            planes: Vec::with_capacity(plane_count),
+        };
+        for plane_index in 0..plane_count {
+            // Planes that share a buffer with an earlier plane are reported with a file
+            // descriptor of -1.
+            let fd = if fds[plane_index] >= 0 {
+                fds[plane_index]
+            } else {
+                dma_buf.planes[0].fd
+            };
+            let fd = unsafe { libc::dup(fd) };
+            dma_buf.planes.push(EGLDmaBufPlane {
+                fd,
+                offset: offsets[plane_index] as u32,
+                stride: strides[plane_index] as u32,
+            });
+        }
+        for &fd in fds.iter().filter(|&&fd| fd >= 0) {
+            unsafe {
+                libc::close(fd);
+            }
+        }
+        Ok(dma_buf)
+    }
+
     pub(crate) fn destroy(
         &mut self,
         gl: &Gl,
@@ -225,12 +539,16 @@ impl EGLBackedSurface {
                     ref mut framebuffer_object,
                     ref mut texture_object,
                     ref mut renderbuffers,
+                    ref mut extra_layers,
                 } => {
-                    gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
-                    gl.DeleteFramebuffers(1, framebuffer_object);
+                    gl_utils::destroy_framebuffer(gl, *framebuffer_object);
                     *framebuffer_object = 0;
                     renderbuffers.destroy(gl);
 
+                    for layer in extra_layers.drain(..) {
+                        layer.destroy(gl, egl_display);
+                    }
+
                     let result = (EGL_EXTENSION_FUNCTIONS.DestroyImageKHR)(egl_display, *egl_image);
                     assert_ne!(result, egl::FALSE);
                     *egl_image = EGL_NO_IMAGE_KHR;
@@ -257,11 +575,45 @@ impl EGLBackedSurface {
         }
     }
 
-    // TODO(pcwalton): Damage regions.
+    // Gives up the surface, keeping only what can be destroyed without its context. The
+    // framebuffer, texture, and renderbuffers live in the context's namespace, so they're left for
+    // the context to free when it's destroyed.
+    pub(crate) fn into_destroy_token(mut self) -> EGLSurfaceDestroyToken {
+        self.destroyed = true;
+        let objects = match self.objects {
+            EGLSurfaceObjects::TextureImage {
+                ref mut egl_image,
+                ref mut renderbuffers,
+                ref mut extra_layers,
+                ..
+            } => {
+                renderbuffers.abandon();
+                let egl_images = iter::once(mem::replace(egl_image, EGL_NO_IMAGE_KHR))
+                    .chain(extra_layers.drain(..).map(|layer| layer.egl_image))
+                    .collect();
+                EGLDestroyTokenObjects::Images(egl_images)
+            }
+            EGLSurfaceObjects::Window {
+                ref mut native_window,
+                ref mut egl_surface,
+            } => EGLDestroyTokenObjects::Window {
+                native_window: mem::replace(native_window, ptr::null()),
+                egl_surface: mem::replace(egl_surface, egl::NO_SURFACE),
+            },
+        };
+        EGLSurfaceDestroyToken {
+            id: self.id,
+            objects,
+        }
+    }
+
+    // Presents the surface, telling the compositor that only the given rectangles changed. An
+    // empty slice damages the whole surface.
     pub(crate) fn present(
         &self,
         egl_display: EGLDisplay,
         egl_context: EGLContext,
+        damage: &[Rect<i32>],
     ) -> Result<(), Error> {
         unsafe {
             match self.objects {
@@ -273,7 +625,7 @@ impl EGLBackedSurface {
                     EGL_FUNCTIONS.with(|egl| {
                         egl.MakeCurrent(egl_display, egl_surface, egl_surface, egl_context);
 
-                        let ok = egl.SwapBuffers(egl_display, egl_surface);
+                        let ok = swap_buffers_with_damage(egl_display, egl_surface, damage);
                         if ok != egl::FALSE {
                             Ok(())
                         } else {
@@ -288,6 +640,109 @@ impl EGLBackedSurface {
         }
     }
 
+    // Presents the surface, scaling the `source` rectangle of its contents into the `destination`
+    // rectangle and clearing the rest. See `swap_buffers_with_viewport()`.
+    pub(crate) fn present_with_viewport(
+        &self,
+        gl: &Gl,
+        egl_display: EGLDisplay,
+        egl_context: EGLContext,
+        source: &Rect<i32>,
+        destination: &Rect<i32>,
+    ) -> Result<(), Error> {
+        match self.objects {
+            EGLSurfaceObjects::Window { egl_surface, .. } => unsafe {
+                swap_buffers_with_viewport(
+                    gl,
+                    egl_display,
+                    egl_surface,
+                    egl_context,
+                    source,
+                    destination,
+                )
+            },
+            EGLSurfaceObjects::TextureImage { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    // Declares the region of the next frame that will be redrawn, via `EGL_KHR_partial_update`.
+    pub(crate) fn set_damage_region(
+        &self,
+        egl_display: EGLDisplay,
+        egl_context: EGLContext,
+        region: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        unsafe {
+            match self.objects {
+                EGLSurfaceObjects::Window { egl_surface, .. } => {
+                    // As with presenting, the surface must be current.
+                    let _guard = CurrentContextGuard::new();
+                    EGL_FUNCTIONS.with(|egl| {
+                        egl.MakeCurrent(egl_display, egl_surface, egl_surface, egl_context);
+                    });
+                    set_damage_region(egl_display, egl_surface, region)
+                }
+                EGLSurfaceObjects::TextureImage { .. } => Err(Error::NoWidgetAttached),
+            }
+        }
+    }
+
+    pub(crate) fn set_preserve_buffer(
+        &self,
+        egl_display: EGLDisplay,
+        preserve: bool,
+    ) -> Result<(), Error> {
+        match self.objects {
+            EGLSurfaceObjects::Window { egl_surface, .. } => unsafe {
+                set_swap_behavior(egl_display, egl_surface, preserve)
+            },
+            EGLSurfaceObjects::TextureImage { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    pub(crate) fn buffer_age(
+        &self,
+        egl_display: EGLDisplay,
+        egl_context: EGLContext,
+    ) -> Result<u32, Error> {
+        unsafe {
+            match self.objects {
+                EGLSurfaceObjects::Window { egl_surface, .. } => {
+                    let _guard = CurrentContextGuard::new();
+                    EGL_FUNCTIONS.with(|egl| {
+                        egl.MakeCurrent(egl_display, egl_surface, egl_surface, egl_context);
+                    });
+                    Ok(query_buffer_age(egl_display, egl_surface))
+                }
+                EGLSurfaceObjects::TextureImage { .. } => Err(Error::NoWidgetAttached),
+            }
+        }
+    }
+
+    // Sets which way up the contents are. EGL window surfaces are always shown the way up that
+    // OpenGL renders them, so they can't be flipped here.
+    pub(crate) fn set_origin(&mut self, origin: SurfaceOrigin) -> Result<(), Error> {
+        if let EGLSurfaceObjects::Window { .. } = self.objects {
+            if origin != SurfaceOrigin::BottomLeft {
+                return Err(Error::UnsupportedOnThisPlatform);
+            }
+        }
+        self.origin = origin;
+        Ok(())
+    }
+
+    // Sets the color space of the contents. EGL only takes the color space of a window surface
+    // when it's created, so window surfaces are always sRGB.
+    pub(crate) fn set_colorspace(&mut self, colorspace: Colorspace) -> Result<(), Error> {
+        if let EGLSurfaceObjects::Window { .. } = self.objects {
+            if colorspace != Colorspace::SRGB {
+                return Err(Error::UnsupportedOnThisPlatform);
+            }
+        }
+        self.colorspace = colorspace;
+        Ok(())
+    }
+
     pub(crate) fn info(&self) -> SurfaceInfo {
         SurfaceInfo {
             size: self.size,
@@ -299,16 +754,106 @@ impl EGLBackedSurface {
                 } => framebuffer_object,
                 EGLSurfaceObjects::Window { .. } => 0,
             },
+            scale_factor: 1.0,
+            transform: SurfaceTransform::Identity,
+            origin: self.origin,
+            format: self.format,
+            alpha_mode: self.alpha_mode,
+            colorspace: self.colorspace,
+            samples: self.samples,
+            bound: false,
         }
     }
 
-    pub(crate) fn id(&self) -> SurfaceID {
+    // Copies the contents of this generic surface into another one of the same size, in the
+    // channel order of the other surface. Both must belong to the current context.
+    pub(crate) fn copy_contents_to(
+        &self,
+        gl: &Gl,
+        dest_surface: &EGLBackedSurface,
+    ) -> Result<(), Error> {
+        let (read_framebuffer_object, draw_framebuffer_object) =
+            match (&self.objects, &dest_surface.objects) {
+                // Only the chosen layer of a layered surface can be read through its framebuffer.
+                (
+                    &EGLSurfaceObjects::TextureImage {
+                        ref extra_layers, ..
+                    },
+                    _,
+                ) if !extra_layers.is_empty() => return Err(Error::UnsupportedOnThisPlatform),
+                (
+                    &EGLSurfaceObjects::TextureImage {
+                        framebuffer_object: read_framebuffer_object,
+                        ..
+                    },
+                    &EGLSurfaceObjects::TextureImage {
+                        framebuffer_object: draw_framebuffer_object,
+                        ..
+                    },
+                ) => (read_framebuffer_object, draw_framebuffer_object),
+                _ => return Err(Error::WidgetAttached),
+            };
+        gl_utils::copy_framebuffer(
+            gl,
+            read_framebuffer_object,
+            draw_framebuffer_object,
+            &self.size,
+            self.format != dest_surface.format,
+        );
+        Ok(())
+    }
+
+    // Attaches the given layer of the surface to its framebuffer, so that rendering draws into it.
+    // The surface's context must be current.
+    pub(crate) fn set_layer(&self, gl: &Gl, layer: u32) -> Result<(), Error> {
         match self.objects {
-            EGLSurfaceObjects::TextureImage { egl_image, .. } => SurfaceID(egl_image as usize),
-            EGLSurfaceObjects::Window { egl_surface, .. } => SurfaceID(egl_surface as usize),
+            EGLSurfaceObjects::TextureImage {
+                framebuffer_object,
+                texture_object,
+                ref extra_layers,
+                ..
+            } => {
+                let texture_object = match layer {
+                    0 => texture_object,
+                    _ => match extra_layers.get(layer as usize - 1) {
+                        Some(layer) => layer.texture_object,
+                        None => return Err(Error::InvalidSurfaceLayer),
+                    },
+                };
+                if extra_layers.is_empty() {
+                    return Ok(());
+                }
+                unsafe {
+                    let mut bindings = gl_utils::BindingsGuard::new(gl);
+                    bindings.bind_framebuffer(gl::DRAW_FRAMEBUFFER, framebuffer_object);
+                    gl.FramebufferTexture2D(
+                        gl::DRAW_FRAMEBUFFER,
+                        gl::COLOR_ATTACHMENT0,
+                        gl::TEXTURE_2D,
+                        texture_object,
+                        0,
+                    );
+                }
+                Ok(())
+            }
+            EGLSurfaceObjects::Window { .. } => Err(Error::WidgetAttached),
         }
     }
 
+    pub(crate) fn layers(&self) -> u32 {
+        match self.objects {
+            EGLSurfaceObjects::TextureImage {
+                ref extra_layers, ..
+            } => 1 + extra_layers.len() as u32,
+            EGLSurfaceObjects::Window { .. } => 1,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn id(&self) -> SurfaceID {
+        self.id
+    }
+
     pub(crate) fn native_window(&self) -> Result<*const c_void, Error> {
         match self.objects {
             EGLSurfaceObjects::TextureImage { .. } => Err(Error::NoWidgetAttached),
@@ -349,6 +894,90 @@ impl EGLBackedSurface {
     }
 }
 
+impl EGLImageLayer {
+    // Creates an uninitialized RGBA texture of the given size, and an EGL image sharing it. The
+    // texture is left bound through `bindings`.
+    unsafe fn new(
+        gl: &Gl,
+        bindings: &mut gl_utils::BindingsGuard,
+        egl_display: EGLDisplay,
+        egl_context: EGLContext,
+        size: &Size2D<i32>,
+    ) -> EGLImageLayer {
+        let egl_image_attribs = [
+            EGL_IMAGE_PRESERVED_KHR as EGLint,
+            egl::FALSE as EGLint,
+            egl::NONE as EGLint,
+            0,
+        ];
+
+        let mut texture_object = 0;
+        gl.GenTextures(1, &mut texture_object);
+        bindings.bind_texture(texture_object);
+        with_no_pixel_unpack_buffer(gl, || {
+            gl.TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGBA as GLint,
+                size.width,
+                size.height,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                ptr::null(),
+            )
+        });
+
+        let egl_client_buffer = texture_object as usize as EGLClientBuffer;
+        let egl_image = (EGL_EXTENSION_FUNCTIONS.CreateImageKHR)(
+            egl_display,
+            egl_context,
+            EGL_GL_TEXTURE_2D_KHR,
+            egl_client_buffer,
+            egl_image_attribs.as_ptr(),
+        );
+        EGLImageLayer {
+            egl_image,
+            texture_object,
+        }
+    }
+
+    unsafe fn destroy(self, gl: &Gl, egl_display: EGLDisplay) {
+        let result = (EGL_EXTENSION_FUNCTIONS.DestroyImageKHR)(egl_display, self.egl_image);
+        assert_ne!(result, egl::FALSE);
+        gl.DeleteTextures(1, &self.texture_object);
+    }
+}
+
+impl EGLSurfaceDestroyToken {
+    // Returns the native window of a widget surface, which the caller must release, like
+    // `EGLBackedSurface::destroy()`.
+    pub(crate) fn destroy(self, egl_display: EGLDisplay) -> Option<*const c_void> {
+        unsafe {
+            match self.objects {
+                EGLDestroyTokenObjects::Images(egl_images) => {
+                    for egl_image in egl_images {
+                        let result =
+                            (EGL_EXTENSION_FUNCTIONS.DestroyImageKHR)(egl_display, egl_image);
+                        assert_ne!(result, egl::FALSE);
+                    }
+                    None
+                }
+                EGLDestroyTokenObjects::Window {
+                    native_window,
+                    egl_surface,
+                } => {
+                    EGL_FUNCTIONS.with(|egl| {
+                        // A surface that's still current is destroyed once it's made non-current.
+                        egl.DestroySurface(egl_display, egl_surface);
+                    });
+                    Some(native_window)
+                }
+            }
+        }
+    }
+}
+
 impl EGLSurfaceTexture {
     pub(crate) fn destroy(mut self, gl: &Gl) -> EGLBackedSurface {
         unsafe {
@@ -359,6 +988,17 @@ impl EGLSurfaceTexture {
     }
 }
 
+#[cfg(any(android, linux))]
+impl Drop for EGLDmaBuf {
+    fn drop(&mut self) {
+        for plane in &self.planes {
+            unsafe {
+                libc::close(plane.fd);
+            }
+        }
+    }
+}
+
 impl Default for ExternalEGLSurfaces {
     #[inline]
     fn default() -> ExternalEGLSurfaces {
@@ -397,17 +1037,257 @@ pub(crate) unsafe fn create_pbuffer_surface(
     })
 }
 
+// Swaps the buffers of a window surface, passing the damaged rectangles along if the display
+// supports `EGL_KHR_swap_buffers_with_damage` or its EXT precursor.
+pub(crate) unsafe fn swap_buffers_with_damage(
+    egl_display: EGLDisplay,
+    egl_surface: EGLSurface,
+    damage: &[Rect<i32>],
+) -> EGLBoolean {
+    let swap_buffers_with_damage = if damage.is_empty() {
+        None
+    } else if display_has_extension(egl_display, b"EGL_KHR_swap_buffers_with_damage") {
+        EGL_EXTENSION_FUNCTIONS.SwapBuffersWithDamageKHR
+    } else if display_has_extension(egl_display, b"EGL_EXT_swap_buffers_with_damage") {
+        EGL_EXTENSION_FUNCTIONS.SwapBuffersWithDamageEXT
+    } else {
+        None
+    };
+
+    match swap_buffers_with_damage {
+        Some(swap_buffers_with_damage) => {
+            let rects = egl_rects(damage);
+            swap_buffers_with_damage(
+                egl_display,
+                egl_surface,
+                rects.as_ptr(),
+                damage.len() as EGLint,
+            )
+        }
+        None => EGL_FUNCTIONS.with(|egl| egl.SwapBuffers(egl_display, egl_surface)),
+    }
+}
+
+// Scales the `source` rectangle of the back buffer of a window surface into its `destination`
+// rectangle, clears the rest, and swaps buffers. The window surface is temporarily made current, as
+// EGL 1.4 requires for swapping anyway.
+pub(crate) unsafe fn swap_buffers_with_viewport(
+    gl: &Gl,
+    egl_display: EGLDisplay,
+    egl_surface: EGLSurface,
+    egl_context: EGLContext,
+    source: &Rect<i32>,
+    destination: &Rect<i32>,
+) -> Result<(), Error> {
+    if source.is_empty() || destination.is_empty() {
+        return Err(Error::Failed);
+    }
+
+    let _guard = CurrentContextGuard::new();
+    EGL_FUNCTIONS.with(|egl| {
+        if egl.MakeCurrent(egl_display, egl_surface, egl_surface, egl_context) == egl::FALSE {
+            return Err(Error::MakeCurrentFailed(
+                egl.GetError().to_windowing_api_error(),
+            ));
+        }
+
+        gl_utils::blit_viewport(gl, 0, source, destination);
+
+        if egl.SwapBuffers(egl_display, egl_surface) != egl::FALSE {
+            Ok(())
+        } else {
+            Err(Error::PresentFailed(
+                egl.GetError().to_windowing_api_error(),
+            ))
+        }
+    })
+}
+
+// Sets the damage region of the next frame of a window surface, which must be current.
+//
+// Without `EGL_KHR_partial_update`, the whole surface is preserved anyway, so this does nothing.
+pub(crate) unsafe fn set_damage_region(
+    egl_display: EGLDisplay,
+    egl_surface: EGLSurface,
+    region: &[Rect<i32>],
+) -> Result<(), Error> {
+    let set_damage_region = match EGL_EXTENSION_FUNCTIONS.SetDamageRegionKHR {
+        Some(set_damage_region)
+            if display_has_extension(egl_display, b"EGL_KHR_partial_update") =>
+        {
+            set_damage_region
+        }
+        _ => return Ok(()),
+    };
+
+    let mut rects = egl_rects(region);
+    if set_damage_region(
+        egl_display,
+        egl_surface,
+        rects.as_mut_ptr(),
+        region.len() as EGLint,
+    ) == egl::FALSE
+    {
+        // This fails with `EGL_BAD_ACCESS` if rendering to the frame has already begun.
+        return Err(Error::Failed);
+    }
+    Ok(())
+}
+
+// Sets whether swapping the buffers of a window surface preserves its contents. Preserving them
+// requires a config with `EGL_SWAP_BEHAVIOR_PRESERVED_BIT`.
+pub(crate) unsafe fn set_swap_behavior(
+    egl_display: EGLDisplay,
+    egl_surface: EGLSurface,
+    preserve: bool,
+) -> Result<(), Error> {
+    let swap_behavior = if preserve {
+        egl::BUFFER_PRESERVED
+    } else {
+        egl::BUFFER_DESTROYED
+    };
+    EGL_FUNCTIONS.with(|egl| {
+        let result = egl.SurfaceAttrib(
+            egl_display,
+            egl_surface,
+            egl::SWAP_BEHAVIOR as EGLint,
+            swap_behavior as EGLint,
+        );
+        if result != egl::FALSE {
+            Ok(())
+        } else if egl.GetError() == egl::BAD_MATCH as EGLint {
+            Err(Error::UnsupportedOnThisPlatform)
+        } else {
+            Err(Error::Failed)
+        }
+    })
+}
+
+// Returns the attributes for creating a window surface with the given alpha mode, terminated by
+// `EGL_NONE`. Compositors blend windows as premultiplied unless `EGL_EXT_present_opaque` is used
+// to tell them that the alpha channel is to be ignored.
+pub(crate) unsafe fn window_surface_attributes(
+    egl_display: EGLDisplay,
+    alpha_mode: SurfaceAlphaMode,
+) -> Vec<EGLint> {
+    let mut attributes = vec![];
+    if alpha_mode == SurfaceAlphaMode::Opaque
+        && display_has_extension(egl_display, b"EGL_EXT_present_opaque")
+    {
+        attributes.extend_from_slice(&[EGL_PRESENT_OPAQUE_EXT as EGLint, egl::TRUE as EGLint]);
+    }
+    attributes.push(egl::NONE as EGLint);
+    attributes
+}
+
+// Returns the age of the back buffer of a window surface, which must be current, or 0 if it's
+// unknown.
+pub(crate) unsafe fn query_buffer_age(egl_display: EGLDisplay, egl_surface: EGLSurface) -> u32 {
+    if !display_has_extension(egl_display, b"EGL_EXT_buffer_age")
+        && !display_has_extension(egl_display, b"EGL_KHR_partial_update")
+    {
+        return 0;
+    }
+
+    EGL_FUNCTIONS.with(|egl| {
+        let mut buffer_age = 0;
+        if egl.QuerySurface(
+            egl_display,
+            egl_surface,
+            EGL_BUFFER_AGE_KHR as EGLint,
+            &mut buffer_age,
+        ) == egl::FALSE
+        {
+            return 0;
+        }
+        buffer_age.max(0) as u32
+    })
+}
+
+// Flattens rectangles into the `x, y, width, height` form that EGL expects.
+fn egl_rects(rects: &[Rect<i32>]) -> Vec<EGLint> {
+    let mut egl_rects = Vec::with_capacity(rects.len() * 4);
+    for rect in rects {
+        egl_rects.extend_from_slice(&[
+            rect.origin.x,
+            rect.origin.y,
+            rect.size.width,
+            rect.size.height,
+        ]);
+    }
+    egl_rects
+}
+
+// Imports a DMA-BUF as an EGL image, preserving its format modifier.
+//
+// This requires `EGL_EXT_image_dma_buf_import`, and
+// `EGL_EXT_image_dma_buf_import_modifiers` if the modifier is explicit.
+#[cfg(linux)]
+pub(crate) unsafe fn create_egl_image_from_dma_buf(
+    egl_display: EGLDisplay,
+    dma_buf: &EGLDmaBuf,
+    size: &Size2D<i32>,
+) -> Result<EGLImageKHR, Error> {
+    if dma_buf.planes.is_empty() || dma_buf.planes.len() > EGL_DMA_BUF_PLANE_FD_EXT.len() {
+        return Err(Error::SurfaceCreationFailed(
+            WindowingApiError::BadParameter,
+        ));
+    }
+
+    let mut attributes = vec![
+        egl::WIDTH as EGLint,
+        size.width,
+        egl::HEIGHT as EGLint,
+        size.height,
+        EGL_LINUX_DRM_FOURCC_EXT as EGLint,
+        dma_buf.fourcc as EGLint,
+    ];
+    for (plane_index, plane) in dma_buf.planes.iter().enumerate() {
+        attributes.extend_from_slice(&[
+            EGL_DMA_BUF_PLANE_FD_EXT[plane_index] as EGLint,
+            plane.fd,
+            EGL_DMA_BUF_PLANE_OFFSET_EXT[plane_index] as EGLint,
+            plane.offset as EGLint,
+            EGL_DMA_BUF_PLANE_PITCH_EXT[plane_index] as EGLint,
+            plane.stride as EGLint,
+        ]);
+        if dma_buf.modifier != DRM_FORMAT_MOD_INVALID {
+            attributes.extend_from_slice(&[
+                EGL_DMA_BUF_PLANE_MODIFIER_LO_EXT[plane_index] as EGLint,
+                dma_buf.modifier as u32 as EGLint,
+                EGL_DMA_BUF_PLANE_MODIFIER_HI_EXT[plane_index] as EGLint,
+                (dma_buf.modifier >> 32) as u32 as EGLint,
+            ]);
+        }
+    }
+    attributes.extend_from_slice(&[egl::NONE as EGLint, 0]);
+
+    // The image holds its own references to the buffers, so the caller keeps ownership of the
+    // file descriptors.
+    let egl_image = (EGL_EXTENSION_FUNCTIONS.CreateImageKHR)(
+        egl_display,
+        egl::NO_CONTEXT,
+        EGL_LINUX_DMA_BUF_EXT,
+        ptr::null_mut(),
+        attributes.as_ptr(),
+    );
+    if egl_image == EGL_NO_IMAGE_KHR {
+        let windowing_api_error = EGL_FUNCTIONS.with(|egl| egl.GetError().to_windowing_api_error());
+        return Err(Error::SurfaceCreationFailed(windowing_api_error));
+    }
+    Ok(egl_image)
+}
+
 #[allow(dead_code)]
 pub(crate) unsafe fn bind_egl_image_to_gl_texture(gl: &Gl, egl_image: EGLImageKHR) -> GLuint {
     let mut texture = 0;
     gl.GenTextures(1, &mut texture);
     debug_assert_ne!(texture, 0);
 
-    let mut texture_binding = 0;
-    gl.GetIntegerv(gl::TEXTURE_BINDING_2D, &mut texture_binding);
-
     // FIXME(pcwalton): Should this be `GL_TEXTURE_EXTERNAL_OES`?
-    gl.BindTexture(gl::TEXTURE_2D, texture);
+    let mut bindings =
+        gl_utils::BindingsGuard::for_texture(gl, gl::TEXTURE_2D, gl::TEXTURE_BINDING_2D);
+    bindings.bind_texture(texture);
     (EGL_EXTENSION_FUNCTIONS.ImageTargetTexture2DOES)(gl::TEXTURE_2D, egl_image);
     gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
     gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
@@ -421,8 +1301,95 @@ pub(crate) unsafe fn bind_egl_image_to_gl_texture(gl: &Gl, egl_image: EGLImageKH
         gl::TEXTURE_WRAP_T,
         gl::CLAMP_TO_EDGE as GLint,
     );
-    gl.BindTexture(gl::TEXTURE_2D, texture_binding as GLuint);
+    drop(bindings);
 
     debug_assert_eq!(gl.GetError(), gl::NO_ERROR);
     texture
 }
+
+// Makes a `GL_TEXTURE_2D_ARRAY` of the current context with a copy of each of the given EGL
+// images in a layer. EGL has no way to share an array of images, so surface textures of layered
+// surfaces hold a copy of their layers; as a surface texture owns its surface, nothing can render
+// to the surface while the copy is in use.
+unsafe fn copy_egl_images_to_texture_array(
+    gl: &Gl,
+    egl_images: &[EGLImageKHR],
+    size: &Size2D<i32>,
+) -> GLuint {
+    let mut bindings = gl_utils::BindingsGuard::new(gl)
+        .with_texture(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_BINDING_2D_ARRAY);
+    let mut texture_array = 0;
+    gl.GenTextures(1, &mut texture_array);
+    bindings.bind_texture(texture_array);
+    with_no_pixel_unpack_buffer(gl, || {
+        gl.TexImage3D(
+            gl::TEXTURE_2D_ARRAY,
+            0,
+            gl::RGBA as GLint,
+            size.width,
+            size.height,
+            egl_images.len() as GLsizei,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            ptr::null(),
+        )
+    });
+    for &(parameter, value) in &[
+        (gl::TEXTURE_MAG_FILTER, gl::LINEAR),
+        (gl::TEXTURE_MIN_FILTER, gl::LINEAR),
+        (gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE),
+        (gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE),
+    ] {
+        gl.TexParameteri(gl::TEXTURE_2D_ARRAY, parameter, value as GLint);
+    }
+
+    let mut framebuffer_object = 0;
+    gl.GenFramebuffers(1, &mut framebuffer_object);
+    bindings.bind_framebuffer(gl::READ_FRAMEBUFFER, framebuffer_object);
+    for (layer, &egl_image) in egl_images.iter().enumerate() {
+        let layer_texture_object = bind_egl_image_to_gl_texture(gl, egl_image);
+        gl.FramebufferTexture2D(
+            gl::READ_FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            gl::TEXTURE_2D,
+            layer_texture_object,
+            0,
+        );
+        gl.CopyTexSubImage3D(
+            gl::TEXTURE_2D_ARRAY,
+            0,
+            0,
+            0,
+            layer as GLint,
+            0,
+            0,
+            size.width,
+            size.height,
+        );
+        gl.DeleteTextures(1, &layer_texture_object);
+    }
+    drop(bindings);
+    gl.DeleteFramebuffers(1, &framebuffer_object);
+
+    debug_assert_eq!(gl.GetError(), gl::NO_ERROR);
+    texture_array
+}
+
+// Runs `callback` with no buffer bound to `GL_PIXEL_UNPACK_BUFFER`, which would otherwise turn the
+// null pointer that allocates uninitialized texture storage into an offset into the buffer.
+unsafe fn with_no_pixel_unpack_buffer<F, R>(gl: &Gl, callback: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let mut unpack_buffer = 0;
+    gl.GetIntegerv(gl::PIXEL_UNPACK_BUFFER_BINDING, &mut unpack_buffer);
+    if unpack_buffer != 0 {
+        gl.BindBuffer(gl::PIXEL_UNPACK_BUFFER, 0);
+    }
+    let result = callback();
+    if unpack_buffer != 0 {
+        gl.BindBuffer(gl::PIXEL_UNPACK_BUFFER, unpack_buffer as GLuint);
+    }
+    result
+}
@@ -2,7 +2,12 @@
 //
 //! Backends that are not specific to any operating system.
 
-#[cfg(any(android, angle, linux))]
+#[cfg(any(android, angle, linux, ohos))]
 pub(crate) mod egl;
+#[cfg(any(android, angle, linux, ohos))]
+pub use self::egl::device::set_software_egl_library;
 
 pub mod multi;
+
+#[cfg(any(linux, redox))]
+pub mod osmesa;
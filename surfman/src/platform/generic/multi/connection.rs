@@ -4,8 +4,10 @@
 
 use super::device::{Adapter, Device, NativeDevice};
 use super::surface::NativeWidget;
-use crate::connection::Connection as ConnectionInterface;
+use crate::connection::NativeWidgetHandle;
+use crate::connection::{Connection as ConnectionInterface, IpcDescriptor, NativeDisplayHandle};
 use crate::device::Device as DeviceInterface;
+use crate::DisplayInfo;
 use crate::Error;
 use crate::GLApi;
 
@@ -75,6 +77,27 @@ where
         }
     }
 
+    /// Connects through a handle that another process opened and passed over IPC.
+    ///
+    /// The default backend is tried first. If it doesn't accept the kind of descriptor given, the
+    /// alternate backend is tried.
+    ///
+    /// # Safety
+    ///
+    /// Any file descriptor in the descriptor must be open, and nothing else may use or close it
+    /// once the backend that accepts it has taken ownership.
+    pub unsafe fn from_ipc_descriptor(
+        descriptor: IpcDescriptor,
+    ) -> Result<Connection<Def, Alt>, Error> {
+        match <Def::Connection>::from_ipc_descriptor(descriptor) {
+            Ok(connection) => Ok(Connection::Default(connection)),
+            Err(Error::UnsupportedOnThisPlatform) => {
+                <Alt::Connection>::from_ipc_descriptor(descriptor).map(Connection::Alternate)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
     /// Returns the native connection corresponding to this connection.
     pub fn native_connection(&self) -> NativeConnection<Def, Alt> {
         match *self {
@@ -87,6 +110,14 @@ where
         }
     }
 
+    /// Returns the raw display connection underneath this connection.
+    pub fn native_display(&self) -> NativeDisplayHandle {
+        match *self {
+            Connection::Default(ref connection) => connection.native_display(),
+            Connection::Alternate(ref connection) => connection.native_display(),
+        }
+    }
+
     /// Returns the OpenGL API flavor that this connection supports (OpenGL or OpenGL ES).
     pub fn gl_api(&self) -> GLApi {
         match *self {
@@ -95,6 +126,14 @@ where
         }
     }
 
+    /// Returns the displays connected to the system.
+    pub fn displays(&self) -> Result<Vec<DisplayInfo>, Error> {
+        match *self {
+            Connection::Default(ref connection) => connection.displays(),
+            Connection::Alternate(ref connection) => connection.displays(),
+        }
+    }
+
     /// Returns the "best" adapter on this system.
     ///
     /// This is an alias for `Connection::create_hardware_adapter()`.
@@ -145,9 +184,24 @@ where
         }
     }
 
+    /// Returns the adapter for the GPU that drives the given display.
+    pub fn create_adapter_for_display(
+        &self,
+        display: &DisplayInfo,
+    ) -> Result<Adapter<Def, Alt>, Error> {
+        match *self {
+            Connection::Default(ref connection) => connection
+                .create_adapter_for_display(display)
+                .map(Adapter::Default),
+            Connection::Alternate(ref connection) => connection
+                .create_adapter_for_display(display)
+                .map(Adapter::Alternate),
+        }
+    }
+
     /// Opens the hardware device corresponding to the given adapter.
     ///
-    /// Device handles are local to a single thread.
+    /// Device handles can be cloned and sent to other threads.
     pub fn create_device(&self, adapter: &Adapter<Def, Alt>) -> Result<Device<Def, Alt>, Error> {
         match (self, adapter) {
             (&Connection::Default(ref connection), &Adapter::Default(ref adapter)) => {
@@ -161,6 +215,11 @@ where
     }
 
     /// Wraps a native device in a device.
+    ///
+    /// # Safety
+    ///
+    /// The native device must be valid, and must stay alive as long as the returned device unless
+    /// the backend documents that it's retained.
     #[inline]
     pub unsafe fn create_device_from_native_device(
         &self,
@@ -210,6 +269,11 @@ where
     }
 
     /// Create a native widget from a raw pointer
+    ///
+    /// # Safety
+    ///
+    /// The pointer must refer to a live window or view of the kind that the backend in use expects,
+    /// which must outlive the native widget and any surfaces created from it.
     pub unsafe fn create_native_widget_from_ptr(
         &self,
         raw: *mut c_void,
@@ -225,6 +289,28 @@ where
         }
     }
 
+    /// Creates a native widget from a handle to a native window or view.
+    ///
+    /// The widget is created by whichever backend this connection belongs to.
+    ///
+    /// # Safety
+    ///
+    /// The handle must refer to a live window or view, which must outlive the native widget and any
+    /// surfaces created from it.
+    pub unsafe fn create_native_widget_from_handle(
+        &self,
+        handle: NativeWidgetHandle,
+    ) -> Result<NativeWidget<Def, Alt>, Error> {
+        match *self {
+            Connection::Default(ref connection) => connection
+                .create_native_widget_from_handle(handle)
+                .map(NativeWidget::Default),
+            Connection::Alternate(ref connection) => connection
+                .create_native_widget_from_handle(handle)
+                .map(NativeWidget::Alternate),
+        }
+    }
+
     /// Create a native widget type from the given `raw_window_handle::HasRawWindowHandle`.
     #[cfg(feature = "sm-raw-window-handle")]
     pub fn create_native_widget_from_rwh(
@@ -260,16 +346,33 @@ where
         Connection::new()
     }
 
+    #[inline]
+    unsafe fn from_ipc_descriptor(
+        descriptor: IpcDescriptor,
+    ) -> Result<Connection<Def, Alt>, Error> {
+        Connection::from_ipc_descriptor(descriptor)
+    }
+
     #[inline]
     fn native_connection(&self) -> NativeConnection<Def, Alt> {
         Connection::native_connection(self)
     }
 
+    #[inline]
+    fn native_display(&self) -> NativeDisplayHandle {
+        Connection::native_display(self)
+    }
+
     #[inline]
     fn gl_api(&self) -> GLApi {
         Connection::gl_api(self)
     }
 
+    #[inline]
+    fn displays(&self) -> Result<Vec<DisplayInfo>, Error> {
+        Connection::displays(self)
+    }
+
     #[inline]
     fn create_adapter(&self) -> Result<Adapter<Def, Alt>, Error> {
         Connection::create_adapter(self)
@@ -290,6 +393,14 @@ where
         Connection::create_software_adapter(self)
     }
 
+    #[inline]
+    fn create_adapter_for_display(
+        &self,
+        display: &DisplayInfo,
+    ) -> Result<Adapter<Def, Alt>, Error> {
+        Connection::create_adapter_for_display(self, display)
+    }
+
     #[inline]
     fn create_device(&self, adapter: &Adapter<Def, Alt>) -> Result<Device<Def, Alt>, Error> {
         Connection::create_device(self, adapter)
@@ -326,6 +437,14 @@ where
         Connection::create_native_widget_from_ptr(self, raw, size)
     }
 
+    #[inline]
+    unsafe fn create_native_widget_from_handle(
+        &self,
+        handle: NativeWidgetHandle,
+    ) -> Result<NativeWidget<Def, Alt>, Error> {
+        Connection::create_native_widget_from_handle(self, handle)
+    }
+
     #[cfg(feature = "sm-raw-window-handle")]
     fn create_native_widget_from_rwh(
         &self,
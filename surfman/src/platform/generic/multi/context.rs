@@ -5,10 +5,16 @@
 use super::device::Device;
 use super::surface::Surface;
 use crate::device::Device as DeviceInterface;
-use crate::{ContextAttributes, ContextID, Error, SurfaceInfo};
+use crate::gl::types::GLuint;
+use crate::{ContextAttributes, ContextDescriptorID, ContextID, Error, Gl, SurfaceInfo};
+use euclid::default::Size2D;
 
+use std::hash::{Hash, Hasher};
+use std::mem;
 use std::os::raw::c_void;
 
+type DeviceAndContext<Def, Alt> = (Device<Def, Alt>, Context<Def, Alt>);
+
 /// Represents an OpenGL rendering context.
 ///
 /// A context allows you to issue rendering commands to a surface. When initially created, a
@@ -51,6 +57,48 @@ where
     Alternate(Alt::ContextDescriptor),
 }
 
+impl<Def, Alt> PartialEq for ContextDescriptor<Def, Alt>
+where
+    Def: DeviceInterface,
+    Alt: DeviceInterface,
+{
+    fn eq(&self, other: &ContextDescriptor<Def, Alt>) -> bool {
+        match (self, other) {
+            (&ContextDescriptor::Default(ref this), &ContextDescriptor::Default(ref other)) => {
+                this == other
+            }
+            (&ContextDescriptor::Alternate(ref this), &ContextDescriptor::Alternate(ref other)) => {
+                this == other
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<Def, Alt> Eq for ContextDescriptor<Def, Alt>
+where
+    Def: DeviceInterface,
+    Alt: DeviceInterface,
+{
+}
+
+impl<Def, Alt> Hash for ContextDescriptor<Def, Alt>
+where
+    Def: DeviceInterface,
+    Alt: DeviceInterface,
+{
+    fn hash<H>(&self, state: &mut H)
+    where
+        H: Hasher,
+    {
+        mem::discriminant(self).hash(state);
+        match *self {
+            ContextDescriptor::Default(ref descriptor) => descriptor.hash(state),
+            ContextDescriptor::Alternate(ref descriptor) => descriptor.hash(state),
+        }
+    }
+}
+
 /// Wraps a platform-specific native context.
 pub enum NativeContext<Def, Alt>
 where
@@ -127,6 +175,11 @@ where
     }
 
     /// Wraps an existing native context in a `Context` object.
+    ///
+    /// # Safety
+    ///
+    /// The native context must be valid, and must stay alive as long as the returned context unless
+    /// the backend documents that it's retained.
     pub unsafe fn create_context_from_native_context(
         &self,
         native_context: NativeContext<Def, Alt>,
@@ -147,6 +200,20 @@ where
         }
     }
 
+    /// Wraps the context that is current on this thread in a new device and context, trying the
+    /// default backend first.
+    ///
+    /// # Safety
+    ///
+    /// The requirements of the backend that wraps the context apply.
+    pub unsafe fn from_current_context() -> Result<DeviceAndContext<Def, Alt>, Error> {
+        match Def::from_current_context() {
+            Ok((device, context)) => Ok((Device::Default(device), Context::Default(context))),
+            Err(_) => Alt::from_current_context()
+                .map(|(device, context)| (Device::Alternate(device), Context::Alternate(context))),
+        }
+    }
+
     /// Destroys a context.
     ///
     /// The context must have been created on this device.
@@ -203,6 +270,33 @@ where
         }
     }
 
+    /// Makes the context the current OpenGL context for this thread, rendering to the given
+    /// surface instead of any surface bound to it.
+    ///
+    /// The surface must have been created with this context, or an `IncompatibleSurface` error is
+    /// returned.
+    pub fn make_context_current_with_surface(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &Surface<Def, Alt>,
+    ) -> Result<(), Error> {
+        match (self, context) {
+            (&Device::Default(ref device), &Context::Default(ref context)) => match *surface {
+                Surface::Default(ref surface) => {
+                    device.make_context_current_with_surface(context, surface)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            (&Device::Alternate(ref device), &Context::Alternate(ref context)) => match *surface {
+                Surface::Alternate(ref surface) => {
+                    device.make_context_current_with_surface(context, surface)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
     /// Removes the current OpenGL context from this thread.
     ///
     /// After calling this function, OpenGL rendering commands will fail until a new context is
@@ -268,6 +362,51 @@ where
         }
     }
 
+    /// Makes a context render to a framebuffer object that the caller created and owns, in place
+    /// of a surface.
+    ///
+    /// The framebuffer object must stay alive until it's detached again with
+    /// `unbind_surface_from_context()`.
+    pub fn bind_external_framebuffer(
+        &self,
+        context: &mut Context<Def, Alt>,
+        framebuffer_object: GLuint,
+        size: Size2D<i32>,
+    ) -> Result<(), Error> {
+        match (self, &mut *context) {
+            (&Device::Default(ref device), &mut Context::Default(ref mut context)) => {
+                device.bind_external_framebuffer(context, framebuffer_object, size)
+            }
+            (&Device::Alternate(ref device), &mut Context::Alternate(ref mut context)) => {
+                device.bind_external_framebuffer(context, framebuffer_object, size)
+            }
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Makes the rendering submitted so far to a surface visible to everything else that reads
+    /// it.
+    ///
+    /// The surface must have been created with the given context, or an `IncompatibleSurface`
+    /// error is returned.
+    pub fn flush_context(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &Surface<Def, Alt>,
+    ) -> Result<(), Error> {
+        match (self, context) {
+            (&Device::Default(ref device), &Context::Default(ref context)) => match *surface {
+                Surface::Default(ref surface) => device.flush_context(context, surface),
+                _ => Err(Error::IncompatibleSurface),
+            },
+            (&Device::Alternate(ref device), &Context::Alternate(ref context)) => match *surface {
+                Surface::Alternate(ref surface) => device.flush_context(context, surface),
+                _ => Err(Error::IncompatibleSurface),
+            },
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
     /// Returns the attributes that the context descriptor was created with.
     pub fn context_descriptor_attributes(
         &self,
@@ -285,6 +424,24 @@ where
         }
     }
 
+    /// Returns a small ID for the given context descriptor, which is the same for every equal
+    /// descriptor.
+    pub fn intern_context_descriptor(
+        &self,
+        context_descriptor: &ContextDescriptor<Def, Alt>,
+    ) -> ContextDescriptorID {
+        match (self, context_descriptor) {
+            (&Device::Default(ref device), &ContextDescriptor::Default(ref context_descriptor)) => {
+                device.intern_context_descriptor(context_descriptor)
+            }
+            (
+                &Device::Alternate(ref device),
+                &ContextDescriptor::Alternate(ref context_descriptor),
+            ) => device.intern_context_descriptor(context_descriptor),
+            _ => panic!("Incompatible context!"),
+        }
+    }
+
     /// Fetches the address of an OpenGL function associated with this context.
     ///
     /// OpenGL functions are local to a context. You should not use OpenGL functions on one context
@@ -308,6 +465,22 @@ where
         }
     }
 
+    // Calls the given function with the OpenGL functions that the backend uses with the context.
+    pub(crate) fn with_gl_functions<F, R>(&self, context: &Context<Def, Alt>, callback: F) -> R
+    where
+        F: FnOnce(&Gl) -> R,
+    {
+        match (self, context) {
+            (&Device::Default(ref device), &Context::Default(ref context)) => {
+                device.with_gl_functions(context, callback)
+            }
+            (&Device::Alternate(ref device), &Context::Alternate(ref context)) => {
+                device.with_gl_functions(context, callback)
+            }
+            _ => panic!("Incompatible context!"),
+        }
+    }
+
     /// Returns a unique ID representing a context.
     ///
     /// This ID is unique to all currently-allocated contexts. If you destroy a context and create
@@ -4,19 +4,28 @@
 
 use super::connection::Connection;
 use super::context::{Context, ContextDescriptor, NativeContext};
-use super::surface::{NativeWidget, Surface, SurfaceTexture};
+use super::surface::{NativeWidget, Surface, SurfaceDestroyToken, SurfaceTexture};
 use crate::connection::Connection as ConnectionInterface;
 use crate::context::ContextAttributes;
-use crate::device::Device as DeviceInterface;
+use crate::device::{Device as DeviceInterface, NativeDeviceHandle};
 use crate::gl::types::{GLenum, GLuint};
-use crate::{ContextID, Error, GLApi, SurfaceAccess, SurfaceInfo, SurfaceType};
-use euclid::default::Size2D;
+#[cfg(feature = "sm-opencl")]
+use crate::opencl::{GLSharing, GLSharingProperties, SharedEGLImage};
+use crate::CreationTimings;
+use crate::{
+    Colorspace, ContextID, Error, GLApi, Gl, PresentationStatistics, SurfaceAccess,
+    SurfaceAlphaMode, SurfaceInfo, SurfaceUsage,
+};
+use crate::{ContextDescriptorID, DeviceEventHandler, SurfaceLayoutRequirements, WidgetOutputInfo};
+use crate::{LifecycleObserver, SurfaceFormat, SurfaceOrigin, SurfaceType, SurfaceVisibility};
+use euclid::default::{Rect, Size2D};
 
 use std::os::raw::c_void;
+use std::sync::Arc;
 
 /// Represents a hardware display adapter that can be used for rendering (including the CPU).
 ///
-/// Adapters can be sent between threads. To render with an adapter, open a thread-local `Device`.
+/// Adapters can be sent between threads. To render with an adapter, open a `Device`.
 pub enum Adapter<Def, Alt>
 where
     Def: DeviceInterface,
@@ -43,9 +52,10 @@ where
     }
 }
 
-/// A thread-local handle to a device.
+/// A handle to a device.
 ///
-/// Devices contain most of the relevant surface management methods.
+/// Devices contain most of the relevant surface management methods. Clones can be sent to other
+/// threads if both device types can, so each rendering thread can own a handle.
 pub enum Device<Def, Alt>
 where
     Def: DeviceInterface,
@@ -57,6 +67,19 @@ where
     Alternate(Alt),
 }
 
+impl<Def, Alt> Clone for Device<Def, Alt>
+where
+    Def: DeviceInterface,
+    Alt: DeviceInterface,
+{
+    fn clone(&self) -> Self {
+        match self {
+            Device::Default(ref device) => Device::Default(device.clone()),
+            Device::Alternate(ref device) => Device::Alternate(device.clone()),
+        }
+    }
+}
+
 /// Represents a native platform-specific device.
 pub enum NativeDevice<Def, Alt>
 where
@@ -84,6 +107,14 @@ where
         }
     }
 
+    /// Returns the raw display or GPU device underneath this device.
+    pub fn native_device_handle(&self) -> NativeDeviceHandle {
+        match *self {
+            Device::Default(ref device) => device.native_device_handle(),
+            Device::Alternate(ref device) => device.native_device_handle(),
+        }
+    }
+
     /// Returns the display server connection that this device was created with.
     pub fn connection(&self) -> Connection<Def, Alt> {
         match *self {
@@ -107,6 +138,57 @@ where
             Device::Alternate(ref device) => device.gl_api(),
         }
     }
+
+    /// Installs an observer that is notified when contexts and surfaces of this device are
+    /// created, destroyed, resized, or presented, replacing any previous one.
+    pub fn set_lifecycle_observer(&mut self, observer: Option<Arc<dyn LifecycleObserver>>) {
+        match *self {
+            Device::Default(ref mut device) => device.set_lifecycle_observer(observer),
+            Device::Alternate(ref mut device) => device.set_lifecycle_observer(observer),
+        }
+    }
+
+    /// Returns the lifecycle observer installed on this device, if any.
+    pub fn lifecycle_observer(&self) -> Option<Arc<dyn LifecycleObserver>> {
+        match *self {
+            Device::Default(ref device) => device.lifecycle_observer(),
+            Device::Alternate(ref device) => device.lifecycle_observer(),
+        }
+    }
+
+    /// Installs a handler that is told when the GPU of this device is removed or reset, or the
+    /// displays are reconfigured, replacing any previous one.
+    pub fn set_device_event_handler(&mut self, handler: Option<DeviceEventHandler>) {
+        match *self {
+            Device::Default(ref mut device) => device.set_device_event_handler(handler),
+            Device::Alternate(ref mut device) => device.set_device_event_handler(handler),
+        }
+    }
+
+    /// Returns the device event handler installed on this device, if any.
+    pub fn device_event_handler(&self) -> Option<DeviceEventHandler> {
+        match *self {
+            Device::Default(ref device) => device.device_event_handler(),
+            Device::Alternate(ref device) => device.device_event_handler(),
+        }
+    }
+
+    /// Returns the GPU memory, in bytes, held by the surfaces of this device.
+    pub fn memory_usage(&self) -> usize {
+        match *self {
+            Device::Default(ref device) => device.memory_usage(),
+            Device::Alternate(ref device) => device.memory_usage(),
+        }
+    }
+
+    /// Returns how long opening this device, and creating its context descriptors and contexts,
+    /// took.
+    pub fn creation_timings(&self) -> CreationTimings {
+        match *self {
+            Device::Default(ref device) => device.creation_timings(),
+            Device::Alternate(ref device) => device.creation_timings(),
+        }
+    }
 }
 
 impl<Def, Alt> DeviceInterface for Device<Def, Alt>
@@ -115,6 +197,8 @@ where
     Alt: DeviceInterface,
     Def::Connection: ConnectionInterface<Device = Def>,
     Alt::Connection: ConnectionInterface<Device = Alt>,
+    Def::Surface: Send + 'static,
+    Alt::Surface: Send + 'static,
 {
     type Connection = Connection<Def, Alt>;
     type Context = Context<Def, Alt>;
@@ -122,6 +206,7 @@ where
     type NativeContext = NativeContext<Def, Alt>;
     type Surface = Surface<Def, Alt>;
     type SurfaceTexture = SurfaceTexture<Def, Alt>;
+    type SurfaceDestroyToken = SurfaceDestroyToken<Def, Alt>;
 
     // device.rs
 
@@ -130,6 +215,11 @@ where
         Device::native_device(self)
     }
 
+    #[inline]
+    fn native_device_handle(&self) -> NativeDeviceHandle {
+        Device::native_device_handle(self)
+    }
+
     #[inline]
     fn connection(&self) -> Connection<Def, Alt> {
         Device::connection(self)
@@ -145,6 +235,36 @@ where
         Device::gl_api(self)
     }
 
+    #[inline]
+    fn set_lifecycle_observer(&mut self, observer: Option<Arc<dyn LifecycleObserver>>) {
+        Device::set_lifecycle_observer(self, observer)
+    }
+
+    #[inline]
+    fn lifecycle_observer(&self) -> Option<Arc<dyn LifecycleObserver>> {
+        Device::lifecycle_observer(self)
+    }
+
+    #[inline]
+    fn set_device_event_handler(&mut self, handler: Option<DeviceEventHandler>) {
+        Device::set_device_event_handler(self, handler)
+    }
+
+    #[inline]
+    fn device_event_handler(&self) -> Option<DeviceEventHandler> {
+        Device::device_event_handler(self)
+    }
+
+    #[inline]
+    fn memory_usage(&self) -> usize {
+        Device::memory_usage(self)
+    }
+
+    #[inline]
+    fn creation_timings(&self) -> CreationTimings {
+        Device::creation_timings(self)
+    }
+
     // context.rs
 
     #[inline]
@@ -155,6 +275,14 @@ where
         Device::create_context_descriptor(self, attributes)
     }
 
+    #[inline]
+    fn intern_context_descriptor(
+        &self,
+        context_descriptor: &ContextDescriptor<Def, Alt>,
+    ) -> ContextDescriptorID {
+        Device::intern_context_descriptor(self, context_descriptor)
+    }
+
     #[inline]
     fn create_context(
         &mut self,
@@ -172,6 +300,11 @@ where
         Device::create_context_from_native_context(self, native_context)
     }
 
+    #[inline]
+    unsafe fn from_current_context() -> Result<(Device<Def, Alt>, Context<Def, Alt>), Error> {
+        Device::from_current_context()
+    }
+
     #[inline]
     fn destroy_context(&self, context: &mut Context<Def, Alt>) -> Result<(), Error> {
         Device::destroy_context(self, context)
@@ -192,6 +325,15 @@ where
         Device::make_context_current(self, context)
     }
 
+    #[inline]
+    fn make_context_current_with_surface(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &Surface<Def, Alt>,
+    ) -> Result<(), Error> {
+        Device::make_context_current_with_surface(self, context, surface)
+    }
+
     #[inline]
     fn make_no_context_current(&self) -> Result<(), Error> {
         Device::make_no_context_current(self)
@@ -210,6 +352,14 @@ where
         Device::get_proc_address(self, context, symbol_name)
     }
 
+    #[inline]
+    fn with_gl_functions<F, R>(&self, context: &Context<Def, Alt>, callback: F) -> R
+    where
+        F: FnOnce(&Gl) -> R,
+    {
+        Device::with_gl_functions(self, context, callback)
+    }
+
     #[inline]
     fn bind_surface_to_context(
         &self,
@@ -227,6 +377,25 @@ where
         Device::unbind_surface_from_context(self, context)
     }
 
+    #[inline]
+    fn bind_external_framebuffer(
+        &self,
+        context: &mut Context<Def, Alt>,
+        framebuffer_object: GLuint,
+        size: Size2D<i32>,
+    ) -> Result<(), Error> {
+        Device::bind_external_framebuffer(self, context, framebuffer_object, size)
+    }
+
+    #[inline]
+    fn flush_context(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &Surface<Def, Alt>,
+    ) -> Result<(), Error> {
+        Device::flush_context(self, context, surface)
+    }
+
     #[inline]
     fn context_id(&self, context: &Context<Def, Alt>) -> ContextID {
         Device::context_id(self, context)
@@ -247,9 +416,86 @@ where
         &mut self,
         context: &Context<Def, Alt>,
         surface_access: SurfaceAccess,
+        surface_usage: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
         surface_type: SurfaceType<NativeWidget<Def, Alt>>,
     ) -> Result<Surface<Def, Alt>, Error> {
-        Device::create_surface(self, context, surface_access, surface_type)
+        Device::create_surface(
+            self,
+            context,
+            surface_access,
+            surface_usage,
+            surface_alpha_mode,
+            surface_type,
+        )
+    }
+
+    #[inline]
+    fn create_surfaces(
+        &mut self,
+        context: &Context<Def, Alt>,
+        surface_access: SurfaceAccess,
+        surface_usage: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
+        size: &Size2D<i32>,
+        count: usize,
+    ) -> Result<Vec<Surface<Def, Alt>>, (Error, Vec<Surface<Def, Alt>>)> {
+        Device::create_surfaces(
+            self,
+            context,
+            surface_access,
+            surface_usage,
+            surface_alpha_mode,
+            size,
+            count,
+        )
+    }
+
+    #[inline]
+    fn create_layered_surface(
+        &mut self,
+        context: &Context<Def, Alt>,
+        surface_access: SurfaceAccess,
+        surface_usage: SurfaceUsage,
+        size: &Size2D<i32>,
+        layers: u32,
+    ) -> Result<Surface<Def, Alt>, Error> {
+        Device::create_layered_surface(self, context, surface_access, surface_usage, size, layers)
+    }
+
+    #[inline]
+    fn set_surface_layer(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+        layer: u32,
+    ) -> Result<(), Error> {
+        Device::set_surface_layer(self, context, surface, layer)
+    }
+
+    #[inline]
+    fn surface_layers(&self, surface: &Surface<Def, Alt>) -> u32 {
+        Device::surface_layers(self, surface)
+    }
+
+    #[inline]
+    fn convert_surface_format(
+        &mut self,
+        context: &mut Context<Def, Alt>,
+        surface: &Surface<Def, Alt>,
+        format: SurfaceFormat,
+    ) -> Result<Surface<Def, Alt>, Error> {
+        Device::convert_surface_format(self, context, surface, format)
+    }
+
+    #[inline]
+    fn create_surface_from_texture(
+        &mut self,
+        context: &mut Context<Def, Alt>,
+        size: &Size2D<i32>,
+        texture_object: GLuint,
+    ) -> Result<Surface<Def, Alt>, Error> {
+        Device::create_surface_from_texture(self, context, size, texture_object)
     }
 
     #[inline]
@@ -270,6 +516,45 @@ where
         Device::destroy_surface(self, context, surface)
     }
 
+    #[inline]
+    fn surface_into_destroy_token(
+        &self,
+        surface: Surface<Def, Alt>,
+    ) -> SurfaceDestroyToken<Def, Alt> {
+        Device::surface_into_destroy_token(self, surface)
+    }
+
+    #[inline]
+    fn destroy_token(&self, token: SurfaceDestroyToken<Def, Alt>) -> Result<(), Error> {
+        Device::destroy_token(self, token)
+    }
+
+    // Surfaces are queued with the backend that created them, so that its device destroys them
+    // when their context is made current, bound, unbound, or destroyed.
+    fn defer_destroy_surface(&self, surface: Surface<Def, Alt>) {
+        match (self, surface) {
+            (&Device::Default(ref device), Surface::Default(surface)) => {
+                device.defer_destroy_surface(surface)
+            }
+            (&Device::Alternate(ref device), Surface::Alternate(surface)) => {
+                device.defer_destroy_surface(surface)
+            }
+            _ => panic!("Incompatible context!"),
+        }
+    }
+
+    fn destroy_deferred_surfaces(&self, context: &mut Context<Def, Alt>) -> Result<(), Error> {
+        match (self, &mut *context) {
+            (&Device::Default(ref device), &mut Context::Default(ref mut context)) => {
+                device.destroy_deferred_surfaces(context)
+            }
+            (&Device::Alternate(ref device), &mut Context::Alternate(ref mut context)) => {
+                device.destroy_deferred_surfaces(context)
+            }
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
     #[inline]
     fn destroy_surface_texture(
         &self,
@@ -284,6 +569,25 @@ where
         Device::surface_gl_texture_target(self)
     }
 
+    #[inline]
+    fn surface_texture_binding_target(&self, surface_texture: &SurfaceTexture<Def, Alt>) -> GLenum {
+        Device::surface_texture_binding_target(self, surface_texture)
+    }
+
+    #[inline]
+    fn surface_texture_layers(&self, surface_texture: &SurfaceTexture<Def, Alt>) -> u32 {
+        Device::surface_texture_layers(self, surface_texture)
+    }
+
+    #[inline]
+    fn generate_surface_mipmaps(
+        &self,
+        context: &mut Context<Def, Alt>,
+        surface_texture: &SurfaceTexture<Def, Alt>,
+    ) -> Result<(), Error> {
+        Device::generate_surface_mipmaps(self, context, surface_texture)
+    }
+
     #[inline]
     fn present_surface(
         &self,
@@ -293,6 +597,74 @@ where
         Device::present_surface(self, context, surface)
     }
 
+    #[inline]
+    fn present_surface_with_damage(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        Device::present_surface_with_damage(self, context, surface, damage)
+    }
+
+    #[inline]
+    fn present_surface_with_viewport(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+        source: &Rect<i32>,
+        destination: &Rect<i32>,
+    ) -> Result<(), Error> {
+        Device::present_surface_with_viewport(self, context, surface, source, destination)
+    }
+
+    #[inline]
+    fn set_surface_damage_region(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+        region: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        Device::set_surface_damage_region(self, context, surface, region)
+    }
+
+    #[inline]
+    fn surface_buffer_age(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &Surface<Def, Alt>,
+    ) -> Result<u32, Error> {
+        Device::surface_buffer_age(self, context, surface)
+    }
+
+    #[inline]
+    fn set_surface_preserve_buffer(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+        preserve: bool,
+    ) -> Result<(), Error> {
+        Device::set_surface_preserve_buffer(self, context, surface, preserve)
+    }
+
+    #[inline]
+    fn set_surface_origin(
+        &self,
+        surface: &mut Surface<Def, Alt>,
+        origin: SurfaceOrigin,
+    ) -> Result<(), Error> {
+        Device::set_surface_origin(self, surface, origin)
+    }
+
+    #[inline]
+    fn set_surface_colorspace(
+        &self,
+        surface: &mut Surface<Def, Alt>,
+        colorspace: Colorspace,
+    ) -> Result<(), Error> {
+        Device::set_surface_colorspace(self, surface, colorspace)
+    }
+
     #[inline]
     fn resize_surface(
         &self,
@@ -308,8 +680,94 @@ where
         Device::surface_info(self, surface)
     }
 
+    #[inline]
+    fn surface_memory_usage(&self, surface: &Surface<Def, Alt>) -> usize {
+        Device::surface_memory_usage(self, surface)
+    }
+
+    #[inline]
+    fn surface_seed(&self, surface: &Surface<Def, Alt>) -> Result<u32, Error> {
+        Device::surface_seed(self, surface)
+    }
+
+    #[inline]
+    fn surface_layout_requirements(
+        &self,
+        size: &Size2D<i32>,
+        format: SurfaceFormat,
+        access: SurfaceAccess,
+    ) -> Result<SurfaceLayoutRequirements, Error> {
+        Device::surface_layout_requirements(self, size, format, access)
+    }
+
+    #[inline]
+    fn surface_presentation_statistics(
+        &self,
+        surface: &Surface<Def, Alt>,
+    ) -> Option<PresentationStatistics> {
+        Device::surface_presentation_statistics(self, surface)
+    }
+
+    #[inline]
+    fn surface_visibility(&self, surface: &Self::Surface) -> SurfaceVisibility {
+        Device::surface_visibility(self, surface)
+    }
+
+    #[inline]
+    fn widget_output_info(&self, surface: &Self::Surface) -> Result<WidgetOutputInfo, Error> {
+        Device::widget_output_info(self, surface)
+    }
+
     #[inline]
     fn surface_texture_object(&self, surface_texture: &SurfaceTexture<Def, Alt>) -> GLuint {
         Device::surface_texture_object(self, surface_texture)
     }
+
+    #[inline]
+    fn set_surface_label(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &Surface<Def, Alt>,
+        label: &str,
+    ) -> Result<(), Error> {
+        Device::set_surface_label(self, context, surface, label)
+    }
+}
+
+#[cfg(feature = "sm-opencl")]
+impl<Def, Alt> GLSharing for Device<Def, Alt>
+where
+    Def: DeviceInterface + GLSharing,
+    Alt: DeviceInterface + GLSharing,
+    Def::Connection: ConnectionInterface<Device = Def>,
+    Alt::Connection: ConnectionInterface<Device = Alt>,
+    Def::Surface: Send + 'static,
+    Alt::Surface: Send + 'static,
+{
+    fn gl_sharing_properties(
+        &self,
+        context: &Context<Def, Alt>,
+    ) -> Result<GLSharingProperties, Error> {
+        match (self, context) {
+            (&Device::Default(ref device), &Context::Default(ref context)) => {
+                device.gl_sharing_properties(context)
+            }
+            (&Device::Alternate(ref device), &Context::Alternate(ref context)) => {
+                device.gl_sharing_properties(context)
+            }
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    fn surface_egl_image(&self, surface: &Surface<Def, Alt>) -> Result<SharedEGLImage, Error> {
+        match (self, surface) {
+            (&Device::Default(ref device), &Surface::Default(ref surface)) => {
+                device.surface_egl_image(surface)
+            }
+            (&Device::Alternate(ref device), &Surface::Alternate(ref surface)) => {
+                device.surface_egl_image(surface)
+            }
+            _ => Err(Error::IncompatibleSurface),
+        }
+    }
 }
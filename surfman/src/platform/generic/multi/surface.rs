@@ -7,8 +7,14 @@ use super::device::Device;
 use crate::connection::Connection as ConnectionInterface;
 use crate::device::Device as DeviceInterface;
 use crate::gl::types::{GLenum, GLuint};
-use crate::{Error, SurfaceAccess, SurfaceInfo, SurfaceType};
-use euclid::default::Size2D;
+use crate::{Colorspace, SurfaceInfo};
+use crate::{
+    CreateSurfacesResult, Error, PresentationStatistics, SurfaceAccess, SurfaceAlphaMode,
+    SurfaceFormat,
+};
+use crate::{SurfaceLayoutRequirements, SurfaceOrigin, SurfaceType, SurfaceUsage};
+use crate::{SurfaceVisibility, WidgetOutputInfo};
+use euclid::default::{Rect, Size2D};
 
 use std::fmt::{self, Debug, Formatter};
 
@@ -59,6 +65,21 @@ where
     Alternate(Alt::SurfaceTexture),
 }
 
+/// The native resources of a surface given up with `Device::surface_into_destroy_token()`, which
+/// can be destroyed later without the surface's context.
+///
+/// Tokens must be destroyed with `Device::destroy_token()`, or their resources leak.
+pub enum SurfaceDestroyToken<Def, Alt>
+where
+    Def: DeviceInterface,
+    Alt: DeviceInterface,
+{
+    /// The default surface destroy token type.
+    Default(Def::SurfaceDestroyToken),
+    /// The alternate surface destroy token type.
+    Alternate(Alt::SurfaceDestroyToken),
+}
+
 /// A native widget/window type that can dynamically switch between backends.
 pub enum NativeWidget<Def, Alt>
 where
@@ -104,6 +125,8 @@ where
         &mut self,
         context: &Context<Def, Alt>,
         surface_access: SurfaceAccess,
+        surface_usage: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
         surface_type: SurfaceType<NativeWidget<Def, Alt>>,
     ) -> Result<Surface<Def, Alt>, Error> {
         match (&mut *self, context) {
@@ -118,7 +141,13 @@ where
                     }
                 };
                 device
-                    .create_surface(context, surface_access, surface_type)
+                    .create_surface(
+                        context,
+                        surface_access,
+                        surface_usage,
+                        surface_alpha_mode,
+                        surface_type,
+                    )
                     .map(Surface::Default)
             }
             (&mut Device::Alternate(ref mut device), &Context::Alternate(ref context)) => {
@@ -132,7 +161,179 @@ where
                     }
                 };
                 device
-                    .create_surface(context, surface_access, surface_type)
+                    .create_surface(
+                        context,
+                        surface_access,
+                        surface_usage,
+                        surface_alpha_mode,
+                        surface_type,
+                    )
+                    .map(Surface::Alternate)
+            }
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Creates `count` generic surfaces of the given size, sharing the work common to all of them.
+    ///
+    /// If one of the surfaces fails to be created, the error is returned along with the surfaces
+    /// that were created before it, which the caller must destroy.
+    pub fn create_surfaces(
+        &mut self,
+        context: &Context<Def, Alt>,
+        surface_access: SurfaceAccess,
+        surface_usage: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
+        size: &Size2D<i32>,
+        count: usize,
+    ) -> CreateSurfacesResult<Surface<Def, Alt>> {
+        match (&mut *self, context) {
+            (&mut Device::Default(ref mut device), &Context::Default(ref context)) => {
+                match device.create_surfaces(
+                    context,
+                    surface_access,
+                    surface_usage,
+                    surface_alpha_mode,
+                    size,
+                    count,
+                ) {
+                    Ok(surfaces) => Ok(surfaces.into_iter().map(Surface::Default).collect()),
+                    Err((err, surfaces)) => {
+                        Err((err, surfaces.into_iter().map(Surface::Default).collect()))
+                    }
+                }
+            }
+            (&mut Device::Alternate(ref mut device), &Context::Alternate(ref context)) => {
+                match device.create_surfaces(
+                    context,
+                    surface_access,
+                    surface_usage,
+                    surface_alpha_mode,
+                    size,
+                    count,
+                ) {
+                    Ok(surfaces) => Ok(surfaces.into_iter().map(Surface::Alternate).collect()),
+                    Err((err, surfaces)) => {
+                        Err((err, surfaces.into_iter().map(Surface::Alternate).collect()))
+                    }
+                }
+            }
+            _ => Err((Error::IncompatibleContext, vec![])),
+        }
+    }
+
+    /// Creates a generic surface with the given number of array layers, of which the context
+    /// renders to one at a time.
+    ///
+    /// Backends without layered surfaces return `Error::UnsupportedOnThisPlatform`.
+    pub fn create_layered_surface(
+        &mut self,
+        context: &Context<Def, Alt>,
+        surface_access: SurfaceAccess,
+        surface_usage: SurfaceUsage,
+        size: &Size2D<i32>,
+        layers: u32,
+    ) -> Result<Surface<Def, Alt>, Error> {
+        match (&mut *self, context) {
+            (&mut Device::Default(ref mut device), &Context::Default(ref context)) => device
+                .create_layered_surface(context, surface_access, surface_usage, size, layers)
+                .map(Surface::Default),
+            (&mut Device::Alternate(ref mut device), &Context::Alternate(ref context)) => device
+                .create_layered_surface(context, surface_access, surface_usage, size, layers)
+                .map(Surface::Alternate),
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Chooses which layer of a layered surface the given context renders to.
+    pub fn set_surface_layer(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+        layer: u32,
+    ) -> Result<(), Error> {
+        match (self, context) {
+            (&Device::Default(ref device), &Context::Default(ref context)) => match *surface {
+                Surface::Default(ref mut surface) => {
+                    device.set_surface_layer(context, surface, layer)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            (&Device::Alternate(ref device), &Context::Alternate(ref context)) => match *surface {
+                Surface::Alternate(ref mut surface) => {
+                    device.set_surface_layer(context, surface, layer)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Returns the number of array layers of the given surface, which is 1 unless it was created
+    /// with `create_layered_surface()`.
+    pub fn surface_layers(&self, surface: &Surface<Def, Alt>) -> u32 {
+        match (self, surface) {
+            (&Device::Default(ref device), Surface::Default(ref surface)) => {
+                device.surface_layers(surface)
+            }
+            (&Device::Alternate(ref device), Surface::Alternate(ref surface)) => {
+                device.surface_layers(surface)
+            }
+            _ => panic!("Incompatible context!"),
+        }
+    }
+
+    /// Creates a new generic surface with a copy of the contents of the given one, stored with
+    /// its color channels in the given order.
+    ///
+    /// The surface must be a generic surface belonging to the given context.
+    pub fn convert_surface_format(
+        &mut self,
+        context: &mut Context<Def, Alt>,
+        surface: &Surface<Def, Alt>,
+        format: SurfaceFormat,
+    ) -> Result<Surface<Def, Alt>, Error> {
+        match (&mut *self, &mut *context) {
+            (&mut Device::Default(ref mut device), &mut Context::Default(ref mut context)) => {
+                match *surface {
+                    Surface::Default(ref surface) => device
+                        .convert_surface_format(context, surface, format)
+                        .map(Surface::Default),
+                    _ => Err(Error::IncompatibleSurface),
+                }
+            }
+            (&mut Device::Alternate(ref mut device), &mut Context::Alternate(ref mut context)) => {
+                match *surface {
+                    Surface::Alternate(ref surface) => device
+                        .convert_surface_format(context, surface, format)
+                        .map(Surface::Alternate),
+                    _ => Err(Error::IncompatibleSurface),
+                }
+            }
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Wraps an existing OpenGL texture of the given context in a new generic surface, so that
+    /// textures produced outside surfman can be shared or presented like any other surface.
+    ///
+    /// The texture stays owned by the caller, but must not be redefined while the surface is
+    /// alive. The given context is left current.
+    pub fn create_surface_from_texture(
+        &mut self,
+        context: &mut Context<Def, Alt>,
+        size: &Size2D<i32>,
+        texture_object: GLuint,
+    ) -> Result<Surface<Def, Alt>, Error> {
+        match (&mut *self, &mut *context) {
+            (&mut Device::Default(ref mut device), &mut Context::Default(ref mut context)) => {
+                device
+                    .create_surface_from_texture(context, size, texture_object)
+                    .map(Surface::Default)
+            }
+            (&mut Device::Alternate(ref mut device), &mut Context::Alternate(ref mut context)) => {
+                device
+                    .create_surface_from_texture(context, size, texture_object)
                     .map(Surface::Alternate)
             }
             _ => Err(Error::IncompatibleContext),
@@ -209,6 +410,41 @@ where
         }
     }
 
+    /// Gives up a surface, returning a token that can be destroyed later with `destroy_token()`,
+    /// on any thread and without the surface's context.
+    ///
+    /// The OpenGL objects that the surface owns in its context, such as its framebuffer, aren't
+    /// part of the token. They're freed when the context is destroyed.
+    pub fn surface_into_destroy_token(
+        &self,
+        surface: Surface<Def, Alt>,
+    ) -> SurfaceDestroyToken<Def, Alt> {
+        match (self, surface) {
+            (&Device::Default(ref device), Surface::Default(surface)) => {
+                SurfaceDestroyToken::Default(device.surface_into_destroy_token(surface))
+            }
+            (&Device::Alternate(ref device), Surface::Alternate(surface)) => {
+                SurfaceDestroyToken::Alternate(device.surface_into_destroy_token(surface))
+            }
+            _ => panic!("Incompatible context!"),
+        }
+    }
+
+    /// Destroys the native resources of a surface given up with `surface_into_destroy_token()`.
+    ///
+    /// Unlike `destroy_surface()`, this doesn't need the context that the surface belonged to.
+    pub fn destroy_token(&self, token: SurfaceDestroyToken<Def, Alt>) -> Result<(), Error> {
+        match (self, token) {
+            (&Device::Default(ref device), SurfaceDestroyToken::Default(token)) => {
+                device.destroy_token(token)
+            }
+            (&Device::Alternate(ref device), SurfaceDestroyToken::Alternate(token)) => {
+                device.destroy_token(token)
+            }
+            _ => Err(Error::IncompatibleSurface),
+        }
+    }
+
     /// Destroys a surface texture and returns the underlying surface.
     ///
     /// The supplied context must be the same context the surface texture was created with, or an
@@ -277,6 +513,168 @@ where
         }
     }
 
+    /// Displays the contents of a widget surface on screen, hinting that only the given
+    /// rectangles have changed since the last present.
+    ///
+    /// The rectangles are in device pixels, with the origin at the lower left corner of the
+    /// surface. An empty slice means that the whole surface changed.
+    pub fn present_surface_with_damage(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        match (self, context) {
+            (&Device::Default(ref device), &Context::Default(ref context)) => match *surface {
+                Surface::Default(ref mut surface) => {
+                    device.present_surface_with_damage(context, surface, damage)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            (&Device::Alternate(ref device), &Context::Alternate(ref context)) => match *surface {
+                Surface::Alternate(ref mut surface) => {
+                    device.present_surface_with_damage(context, surface, damage)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Displays the `source` rectangle of a widget surface in the `destination` rectangle of its
+    /// widget, scaling it to fit.
+    ///
+    /// The rectangles are in device pixels, with the origin at the lower left corner. The viewport
+    /// applies only to this present.
+    pub fn present_surface_with_viewport(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+        source: &Rect<i32>,
+        destination: &Rect<i32>,
+    ) -> Result<(), Error> {
+        match (self, context) {
+            (&Device::Default(ref device), &Context::Default(ref context)) => match *surface {
+                Surface::Default(ref mut surface) => {
+                    device.present_surface_with_viewport(context, surface, source, destination)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            (&Device::Alternate(ref device), &Context::Alternate(ref context)) => match *surface {
+                Surface::Alternate(ref mut surface) => {
+                    device.present_surface_with_viewport(context, surface, source, destination)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Declares which parts of a widget surface will be redrawn before it is next presented.
+    ///
+    /// This must be called before any rendering to the frame. An empty slice means that the
+    /// whole surface will be redrawn.
+    pub fn set_surface_damage_region(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+        region: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        match (self, context) {
+            (&Device::Default(ref device), &Context::Default(ref context)) => match *surface {
+                Surface::Default(ref mut surface) => {
+                    device.set_surface_damage_region(context, surface, region)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            (&Device::Alternate(ref device), &Context::Alternate(ref context)) => match *surface {
+                Surface::Alternate(ref mut surface) => {
+                    device.set_surface_damage_region(context, surface, region)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Returns the number of presents ago that the current back buffer of a widget surface was
+    /// displayed, or 0 if its contents are undefined.
+    pub fn surface_buffer_age(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &Surface<Def, Alt>,
+    ) -> Result<u32, Error> {
+        match (self, context) {
+            (&Device::Default(ref device), &Context::Default(ref context)) => match *surface {
+                Surface::Default(ref surface) => device.surface_buffer_age(context, surface),
+                _ => Err(Error::IncompatibleSurface),
+            },
+            (&Device::Alternate(ref device), &Context::Alternate(ref context)) => match *surface {
+                Surface::Alternate(ref surface) => device.surface_buffer_age(context, surface),
+                _ => Err(Error::IncompatibleSurface),
+            },
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Sets whether presenting a widget surface preserves its contents.
+    pub fn set_surface_preserve_buffer(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+        preserve: bool,
+    ) -> Result<(), Error> {
+        match (self, context) {
+            (&Device::Default(ref device), &Context::Default(ref context)) => match *surface {
+                Surface::Default(ref mut surface) => {
+                    device.set_surface_preserve_buffer(context, surface, preserve)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            (&Device::Alternate(ref device), &Context::Alternate(ref context)) => match *surface {
+                Surface::Alternate(ref mut surface) => {
+                    device.set_surface_preserve_buffer(context, surface, preserve)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Sets which way up the contents of a surface are.
+    pub fn set_surface_origin(
+        &self,
+        surface: &mut Surface<Def, Alt>,
+        origin: SurfaceOrigin,
+    ) -> Result<(), Error> {
+        match (self, surface) {
+            (&Device::Default(ref device), &mut Surface::Default(ref mut surface)) => {
+                device.set_surface_origin(surface, origin)
+            }
+            (&Device::Alternate(ref device), &mut Surface::Alternate(ref mut surface)) => {
+                device.set_surface_origin(surface, origin)
+            }
+            _ => Err(Error::IncompatibleSurface),
+        }
+    }
+
+    /// Sets the color space that the contents of a surface are in.
+    pub fn set_surface_colorspace(
+        &self,
+        surface: &mut Surface<Def, Alt>,
+        colorspace: Colorspace,
+    ) -> Result<(), Error> {
+        match (self, surface) {
+            (&Device::Default(ref device), &mut Surface::Default(ref mut surface)) => {
+                device.set_surface_colorspace(surface, colorspace)
+            }
+            (&Device::Alternate(ref device), &mut Surface::Alternate(ref mut surface)) => {
+                device.set_surface_colorspace(surface, colorspace)
+            }
+            _ => Err(Error::IncompatibleSurface),
+        }
+    }
+
     /// Resizes a widget surface.
     pub fn resize_surface(
         &self,
@@ -310,6 +708,65 @@ where
         }
     }
 
+    /// Returns the OpenGL texture target needed to read from the given surface texture.
+    pub fn surface_texture_binding_target(
+        &self,
+        surface_texture: &SurfaceTexture<Def, Alt>,
+    ) -> GLenum {
+        match (self, surface_texture) {
+            (&Device::Default(ref device), SurfaceTexture::Default(ref surface_texture)) => {
+                device.surface_texture_binding_target(surface_texture)
+            }
+            (&Device::Alternate(ref device), SurfaceTexture::Alternate(ref surface_texture)) => {
+                device.surface_texture_binding_target(surface_texture)
+            }
+            _ => panic!("Incompatible context!"),
+        }
+    }
+
+    /// Returns the number of layers of the surface that a surface texture wraps.
+    pub fn surface_texture_layers(&self, surface_texture: &SurfaceTexture<Def, Alt>) -> u32 {
+        match (self, surface_texture) {
+            (&Device::Default(ref device), SurfaceTexture::Default(ref surface_texture)) => {
+                device.surface_texture_layers(surface_texture)
+            }
+            (&Device::Alternate(ref device), SurfaceTexture::Alternate(ref surface_texture)) => {
+                device.surface_texture_layers(surface_texture)
+            }
+            _ => panic!("Incompatible context!"),
+        }
+    }
+
+    /// Fills in the mip chain of a surface texture from the current contents of its surface, so
+    /// that it can be sampled with mipmapping at scales well below its size.
+    ///
+    /// The given context must be the one the surface texture was created for, and is left current.
+    pub fn generate_surface_mipmaps(
+        &self,
+        context: &mut Context<Def, Alt>,
+        surface_texture: &SurfaceTexture<Def, Alt>,
+    ) -> Result<(), Error> {
+        match (self, &mut *context) {
+            (&Device::Default(ref device), &mut Context::Default(ref mut context)) => {
+                match *surface_texture {
+                    SurfaceTexture::Default(ref surface_texture) => {
+                        device.generate_surface_mipmaps(context, surface_texture)
+                    }
+                    _ => Err(Error::IncompatibleSurfaceTexture),
+                }
+            }
+            (&Device::Alternate(ref device), &mut Context::Alternate(ref mut context)) => {
+                match *surface_texture {
+                    SurfaceTexture::Alternate(ref surface_texture) => {
+                        device.generate_surface_mipmaps(context, surface_texture)
+                    }
+                    _ => Err(Error::IncompatibleSurfaceTexture),
+                }
+            }
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
     /// Returns various information about the surface, including the framebuffer object needed to
     /// render to this surface.
     ///
@@ -328,6 +785,103 @@ where
         }
     }
 
+    /// Returns the GPU memory, in bytes, held by the given surface.
+    ///
+    /// Depending on the platform, this may be an estimate.
+    pub fn surface_memory_usage(&self, surface: &Surface<Def, Alt>) -> usize {
+        match (self, surface) {
+            (&Device::Default(ref device), Surface::Default(ref surface)) => {
+                device.surface_memory_usage(surface)
+            }
+            (&Device::Alternate(ref device), Surface::Alternate(ref surface)) => {
+                device.surface_memory_usage(surface)
+            }
+            _ => panic!("Incompatible context!"),
+        }
+    }
+
+    /// Returns a counter that goes up whenever the given surface is modified, for detecting
+    /// writes by other processes.
+    pub fn surface_seed(&self, surface: &Surface<Def, Alt>) -> Result<u32, Error> {
+        match (self, surface) {
+            (&Device::Default(ref device), Surface::Default(ref surface)) => {
+                device.surface_seed(surface)
+            }
+            (&Device::Alternate(ref device), Surface::Alternate(ref surface)) => {
+                device.surface_seed(surface)
+            }
+            _ => Err(Error::IncompatibleSurface),
+        }
+    }
+
+    /// Returns the stride, row alignment, and total size of the memory that a generic surface of
+    /// the given size, format, and access would be allocated with, without creating one.
+    pub fn surface_layout_requirements(
+        &self,
+        size: &Size2D<i32>,
+        format: SurfaceFormat,
+        access: SurfaceAccess,
+    ) -> Result<SurfaceLayoutRequirements, Error> {
+        match *self {
+            Device::Default(ref device) => device.surface_layout_requirements(size, format, access),
+            Device::Alternate(ref device) => {
+                device.surface_layout_requirements(size, format, access)
+            }
+        }
+    }
+
+    /// Returns timing information about the most recently displayed frame of a widget surface.
+    ///
+    /// This returns `None` if no frame has been displayed yet, if the surface is not a widget
+    /// surface, or if the platform doesn't report presentation timing.
+    pub fn surface_presentation_statistics(
+        &self,
+        surface: &Surface<Def, Alt>,
+    ) -> Option<PresentationStatistics> {
+        match (self, surface) {
+            (&Device::Default(ref device), Surface::Default(ref surface)) => {
+                device.surface_presentation_statistics(surface)
+            }
+            (&Device::Alternate(ref device), Surface::Alternate(ref surface)) => {
+                device.surface_presentation_statistics(surface)
+            }
+            _ => panic!("Incompatible context!"),
+        }
+    }
+
+    /// Returns whether a widget surface can currently be seen on screen.
+    ///
+    /// This returns `SurfaceVisibility::Unknown` for generic surfaces and on platforms that don't
+    /// report visibility.
+    pub fn surface_visibility(&self, surface: &Surface<Def, Alt>) -> SurfaceVisibility {
+        match (self, surface) {
+            (&Device::Default(ref device), Surface::Default(ref surface)) => {
+                device.surface_visibility(surface)
+            }
+            (&Device::Alternate(ref device), Surface::Alternate(ref surface)) => {
+                device.surface_visibility(surface)
+            }
+            _ => panic!("Incompatible context!"),
+        }
+    }
+
+    /// Returns the color space, bit depth, and HDR capability of the display that a widget surface
+    /// is shown on.
+    pub fn widget_output_info(
+        &self,
+        surface: &Surface<Def, Alt>,
+    ) -> Result<WidgetOutputInfo, Error> {
+        match (self, surface) {
+            (&Device::Default(ref device), Surface::Default(ref surface)) => {
+                device.widget_output_info(surface)
+            }
+            (&Device::Alternate(ref device), Surface::Alternate(ref surface)) => {
+                device.widget_output_info(surface)
+            }
+            _ => panic!("Incompatible context!"),
+        }
+    }
+
     /// Returns the OpenGL texture object containing the contents of this surface.
     ///
     /// It is only legal to read from, not write to, this texture object.
@@ -342,4 +896,28 @@ where
             _ => panic!("Incompatible context!"),
         }
     }
+
+    /// Attaches a debug name to a surface, so that it shows up under that name in GPU captures.
+    ///
+    /// The context that was current before is made current again afterward.
+    pub fn set_surface_label(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &Surface<Def, Alt>,
+        label: &str,
+    ) -> Result<(), Error> {
+        match (self, context) {
+            (&Device::Default(ref device), &Context::Default(ref context)) => match *surface {
+                Surface::Default(ref surface) => device.set_surface_label(context, surface, label),
+                _ => Err(Error::IncompatibleSurface),
+            },
+            (&Device::Alternate(ref device), &Context::Alternate(ref context)) => match *surface {
+                Surface::Alternate(ref surface) => {
+                    device.set_surface_label(context, surface, label)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
 }
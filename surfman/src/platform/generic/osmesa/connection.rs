@@ -0,0 +1,231 @@
+// surfman/surfman/src/platform/generic/osmesa/connection.rs
+//
+//! A no-op connection for the OSMesa backend, which needs no display server.
+
+use super::device::{Adapter, Device, NativeDevice};
+use super::ffi::OSMESA_FUNCTIONS;
+use super::surface::NativeWidget;
+use crate::context::ContextIDAllocator;
+use crate::GLApi;
+use crate::{DisplayInfo, Error, IpcDescriptor, NativeDisplayHandle, NativeWidgetHandle};
+
+use euclid::default::Size2D;
+
+use std::os::raw::c_void;
+
+#[cfg(feature = "sm-winit")]
+use winit::window::Window;
+
+/// A no-op connection.
+///
+/// OSMesa renders into CPU memory, so there is nothing to connect to.
+#[derive(Clone)]
+pub struct Connection {
+    pub(crate) context_ids: ContextIDAllocator,
+}
+
+/// A no-op native connection.
+#[derive(Clone)]
+pub struct NativeConnection;
+
+impl Connection {
+    /// Loads OSMesa.
+    ///
+    /// This fails with `Error::ConnectionFailed` if `libOSMesa` can't be loaded.
+    #[inline]
+    pub fn new() -> Result<Connection, Error> {
+        match *OSMESA_FUNCTIONS {
+            Some(_) => Ok(Connection {
+                context_ids: ContextIDAllocator::new()?,
+            }),
+            None => Err(Error::ConnectionFailed),
+        }
+    }
+
+    /// Returns `UnsupportedOnThisPlatform`, because OSMesa renders on the CPU and needs
+    /// no handle to a display server or GPU.
+    ///
+    /// # Safety
+    ///
+    /// The descriptor is never used, so there are no requirements on it.
+    #[inline]
+    pub unsafe fn from_ipc_descriptor(_: IpcDescriptor) -> Result<Connection, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// An alias for `Connection::new()`, present for consistency with other backends.
+    ///
+    /// # Safety
+    ///
+    /// The native connection carries no handles, so there are no requirements on it.
+    #[inline]
+    pub unsafe fn from_native_connection(_: NativeConnection) -> Result<Connection, Error> {
+        Connection::new()
+    }
+
+    /// Returns the underlying native connection.
+    #[inline]
+    pub fn native_connection(&self) -> NativeConnection {
+        NativeConnection
+    }
+
+    /// Returns the raw display connection underneath this connection.
+    ///
+    /// OSMesa has no display connection, so this returns `NativeDisplayHandle::None`.
+    #[inline]
+    pub fn native_display(&self) -> NativeDisplayHandle {
+        NativeDisplayHandle::None
+    }
+
+    /// Returns the OpenGL API flavor that this connection supports (OpenGL or OpenGL ES).
+    #[inline]
+    pub fn gl_api(&self) -> GLApi {
+        GLApi::GL
+    }
+
+    /// Returns the displays connected to the system.
+    ///
+    /// OSMesa renders offscreen only, so this always returns an empty list.
+    #[inline]
+    pub fn displays(&self) -> Result<Vec<DisplayInfo>, Error> {
+        Ok(vec![])
+    }
+
+    /// Returns the "best" adapter on this system.
+    ///
+    /// This is an alias for `Connection::create_software_adapter()`.
+    #[inline]
+    pub fn create_adapter(&self) -> Result<Adapter, Error> {
+        self.create_software_adapter()
+    }
+
+    /// Returns the "best" adapter on this system, preferring high-performance hardware adapters.
+    ///
+    /// On the OSMesa backend, this returns a software adapter.
+    #[inline]
+    pub fn create_hardware_adapter(&self) -> Result<Adapter, Error> {
+        self.create_software_adapter()
+    }
+
+    /// Returns the "best" adapter on this system, preferring low-power hardware adapters.
+    ///
+    /// On the OSMesa backend, this returns a software adapter.
+    #[inline]
+    pub fn create_low_power_adapter(&self) -> Result<Adapter, Error> {
+        self.create_software_adapter()
+    }
+
+    /// Returns the "best" adapter on this system, preferring software adapters.
+    #[inline]
+    pub fn create_software_adapter(&self) -> Result<Adapter, Error> {
+        Ok(Adapter)
+    }
+
+    /// Returns the adapter for the GPU that drives the given display.
+    ///
+    /// OSMesa always renders on the CPU, so this is the same as the default adapter.
+    #[inline]
+    pub fn create_adapter_for_display(&self, _: &DisplayInfo) -> Result<Adapter, Error> {
+        Ok(Adapter)
+    }
+
+    /// Opens the device corresponding to the given adapter.
+    ///
+    /// Device handles can be cloned and sent to other threads.
+    #[inline]
+    pub(crate) fn create_device_impl(&self, adapter: &Adapter) -> Result<Device, Error> {
+        Device::new(self, adapter)
+    }
+
+    /// An alias for `connection.create_device()` with the default adapter.
+    #[inline]
+    pub(crate) unsafe fn create_device_from_native_device_impl(
+        &self,
+        native_device: NativeDevice,
+    ) -> Result<Device, Error> {
+        Device::new(self, &native_device.adapter)
+    }
+
+    /// Opens the display connection corresponding to the given `winit` window.
+    ///
+    /// OSMesa can't display anything, so this always fails.
+    #[inline]
+    #[cfg(feature = "sm-winit")]
+    pub fn from_winit_window(_: &Window) -> Result<Connection, Error> {
+        Err(Error::IncompatibleNativeWidget)
+    }
+
+    /// Creates a native widget type from the given `winit` window.
+    ///
+    /// OSMesa can't display anything, so this always fails.
+    #[inline]
+    #[cfg(feature = "sm-winit")]
+    pub fn create_native_widget_from_winit_window(
+        &self,
+        _: &Window,
+    ) -> Result<NativeWidget, Error> {
+        Err(Error::IncompatibleNativeWidget)
+    }
+
+    /// Create a native widget from a raw pointer
+    ///
+    /// # Safety
+    ///
+    /// On Redox, the pointer must be to an `orbclient::Window`, which must outlive the native
+    /// widget and any surfaces created from it. Elsewhere, the pointer is never used.
+    #[inline]
+    pub unsafe fn create_native_widget_from_ptr(
+        &self,
+        _raw: *mut c_void,
+        _size: Size2D<i32>,
+    ) -> NativeWidget {
+        #[cfg(redox)]
+        return NativeWidget {
+            window: _raw as *mut orbclient::Window,
+        };
+        #[cfg(not(redox))]
+        NativeWidget
+    }
+
+    /// Creates a native widget from an Orbital window.
+    ///
+    /// # Safety
+    ///
+    /// The window must outlive every widget surface created for it, and must not be used while
+    /// they are presented.
+    #[cfg(redox)]
+    #[inline]
+    pub unsafe fn create_native_widget_from_orbclient_window(
+        &self,
+        window: &mut orbclient::Window,
+    ) -> NativeWidget {
+        NativeWidget { window }
+    }
+
+    /// Creates a native widget from a handle to a native window or view.
+    ///
+    /// OSMesa can only present into Orbital windows, which have no handle variant, so this always
+    /// returns an `IncompatibleNativeWidget` error.
+    ///
+    /// # Safety
+    ///
+    /// The handle is never used, so there are no requirements on it.
+    pub unsafe fn create_native_widget_from_handle(
+        &self,
+        _: NativeWidgetHandle,
+    ) -> Result<NativeWidget, Error> {
+        Err(Error::IncompatibleNativeWidget)
+    }
+
+    /// Create a native widget type from the given `raw_window_handle::RawWindowHandle`.
+    ///
+    /// OSMesa can't display anything, so this always fails.
+    #[cfg(feature = "sm-raw-window-handle")]
+    #[inline]
+    pub fn create_native_widget_from_rwh(
+        &self,
+        _: raw_window_handle::RawWindowHandle,
+    ) -> Result<NativeWidget, Error> {
+        Err(Error::IncompatibleNativeWidget)
+    }
+}
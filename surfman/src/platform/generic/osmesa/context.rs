@@ -0,0 +1,698 @@
+// surfman/surfman/src/platform/generic/osmesa/context.rs
+//
+//! OpenGL rendering contexts on OSMesa.
+
+use super::connection::Connection;
+use super::device::Device;
+use super::ffi::{osmesa, OSMesaContext, OSMESA_ACCUM_BITS, OSMESA_COMPAT_PROFILE};
+use super::ffi::{OSMESA_CONTEXT_MAJOR_VERSION, OSMESA_CONTEXT_MINOR_VERSION};
+use super::ffi::{OSMESA_CORE_PROFILE, OSMESA_DEPTH_BITS, OSMESA_FORMAT, OSMESA_PROFILE};
+use super::ffi::{OSMESA_RGBA, OSMESA_STENCIL_BITS};
+use super::surface::Surface;
+use crate::context;
+use crate::gl;
+use crate::gl::types::GLuint;
+use crate::gl_utils;
+#[cfg(feature = "sm-opencl")]
+use crate::opencl::GLSharingProperties;
+use crate::overrides;
+use crate::surface::{ExternalFramebuffer, Framebuffer};
+use crate::{ContextAttributeFlags, ContextAttributes, ContextDescriptorID, ContextID, Error};
+use crate::{GLApi, GLVersion, Gl};
+use crate::{SurfaceInfo, WindowingApiError};
+
+use euclid::default::Size2D;
+use std::ffi::CString;
+use std::mem;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use std::thread;
+
+thread_local! {
+    #[doc(hidden)]
+    pub static GL_FUNCTIONS: Gl = Gl::load_with(get_proc_address);
+}
+
+/// Represents an OpenGL rendering context.
+///
+/// A context allows you to issue rendering commands to a surface. When initially created, a
+/// context has no attached surface, so rendering commands will fail or be ignored. Typically, you
+/// attach a surface to the context before rendering.
+///
+/// Contexts take ownership of the surfaces attached to them. In order to mutate a surface in any
+/// way other than rendering to it (e.g. presenting it to a window, which causes a buffer swap), it
+/// must first be detached from its context. Each surface is associated with a single context upon
+/// creation and may not be rendered to from any other context. However, you can wrap a surface in
+/// a surface texture, which allows the surface to be read from another context.
+///
+/// OpenGL objects may not be shared across contexts directly, but surface textures effectively
+/// allow for sharing of texture data. Contexts are local to a single thread and device.
+///
+/// A context must be explicitly destroyed with `destroy_context()`, or a panic will occur.
+pub struct Context {
+    pub(crate) osmesa_context: OSMesaContext,
+    pub(crate) id: ContextID,
+    descriptor: ContextDescriptor,
+    // OSMesa has no notion of a context without a color buffer, so the context is made current on
+    // this single pixel when no surface is bound.
+    dummy_buffer: Vec<u8>,
+    pub(crate) framebuffer: Framebuffer<Surface, ExternalColorBuffer>,
+    status: ContextStatus,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ContextStatus {
+    Owned,
+    Referenced,
+    Destroyed,
+}
+
+// The color buffer that a wrapped native context was rendering to.
+#[derive(Clone, Copy)]
+pub(crate) struct ExternalColorBuffer {
+    buffer: *mut c_void,
+    size: Size2D<i32>,
+}
+
+/// Wrapper for a native `OSMesaContext`.
+#[derive(Clone, Copy)]
+pub struct NativeContext {
+    /// The OSMesa context.
+    pub osmesa_context: *mut c_void,
+}
+
+/// Information needed to create a context. Some APIs call this a "config" or a "pixel format".
+///
+/// OSMesa has no configs, so this simply records the requested attributes.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ContextDescriptor {
+    pub(crate) attributes: ContextAttributes,
+}
+
+#[must_use]
+pub(crate) struct CurrentContextGuard {
+    old_osmesa_context: OSMesaContext,
+    old_color_buffer: Option<ExternalColorBuffer>,
+}
+
+impl Drop for Context {
+    #[inline]
+    fn drop(&mut self) {
+        if self.status != ContextStatus::Destroyed && !thread::panicking() {
+            panic!("Contexts must be destroyed explicitly with `destroy_context`!")
+        }
+    }
+}
+
+impl Drop for CurrentContextGuard {
+    fn drop(&mut self) {
+        unsafe {
+            match self.old_color_buffer {
+                Some(color_buffer) => {
+                    make_current(
+                        self.old_osmesa_context,
+                        color_buffer.buffer,
+                        color_buffer.size,
+                    );
+                }
+                None => {
+                    make_current(ptr::null_mut(), ptr::null_mut(), Size2D::zero());
+                }
+            }
+        }
+    }
+}
+
+impl Device {
+    /// Creates a context descriptor with the given attributes.
+    ///
+    /// Context descriptors are local to this device.
+    pub(crate) fn create_context_descriptor_impl(
+        &self,
+        attributes: &ContextAttributes,
+    ) -> Result<ContextDescriptor, Error> {
+        let attributes = overrides::context_attributes(attributes);
+        // OSMesa renders into a single 8-bit buffer in memory.
+        let unsupported = ContextAttributeFlags::STEREO | ContextAttributeFlags::FLOAT_COLOR;
+        if attributes.flags.intersects(unsupported) {
+            return Err(Error::UnsupportedOnThisPlatform);
+        }
+        Ok(ContextDescriptor {
+            attributes: ContextAttributes {
+                samples: 1,
+                ..attributes
+            },
+        })
+    }
+
+    /// Returns a small ID for the given context descriptor, which is the same for every equal
+    /// descriptor.
+    ///
+    /// The IDs are shared with clones of this device.
+    #[inline]
+    pub fn intern_context_descriptor(
+        &self,
+        context_descriptor: &ContextDescriptor,
+    ) -> ContextDescriptorID {
+        self.context_descriptors.intern(context_descriptor)
+    }
+
+    /// Creates a new OpenGL context.
+    ///
+    /// The context initially has no surface attached. Until a surface is bound to it, rendering
+    /// commands will fail or have no effect.
+    ///
+    /// OpenGL versions before 3.2 always get a compatibility profile context, since they predate
+    /// the core profile.
+    pub(crate) fn create_context_impl(
+        &mut self,
+        descriptor: &ContextDescriptor,
+        share_with: Option<&Context>,
+    ) -> Result<Context, Error> {
+        let attributes = &descriptor.attributes;
+        let flags = attributes.flags;
+        let depth_bits = if flags.contains(ContextAttributeFlags::DEPTH) {
+            24
+        } else {
+            0
+        };
+        let stencil_bits = if flags.contains(ContextAttributeFlags::STENCIL) {
+            8
+        } else {
+            0
+        };
+        let create_context = |version: GLVersion, share_context: OSMesaContext| {
+            let profile = if flags.contains(ContextAttributeFlags::COMPATIBILITY_PROFILE)
+                || version.major < 3
+                || version.major == 3 && version.minor < 2
+            {
+                OSMESA_COMPAT_PROFILE
+            } else {
+                OSMESA_CORE_PROFILE
+            };
+
+            let osmesa_context_attributes = [
+                OSMESA_FORMAT,
+                OSMESA_RGBA as c_int,
+                OSMESA_DEPTH_BITS,
+                depth_bits,
+                OSMESA_STENCIL_BITS,
+                stencil_bits,
+                OSMESA_ACCUM_BITS,
+                0,
+                OSMESA_PROFILE,
+                profile,
+                OSMESA_CONTEXT_MAJOR_VERSION,
+                version.major as c_int,
+                OSMESA_CONTEXT_MINOR_VERSION,
+                version.minor as c_int,
+                0,
+            ];
+            unsafe {
+                (osmesa().CreateContextAttribs)(osmesa_context_attributes.as_ptr(), share_context)
+            }
+        };
+
+        let share_context = share_with.map_or(ptr::null_mut(), |context| context.osmesa_context);
+
+        // Try the highest version first, and remember which one worked.
+        let gl_versions =
+            context::gl_versions(GLApi::GL, attributes.version, attributes.highest_version());
+        let (osmesa_context, version) = match gl_versions
+            .into_iter()
+            .map(|version| (create_context(version, share_context), version))
+            .find(|&(osmesa_context, _)| !osmesa_context.is_null())
+        {
+            Some(created) => created,
+            None => {
+                // OSMesa doesn't say why context creation failed, but the usual reason is that
+                // the requested version isn't supported.
+                return Err(Error::ContextCreationFailed(WindowingApiError::BadMatch));
+            }
+        };
+
+        let context = Context {
+            osmesa_context,
+            id: self.context_ids.next(),
+            descriptor: ContextDescriptor {
+                attributes: ContextAttributes {
+                    version,
+                    max_version: None,
+                    ..attributes.clone()
+                },
+            },
+            dummy_buffer: vec![0; 4],
+            framebuffer: Framebuffer::None,
+            status: ContextStatus::Owned,
+        };
+        Ok(context)
+    }
+
+    /// Wraps an `OSMesaContext` in a native context and returns it.
+    ///
+    /// The context keeps rendering to the color buffer that it's currently bound to. It is not
+    /// retained, as there is no way to do this in the OSMesa API. Therefore, it is the caller's
+    /// responsibility to ensure that the returned `Context` object and that color buffer remain
+    /// alive as long as the `OSMesaContext` is.
+    pub(crate) unsafe fn create_context_from_native_context_impl(
+        &self,
+        native_context: NativeContext,
+    ) -> Result<Context, Error> {
+        let color_buffer = match color_buffer(native_context.osmesa_context) {
+            Some(color_buffer) => color_buffer,
+            None => return Err(Error::IncompatibleNativeContext),
+        };
+
+        // Recover the attributes from the context itself.
+        let guard = CurrentContextGuard::new();
+        make_current(
+            native_context.osmesa_context,
+            color_buffer.buffer,
+            color_buffer.size,
+        );
+        let attributes = GL_FUNCTIONS.with(|gl| {
+            let mut flags = ContextAttributeFlags::ALPHA;
+            flags.set(
+                ContextAttributeFlags::COMPATIBILITY_PROFILE,
+                context::current_context_uses_compatibility_profile(gl),
+            );
+            ContextAttributes {
+                version: GLVersion::current(gl),
+                max_version: None,
+                flags,
+                required_extensions: vec![],
+                samples: 1,
+            }
+        });
+        drop(guard);
+
+        let context = Context {
+            osmesa_context: native_context.osmesa_context,
+            id: self.context_ids.next(),
+            descriptor: ContextDescriptor { attributes },
+            dummy_buffer: vec![0; 4],
+            framebuffer: Framebuffer::External(color_buffer),
+            status: ContextStatus::Referenced,
+        };
+        Ok(context)
+    }
+
+    /// Wraps the OSMesa context that is current on this thread in a context on a new device.
+    ///
+    /// If no context is current, this returns a `NoCurrentContext` error.
+    ///
+    /// # Safety
+    ///
+    /// As with `create_context_from_native_context()`, the caller must keep the `OSMesaContext`
+    /// and its color buffer alive as long as the returned context is.
+    pub unsafe fn from_current_context() -> Result<(Device, Context), Error> {
+        let native_context = NativeContext::current()?;
+        let connection = Connection::new()?;
+        let device = connection.create_device(&connection.create_adapter()?)?;
+        let context = device.create_context_from_native_context(native_context)?;
+        Ok((device, context))
+    }
+
+    /// Destroys a context.
+    ///
+    /// The context must have been created on this device.
+    pub(crate) fn destroy_context_impl(&self, context: &mut Context) -> Result<(), Error> {
+        if context.status == ContextStatus::Destroyed {
+            return Ok(());
+        }
+
+        if let Ok(Some(mut surface)) = self.unbind_surface_from_context_impl(context) {
+            self.destroy_surface(context, &mut surface)?;
+        }
+
+        unsafe {
+            if self.context_is_current(context) {
+                make_current(ptr::null_mut(), ptr::null_mut(), Size2D::zero());
+            }
+            if context.status == ContextStatus::Owned {
+                (osmesa().DestroyContext)(context.osmesa_context);
+            }
+        }
+
+        context.osmesa_context = ptr::null_mut();
+        context.status = ContextStatus::Destroyed;
+        Ok(())
+    }
+
+    /// Given a context, returns its underlying OSMesa context.
+    #[inline]
+    pub fn native_context(&self, context: &Context) -> NativeContext {
+        NativeContext {
+            osmesa_context: context.osmesa_context,
+        }
+    }
+
+    /// Returns the descriptor that this context was created with.
+    #[inline]
+    pub fn context_descriptor(&self, context: &Context) -> ContextDescriptor {
+        context.descriptor.clone()
+    }
+
+    /// Makes the context the current OpenGL context for this thread.
+    ///
+    /// After calling this function, it is valid to use OpenGL rendering commands.
+    pub(crate) fn make_context_current_impl(&self, context: &Context) -> Result<(), Error> {
+        let (buffer, size) = match context.framebuffer {
+            Framebuffer::Surface(ref surface) => (surface.buffer(), surface.size),
+            Framebuffer::External(color_buffer) => (color_buffer.buffer, color_buffer.size),
+            Framebuffer::None | Framebuffer::ExternalFramebuffer(_) => (
+                context.dummy_buffer.as_ptr() as *mut c_void,
+                Size2D::new(1, 1),
+            ),
+        };
+        unsafe {
+            if make_current(context.osmesa_context, buffer, size) {
+                Ok(())
+            } else {
+                Err(Error::MakeCurrentFailed(WindowingApiError::Failed))
+            }
+        }
+    }
+
+    /// Makes the context the current OpenGL context for this thread, rendering to the given
+    /// surface instead of any surface bound to it.
+    ///
+    /// The surface must have been created with this context, or an `IncompatibleSurface` error is
+    /// returned.
+    pub(crate) fn make_context_current_with_surface_impl(
+        &self,
+        context: &Context,
+        surface: &Surface,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        unsafe {
+            if make_current(context.osmesa_context, surface.buffer(), surface.size) {
+                Ok(())
+            } else {
+                Err(Error::MakeCurrentFailed(WindowingApiError::Failed))
+            }
+        }
+    }
+
+    /// Removes the current OpenGL context from this thread.
+    ///
+    /// After calling this function, OpenGL rendering commands will fail until a new context is
+    /// made current.
+    pub fn make_no_context_current(&self) -> Result<(), Error> {
+        unsafe {
+            if make_current(ptr::null_mut(), ptr::null_mut(), Size2D::zero()) {
+                Ok(())
+            } else {
+                Err(Error::MakeCurrentFailed(WindowingApiError::Failed))
+            }
+        }
+    }
+
+    #[inline]
+    pub(crate) fn temporarily_make_context_current(
+        &self,
+        context: &Context,
+    ) -> Result<CurrentContextGuard, Error> {
+        let guard = CurrentContextGuard::new();
+        self.make_context_current_impl(context)?;
+        Ok(guard)
+    }
+
+    // Calls the given function with the OpenGL functions, which are loaded once per thread and
+    // shared by all contexts.
+    #[inline]
+    pub(crate) fn with_gl_functions<F, R>(&self, _: &Context, callback: F) -> R
+    where
+        F: FnOnce(&Gl) -> R,
+    {
+        GL_FUNCTIONS.with(|gl| callback(gl))
+    }
+
+    /// Returns the attributes that the context descriptor was created with.
+    #[inline]
+    pub fn context_descriptor_attributes(
+        &self,
+        context_descriptor: &ContextDescriptor,
+    ) -> ContextAttributes {
+        context_descriptor.attributes.clone()
+    }
+
+    /// Fetches the address of an OpenGL function associated with this context.
+    ///
+    /// OpenGL functions are local to a context. You should not use OpenGL functions on one context
+    /// with any other context.
+    ///
+    /// This method is typically used with a function like `gl::load_with()` from the `gl` crate to
+    /// load OpenGL function pointers.
+    #[inline]
+    pub fn get_proc_address(&self, _: &Context, symbol_name: &str) -> *const c_void {
+        get_proc_address(symbol_name)
+    }
+
+    /// Attaches a surface to a context for rendering.
+    ///
+    /// This function takes ownership of the surface. The surface must have been created with this
+    /// context, or an `IncompatibleSurface` error is returned.
+    ///
+    /// If this function is called with a surface already bound, a `SurfaceAlreadyBound` error is
+    /// returned. To avoid this error, first unbind the existing surface with
+    /// `unbind_surface_from_context`.
+    ///
+    /// If an error is returned, the surface is returned alongside it.
+    pub(crate) fn bind_surface_to_context_impl(
+        &self,
+        context: &mut Context,
+        surface: Surface,
+    ) -> Result<(), (Error, Surface)> {
+        if context.id != surface.context_id {
+            return Err((Error::IncompatibleSurface, surface));
+        }
+
+        match context.framebuffer {
+            Framebuffer::None => {}
+            Framebuffer::External(_) | Framebuffer::ExternalFramebuffer(_) => {
+                return Err((Error::ExternalRenderTarget, surface))
+            }
+            Framebuffer::Surface(_) => return Err((Error::SurfaceAlreadyBound, surface)),
+        }
+
+        let is_current = self.context_is_current(context);
+        context.framebuffer = Framebuffer::Surface(surface);
+
+        if is_current {
+            // We need to make ourselves current again, because the surface changed.
+            drop(self.make_context_current_impl(context));
+        }
+
+        Ok(())
+    }
+
+    /// Removes and returns any attached surface from this context.
+    ///
+    /// Any pending OpenGL commands targeting this surface will be automatically finished, so the
+    /// surface's pixels are safe to read from immediately when this function returns.
+    pub(crate) fn unbind_surface_from_context_impl(
+        &self,
+        context: &mut Context,
+    ) -> Result<Option<Surface>, Error> {
+        match context.framebuffer {
+            Framebuffer::None => return Ok(None),
+            Framebuffer::Surface(_) => {}
+            Framebuffer::External(_) => return Err(Error::ExternalRenderTarget),
+            Framebuffer::ExternalFramebuffer(_) => {
+                context.framebuffer = Framebuffer::None;
+                return Ok(None);
+            }
+        }
+
+        // Rendering may be deferred until the commands are finished, so flushing isn't enough
+        // before the pixels are read on the CPU.
+        let is_current = self.context_is_current(context);
+        if is_current {
+            GL_FUNCTIONS.with(|gl| unsafe { gl.Finish() });
+        }
+
+        let surface = match mem::replace(&mut context.framebuffer, Framebuffer::None) {
+            Framebuffer::Surface(surface) => surface,
+            Framebuffer::None | Framebuffer::External(_) | Framebuffer::ExternalFramebuffer(_) => {
+                unreachable!()
+            }
+        };
+
+        // If we're current, we stay current, but on the dummy buffer.
+        if is_current {
+            drop(self.make_context_current_impl(context));
+        }
+
+        Ok(Some(surface))
+    }
+
+    /// Makes a context render to a framebuffer object that the caller created and owns, in place
+    /// of a surface.
+    ///
+    /// The framebuffer object must stay alive until it's detached again with
+    /// `unbind_surface_from_context()`.
+    pub fn bind_external_framebuffer(
+        &self,
+        context: &mut Context,
+        framebuffer_object: GLuint,
+        size: Size2D<i32>,
+    ) -> Result<(), Error> {
+        match context.framebuffer {
+            Framebuffer::None => {}
+            Framebuffer::External(_) | Framebuffer::ExternalFramebuffer(_) => {
+                return Err(Error::ExternalRenderTarget)
+            }
+            Framebuffer::Surface(_) => return Err(Error::SurfaceAlreadyBound),
+        }
+
+        // The context stays current on its dummy buffer, as it does when nothing is bound.
+        context.framebuffer =
+            Framebuffer::ExternalFramebuffer(ExternalFramebuffer::new(framebuffer_object, size));
+        Ok(())
+    }
+
+    /// Makes the rendering submitted so far to a surface visible to everything else that reads
+    /// it.
+    ///
+    /// OSMesa may defer rendering until the commands are finished, so this waits for them to
+    /// finish.
+    ///
+    /// The surface must have been created with the given context, or an `IncompatibleSurface`
+    /// error is returned.
+    pub(crate) fn flush_context_impl(
+        &self,
+        context: &Context,
+        surface: &Surface,
+    ) -> Result<(), Error> {
+        if surface.context_id != context.id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        let _guard = self.temporarily_make_context_current(context)?;
+        GL_FUNCTIONS.with(gl_utils::finish_with_fence);
+        Ok(())
+    }
+
+    #[inline]
+    fn context_is_current(&self, context: &Context) -> bool {
+        unsafe { (osmesa().GetCurrentContext)() == context.osmesa_context }
+    }
+
+    /// Returns a unique ID representing a context.
+    ///
+    /// This ID is unique to all currently-allocated contexts. If you destroy a context and create
+    /// a new one, the new context might have the same ID as the destroyed one.
+    #[inline]
+    pub fn context_id(&self, context: &Context) -> ContextID {
+        context.id
+    }
+
+    /// Returns various information about the surface attached to a context.
+    ///
+    /// This includes, most notably, the OpenGL framebuffer object needed to render to the surface.
+    pub fn context_surface_info(&self, context: &Context) -> Result<Option<SurfaceInfo>, Error> {
+        match context.framebuffer {
+            Framebuffer::None => Ok(None),
+            Framebuffer::External(_) => Err(Error::ExternalRenderTarget),
+            Framebuffer::ExternalFramebuffer(ref framebuffer) => {
+                Ok(Some(framebuffer.info(context.id)))
+            }
+            Framebuffer::Surface(ref surface) => Ok(Some(SurfaceInfo {
+                bound: true,
+                ..self.surface_info(surface)
+            })),
+        }
+    }
+}
+
+impl NativeContext {
+    /// Returns the current OSMesa context.
+    ///
+    /// If there is no current OSMesa context, this returns a `NoCurrentContext` error.
+    pub fn current() -> Result<NativeContext, Error> {
+        unsafe {
+            let osmesa_context = (osmesa().GetCurrentContext)();
+            if osmesa_context.is_null() {
+                Err(Error::NoCurrentContext)
+            } else {
+                Ok(NativeContext { osmesa_context })
+            }
+        }
+    }
+}
+
+impl CurrentContextGuard {
+    fn new() -> CurrentContextGuard {
+        unsafe {
+            let old_osmesa_context = (osmesa().GetCurrentContext)();
+            CurrentContextGuard {
+                old_osmesa_context,
+                old_color_buffer: color_buffer(old_osmesa_context),
+            }
+        }
+    }
+}
+
+// Binds the given context to an RGBA color buffer of the given size, or unbinds the current
+// context if both are null.
+unsafe fn make_current(
+    osmesa_context: OSMesaContext,
+    buffer: *mut c_void,
+    size: Size2D<i32>,
+) -> bool {
+    let ok = (osmesa().MakeCurrent)(
+        osmesa_context,
+        buffer,
+        gl::UNSIGNED_BYTE,
+        size.width,
+        size.height,
+    );
+    ok != gl::FALSE
+}
+
+// Returns the color buffer that the given context is bound to, if any.
+unsafe fn color_buffer(osmesa_context: OSMesaContext) -> Option<ExternalColorBuffer> {
+    if osmesa_context.is_null() {
+        return None;
+    }
+    let (mut width, mut height, mut format) = (0, 0, 0);
+    let mut buffer = ptr::null_mut();
+    let ok = (osmesa().GetColorBuffer)(
+        osmesa_context,
+        &mut width,
+        &mut height,
+        &mut format,
+        &mut buffer,
+    );
+    if ok == gl::FALSE || buffer.is_null() {
+        return None;
+    }
+    Some(ExternalColorBuffer {
+        buffer,
+        size: Size2D::new(width, height),
+    })
+}
+
+fn get_proc_address(symbol_name: &str) -> *const c_void {
+    unsafe {
+        let symbol_name: CString = CString::new(symbol_name).unwrap();
+        let symbol_ptr = symbol_name.as_ptr() as *const c_char;
+        match (osmesa().GetProcAddress)(symbol_ptr) {
+            Some(function) => function as *const c_void,
+            None => ptr::null(),
+        }
+    }
+}
+
+#[cfg(feature = "sm-opencl")]
+impl Device {
+    /// Returns `Error::UnsupportedOnThisPlatform`, as OpenCL can't share objects with OSMesa
+    /// contexts.
+    #[inline]
+    pub fn gl_sharing_properties(&self, _: &Context) -> Result<GLSharingProperties, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+}
@@ -0,0 +1,101 @@
+// surfman/surfman/src/platform/generic/osmesa/ffi.rs
+//
+//! OSMesa entry points, loaded at runtime.
+//!
+//! `libOSMesa` is opened with `dlopen()` rather than linked, so that a system without it simply
+//! can't create connections on this backend instead of failing to start.
+
+use crate::gl::types::{GLboolean, GLenum, GLint, GLsizei};
+
+use std::mem;
+use std::os::raw::{c_char, c_int, c_void};
+
+pub(crate) type OSMesaContext = *mut c_void;
+
+pub(crate) const OSMESA_RGBA: GLenum = 0x1908;
+pub(crate) const OSMESA_FORMAT: c_int = 0x22;
+pub(crate) const OSMESA_DEPTH_BITS: c_int = 0x30;
+pub(crate) const OSMESA_STENCIL_BITS: c_int = 0x31;
+pub(crate) const OSMESA_ACCUM_BITS: c_int = 0x32;
+pub(crate) const OSMESA_PROFILE: c_int = 0x33;
+pub(crate) const OSMESA_CORE_PROFILE: c_int = 0x34;
+pub(crate) const OSMESA_COMPAT_PROFILE: c_int = 0x35;
+pub(crate) const OSMESA_CONTEXT_MAJOR_VERSION: c_int = 0x36;
+pub(crate) const OSMESA_CONTEXT_MINOR_VERSION: c_int = 0x37;
+
+type CreateContextAttribsFn =
+    unsafe extern "C" fn(attrib_list: *const c_int, sharelist: OSMesaContext) -> OSMesaContext;
+type DestroyContextFn = unsafe extern "C" fn(ctx: OSMesaContext);
+type MakeCurrentFn = unsafe extern "C" fn(
+    ctx: OSMesaContext,
+    buffer: *mut c_void,
+    type_: GLenum,
+    width: GLsizei,
+    height: GLsizei,
+) -> GLboolean;
+type GetCurrentContextFn = unsafe extern "C" fn() -> OSMesaContext;
+type GetColorBufferFn = unsafe extern "C" fn(
+    ctx: OSMesaContext,
+    width: *mut GLint,
+    height: *mut GLint,
+    format: *mut GLint,
+    buffer: *mut *mut c_void,
+) -> GLboolean;
+type GetProcAddressFn =
+    unsafe extern "C" fn(func_name: *const c_char) -> Option<unsafe extern "C" fn()>;
+
+#[allow(non_snake_case)]
+pub(crate) struct OSMesaFunctions {
+    pub(crate) CreateContextAttribs: CreateContextAttribsFn,
+    pub(crate) DestroyContext: DestroyContextFn,
+    pub(crate) MakeCurrent: MakeCurrentFn,
+    pub(crate) GetCurrentContext: GetCurrentContextFn,
+    pub(crate) GetColorBuffer: GetColorBufferFn,
+    pub(crate) GetProcAddress: GetProcAddressFn,
+}
+
+lazy_static! {
+    pub(crate) static ref OSMESA_FUNCTIONS: Option<OSMesaFunctions> =
+        unsafe { load_osmesa_functions() };
+}
+
+macro_rules! load_symbol {
+    ($library:expr, $name:expr, $ty:ty) => {{
+        let symbol = libc::dlsym($library, concat!($name, "\0").as_ptr() as *const c_char);
+        if symbol.is_null() {
+            return None;
+        }
+        mem::transmute::<*mut c_void, $ty>(symbol)
+    }};
+}
+
+unsafe fn load_osmesa_functions() -> Option<OSMesaFunctions> {
+    let library_names: [&[u8]; 3] = [b"libOSMesa.so.8\0", b"libOSMesa.so.6\0", b"libOSMesa.so\0"];
+    let library = library_names
+        .iter()
+        .map(|name| libc::dlopen(name.as_ptr() as *const c_char, libc::RTLD_LAZY))
+        .find(|library| !library.is_null())?;
+
+    // `OSMesaCreateContextAttribs()` first appeared in Mesa 10.
+    Some(OSMesaFunctions {
+        CreateContextAttribs: load_symbol!(
+            library,
+            "OSMesaCreateContextAttribs",
+            CreateContextAttribsFn
+        ),
+        DestroyContext: load_symbol!(library, "OSMesaDestroyContext", DestroyContextFn),
+        MakeCurrent: load_symbol!(library, "OSMesaMakeCurrent", MakeCurrentFn),
+        GetCurrentContext: load_symbol!(library, "OSMesaGetCurrentContext", GetCurrentContextFn),
+        GetColorBuffer: load_symbol!(library, "OSMesaGetColorBuffer", GetColorBufferFn),
+        GetProcAddress: load_symbol!(library, "OSMesaGetProcAddress", GetProcAddressFn),
+    })
+}
+
+// Returns the loaded OSMesa entry points. Connections can't be created without them, so this
+// never fails once a connection exists.
+#[inline]
+pub(crate) fn osmesa() -> &'static OSMesaFunctions {
+    OSMESA_FUNCTIONS
+        .as_ref()
+        .expect("OSMesa functions should have been loaded when the connection was created!")
+}
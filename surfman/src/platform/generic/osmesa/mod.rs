@@ -0,0 +1,28 @@
+// surfman/surfman/src/platform/generic/osmesa/mod.rs
+//
+//! A pure-software backend using Mesa's off-screen rendering interface (OSMesa).
+//!
+//! Surfaces are plain buffers in CPU memory, so no display server, GPU, or EGL implementation is
+//! needed; only `libOSMesa` has to be installed. This makes the backend suitable for tests and for
+//! servers without graphics hardware. Widget surfaces are only supported on Redox, where
+//! presenting copies them into `orbclient` windows.
+//!
+//! Every adapter on this backend is a software adapter. To fall back to it when hardware
+//! rendering isn't available, combine it with another backend using `multi`, for example
+//! `multi::Connection<wayland::Connection, osmesa::Connection>`.
+//!
+//! This is the default backend on Redox, where Mesa's llvmpipe is the only OpenGL implementation.
+
+pub mod connection;
+pub mod context;
+pub mod device;
+pub mod surface;
+
+mod ffi;
+
+#[path = "../../../implementation/mod.rs"]
+mod implementation;
+
+#[cfg(test)]
+#[path = "../../../tests.rs"]
+mod tests;
@@ -0,0 +1,823 @@
+// surfman/surfman/src/platform/generic/osmesa/surface.rs
+//
+//! Surface management for OSMesa, using buffers in CPU memory.
+
+use super::context::{Context, GL_FUNCTIONS};
+use super::device::Device;
+use crate::gl;
+use crate::gl::types::{GLenum, GLint, GLuint};
+use crate::gl_utils;
+#[cfg(feature = "sm-opencl")]
+use crate::opencl::SharedEGLImage;
+use crate::surface::{estimate_memory_usage, SurfaceLayoutRequirements};
+use crate::SurfaceType;
+use crate::{
+    Colorspace, ContextID, CreateSurfacesResult, Error, PresentationStatistics, SurfaceAccess,
+    SurfaceAlphaMode, SurfaceFormat, SurfaceID, SurfaceInfo, SurfaceOrigin, SurfaceTransform,
+    SurfaceUsage, SurfaceVisibility, WidgetOutputInfo,
+};
+
+use euclid::default::{Rect, Size2D};
+#[cfg(redox)]
+use orbclient::{Color, Renderer};
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+use std::mem;
+use std::os::raw::c_void;
+use std::thread;
+
+const SURFACE_GL_TEXTURE_TARGET: GLenum = gl::TEXTURE_2D;
+const BYTES_PER_PIXEL: usize = 4;
+
+/// Represents a hardware buffer of pixels that can be rendered to via the CPU or GPU and either
+/// displayed in a native widget or bound to a texture for reading.
+///
+/// Surfaces come in two varieties: generic and widget surfaces. Generic surfaces can be bound to a
+/// texture but cannot be displayed in a widget (without using other APIs such as Core Animation,
+/// DirectComposition, or XPRESENT). Widget surfaces are the opposite: they can be displayed in a
+/// widget but not bound to a texture.
+///
+/// On this backend, surfaces are RGBA buffers in CPU memory, with the bottom row first. Widget
+/// surfaces are only supported on Redox, where presenting them copies their pixels into an
+/// Orbital window.
+///
+/// Surfaces are specific to a given context and cannot be rendered to from any context other than
+/// the one they were created with. However, they can be *read* from any context on any thread (as
+/// long as that context shares the same adapter and connection), by wrapping them in a
+/// `SurfaceTexture`.
+///
+/// Surfaces must be destroyed with the `destroy_surface()` method, or a panic will occur.
+pub struct Surface {
+    pub(crate) id: SurfaceID,
+    pub(crate) size: Size2D<i32>,
+    pub(crate) context_id: ContextID,
+    pixels: Vec<u8>,
+    access: SurfaceAccess,
+    format: SurfaceFormat,
+    origin: SurfaceOrigin,
+    alpha_mode: SurfaceAlphaMode,
+    colorspace: Colorspace,
+    // Like an IOSurface seed, goes up every time the CPU is done with the pixels.
+    seed: u32,
+    #[cfg(redox)]
+    widget: Option<NativeWidget>,
+    destroyed: bool,
+}
+
+/// Represents an OpenGL texture that wraps a surface.
+///
+/// On this backend, the texture holds a copy of the surface's pixels as they were when the
+/// surface texture was created. It is undefined behavior to write to such a texture (e.g. by
+/// binding it to a framebuffer and rendering to that framebuffer).
+///
+/// Surface textures are local to a context, but that context does not have to be the same context
+/// as that associated with the underlying surface. The texture must be destroyed with the
+/// `destroy_surface_texture()` method, or a panic will occur.
+pub struct SurfaceTexture {
+    pub(crate) surface: Surface,
+    pub(crate) texture_object: GLuint,
+    pub(crate) phantom: PhantomData<*const ()>,
+}
+
+/// A wrapper for an Orbital window, which widget surfaces are copied into when presented.
+///
+/// The window is borrowed, and must outlive every widget surface created for it.
+#[cfg(redox)]
+#[derive(Clone)]
+pub struct NativeWidget {
+    pub(crate) window: *mut orbclient::Window,
+}
+
+/// A placeholder wrapper for a native widget.
+///
+/// OSMesa can't display anything outside Redox, so widget surfaces can't be created.
+#[cfg(not(redox))]
+#[derive(Clone)]
+pub struct NativeWidget;
+
+// The window of a widget surface is only touched when the surface is presented.
+#[cfg(redox)]
+unsafe impl Send for Surface {}
+
+impl Debug for Surface {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "Surface({:x})", self.id().0)
+    }
+}
+
+impl Debug for SurfaceTexture {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "SurfaceTexture({:?})", self.surface)
+    }
+}
+
+/// The pixels of a surface given up with `Device::surface_into_destroy_token()`, which can be freed
+/// later without the surface's context.
+///
+/// Tokens can be sent to other threads, and are destroyed with `Device::destroy_token()`.
+pub struct SurfaceDestroyToken {
+    _pixels: Vec<u8>,
+}
+
+impl Drop for Surface {
+    fn drop(&mut self) {
+        if !self.destroyed && !thread::panicking() {
+            panic!("Should have destroyed the surface first with `destroy_surface()`!")
+        }
+    }
+}
+
+impl Surface {
+    fn new(
+        context_id: ContextID,
+        access: SurfaceAccess,
+        alpha_mode: SurfaceAlphaMode,
+        size: &Size2D<i32>,
+    ) -> Surface {
+        Surface {
+            id: SurfaceID::next(),
+            size: *size,
+            context_id,
+            pixels: vec![0; pixel_buffer_length(size)],
+            access,
+            format: SurfaceFormat::RGBA8,
+            origin: SurfaceOrigin::BottomLeft,
+            alpha_mode,
+            colorspace: Colorspace::SRGB,
+            seed: 0,
+            #[cfg(redox)]
+            widget: None,
+            destroyed: false,
+        }
+    }
+
+    #[cfg(redox)]
+    #[inline]
+    fn has_widget(&self) -> bool {
+        self.widget.is_some()
+    }
+
+    #[cfg(not(redox))]
+    #[inline]
+    fn has_widget(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    fn id(&self) -> SurfaceID {
+        self.id
+    }
+
+    // Returns the color buffer to make the owning context current on.
+    #[inline]
+    pub(crate) fn buffer(&self) -> *mut c_void {
+        self.pixels.as_ptr() as *mut c_void
+    }
+
+    /// Gives up this surface, returning a token that can be destroyed later with
+    /// `Device::destroy_token()`, on any thread and without the surface's context.
+    ///
+    /// On this backend, surfaces are only memory, so the token just holds the pixels.
+    pub(crate) fn into_destroy_token(mut self) -> SurfaceDestroyToken {
+        self.destroyed = true;
+        SurfaceDestroyToken {
+            _pixels: mem::take(&mut self.pixels),
+        }
+    }
+
+    // Copies the pixels into the Orbital window, which is top row first and stores each pixel as
+    // a 0xAARRGGBB word. Rendering was finished when the surface was unbound.
+    #[cfg(redox)]
+    fn present(&mut self) -> Result<(), Error> {
+        let native_widget = match self.widget {
+            Some(ref native_widget) => native_widget,
+            None => return Err(Error::NoWidgetAttached),
+        };
+        let window = unsafe { &mut *native_widget.window };
+
+        // The window may have been resized since the surface was; copy what overlaps.
+        let window_width = window.width() as usize;
+        let width = window_width.min(self.size.width as usize);
+        let height = (window.height() as usize).min(self.size.height as usize);
+        let stride = self.size.width.max(1) as usize * BYTES_PER_PIXEL;
+        let window_data = window.data_mut();
+        for y in 0..height {
+            let row = match self.origin {
+                SurfaceOrigin::BottomLeft => self.size.height as usize - 1 - y,
+                SurfaceOrigin::TopLeft => y,
+            };
+            let source = &self.pixels[row * stride..][..width * BYTES_PER_PIXEL];
+            let destination = &mut window_data[y * window_width..][..width];
+            for (pixel, rgba) in destination.iter_mut().zip(source.chunks(BYTES_PER_PIXEL)) {
+                *pixel = Color::rgba(rgba[0], rgba[1], rgba[2], rgba[3]);
+            }
+        }
+        window.sync();
+        Ok(())
+    }
+
+    #[cfg(not(redox))]
+    #[inline]
+    fn present(&mut self) -> Result<(), Error> {
+        Err(Error::NoWidgetAttached)
+    }
+}
+
+impl Device {
+    /// Creates either a generic or a widget surface, depending on the supplied surface type.
+    ///
+    /// Only the given context may ever render to the surface, but generic surfaces can be wrapped
+    /// up in a `SurfaceTexture` for reading by other contexts.
+    ///
+    /// Widget surfaces are only supported on Redox, and take the size of their window. Elsewhere,
+    /// requesting one returns an `UnsupportedOnThisPlatform` error.
+    pub(crate) fn create_surface_impl(
+        &mut self,
+        context: &Context,
+        access: SurfaceAccess,
+        _: SurfaceUsage,
+        alpha_mode: SurfaceAlphaMode,
+        surface_type: SurfaceType<NativeWidget>,
+    ) -> Result<Surface, Error> {
+        match surface_type {
+            SurfaceType::Generic { size } => {
+                Ok(Surface::new(context.id, access, alpha_mode, &size))
+            }
+            #[cfg(redox)]
+            SurfaceType::Widget { native_widget } => {
+                let window = unsafe { &*native_widget.window };
+                let size = Size2D::new(window.width() as i32, window.height() as i32);
+                let mut surface = Surface::new(context.id, access, alpha_mode, &size);
+                surface.widget = Some(native_widget);
+                Ok(surface)
+            }
+            #[cfg(not(redox))]
+            SurfaceType::Widget { .. } => Err(Error::UnsupportedOnThisPlatform),
+        }
+    }
+
+    /// Creates `count` generic surfaces of the given size.
+    ///
+    /// Surfaces on this backend live in CPU memory, so this never fails.
+    pub(crate) fn create_surfaces_impl(
+        &mut self,
+        context: &Context,
+        access: SurfaceAccess,
+        _: SurfaceUsage,
+        alpha_mode: SurfaceAlphaMode,
+        size: &Size2D<i32>,
+        count: usize,
+    ) -> CreateSurfacesResult<Surface> {
+        Ok((0..count)
+            .map(|_| Surface::new(context.id, access, alpha_mode, size))
+            .collect())
+    }
+
+    /// Creates a generic surface with the given number of layers.
+    ///
+    /// Layered surfaces aren't supported on OSMesa, so this always returns
+    /// `UnsupportedOnThisPlatform`.
+    #[inline]
+    pub fn create_layered_surface(
+        &mut self,
+        _: &Context,
+        _: SurfaceAccess,
+        _: SurfaceUsage,
+        _: &Size2D<i32>,
+        _: u32,
+    ) -> Result<Surface, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Chooses the layer of a layered surface that rendering to the surface draws into.
+    ///
+    /// Layered surfaces aren't supported on OSMesa, so this always returns
+    /// `UnsupportedOnThisPlatform`.
+    #[inline]
+    pub fn set_surface_layer(&self, _: &Context, _: &mut Surface, _: u32) -> Result<(), Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Returns the number of layers of a surface, which is always 1 on OSMesa.
+    #[inline]
+    pub fn surface_layers(&self, _: &Surface) -> u32 {
+        1
+    }
+
+    /// Creates a new generic surface with a copy of the contents of the given one, stored with
+    /// its color channels in the given order.
+    ///
+    /// Surfaces on this backend are RGBA in memory, and the pixels are swapped on the CPU.
+    pub(crate) fn convert_surface_format_impl(
+        &mut self,
+        context: &mut Context,
+        surface: &Surface,
+        format: SurfaceFormat,
+    ) -> Result<Surface, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        let mut new_surface = Surface::new(
+            context.id,
+            SurfaceAccess::GPUOnly,
+            surface.alpha_mode,
+            &surface.size,
+        );
+        new_surface.pixels.copy_from_slice(&surface.pixels);
+        if format != surface.format {
+            for pixel in new_surface.pixels.chunks_mut(BYTES_PER_PIXEL) {
+                pixel.swap(0, 2);
+            }
+        }
+        new_surface.format = format;
+        new_surface.origin = surface.origin;
+        new_surface.colorspace = surface.colorspace;
+        Ok(new_surface)
+    }
+
+    /// Wraps an existing OpenGL texture of the given context in a new generic surface.
+    ///
+    /// Generic surfaces on this backend live in CPU memory, which can't be shared with a texture, so this always returns an `UnsupportedOnThisPlatform` error.
+    #[inline]
+    pub(crate) fn create_surface_from_texture_impl(
+        &mut self,
+        _: &mut Context,
+        _: &Size2D<i32>,
+        _: GLuint,
+    ) -> Result<Surface, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Creates a surface texture from an existing generic surface for use with the given context.
+    ///
+    /// The surface texture is local to the supplied context and takes ownership of the surface.
+    /// Destroying the surface texture allows you to retrieve the surface again.
+    ///
+    /// *The supplied context does not have to be the same context that the surface is associated
+    /// with.* This allows you to render to a surface in one context and sample from that surface
+    /// in another context.
+    ///
+    /// The surface's pixels are uploaded into the texture when this is called, so later rendering
+    /// to the surface isn't visible through the texture until it's recreated.
+    pub fn create_surface_texture(
+        &self,
+        context: &mut Context,
+        surface: Surface,
+    ) -> Result<SurfaceTexture, (Error, Surface)> {
+        if surface.has_widget() {
+            return Err((Error::WidgetAttached, surface));
+        }
+
+        let _guard = match self.temporarily_make_context_current(context) {
+            Ok(guard) => guard,
+            Err(err) => return Err((err, surface)),
+        };
+
+        GL_FUNCTIONS.with(|gl| unsafe {
+            let mut bindings =
+                gl_utils::BindingsGuard::for_texture(gl, gl::TEXTURE_2D, gl::TEXTURE_BINDING_2D);
+            let mut old_unpack_alignment = 0;
+            gl.GetIntegerv(gl::UNPACK_ALIGNMENT, &mut old_unpack_alignment);
+
+            let mut texture_object = 0;
+            gl.GenTextures(1, &mut texture_object);
+            bindings.bind_texture(texture_object);
+
+            gl.PixelStorei(gl::UNPACK_ALIGNMENT, BYTES_PER_PIXEL as GLint);
+            gl.TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGBA as GLint,
+                surface.size.width.max(1),
+                surface.size.height.max(1),
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                surface.pixels.as_ptr() as *const c_void,
+            );
+
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
+            gl.TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_WRAP_S,
+                gl::CLAMP_TO_EDGE as GLint,
+            );
+            gl.TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_WRAP_T,
+                gl::CLAMP_TO_EDGE as GLint,
+            );
+
+            gl.PixelStorei(gl::UNPACK_ALIGNMENT, old_unpack_alignment);
+            drop(bindings);
+
+            Ok(SurfaceTexture {
+                surface,
+                texture_object,
+                phantom: PhantomData,
+            })
+        })
+    }
+
+    /// Destroys a surface.
+    ///
+    /// The supplied context must be the context the surface is associated with, or this returns
+    /// an `IncompatibleSurface` error.
+    ///
+    /// You must explicitly call this method to dispose of a surface. Otherwise, a panic occurs in
+    /// the `drop` method.
+    pub(crate) fn destroy_surface_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        surface.pixels = vec![];
+        surface.destroyed = true;
+        Ok(())
+    }
+
+    /// Destroys a surface texture and returns the underlying surface.
+    ///
+    /// The supplied context must be the same context the surface texture was created with, or an
+    /// `IncompatibleSurfaceTexture` error is returned.
+    ///
+    /// All surface textures must be explicitly destroyed with this function, or a panic will
+    /// occur.
+    pub fn destroy_surface_texture(
+        &self,
+        context: &mut Context,
+        mut surface_texture: SurfaceTexture,
+    ) -> Result<Surface, (Error, SurfaceTexture)> {
+        let _guard = match self.temporarily_make_context_current(context) {
+            Ok(guard) => guard,
+            Err(err) => return Err((err, surface_texture)),
+        };
+
+        GL_FUNCTIONS.with(|gl| unsafe {
+            gl.DeleteTextures(1, &surface_texture.texture_object);
+            surface_texture.texture_object = 0;
+        });
+
+        Ok(surface_texture.surface)
+    }
+
+    /// Destroys the native resources of a surface given up with `surface_into_destroy_token()`.
+    ///
+    /// Unlike `destroy_surface()`, this doesn't need the context that the surface belonged to.
+    #[inline]
+    pub fn destroy_token(&self, token: SurfaceDestroyToken) -> Result<(), Error> {
+        drop(token);
+        Ok(())
+    }
+
+    /// Displays the contents of a widget surface on screen.
+    ///
+    /// On Redox, the pixels are copied into the surface's Orbital window, which is then synced.
+    /// Elsewhere there are no widget surfaces, so this always returns a `NoWidgetAttached` error.
+    #[inline]
+    pub(crate) fn present_surface_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        self.present_surface_with_damage_impl(context, surface, &[])
+    }
+
+    /// Displays the contents of a widget surface on screen, hinting that only the given
+    /// rectangles have changed since the last present.
+    ///
+    /// The whole surface is copied into its window regardless of the damage. Outside Redox there
+    /// are no widget surfaces, so this always returns a `NoWidgetAttached` error.
+    pub(crate) fn present_surface_with_damage_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        _: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        surface.present()
+    }
+
+    /// Displays the `source` rectangle of a widget surface in the `destination` rectangle of its
+    /// widget, scaling it to fit.
+    ///
+    /// Presents are plain copies on this backend, so this returns `UnsupportedOnThisPlatform` for
+    /// widget surfaces, and `NoWidgetAttached` for the others.
+    pub(crate) fn present_surface_with_viewport_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        _: &Rect<i32>,
+        _: &Rect<i32>,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if !surface.has_widget() {
+            return Err(Error::NoWidgetAttached);
+        }
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Declares which parts of a widget surface will be redrawn before it is next presented.
+    ///
+    /// Widget surfaces keep their pixels across presents, so this does nothing for them. Other
+    /// surfaces return a `NoWidgetAttached` error.
+    pub fn set_surface_damage_region(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        _: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if !surface.has_widget() {
+            return Err(Error::NoWidgetAttached);
+        }
+        Ok(())
+    }
+
+    /// Returns the number of presents ago that the current back buffer of a widget surface was
+    /// displayed, or 0 if its contents are undefined.
+    ///
+    /// Widget surfaces have a single buffer that presents copy out of, so this is always 1 for
+    /// them. Other surfaces return a `NoWidgetAttached` error.
+    pub fn surface_buffer_age(&self, context: &Context, surface: &Surface) -> Result<u32, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if !surface.has_widget() {
+            return Err(Error::NoWidgetAttached);
+        }
+        Ok(1)
+    }
+
+    /// Sets whether presenting a widget surface preserves its contents.
+    ///
+    /// Widget surfaces are created without preservation, so their contents are undefined after
+    /// `present_surface()`. With it, applications that only repaint the dirty regions of each
+    /// frame can draw over the previous one, at the cost of a copy per present on some hardware.
+    ///
+    /// Widget surfaces on this backend always keep their contents, so this does nothing for them.
+    pub fn set_surface_preserve_buffer(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        _: bool,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if !surface.has_widget() {
+            return Err(Error::NoWidgetAttached);
+        }
+        Ok(())
+    }
+
+    /// Sets which way up the contents of a surface are.
+    ///
+    /// Widget surfaces are flipped accordingly when they are copied into their window. For other
+    /// surfaces, the origin is only recorded.
+    pub fn set_surface_origin(
+        &self,
+        surface: &mut Surface,
+        origin: SurfaceOrigin,
+    ) -> Result<(), Error> {
+        surface.origin = origin;
+        Ok(())
+    }
+
+    /// Sets the color space that the contents of a surface are in.
+    ///
+    /// There are no widget surfaces on this backend, so the color space is only recorded.
+    pub fn set_surface_colorspace(
+        &self,
+        surface: &mut Surface,
+        colorspace: Colorspace,
+    ) -> Result<(), Error> {
+        surface.colorspace = colorspace;
+        Ok(())
+    }
+
+    /// Resizes a surface.
+    ///
+    /// The surface's buffer is reallocated, discarding its contents.
+    pub(crate) fn resize_surface_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        size: Size2D<i32>,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if surface.size != size {
+            surface.pixels = vec![0; pixel_buffer_length(&size)];
+            surface.size = size;
+        }
+        Ok(())
+    }
+
+    /// Returns a pointer to the underlying surface data for reading or writing by the CPU.
+    ///
+    /// The surface must have been created with CPU access, or this returns a
+    /// `SurfaceDataInaccessible` error.
+    #[inline]
+    pub fn lock_surface_data<'s>(
+        &self,
+        surface: &'s mut Surface,
+    ) -> Result<SurfaceDataGuard<'s>, Error> {
+        if !surface.access.cpu_access_allowed() {
+            return Err(Error::SurfaceDataInaccessible);
+        }
+        Ok(SurfaceDataGuard { surface })
+    }
+
+    /// Returns the OpenGL texture target needed to read from this surface texture.
+    ///
+    /// This will be `GL_TEXTURE_2D` or `GL_TEXTURE_RECTANGLE`, depending on platform.
+    #[inline]
+    pub fn surface_gl_texture_target(&self) -> GLenum {
+        SURFACE_GL_TEXTURE_TARGET
+    }
+
+    /// Returns the OpenGL texture target that the given surface texture is bound to, which is
+    /// always `surface_gl_texture_target()` on OSMesa.
+    #[inline]
+    pub fn surface_texture_binding_target(&self, _: &SurfaceTexture) -> GLenum {
+        SURFACE_GL_TEXTURE_TARGET
+    }
+
+    /// Returns the number of layers of the surface that a surface texture wraps, which is always
+    /// 1 on OSMesa.
+    #[inline]
+    pub fn surface_texture_layers(&self, _: &SurfaceTexture) -> u32 {
+        1
+    }
+
+    /// Fills in the mip chain of a surface texture from the current contents of its surface, so
+    /// that it can be sampled with mipmapping at scales well below its size.
+    pub fn generate_surface_mipmaps(
+        &self,
+        context: &mut Context,
+        surface_texture: &SurfaceTexture,
+    ) -> Result<(), Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        GL_FUNCTIONS.with(|gl| {
+            gl_utils::generate_mipmaps(gl, surface_texture.texture_object);
+            Ok(())
+        })
+    }
+
+    /// Returns various information about the surface, including the framebuffer object needed to
+    /// render to this surface.
+    ///
+    /// Before rendering to a surface attached to a context, you must call `glBindFramebuffer()`
+    /// on the framebuffer object returned by this function. This framebuffer object may or not be
+    /// 0, the default framebuffer, depending on platform.
+    pub fn surface_info(&self, surface: &Surface) -> SurfaceInfo {
+        SurfaceInfo {
+            size: surface.size,
+            id: surface.id(),
+            context_id: surface.context_id,
+            framebuffer_object: 0,
+            scale_factor: 1.0,
+            transform: SurfaceTransform::Identity,
+            origin: surface.origin,
+            format: surface.format,
+            alpha_mode: surface.alpha_mode,
+            colorspace: surface.colorspace,
+            samples: 1,
+            bound: false,
+        }
+    }
+
+    /// Returns the GPU memory, in bytes, held by the given surface.
+    ///
+    /// OSMesa doesn't report the size of its allocations, so this is an estimate that counts four
+    /// bytes per pixel of color storage.
+    #[inline]
+    pub fn surface_memory_usage(&self, surface: &Surface) -> usize {
+        estimate_memory_usage(&self.surface_info(surface).size)
+    }
+
+    /// Returns the seed of the given surface, which goes up every time a `SurfaceDataGuard` for
+    /// it is unlocked or dropped.
+    ///
+    /// Compare it with the seed seen last time to find out whether the surface's pixels were
+    /// written through `lock_surface_data()` since.
+    #[inline]
+    pub fn surface_seed(&self, surface: &Surface) -> Result<u32, Error> {
+        Ok(surface.seed)
+    }
+
+    /// Returns the stride, row alignment, and total size of the memory that a generic surface of
+    /// the given size would be allocated with, without creating one.
+    ///
+    /// OSMesa renders into a tightly packed buffer in memory, so this is exactly the layout that
+    /// `lock_surface_data()` exposes.
+    #[inline]
+    pub fn surface_layout_requirements(
+        &self,
+        size: &Size2D<i32>,
+        _: SurfaceFormat,
+        _: SurfaceAccess,
+    ) -> Result<SurfaceLayoutRequirements, Error> {
+        Ok(SurfaceLayoutRequirements::packed(size))
+    }
+
+    /// Returns timing information about the most recently displayed frame of a widget surface.
+    ///
+    /// There are no widget surfaces on this backend, so this always returns `None`.
+    #[inline]
+    pub fn surface_presentation_statistics(&self, _: &Surface) -> Option<PresentationStatistics> {
+        None
+    }
+
+    /// Returns whether a widget surface can currently be seen on screen.
+    ///
+    /// There are no widget surfaces on this backend, so this always returns `Unknown`.
+    #[inline]
+    pub fn surface_visibility(&self, _: &Surface) -> SurfaceVisibility {
+        SurfaceVisibility::Unknown
+    }
+
+    /// Returns the display that a widget surface is shown on.
+    ///
+    /// There are no widget surfaces on this backend, so this always returns
+    /// `Error::NoWidgetAttached`.
+    #[inline]
+    pub fn widget_output_info(&self, _: &Surface) -> Result<WidgetOutputInfo, Error> {
+        Err(Error::NoWidgetAttached)
+    }
+
+    /// Returns the OpenGL texture object containing the contents of this surface.
+    ///
+    /// It is only legal to read from, not write to, this texture object.
+    #[inline]
+    pub fn surface_texture_object(&self, surface_texture: &SurfaceTexture) -> GLuint {
+        surface_texture.texture_object
+    }
+}
+
+// OSMesa can't bind an empty buffer, so zero-sized surfaces get a single pixel.
+fn pixel_buffer_length(size: &Size2D<i32>) -> usize {
+    size.width.max(1) as usize * size.height.max(1) as usize * BYTES_PER_PIXEL
+}
+
+/// Represents the CPU view of the pixel data of this surface.
+pub struct SurfaceDataGuard<'a> {
+    surface: &'a mut Surface,
+}
+
+impl<'a> SurfaceDataGuard<'a> {
+    /// Returns the number of bytes per row of the surface.
+    #[inline]
+    pub fn stride(&self) -> usize {
+        self.surface.size.width.max(1) as usize * BYTES_PER_PIXEL
+    }
+
+    /// Returns a mutable slice of the pixel data in this surface, in RGBA format, with the bottom
+    /// row first.
+    #[inline]
+    pub fn data(&mut self) -> &mut [u8] {
+        &mut self.surface.pixels
+    }
+
+    /// Unlocks the surface, returning its seed afterward.
+    ///
+    /// The seed goes up every time a guard is unlocked or dropped, so if it went up by more than
+    /// one since the last unlock, someone else modified the surface in between. Unlocking never
+    /// fails on this backend.
+    pub fn unlock(self) -> Result<u32, Error> {
+        self.surface.seed = self.surface.seed.wrapping_add(1);
+        let seed = self.surface.seed;
+        mem::forget(self);
+        Ok(seed)
+    }
+}
+
+impl<'a> Drop for SurfaceDataGuard<'a> {
+    #[inline]
+    fn drop(&mut self) {
+        self.surface.seed = self.surface.seed.wrapping_add(1);
+    }
+}
+
+#[cfg(feature = "sm-opencl")]
+impl Device {
+    /// Returns `Error::UnsupportedOnThisPlatform`, as OSMesa surfaces have no EGL image.
+    #[inline]
+    pub fn surface_egl_image(&self, _: &Surface) -> Result<SharedEGLImage, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+}
@@ -2,15 +2,19 @@
 //
 //! Represents the connection to the Core Graphics window server.
 //!
-//! Connection types are zero-sized on macOS, because the system APIs automatically manage the
-//! global window server connection.
+//! Connections hold no window server state on macOS, because the system APIs automatically manage
+//! the global window server connection.
 
 use super::device::{Adapter, Device};
+use crate::context::ContextDescriptorCache;
 use crate::platform::macos::system::connection::Connection as SystemConnection;
 use crate::platform::macos::system::device::NativeDevice;
 use crate::platform::macos::system::surface::NativeWidget;
+use crate::DisplayInfo;
 use crate::Error;
 use crate::GLApi;
+use crate::IpcDescriptor;
+use crate::{NativeDisplayHandle, NativeWidgetHandle};
 
 use euclid::default::Size2D;
 
@@ -37,7 +41,22 @@ impl Connection {
         SystemConnection::new().map(Connection)
     }
 
+    /// Returns `UnsupportedOnThisPlatform`, because CGL pixel formats can be created
+    /// from within the App Sandbox.
+    ///
+    /// # Safety
+    ///
+    /// The descriptor is never used, so there are no requirements on it.
+    #[inline]
+    pub unsafe fn from_ipc_descriptor(_: IpcDescriptor) -> Result<Connection, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
     /// An alias for `Connection::new()`, present for consistency with other backends.
+    ///
+    /// # Safety
+    ///
+    /// The native connection carries no handles, so there are no requirements on it.
     #[inline]
     pub unsafe fn from_native_connection(
         native_connection: NativeConnection,
@@ -51,12 +70,26 @@ impl Connection {
         self.0.native_connection()
     }
 
+    /// Returns the raw display connection underneath this connection.
+    ///
+    /// macOS has no display connection, so this returns `NativeDisplayHandle::None`.
+    #[inline]
+    pub fn native_display(&self) -> NativeDisplayHandle {
+        self.0.native_display()
+    }
+
     /// Returns the OpenGL API flavor that this connection supports (OpenGL or OpenGL ES).
     #[inline]
     pub fn gl_api(&self) -> GLApi {
         GLApi::GL
     }
 
+    /// Returns the displays connected to the system.
+    #[inline]
+    pub fn displays(&self) -> Result<Vec<DisplayInfo>, Error> {
+        self.0.displays()
+    }
+
     /// Returns the "best" adapter on this system, preferring high-performance hardware adapters.
     ///
     /// This is an alias for `Connection::create_hardware_adapter()`.
@@ -83,23 +116,31 @@ impl Connection {
         self.0.create_software_adapter().map(Adapter)
     }
 
+    /// Returns the adapter for the GPU that drives the given display.
+    #[inline]
+    pub fn create_adapter_for_display(&self, display: &DisplayInfo) -> Result<Adapter, Error> {
+        self.0.create_adapter_for_display(display).map(Adapter)
+    }
+
     /// Opens the hardware device corresponding to the given adapter.
     ///
-    /// Device handles are local to a single thread.
+    /// Device handles can be cloned and sent to other threads.
     #[inline]
-    pub fn create_device(&self, adapter: &Adapter) -> Result<Device, Error> {
-        self.0.create_device(&adapter.0).map(Device)
+    pub(crate) fn create_device_impl(&self, adapter: &Adapter) -> Result<Device, Error> {
+        self.0
+            .create_device(&adapter.0)
+            .map(|device| Device(device, ContextDescriptorCache::new()))
     }
 
     /// An alias for `connection.create_device()` with the default adapter.
     #[inline]
-    pub unsafe fn create_device_from_native_device(
+    pub(crate) unsafe fn create_device_from_native_device_impl(
         &self,
         native_device: NativeDevice,
     ) -> Result<Device, Error> {
         self.0
             .create_device_from_native_device(native_device)
-            .map(Device)
+            .map(|device| Device(device, ContextDescriptorCache::new()))
     }
 
     /// Opens the display connection corresponding to the given `winit` window.
@@ -121,6 +162,11 @@ impl Connection {
     }
 
     /// Creates a native widget from a raw pointer
+    ///
+    /// # Safety
+    ///
+    /// The pointer must be to a live `NSView`, which must outlive the native widget and any
+    /// surfaces created from it.
     pub unsafe fn create_native_widget_from_ptr(
         &self,
         raw: *mut c_void,
@@ -129,6 +175,22 @@ impl Connection {
         self.0.create_native_widget_from_ptr(raw, size)
     }
 
+    /// Creates a native widget from a handle to a native window or view.
+    ///
+    /// `NSView` handles are accepted, as are `CALayer` handles for layers that back a view.
+    ///
+    /// # Safety
+    ///
+    /// The handle must refer to a live window or view, which must outlive the native widget and any
+    /// surfaces created from it.
+    #[inline]
+    pub unsafe fn create_native_widget_from_handle(
+        &self,
+        handle: NativeWidgetHandle,
+    ) -> Result<NativeWidget, Error> {
+        self.0.create_native_widget_from_handle(handle)
+    }
+
     /// Create a native widget type from the given `raw_window_handle::RawWindowHandle`.
     #[cfg(feature = "sm-raw-window-handle")]
     #[inline]
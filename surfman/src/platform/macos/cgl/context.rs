@@ -2,17 +2,27 @@
 //
 //! Wrapper for Core OpenGL contexts.
 
+use super::connection::Connection;
 use super::device::Device;
 use super::error::ToWindowingApiError;
+#[cfg(feature = "sm-opencl")]
+use super::ffi::{CGDisplayIDToOpenGLDisplayMask, CGLGetShareGroup};
 use super::ffi::{CGLReleaseContext, CGLRetainContext};
 use super::surface::Surface;
-use crate::context::{ContextID, CREATE_CONTEXT_MUTEX};
+use crate::context::{self, ContextID};
+use crate::gl::types::GLuint;
 use crate::gl_utils;
-use crate::surface::Framebuffer;
-use crate::{ContextAttributeFlags, ContextAttributes, Error, GLVersion, Gl, SurfaceInfo};
+#[cfg(feature = "sm-opencl")]
+use crate::opencl::{GLSharingDisplay, GLSharingProperties};
+use crate::overrides;
+use crate::surface::{ExternalFramebuffer, Framebuffer};
+use crate::{
+    ContextAttributeFlags, ContextAttributes, ContextDescriptorID, Error, GLApi, GLVersion, Gl,
+    SurfaceInfo,
+};
 
 use cgl::{kCGLPFAAllowOfflineRenderers, kCGLPFAAlphaSize, kCGLPFADepthSize};
-use cgl::{kCGLPFAOpenGLProfile, kCGLPFAStencilSize};
+use cgl::{kCGLPFADisplayMask, kCGLPFAOpenGLProfile, kCGLPFAStencilSize};
 use cgl::{
     CGLChoosePixelFormat, CGLContextObj, CGLCreateContext, CGLDescribePixelFormat, CGLError,
 };
@@ -23,10 +33,13 @@ use core_foundation::bundle::CFBundleGetBundleWithIdentifier;
 use core_foundation::bundle::CFBundleGetFunctionPointerForName;
 use core_foundation::bundle::CFBundleRef;
 use core_foundation::string::CFString;
+use euclid::default::Size2D;
+use std::hash::{Hash, Hasher};
 use std::mem;
 use std::os::raw::c_void;
 use std::ptr;
 use std::str::FromStr;
+use std::sync::Mutex;
 use std::thread;
 
 // No CGL error occurred.
@@ -45,6 +58,11 @@ const kCGLOGLPVersion_GL4_Core: CGLPixelFormatAttribute = 0x4100;
 
 static OPENGL_FRAMEWORK_IDENTIFIER: &'static str = "com.apple.opengl";
 
+lazy_static! {
+    // Serializes context creation; see `Device::create_context()`.
+    static ref CREATE_CONTEXT_MUTEX: Mutex<()> = Mutex::new(());
+}
+
 thread_local! {
     #[doc(hidden)]
     pub static GL_FUNCTIONS: Gl = Gl::load_with(get_proc_address);
@@ -100,8 +118,13 @@ impl Drop for Context {
 /// Options that control OpenGL rendering.
 ///
 /// This corresponds to a "pixel format" object in many APIs. These are thread-safe.
+///
+/// Descriptors compare equal when they wrap the same pixel format object and require the same
+/// extensions, which is the case for the descriptors that a device hands out for the same
+/// attributes, since it caches them.
 pub struct ContextDescriptor {
     cgl_pixel_format: CGLPixelFormatObj,
+    required_extensions: Vec<String>,
 }
 
 impl Drop for ContextDescriptor {
@@ -120,18 +143,77 @@ impl Clone for ContextDescriptor {
         unsafe {
             ContextDescriptor {
                 cgl_pixel_format: CGLRetainPixelFormat(self.cgl_pixel_format),
+                required_extensions: self.required_extensions.clone(),
             }
         }
     }
 }
 
+impl PartialEq for ContextDescriptor {
+    #[inline]
+    fn eq(&self, other: &ContextDescriptor) -> bool {
+        self.cgl_pixel_format == other.cgl_pixel_format
+            && self.required_extensions == other.required_extensions
+    }
+}
+
+impl Eq for ContextDescriptor {}
+
+impl Hash for ContextDescriptor {
+    #[inline]
+    fn hash<H>(&self, state: &mut H)
+    where
+        H: Hasher,
+    {
+        self.cgl_pixel_format.hash(state);
+        self.required_extensions.hash(state);
+    }
+}
+
 unsafe impl Send for ContextDescriptor {}
 
+impl ContextDescriptor {
+    /// Returns the CGL pixel format object that contexts created from this descriptor use.
+    ///
+    /// Libraries that create their own contexts can pass it to `CGLCreateContext()`, to match
+    /// surfman's configuration. The pixel format is not retained, so call `CGLRetainPixelFormat()`
+    /// to keep it alive beyond the lifetime of this descriptor.
+    #[inline]
+    pub fn cgl_pixel_format(&self) -> CGLPixelFormatObj {
+        self.cgl_pixel_format
+    }
+}
+
 impl Device {
     /// Creates a context descriptor with the given attributes.
     ///
     /// Context descriptors are local to this device.
-    pub fn create_context_descriptor(
+    ///
+    /// Descriptors are cached by this device, so asking again for the same attributes returns a
+    /// copy of the earlier descriptor without choosing a pixel format again.
+    #[inline]
+    pub(crate) fn create_context_descriptor_impl(
+        &self,
+        attributes: &ContextAttributes,
+    ) -> Result<ContextDescriptor, Error> {
+        let attributes = &overrides::context_attributes(attributes);
+        self.1
+            .get_or_insert_with(attributes, || self.choose_context_descriptor(attributes))
+    }
+
+    /// Returns a small ID for the given context descriptor, which is the same for every equal
+    /// descriptor.
+    ///
+    /// The IDs are shared with clones of this device.
+    #[inline]
+    pub fn intern_context_descriptor(
+        &self,
+        context_descriptor: &ContextDescriptor,
+    ) -> ContextDescriptorID {
+        self.1.intern(context_descriptor)
+    }
+
+    fn choose_context_descriptor(
         &self,
         attributes: &ContextAttributes,
     ) -> Result<ContextDescriptor, Error> {
@@ -143,13 +225,24 @@ impl Device {
             return Err(Error::UnsupportedGLProfile);
         };
 
-        let profile = if attributes.version.major >= 4 {
-            kCGLOGLPVersion_GL4_Core
-        } else if attributes.version.major == 3 {
-            kCGLOGLPVersion_3_2_Core
+        // The profile decides the version: CGL picks the highest one that the profile allows. To
+        // negotiate a range of versions, try the profile of each version in it, highest first.
+        // Only the legacy profile has compatibility features.
+        let mut profiles = vec![];
+        if attributes
+            .flags
+            .contains(ContextAttributeFlags::COMPATIBILITY_PROFILE)
+        {
+            profiles.push(kCGLOGLPVersion_Legacy);
         } else {
-            kCGLOGLPVersion_Legacy
-        };
+            let highest_version = attributes.highest_version();
+            for version in context::gl_versions(GLApi::GL, attributes.version, highest_version) {
+                let profile = profile_for_version(version);
+                if !profiles.contains(&profile) {
+                    profiles.push(profile);
+                }
+            }
+        }
 
         let flags = attributes.flags;
         let alpha_size = if flags.contains(ContextAttributeFlags::ALPHA) {
@@ -168,9 +261,20 @@ impl Device {
             0
         };
 
+        // Stereo pixel formats went away with the other legacy renderers, and surfaces here are
+        // `IOSurface`s with a single color buffer anyway.
+        if flags.contains(ContextAttributeFlags::STEREO) {
+            return Err(Error::UnsupportedOnThisPlatform);
+        }
+
+        // Widget surfaces are `CALayer`s whose contents are those same 8-bit `IOSurface`s.
+        if flags.contains(ContextAttributeFlags::FLOAT_COLOR) {
+            return Err(Error::UnsupportedOnThisPlatform);
+        }
+
         let mut cgl_pixel_format_attributes = vec![
             kCGLPFAOpenGLProfile,
-            profile,
+            0,
             kCGLPFAAlphaSize,
             alpha_size,
             kCGLPFADepthSize,
@@ -186,33 +290,37 @@ impl Device {
             cgl_pixel_format_attributes.push(kCGLPFAAllowOfflineRenderers);
         }
 
+        // Restrict the choice of renderer to the GPU that drives the adapter's display, if any.
+        if let Some(display_id) = self.adapter().0.display_id {
+            let display_mask = unsafe { CGDisplayIDToOpenGLDisplayMask(display_id) };
+            cgl_pixel_format_attributes.push(kCGLPFADisplayMask);
+            cgl_pixel_format_attributes.push(display_mask as CGLPixelFormatAttribute);
+        }
+
         cgl_pixel_format_attributes.extend_from_slice(&[0, 0]);
 
-        unsafe {
-            let (mut cgl_pixel_format, mut cgl_pixel_format_count) = (ptr::null_mut(), 0);
-            let err = CGLChoosePixelFormat(
-                cgl_pixel_format_attributes.as_ptr(),
-                &mut cgl_pixel_format,
-                &mut cgl_pixel_format_count,
-            );
-            if err != kCGLNoError {
-                return Err(Error::PixelFormatSelectionFailed(
-                    err.to_windowing_api_error(),
-                ));
-            }
-            if cgl_pixel_format_count == 0 {
-                return Err(Error::NoPixelFormatFound);
+        // Report the failure for the lowest version if none of them work.
+        let mut result = Err(Error::NoPixelFormatFound);
+        for profile in profiles {
+            cgl_pixel_format_attributes[1] = profile;
+            result = unsafe {
+                choose_pixel_format(
+                    &cgl_pixel_format_attributes,
+                    &attributes.required_extensions,
+                )
+            };
+            if result.is_ok() {
+                break;
             }
-
-            Ok(ContextDescriptor { cgl_pixel_format })
         }
+        result
     }
 
     /// Creates a new OpenGL context.
     ///
     /// The context initially has no surface attached. Until a surface is bound to it, rendering
     /// commands will fail or have no effect.
-    pub fn create_context(
+    pub(crate) fn create_context_impl(
         &mut self,
         descriptor: &ContextDescriptor,
         share_with: Option<&Context>,
@@ -220,7 +328,7 @@ impl Device {
         // Take a lock so that we're only creating one context at a time. `CGLChoosePixelFormat`
         // will fail, returning `kCGLBadConnection`, if multiple threads try to open a display
         // connection simultaneously.
-        let mut next_context_id = CREATE_CONTEXT_MUTEX.lock().unwrap();
+        let _guard = CREATE_CONTEXT_MUTEX.lock().unwrap();
 
         unsafe {
             // Create the CGL context.
@@ -238,10 +346,9 @@ impl Device {
             // Wrap and return the context.
             let context = Context {
                 cgl_context,
-                id: *next_context_id,
+                id: self.0.context_ids.next(),
                 framebuffer: Framebuffer::None,
             };
-            next_context_id.0 += 1;
             Ok(context)
         }
     }
@@ -250,25 +357,44 @@ impl Device {
     ///
     /// This function takes ownership of the native context and does not adjust its reference
     /// count.
-    pub unsafe fn create_context_from_native_context(
+    pub(crate) unsafe fn create_context_from_native_context_impl(
         &self,
         native_context: NativeContext,
     ) -> Result<Context, Error> {
-        let mut next_context_id = CREATE_CONTEXT_MUTEX.lock().unwrap();
         let context = Context {
             cgl_context: native_context.0,
-            id: *next_context_id,
+            id: self.0.context_ids.next(),
             framebuffer: Framebuffer::None,
         };
-        next_context_id.0 += 1;
         mem::forget(native_context);
         Ok(context)
     }
 
+    /// Wraps the CGL context that is current on this thread in a context on a new device, opened
+    /// on the default adapter.
+    ///
+    /// Unlike `create_context_from_native_context()`, this retains the context, so destroying the
+    /// returned context releases only that reference. The context keeps rendering to whatever it
+    /// was attached to. If no context is current, this returns a `NoCurrentContext` error.
+    ///
+    /// # Safety
+    ///
+    /// The context is retained, so there are no requirements on how long it stays alive.
+    pub unsafe fn from_current_context() -> Result<(Device, Context), Error> {
+        // `NativeContext` releases the context when dropped, but `current()` doesn't retain it.
+        let native_context = NativeContext::current()?;
+        CGLRetainContext(native_context.0);
+        let connection = Connection::new()?;
+        let device = connection.create_device(&connection.create_adapter()?)?;
+        let mut context = device.create_context_from_native_context(native_context)?;
+        context.framebuffer = Framebuffer::External(());
+        Ok((device, context))
+    }
+
     /// Destroys a context.
     ///
     /// The context must have been created on this device.
-    pub fn destroy_context(&self, context: &mut Context) -> Result<(), Error> {
+    pub(crate) fn destroy_context_impl(&self, context: &mut Context) -> Result<(), Error> {
         if context.cgl_context.is_null() {
             return Ok(());
         }
@@ -294,14 +420,17 @@ impl Device {
         unsafe {
             let mut cgl_pixel_format = CGLGetPixelFormat(context.cgl_context);
             cgl_pixel_format = CGLRetainPixelFormat(cgl_pixel_format);
-            ContextDescriptor { cgl_pixel_format }
+            ContextDescriptor {
+                cgl_pixel_format,
+                required_extensions: vec![],
+            }
         }
     }
 
     /// Makes the context the current OpenGL context for this thread.
     ///
     /// After calling this function, it is valid to use OpenGL rendering commands.
-    pub fn make_context_current(&self, context: &Context) -> Result<(), Error> {
+    pub(crate) fn make_context_current_impl(&self, context: &Context) -> Result<(), Error> {
         unsafe {
             let err = CGLSetCurrentContext(context.cgl_context);
             if err != kCGLNoError {
@@ -311,6 +440,25 @@ impl Device {
         }
     }
 
+    /// Makes the context the current OpenGL context for this thread, rendering to the given
+    /// surface instead of any surface bound to it.
+    ///
+    /// The surface must have been created with this context, or an `IncompatibleSurface` error is
+    /// returned.
+    ///
+    /// All surfaces on this backend are rendered to through their framebuffer objects, so this is
+    /// the same as `make_context_current()` once the surface has been checked.
+    pub(crate) fn make_context_current_with_surface_impl(
+        &self,
+        context: &Context,
+        surface: &Surface,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        self.make_context_current_impl(context)
+    }
+
     /// Removes the current OpenGL context from this thread.
     ///
     /// After calling this function, OpenGL rendering commands will fail until a new context is
@@ -330,10 +478,20 @@ impl Device {
         context: &Context,
     ) -> Result<CurrentContextGuard, Error> {
         let guard = CurrentContextGuard::new();
-        self.make_context_current(context)?;
+        self.make_context_current_impl(context)?;
         Ok(guard)
     }
 
+    // Calls the given function with the OpenGL functions, which are loaded once per thread and
+    // shared by all contexts.
+    #[inline]
+    pub(crate) fn with_gl_functions<F, R>(&self, _: &Context, callback: F) -> R
+    where
+        F: FnOnce(&Gl) -> R,
+    {
+        GL_FUNCTIONS.with(|gl| callback(gl))
+    }
+
     /// Attaches a surface to a context for rendering.
     ///
     /// This function takes ownership of the surface. The surface must have been created with this
@@ -344,13 +502,15 @@ impl Device {
     /// `unbind_surface_from_context`.
     ///
     /// If an error is returned, the surface is returned alongside it.
-    pub fn bind_surface_to_context(
+    pub(crate) fn bind_surface_to_context_impl(
         &self,
         context: &mut Context,
         new_surface: Surface,
     ) -> Result<(), (Error, Surface)> {
         match context.framebuffer {
-            Framebuffer::External(_) => return Err((Error::ExternalRenderTarget, new_surface)),
+            Framebuffer::External(_) | Framebuffer::ExternalFramebuffer(_) => {
+                return Err((Error::ExternalRenderTarget, new_surface))
+            }
             Framebuffer::Surface(_) => return Err((Error::SurfaceAlreadyBound, new_surface)),
             Framebuffer::None => {}
         }
@@ -367,18 +527,18 @@ impl Device {
     ///
     /// Any pending OpenGL commands targeting this surface will be automatically flushed, so the
     /// surface is safe to read from immediately when this function returns.
-    pub fn unbind_surface_from_context(
+    pub(crate) fn unbind_surface_from_context_impl(
         &self,
         context: &mut Context,
     ) -> Result<Option<Surface>, Error> {
         match context.framebuffer {
             Framebuffer::External(_) => return Err(Error::ExternalRenderTarget),
-            Framebuffer::None | Framebuffer::Surface(_) => {}
+            Framebuffer::None | Framebuffer::ExternalFramebuffer(_) | Framebuffer::Surface(_) => {}
         }
 
         match mem::replace(&mut context.framebuffer, Framebuffer::None) {
             Framebuffer::External(_) => unreachable!(),
-            Framebuffer::None => Ok(None),
+            Framebuffer::None | Framebuffer::ExternalFramebuffer(_) => Ok(None),
             Framebuffer::Surface(surface) => {
                 // Make sure all changes are synchronized. Apple requires this.
                 //
@@ -397,6 +557,53 @@ impl Device {
         }
     }
 
+    /// Makes a context render to a framebuffer object that the caller created and owns, in place
+    /// of a surface.
+    ///
+    /// The framebuffer object must stay alive until it's detached again with
+    /// `unbind_surface_from_context()`.
+    pub fn bind_external_framebuffer(
+        &self,
+        context: &mut Context,
+        framebuffer_object: GLuint,
+        size: Size2D<i32>,
+    ) -> Result<(), Error> {
+        match context.framebuffer {
+            Framebuffer::None => {}
+            Framebuffer::External(_) | Framebuffer::ExternalFramebuffer(_) => {
+                return Err(Error::ExternalRenderTarget)
+            }
+            Framebuffer::Surface(_) => return Err(Error::SurfaceAlreadyBound),
+        }
+
+        // CGL contexts have no drawable, so there's nothing to switch.
+        context.framebuffer =
+            Framebuffer::ExternalFramebuffer(ExternalFramebuffer::new(framebuffer_object, size));
+        Ok(())
+    }
+
+    /// Makes the rendering submitted so far to a surface visible to everything else that reads
+    /// it.
+    ///
+    /// This flushes the context, which Apple requires before other contexts see changes to an
+    /// `IOSurface`, and waits on a fence for the rendering to finish.
+    ///
+    /// The surface must have been created with the given context, or an `IncompatibleSurface`
+    /// error is returned.
+    pub(crate) fn flush_context_impl(
+        &self,
+        context: &Context,
+        surface: &Surface,
+    ) -> Result<(), Error> {
+        if surface.context_id != context.id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        let _guard = self.temporarily_make_context_current(context)?;
+        GL_FUNCTIONS.with(gl_utils::finish_with_fence);
+        Ok(())
+    }
+
     /// Returns the attributes that the context descriptor was created with.
     pub fn context_descriptor_attributes(
         &self,
@@ -423,9 +630,14 @@ impl Device {
                 attribute_flags.insert(ContextAttributeFlags::COMPATIBILITY_PROFILE);
             }
 
+            // Surfaces here are all single-sampled `IOSurface`s rendered through framebuffer
+            // objects, so there's no multisampled window-system framebuffer to ask for.
             return ContextAttributes {
                 flags: attribute_flags,
                 version,
+                max_version: None,
+                required_extensions: context_descriptor.required_extensions.clone(),
+                samples: 1,
             };
         }
 
@@ -464,7 +676,13 @@ impl Device {
         match context.framebuffer {
             Framebuffer::None => Ok(None),
             Framebuffer::External(_) => Err(Error::ExternalRenderTarget),
-            Framebuffer::Surface(ref surface) => Ok(Some(self.surface_info(surface))),
+            Framebuffer::ExternalFramebuffer(ref framebuffer) => {
+                Ok(Some(framebuffer.info(context.id)))
+            }
+            Framebuffer::Surface(ref surface) => Ok(Some(SurfaceInfo {
+                bound: true,
+                ..self.surface_info(surface)
+            })),
         }
     }
 
@@ -487,6 +705,41 @@ impl Device {
     }
 }
 
+fn profile_for_version(version: GLVersion) -> CGLPixelFormatAttribute {
+    if version.major >= 4 {
+        kCGLOGLPVersion_GL4_Core
+    } else if version.major == 3 {
+        kCGLOGLPVersion_3_2_Core
+    } else {
+        kCGLOGLPVersion_Legacy
+    }
+}
+
+unsafe fn choose_pixel_format(
+    cgl_pixel_format_attributes: &[CGLPixelFormatAttribute],
+    required_extensions: &[String],
+) -> Result<ContextDescriptor, Error> {
+    let (mut cgl_pixel_format, mut cgl_pixel_format_count) = (ptr::null_mut(), 0);
+    let err = CGLChoosePixelFormat(
+        cgl_pixel_format_attributes.as_ptr(),
+        &mut cgl_pixel_format,
+        &mut cgl_pixel_format_count,
+    );
+    if err != kCGLNoError {
+        return Err(Error::PixelFormatSelectionFailed(
+            err.to_windowing_api_error(),
+        ));
+    }
+    if cgl_pixel_format_count == 0 {
+        return Err(Error::NoPixelFormatFound);
+    }
+
+    Ok(ContextDescriptor {
+        cgl_pixel_format,
+        required_extensions: required_extensions.to_vec(),
+    })
+}
+
 fn get_proc_address(symbol_name: &str) -> *const c_void {
     OPENGL_FRAMEWORK.with(|framework| unsafe {
         let symbol_name: CFString = FromStr::from_str(symbol_name).unwrap();
@@ -550,3 +803,19 @@ impl NativeContext {
         }
     }
 }
+
+#[cfg(feature = "sm-opencl")]
+impl Device {
+    /// Returns what OpenCL needs to share objects with the given context through
+    /// `cl_khr_gl_sharing`.
+    ///
+    /// OpenCL finds the context through its share group.
+    pub fn gl_sharing_properties(&self, context: &Context) -> Result<GLSharingProperties, Error> {
+        unsafe {
+            Ok(GLSharingProperties {
+                gl_context: context.cgl_context as *mut c_void,
+                display: GLSharingDisplay::CGLShareGroup(CGLGetShareGroup(context.cgl_context)),
+            })
+        }
+    }
+}
@@ -3,22 +3,34 @@
 //! A handle to the device. (This is a no-op, because handles are implicit in Apple's Core OpenGL.)
 
 use super::connection::Connection;
+use super::context::ContextDescriptor;
+use crate::context::ContextDescriptorCache;
+use crate::device_events::{self, DeviceEventDispatcher};
 use crate::platform::macos::system::device::{Adapter as SystemAdapter, Device as SystemDevice};
-use crate::GLApi;
+use crate::timings::{CreationTimings, CreationTimingsRecorder};
+use crate::{DeviceEventHandler, GLApi, LifecycleObserver, NativeDeviceHandle};
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 pub use crate::platform::macos::system::device::NativeDevice;
 
 /// Represents a hardware display adapter that can be used for rendering (including the CPU).
 ///
-/// Adapters can be sent between threads. To render with an adapter, open a thread-local `Device`.
+/// Adapters can be sent between threads. To render with an adapter, open a `Device`.
 #[derive(Clone, Debug)]
 pub struct Adapter(pub(crate) SystemAdapter);
 
-/// A thread-local handle to a device.
+/// A handle to a device.
 ///
-/// Devices contain most of the relevant surface management methods.
+/// Devices contain most of the relevant surface management methods. Clones share the device's
+/// context descriptor cache and can be sent to other threads, so each rendering thread can own a
+/// handle.
 #[derive(Clone)]
-pub struct Device(pub(crate) SystemDevice);
+pub struct Device(
+    pub(crate) SystemDevice,
+    pub(crate) ContextDescriptorCache<ContextDescriptor>,
+);
 
 impl Device {
     /// Returns the native device corresponding to this device.
@@ -27,6 +39,12 @@ impl Device {
         self.0.native_device()
     }
 
+    /// Returns the raw GPU device underneath this device: the Metal device of its GPU.
+    #[inline]
+    pub fn native_device_handle(&self) -> NativeDeviceHandle {
+        self.0.native_device_handle()
+    }
+
     /// Returns the display server connection that this device was created with.
     #[inline]
     pub fn connection(&self) -> Connection {
@@ -44,4 +62,61 @@ impl Device {
     pub fn gl_api(&self) -> GLApi {
         GLApi::GL
     }
+
+    /// Installs an observer that is notified when contexts and surfaces of this device are
+    /// created, destroyed, resized, or presented, replacing any previous one.
+    #[inline]
+    pub fn set_lifecycle_observer(&mut self, observer: Option<Arc<dyn LifecycleObserver>>) {
+        self.0.lifecycle_observer = observer;
+    }
+
+    /// Returns the lifecycle observer installed on this device, if any.
+    #[inline]
+    pub fn lifecycle_observer(&self) -> Option<Arc<dyn LifecycleObserver>> {
+        self.0.lifecycle_observer.clone()
+    }
+
+    /// Installs a handler that is told when the GPU of this device is removed or reset, or the
+    /// displays are reconfigured, replacing any previous one.
+    ///
+    /// Display changes are found by checking `Connection::displays()` once a second.
+    pub fn set_device_event_handler(&mut self, handler: Option<DeviceEventHandler>) {
+        let connection = self.connection();
+        let poll = device_events::display_poller(move || connection.displays());
+        self.0.device_event_dispatcher.set_handler(handler, poll);
+    }
+
+    /// Returns the device event handler installed on this device, if any.
+    #[inline]
+    pub fn device_event_handler(&self) -> Option<DeviceEventHandler> {
+        self.0.device_event_dispatcher.handler()
+    }
+
+    #[inline]
+    pub(crate) fn device_event_dispatcher(&self) -> &DeviceEventDispatcher {
+        &self.0.device_event_dispatcher
+    }
+
+    /// Returns the GPU memory, in bytes, held by the surfaces of this device.
+    #[inline]
+    pub fn memory_usage(&self) -> usize {
+        self.0.memory_usage.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub(crate) fn memory_usage_counter(&self) -> &AtomicUsize {
+        &self.0.memory_usage
+    }
+
+    /// Returns how long opening this device, and creating its context descriptors and contexts,
+    /// took.
+    #[inline]
+    pub fn creation_timings(&self) -> CreationTimings {
+        self.0.creation_timings.get()
+    }
+
+    #[inline]
+    pub(crate) fn creation_timings_recorder(&self) -> &CreationTimingsRecorder {
+        &self.0.creation_timings
+    }
 }
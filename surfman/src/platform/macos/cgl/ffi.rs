@@ -3,9 +3,21 @@
 //! FFI declarations not provided by the upstream `cgl` crate.
 
 use cgl::CGLContextObj;
+use core_graphics::display::CGDirectDisplayID;
+use std::os::raw::c_void;
+
+pub(crate) type CGLShareGroupObj = *mut c_void;
+pub(crate) type CGOpenGLDisplayMask = u32;
 
 #[link(name = "OpenGL", kind = "framework")]
 extern "C" {
     pub(crate) fn CGLRetainContext(ctx: CGLContextObj) -> CGLContextObj;
     pub(crate) fn CGLReleaseContext(ctx: CGLContextObj);
+    pub(crate) fn CGLGetShareGroup(ctx: CGLContextObj) -> CGLShareGroupObj;
+}
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    pub(crate) fn CGDisplayIDToOpenGLDisplayMask(display: CGDirectDisplayID)
+        -> CGOpenGLDisplayMask;
 }
@@ -7,12 +7,19 @@ use super::device::Device;
 use crate::context::ContextID;
 use crate::gl::types::{GLenum, GLint, GLuint};
 use crate::gl_utils;
+#[cfg(feature = "sm-opencl")]
+use crate::opencl::SharedEGLImage;
 use crate::platform::macos::system::surface::Surface as SystemSurface;
 use crate::renderbuffers::Renderbuffers;
-use crate::{gl, Error, SurfaceAccess, SurfaceID, SurfaceInfo, SurfaceType, WindowingApiError};
+use crate::{
+    gl, Colorspace, CreateSurfacesResult, Error, PresentationStatistics, SurfaceAccess,
+    SurfaceAlphaMode, SurfaceFormat, SurfaceID, SurfaceInfo, SurfaceLayoutRequirements,
+    SurfaceOrigin, SurfaceTransform, SurfaceType, SurfaceUsage, SurfaceVisibility,
+    WidgetOutputInfo, WindowingApiError,
+};
 
 use core_foundation::base::TCFType;
-use euclid::default::Size2D;
+use euclid::default::{Rect, Size2D};
 use io_surface::{self, IOSurface};
 use std::fmt::{self, Debug, Formatter};
 use std::marker::PhantomData;
@@ -43,6 +50,14 @@ pub struct Surface {
     pub(crate) framebuffer_object: GLuint,
     pub(crate) texture_object: GLuint,
     pub(crate) renderbuffers: Renderbuffers,
+    // The order of the channels in memory. See `SurfaceInfo::format`.
+    pub(crate) format: SurfaceFormat,
+    // Which way up the contents are. See `SurfaceInfo::origin`.
+    pub(crate) origin: SurfaceOrigin,
+    // How the alpha channel is interpreted. See `SurfaceInfo::alpha_mode`.
+    pub(crate) alpha_mode: SurfaceAlphaMode,
+    // The color space of the contents. See `SurfaceInfo::colorspace`.
+    pub(crate) colorspace: Colorspace,
 }
 
 /// Represents an OpenGL texture that wraps a surface.
@@ -79,13 +94,78 @@ impl Device {
     ///
     /// Only the given context may ever render to the surface, but generic surfaces can be wrapped
     /// up in a `SurfaceTexture` for reading by other contexts.
-    pub fn create_surface(
+    ///
+    /// The layers of opaque widget surfaces are marked opaque, so Core Animation skips blending
+    /// them; the others are blended as premultiplied. Translucent pixels only show what is behind
+    /// the window if the window is not opaque and has a clear background color. Widget surfaces
+    /// with straight alpha are unsupported.
+    pub(crate) fn create_surface_impl(
         &mut self,
         context: &Context,
         access: SurfaceAccess,
+        _: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
         surface_type: SurfaceType<NativeWidget>,
     ) -> Result<Surface, Error> {
+        if let SurfaceType::Widget { .. } = surface_type {
+            // Core Animation only composites premultiplied contents.
+            if surface_alpha_mode == SurfaceAlphaMode::Straight {
+                return Err(Error::UnsupportedOnThisPlatform);
+            }
+        }
         let mut system_surface = self.0.create_surface(access, surface_type)?;
+        self.0.set_surface_opaque(
+            &mut system_surface,
+            surface_alpha_mode == SurfaceAlphaMode::Opaque,
+        );
+        let mut surface = self.wrap_system_surface(context, system_surface)?;
+        surface.alpha_mode = surface_alpha_mode;
+        Ok(surface)
+    }
+
+    /// Creates `count` generic surfaces of the given size.
+    ///
+    /// The properties of the `IOSurface`s are built, and the context made current, once for the
+    /// whole batch. If one of the surfaces fails to be created, the error is returned along with
+    /// the surfaces that were created before it.
+    pub(crate) fn create_surfaces_impl(
+        &mut self,
+        context: &Context,
+        access: SurfaceAccess,
+        _: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
+        size: &Size2D<i32>,
+        count: usize,
+    ) -> CreateSurfacesResult<Surface> {
+        let _guard = self.temporarily_make_context_current(context);
+        let system_surfaces = self.0.create_generic_surfaces(access, size, count);
+
+        let mut surfaces = Vec::with_capacity(count);
+        let mut system_surfaces = system_surfaces.into_iter();
+        while let Some(system_surface) = system_surfaces.next() {
+            match self.wrap_system_surface(context, system_surface) {
+                Ok(mut surface) => {
+                    surface.alpha_mode = surface_alpha_mode;
+                    surfaces.push(surface)
+                }
+                Err(err) => {
+                    for mut system_surface in system_surfaces {
+                        let _ = self.0.destroy_surface(&mut system_surface);
+                    }
+                    return Err((err, surfaces));
+                }
+            }
+        }
+        Ok(surfaces)
+    }
+
+    // Attaches a framebuffer of the given context to a new system surface. The system surface is
+    // destroyed on failure.
+    fn wrap_system_surface(
+        &self,
+        context: &Context,
+        mut system_surface: SystemSurface,
+    ) -> Result<Surface, Error> {
         self.0.set_surface_flipped(&mut system_surface, true);
 
         let _guard = self.temporarily_make_context_current(context);
@@ -137,11 +217,71 @@ impl Device {
                     framebuffer_object,
                     texture_object,
                     renderbuffers,
+                    format: SurfaceFormat::BGRA8,
+                    origin: SurfaceOrigin::BottomLeft,
+                    alpha_mode: SurfaceAlphaMode::Premultiplied,
+                    colorspace: Colorspace::SRGB,
                 })
             }
         })
     }
 
+    /// Creates a new generic surface with a copy of the contents of the given one, stored with
+    /// its color channels in the given order.
+    ///
+    /// Surfaces on this backend are `IOSurface`s in BGRA order. Calling this method on a widget
+    /// surface returns a `WidgetAttached` error.
+    pub(crate) fn convert_surface_format_impl(
+        &mut self,
+        context: &mut Context,
+        surface: &Surface,
+        format: SurfaceFormat,
+    ) -> Result<Surface, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if surface.system_surface.view_info.is_some() {
+            return Err(Error::WidgetAttached);
+        }
+
+        let size = surface.system_surface.size;
+        let mut new_surface = self.create_surface_impl(
+            context,
+            SurfaceAccess::GPUOnly,
+            SurfaceUsage::default(),
+            surface.alpha_mode,
+            SurfaceType::Generic { size },
+        )?;
+        new_surface.format = format;
+        new_surface.origin = surface.origin;
+        self.set_surface_colorspace(&mut new_surface, surface.colorspace)?;
+
+        let _guard = self.temporarily_make_context_current(context);
+        GL_FUNCTIONS.with(|gl| {
+            gl_utils::copy_framebuffer(
+                gl,
+                surface.framebuffer_object,
+                new_surface.framebuffer_object,
+                &size,
+                surface.format != format,
+            )
+        });
+        Ok(new_surface)
+    }
+
+    /// Wraps an existing OpenGL texture of the given context in a new generic surface.
+    ///
+    /// Generic surfaces on this backend are `IOSurface`s, which can't be made from a texture, so this always returns an `UnsupportedOnThisPlatform` error.
+    #[inline]
+    pub(crate) fn create_surface_from_texture_impl(
+        &mut self,
+        _: &mut Context,
+        _: &Size2D<i32>,
+        _: GLuint,
+    ) -> Result<Surface, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
     /// Creates a surface texture from an existing generic surface for use with the given context.
     ///
     /// The surface texture is local to the supplied context and takes ownership of the surface.
@@ -180,7 +320,12 @@ impl Device {
             gl.GenTextures(1, &mut texture);
             debug_assert_ne!(texture, 0);
 
-            gl.BindTexture(gl::TEXTURE_RECTANGLE, texture);
+            let mut bindings = gl_utils::BindingsGuard::for_texture(
+                gl,
+                gl::TEXTURE_RECTANGLE,
+                gl::TEXTURE_BINDING_RECTANGLE,
+            );
+            bindings.bind_texture(texture);
             io_surface.bind_to_gl_texture(size.width, size.height, true);
 
             gl.TexParameteri(
@@ -204,8 +349,6 @@ impl Device {
                 gl::CLAMP_TO_EDGE as GLint,
             );
 
-            gl.BindTexture(gl::TEXTURE_RECTANGLE, 0);
-
             debug_assert_eq!(gl.GetError(), gl::NO_ERROR);
 
             texture
@@ -219,9 +362,9 @@ impl Device {
     ///
     /// You must explicitly call this method to dispose of a surface. Otherwise, a panic occurs in
     /// the `drop` method.
-    pub fn destroy_surface(
+    pub(crate) fn destroy_surface_impl(
         &self,
-        context: &mut Context,
+        context: &Context,
         surface: &mut Surface,
     ) -> Result<(), Error> {
         GL_FUNCTIONS.with(|gl| {
@@ -264,6 +407,36 @@ impl Device {
         })
     }
 
+    /// Destroys the native resources of a surface given up with `surface_into_destroy_token()`.
+    ///
+    /// Unlike `destroy_surface()`, this doesn't need the context that the surface belonged to.
+    #[inline]
+    pub fn destroy_token(&self, mut token: SurfaceDestroyToken) -> Result<(), Error> {
+        self.0.destroy_surface(&mut token.system_surface)
+    }
+
+    /// Returns timing information about the most recently displayed frame of a widget surface.
+    ///
+    /// Presentation timing isn't available on this backend, so this always returns `None`.
+    #[inline]
+    pub fn surface_presentation_statistics(&self, _: &Surface) -> Option<PresentationStatistics> {
+        None
+    }
+
+    /// Returns whether a widget surface can currently be seen on screen, according to the
+    /// occlusion state of its window.
+    #[inline]
+    pub fn surface_visibility(&self, surface: &Surface) -> SurfaceVisibility {
+        self.0.surface_visibility(&surface.system_surface)
+    }
+
+    /// Returns the screen that a widget surface is shown on, along with its bit depth, whether it
+    /// has a wide color gamut, and whether it can show extended dynamic range content.
+    #[inline]
+    pub fn widget_output_info(&self, surface: &Surface) -> Result<WidgetOutputInfo, Error> {
+        self.0.widget_output_info(&surface.system_surface)
+    }
+
     /// Returns the OpenGL texture object containing the contents of this surface.
     ///
     /// It is only legal to read from, not write to, this texture object.
@@ -280,6 +453,18 @@ impl Device {
         SURFACE_GL_TEXTURE_TARGET
     }
 
+    /// Fills in the mip chain of a surface texture from the current contents of its surface.
+    ///
+    /// Surface textures on this backend are rectangle textures, which can't be mipmapped, so this always returns an `UnsupportedOnThisPlatform` error.
+    #[inline]
+    pub fn generate_surface_mipmaps(
+        &self,
+        _: &mut Context,
+        _: &SurfaceTexture,
+    ) -> Result<(), Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
     /// Displays the contents of a widget surface on screen.
     ///
     /// Widget surfaces are internally double-buffered, so changes to them don't show up in their
@@ -287,26 +472,138 @@ impl Device {
     ///
     /// The supplied context must match the context the surface was created with, or an
     /// `IncompatibleSurface` error is returned.
-    pub fn present_surface(&self, _: &Context, surface: &mut Surface) -> Result<(), Error> {
+    pub(crate) fn present_surface_impl(
+        &self,
+        _: &Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
         self.0.present_surface(&mut surface.system_surface)?;
 
         GL_FUNCTIONS.with(|gl| {
             unsafe {
                 let size = surface.system_surface.size;
-                gl.BindTexture(gl::TEXTURE_RECTANGLE, surface.texture_object);
+                let mut bindings = gl_utils::BindingsGuard::for_texture(
+                    gl,
+                    gl::TEXTURE_RECTANGLE,
+                    gl::TEXTURE_BINDING_RECTANGLE,
+                );
+                bindings.bind_texture(surface.texture_object);
                 surface
                     .system_surface
                     .io_surface
                     .bind_to_gl_texture(size.width, size.height, true);
-                gl.BindTexture(gl::TEXTURE_RECTANGLE, 0);
             }
 
             Ok(())
         })
     }
 
+    /// Displays the contents of a widget surface on screen, hinting that only the given
+    /// rectangles have changed since the last present.
+    ///
+    /// Core Animation always displays the whole surface, so the damage is ignored.
+    #[inline]
+    pub(crate) fn present_surface_with_damage_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        _: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        self.present_surface_impl(context, surface)
+    }
+
+    /// Displays the `source` rectangle of a widget surface in the `destination` rectangle of its
+    /// widget, scaling it to fit.
+    ///
+    /// This is not yet supported on CGL, so it always returns `UnsupportedOnThisPlatform`.
+    #[inline]
+    pub(crate) fn present_surface_with_viewport_impl(
+        &self,
+        _: &Context,
+        _: &mut Surface,
+        _: &Rect<i32>,
+        _: &Rect<i32>,
+    ) -> Result<(), Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Declares which parts of a widget surface will be redrawn before it is next presented.
+    ///
+    /// CGL has no notion of partial updates, so this does nothing.
+    #[inline]
+    pub fn set_surface_damage_region(
+        &self,
+        _: &Context,
+        _: &mut Surface,
+        _: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Returns the number of presents ago that the current back buffer of a widget surface was
+    /// displayed, or 0 if its contents are undefined.
+    ///
+    /// Buffer ages aren't tracked on this backend, so this always returns 0.
+    #[inline]
+    pub fn surface_buffer_age(&self, _: &Context, _: &Surface) -> Result<u32, Error> {
+        Ok(0)
+    }
+
+    /// Sets whether presenting a widget surface preserves its contents.
+    ///
+    /// Widget surfaces are created without preservation, so their contents are undefined after
+    /// `present_surface()`. With it, applications that only repaint the dirty regions of each
+    /// frame can draw over the previous one, at the cost of a copy per present on some hardware.
+    ///
+    /// The contents of the back buffer aren't tracked across presents on macOS, so this returns
+    /// `Error::UnsupportedOnThisPlatform`.
+    pub fn set_surface_preserve_buffer(
+        &self,
+        _: &Context,
+        _: &mut Surface,
+        _: bool,
+    ) -> Result<(), Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Sets which way up the contents of a surface are.
+    ///
+    /// Widget surfaces are flipped by the transform of the Core Animation layer that shows them.
+    pub fn set_surface_origin(
+        &self,
+        surface: &mut Surface,
+        origin: SurfaceOrigin,
+    ) -> Result<(), Error> {
+        self.0.set_surface_flipped(
+            &mut surface.system_surface,
+            origin == SurfaceOrigin::BottomLeft,
+        );
+        surface.origin = origin;
+        Ok(())
+    }
+
+    /// Sets the color space that the contents of a surface are in.
+    ///
+    /// The `IOSurface`s of the surface are tagged with the color space, so that Core Animation
+    /// converts the contents of widget surfaces for the display.
+    pub fn set_surface_colorspace(
+        &self,
+        surface: &mut Surface,
+        colorspace: Colorspace,
+    ) -> Result<(), Error> {
+        self.0
+            .set_surface_colorspace(&mut surface.system_surface, colorspace);
+        surface.colorspace = colorspace;
+        Ok(())
+    }
+
+    // Names the `IOSurface`s behind the surface with its label.
+    pub(crate) fn set_surface_native_label(&self, surface: &Surface, label: &str) {
+        self.0.set_surface_label(&surface.system_surface, label);
+    }
+
     /// Resizes a widget surface.
-    pub fn resize_surface(
+    pub(crate) fn resize_surface_impl(
         &self,
         context: &Context,
         surface: &mut Surface,
@@ -320,6 +617,15 @@ impl Device {
         let _guard = self.temporarily_bind_framebuffer(surface.framebuffer_object);
 
         self.0.resize_surface(&mut surface.system_surface, size)?;
+        // Resizing always sets up the layer for bottom-left contents.
+        if surface.origin == SurfaceOrigin::TopLeft {
+            self.0
+                .set_surface_flipped(&mut surface.system_surface, false);
+        }
+        if surface.colorspace != Colorspace::SRGB {
+            self.0
+                .set_surface_colorspace(&mut surface.system_surface, surface.colorspace);
+        }
 
         let context_descriptor = self.context_descriptor(context);
         let context_attributes = self.context_descriptor_attributes(&context_descriptor);
@@ -384,9 +690,47 @@ impl Device {
             id: system_surface_info.id,
             context_id: surface.context_id,
             framebuffer_object: surface.framebuffer_object,
+            scale_factor: 1.0,
+            transform: SurfaceTransform::Identity,
+            origin: surface.origin,
+            format: surface.format,
+            alpha_mode: surface.alpha_mode,
+            colorspace: surface.colorspace,
+            samples: 1,
+            bound: false,
         }
     }
 
+    /// Returns the GPU memory, in bytes, held by the given surface, as reported by
+    /// `IOSurfaceGetAllocSize()`.
+    #[inline]
+    pub fn surface_memory_usage(&self, surface: &Surface) -> usize {
+        self.0.surface_memory_usage(&surface.system_surface)
+    }
+
+    /// Returns the seed of the given surface, as reported by `IOSurfaceGetSeed()`.
+    ///
+    /// The seed goes up whenever the surface is modified, by this process or any other that shares
+    /// it.
+    #[inline]
+    pub fn surface_seed(&self, surface: &Surface) -> Result<u32, Error> {
+        self.0.surface_seed(&surface.system_surface)
+    }
+
+    /// Returns the stride, row alignment, and total size of the memory that a generic surface of
+    /// the given size would be allocated with, without creating one.
+    ///
+    /// These come from `IOSurface`, which may pad rows beyond four bytes per pixel.
+    #[inline]
+    pub fn surface_layout_requirements(
+        &self,
+        size: &Size2D<i32>,
+        format: SurfaceFormat,
+        access: SurfaceAccess,
+    ) -> Result<SurfaceLayoutRequirements, Error> {
+        self.0.surface_layout_requirements(size, format, access)
+    }
+
     /// Returns the native `IOSurface` corresponding to this surface.
     ///
     /// The reference count is increased on the `IOSurface` before returning.
@@ -399,10 +743,31 @@ impl Device {
 impl Surface {
     #[inline]
     fn id(&self) -> SurfaceID {
-        SurfaceID(self.system_surface.io_surface.as_concrete_TypeRef() as usize)
+        self.system_surface.id
+    }
+
+    /// Gives up this surface, returning a token that can be destroyed later with
+    /// `Device::destroy_token()`, on any thread and without the surface's context.
+    ///
+    /// The OpenGL objects that the surface owns in its context, such as its framebuffer, aren't
+    /// part of the token. They're freed when the context is destroyed.
+    pub(crate) fn into_destroy_token(mut self) -> SurfaceDestroyToken {
+        self.renderbuffers.abandon();
+        SurfaceDestroyToken {
+            system_surface: self.system_surface,
+        }
     }
 }
 
+/// The `IOSurface` of a surface given up with `Device::surface_into_destroy_token()`, which can be
+/// destroyed later without the surface's context.
+///
+/// Tokens can be sent to other threads, and must be destroyed with `Device::destroy_token()`, or
+/// a panic will occur.
+pub struct SurfaceDestroyToken {
+    system_surface: SystemSurface,
+}
+
 #[must_use]
 struct FramebufferGuard {
     draw: GLuint,
@@ -417,3 +782,12 @@ impl Drop for FramebufferGuard {
         })
     }
 }
+
+#[cfg(feature = "sm-opencl")]
+impl Device {
+    /// Returns `Error::UnsupportedOnThisPlatform`, as CGL surfaces have no EGL image.
+    #[inline]
+    pub fn surface_egl_image(&self, _: &Surface) -> Result<SharedEGLImage, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+}
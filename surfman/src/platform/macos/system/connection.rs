@@ -2,22 +2,24 @@
 //
 //! Represents the connection to the Core Graphics window server.
 //!
-//! Connection types are zero-sized on macOS, because the system APIs automatically manage the
-//! global window server connection.
+//! Connections hold no window server state on macOS, because the system APIs automatically manage
+//! the global window server connection.
 
 use super::device::{Adapter, Device, NativeDevice};
 use super::surface::{NSView, NativeWidget};
-use crate::Error;
+use crate::context::ContextIDAllocator;
+use crate::{DisplayInfo, Error, NativeDisplayHandle, NativeWidgetHandle};
 
-use cocoa::base::id;
+use cocoa::base::{id, nil, BOOL, YES};
 use core_foundation::base::TCFType;
 use core_foundation::boolean::CFBoolean;
 use core_foundation::bundle::CFBundleGetInfoDictionary;
 use core_foundation::bundle::CFBundleGetMainBundle;
 use core_foundation::dictionary::{CFMutableDictionary, CFMutableDictionaryRef};
 use core_foundation::string::CFString;
+use core_graphics::display::{CGDirectDisplayID, CGDisplay};
 
-use euclid::default::Size2D;
+use euclid::default::{Point2D, Size2D};
 
 use std::os::raw::c_void;
 use std::str::FromStr;
@@ -29,9 +31,12 @@ use winit::window::Window;
 
 /// A no-op connection.
 ///
-/// Connections to the CGS window server are implicit on macOS, so this is a zero-sized type.
+/// Connections to the CGS window server are implicit on macOS, so this only holds surfman's own
+/// state, such as the counter that context IDs are allocated from.
 #[derive(Clone)]
-pub struct Connection;
+pub struct Connection {
+    pub(crate) context_ids: ContextIDAllocator,
+}
 
 /// An empty placeholder for native connections.
 ///
@@ -63,10 +68,16 @@ impl Connection {
             );
         }
 
-        Ok(Connection)
+        Ok(Connection {
+            context_ids: ContextIDAllocator::new()?,
+        })
     }
 
     /// An alias for `Connection::new()`, present for consistency with other backends.
+    ///
+    /// # Safety
+    ///
+    /// The native connection carries no handles, so there are no requirements on it.
     #[inline]
     pub unsafe fn from_native_connection(_: NativeConnection) -> Result<Connection, Error> {
         Connection::new()
@@ -78,6 +89,57 @@ impl Connection {
         NativeConnection
     }
 
+    /// Returns the raw display connection underneath this connection.
+    ///
+    /// macOS has no display connection, so this returns `NativeDisplayHandle::None`.
+    #[inline]
+    pub fn native_display(&self) -> NativeDisplayHandle {
+        NativeDisplayHandle::None
+    }
+
+    /// Returns the displays connected to the system.
+    ///
+    /// Display bounds are reported in device pixels, by scaling the Core Graphics bounds (which are
+    /// in points) by each display's backing scale factor.
+    pub fn displays(&self) -> Result<Vec<DisplayInfo>, Error> {
+        let display_ids = CGDisplay::active_displays().map_err(|_| Error::Failed)?;
+        Ok(display_ids
+            .into_iter()
+            .map(|display_id| {
+                let display = CGDisplay::new(display_id);
+                let bounds = display.bounds();
+                let (scale_factor, refresh_rate) = match display.display_mode() {
+                    Some(mode) if mode.width() > 0 => (
+                        mode.pixel_width() as f64 / mode.width() as f64,
+                        mode.refresh_rate(),
+                    ),
+                    Some(mode) => (1.0, mode.refresh_rate()),
+                    None => (1.0, 0.0),
+                };
+                DisplayInfo {
+                    id: display_id as u64,
+                    name: String::new(),
+                    origin: Point2D::new(
+                        (bounds.origin.x * scale_factor) as i32,
+                        (bounds.origin.y * scale_factor) as i32,
+                    ),
+                    size: Size2D::new(
+                        (bounds.size.width * scale_factor) as i32,
+                        (bounds.size.height * scale_factor) as i32,
+                    ),
+                    // Built-in panels, and ProMotion displays, report a refresh rate of zero.
+                    refresh_rate: if refresh_rate > 0.0 {
+                        Some(refresh_rate)
+                    } else {
+                        None
+                    },
+                    scale_factor,
+                    colorspace: None,
+                }
+            })
+            .collect())
+    }
+
     /// Returns the "best" adapter on this system, preferring high-performance hardware adapters.
     ///
     /// This is an alias for `Connection::create_hardware_adapter()`.
@@ -91,13 +153,17 @@ impl Connection {
     pub fn create_hardware_adapter(&self) -> Result<Adapter, Error> {
         Ok(Adapter {
             is_low_power: false,
+            display_id: None,
         })
     }
 
     /// Returns the "best" adapter on this system, preferring low-power hardware adapters.
     #[inline]
     pub fn create_low_power_adapter(&self) -> Result<Adapter, Error> {
-        Ok(Adapter { is_low_power: true })
+        Ok(Adapter {
+            is_low_power: true,
+            display_id: None,
+        })
     }
 
     /// Returns the "best" adapter on this system, preferring software adapters.
@@ -106,15 +172,35 @@ impl Connection {
         self.create_low_power_adapter()
     }
 
+    /// Returns the adapter for the GPU that drives the given display.
+    ///
+    /// Contexts created on this adapter only use renderers that can drive the display, so that
+    /// presenting to it never involves a copy between GPUs.
+    pub fn create_adapter_for_display(&self, display: &DisplayInfo) -> Result<Adapter, Error> {
+        let display_id = display.id as CGDirectDisplayID;
+        let display_ids = CGDisplay::active_displays().map_err(|_| Error::Failed)?;
+        if !display_ids.contains(&display_id) {
+            return Err(Error::NoAdapterFound);
+        }
+        Ok(Adapter {
+            is_low_power: false,
+            display_id: Some(display_id),
+        })
+    }
+
     /// Opens the hardware device corresponding to the given adapter.
     ///
-    /// Device handles are local to a single thread.
+    /// Device handles can be cloned and sent to other threads.
     #[inline]
     pub fn create_device(&self, adapter: &Adapter) -> Result<Device, Error> {
-        Device::new((*adapter).clone())
+        Device::new(self, (*adapter).clone())
     }
 
     /// An alias for `connection.create_device()` with the default adapter.
+    ///
+    /// # Safety
+    ///
+    /// The native device is never used, so there are no requirements on it.
     #[inline]
     pub unsafe fn create_device_from_native_device(
         &self,
@@ -149,6 +235,11 @@ impl Connection {
     }
 
     /// Create a native widget from a raw pointer
+    ///
+    /// # Safety
+    ///
+    /// The pointer must be to a live `NSView`, which must outlive the native widget and any
+    /// surfaces created from it.
     pub unsafe fn create_native_widget_from_ptr(
         &self,
         raw: *mut c_void,
@@ -159,6 +250,39 @@ impl Connection {
         }
     }
 
+    /// Creates a native widget from a handle to a native window or view.
+    ///
+    /// `NSView` handles are accepted, as are `CALayer` handles for layers that back a view.
+    ///
+    /// # Safety
+    ///
+    /// The handle must refer to a live window or view, which must outlive the native widget and any
+    /// surfaces created from it.
+    pub unsafe fn create_native_widget_from_handle(
+        &self,
+        handle: NativeWidgetHandle,
+    ) -> Result<NativeWidget, Error> {
+        let view = match handle {
+            NativeWidgetHandle::NSView(view) => view as id,
+            NativeWidgetHandle::CALayer(layer) => {
+                // A layer-backed view is the delegate of its layer.
+                let view: id = msg_send![layer as id, delegate];
+                let is_view: BOOL = msg_send![view, isKindOfClass: class!(NSView)];
+                if is_view != YES {
+                    return Err(Error::IncompatibleNativeWidget);
+                }
+                view
+            }
+            _ => return Err(Error::IncompatibleNativeWidget),
+        };
+        if view == nil {
+            return Err(Error::IncompatibleNativeWidget);
+        }
+        Ok(NativeWidget {
+            view: NSView(msg_send![view, retain]),
+        })
+    }
+
     /// Create a native widget type from the given `raw_window_handle::RawWindowHandle`.
     #[cfg(feature = "sm-raw-window-handle")]
     #[inline]
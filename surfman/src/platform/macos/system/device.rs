@@ -3,26 +3,39 @@
 //! A handle to the device. (This is a no-op, because handles are implicit in `IOSurface`.)
 
 use super::connection::Connection;
-use crate::Error;
+use crate::context::ContextIDAllocator;
+use crate::device_events::DeviceEventDispatcher;
+use crate::timings::CreationTimingsRecorder;
+use crate::{Error, LifecycleObserver, NativeDeviceHandle};
 
+use core_graphics::display::CGDirectDisplayID;
 use metal::Device as MetalDevice;
-use std::marker::PhantomData;
+use std::os::raw::c_void;
+use std::sync::atomic::AtomicUsize;
+use std::sync::Arc;
 
 /// Represents a hardware display adapter that can be used for rendering (including the CPU).
 ///
-/// Adapters can be sent between threads. To render with an adapter, open a thread-local `Device`.
+/// Adapters can be sent between threads. To render with an adapter, open a `Device`.
 #[derive(Clone, Debug)]
 pub struct Adapter {
     pub(crate) is_low_power: bool,
+    // If set, only renderers that drive this display are used.
+    pub(crate) display_id: Option<CGDirectDisplayID>,
 }
 
-/// A thread-local handle to a device.
+/// A handle to a device.
 ///
-/// Devices contain most of the relevant surface management methods.
+/// Devices contain most of the relevant surface management methods. Clones can be sent to other
+/// threads, so each rendering thread can own a handle.
 #[derive(Clone)]
 pub struct Device {
     adapter: Adapter,
-    phantom: PhantomData<*mut ()>,
+    pub(crate) lifecycle_observer: Option<Arc<dyn LifecycleObserver>>,
+    pub(crate) device_event_dispatcher: DeviceEventDispatcher,
+    pub(crate) creation_timings: CreationTimingsRecorder,
+    pub(crate) memory_usage: Arc<AtomicUsize>,
+    pub(crate) context_ids: ContextIDAllocator,
 }
 
 /// The Metal device corresponding to this device.
@@ -31,10 +44,14 @@ pub struct NativeDevice(pub MetalDevice);
 
 impl Device {
     #[inline]
-    pub(crate) fn new(adapter: Adapter) -> Result<Device, Error> {
+    pub(crate) fn new(connection: &Connection, adapter: Adapter) -> Result<Device, Error> {
         Ok(Device {
             adapter,
-            phantom: PhantomData,
+            lifecycle_observer: None,
+            device_event_dispatcher: DeviceEventDispatcher::default(),
+            creation_timings: CreationTimingsRecorder::default(),
+            memory_usage: Arc::new(AtomicUsize::new(0)),
+            context_ids: connection.context_ids.clone(),
         })
     }
 
@@ -49,10 +66,20 @@ impl Device {
         )
     }
 
+    /// Returns the raw GPU device underneath this device: the Metal device of its GPU.
+    pub fn native_device_handle(&self) -> NativeDeviceHandle {
+        // A `DeviceRef` points at the `id<MTLDevice>` itself. The system keeps its devices alive
+        // after ours is released.
+        let NativeDevice(metal_device) = self.native_device();
+        NativeDeviceHandle::MTLDevice(&*metal_device as *const _ as *mut c_void)
+    }
+
     /// Returns the display server connection that this device was created with.
     #[inline]
     pub fn connection(&self) -> Connection {
-        Connection
+        Connection {
+            context_ids: self.context_ids.clone(),
+        }
     }
 
     /// Returns the adapter that this device was created with.
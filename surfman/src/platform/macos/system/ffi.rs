@@ -4,6 +4,8 @@
 
 #![allow(non_upper_case_globals)]
 
+use core_foundation::base::CFTypeRef;
+use core_foundation::string::CFStringRef;
 use io_surface::IOSurfaceRef;
 use mach::kern_return::kern_return_t;
 use std::os::raw::c_void;
@@ -20,11 +22,31 @@ pub(crate) const kIOMapWriteCombineCache: i32 = kIOWriteCombineCache << kIOMapCa
 
 pub(crate) type IOSurfaceLockOptions = u32;
 
+pub(crate) type NSWindowDepth = i32;
+
+#[link(name = "AppKit", kind = "framework")]
+extern "C" {
+    pub(crate) fn NSBitsPerSampleFromDepth(depth: NSWindowDepth) -> isize;
+}
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    pub(crate) fn CGColorSpaceIsWideGamutRGB(space: *const c_void) -> bool;
+}
+
 #[link(name = "IOSurface", kind = "framework")]
 extern "C" {
+    pub(crate) static kIOSurfaceAllocSize: CFStringRef;
+    pub(crate) static kIOSurfaceName: CFStringRef;
+
+    pub(crate) fn IOSurfaceAlignProperty(property: CFStringRef, value: usize) -> usize;
+    pub(crate) fn IOSurfaceGetPropertyAlignment(property: CFStringRef) -> usize;
     pub(crate) fn IOSurfaceGetAllocSize(buffer: IOSurfaceRef) -> usize;
     pub(crate) fn IOSurfaceGetBaseAddress(buffer: IOSurfaceRef) -> *mut c_void;
     pub(crate) fn IOSurfaceGetBytesPerRow(buffer: IOSurfaceRef) -> usize;
+    pub(crate) fn IOSurfaceGetSeed(buffer: IOSurfaceRef) -> u32;
+    pub(crate) fn IOSurfaceSetValue(buffer: IOSurfaceRef, key: CFStringRef, value: CFTypeRef);
+    pub(crate) fn IOSurfaceRemoveValue(buffer: IOSurfaceRef, key: CFStringRef);
     pub(crate) fn IOSurfaceLock(
         buffer: IOSurfaceRef,
         options: IOSurfaceLockOptions,
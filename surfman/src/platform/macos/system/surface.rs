@@ -4,15 +4,20 @@
 
 use super::device::Device;
 use super::ffi::{kCVPixelFormatType_32BGRA, kIOMapDefaultCache, IOSurfaceLock, IOSurfaceUnlock};
-use super::ffi::{kCVReturnSuccess, kIOMapWriteCombineCache};
+use super::ffi::{kCVReturnSuccess, kIOMapWriteCombineCache, kIOSurfaceName};
 use super::ffi::{IOSurfaceGetAllocSize, IOSurfaceGetBaseAddress, IOSurfaceGetBytesPerRow};
-use crate::{Error, SurfaceAccess, SurfaceID, SurfaceType, SystemSurfaceInfo};
+use super::ffi::{CGColorSpaceIsWideGamutRGB, NSBitsPerSampleFromDepth, NSWindowDepth};
+use super::ffi::{kIOSurfaceAllocSize, IOSurfaceAlignProperty, IOSurfaceGetPropertyAlignment};
+use super::ffi::{IOSurfaceGetSeed, IOSurfaceRemoveValue, IOSurfaceSetValue};
+use crate::{Colorspace, Error, SurfaceAccess, SurfaceID, SurfaceType, SurfaceVisibility};
+use crate::{SurfaceFormat, SurfaceLayoutRequirements, SystemSurfaceInfo};
+use crate::WidgetOutputInfo;
 
 use cocoa::appkit::{NSScreen, NSView as NSViewMethods, NSWindow};
-use cocoa::base::{id, YES};
-use cocoa::foundation::{NSPoint, NSRect, NSSize};
+use cocoa::base::{id, nil, BOOL, YES};
+use cocoa::foundation::{NSPoint, NSRect, NSSize, NSUInteger};
 use cocoa::quartzcore::{transaction, CALayer, CATransform3D};
-use core_foundation::base::TCFType;
+use core_foundation::base::{CFType, TCFType};
 use core_foundation::dictionary::CFDictionary;
 use core_foundation::number::CFNumber;
 use core_foundation::string::CFString;
@@ -31,6 +36,9 @@ use std::thread;
 
 const BYTES_PER_PIXEL: i32 = 4;
 
+// `NSWindowOcclusionStateVisible`, set while any part of a window is visible.
+const NS_WINDOW_OCCLUSION_STATE_VISIBLE: NSUInteger = 1 << 1;
+
 /// Represents a hardware buffer of pixels that can be rendered to via the CPU or GPU and either
 /// displayed in a native widget or bound to a texture for reading.
 ///
@@ -43,6 +51,7 @@ const BYTES_PER_PIXEL: i32 = 4;
 ///
 /// Surfaces must be destroyed with the `destroy_surface()` method, or a panic will occur.
 pub struct Surface {
+    pub(crate) id: SurfaceID,
     pub(crate) io_surface: IOSurface,
     pub(crate) size: Size2D<i32>,
     access: SurfaceAccess,
@@ -140,6 +149,7 @@ impl Device {
             };
 
             Ok(Surface {
+                id: SurfaceID::next(),
                 io_surface,
                 size,
                 access,
@@ -149,6 +159,28 @@ impl Device {
         }
     }
 
+    /// Creates `count` generic surfaces of the given size.
+    ///
+    /// The properties of the `IOSurface`s are built once for the whole batch.
+    pub fn create_generic_surfaces(
+        &mut self,
+        access: SurfaceAccess,
+        size: &Size2D<i32>,
+        count: usize,
+    ) -> Vec<Surface> {
+        let properties = self.io_surface_properties(size, access);
+        (0..count)
+            .map(|_| Surface {
+                id: SurfaceID::next(),
+                io_surface: io_surface::new(&properties),
+                size: *size,
+                access,
+                destroyed: false,
+                view_info: None,
+            })
+            .collect()
+    }
+
     pub(crate) fn set_surface_flipped(&self, surface: &mut Surface, flipped: bool) {
         if let Some(ref mut view_info) = surface.view_info {
             let (scale_y, translate_y) = if flipped {
@@ -165,6 +197,37 @@ impl Device {
         }
     }
 
+    // Whether Core Animation may skip blending the layer of a widget surface. Widget layers start
+    // out opaque.
+    pub(crate) fn set_surface_opaque(&self, surface: &mut Surface, opaque: bool) {
+        if let Some(ref mut view_info) = surface.view_info {
+            transaction::begin();
+            transaction::set_disable_actions(true);
+            view_info.layer.set_opaque(opaque);
+            view_info.layer.set_contents_opaque(opaque);
+            transaction::commit();
+        }
+    }
+
+    // Tags the `IOSurface`s of a surface with the color space of their contents, which Core
+    // Animation converts from when it composites a widget surface. sRGB surfaces are left untagged,
+    // as they always were. Resizing allocates untagged `IOSurface`s.
+    pub(crate) fn set_surface_colorspace(&self, surface: &mut Surface, colorspace: Colorspace) {
+        tag_io_surface(&surface.io_surface, colorspace);
+        if let Some(ref view_info) = surface.view_info {
+            tag_io_surface(&view_info.front_surface, colorspace);
+        }
+    }
+
+    /// Names the `IOSurface`s of a surface, so that the label shows up on them in Instruments and
+    /// Metal captures. Resizing allocates unnamed `IOSurface`s.
+    pub fn set_surface_label(&self, surface: &Surface, label: &str) {
+        name_io_surface(&surface.io_surface, label);
+        if let Some(ref view_info) = surface.view_info {
+            name_io_surface(&view_info.front_surface, label);
+        }
+    }
+
     unsafe fn create_view_info(
         &mut self,
         size: &Size2D<i32>,
@@ -284,8 +347,6 @@ impl Device {
             view_info
                 .layer
                 .set_contents(view_info.front_surface.obj as id);
-            view_info.layer.set_opaque(true);
-            view_info.layer.set_contents_opaque(true);
             surface.io_surface = self.create_io_surface(&size, surface.access);
             surface.size = size;
         }
@@ -294,6 +355,82 @@ impl Device {
         Ok(())
     }
 
+    /// Returns whether a widget surface can currently be seen on screen, according to the
+    /// occlusion state of its window.
+    ///
+    /// Generic surfaces are never on screen, so this returns `Unknown` for them.
+    pub fn surface_visibility(&self, surface: &Surface) -> SurfaceVisibility {
+        let view_info = match surface.view_info {
+            None => return SurfaceVisibility::Unknown,
+            Some(ref view_info) => view_info,
+        };
+        unsafe {
+            let window: id = msg_send![view_info.view.0, window];
+            if window == nil {
+                return SurfaceVisibility::Occluded;
+            }
+            let occlusion_state: NSUInteger = msg_send![window, occlusionState];
+            if occlusion_state & NS_WINDOW_OCCLUSION_STATE_VISIBLE != 0 {
+                SurfaceVisibility::Visible
+            } else {
+                SurfaceVisibility::Occluded
+            }
+        }
+    }
+
+    /// Returns the screen that the window of a widget surface is on, along with its bit depth,
+    /// whether it has a wide color gamut, and whether it can show extended dynamic range content.
+    ///
+    /// Wide-gamut screens are reported as `DisplayP3`, whether or not HDR is available. Windows
+    /// that aren't on any screen are reported as being on an unknown 8-bit sRGB display.
+    pub fn widget_output_info(&self, surface: &Surface) -> Result<WidgetOutputInfo, Error> {
+        let view_info = match surface.view_info {
+            None => return Err(Error::NoWidgetAttached),
+            Some(ref view_info) => view_info,
+        };
+        unsafe {
+            let window: id = msg_send![view_info.view.0, window];
+            let screen = if window == nil { nil } else { window.screen() };
+            if screen == nil {
+                return Ok(WidgetOutputInfo::unknown(None));
+            }
+
+            let device_description: CFDictionary<CFString, CFNumber> =
+                CFDictionary::wrap_under_get_rule(screen.deviceDescription() as *const _);
+            let description_key: CFString = CFString::from("NSScreenNumber");
+            let display_id = device_description
+                .find(description_key)
+                .and_then(|display_id| display_id.to_i64())
+                .map(|display_id| display_id as u64);
+            let mut output_info = WidgetOutputInfo::unknown(display_id);
+
+            let depth: NSWindowDepth = msg_send![screen, depth];
+            let bits_per_sample = NSBitsPerSampleFromDepth(depth);
+            if bits_per_sample > 0 {
+                output_info.bits_per_component = bits_per_sample as u32;
+            }
+
+            let color_space: id = msg_send![screen, colorSpace];
+            if color_space != nil {
+                let cg_color_space: *const c_void = msg_send![color_space, CGColorSpace];
+                if !cg_color_space.is_null() && CGColorSpaceIsWideGamutRGB(cg_color_space) {
+                    output_info.colorspace = Colorspace::DisplayP3;
+                }
+            }
+
+            // Screens that can show extended dynamic range content allow component values above 1.
+            // This was added in macOS 10.15.
+            let edr_selector = sel!(maximumPotentialExtendedDynamicRangeColorComponentValue);
+            let responds: BOOL = msg_send![screen, respondsToSelector: edr_selector];
+            if responds == YES {
+                let maximum_edr: f64 =
+                    msg_send![screen, maximumPotentialExtendedDynamicRangeColorComponentValue];
+                output_info.hdr = maximum_edr > 1.0;
+            }
+            Ok(output_info)
+        }
+    }
+
     /// Returns a pointer to the underlying surface data for reading or writing by the CPU.
     #[inline]
     pub fn lock_surface_data<'s>(
@@ -304,13 +441,21 @@ impl Device {
     }
 
     fn create_io_surface(&self, size: &Size2D<i32>, access: SurfaceAccess) -> IOSurface {
+        io_surface::new(&self.io_surface_properties(size, access))
+    }
+
+    fn io_surface_properties(
+        &self,
+        size: &Size2D<i32>,
+        access: SurfaceAccess,
+    ) -> CFDictionary<CFString, CFType> {
         let cache_mode = match access {
             SurfaceAccess::GPUCPUWriteCombined => kIOMapWriteCombineCache,
             SurfaceAccess::GPUOnly | SurfaceAccess::GPUCPU => kIOMapDefaultCache,
         };
 
         unsafe {
-            let properties = CFDictionary::from_CFType_pairs(&[
+            CFDictionary::from_CFType_pairs(&[
                 (
                     CFString::wrap_under_get_rule(kIOSurfaceWidth),
                     CFNumber::from(size.width).as_CFType(),
@@ -335,9 +480,7 @@ impl Device {
                     CFString::wrap_under_get_rule(kIOSurfaceCacheMode),
                     CFNumber::from(cache_mode).as_CFType(),
                 ),
-            ]);
-
-            io_surface::new(&properties)
+            ])
         }
     }
 
@@ -350,6 +493,46 @@ impl Device {
         }
     }
 
+    /// Returns the memory, in bytes, held by the given surface, as reported by
+    /// `IOSurfaceGetAllocSize()`.
+    #[inline]
+    pub fn surface_memory_usage(&self, surface: &Surface) -> usize {
+        unsafe { IOSurfaceGetAllocSize(surface.io_surface.as_concrete_TypeRef()) }
+    }
+
+    /// Returns the seed of the given surface, as reported by `IOSurfaceGetSeed()`.
+    ///
+    /// The seed goes up whenever the surface is modified, by this process or any other that shares
+    /// it. Compare it with the seed seen last time to find out cheaply whether the surface has to
+    /// be sampled again.
+    #[inline]
+    pub fn surface_seed(&self, surface: &Surface) -> Result<u32, Error> {
+        unsafe { Ok(IOSurfaceGetSeed(surface.io_surface.as_concrete_TypeRef())) }
+    }
+
+    /// Returns the stride, row alignment, and total size of the memory that a surface of the
+    /// given size would be allocated with, without creating one.
+    ///
+    /// These are the values that `IOSurface` rounds the properties of a new surface up to, so rows
+    /// may be padded beyond four bytes per pixel.
+    pub fn surface_layout_requirements(
+        &self,
+        size: &Size2D<i32>,
+        _: SurfaceFormat,
+        _: SurfaceAccess,
+    ) -> Result<SurfaceLayoutRequirements, Error> {
+        unsafe {
+            let packed_stride = size.width.max(0) as usize * BYTES_PER_PIXEL as usize;
+            let stride = IOSurfaceAlignProperty(kIOSurfaceBytesPerRow, packed_stride);
+            let alloc_size = stride * size.height.max(0) as usize;
+            Ok(SurfaceLayoutRequirements {
+                stride,
+                alignment: IOSurfaceGetPropertyAlignment(kIOSurfaceBytesPerRow),
+                size: IOSurfaceAlignProperty(kIOSurfaceAllocSize, alloc_size),
+            })
+        }
+    }
+
     /// Returns the native `IOSurface` corresponding to this surface.
     ///
     /// The reference count is increased on the `IOSurface` before returning.
@@ -362,10 +545,43 @@ impl Device {
     }
 }
 
+// Sets or removes the color space attachment of an `IOSurface`. The attachment is the name of a
+// `CGColorSpace`; the `kCGColorSpace*` constants are their own names.
+fn tag_io_surface(io_surface: &IOSurface, colorspace: Colorspace) {
+    let key = CFString::from_static_string("IOSurfaceColorSpace");
+    let name = match colorspace {
+        Colorspace::SRGB => None,
+        Colorspace::DisplayP3 => Some("kCGColorSpaceDisplayP3"),
+        Colorspace::BT2020 => Some("kCGColorSpaceITUR_2100_PQ"),
+    };
+    unsafe {
+        match name {
+            Some(name) => IOSurfaceSetValue(
+                io_surface.as_concrete_TypeRef(),
+                key.as_concrete_TypeRef(),
+                CFString::from_static_string(name).as_CFTypeRef(),
+            ),
+            None => {
+                IOSurfaceRemoveValue(io_surface.as_concrete_TypeRef(), key.as_concrete_TypeRef())
+            }
+        }
+    }
+}
+
+fn name_io_surface(io_surface: &IOSurface, label: &str) {
+    unsafe {
+        IOSurfaceSetValue(
+            io_surface.as_concrete_TypeRef(),
+            kIOSurfaceName,
+            CFString::new(label).as_CFTypeRef(),
+        );
+    }
+}
+
 impl Surface {
     #[inline]
     fn id(&self) -> SurfaceID {
-        SurfaceID(self.io_surface.as_concrete_TypeRef() as usize)
+        self.id
     }
 
     fn present(&mut self) -> Result<(), Error> {
@@ -422,6 +638,18 @@ impl Surface {
             })
         }
     }
+
+    // Unlocks the IOSurface after `lock_data()`, returning its new seed.
+    fn unlock_data(&mut self) -> Result<u32, Error> {
+        unsafe {
+            let mut seed = 0;
+            let result = IOSurfaceUnlock(self.io_surface.as_concrete_TypeRef(), 0, &mut seed);
+            if result != KERN_SUCCESS {
+                return Err(Error::SurfaceLockFailed);
+            }
+            Ok(seed)
+        }
+    }
 }
 
 impl<'a> SurfaceDataGuard<'a> {
@@ -436,14 +664,26 @@ impl<'a> SurfaceDataGuard<'a> {
     pub fn data(&mut self) -> &mut [u8] {
         unsafe { slice::from_raw_parts_mut(self.ptr, self.len) }
     }
+
+    /// Unlocks the surface, returning its seed afterward.
+    ///
+    /// The seed is the IOSurface seed, which goes up every time the surface is unlocked after
+    /// being locked for writing, by this process or any other. If it went up by more than one
+    /// since the last unlock, someone else modified the surface in between.
+    ///
+    /// Dropping the guard unlocks the surface too, but ignores errors.
+    pub fn unlock(self) -> Result<u32, Error> {
+        let result = self.surface.unlock_data();
+        mem::forget(self);
+        result
+    }
 }
 
 impl<'a> Drop for SurfaceDataGuard<'a> {
     #[inline]
     fn drop(&mut self) {
-        unsafe {
-            let mut seed = 0;
-            IOSurfaceUnlock(self.surface.io_surface.as_concrete_TypeRef(), 0, &mut seed);
+        if let Err(err) = self.surface.unlock_data() {
+            warn!("Failed to unlock the surface: {:?}", err);
         }
     }
 }
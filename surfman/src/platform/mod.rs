@@ -9,6 +9,11 @@ pub mod android;
 #[cfg(android)]
 pub use android as default;
 
+#[cfg(ohos)]
+pub mod ohos;
+#[cfg(ohos)]
+pub use ohos as default;
+
 #[cfg(macos)]
 pub mod macos;
 #[cfg(macos)]
@@ -21,6 +26,9 @@ pub mod unix;
 #[cfg(linux)]
 pub use unix::default;
 
+#[cfg(redox)]
+pub use generic::osmesa as default;
+
 #[cfg(windows)]
 pub mod windows;
 #[cfg(angle_default)]
@@ -0,0 +1,237 @@
+// surfman/surfman/src/platform/ohos/connection.rs
+//
+//! A no-op connection for OpenHarmony.
+
+use super::device::{Adapter, Device, NativeDevice};
+use super::ffi::OHNativeWindow;
+use super::surface::NativeWidget;
+use crate::context::{ContextDescriptorCache, ContextIDAllocator};
+use crate::device_events::DeviceEventDispatcher;
+use crate::timings::CreationTimingsRecorder;
+use crate::GLApi;
+use crate::{DisplayInfo, Error, IpcDescriptor, NativeDisplayHandle, NativeWidgetHandle};
+
+use euclid::default::Size2D;
+
+use std::os::raw::c_void;
+use std::sync::atomic::AtomicUsize;
+use std::sync::Arc;
+
+#[cfg(feature = "sm-winit")]
+use winit::window::Window;
+
+/// A connection to the display server.
+#[derive(Clone)]
+pub struct Connection {
+    pub(crate) context_ids: ContextIDAllocator,
+}
+
+/// An empty placeholder for native connections.
+#[derive(Clone)]
+pub struct NativeConnection;
+
+impl Connection {
+    /// Connects to the default display.
+    #[inline]
+    pub fn new() -> Result<Connection, Error> {
+        Ok(Connection {
+            context_ids: ContextIDAllocator::new()?,
+        })
+    }
+
+    /// Returns `UnsupportedOnThisPlatform`, because EGL displays can be opened from
+    /// within the sandbox.
+    ///
+    /// # Safety
+    ///
+    /// The descriptor is never used, so there are no requirements on it.
+    #[inline]
+    pub unsafe fn from_ipc_descriptor(_: IpcDescriptor) -> Result<Connection, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// An alias for `Connection::new()`, present for consistency with other backends.
+    ///
+    /// # Safety
+    ///
+    /// The native connection carries no handles, so there are no requirements on it.
+    #[inline]
+    pub unsafe fn from_native_connection(_: NativeConnection) -> Result<Connection, Error> {
+        Connection::new()
+    }
+
+    /// Returns the underlying native connection.
+    #[inline]
+    pub fn native_connection(&self) -> NativeConnection {
+        NativeConnection
+    }
+
+    /// Returns the raw display connection underneath this connection.
+    ///
+    /// OpenHarmony has no display connection, so this returns `NativeDisplayHandle::None`. The
+    /// device's EGL display is returned by `Device::native_device_handle()`.
+    #[inline]
+    pub fn native_display(&self) -> NativeDisplayHandle {
+        NativeDisplayHandle::None
+    }
+
+    /// Returns the OpenGL API flavor that this connection supports (OpenGL or OpenGL ES).
+    #[inline]
+    pub fn gl_api(&self) -> GLApi {
+        GLApi::GLES
+    }
+
+    /// Returns the displays connected to the system.
+    ///
+    /// OpenHarmony only describes its displays through the ArkTS `display` API, so this always
+    /// returns `UnsupportedOnThisPlatform`.
+    #[inline]
+    pub fn displays(&self) -> Result<Vec<DisplayInfo>, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Returns the "best" adapter on this system.
+    ///
+    /// This is an alias for `Connection::create_hardware_adapter()`.
+    #[inline]
+    pub fn create_adapter(&self) -> Result<Adapter, Error> {
+        self.create_hardware_adapter()
+    }
+
+    /// Returns the "best" adapter on this system, preferring high-performance hardware adapters.
+    #[inline]
+    pub fn create_hardware_adapter(&self) -> Result<Adapter, Error> {
+        Ok(Adapter)
+    }
+
+    /// Returns the "best" adapter on this system, preferring low-power hardware adapters.
+    #[inline]
+    pub fn create_low_power_adapter(&self) -> Result<Adapter, Error> {
+        Ok(Adapter)
+    }
+
+    /// Returns the "best" adapter on this system, preferring software adapters.
+    #[inline]
+    pub fn create_software_adapter(&self) -> Result<Adapter, Error> {
+        Ok(Adapter)
+    }
+
+    /// Returns the adapter for the GPU that drives the given display.
+    ///
+    /// OpenHarmony devices have a single GPU, so this is the same as the default adapter.
+    #[inline]
+    pub fn create_adapter_for_display(&self, _: &DisplayInfo) -> Result<Adapter, Error> {
+        Ok(Adapter)
+    }
+
+    /// Opens the hardware device corresponding to the given adapter.
+    ///
+    /// Device handles can be cloned and sent to other threads.
+    #[inline]
+    pub(crate) fn create_device_impl(&self, _: &Adapter) -> Result<Device, Error> {
+        Device::new(self)
+    }
+
+    /// Wraps an OpenHarmony `EGLDisplay` in a device and returns it.
+    ///
+    /// The underlying `EGLDisplay` is not retained, as there is no way to do this in the EGL API.
+    /// Therefore, it is the caller's responsibility to keep it alive as long as this `Device`
+    /// remains alive.
+    #[inline]
+    pub(crate) unsafe fn create_device_from_native_device_impl(
+        &self,
+        native_device: NativeDevice,
+    ) -> Result<Device, Error> {
+        Ok(Device {
+            egl_display: native_device.0,
+            _display_owner: None,
+            lifecycle_observer: None,
+            device_event_dispatcher: DeviceEventDispatcher::default(),
+            creation_timings: CreationTimingsRecorder::default(),
+            memory_usage: Arc::new(AtomicUsize::new(0)),
+            context_ids: self.context_ids.clone(),
+            context_descriptors: ContextDescriptorCache::new(),
+        })
+    }
+
+    /// Opens the display connection corresponding to the given `winit` window.
+    #[cfg(feature = "sm-winit")]
+    #[inline]
+    pub fn from_winit_window(_: &Window) -> Result<Connection, Error> {
+        Ok(Connection {
+            context_ids: ContextIDAllocator::new()?,
+        })
+    }
+
+    /// Creates a native widget type from the given `winit` window.
+    ///
+    /// `winit` doesn't support OpenHarmony, so this always returns `IncompatibleNativeWidget`.
+    #[cfg(feature = "sm-winit")]
+    #[inline]
+    pub fn create_native_widget_from_winit_window(
+        &self,
+        _: &Window,
+    ) -> Result<NativeWidget, Error> {
+        Err(Error::IncompatibleNativeWidget)
+    }
+
+    /// Create a native widget from a raw pointer
+    ///
+    /// # Safety
+    ///
+    /// The pointer must be to a live `OHNativeWindow`, which must outlive the native widget and any
+    /// surfaces created from it.
+    pub unsafe fn create_native_widget_from_ptr(
+        &self,
+        raw: *mut c_void,
+        _size: Size2D<i32>,
+    ) -> NativeWidget {
+        NativeWidget {
+            native_window: raw as *mut OHNativeWindow,
+        }
+    }
+
+    /// Creates a native widget from a handle to a native window or view.
+    ///
+    /// Only `OHNativeWindow` handles are accepted.
+    ///
+    /// # Safety
+    ///
+    /// The handle must refer to a live window or view, which must outlive the native widget and any
+    /// surfaces created from it.
+    pub unsafe fn create_native_widget_from_handle(
+        &self,
+        handle: NativeWidgetHandle,
+    ) -> Result<NativeWidget, Error> {
+        match handle {
+            NativeWidgetHandle::OHNativeWindow(native_window) => Ok(NativeWidget {
+                native_window: native_window as *mut OHNativeWindow,
+            }),
+            _ => Err(Error::IncompatibleNativeWidget),
+        }
+    }
+
+    /// Create a native widget type from the given `raw_window_handle::RawWindowHandle`.
+    ///
+    /// `raw-window-handle` has no OpenHarmony window handles, so this always returns
+    /// `IncompatibleNativeWidget`. Use `create_native_widget_from_ptr()` with an `OHNativeWindow`
+    /// instead.
+    #[cfg(feature = "sm-raw-window-handle")]
+    #[inline]
+    pub fn create_native_widget_from_rwh(
+        &self,
+        _: raw_window_handle::RawWindowHandle,
+    ) -> Result<NativeWidget, Error> {
+        Err(Error::IncompatibleNativeWidget)
+    }
+}
+
+impl NativeConnection {
+    /// Creates a native connection.
+    ///
+    /// This is a no-op method present for consistency with other backends.
+    #[inline]
+    pub fn current() -> Result<NativeConnection, Error> {
+        Ok(NativeConnection)
+    }
+}
@@ -0,0 +1,172 @@
+// surfman/surfman/src/platform/ohos/device.rs
+//
+//! A handle to the device.
+
+use super::connection::Connection;
+use super::context::ContextDescriptor;
+use crate::context::{ContextDescriptorCache, ContextIDAllocator};
+use crate::device_events::DeviceEventDispatcher;
+use crate::egl;
+use crate::egl::types::EGLDisplay;
+use crate::platform::generic::egl::device::{OwnedEGLDisplay, EGL_FUNCTIONS};
+use crate::timings::{CreationTimings, CreationTimingsRecorder};
+use crate::{DeviceEventHandler, Error, GLApi, LifecycleObserver, NativeDeviceHandle};
+
+use std::os::raw::c_void;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Represents a hardware display adapter that can be used for rendering (including the CPU).
+///
+/// Adapters can be sent between threads. To render with an adapter, open a `Device`.
+#[derive(Clone, Debug)]
+pub struct Adapter;
+
+/// A handle to a device.
+///
+/// Devices contain most of the relevant surface management methods. Clones share the device's
+/// EGL display and can be sent to other threads, so each rendering thread can own a handle.
+#[derive(Clone)]
+pub struct Device {
+    pub(crate) egl_display: EGLDisplay,
+    // Present if the device initialized the display and must terminate it.
+    pub(crate) _display_owner: Option<Arc<OwnedEGLDisplay>>,
+    pub(crate) lifecycle_observer: Option<Arc<dyn LifecycleObserver>>,
+    pub(crate) device_event_dispatcher: DeviceEventDispatcher,
+    pub(crate) creation_timings: CreationTimingsRecorder,
+    pub(crate) memory_usage: Arc<AtomicUsize>,
+    pub(crate) context_ids: ContextIDAllocator,
+    pub(crate) context_descriptors: ContextDescriptorCache<ContextDescriptor>,
+}
+
+/// Wrapper for an `EGLDisplay`.
+#[derive(Clone, Copy)]
+pub struct NativeDevice(pub EGLDisplay);
+
+// EGL displays are process-wide, and EGL is thread-safe.
+unsafe impl Send for Device {}
+
+impl NativeDevice {
+    /// Returns the current EGL display.
+    ///
+    /// If there is no current EGL display, `egl::NO_DISPLAY` is returned.
+    pub fn current() -> NativeDevice {
+        EGL_FUNCTIONS.with(|egl| unsafe { NativeDevice(egl.GetCurrentDisplay()) })
+    }
+}
+
+impl Device {
+    #[inline]
+    pub(crate) fn new(connection: &Connection) -> Result<Device, Error> {
+        EGL_FUNCTIONS.with(|egl| {
+            unsafe {
+                let egl_display = egl.GetDisplay(egl::DEFAULT_DISPLAY);
+                assert_ne!(egl_display, egl::NO_DISPLAY);
+
+                // I don't think this should ever fail.
+                let (mut major_version, mut minor_version) = (0, 0);
+                let result = egl.Initialize(egl_display, &mut major_version, &mut minor_version);
+                assert_ne!(result, egl::FALSE);
+
+                Ok(Device {
+                    egl_display,
+                    _display_owner: Some(Arc::new(OwnedEGLDisplay(egl_display))),
+                    lifecycle_observer: None,
+                    device_event_dispatcher: DeviceEventDispatcher::default(),
+                    creation_timings: CreationTimingsRecorder::default(),
+                    memory_usage: Arc::new(AtomicUsize::new(0)),
+                    context_ids: connection.context_ids.clone(),
+                    context_descriptors: ContextDescriptorCache::new(),
+                })
+            }
+        })
+    }
+
+    /// Returns the EGL display corresponding to this device.
+    #[inline]
+    pub fn native_device(&self) -> NativeDevice {
+        NativeDevice(self.egl_display)
+    }
+
+    /// Returns the raw display underneath this device: its EGL display.
+    #[inline]
+    pub fn native_device_handle(&self) -> NativeDeviceHandle {
+        NativeDeviceHandle::EGLDisplay(self.egl_display as *mut c_void)
+    }
+
+    /// Returns the display server connection that this device was created with.
+    #[inline]
+    pub fn connection(&self) -> Connection {
+        Connection {
+            context_ids: self.context_ids.clone(),
+        }
+    }
+
+    /// Returns the adapter that this device was created with.
+    #[inline]
+    pub fn adapter(&self) -> Adapter {
+        Adapter
+    }
+
+    /// Returns the OpenGL API flavor that this device supports (OpenGL or OpenGL ES).
+    #[inline]
+    pub fn gl_api(&self) -> GLApi {
+        GLApi::GLES
+    }
+
+    /// Installs an observer that is notified when contexts and surfaces of this device are
+    /// created, destroyed, resized, or presented, replacing any previous one.
+    #[inline]
+    pub fn set_lifecycle_observer(&mut self, observer: Option<Arc<dyn LifecycleObserver>>) {
+        self.lifecycle_observer = observer;
+    }
+
+    /// Returns the lifecycle observer installed on this device, if any.
+    #[inline]
+    pub fn lifecycle_observer(&self) -> Option<Arc<dyn LifecycleObserver>> {
+        self.lifecycle_observer.clone()
+    }
+
+    /// Installs a handler that is told when the GPU of this device is removed or reset, or the
+    /// displays are reconfigured, replacing any previous one.
+    ///
+    /// This backend can't list displays, so only resets are reported.
+    #[inline]
+    pub fn set_device_event_handler(&mut self, handler: Option<DeviceEventHandler>) {
+        self.device_event_dispatcher.set_handler(handler, || None);
+    }
+
+    /// Returns the device event handler installed on this device, if any.
+    #[inline]
+    pub fn device_event_handler(&self) -> Option<DeviceEventHandler> {
+        self.device_event_dispatcher.handler()
+    }
+
+    #[inline]
+    pub(crate) fn device_event_dispatcher(&self) -> &DeviceEventDispatcher {
+        &self.device_event_dispatcher
+    }
+
+    /// Returns the GPU memory, in bytes, held by the surfaces of this device.
+    #[inline]
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub(crate) fn memory_usage_counter(&self) -> &AtomicUsize {
+        &self.memory_usage
+    }
+
+    /// Returns how long opening this device, and creating its context descriptors and contexts,
+    /// took.
+    #[inline]
+    pub fn creation_timings(&self) -> CreationTimings {
+        self.creation_timings.get()
+    }
+
+    #[inline]
+    pub(crate) fn creation_timings_recorder(&self) -> &CreationTimingsRecorder {
+        &self.creation_timings
+    }
+}
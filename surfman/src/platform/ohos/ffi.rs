@@ -0,0 +1,64 @@
+// surfman/surfman/src/platform/ohos/ffi.rs
+
+#![allow(non_camel_case_types)]
+
+use crate::egl::types::EGLenum;
+
+use std::os::raw::c_int;
+
+pub(crate) const NATIVEBUFFER_PIXEL_FMT_RGBA_8888: i32 = 12;
+
+pub(crate) const NATIVEBUFFER_USAGE_MEM_DMA: i32 = 1 << 3;
+pub(crate) const NATIVEBUFFER_USAGE_HW_RENDER: i32 = 1 << 8;
+pub(crate) const NATIVEBUFFER_USAGE_HW_TEXTURE: i32 = 1 << 9;
+
+// The `code` passed to `OH_NativeWindow_NativeWindowHandleOpt()` to query the window size.
+pub(crate) const GET_BUFFER_GEOMETRY: c_int = 1;
+
+// From `EGL_OHOS_image_native_buffer`.
+pub(crate) const EGL_NATIVE_BUFFER_OHOS: EGLenum = 0x34e1;
+
+#[repr(C)]
+pub struct OH_NativeBuffer {
+    opaque: i32,
+}
+
+#[repr(C)]
+pub(crate) struct OH_NativeBuffer_Config {
+    pub(crate) width: i32,
+    pub(crate) height: i32,
+    pub(crate) format: i32,
+    pub(crate) usage: i32,
+    pub(crate) stride: i32,
+}
+
+#[repr(C)]
+pub struct OHNativeWindow {
+    opaque: i32,
+}
+
+#[repr(C)]
+pub struct OHNativeWindowBuffer {
+    opaque: i32,
+}
+
+#[link(name = "native_buffer")]
+extern "C" {
+    pub(crate) fn OH_NativeBuffer_Alloc(
+        config: *const OH_NativeBuffer_Config,
+    ) -> *mut OH_NativeBuffer;
+    pub(crate) fn OH_NativeBuffer_Unreference(buffer: *mut OH_NativeBuffer) -> i32;
+}
+
+#[link(name = "native_window")]
+extern "C" {
+    pub(crate) fn OH_NativeWindow_NativeWindowHandleOpt(
+        window: *mut OHNativeWindow,
+        code: c_int,
+        ...
+    ) -> i32;
+    pub(crate) fn OH_NativeWindow_CreateNativeWindowBufferFromNativeBuffer(
+        nativeBuffer: *mut OH_NativeBuffer,
+    ) -> *mut OHNativeWindowBuffer;
+    pub(crate) fn OH_NativeWindow_DestroyNativeWindowBuffer(buffer: *mut OHNativeWindowBuffer);
+}
@@ -0,0 +1,973 @@
+// surfman/surfman/src/platform/ohos/surface.rs
+//
+//! Surface management for OpenHarmony using `OH_NativeBuffer` and EGL.
+
+use super::context::{Context, GL_FUNCTIONS};
+use super::device::Device;
+use super::ffi::NATIVEBUFFER_USAGE_MEM_DMA;
+use super::ffi::{OHNativeWindow, OH_NativeBuffer, OH_NativeBuffer_Config};
+use super::ffi::{OH_NativeBuffer_Alloc, OH_NativeBuffer_Unreference};
+use super::ffi::{OH_NativeWindow_CreateNativeWindowBufferFromNativeBuffer, GET_BUFFER_GEOMETRY};
+use super::ffi::{
+    OH_NativeWindow_DestroyNativeWindowBuffer, OH_NativeWindow_NativeWindowHandleOpt,
+};
+use super::ffi::{EGL_NATIVE_BUFFER_OHOS, NATIVEBUFFER_PIXEL_FMT_RGBA_8888};
+use super::ffi::{NATIVEBUFFER_USAGE_HW_RENDER, NATIVEBUFFER_USAGE_HW_TEXTURE};
+use crate::context::ContextID;
+use crate::egl;
+use crate::egl::types::{EGLSurface, EGLint};
+use crate::gl;
+use crate::gl::types::{GLenum, GLuint};
+use crate::gl_utils;
+#[cfg(feature = "sm-opencl")]
+use crate::opencl::SharedEGLImage;
+use crate::platform::generic;
+use crate::platform::generic::egl::device::EGL_FUNCTIONS;
+use crate::platform::generic::egl::error::ToWindowingApiError;
+use crate::platform::generic::egl::ffi::EGLImageKHR;
+use crate::platform::generic::egl::ffi::EGL_EXTENSION_FUNCTIONS;
+use crate::platform::generic::egl::ffi::EGL_IMAGE_PRESERVED_KHR;
+use crate::platform::generic::egl::ffi::EGL_NO_IMAGE_KHR;
+use crate::renderbuffers::Renderbuffers;
+use crate::surface::{estimate_memory_usage, SurfaceLayoutRequirements};
+use crate::{
+    Colorspace, CreateSurfacesResult, Error, PresentationStatistics, SurfaceAccess,
+    SurfaceAlphaMode, SurfaceFormat, SurfaceID, SurfaceInfo, SurfaceOrigin, SurfaceTransform,
+    SurfaceType, SurfaceUsage, SurfaceVisibility, WidgetOutputInfo, WindowingApiError,
+};
+
+use euclid::default::{Rect, Size2D};
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+use std::mem;
+use std::os::raw::c_void;
+use std::ptr;
+use std::thread;
+
+pub use crate::platform::generic::egl::context::ContextDescriptor;
+
+const SURFACE_GL_TEXTURE_TARGET: GLenum = gl::TEXTURE_2D;
+
+/// Represents a hardware buffer of pixels that can be rendered to via the CPU or GPU and either
+/// displayed in a native widget or bound to a texture for reading.
+///
+/// Surfaces come in two varieties: generic and widget surfaces. Generic surfaces can be bound to a
+/// texture but cannot be displayed in a widget (without using other APIs such as Core Animation,
+/// DirectComposition, or XPRESENT). Widget surfaces are the opposite: they can be displayed in a
+/// widget but not bound to a texture.
+///
+/// Surfaces are specific to a given context and cannot be rendered to from any context other than
+/// the one they were created with. However, they can be *read* from any context on any thread (as
+/// long as that context shares the same adapter and connection), by wrapping them in a
+/// `SurfaceTexture`.
+///
+/// Depending on the platform, each surface may be internally double-buffered.
+///
+/// Surfaces must be destroyed with the `destroy_surface()` method, or a panic will occur.
+pub struct Surface {
+    pub(crate) id: SurfaceID,
+    pub(crate) context_id: ContextID,
+    pub(crate) size: Size2D<i32>,
+    pub(crate) objects: SurfaceObjects,
+    // The order of the channels in memory. See `SurfaceInfo::format`.
+    pub(crate) format: SurfaceFormat,
+    // Which way up the contents are. See `SurfaceInfo::origin`.
+    pub(crate) origin: SurfaceOrigin,
+    // How the alpha channel is interpreted. See `SurfaceInfo::alpha_mode`.
+    pub(crate) alpha_mode: SurfaceAlphaMode,
+    // The color space of the contents. See `SurfaceInfo::colorspace`.
+    pub(crate) colorspace: Colorspace,
+    // The number of samples per pixel. See `SurfaceInfo::samples`.
+    pub(crate) samples: u32,
+    pub(crate) destroyed: bool,
+}
+
+/// Represents an OpenGL texture that wraps a surface.
+///
+/// Reading from the associated OpenGL texture reads from the surface. It is undefined behavior to
+/// write to such a texture (e.g. by binding it to a framebuffer and rendering to that
+/// framebuffer).
+///
+/// Surface textures are local to a context, but that context does not have to be the same context
+/// as that associated with the underlying surface. The texture must be destroyed with the
+/// `destroy_surface_texture()` method, or a panic will occur.
+pub struct SurfaceTexture {
+    pub(crate) surface: Surface,
+    pub(crate) local_egl_image: EGLImageKHR,
+    pub(crate) texture_object: GLuint,
+    pub(crate) phantom: PhantomData<*const ()>,
+}
+
+pub(crate) enum SurfaceObjects {
+    NativeBuffer {
+        native_buffer: *mut OH_NativeBuffer,
+        egl_image: EGLImageKHR,
+        framebuffer_object: GLuint,
+        texture_object: GLuint,
+        renderbuffers: Renderbuffers,
+    },
+    Window {
+        egl_surface: EGLSurface,
+    },
+}
+
+unsafe impl Send for Surface {}
+
+impl Debug for Surface {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "Surface({:x})", self.id().0)
+    }
+}
+
+impl Drop for Surface {
+    fn drop(&mut self) {
+        if !self.destroyed && !thread::panicking() {
+            panic!("Should have destroyed the surface first with `destroy_surface()`!")
+        }
+    }
+}
+
+impl Debug for SurfaceTexture {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "SurfaceTexture({:?})", self.surface)
+    }
+}
+
+/// An OpenHarmony native window.
+pub struct NativeWidget {
+    pub(crate) native_window: *mut OHNativeWindow,
+}
+
+impl Device {
+    /// Creates either a generic or a widget surface, depending on the supplied surface type.
+    ///
+    /// Only the given context may ever render to the surface, but generic surfaces can be wrapped
+    /// up in a `SurfaceTexture` for reading by other contexts.
+    ///
+    /// Opaque widget surfaces ask the compositor to ignore their alpha channel where
+    /// `EGL_EXT_present_opaque` is available. Widget surfaces with straight alpha are unsupported.
+    pub(crate) fn create_surface_impl(
+        &mut self,
+        context: &Context,
+        _: SurfaceAccess,
+        _: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
+        surface_type: SurfaceType<NativeWidget>,
+    ) -> Result<Surface, Error> {
+        let mut surface = match surface_type {
+            SurfaceType::Generic { size } => self.create_generic_surface(context, &size)?,
+            // The compositor only blends premultiplied contents.
+            SurfaceType::Widget { .. } if surface_alpha_mode == SurfaceAlphaMode::Straight => {
+                return Err(Error::UnsupportedOnThisPlatform)
+            }
+            SurfaceType::Widget { native_widget } => unsafe {
+                self.create_window_surface(
+                    context,
+                    native_widget.native_window,
+                    surface_alpha_mode,
+                )?
+            },
+        };
+        surface.alpha_mode = surface_alpha_mode;
+        Ok(surface)
+    }
+
+    /// Creates `count` generic surfaces of the given size.
+    ///
+    /// The context is made current once for the whole batch. If one of the surfaces fails to be
+    /// created, the error is returned along with the surfaces that were created before it.
+    pub(crate) fn create_surfaces_impl(
+        &mut self,
+        context: &Context,
+        _: SurfaceAccess,
+        _: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
+        size: &Size2D<i32>,
+        count: usize,
+    ) -> CreateSurfacesResult<Surface> {
+        let _guard = match self.temporarily_make_context_current(context) {
+            Ok(guard) => guard,
+            Err(err) => return Err((err, vec![])),
+        };
+
+        let mut surfaces = Vec::with_capacity(count);
+        for _ in 0..count {
+            match self.create_generic_surface(context, size) {
+                Ok(mut surface) => {
+                    surface.alpha_mode = surface_alpha_mode;
+                    surfaces.push(surface)
+                }
+                Err(err) => return Err((err, surfaces)),
+            }
+        }
+        Ok(surfaces)
+    }
+
+    fn create_generic_surface(
+        &mut self,
+        context: &Context,
+        size: &Size2D<i32>,
+    ) -> Result<Surface, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+
+        GL_FUNCTIONS.with(|gl| {
+            unsafe {
+                // Create a native buffer.
+                let native_buffer_config = OH_NativeBuffer_Config {
+                    width: size.width,
+                    height: size.height,
+                    format: NATIVEBUFFER_PIXEL_FMT_RGBA_8888,
+                    usage: NATIVEBUFFER_USAGE_MEM_DMA
+                        | NATIVEBUFFER_USAGE_HW_RENDER
+                        | NATIVEBUFFER_USAGE_HW_TEXTURE,
+                    stride: 0,
+                };
+                let native_buffer = OH_NativeBuffer_Alloc(&native_buffer_config);
+                if native_buffer.is_null() {
+                    return Err(Error::SurfaceCreationFailed(WindowingApiError::Failed));
+                }
+
+                // Create an EGL image, and bind it to a texture.
+                let egl_image = match self.create_egl_image(context, native_buffer) {
+                    Ok(egl_image) => egl_image,
+                    Err(err) => {
+                        OH_NativeBuffer_Unreference(native_buffer);
+                        return Err(err);
+                    }
+                };
+
+                // Initialize and bind the image to the texture.
+                let texture_object =
+                    generic::egl::surface::bind_egl_image_to_gl_texture(gl, egl_image);
+
+                // Create the framebuffer, and bind the texture to it. The caller's bindings are put
+                // back afterward.
+                let mut bindings = gl_utils::BindingsGuard::new(gl);
+                let framebuffer_object =
+                    bindings.create_and_bind_framebuffer(SURFACE_GL_TEXTURE_TARGET, texture_object);
+
+                // Bind renderbuffers as appropriate.
+                let context_descriptor = self.context_descriptor(context);
+                let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+                let renderbuffers = Renderbuffers::new(gl, size, &context_attributes);
+                renderbuffers.bind_to_current_framebuffer(gl);
+
+                debug_assert_eq!(
+                    gl.CheckFramebufferStatus(gl::FRAMEBUFFER),
+                    gl::FRAMEBUFFER_COMPLETE
+                );
+
+                Ok(Surface {
+                    id: SurfaceID::next(),
+                    size: *size,
+                    context_id: context.id,
+                    objects: SurfaceObjects::NativeBuffer {
+                        native_buffer,
+                        egl_image,
+                        framebuffer_object,
+                        texture_object,
+                        renderbuffers,
+                    },
+                    format: SurfaceFormat::RGBA8,
+                    origin: SurfaceOrigin::BottomLeft,
+                    alpha_mode: SurfaceAlphaMode::Premultiplied,
+                    colorspace: Colorspace::SRGB,
+                    samples: 1,
+                    destroyed: false,
+                })
+            }
+        })
+    }
+
+    unsafe fn create_window_surface(
+        &mut self,
+        context: &Context,
+        native_window: *mut OHNativeWindow,
+        alpha_mode: SurfaceAlphaMode,
+    ) -> Result<Surface, Error> {
+        let (mut width, mut height) = (0, 0);
+        let result = OH_NativeWindow_NativeWindowHandleOpt(
+            native_window,
+            GET_BUFFER_GEOMETRY,
+            &mut height as *mut i32,
+            &mut width as *mut i32,
+        );
+        if result != 0 {
+            return Err(Error::SurfaceCreationFailed(WindowingApiError::Failed));
+        }
+
+        let attributes =
+            generic::egl::surface::window_surface_attributes(self.egl_display, alpha_mode);
+        EGL_FUNCTIONS.with(|egl| {
+            let egl_surface = egl.CreateWindowSurface(
+                self.egl_display,
+                self.context_to_egl_config(context),
+                native_window as *const c_void,
+                attributes.as_ptr(),
+            );
+            assert_ne!(egl_surface, egl::NO_SURFACE);
+            let samples = generic::egl::context::get_config_samples(
+                self.egl_display,
+                self.context_to_egl_config(context),
+            );
+
+            Ok(Surface {
+                id: SurfaceID::next(),
+                context_id: context.id,
+                size: Size2D::new(width, height),
+                objects: SurfaceObjects::Window { egl_surface },
+                format: SurfaceFormat::RGBA8,
+                origin: SurfaceOrigin::BottomLeft,
+                alpha_mode,
+                colorspace: Colorspace::SRGB,
+                samples,
+                destroyed: false,
+            })
+        })
+    }
+
+    /// Creates a new generic surface with a copy of the contents of the given one, stored with
+    /// its color channels in the given order.
+    ///
+    /// Surfaces on this backend are RGBA in memory. Calling this method on a widget surface
+    /// returns a `WidgetAttached` error.
+    pub(crate) fn convert_surface_format_impl(
+        &mut self,
+        context: &mut Context,
+        surface: &Surface,
+        format: SurfaceFormat,
+    ) -> Result<Surface, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        let read_framebuffer_object = match surface.objects {
+            SurfaceObjects::NativeBuffer {
+                framebuffer_object, ..
+            } => framebuffer_object,
+            SurfaceObjects::Window { .. } => return Err(Error::WidgetAttached),
+        };
+
+        let mut new_surface = self.create_generic_surface(context, &surface.size)?;
+        new_surface.format = format;
+        new_surface.origin = surface.origin;
+        new_surface.alpha_mode = surface.alpha_mode;
+        new_surface.colorspace = surface.colorspace;
+        let draw_framebuffer_object = match new_surface.objects {
+            SurfaceObjects::NativeBuffer {
+                framebuffer_object, ..
+            } => framebuffer_object,
+            SurfaceObjects::Window { .. } => unreachable!(),
+        };
+
+        match self.temporarily_make_context_current(context) {
+            Ok(_guard) => {
+                GL_FUNCTIONS.with(|gl| {
+                    gl_utils::copy_framebuffer(
+                        gl,
+                        read_framebuffer_object,
+                        draw_framebuffer_object,
+                        &surface.size,
+                        surface.format != format,
+                    )
+                });
+                Ok(new_surface)
+            }
+            Err(err) => {
+                self.destroy_surface_impl(context, &mut new_surface)?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Wraps an existing OpenGL texture of the given context in a new generic surface.
+    ///
+    /// Generic surfaces on this backend are native buffers, which can't be made from a texture, so this always returns an `UnsupportedOnThisPlatform` error.
+    #[inline]
+    pub(crate) fn create_surface_from_texture_impl(
+        &mut self,
+        _: &mut Context,
+        _: &Size2D<i32>,
+        _: GLuint,
+    ) -> Result<Surface, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Creates a surface texture from an existing generic surface for use with the given context.
+    ///
+    /// The surface texture is local to the supplied context and takes ownership of the surface.
+    /// Destroying the surface texture allows you to retrieve the surface again.
+    ///
+    /// *The supplied context does not have to be the same context that the surface is associated
+    /// with.* This allows you to render to a surface in one context and sample from that surface
+    /// in another context.
+    ///
+    /// Calling this method on a widget surface returns a `WidgetAttached` error.
+    pub fn create_surface_texture(
+        &self,
+        context: &mut Context,
+        surface: Surface,
+    ) -> Result<SurfaceTexture, (Error, Surface)> {
+        unsafe {
+            match surface.objects {
+                SurfaceObjects::Window { .. } => return Err((Error::WidgetAttached, surface)),
+                SurfaceObjects::NativeBuffer { native_buffer, .. } => GL_FUNCTIONS.with(|gl| {
+                    let _guard = match self.temporarily_make_context_current(context) {
+                        Ok(guard) => guard,
+                        Err(err) => return Err((err, surface)),
+                    };
+
+                    let local_egl_image = match self.create_egl_image(context, native_buffer) {
+                        Ok(egl_image) => egl_image,
+                        Err(err) => return Err((err, surface)),
+                    };
+                    let texture_object =
+                        generic::egl::surface::bind_egl_image_to_gl_texture(gl, local_egl_image);
+                    Ok(SurfaceTexture {
+                        surface,
+                        local_egl_image,
+                        texture_object,
+                        phantom: PhantomData,
+                    })
+                }),
+            }
+        }
+    }
+
+    /// Displays the contents of a widget surface on screen.
+    ///
+    /// Widget surfaces are internally double-buffered, so changes to them don't show up in their
+    /// associated widgets until this method is called.
+    ///
+    /// The supplied context must match the context the surface was created with, or an
+    /// `IncompatibleSurface` error is returned.
+    #[inline]
+    pub(crate) fn present_surface_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        self.present_surface_with_damage_impl(context, surface, &[])
+    }
+
+    /// Displays the contents of a widget surface on screen, hinting that only the given
+    /// rectangles have changed since the last present.
+    ///
+    /// The rectangles are in device pixels, with the origin at the lower left corner of the
+    /// surface. An empty slice means that the whole surface changed. The damage is passed to
+    /// `eglSwapBuffersWithDamageKHR()`, which the render service uses to limit composition.
+    pub(crate) fn present_surface_with_damage_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        match surface.objects {
+            SurfaceObjects::Window { egl_surface } => unsafe {
+                generic::egl::surface::swap_buffers_with_damage(
+                    self.egl_display,
+                    egl_surface,
+                    damage,
+                );
+                Ok(())
+            },
+            SurfaceObjects::NativeBuffer { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Displays the `source` rectangle of a widget surface in the `destination` rectangle of its
+    /// widget, scaling it to fit.
+    ///
+    /// The rectangles are in device pixels, with the origin at the lower left corner. The back
+    /// buffer is rescaled in place before `eglSwapBuffers()`.
+    pub(crate) fn present_surface_with_viewport_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        source: &Rect<i32>,
+        destination: &Rect<i32>,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        match surface.objects {
+            SurfaceObjects::Window { egl_surface } => GL_FUNCTIONS.with(|gl| unsafe {
+                generic::egl::surface::swap_buffers_with_viewport(
+                    gl,
+                    self.egl_display,
+                    egl_surface,
+                    context.egl_context,
+                    source,
+                    destination,
+                )
+            }),
+            SurfaceObjects::NativeBuffer { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Declares which parts of a widget surface will be redrawn before it is next presented.
+    ///
+    /// This must be called while the surface is bound to the current context, before any
+    /// rendering to the frame. With `EGL_KHR_partial_update`, the contents of the surface outside
+    /// the region are undefined afterward, which spares tiled GPUs from loading them. An empty
+    /// slice means that the whole surface will be redrawn.
+    pub fn set_surface_damage_region(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        region: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        match surface.objects {
+            SurfaceObjects::Window { egl_surface } => unsafe {
+                generic::egl::surface::set_damage_region(self.egl_display, egl_surface, region)
+            },
+            SurfaceObjects::NativeBuffer { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Returns the number of presents ago that the current back buffer of a widget surface was
+    /// displayed, or 0 if its contents are undefined.
+    ///
+    /// The surface must be bound to the current context.
+    pub fn surface_buffer_age(&self, context: &Context, surface: &Surface) -> Result<u32, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        match surface.objects {
+            SurfaceObjects::Window { egl_surface } => unsafe {
+                Ok(generic::egl::surface::query_buffer_age(
+                    self.egl_display,
+                    egl_surface,
+                ))
+            },
+            SurfaceObjects::NativeBuffer { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Sets whether presenting a widget surface preserves its contents.
+    ///
+    /// Widget surfaces are created without preservation, so their contents are undefined after
+    /// `present_surface()`. With it, applications that only repaint the dirty regions of each
+    /// frame can draw over the previous one, at the cost of a copy per present on some hardware.
+    ///
+    /// If the config of the context doesn't allow preservation, this returns
+    /// `Error::UnsupportedOnThisPlatform`.
+    pub fn set_surface_preserve_buffer(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        preserve: bool,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        match surface.objects {
+            SurfaceObjects::Window { egl_surface } => unsafe {
+                generic::egl::surface::set_swap_behavior(self.egl_display, egl_surface, preserve)
+            },
+            SurfaceObjects::NativeBuffer { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Sets which way up the contents of a surface are.
+    ///
+    /// Widget surfaces can only be bottom-left, and setting `SurfaceOrigin::TopLeft` on them
+    /// returns `Error::UnsupportedOnThisPlatform`.
+    pub fn set_surface_origin(
+        &self,
+        surface: &mut Surface,
+        origin: SurfaceOrigin,
+    ) -> Result<(), Error> {
+        if let SurfaceObjects::Window { .. } = surface.objects {
+            if origin == SurfaceOrigin::TopLeft {
+                return Err(Error::UnsupportedOnThisPlatform);
+            }
+        }
+        surface.origin = origin;
+        Ok(())
+    }
+
+    /// Sets the color space that the contents of a surface are in.
+    ///
+    /// Widget surfaces can only be sRGB, and setting any other color space on them returns
+    /// `Error::UnsupportedOnThisPlatform`.
+    pub fn set_surface_colorspace(
+        &self,
+        surface: &mut Surface,
+        colorspace: Colorspace,
+    ) -> Result<(), Error> {
+        if let SurfaceObjects::Window { .. } = surface.objects {
+            if colorspace != Colorspace::SRGB {
+                return Err(Error::UnsupportedOnThisPlatform);
+            }
+        }
+        surface.colorspace = colorspace;
+        Ok(())
+    }
+
+    /// Resizes a widget surface.
+    pub(crate) fn resize_surface_impl(
+        &self,
+        _context: &Context,
+        surface: &mut Surface,
+        size: Size2D<i32>,
+    ) -> Result<(), Error> {
+        surface.size = size;
+        Ok(())
+    }
+
+    // Wraps the native buffer in an EGL image.
+    //
+    // The EGL image holds its own reference to the buffer, so the intermediate window buffer can
+    // be destroyed right away.
+    unsafe fn create_egl_image(
+        &self,
+        _: &Context,
+        native_buffer: *mut OH_NativeBuffer,
+    ) -> Result<EGLImageKHR, Error> {
+        let native_window_buffer =
+            OH_NativeWindow_CreateNativeWindowBufferFromNativeBuffer(native_buffer);
+        if native_window_buffer.is_null() {
+            return Err(Error::SurfaceCreationFailed(WindowingApiError::Failed));
+        }
+
+        // Create the EGL image.
+        let egl_image_attributes = [
+            EGL_IMAGE_PRESERVED_KHR as EGLint,
+            egl::TRUE as EGLint,
+            egl::NONE as EGLint,
+            0,
+        ];
+        let egl_image = (EGL_EXTENSION_FUNCTIONS.CreateImageKHR)(
+            self.egl_display,
+            egl::NO_CONTEXT,
+            EGL_NATIVE_BUFFER_OHOS,
+            native_window_buffer as *mut _,
+            egl_image_attributes.as_ptr(),
+        );
+        OH_NativeWindow_DestroyNativeWindowBuffer(native_window_buffer);
+        if egl_image == EGL_NO_IMAGE_KHR {
+            let err = EGL_FUNCTIONS.with(|egl| egl.GetError().to_windowing_api_error());
+            return Err(Error::SurfaceCreationFailed(err));
+        }
+        Ok(egl_image)
+    }
+
+    /// Destroys a surface.
+    ///
+    /// The supplied context must be the context the surface is associated with, or this returns
+    /// an `IncompatibleSurface` error.
+    ///
+    /// You must explicitly call this method to dispose of a surface. Otherwise, a panic occurs in
+    /// the `drop` method.
+    pub(crate) fn destroy_surface_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        unsafe {
+            match surface.objects {
+                SurfaceObjects::NativeBuffer {
+                    ref mut native_buffer,
+                    ref mut egl_image,
+                    ref mut framebuffer_object,
+                    ref mut texture_object,
+                    ref mut renderbuffers,
+                } => {
+                    GL_FUNCTIONS.with(|gl| {
+                        gl_utils::destroy_framebuffer(gl, *framebuffer_object);
+                        *framebuffer_object = 0;
+
+                        renderbuffers.destroy(gl);
+
+                        gl.DeleteTextures(1, texture_object);
+                        *texture_object = 0;
+
+                        let egl_display = self.egl_display;
+                        let result =
+                            (EGL_EXTENSION_FUNCTIONS.DestroyImageKHR)(egl_display, *egl_image);
+                        assert_ne!(result, egl::FALSE);
+                        *egl_image = EGL_NO_IMAGE_KHR;
+
+                        OH_NativeBuffer_Unreference(*native_buffer);
+                        *native_buffer = ptr::null_mut();
+                    });
+                }
+                SurfaceObjects::Window {
+                    ref mut egl_surface,
+                } => EGL_FUNCTIONS.with(|egl| {
+                    egl.DestroySurface(self.egl_display, *egl_surface);
+                    *egl_surface = egl::NO_SURFACE;
+                }),
+            }
+        }
+
+        surface.destroyed = true;
+        Ok(())
+    }
+
+    /// Destroys a surface texture and returns the underlying surface.
+    ///
+    /// The supplied context must be the same context the surface texture was created with, or an
+    /// `IncompatibleSurfaceTexture` error is returned.
+    ///
+    /// All surface textures must be explicitly destroyed with this function, or a panic will
+    /// occur.
+    pub fn destroy_surface_texture(
+        &self,
+        context: &mut Context,
+        mut surface_texture: SurfaceTexture,
+    ) -> Result<Surface, (Error, SurfaceTexture)> {
+        let _guard = self.temporarily_make_context_current(context);
+        GL_FUNCTIONS.with(|gl| {
+            unsafe {
+                gl.DeleteTextures(1, &surface_texture.texture_object);
+                surface_texture.texture_object = 0;
+
+                let egl_display = self.egl_display;
+                let result = (EGL_EXTENSION_FUNCTIONS.DestroyImageKHR)(
+                    egl_display,
+                    surface_texture.local_egl_image,
+                );
+                assert_ne!(result, egl::FALSE);
+                surface_texture.local_egl_image = EGL_NO_IMAGE_KHR;
+            }
+
+            Ok(surface_texture.surface)
+        })
+    }
+
+    /// Destroys the native resources of a surface given up with `surface_into_destroy_token()`.
+    ///
+    /// Unlike `destroy_surface()`, this doesn't need the context that the surface belonged to.
+    pub fn destroy_token(&self, token: SurfaceDestroyToken) -> Result<(), Error> {
+        unsafe {
+            match token.objects {
+                SurfaceObjects::NativeBuffer {
+                    native_buffer,
+                    egl_image,
+                    ..
+                } => {
+                    let result =
+                        (EGL_EXTENSION_FUNCTIONS.DestroyImageKHR)(self.egl_display, egl_image);
+                    assert_ne!(result, egl::FALSE);
+                    OH_NativeBuffer_Unreference(native_buffer);
+                }
+                SurfaceObjects::Window { egl_surface } => EGL_FUNCTIONS.with(|egl| {
+                    egl.DestroySurface(self.egl_display, egl_surface);
+                }),
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns a pointer to the underlying surface data for reading or writing by the CPU.
+    #[inline]
+    pub fn lock_surface_data<'s>(&self, _: &'s mut Surface) -> Result<SurfaceDataGuard<'s>, Error> {
+        // TODO(pcwalton)
+        Err(Error::Unimplemented)
+    }
+
+    /// Returns the OpenGL texture target needed to read from this surface texture.
+    ///
+    /// This will be `GL_TEXTURE_2D` or `GL_TEXTURE_RECTANGLE`, depending on platform.
+    #[inline]
+    pub fn surface_gl_texture_target(&self) -> GLenum {
+        SURFACE_GL_TEXTURE_TARGET
+    }
+
+    /// Fills in the mip chain of a surface texture from the current contents of its surface, so
+    /// that it can be sampled with mipmapping at scales well below its size.
+    ///
+    /// Surface textures on this backend sample the surface's EGL image, which only has a base
+    /// level, so this always returns an `UnsupportedOnThisPlatform` error.
+    #[inline]
+    pub fn generate_surface_mipmaps(
+        &self,
+        _: &mut Context,
+        _: &SurfaceTexture,
+    ) -> Result<(), Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Returns various information about the surface, including the framebuffer object needed to
+    /// render to this surface.
+    ///
+    /// Before rendering to a surface attached to a context, you must call `glBindFramebuffer()`
+    /// on the framebuffer object returned by this function. This framebuffer object may or not be
+    /// 0, the default framebuffer, depending on platform.
+    pub fn surface_info(&self, surface: &Surface) -> SurfaceInfo {
+        SurfaceInfo {
+            size: surface.size,
+            id: surface.id(),
+            context_id: surface.context_id,
+            framebuffer_object: match surface.objects {
+                SurfaceObjects::NativeBuffer {
+                    framebuffer_object, ..
+                } => framebuffer_object,
+                SurfaceObjects::Window { .. } => 0,
+            },
+            scale_factor: 1.0,
+            transform: SurfaceTransform::Identity,
+            origin: surface.origin,
+            format: surface.format,
+            alpha_mode: surface.alpha_mode,
+            colorspace: surface.colorspace,
+            samples: surface.samples,
+            bound: false,
+        }
+    }
+
+    /// Returns the GPU memory, in bytes, held by the given surface.
+    ///
+    /// This backend has no way to query the size of the underlying allocation, so this is an
+    /// estimate that counts four bytes per pixel of color storage.
+    #[inline]
+    pub fn surface_memory_usage(&self, surface: &Surface) -> usize {
+        estimate_memory_usage(&self.surface_info(surface).size)
+    }
+
+    /// Returns `Error::UnsupportedOnThisPlatform`, as native buffers have no modification counter.
+    #[inline]
+    pub fn surface_seed(&self, _: &Surface) -> Result<u32, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Returns the stride, row alignment, and total size of the memory that a generic surface of
+    /// the given size would be allocated with, without creating one.
+    ///
+    /// Native buffers aren't mapped for CPU access on this backend, so this is the tightly packed
+    /// layout that readbacks use.
+    #[inline]
+    pub fn surface_layout_requirements(
+        &self,
+        size: &Size2D<i32>,
+        _: SurfaceFormat,
+        _: SurfaceAccess,
+    ) -> Result<SurfaceLayoutRequirements, Error> {
+        Ok(SurfaceLayoutRequirements::packed(size))
+    }
+
+    /// Returns timing information about the most recently displayed frame of a widget surface.
+    ///
+    /// Presentation timing isn't available on this backend, so this always returns `None`.
+    #[inline]
+    pub fn surface_presentation_statistics(&self, _: &Surface) -> Option<PresentationStatistics> {
+        None
+    }
+
+    /// Returns whether a widget surface can currently be seen on screen.
+    ///
+    /// OpenHarmony doesn't report whether a native window is visible, so this always returns
+    /// `Unknown`.
+    #[inline]
+    pub fn surface_visibility(&self, _: &Surface) -> SurfaceVisibility {
+        SurfaceVisibility::Unknown
+    }
+
+    /// Returns what a widget surface is shown on.
+    ///
+    /// OpenHarmony only describes its displays through the ArkTS `display` API, so widget surfaces
+    /// are always reported as being on an unknown 8-bit sRGB display without HDR.
+    pub fn widget_output_info(&self, surface: &Surface) -> Result<WidgetOutputInfo, Error> {
+        match surface.objects {
+            SurfaceObjects::Window { .. } => Ok(WidgetOutputInfo::unknown(None)),
+            SurfaceObjects::NativeBuffer { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Returns the OpenGL texture object containing the contents of this surface.
+    ///
+    /// It is only legal to read from, not write to, this texture object.
+    #[inline]
+    pub fn surface_texture_object(&self, surface_texture: &SurfaceTexture) -> GLuint {
+        surface_texture.texture_object
+    }
+}
+
+impl NativeWidget {
+    /// Creates a native widget type from an `OHNativeWindow`.
+    #[inline]
+    pub unsafe fn from_native_window(native_window: *mut OHNativeWindow) -> NativeWidget {
+        NativeWidget { native_window }
+    }
+}
+
+impl Surface {
+    fn id(&self) -> SurfaceID {
+        self.id
+    }
+
+    /// Gives up this surface, returning a token that can be destroyed later with
+    /// `Device::destroy_token()`, on any thread and without the surface's context.
+    ///
+    /// The OpenGL objects that the surface owns in its context, such as its framebuffer, aren't
+    /// part of the token. They're freed when the context is destroyed.
+    pub(crate) fn into_destroy_token(mut self) -> SurfaceDestroyToken {
+        self.destroyed = true;
+        let placeholder = SurfaceObjects::Window {
+            egl_surface: egl::NO_SURFACE,
+        };
+        let mut objects = mem::replace(&mut self.objects, placeholder);
+        if let SurfaceObjects::NativeBuffer {
+            ref mut framebuffer_object,
+            ref mut texture_object,
+            ref mut renderbuffers,
+            ..
+        } = objects
+        {
+            *framebuffer_object = 0;
+            *texture_object = 0;
+            renderbuffers.abandon();
+        }
+        SurfaceDestroyToken { objects }
+    }
+}
+
+/// The native resources of a surface given up with `Device::surface_into_destroy_token()`, which
+/// can be destroyed later without the surface's context.
+///
+/// Tokens can be sent to other threads, and must be destroyed with `Device::destroy_token()`, or
+/// their resources leak.
+pub struct SurfaceDestroyToken {
+    objects: SurfaceObjects,
+}
+
+unsafe impl Send for SurfaceDestroyToken {}
+
+/// Represents the CPU view of the pixel data of this surface.
+pub struct SurfaceDataGuard<'a> {
+    phantom: PhantomData<&'a ()>,
+}
+
+#[cfg(feature = "sm-opencl")]
+impl Device {
+    /// Returns the EGL image holding the contents of the given surface, for OpenCL to wrap with
+    /// `cl_khr_egl_image`.
+    ///
+    /// The image is owned by the surface and is destroyed along with it. Widget surfaces have no
+    /// EGL image; this returns `Error::WidgetAttached` for them.
+    pub fn surface_egl_image(&self, surface: &Surface) -> Result<SharedEGLImage, Error> {
+        match surface.objects {
+            SurfaceObjects::NativeBuffer { egl_image, .. } => Ok(SharedEGLImage {
+                egl_display: self.egl_display,
+                egl_image: egl_image as *mut c_void,
+            }),
+            SurfaceObjects::Window { .. } => Err(Error::WidgetAttached),
+        }
+    }
+}
@@ -1,6 +1,8 @@
 // surfman/src/platform/unix/default.rs
 //
 //! The default backend for Unix, which dynamically switches between Wayland, X11 and surfaceless.
+//!
+//! On X11, EGL is preferred, falling back to GLX if the driver has no usable EGL implementation.
 
 /// Wayland or X11 display server connections.
 pub mod connection {
@@ -8,8 +10,10 @@ pub mod connection {
     use crate::platform::generic::multi::connection::NativeConnection as MultiNativeConnection;
     use crate::platform::generic::multi::device::Device as MultiDevice;
     use crate::platform::unix::generic::device::Device as SWDevice;
+    use crate::platform::unix::glx::device::Device as GLXDevice;
     use crate::platform::unix::wayland::device::Device as WaylandDevice;
-    use crate::platform::unix::x11::device::Device as X11Device;
+    use crate::platform::unix::x11::device::Device as X11EGLDevice;
+    type X11Device = MultiDevice<X11EGLDevice, GLXDevice>;
     type HWDevice = MultiDevice<WaylandDevice, X11Device>;
 
     /// Either a Wayland or an X11 display server connection.
@@ -26,8 +30,10 @@ pub mod context {
     use crate::platform::generic::multi::context::NativeContext as MultiNativeContext;
     use crate::platform::generic::multi::device::Device as MultiDevice;
     use crate::platform::unix::generic::device::Device as SWDevice;
+    use crate::platform::unix::glx::device::Device as GLXDevice;
     use crate::platform::unix::wayland::device::Device as WaylandDevice;
-    use crate::platform::unix::x11::device::Device as X11Device;
+    use crate::platform::unix::x11::device::Device as X11EGLDevice;
+    type X11Device = MultiDevice<X11EGLDevice, GLXDevice>;
     type HWDevice = MultiDevice<WaylandDevice, X11Device>;
 
     /// Represents an OpenGL rendering context.
@@ -58,26 +64,28 @@ pub mod context {
     pub type NativeContext = MultiNativeContext<HWDevice, SWDevice>;
 }
 
-/// Thread-local handles to devices.
+/// Handles to devices.
 pub mod device {
     use crate::platform::generic::multi::device::Adapter as MultiAdapter;
     use crate::platform::generic::multi::device::NativeDevice as MultiNativeDevice;
     use crate::platform::unix::generic::device::Device as SWDevice;
+    use crate::platform::unix::glx::device::Device as GLXDevice;
     use crate::platform::unix::wayland::device::Device as WaylandDevice;
-    use crate::platform::unix::x11::device::Device as X11Device;
+    use crate::platform::unix::x11::device::Device as X11EGLDevice;
 
     use crate::platform::generic::multi::device::Device as MultiDevice;
+    type X11Device = MultiDevice<X11EGLDevice, GLXDevice>;
     type HWDevice = MultiDevice<WaylandDevice, X11Device>;
 
     /// Represents a hardware display adapter that can be used for rendering (including the CPU).
     ///
-    /// Adapters can be sent between threads. To render with an adapter, open a thread-local
-    /// `Device`.
+    /// Adapters can be sent between threads. To render with an adapter, open a `Device`.
     pub type Adapter = MultiAdapter<HWDevice, SWDevice>;
 
-    /// A thread-local handle to a device.
+    /// A handle to a device.
     ///
-    /// Devices contain most of the relevant surface management methods.
+    /// Devices contain most of the relevant surface management methods. Clones can be sent to
+    /// other threads, so each rendering thread can own a handle.
     pub type Device = MultiDevice<HWDevice, SWDevice>;
 
     /// Either a Wayland or an X11 native device
@@ -89,10 +97,13 @@ pub mod surface {
     use crate::platform::generic::multi::device::Device as MultiDevice;
     use crate::platform::generic::multi::surface::NativeWidget as MultiNativeWidget;
     use crate::platform::generic::multi::surface::Surface as MultiSurface;
+    use crate::platform::generic::multi::surface::SurfaceDestroyToken as MultiSurfaceDestroyToken;
     use crate::platform::generic::multi::surface::SurfaceTexture as MultiSurfaceTexture;
     use crate::platform::unix::generic::device::Device as SWDevice;
+    use crate::platform::unix::glx::device::Device as GLXDevice;
     use crate::platform::unix::wayland::device::Device as WaylandDevice;
-    use crate::platform::unix::x11::device::Device as X11Device;
+    use crate::platform::unix::x11::device::Device as X11EGLDevice;
+    type X11Device = MultiDevice<X11EGLDevice, GLXDevice>;
     type HWDevice = MultiDevice<WaylandDevice, X11Device>;
 
     /// A wrapper for a Wayland surface or an X11 `Window`, as appropriate.
@@ -127,6 +138,12 @@ pub mod surface {
     /// the `destroy_surface_texture()` method, or a panic will occur.
     pub type SurfaceTexture = MultiSurfaceTexture<HWDevice, SWDevice>;
 
+    /// The native resources of a surface given up with `Device::surface_into_destroy_token()`,
+    /// which can be destroyed later without the surface's context.
+    ///
+    /// Tokens must be destroyed with `Device::destroy_token()`, or their resources leak.
+    pub type SurfaceDestroyToken = MultiSurfaceDestroyToken<HWDevice, SWDevice>;
+
     // FIXME(pcwalton): Revamp how this works.
     #[doc(hidden)]
     pub struct SurfaceDataGuard {}
@@ -4,18 +4,34 @@
 
 use super::device::{Adapter, Device, NativeDevice};
 use super::surface::NativeWidget;
+use super::wlshm::WlShmPresenter;
 use crate::egl;
-use crate::egl::types::{EGLAttrib, EGLDisplay};
+use crate::egl::types::{EGLAttrib, EGLDisplay, EGLenum};
+use crate::egl::Egl;
 use crate::info::GLApi;
-use crate::platform::generic::egl::device::EGL_FUNCTIONS;
-use crate::platform::generic::egl::ffi::EGL_PLATFORM_SURFACELESS_MESA;
-use crate::Error;
+use crate::overrides;
+use crate::platform::generic::egl::device::query_display_vendor;
+use crate::platform::generic::egl::device::{display_has_extension, EGL_FUNCTIONS};
+use crate::platform::generic::egl::ffi::EGL_EXTENSION_FUNCTIONS;
+use crate::platform::generic::egl::ffi::{EGL_NO_DEVICE_EXT, EGL_PLATFORM_DEVICE_EXT};
+use crate::platform::generic::egl::ffi::{EGL_PLATFORM_GBM_KHR, EGL_PLATFORM_SURFACELESS_MESA};
+use crate::platform::unix::wayland::gbm::GbmDevice;
+use crate::{DisplayInfo, Error, IpcDescriptor, NativeDisplayHandle, NativeWidgetHandle};
 
 use euclid::default::Size2D;
 
 use std::os::raw::c_void;
-use std::sync::Arc;
+use std::ptr;
+use std::sync::{Arc, Mutex};
 
+#[cfg(x11)]
+use super::surface::NativeWidgetKind;
+#[cfg(x11)]
+use super::xshm::ShmPresenter;
+use crate::context::ContextIDAllocator;
+
+#[cfg(feature = "sm-winit")]
+use winit::platform::unix::WindowExtUnix;
 #[cfg(feature = "sm-winit")]
 use winit::window::Window;
 
@@ -23,6 +39,7 @@ use winit::window::Window;
 #[derive(Clone)]
 pub struct Connection {
     pub(crate) native_connection: Arc<NativeConnectionWrapper>,
+    pub(crate) context_ids: ContextIDAllocator,
 }
 
 /// Native connections.
@@ -32,49 +49,121 @@ pub struct NativeConnection(Arc<NativeConnectionWrapper>);
 /// Native connections.
 pub struct NativeConnectionWrapper {
     pub(crate) egl_display: EGLDisplay,
+    #[cfg(x11)]
+    pub(crate) shm_presenter: Option<Mutex<ShmPresenter>>,
+    pub(crate) wl_shm_presenter: Mutex<WlShmPresenter>,
+    // The GBM device that the display was opened on, for connections made on a DRM render node.
+    gbm_device: Option<GbmDevice>,
 }
 
 unsafe impl Send for NativeConnectionWrapper {}
 unsafe impl Sync for NativeConnectionWrapper {}
 
+impl Drop for NativeConnectionWrapper {
+    fn drop(&mut self) {
+        // A display opened on a GBM device must be terminated before the device is destroyed.
+        // Other displays are left initialized, since they may be shared with the application.
+        if self.gbm_device.is_some() {
+            EGL_FUNCTIONS.with(|egl| unsafe {
+                egl.Terminate(self.egl_display);
+            });
+        }
+    }
+}
+
 impl Connection {
-    /// Opens a surfaceless Mesa display.
+    /// Opens a display that doesn't need a display server.
+    ///
+    /// This uses surfaceless Mesa (`EGL_MESA_platform_surfaceless`) if it's available, and
+    /// otherwise the first EGL device that can be initialized (`EGL_EXT_platform_device`), as
+    /// on NVIDIA's drivers.
     #[inline]
     pub fn new() -> Result<Connection, Error> {
         unsafe {
             EGL_FUNCTIONS.with(|egl| {
-                let egl_display_attributes = [egl::NONE as EGLAttrib];
-                let egl_display = egl.GetPlatformDisplay(
+                let egl_display = match initialize_display(
+                    egl,
                     EGL_PLATFORM_SURFACELESS_MESA,
                     egl::DEFAULT_DISPLAY as *mut c_void,
-                    egl_display_attributes.as_ptr(),
-                );
-                if egl_display == egl::NO_DISPLAY {
-                    return Err(Error::ConnectionFailed);
-                }
-
-                let (mut egl_major_version, mut egl_minor_version) = (0, 0);
-                let ok =
-                    egl.Initialize(egl_display, &mut egl_major_version, &mut egl_minor_version);
-                if ok == egl::FALSE {
-                    return Err(Error::ConnectionFailed);
-                }
-
-                let native_connection =
-                    NativeConnection(Arc::new(NativeConnectionWrapper { egl_display }));
+                ) {
+                    Some(egl_display) => egl_display,
+                    None => open_device_display(egl, None).ok_or(Error::ConnectionFailed)?,
+                };
+                Connection::from_egl_display(egl_display, None)
+            })
+        }
+    }
 
-                Connection::from_native_connection(native_connection)
+    /// Opens a display on the first EGL device whose driver comes from the given vendor.
+    ///
+    /// On systems with drivers from several vendors installed side by side (for example, NVIDIA's
+    /// and Mesa's under libglvnd), this picks the vendor explicitly instead of taking whichever
+    /// one libEGL routes the default display to. The vendor is matched case-insensitively
+    /// against the start of each device display's `EGL_VENDOR` string, so "nvidia" and "Mesa"
+    /// both work.
+    ///
+    /// This requires `EGL_EXT_device_enumeration` and `EGL_EXT_platform_device`. If no device
+    /// from the vendor can be initialized, this returns `Error::ConnectionFailed`.
+    pub fn new_with_vendor(vendor: &str) -> Result<Connection, Error> {
+        unsafe {
+            EGL_FUNCTIONS.with(|egl| {
+                let egl_display =
+                    open_device_display(egl, Some(vendor)).ok_or(Error::ConnectionFailed)?;
+                Connection::from_egl_display(egl_display, None)
             })
         }
     }
 
+    /// Connects through a handle that another process opened and passed over IPC.
+    ///
+    /// Only `IpcDescriptor::DrmRenderNode` is accepted. The display is opened on the render node
+    /// through GBM (`EGL_KHR_platform_gbm`), and the render node is closed when the connection is
+    /// dropped, or right away if connecting fails.
+    ///
+    /// # Safety
+    ///
+    /// The render node must be an open file descriptor of a DRM render node, and nothing else may
+    /// use or close it.
+    pub unsafe fn from_ipc_descriptor(descriptor: IpcDescriptor) -> Result<Connection, Error> {
+        let drm_fd = match descriptor {
+            IpcDescriptor::DrmRenderNode(drm_fd) => drm_fd,
+            _ => return Err(Error::UnsupportedOnThisPlatform),
+        };
+        let gbm_device = GbmDevice::new(drm_fd).ok_or(Error::ConnectionFailed)?;
+        EGL_FUNCTIONS.with(|egl| {
+            let egl_display = initialize_display(egl, EGL_PLATFORM_GBM_KHR, gbm_device.as_ptr())
+                .ok_or(Error::ConnectionFailed)?;
+            Connection::from_egl_display(egl_display, Some(gbm_device))
+        })
+    }
+
+    pub(crate) unsafe fn from_egl_display(
+        egl_display: EGLDisplay,
+        gbm_device: Option<GbmDevice>,
+    ) -> Result<Connection, Error> {
+        let native_connection = NativeConnection(Arc::new(NativeConnectionWrapper {
+            egl_display,
+            #[cfg(x11)]
+            shm_presenter: ShmPresenter::open().map(Mutex::new),
+            wl_shm_presenter: Mutex::new(WlShmPresenter::new()),
+            gbm_device,
+        }));
+
+        Connection::from_native_connection(native_connection)
+    }
+
     /// An alias for `Connection::new()`, present for consistency with other backends.
+    ///
+    /// # Safety
+    ///
+    /// The native connection owns its display, so there are no requirements on it.
     #[inline]
     pub unsafe fn from_native_connection(
         native_connection: NativeConnection,
     ) -> Result<Connection, Error> {
         Ok(Connection {
             native_connection: native_connection.0,
+            context_ids: ContextIDAllocator::new()?,
         })
     }
 
@@ -84,12 +173,26 @@ impl Connection {
         NativeConnection(self.native_connection.clone())
     }
 
+    /// Returns the raw display connection underneath this connection: its EGL display.
+    #[inline]
+    pub fn native_display(&self) -> NativeDisplayHandle {
+        NativeDisplayHandle::EGLDisplay(self.native_connection.egl_display as *mut c_void)
+    }
+
     /// Returns the OpenGL API flavor that this connection supports (OpenGL or OpenGL ES).
     #[inline]
     pub fn gl_api(&self) -> GLApi {
         GLApi::GL
     }
 
+    /// Returns the displays connected to the system.
+    ///
+    /// Surfaceless EGL renders offscreen only, so this always returns an empty list.
+    #[inline]
+    pub fn displays(&self) -> Result<Vec<DisplayInfo>, Error> {
+        Ok(vec![])
+    }
+
     /// Returns the "best" adapter on this system, preferring high-performance hardware adapters.
     ///
     /// This is an alias for `Connection::create_hardware_adapter()`.
@@ -103,7 +206,10 @@ impl Connection {
     /// On the OSMesa backend, this returns a software adapter.
     #[inline]
     pub fn create_hardware_adapter(&self) -> Result<Adapter, Error> {
-        Ok(Adapter::hardware())
+        if overrides::software_rendering_forced() {
+            return self.create_software_adapter();
+        }
+        Ok(Adapter::hardware().with_vendor(self.vendor()))
     }
 
     /// Returns the "best" adapter on this system, preferring low-power hardware adapters.
@@ -111,26 +217,43 @@ impl Connection {
     /// On the OSMesa backend, this returns a software adapter.
     #[inline]
     pub fn create_low_power_adapter(&self) -> Result<Adapter, Error> {
-        Ok(Adapter::low_power())
+        if overrides::software_rendering_forced() {
+            return self.create_software_adapter();
+        }
+        Ok(Adapter::low_power().with_vendor(self.vendor()))
     }
 
     /// Returns the "best" adapter on this system, preferring software adapters.
     #[inline]
     pub fn create_software_adapter(&self) -> Result<Adapter, Error> {
-        Ok(Adapter::software())
+        Ok(Adapter::software().with_vendor(self.vendor()))
+    }
+
+    /// Returns the adapter for the GPU that drives the given display.
+    ///
+    /// The GPU is found by matching the display's name against the DRM connectors in sysfs, and
+    /// selected by pointing Mesa's `DRI_PRIME` variable at its PCI slot.
+    #[inline]
+    pub fn create_adapter_for_display(&self, display: &DisplayInfo) -> Result<Adapter, Error> {
+        Ok(Adapter::for_display(display)?.with_vendor(self.vendor()))
+    }
+
+    // Returns the vendor library that this connection's display is dispatched to.
+    fn vendor(&self) -> Option<String> {
+        unsafe { query_display_vendor(self.native_connection.egl_display) }
     }
 
     /// Opens the hardware device corresponding to the given adapter.
     ///
-    /// Device handles are local to a single thread.
+    /// Device handles can be cloned and sent to other threads.
     #[inline]
-    pub fn create_device(&self, adapter: &Adapter) -> Result<Device, Error> {
+    pub(crate) fn create_device_impl(&self, adapter: &Adapter) -> Result<Device, Error> {
         Device::new(self, adapter)
     }
 
     /// An alias for `connection.create_device()` with the default adapter.
     #[inline]
-    pub unsafe fn create_device_from_native_device(
+    pub(crate) unsafe fn create_device_from_native_device_impl(
         &self,
         _: NativeDevice,
     ) -> Result<Device, Error> {
@@ -146,32 +269,185 @@ impl Connection {
 
     /// Creates a native widget type from the given `winit` window.
     ///
-    /// This type can be later used to create surfaces that render to the window.
-    #[inline]
+    /// This type can be later used to create surfaces that render to the window. X11 windows and
+    /// Wayland surfaces are supported.
     #[cfg(feature = "sm-winit")]
     pub fn create_native_widget_from_winit_window(
         &self,
-        _: &Window,
+        window: &Window,
     ) -> Result<NativeWidget, Error> {
-        Err(Error::IncompatibleNativeWidget)
+        #[cfg(x11)]
+        {
+            if let Some(window) = window.xlib_window() {
+                return Ok(NativeWidget {
+                    kind: NativeWidgetKind::X11Window(window),
+                });
+            }
+        }
+
+        match (window.wayland_display(), window.wayland_surface()) {
+            (Some(wayland_display), Some(wayland_surface)) => {
+                let window_size = window.inner_size();
+                let size = Size2D::new(window_size.width as i32, window_size.height as i32);
+                unsafe {
+                    Ok(NativeWidget::from_wayland_surface(
+                        wayland_display,
+                        wayland_surface,
+                        size,
+                    ))
+                }
+            }
+            _ => Err(Error::IncompatibleNativeWidget),
+        }
     }
 
     /// Create a native widget from a raw pointer
+    ///
+    /// With X11 enabled, the pointer is an X11 `Window`.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must hold the ID of an X11 window, which must outlive the native widget and any
+    /// surfaces created from it.
+    #[cfg(x11)]
     pub unsafe fn create_native_widget_from_ptr(
         &self,
-        _raw: *mut c_void,
+        raw: *mut c_void,
         _size: Size2D<i32>,
     ) -> NativeWidget {
-        NativeWidget
+        NativeWidget {
+            kind: NativeWidgetKind::X11Window(raw as usize as x11::xlib::Window),
+        }
+    }
+
+    /// Create a native widget from a raw pointer
+    ///
+    /// The pointer is a Wayland surface. Presenting to it also needs its display connection,
+    /// which a pointer can't carry, so creating a surface from the widget fails with
+    /// `IncompatibleNativeWidget`; use `NativeWidget::from_wayland_surface()` instead.
+    ///
+    /// # Safety
+    ///
+    /// Since no surface can be created from the widget, the pointer is never used, so there are no
+    /// requirements on it.
+    #[cfg(not(x11))]
+    pub unsafe fn create_native_widget_from_ptr(
+        &self,
+        raw: *mut c_void,
+        size: Size2D<i32>,
+    ) -> NativeWidget {
+        NativeWidget::from_wayland_surface(ptr::null_mut(), raw, size)
+    }
+
+    /// Creates a native widget from a handle to a native window or view.
+    ///
+    /// Wayland surface handles are accepted, and so are X11 window handles if surfman was built
+    /// with X11 support. Software-rendered frames are presented to them through shared memory.
+    ///
+    /// # Safety
+    ///
+    /// The handle must refer to a live window or view, which must outlive the native widget and any
+    /// surfaces created from it.
+    pub unsafe fn create_native_widget_from_handle(
+        &self,
+        handle: NativeWidgetHandle,
+    ) -> Result<NativeWidget, Error> {
+        match handle {
+            #[cfg(x11)]
+            NativeWidgetHandle::X11Window(window) => Ok(NativeWidget {
+                kind: NativeWidgetKind::X11Window(window),
+            }),
+            NativeWidgetHandle::WaylandSurface {
+                display,
+                surface,
+                size,
+            } => Ok(NativeWidget::from_wayland_surface(display, surface, size)),
+            _ => Err(Error::IncompatibleNativeWidget),
+        }
     }
 
     /// Create a native widget type from the given `raw_window_handle::RawWindowHandle`.
+    ///
+    /// X11 windows and Wayland surfaces are supported. Wayland handles don't carry the size of
+    /// the surface, so surfaces created from them start out 1x1, and must be sized with
+    /// `resize_surface()` before they're presented.
     #[cfg(feature = "sm-raw-window-handle")]
     #[inline]
     pub fn create_native_widget_from_rwh(
         &self,
-        _: raw_window_handle::RawWindowHandle,
+        raw_handle: raw_window_handle::RawWindowHandle,
     ) -> Result<NativeWidget, Error> {
-        Err(Error::IncompatibleNativeWidget)
+        match raw_handle {
+            #[cfg(x11)]
+            raw_window_handle::RawWindowHandle::Xlib(handle) => Ok(NativeWidget {
+                kind: NativeWidgetKind::X11Window(handle.window),
+            }),
+            raw_window_handle::RawWindowHandle::Wayland(handle) => unsafe {
+                Ok(NativeWidget::from_wayland_surface(
+                    handle.display,
+                    handle.surface,
+                    Size2D::new(1, 1),
+                ))
+            },
+            _ => Err(Error::IncompatibleNativeWidget),
+        }
+    }
+}
+
+// Creates and initializes an EGL display on the given platform.
+unsafe fn initialize_display(
+    egl: &Egl,
+    platform: EGLenum,
+    native_display: *mut c_void,
+) -> Option<EGLDisplay> {
+    let egl_display_attributes = [egl::NONE as EGLAttrib];
+    let egl_display =
+        egl.GetPlatformDisplay(platform, native_display, egl_display_attributes.as_ptr());
+    if egl_display == egl::NO_DISPLAY {
+        return None;
     }
+
+    let (mut egl_major_version, mut egl_minor_version) = (0, 0);
+    let ok = egl.Initialize(egl_display, &mut egl_major_version, &mut egl_minor_version);
+    if ok == egl::FALSE {
+        return None;
+    }
+    Some(egl_display)
+}
+
+// Opens a display on the first EGL device that can be initialized and, if a vendor is given,
+// whose `EGL_VENDOR` string starts with it.
+//
+// This requires `EGL_EXT_device_enumeration` and `EGL_EXT_platform_device`.
+unsafe fn open_device_display(egl: &Egl, vendor: Option<&str>) -> Option<EGLDisplay> {
+    if !display_has_extension(egl::NO_DISPLAY, b"EGL_EXT_platform_device") {
+        return None;
+    }
+    let query_devices = EGL_EXTENSION_FUNCTIONS.QueryDevicesEXT?;
+
+    let mut device_count = 0;
+    if query_devices(0, ptr::null_mut(), &mut device_count) == egl::FALSE || device_count <= 0 {
+        return None;
+    }
+    let mut devices = vec![EGL_NO_DEVICE_EXT; device_count as usize];
+    if query_devices(device_count, devices.as_mut_ptr(), &mut device_count) == egl::FALSE {
+        return None;
+    }
+    devices.truncate(device_count as usize);
+
+    devices.into_iter().find_map(|device| {
+        let egl_display = initialize_display(egl, EGL_PLATFORM_DEVICE_EXT, device as *mut c_void)?;
+        let vendor = match vendor {
+            None => return Some(egl_display),
+            Some(vendor) => vendor.to_lowercase(),
+        };
+        // With libglvnd, each device is owned by one vendor library, and displays opened on it
+        // are dispatched there.
+        let display_vendor = query_display_vendor(egl_display).unwrap_or_default();
+        if display_vendor.to_lowercase().starts_with(&vendor) {
+            return Some(egl_display);
+        }
+        egl.Terminate(egl_display);
+        None
+    })
 }
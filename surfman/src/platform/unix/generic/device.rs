@@ -3,73 +3,200 @@
 //! A wrapper around surfaceless Mesa `EGLDisplay`s.
 
 use super::connection::{Connection, NativeConnectionWrapper};
-use crate::{Error, GLApi};
+use super::context::ContextDescriptor;
+use crate::context::{ContextDescriptorCache, ContextIDAllocator};
+use crate::device_events::DeviceEventDispatcher;
+use crate::timings::{CreationTimings, CreationTimingsRecorder};
+use crate::{DeviceEventHandler, DisplayInfo, Error, GLApi, LifecycleObserver, NativeDeviceHandle};
 
 use std::env;
+use std::fs;
+use std::os::raw::c_void;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
 static MESA_SOFTWARE_RENDERING_ENV_VAR: &'static str = "LIBGL_ALWAYS_SOFTWARE";
 static MESA_DRI_PRIME_ENV_VAR: &'static str = "DRI_PRIME";
 
+static SYSFS_DRM_PATH: &'static str = "/sys/class/drm";
+
 /// Represents a hardware display adapter that can be used for rendering (including the CPU).
 ///
-/// Adapters can be sent between threads. To render with an adapter, open a thread-local `Device`.
+/// Adapters can be sent between threads. To render with an adapter, open a `Device`.
+#[derive(Clone, Debug)]
+pub struct Adapter {
+    kind: AdapterKind,
+    vendor: Option<String>,
+}
+
 #[derive(Clone, Debug)]
-pub enum Adapter {
-    #[doc(hidden)]
+enum AdapterKind {
     Hardware,
-    #[doc(hidden)]
     HardwarePrime,
-    #[doc(hidden)]
     Software,
+    // The GPU at a particular PCI slot, in the `pci-0000_01_00_0` form that `DRI_PRIME` takes.
+    Pci(String),
 }
 
 impl Adapter {
     #[inline]
     pub(crate) fn hardware() -> Adapter {
-        Adapter::HardwarePrime
+        Adapter::new(AdapterKind::HardwarePrime)
     }
 
     #[inline]
     pub(crate) fn low_power() -> Adapter {
-        Adapter::Hardware
+        Adapter::new(AdapterKind::Hardware)
     }
 
     #[inline]
     pub(crate) fn software() -> Adapter {
-        Adapter::Software
+        Adapter::new(AdapterKind::Software)
+    }
+
+    // Returns the adapter for the GPU whose DRM device has a connected connector named like the
+    // display.
+    pub(crate) fn for_display(display: &DisplayInfo) -> Result<Adapter, Error> {
+        match find_pci_slot_driving_connector(&display.name) {
+            Some(pci_slot) => Ok(Adapter::new(AdapterKind::Pci(pci_slot))),
+            None => Err(Error::NoAdapterFound),
+        }
+    }
+
+    /// Returns the adapter for the GPU behind the given DRM render node, such as
+    /// `/dev/dri/renderD129`.
+    ///
+    /// On headless servers with several GPUs, this picks the GPU deterministically, instead of
+    /// leaving it to whichever device EGL enumerates first. Symlinks, such as those in
+    /// `/dev/dri/by-path`, are followed. The GPU is selected by pointing Mesa's `DRI_PRIME`
+    /// variable at its PCI slot, so this returns `NoAdapterFound` if the node doesn't exist or
+    /// isn't backed by a PCI device. The adapter's `vendor()` is unknown.
+    pub fn from_render_node<P>(path: P) -> Result<Adapter, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = fs::canonicalize(path).map_err(|_| Error::NoAdapterFound)?;
+        let node = path.file_name().ok_or(Error::NoAdapterFound)?;
+        match pci_slot_of_drm_node(node) {
+            Some(pci_slot) => Ok(Adapter::new(AdapterKind::Pci(pci_slot))),
+            None => Err(Error::NoAdapterFound),
+        }
+    }
+
+    #[inline]
+    fn new(kind: AdapterKind) -> Adapter {
+        Adapter { kind, vendor: None }
+    }
+
+    // Records the vendor library that the connection's display is dispatched to.
+    #[inline]
+    pub(crate) fn with_vendor(mut self, vendor: Option<String>) -> Adapter {
+        self.vendor = vendor;
+        self
+    }
+
+    /// Returns the name of the vendor driver that renders for this adapter, as reported by
+    /// `EGL_VENDOR` (for example, "NVIDIA" or "Mesa Project").
+    ///
+    /// On systems that use libglvnd, this tells which vendor library EGL calls are dispatched
+    /// to. Returns `None` if the backend can't tell, as on GLX.
+    #[inline]
+    pub fn vendor(&self) -> Option<&str> {
+        self.vendor.as_deref()
     }
 
     pub(crate) fn set_environment_variables(&self) {
-        match *self {
-            Adapter::Hardware | Adapter::HardwarePrime => {
+        match self.kind {
+            AdapterKind::Hardware | AdapterKind::HardwarePrime | AdapterKind::Pci(_) => {
                 env::remove_var(MESA_SOFTWARE_RENDERING_ENV_VAR);
             }
-            Adapter::Software => {
+            AdapterKind::Software => {
                 env::set_var(MESA_SOFTWARE_RENDERING_ENV_VAR, "1");
             }
         }
 
-        match *self {
-            Adapter::Software => {}
-            Adapter::Hardware => {
+        match self.kind {
+            AdapterKind::Software => {}
+            AdapterKind::Hardware => {
                 env::remove_var(MESA_DRI_PRIME_ENV_VAR);
             }
-            Adapter::HardwarePrime => {
+            AdapterKind::HardwarePrime => {
                 env::set_var(MESA_DRI_PRIME_ENV_VAR, "1");
             }
+            AdapterKind::Pci(ref pci_slot) => {
+                env::set_var(MESA_DRI_PRIME_ENV_VAR, pci_slot);
+            }
+        }
+    }
+}
+
+// Finds the PCI slot of the GPU that drives the named output, by looking for a connected DRM
+// connector with the same name.
+//
+// Connectors appear in sysfs as `card0-HDMI-A-1` and so on. Wayland compositors name outputs
+// after their connectors, but the X.Org modesetting driver calls HDMI-A connectors `HDMI-1`, so
+// that spelling is accepted too.
+fn find_pci_slot_driving_connector(output_name: &str) -> Option<String> {
+    if output_name.is_empty() {
+        return None;
+    }
+    for entry in fs::read_dir(SYSFS_DRM_PATH).ok()? {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        let entry_name = entry.file_name().to_string_lossy().into_owned();
+        let separator = match entry_name.find('-') {
+            Some(separator) if entry_name.starts_with("card") => separator,
+            _ => continue,
+        };
+        let (card, connector) = (&entry_name[..separator], &entry_name[separator + 1..]);
+        if connector != output_name && connector.replacen("HDMI-A-", "HDMI-", 1) != output_name {
+            continue;
         }
+        match fs::read_to_string(entry.path().join("status")) {
+            Ok(ref status) if status.trim() == "connected" => {}
+            _ => continue,
+        }
+        return pci_slot_of_drm_node(card);
     }
+    None
+}
+
+// Returns the PCI slot of the GPU behind the named DRM node, such as `card0` or `renderD128`.
+fn pci_slot_of_drm_node<N>(node: N) -> Option<String>
+where
+    N: AsRef<Path>,
+{
+    // The node's `device` link points at the PCI device, e.g. `../../../0000:01:00.0`.
+    let device = fs::read_link(Path::new(SYSFS_DRM_PATH).join(node).join("device")).ok()?;
+    let pci_address = device
+        .file_name()?
+        .to_string_lossy()
+        .replace(&[':', '.'][..], "_");
+    Some(format!("pci-{}", pci_address))
 }
 
-/// A thread-local handle to a device.
+/// A handle to a device.
 ///
-/// Devices contain most of the relevant surface management methods.
+/// Devices contain most of the relevant surface management methods. Clones share the device's
+/// display connection and can be sent to other threads, so each rendering thread can own a handle.
+#[derive(Clone)]
 pub struct Device {
     pub(crate) native_connection: Arc<NativeConnectionWrapper>,
     pub(crate) adapter: Adapter,
+    pub(crate) lifecycle_observer: Option<Arc<dyn LifecycleObserver>>,
+    pub(crate) device_event_dispatcher: DeviceEventDispatcher,
+    pub(crate) creation_timings: CreationTimingsRecorder,
+    pub(crate) memory_usage: Arc<AtomicUsize>,
+    pub(crate) context_ids: ContextIDAllocator,
+    pub(crate) context_descriptors: ContextDescriptorCache<ContextDescriptor>,
 }
 
+// Like the connection, the device can be used from any thread.
+unsafe impl Send for Device {}
+
 /// Wraps an adapter.
 ///
 /// On Wayland, devices and adapters are essentially identical types.
@@ -85,6 +212,12 @@ impl Device {
         Ok(Device {
             native_connection: connection.native_connection.clone(),
             adapter: (*adapter).clone(),
+            lifecycle_observer: None,
+            device_event_dispatcher: DeviceEventDispatcher::default(),
+            creation_timings: CreationTimingsRecorder::default(),
+            memory_usage: Arc::new(AtomicUsize::new(0)),
+            context_ids: connection.context_ids.clone(),
+            context_descriptors: ContextDescriptorCache::new(),
         })
     }
 
@@ -99,11 +232,18 @@ impl Device {
         }
     }
 
+    /// Returns the raw display underneath this device: the EGL display of its connection.
+    #[inline]
+    pub fn native_device_handle(&self) -> NativeDeviceHandle {
+        NativeDeviceHandle::EGLDisplay(self.native_connection.egl_display as *mut c_void)
+    }
+
     /// Returns the display server connection that this device was created with.
     #[inline]
     pub fn connection(&self) -> Connection {
         Connection {
             native_connection: self.native_connection.clone(),
+            context_ids: self.context_ids.clone(),
         }
     }
 
@@ -118,4 +258,60 @@ impl Device {
     pub fn gl_api(&self) -> GLApi {
         GLApi::GL
     }
+
+    /// Installs an observer that is notified when contexts and surfaces of this device are
+    /// created, destroyed, resized, or presented, replacing any previous one.
+    #[inline]
+    pub fn set_lifecycle_observer(&mut self, observer: Option<Arc<dyn LifecycleObserver>>) {
+        self.lifecycle_observer = observer;
+    }
+
+    /// Returns the lifecycle observer installed on this device, if any.
+    #[inline]
+    pub fn lifecycle_observer(&self) -> Option<Arc<dyn LifecycleObserver>> {
+        self.lifecycle_observer.clone()
+    }
+
+    /// Installs a handler that is told when the GPU of this device is removed or reset, or the
+    /// displays are reconfigured, replacing any previous one.
+    ///
+    /// This backend can't list displays, so only resets are reported.
+    #[inline]
+    pub fn set_device_event_handler(&mut self, handler: Option<DeviceEventHandler>) {
+        self.device_event_dispatcher.set_handler(handler, || None);
+    }
+
+    /// Returns the device event handler installed on this device, if any.
+    #[inline]
+    pub fn device_event_handler(&self) -> Option<DeviceEventHandler> {
+        self.device_event_dispatcher.handler()
+    }
+
+    #[inline]
+    pub(crate) fn device_event_dispatcher(&self) -> &DeviceEventDispatcher {
+        &self.device_event_dispatcher
+    }
+
+    /// Returns the GPU memory, in bytes, held by the surfaces of this device.
+    #[inline]
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub(crate) fn memory_usage_counter(&self) -> &AtomicUsize {
+        &self.memory_usage
+    }
+
+    /// Returns how long opening this device, and creating its context descriptors and contexts,
+    /// took.
+    #[inline]
+    pub fn creation_timings(&self) -> CreationTimings {
+        self.creation_timings.get()
+    }
+
+    #[inline]
+    pub(crate) fn creation_timings_recorder(&self) -> &CreationTimingsRecorder {
+        &self.creation_timings
+    }
 }
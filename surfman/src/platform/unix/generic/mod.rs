@@ -2,12 +2,24 @@
 //
 //! The Mesa "surfaceless" backend, which only supports off-screen surfaces and cannot directly
 //! display surfaces on a screen.
+//!
+//! No display server is needed, which makes this suitable for headless rendering. Where surfaceless
+//! Mesa isn't available, an EGL device display is used instead. Generic surfaces can be shared
+//! with other processes and APIs by exporting them as DMA-BUFs.
+//!
+//! Widget surfaces are supported by copying their contents into X11 windows, when an X server is
+//! available, or into Wayland surfaces through `wl_shm`, on the CPU. This lets software devices
+//! display windows without any GPU presentation path.
 
 pub mod connection;
 pub mod context;
 pub mod device;
 pub mod surface;
 
+mod wlshm;
+#[cfg(x11)]
+mod xshm;
+
 #[path = "../../../implementation/mod.rs"]
 mod implementation;
 
@@ -6,12 +6,33 @@ use super::context::{Context, GL_FUNCTIONS};
 use super::device::Device;
 use crate::gl;
 use crate::gl::types::{GLenum, GLuint};
-use crate::platform::generic::egl::surface::{EGLBackedSurface, EGLSurfaceTexture};
-use crate::{Error, SurfaceAccess, SurfaceInfo, SurfaceType};
+#[cfg(feature = "sm-opencl")]
+use crate::opencl::SharedEGLImage;
+use crate::platform::generic::egl::device::display_has_extension;
+use crate::platform::generic::egl::surface::EGLSurfaceTexture;
+use crate::platform::generic::egl::surface::{EGLBackedSurface, EGLSurfaceDestroyToken};
+use crate::surface::{estimate_memory_usage, SurfaceLayoutRequirements};
+use crate::SurfaceInfo;
+use crate::WidgetOutputInfo;
+use crate::{Colorspace, SurfaceOrigin, SurfaceType, SurfaceUsage, SurfaceVisibility};
+use crate::{
+    CreateSurfacesResult, Error, PresentationStatistics, SurfaceAccess, SurfaceAlphaMode,
+    SurfaceFormat,
+};
 
-use euclid::default::Size2D;
+pub use crate::platform::generic::egl::surface::EGLDmaBuf as DmaBuf;
+pub use crate::platform::generic::egl::surface::EGLDmaBufPlane as DmaBufPlane;
+
+use euclid::default::{Rect, Size2D};
 use std::marker::PhantomData;
 
+use std::mem;
+use std::os::raw::c_void;
+use wayland_sys::client::{wl_display, wl_proxy};
+
+#[cfg(x11)]
+use x11::xlib::Window;
+
 // FIXME(pcwalton): Is this right, or should it be `TEXTURE_EXTERNAL_OES`?
 const SURFACE_GL_TEXTURE_TARGET: GLenum = gl::TEXTURE_2D;
 
@@ -46,27 +67,150 @@ pub struct Surface(pub(crate) EGLBackedSurface);
 #[derive(Debug)]
 pub struct SurfaceTexture(pub(crate) EGLSurfaceTexture);
 
-/// A placeholder wrapper for a native widget.
+/// A wrapper for an X11 window or a Wayland surface.
+///
+/// Surfaces are presented to the widget on the CPU.
 #[derive(Clone)]
-pub struct NativeWidget;
+pub struct NativeWidget {
+    pub(crate) kind: NativeWidgetKind,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum NativeWidgetKind {
+    #[cfg(x11)]
+    X11Window(Window),
+    WaylandSurface {
+        wayland_display: *mut wl_display,
+        wayland_surface: *mut wl_proxy,
+        size: Size2D<i32>,
+    },
+}
+
+impl NativeWidget {
+    /// Wraps a Wayland surface (`wl_surface`), along with the display connection (`wl_display`)
+    /// that it belongs to and its size in device pixels.
+    ///
+    /// # Safety
+    ///
+    /// Both must be valid, and must stay alive for as long as any surface created from the widget.
+    pub unsafe fn from_wayland_surface(
+        wayland_display: *mut c_void,
+        wayland_surface: *mut c_void,
+        size: Size2D<i32>,
+    ) -> NativeWidget {
+        NativeWidget {
+            kind: NativeWidgetKind::WaylandSurface {
+                wayland_display: wayland_display as *mut wl_display,
+                wayland_surface: wayland_surface as *mut wl_proxy,
+                size,
+            },
+        }
+    }
+}
+
+/// The native resources of a surface given up with `Device::surface_into_destroy_token()`, which
+/// can be destroyed later without the surface's context.
+///
+/// Tokens can be sent to other threads, and must be destroyed with `Device::destroy_token()`, or
+/// their resources leak.
+pub struct SurfaceDestroyToken(pub(crate) EGLSurfaceDestroyToken);
 
 unsafe impl Send for Surface {}
 
+impl Surface {
+    /// Gives up this surface, returning a token that can be destroyed later with
+    /// `Device::destroy_token()`, on any thread and without the surface's context.
+    ///
+    /// The OpenGL objects that the surface owns in its context, such as its framebuffer, aren't
+    /// part of the token. They're freed when the context is destroyed.
+    #[inline]
+    pub(crate) fn into_destroy_token(self) -> SurfaceDestroyToken {
+        SurfaceDestroyToken(self.0.into_destroy_token())
+    }
+}
+
 impl Device {
     /// Creates either a generic or a widget surface, depending on the supplied surface type.
     ///
     /// Only the given context may ever render to the surface, but generic surfaces can be wrapped
     /// up in a `SurfaceTexture` for reading by other contexts.
-    pub fn create_surface(
+    ///
+    /// Widget surfaces are supported on X11 windows if an X server is available, and on Wayland
+    /// surfaces if the compositor offers `wl_shm`. They are rendered offscreen and copied into
+    /// their widgets on the CPU when presented, which is slow.
+    ///
+    /// Opaque surfaces are shown on Wayland with buffers that have no alpha channel, and the others
+    /// are blended with what is behind them. X11 windows with a 32-bit ARGB visual blend
+    /// premultiplied surfaces with what is behind them under a compositing manager, and show
+    /// opaque surfaces opaque. Windows of depth 24 are always opaque.
+    pub(crate) fn create_surface_impl(
         &mut self,
         context: &Context,
         _: SurfaceAccess,
+        _: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
         surface_type: SurfaceType<NativeWidget>,
     ) -> Result<Surface, Error> {
-        match surface_type {
-            SurfaceType::Generic { size } => self.create_generic_surface(context, &size),
-            SurfaceType::Widget { .. } => Err(Error::UnsupportedOnThisPlatform),
-        }
+        let mut surface = match surface_type {
+            SurfaceType::Generic { size } => self.create_generic_surface(context, &size)?,
+            // Window systems only composite premultiplied contents.
+            SurfaceType::Widget { .. } if surface_alpha_mode == SurfaceAlphaMode::Straight => {
+                return Err(Error::UnsupportedOnThisPlatform)
+            }
+            SurfaceType::Widget { native_widget } => match native_widget.kind {
+                #[cfg(x11)]
+                NativeWidgetKind::X11Window(window) => unsafe {
+                    self.create_x11_window_surface(context, window)?
+                },
+                NativeWidgetKind::WaylandSurface {
+                    wayland_display,
+                    wayland_surface,
+                    size,
+                } => unsafe {
+                    self.create_wayland_surface(context, wayland_display, wayland_surface, &size)?
+                },
+            },
+        };
+        surface.0.alpha_mode = surface_alpha_mode;
+        Ok(surface)
+    }
+
+    /// Creates `count` generic surfaces of the given size.
+    ///
+    /// The context is made current, and its attributes looked up, once for the whole batch. If
+    /// one of the surfaces fails to be created, the error is returned along with the surfaces that
+    /// were created before it.
+    pub(crate) fn create_surfaces_impl(
+        &mut self,
+        context: &Context,
+        _: SurfaceAccess,
+        _: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
+        size: &Size2D<i32>,
+        count: usize,
+    ) -> CreateSurfacesResult<Surface> {
+        let _guard = match self.temporarily_make_context_current(context) {
+            Ok(guard) => guard,
+            Err(err) => return Err((err, vec![])),
+        };
+        let context_descriptor = self.context_descriptor(context);
+        let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+        GL_FUNCTIONS.with(|gl| {
+            Ok((0..count)
+                .map(|_| {
+                    let mut surface = EGLBackedSurface::new_generic(
+                        gl,
+                        self.native_connection.egl_display,
+                        context.0.egl_context,
+                        context.0.id,
+                        &context_attributes,
+                        size,
+                    );
+                    surface.alpha_mode = surface_alpha_mode;
+                    Surface(surface)
+                })
+                .collect())
+        })
     }
 
     fn create_generic_surface(
@@ -89,6 +233,138 @@ impl Device {
         })
     }
 
+    /// Creates a generic surface with the given number of layers, each of the given size.
+    ///
+    /// Surface textures of a layered surface are bound to `GL_TEXTURE_2D_ARRAY`. Rendering to the
+    /// surface draws into the layer chosen with `set_surface_layer()`, initially the first.
+    ///
+    /// EGL can only share single images, so each layer is an image of its own, and a surface
+    /// texture of a layered surface holds a copy of the layers, made when it's created. As the
+    /// surface texture owns the surface, the copy always matches the surface's contents. `layers`
+    /// must be at least 1, or this returns `Error::InvalidSurfaceLayer`.
+    pub fn create_layered_surface(
+        &mut self,
+        context: &Context,
+        _: SurfaceAccess,
+        _: SurfaceUsage,
+        size: &Size2D<i32>,
+        layers: u32,
+    ) -> Result<Surface, Error> {
+        if layers == 0 {
+            return Err(Error::InvalidSurfaceLayer);
+        }
+        let _guard = self.temporarily_make_context_current(context)?;
+        let context_descriptor = self.context_descriptor(context);
+        let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+        let surface = GL_FUNCTIONS.with(|gl| {
+            Surface(EGLBackedSurface::new_generic_layered(
+                gl,
+                self.native_connection.egl_display,
+                context.0.egl_context,
+                context.0.id,
+                &context_attributes,
+                size,
+                layers,
+            ))
+        });
+        self.note_surface_created(&surface);
+        Ok(surface)
+    }
+
+    /// Chooses the layer of a layered surface that rendering to the surface draws into.
+    ///
+    /// The supplied context must be the context the surface is associated with, or this returns
+    /// an `IncompatibleSurface` error. Surfaces not created with `create_layered_surface()` have
+    /// only the first layer, and asking for a layer past the last returns
+    /// `Error::InvalidSurfaceLayer`.
+    pub fn set_surface_layer(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        layer: u32,
+    ) -> Result<(), Error> {
+        if surface.0.context_id != context.0.id {
+            return Err(Error::IncompatibleSurface);
+        }
+        let _guard = self.temporarily_make_context_current(context)?;
+        GL_FUNCTIONS.with(|gl| surface.0.set_layer(gl, layer))
+    }
+
+    /// Returns the number of layers of a generic surface.
+    ///
+    /// This is 1 for all surfaces except those created with `create_layered_surface()`.
+    #[inline]
+    pub fn surface_layers(&self, surface: &Surface) -> u32 {
+        surface.0.layers()
+    }
+
+    /// Creates a new generic surface with a copy of the contents of the given one, stored with
+    /// its color channels in the given order.
+    ///
+    /// Generic surfaces on this backend are RGBA in memory. Calling this method on a widget surface
+    /// returns a `WidgetAttached` error.
+    pub(crate) fn convert_surface_format_impl(
+        &mut self,
+        context: &mut Context,
+        surface: &Surface,
+        format: SurfaceFormat,
+    ) -> Result<Surface, Error> {
+        if surface.0.context_id != context.0.id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if self.surface_has_widget(surface) {
+            return Err(Error::WidgetAttached);
+        }
+
+        let mut new_surface = self.create_generic_surface(context, &surface.0.size)?;
+        new_surface.0.format = format;
+        new_surface.0.origin = surface.0.origin;
+        new_surface.0.alpha_mode = surface.0.alpha_mode;
+        new_surface.0.colorspace = surface.0.colorspace;
+
+        let result = match self.temporarily_make_context_current(context) {
+            Ok(_guard) => GL_FUNCTIONS.with(|gl| surface.0.copy_contents_to(gl, &new_surface.0)),
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(()) => Ok(new_surface),
+            Err(err) => {
+                self.destroy_surface_impl(context, &mut new_surface)?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Wraps an existing OpenGL texture of the given context in a new generic surface, so that
+    /// textures produced outside surfman can be shared or presented like any other surface.
+    ///
+    /// The texture must be a complete `GL_TEXTURE_2D` of the given size. It stays owned by the
+    /// caller, but must not be redefined while the surface is alive.
+    ///
+    /// This requires the `EGL_KHR_gl_texture_2D_image` extension.
+    pub(crate) fn create_surface_from_texture_impl(
+        &mut self,
+        context: &mut Context,
+        size: &Size2D<i32>,
+        texture_object: GLuint,
+    ) -> Result<Surface, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        let context_descriptor = self.context_descriptor(context);
+        let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+        GL_FUNCTIONS.with(|gl| {
+            EGLBackedSurface::new_from_texture(
+                gl,
+                self.native_connection.egl_display,
+                context.0.egl_context,
+                context.0.id,
+                &context_attributes,
+                size,
+                texture_object,
+            )
+            .map(Surface)
+        })
+    }
+
     /// Creates a surface texture from an existing generic surface for use with the given context.
     ///
     /// The surface texture is local to the supplied context and takes ownership of the surface.
@@ -104,6 +380,10 @@ impl Device {
         context: &mut Context,
         surface: Surface,
     ) -> Result<SurfaceTexture, (Error, Surface)> {
+        if self.surface_has_widget(&surface) {
+            return Err((Error::WidgetAttached, surface));
+        }
+
         let _guard = match self.temporarily_make_context_current(context) {
             Ok(guard) => guard,
             Err(err) => return Err((err, surface)),
@@ -115,6 +395,57 @@ impl Device {
         })
     }
 
+    /// Creates a surface texture that samples from a DMA-BUF image, such as a video frame decoded
+    /// with VA-API and exported through FFmpeg's DRM PRIME mapping.
+    ///
+    /// The image must be in a format that the driver can sample as a `GL_TEXTURE_2D`; formats that
+    /// need `GL_TEXTURE_EXTERNAL_OES`, such as most YUV formats, fail to import. The image holds
+    /// its own references to the buffers, so the caller keeps ownership of the file descriptors.
+    /// Destroying the surface texture returns a surface wrapping the image, which must then be
+    /// destroyed with `destroy_surface()`.
+    ///
+    /// This requires the `EGL_EXT_image_dma_buf_import` extension.
+    ///
+    /// # Safety
+    ///
+    /// The file descriptors in `dma_buf` must be open.
+    pub unsafe fn create_surface_texture_from_dma_buf(
+        &mut self,
+        context: &mut Context,
+        dma_buf: &DmaBuf,
+        size: &Size2D<i32>,
+    ) -> Result<SurfaceTexture, Error> {
+        let egl_display = self.native_connection.egl_display;
+        if !display_has_extension(egl_display, b"EGL_EXT_image_dma_buf_import") {
+            return Err(Error::RequiredExtensionUnavailable);
+        }
+
+        let _guard = self.temporarily_make_context_current(context)?;
+        let context_descriptor = self.context_descriptor(context);
+        let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+        GL_FUNCTIONS.with(|gl| {
+            let surface = EGLBackedSurface::new_from_dma_buf(
+                gl,
+                egl_display,
+                dma_buf,
+                context.0.id,
+                &context_attributes,
+                size,
+            )
+            .map_err(|err| match err {
+                Error::SurfaceCreationFailed(err) => Error::SurfaceTextureCreationFailed(err),
+                err => err,
+            })?;
+            match surface.to_surface_texture(gl) {
+                Ok(surface_texture) => Ok(SurfaceTexture(surface_texture)),
+                Err((err, mut surface)) => {
+                    let _ = surface.destroy(gl, egl_display, context.0.id);
+                    Err(err)
+                }
+            }
+        })
+    }
+
     /// Destroys a surface.
     ///
     /// The supplied context must be the context the surface is associated with, or this returns
@@ -122,11 +453,15 @@ impl Device {
     ///
     /// You must explicitly call this method to dispose of a surface. Otherwise, a panic occurs in
     /// the `drop` method.
-    pub fn destroy_surface(
+    pub(crate) fn destroy_surface_impl(
         &self,
-        context: &mut Context,
+        context: &Context,
         surface: &mut Surface,
     ) -> Result<(), Error> {
+        if surface.0.context_id == context.0.id {
+            self.destroy_widget_target(surface.0.id().0);
+        }
+
         GL_FUNCTIONS.with(|gl| {
             let egl_display = self.native_connection.egl_display;
             let window = surface.0.destroy(gl, egl_display, context.0.id)?;
@@ -153,6 +488,17 @@ impl Device {
         }
     }
 
+    /// Destroys the native resources of a surface given up with `surface_into_destroy_token()`.
+    ///
+    /// Unlike `destroy_surface()`, this doesn't need the context that the surface belonged to.
+    pub fn destroy_token(&self, token: SurfaceDestroyToken) -> Result<(), Error> {
+        self.destroy_widget_target(token.0.id.0);
+
+        let window = token.0.destroy(self.native_connection.egl_display);
+        debug_assert!(window.is_none());
+        Ok(())
+    }
+
     /// Displays the contents of a widget surface on screen.
     ///
     /// Widget surfaces are internally double-buffered, so changes to them don't show up in their
@@ -160,19 +506,178 @@ impl Device {
     ///
     /// The supplied context must match the context the surface was created with, or an
     /// `IncompatibleSurface` error is returned.
-    pub fn present_surface(&self, context: &Context, surface: &mut Surface) -> Result<(), Error> {
+    #[inline]
+    pub(crate) fn present_surface_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        self.present_surface_with_damage_impl(context, surface, &[])
+    }
+
+    /// Displays the contents of a widget surface on screen, hinting that only the given
+    /// rectangles have changed since the last present.
+    ///
+    /// The rectangles are in device pixels, with the origin at the lower left corner of the
+    /// surface. An empty slice means that the whole surface changed. Widget surfaces on this
+    /// backend are read back and uploaded in full, so the damage is only a hint.
+    pub(crate) fn present_surface_with_damage_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        if self.surface_has_widget(surface) {
+            return self.present_widget_surface(context, surface);
+        }
+
+        surface.0.present(
+            self.native_connection.egl_display,
+            context.0.egl_context,
+            damage,
+        )
+    }
+
+    /// Displays the `source` rectangle of a widget surface in the `destination` rectangle of its
+    /// widget, scaling it to fit.
+    ///
+    /// The rectangles are in device pixels, with the origin at the lower left corner. Widget
+    /// surfaces are uploaded to their widgets in full, so they return
+    /// `Error::UnsupportedOnThisPlatform`.
+    pub(crate) fn present_surface_with_viewport_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        source: &Rect<i32>,
+        destination: &Rect<i32>,
+    ) -> Result<(), Error> {
+        if surface.0.context_id != context.0.id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        if self.surface_has_widget(surface) {
+            return Err(Error::UnsupportedOnThisPlatform);
+        }
+
+        GL_FUNCTIONS.with(|gl| {
+            surface.0.present_with_viewport(
+                gl,
+                self.native_connection.egl_display,
+                context.0.egl_context,
+                source,
+                destination,
+            )
+        })
+    }
+
+    /// Declares which parts of a widget surface will be redrawn before it is next presented.
+    ///
+    /// Widget surfaces on this backend are rendered offscreen and always keep their contents, so
+    /// this does nothing for them.
+    pub fn set_surface_damage_region(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        region: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        if surface.0.context_id != context.0.id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        if self.surface_has_widget(surface) {
+            return Ok(());
+        }
+
+        surface.0.set_damage_region(
+            self.native_connection.egl_display,
+            context.0.egl_context,
+            region,
+        )
+    }
+
+    /// Returns the number of presents ago that the current back buffer of a widget surface was
+    /// displayed, or 0 if its contents are undefined.
+    ///
+    /// Widget surfaces on this backend keep their contents across presents, so this returns 1.
+    pub fn surface_buffer_age(&self, context: &Context, surface: &Surface) -> Result<u32, Error> {
+        if surface.0.context_id != context.0.id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        if self.surface_has_widget(surface) {
+            return Ok(1);
+        }
+
         surface
             .0
-            .present(self.native_connection.egl_display, context.0.egl_context)
+            .buffer_age(self.native_connection.egl_display, context.0.egl_context)
+    }
+
+    /// Sets whether presenting a widget surface preserves its contents.
+    ///
+    /// Widget surfaces are created without preservation, so their contents are undefined after
+    /// `present_surface()`. With it, applications that only repaint the dirty regions of each
+    /// frame can draw over the previous one, at the cost of a copy per present on some hardware.
+    ///
+    /// Widget surfaces are copied to their widgets on present, so they always keep their
+    /// contents. For the others, if the config of the context doesn't allow preservation, this
+    /// returns `Error::UnsupportedOnThisPlatform`.
+    pub fn set_surface_preserve_buffer(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        preserve: bool,
+    ) -> Result<(), Error> {
+        if surface.0.context_id != context.0.id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        if self.surface_has_widget(surface) {
+            return Ok(());
+        }
+
+        surface
+            .0
+            .set_preserve_buffer(self.native_connection.egl_display, preserve)
+    }
+
+    /// Sets which way up the contents of a surface are.
+    ///
+    /// Widget surfaces are flipped as they are copied into the shared memory of their widgets.
+    pub fn set_surface_origin(
+        &self,
+        surface: &mut Surface,
+        origin: SurfaceOrigin,
+    ) -> Result<(), Error> {
+        surface.0.set_origin(origin)
+    }
+
+    /// Sets the color space that the contents of a surface are in.
+    ///
+    /// Widget surfaces are copied into their widgets untagged, so they can only be sRGB, and
+    /// setting any other color space on them returns `Error::UnsupportedOnThisPlatform`.
+    pub fn set_surface_colorspace(
+        &self,
+        surface: &mut Surface,
+        colorspace: Colorspace,
+    ) -> Result<(), Error> {
+        if self.surface_has_widget(surface) && colorspace != Colorspace::SRGB {
+            return Err(Error::UnsupportedOnThisPlatform);
+        }
+        surface.0.set_colorspace(colorspace)
     }
 
     /// Resizes a widget surface.
-    pub fn resize_surface(
+    pub(crate) fn resize_surface_impl(
         &self,
         _context: &Context,
         surface: &mut Surface,
         size: Size2D<i32>,
     ) -> Result<(), Error> {
+        if surface.0.size != size && self.surface_has_widget(surface) {
+            return self.resize_widget_surface(_context, surface, size);
+        }
+
         surface.0.size = size;
         Ok(())
     }
@@ -191,6 +696,38 @@ impl Device {
         SURFACE_GL_TEXTURE_TARGET
     }
 
+    /// Returns the OpenGL texture target that the given surface texture is bound to.
+    ///
+    /// This is `GL_TEXTURE_2D_ARRAY` for surface textures of layered surfaces, and
+    /// `surface_gl_texture_target()` otherwise.
+    #[inline]
+    pub fn surface_texture_binding_target(&self, surface_texture: &SurfaceTexture) -> GLenum {
+        surface_texture.0.texture_target
+    }
+
+    /// Returns the number of layers of the surface that a surface texture wraps.
+    ///
+    /// This is 1 for all surface textures except those of surfaces created with
+    /// `create_layered_surface()`.
+    #[inline]
+    pub fn surface_texture_layers(&self, surface_texture: &SurfaceTexture) -> u32 {
+        surface_texture.0.surface.layers()
+    }
+
+    /// Fills in the mip chain of a surface texture from the current contents of its surface, so
+    /// that it can be sampled with mipmapping at scales well below its size.
+    ///
+    /// Surface textures on this backend sample the surface's EGL image, which only has a base
+    /// level, so this always returns an `UnsupportedOnThisPlatform` error.
+    #[inline]
+    pub fn generate_surface_mipmaps(
+        &self,
+        _: &mut Context,
+        _: &SurfaceTexture,
+    ) -> Result<(), Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
     /// Returns various information about the surface, including the framebuffer object needed to
     /// render to this surface.
     ///
@@ -201,6 +738,61 @@ impl Device {
         surface.0.info()
     }
 
+    /// Returns the GPU memory, in bytes, held by the given surface.
+    ///
+    /// EGL doesn't report the size of the underlying allocation, so this is an estimate that counts
+    /// four bytes per pixel of color storage in each layer.
+    #[inline]
+    pub fn surface_memory_usage(&self, surface: &Surface) -> usize {
+        estimate_memory_usage(&self.surface_info(surface).size) * surface.0.layers() as usize
+    }
+
+    /// Returns `Error::UnsupportedOnThisPlatform`, as EGL surfaces have no modification counter.
+    #[inline]
+    pub fn surface_seed(&self, _: &Surface) -> Result<u32, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Returns the stride, row alignment, and total size of the memory that a generic surface of
+    /// the given size would be allocated with, without creating one.
+    ///
+    /// Surfaces on this backend can't be mapped for CPU access, so this is the tightly packed
+    /// layout that readbacks use.
+    #[inline]
+    pub fn surface_layout_requirements(
+        &self,
+        size: &Size2D<i32>,
+        _: SurfaceFormat,
+        _: SurfaceAccess,
+    ) -> Result<SurfaceLayoutRequirements, Error> {
+        Ok(SurfaceLayoutRequirements::packed(size))
+    }
+
+    /// Returns timing information about the most recently displayed frame of a widget surface.
+    ///
+    /// Presentation timing isn't available on this backend, so this always returns `None`.
+    #[inline]
+    pub fn surface_presentation_statistics(&self, _: &Surface) -> Option<PresentationStatistics> {
+        None
+    }
+
+    /// Returns whether a widget surface can currently be seen on screen.
+    ///
+    /// There are no widget surfaces on this backend, so this always returns `Unknown`.
+    #[inline]
+    pub fn surface_visibility(&self, _: &Surface) -> SurfaceVisibility {
+        SurfaceVisibility::Unknown
+    }
+
+    /// Returns the display that a widget surface is shown on.
+    ///
+    /// There are no widget surfaces on this backend, so this always returns
+    /// `Error::NoWidgetAttached`.
+    #[inline]
+    pub fn widget_output_info(&self, _: &Surface) -> Result<WidgetOutputInfo, Error> {
+        Err(Error::NoWidgetAttached)
+    }
+
     /// Returns the OpenGL texture object containing the contents of this surface.
     ///
     /// It is only legal to read from, not write to, this texture object.
@@ -208,9 +800,193 @@ impl Device {
     pub fn surface_texture_object(&self, surface_texture: &SurfaceTexture) -> GLuint {
         surface_texture.0.texture_object
     }
+
+    /// Exports the contents of a generic surface as DMA-BUF file descriptors, for sharing with
+    /// other processes or APIs (e.g. Vulkan, a video encoder, or a compositor) without a display
+    /// server.
+    ///
+    /// The buffers share storage with the surface rather than copying it, so rendering to the
+    /// surface must be flushed before the consumer reads them. They remain valid after the
+    /// surface is destroyed, as long as the file descriptors are open.
+    ///
+    /// This requires the `EGL_MESA_image_dma_buf_export` extension. Widget surfaces can't be
+    /// exported; this returns `Error::WidgetAttached` for them.
+    pub fn export_dma_buf(&self, surface: &Surface) -> Result<DmaBuf, Error> {
+        if self.surface_has_widget(surface) {
+            return Err(Error::WidgetAttached);
+        }
+
+        surface.0.export_dma_buf(self.native_connection.egl_display)
+    }
+}
+
+// Widget surfaces are generic surfaces under the hood, which are copied into their X11 windows
+// or Wayland surfaces when presented.
+impl Device {
+    #[cfg(x11)]
+    unsafe fn create_x11_window_surface(
+        &mut self,
+        context: &Context,
+        x11_window: Window,
+    ) -> Result<Surface, Error> {
+        let size = match self.native_connection.shm_presenter {
+            None => return Err(Error::UnsupportedOnThisPlatform),
+            Some(ref shm_presenter) => shm_presenter.lock().unwrap().window_size(x11_window),
+        };
+        let size = size.ok_or(Error::IncompatibleNativeWidget)?;
+
+        let mut surface = self.create_generic_surface(context, &size)?;
+        let shm_presenter = self.native_connection.shm_presenter.as_ref().unwrap();
+        if shm_presenter
+            .lock()
+            .unwrap()
+            .create_target(surface.0.id().0, x11_window)
+        {
+            return Ok(surface);
+        }
+
+        self.destroy_unattached_surface(context, &mut surface)?;
+        Err(Error::IncompatibleNativeWidget)
+    }
+
+    unsafe fn create_wayland_surface(
+        &mut self,
+        context: &Context,
+        wayland_display: *mut wl_display,
+        wayland_surface: *mut wl_proxy,
+        size: &Size2D<i32>,
+    ) -> Result<Surface, Error> {
+        if wayland_display.is_null() || wayland_surface.is_null() {
+            return Err(Error::IncompatibleNativeWidget);
+        }
+
+        let mut surface = self.create_generic_surface(context, size)?;
+        if self
+            .native_connection
+            .wl_shm_presenter
+            .lock()
+            .unwrap()
+            .create_target(surface.0.id().0, wayland_display, wayland_surface)
+        {
+            return Ok(surface);
+        }
+
+        self.destroy_unattached_surface(context, &mut surface)?;
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    fn destroy_unattached_surface(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        GL_FUNCTIONS.with(|gl| {
+            surface
+                .0
+                .destroy(gl, self.native_connection.egl_display, context.0.id)
+        })?;
+        Ok(())
+    }
+
+    fn surface_has_widget(&self, surface: &Surface) -> bool {
+        let surface_id = surface.0.id().0;
+        #[cfg(x11)]
+        {
+            if let Some(ref shm_presenter) = self.native_connection.shm_presenter {
+                if shm_presenter.lock().unwrap().has_target(surface_id) {
+                    return true;
+                }
+            }
+        }
+        let wl_shm_presenter = self.native_connection.wl_shm_presenter.lock().unwrap();
+        wl_shm_presenter.has_target(surface_id)
+    }
+
+    fn destroy_widget_target(&self, surface_id: u64) {
+        #[cfg(x11)]
+        {
+            if let Some(ref shm_presenter) = self.native_connection.shm_presenter {
+                unsafe { shm_presenter.lock().unwrap().destroy_target(surface_id) }
+            }
+        }
+        let mut wl_shm_presenter = self.native_connection.wl_shm_presenter.lock().unwrap();
+        unsafe { wl_shm_presenter.destroy_target(surface_id) }
+    }
+
+    fn present_widget_surface(&self, context: &Context, surface: &Surface) -> Result<(), Error> {
+        if surface.0.context_id != context.0.id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        let _guard = self.temporarily_make_context_current(context)?;
+        #[cfg(x11)]
+        {
+            if let Some(ref shm_presenter) = self.native_connection.shm_presenter {
+                let mut shm_presenter = shm_presenter.lock().unwrap();
+                if shm_presenter.has_target(surface.0.id().0) {
+                    return GL_FUNCTIONS
+                        .with(|gl| unsafe { shm_presenter.present(gl, &surface.0) });
+                }
+            }
+        }
+        let mut wl_shm_presenter = self.native_connection.wl_shm_presenter.lock().unwrap();
+        GL_FUNCTIONS.with(|gl| unsafe { wl_shm_presenter.present(gl, &surface.0) })
+    }
+
+    // The storage of widget surfaces is offscreen, so it has to be reallocated.
+    fn resize_widget_surface(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        size: Size2D<i32>,
+    ) -> Result<(), Error> {
+        if surface.0.context_id != context.0.id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        let _guard = self.temporarily_make_context_current(context)?;
+        let context_descriptor = self.context_descriptor(context);
+        let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+        let egl_display = self.native_connection.egl_display;
+        GL_FUNCTIONS.with(|gl| {
+            let mut new_surface = EGLBackedSurface::new_generic(
+                gl,
+                egl_display,
+                context.0.egl_context,
+                context.0.id,
+                &context_attributes,
+                &size,
+            );
+            // Keep the ID, so that the surface stays keyed to its presentation target.
+            new_surface.id = surface.0.id;
+            mem::swap(&mut surface.0, &mut new_surface);
+            new_surface.destroy(gl, egl_display, context.0.id)?;
+            Ok(())
+        })
+    }
 }
 
 /// Represents the CPU view of the pixel data of this surface.
 pub struct SurfaceDataGuard<'a> {
     phantom: PhantomData<&'a ()>,
 }
+
+#[cfg(feature = "sm-opencl")]
+impl Device {
+    /// Returns the EGL image holding the contents of the given surface, for OpenCL to wrap with
+    /// `cl_khr_egl_image`.
+    ///
+    /// The image is owned by the surface and is destroyed along with it. Widget surfaces have no
+    /// EGL image; this returns `Error::WidgetAttached` for them.
+    pub fn surface_egl_image(&self, surface: &Surface) -> Result<SharedEGLImage, Error> {
+        if self.surface_has_widget(surface) {
+            return Err(Error::WidgetAttached);
+        }
+
+        Ok(SharedEGLImage {
+            egl_display: self.native_connection.egl_display,
+            egl_image: surface.0.egl_image()? as *mut c_void,
+        })
+    }
+}
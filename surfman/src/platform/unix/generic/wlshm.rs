@@ -0,0 +1,419 @@
+// surfman/surfman/src/platform/unix/generic/wlshm.rs
+//
+//! Presentation of widget surfaces on Wayland without a GPU, via `wl_shm`.
+//!
+//! As with X11 windows, widget surfaces are rendered offscreen. Presenting one reads its pixels
+//! back into a shared memory file that the compositor has mapped as a `wl_shm` pool, and attaches
+//! that to the widget's `wl_surface` as a `wl_buffer`. Buffers are reused once the compositor has
+//! released them.
+//!
+//! The objects we create for each widget live on a private event queue, so that we never
+//! dispatch events that belong to the application, and the application's event loop doesn't
+//! dispatch ours.
+
+use crate::gl_utils;
+use crate::platform::generic::egl::surface::EGLBackedSurface;
+use crate::platform::unix::wayland::connection::bind;
+use crate::platform::unix::wayland::ffi::WL_SHM_POOL_CREATE_BUFFER;
+use crate::platform::unix::wayland::ffi::WL_SURFACE_DAMAGE_BUFFER;
+use crate::platform::unix::wayland::ffi::WL_SURFACE_DAMAGE_BUFFER_SINCE_VERSION;
+use crate::platform::unix::wayland::ffi::{wl_buffer_interface, wl_buffer_listener};
+use crate::platform::unix::wayland::ffi::{wl_registry_interface, wl_registry_listener};
+use crate::platform::unix::wayland::ffi::{wl_shm_interface, wl_shm_pool_interface, DESTROY};
+use crate::platform::unix::wayland::ffi::{WL_DISPLAY_GET_REGISTRY, WL_SHM_CREATE_POOL};
+use crate::platform::unix::wayland::ffi::{WL_SHM_FORMAT_ARGB8888, WL_SHM_FORMAT_XRGB8888};
+use crate::platform::unix::wayland::ffi::{WL_SHM_POOL_DESTROY, WL_SURFACE_ATTACH};
+use crate::platform::unix::wayland::ffi::{WL_SURFACE_COMMIT, WL_SURFACE_DAMAGE};
+use crate::{Error, Gl, SurfaceAlphaMode, WindowingApiError};
+
+use euclid::default::Size2D;
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_void};
+use std::os::unix::io::RawFd;
+use std::ptr;
+use wayland_sys::client::{wl_display, wl_event_queue, wl_proxy, WAYLAND_CLIENT_HANDLE};
+use wayland_sys::common::wl_argument;
+
+#[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// The pixel layout that `glReadPixels()` produces with `GL_BGRA` and
+// `GL_UNSIGNED_INT_8_8_8_8_REV`, which is `WL_SHM_FORMAT_XRGB8888` or `WL_SHM_FORMAT_ARGB8888` on
+// little-endian machines.
+const BYTES_PER_PIXEL: i32 = 4;
+
+#[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
+static NEXT_SHM_FILE_ID: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) struct WlShmPresenter {
+    // Keyed by surface ID.
+    targets: HashMap<u64, Target>,
+}
+
+unsafe impl Send for WlShmPresenter {}
+
+struct Target {
+    wayland_display: *mut wl_display,
+    wayland_surface: *mut wl_proxy,
+    event_queue: *mut wl_event_queue,
+    shm: *mut wl_proxy,
+    buffers: Vec<Box<Buffer>>,
+}
+
+struct Buffer {
+    wl_buffer: *mut wl_proxy,
+    pool: *mut wl_proxy,
+    pixels: *mut u8,
+    byte_size: usize,
+    size: Size2D<i32>,
+    // Set when the buffer is attached, and cleared when the compositor releases it.
+    busy: bool,
+}
+
+static REGISTRY_LISTENER: wl_registry_listener = wl_registry_listener {
+    global: registry_global,
+    global_remove: registry_global_remove,
+};
+
+static BUFFER_LISTENER: wl_buffer_listener = wl_buffer_listener {
+    release: buffer_release,
+};
+
+impl WlShmPresenter {
+    pub(crate) fn new() -> WlShmPresenter {
+        WlShmPresenter {
+            targets: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn has_target(&self, surface_id: u64) -> bool {
+        self.targets.contains_key(&surface_id)
+    }
+
+    // Prepares to present the surface with the given ID to a Wayland surface. Returns false if
+    // the compositor doesn't offer `wl_shm`.
+    pub(crate) unsafe fn create_target(
+        &mut self,
+        surface_id: u64,
+        wayland_display: *mut wl_display,
+        wayland_surface: *mut wl_proxy,
+    ) -> bool {
+        let event_queue = (WAYLAND_CLIENT_HANDLE.wl_display_create_queue)(wayland_display);
+        if event_queue.is_null() {
+            return false;
+        }
+
+        let display_wrapper =
+            (WAYLAND_CLIENT_HANDLE.wl_proxy_create_wrapper)(wayland_display as *mut wl_proxy);
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_set_queue)(display_wrapper, event_queue);
+        let mut args = [wl_argument { n: 0 }];
+        let registry = (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array_constructor)(
+            display_wrapper,
+            WL_DISPLAY_GET_REGISTRY,
+            args.as_mut_ptr(),
+            &wl_registry_interface.0,
+        );
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_wrapper_destroy)(display_wrapper);
+
+        // The registry is only needed to bind `wl_shm`, which it does during the roundtrip.
+        let mut shm: *mut wl_proxy = ptr::null_mut();
+        if !registry.is_null() {
+            (WAYLAND_CLIENT_HANDLE.wl_proxy_add_listener)(
+                registry,
+                &REGISTRY_LISTENER as *const wl_registry_listener as *mut extern "C" fn(),
+                &mut shm as *mut *mut wl_proxy as *mut c_void,
+            );
+            (WAYLAND_CLIENT_HANDLE.wl_display_roundtrip_queue)(wayland_display, event_queue);
+            (WAYLAND_CLIENT_HANDLE.wl_proxy_destroy)(registry);
+        }
+        if shm.is_null() {
+            (WAYLAND_CLIENT_HANDLE.wl_event_queue_destroy)(event_queue);
+            return false;
+        }
+
+        self.targets.insert(
+            surface_id,
+            Target {
+                wayland_display,
+                wayland_surface,
+                event_queue,
+                shm,
+                buffers: vec![],
+            },
+        );
+        true
+    }
+
+    pub(crate) unsafe fn destroy_target(&mut self, surface_id: u64) {
+        if let Some(mut target) = self.targets.remove(&surface_id) {
+            for buffer in target.buffers.drain(..) {
+                destroy_buffer(buffer);
+            }
+            (WAYLAND_CLIENT_HANDLE.wl_proxy_destroy)(target.shm);
+            (WAYLAND_CLIENT_HANDLE.wl_display_flush)(target.wayland_display);
+            (WAYLAND_CLIENT_HANDLE.wl_event_queue_destroy)(target.event_queue);
+        }
+    }
+
+    // Copies the contents of the surface into a buffer and commits it to the Wayland surface.
+    // The surface's context must be current.
+    pub(crate) unsafe fn present(
+        &mut self,
+        gl: &Gl,
+        surface: &EGLBackedSurface,
+    ) -> Result<(), Error> {
+        let surface_info = surface.info();
+        let size = surface_info.size;
+        let target = match self.targets.get_mut(&surface_info.id.0) {
+            None => return Err(Error::NoWidgetAttached),
+            Some(target) => target,
+        };
+
+        // Pick up any releases that the application's event loop has read off the socket.
+        (WAYLAND_CLIENT_HANDLE.wl_display_dispatch_queue_pending)(
+            target.wayland_display,
+            target.event_queue,
+        );
+
+        // Buffers of the old size are freed once the compositor is done with them.
+        let (buffers, stale_buffers): (Vec<_>, Vec<_>) = target
+            .buffers
+            .drain(..)
+            .partition(|buffer| buffer.busy || buffer.size == size);
+        target.buffers = buffers;
+        for buffer in stale_buffers {
+            destroy_buffer(buffer);
+        }
+
+        let buffer_index = match target.buffers.iter().position(|buffer| !buffer.busy) {
+            Some(buffer_index) => buffer_index,
+            None => match create_buffer(target.shm, &size, shm_format(surface_info.alpha_mode)) {
+                None => return Err(Error::PresentFailed(WindowingApiError::BadAlloc)),
+                Some(buffer) => {
+                    target.buffers.push(buffer);
+                    target.buffers.len() - 1
+                }
+            },
+        };
+        let buffer = &mut target.buffers[buffer_index];
+
+        gl_utils::read_pixels_top_down(
+            gl,
+            surface_info.framebuffer_object,
+            &size,
+            surface_info.origin,
+            (buffer.size.width * BYTES_PER_PIXEL) as usize,
+            buffer.pixels,
+        );
+
+        let wayland_surface = target.wayland_surface;
+        let mut args = [
+            wl_argument {
+                o: buffer.wl_buffer as *const c_void,
+            },
+            wl_argument { i: 0 },
+            wl_argument { i: 0 },
+        ];
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array)(
+            wayland_surface,
+            WL_SURFACE_ATTACH,
+            args.as_mut_ptr(),
+        );
+        let damage_opcode = if (WAYLAND_CLIENT_HANDLE.wl_proxy_get_version)(wayland_surface)
+            >= WL_SURFACE_DAMAGE_BUFFER_SINCE_VERSION
+        {
+            WL_SURFACE_DAMAGE_BUFFER
+        } else {
+            WL_SURFACE_DAMAGE
+        };
+        let mut args = [
+            wl_argument { i: 0 },
+            wl_argument { i: 0 },
+            wl_argument { i: size.width },
+            wl_argument { i: size.height },
+        ];
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array)(
+            wayland_surface,
+            damage_opcode,
+            args.as_mut_ptr(),
+        );
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array)(
+            wayland_surface,
+            WL_SURFACE_COMMIT,
+            ptr::null_mut(),
+        );
+        buffer.busy = true;
+
+        (WAYLAND_CLIENT_HANDLE.wl_display_flush)(target.wayland_display);
+        Ok(())
+    }
+}
+
+impl Drop for WlShmPresenter {
+    fn drop(&mut self) {
+        unsafe {
+            let surface_ids: Vec<u64> = self.targets.keys().cloned().collect();
+            for surface_id in surface_ids {
+                self.destroy_target(surface_id);
+            }
+        }
+    }
+}
+
+// Creates a buffer of the given size in a pool of its own, on the event queue of `wl_shm`.
+// The compositor blends ARGB buffers as premultiplied, and ignores the alpha channel of XRGB ones.
+fn shm_format(alpha_mode: SurfaceAlphaMode) -> u32 {
+    match alpha_mode {
+        SurfaceAlphaMode::Opaque => WL_SHM_FORMAT_XRGB8888,
+        SurfaceAlphaMode::Premultiplied | SurfaceAlphaMode::Straight => WL_SHM_FORMAT_ARGB8888,
+    }
+}
+
+unsafe fn create_buffer(
+    shm: *mut wl_proxy,
+    size: &Size2D<i32>,
+    format: u32,
+) -> Option<Box<Buffer>> {
+    let size = Size2D::new(size.width.max(1), size.height.max(1));
+    let stride = size.width * BYTES_PER_PIXEL;
+    let byte_size = stride as usize * size.height as usize;
+
+    let fd = create_shm_file(byte_size)?;
+    let pixels = libc::mmap(
+        ptr::null_mut(),
+        byte_size,
+        libc::PROT_READ | libc::PROT_WRITE,
+        libc::MAP_SHARED,
+        fd,
+        0,
+    );
+    if pixels == libc::MAP_FAILED {
+        libc::close(fd);
+        return None;
+    }
+
+    // `libwayland-client` duplicates the file descriptor when it marshals the request.
+    let mut args = [
+        wl_argument { n: 0 },
+        wl_argument { h: fd },
+        wl_argument {
+            i: byte_size as i32,
+        },
+    ];
+    let pool = (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array_constructor)(
+        shm,
+        WL_SHM_CREATE_POOL,
+        args.as_mut_ptr(),
+        &wl_shm_pool_interface.0,
+    );
+    libc::close(fd);
+    if pool.is_null() {
+        libc::munmap(pixels, byte_size);
+        return None;
+    }
+
+    let mut args = [
+        wl_argument { n: 0 },
+        wl_argument { i: 0 },
+        wl_argument { i: size.width },
+        wl_argument { i: size.height },
+        wl_argument { i: stride },
+        wl_argument { u: format },
+    ];
+    let wl_buffer = (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array_constructor)(
+        pool,
+        WL_SHM_POOL_CREATE_BUFFER,
+        args.as_mut_ptr(),
+        &wl_buffer_interface.0,
+    );
+
+    let mut buffer = Box::new(Buffer {
+        wl_buffer,
+        pool,
+        pixels: pixels as *mut u8,
+        byte_size,
+        size,
+        busy: false,
+    });
+    if wl_buffer.is_null() {
+        destroy_buffer(buffer);
+        return None;
+    }
+    (WAYLAND_CLIENT_HANDLE.wl_proxy_add_listener)(
+        wl_buffer,
+        &BUFFER_LISTENER as *const wl_buffer_listener as *mut extern "C" fn(),
+        &mut *buffer as *mut Buffer as *mut c_void,
+    );
+    Some(buffer)
+}
+
+unsafe fn destroy_buffer(buffer: Box<Buffer>) {
+    if !buffer.wl_buffer.is_null() {
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array)(buffer.wl_buffer, DESTROY, ptr::null_mut());
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_destroy)(buffer.wl_buffer);
+    }
+    (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array)(
+        buffer.pool,
+        WL_SHM_POOL_DESTROY,
+        ptr::null_mut(),
+    );
+    (WAYLAND_CLIENT_HANDLE.wl_proxy_destroy)(buffer.pool);
+    libc::munmap(buffer.pixels as *mut c_void, buffer.byte_size);
+}
+
+// Creates an anonymous file of the given size to back a `wl_shm` pool.
+unsafe fn create_shm_file(byte_size: usize) -> Option<RawFd> {
+    #[cfg(any(target_os = "linux", target_os = "freebsd"))]
+    let fd = libc::memfd_create(
+        b"surfman-wl-shm\0".as_ptr() as *const c_char,
+        libc::MFD_CLOEXEC,
+    );
+
+    // Elsewhere, a uniquely named shared memory object is unlinked as soon as it's open.
+    #[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
+    let fd = {
+        let name = format!(
+            "/surfman-wl-shm-{}-{}\0",
+            libc::getpid(),
+            NEXT_SHM_FILE_ID.fetch_add(1, Ordering::Relaxed)
+        );
+        let fd = libc::shm_open(
+            name.as_ptr() as *const c_char,
+            libc::O_RDWR | libc::O_CREAT | libc::O_EXCL | libc::O_CLOEXEC,
+            0o600,
+        );
+        if fd >= 0 {
+            libc::shm_unlink(name.as_ptr() as *const c_char);
+        }
+        fd
+    };
+
+    if fd < 0 {
+        return None;
+    }
+    if libc::ftruncate(fd, byte_size as libc::off_t) < 0 {
+        libc::close(fd);
+        return None;
+    }
+    Some(fd)
+}
+
+unsafe extern "C" fn registry_global(
+    data: *mut c_void,
+    registry: *mut wl_proxy,
+    name: u32,
+    interface: *const c_char,
+    version: u32,
+) {
+    let shm = &mut *(data as *mut *mut wl_proxy);
+    if CStr::from_ptr(interface).to_bytes() == b"wl_shm" && shm.is_null() {
+        *shm = bind(registry, name, &wl_shm_interface.0, version);
+    }
+}
+
+unsafe extern "C" fn registry_global_remove(_: *mut c_void, _: *mut wl_proxy, _: u32) {}
+
+unsafe extern "C" fn buffer_release(data: *mut c_void, _: *mut wl_proxy) {
+    (*(data as *mut Buffer)).busy = false;
+}
@@ -0,0 +1,429 @@
+// surfman/surfman/src/platform/unix/generic/xshm.rs
+//
+//! Presentation of widget surfaces on X11 without a GPU, via the MIT-SHM extension.
+//!
+//! Surfaceless Mesa can't render to windows, so widget surfaces are rendered offscreen, just like
+//! generic surfaces. Presenting one reads its pixels back into an image in shared memory and
+//! hands that to the X server with `XShmPutImage()`. If the X server can't attach the shared
+//! memory (for example, because it's on another machine), the pixels are sent over the wire with
+//! `XPutImage()` instead.
+//!
+//! Requests are made on a private display connection, so that applications don't have to share
+//! theirs with the software device.
+
+use crate::gl_utils;
+use crate::platform::generic::egl::surface::EGLBackedSurface;
+use crate::platform::unix::x11::connection::trap_x_errors;
+use crate::{Error, Gl, SurfaceAlphaMode, WindowingApiError};
+
+use euclid::default::Size2D;
+use std::collections::HashMap;
+use std::mem;
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+use std::ptr;
+use std::slice;
+use x11::xlib::ZPixmap;
+use x11::xlib::{Bool, Display, Drawable, False, LSBFirst, MSBFirst, Visual, Window, GC};
+use x11::xlib::{XCloseDisplay, XCreateGC, XCreateImage, XDestroyImage, XFreeGC, XImage};
+use x11::xlib::{XGetWindowAttributes, XOpenDisplay, XPutImage, XSync, XWindowAttributes};
+use x11::xshm::XShmSegmentInfo;
+
+// The pixel layout that `glReadPixels()` produces with `GL_BGRA` and
+// `GL_UNSIGNED_INT_8_8_8_8_REV`, which matches 24- and 32-bit TrueColor visuals.
+const BITS_PER_PIXEL: c_int = 32;
+
+type QueryExtensionFn = unsafe extern "C" fn(display: *mut Display) -> Bool;
+type CreateImageFn = unsafe extern "C" fn(
+    display: *mut Display,
+    visual: *mut Visual,
+    depth: c_uint,
+    format: c_int,
+    data: *mut c_char,
+    shminfo: *mut XShmSegmentInfo,
+    width: c_uint,
+    height: c_uint,
+) -> *mut XImage;
+type AttachFn = unsafe extern "C" fn(display: *mut Display, shminfo: *mut XShmSegmentInfo) -> Bool;
+type DetachFn = unsafe extern "C" fn(display: *mut Display, shminfo: *mut XShmSegmentInfo) -> Bool;
+type PutImageFn = unsafe extern "C" fn(
+    display: *mut Display,
+    drawable: Drawable,
+    gc: GC,
+    image: *mut XImage,
+    src_x: c_int,
+    src_y: c_int,
+    dst_x: c_int,
+    dst_y: c_int,
+    width: c_uint,
+    height: c_uint,
+    send_event: Bool,
+) -> Bool;
+
+// `libXext` is loaded at runtime, so that its absence just means falling back to `XPutImage()`.
+#[allow(non_snake_case)]
+struct XShmFunctions {
+    QueryExtension: QueryExtensionFn,
+    CreateImage: CreateImageFn,
+    Attach: AttachFn,
+    Detach: DetachFn,
+    PutImage: PutImageFn,
+}
+
+lazy_static! {
+    static ref XSHM_FUNCTIONS: Option<XShmFunctions> = unsafe {
+        let library_names: [&[u8]; 2] = [b"libXext.so.6\0", b"libXext.so\0"];
+        let library = library_names
+            .iter()
+            .map(|name| libc::dlopen(name.as_ptr() as *const c_char, libc::RTLD_LAZY))
+            .find(|library| !library.is_null())?;
+        let get = |name: &'static [u8]| libc::dlsym(library, name.as_ptr() as *const c_char);
+        let symbols = [
+            get(b"XShmQueryExtension\0"),
+            get(b"XShmCreateImage\0"),
+            get(b"XShmAttach\0"),
+            get(b"XShmDetach\0"),
+            get(b"XShmPutImage\0"),
+        ];
+        if symbols.iter().any(|symbol| symbol.is_null()) {
+            return None;
+        }
+        Some(XShmFunctions {
+            QueryExtension: mem::transmute::<*mut c_void, QueryExtensionFn>(symbols[0]),
+            CreateImage: mem::transmute::<*mut c_void, CreateImageFn>(symbols[1]),
+            Attach: mem::transmute::<*mut c_void, AttachFn>(symbols[2]),
+            Detach: mem::transmute::<*mut c_void, DetachFn>(symbols[3]),
+            PutImage: mem::transmute::<*mut c_void, PutImageFn>(symbols[4]),
+        })
+    };
+}
+
+pub(crate) struct ShmPresenter {
+    display: *mut Display,
+    // `None` if the X server doesn't support MIT-SHM or can't attach our segments.
+    shm_functions: Option<&'static XShmFunctions>,
+    // Keyed by surface ID.
+    targets: HashMap<u64, Target>,
+}
+
+unsafe impl Send for ShmPresenter {}
+
+struct Target {
+    window: Window,
+    visual: *mut Visual,
+    depth: c_int,
+    gc: GC,
+    image: Option<Image>,
+}
+
+struct Image {
+    ximage: *mut XImage,
+    size: Size2D<i32>,
+    // Boxed because the X image keeps a pointer to it.
+    shm_segment: Option<Box<XShmSegmentInfo>>,
+}
+
+impl ShmPresenter {
+    // Opens a private connection to the X server named by `DISPLAY`, if there is one.
+    pub(crate) unsafe fn open() -> Option<ShmPresenter> {
+        let display = XOpenDisplay(ptr::null());
+        if display.is_null() {
+            return None;
+        }
+        let shm_functions = XSHM_FUNCTIONS
+            .as_ref()
+            .filter(|functions| (functions.QueryExtension)(display) != 0);
+        Some(ShmPresenter {
+            display,
+            shm_functions,
+            targets: HashMap::new(),
+        })
+    }
+
+    pub(crate) fn has_target(&self, surface_id: u64) -> bool {
+        self.targets.contains_key(&surface_id)
+    }
+
+    // Returns the size of the window, or `None` if it doesn't exist.
+    pub(crate) unsafe fn window_size(&self, window: Window) -> Option<Size2D<i32>> {
+        let display = self.display;
+        let mut window_attributes: XWindowAttributes = mem::zeroed();
+        match trap_x_errors(display, || {
+            XGetWindowAttributes(display, window, &mut window_attributes)
+        }) {
+            Some(status) if status != 0 => Some(Size2D::new(
+                window_attributes.width,
+                window_attributes.height,
+            )),
+            _ => None,
+        }
+    }
+
+    // Prepares to present the surface with the given ID to a window. Returns false if the window
+    // doesn't exist or has a visual that we can't produce pixels for.
+    pub(crate) unsafe fn create_target(&mut self, surface_id: u64, window: Window) -> bool {
+        let display = self.display;
+        let mut window_attributes: XWindowAttributes = mem::zeroed();
+        match trap_x_errors(display, || {
+            XGetWindowAttributes(display, window, &mut window_attributes)
+        }) {
+            Some(status) if status != 0 => {}
+            _ => return false,
+        }
+        if window_attributes.depth != 24 && window_attributes.depth != 32 {
+            return false;
+        }
+
+        let gc = XCreateGC(display, window, 0, ptr::null_mut());
+        self.targets.insert(
+            surface_id,
+            Target {
+                window,
+                visual: window_attributes.visual,
+                depth: window_attributes.depth,
+                gc,
+                image: None,
+            },
+        );
+        true
+    }
+
+    pub(crate) unsafe fn destroy_target(&mut self, surface_id: u64) {
+        if let Some(mut target) = self.targets.remove(&surface_id) {
+            if let Some(image) = target.image.take() {
+                self.destroy_image(image);
+            }
+            XFreeGC(self.display, target.gc);
+        }
+    }
+
+    // Copies the contents of the surface into its window. The surface's context must be current.
+    pub(crate) unsafe fn present(
+        &mut self,
+        gl: &Gl,
+        surface: &EGLBackedSurface,
+    ) -> Result<(), Error> {
+        let surface_info = surface.info();
+        let size = surface_info.size;
+        let mut target = match self.targets.remove(&surface_info.id.0) {
+            None => return Err(Error::NoWidgetAttached),
+            Some(target) => target,
+        };
+
+        if target.image.as_ref().map(|image| image.size) != Some(size) {
+            if let Some(image) = target.image.take() {
+                self.destroy_image(image);
+            }
+            target.image = self.create_image(&target, &size);
+        }
+
+        let result = match target.image {
+            None => Err(Error::PresentFailed(WindowingApiError::BadAlloc)),
+            Some(ref image) => {
+                let ximage = &*image.ximage;
+                gl_utils::read_pixels_top_down(
+                    gl,
+                    surface_info.framebuffer_object,
+                    &Size2D::new(ximage.width, ximage.height),
+                    surface_info.origin,
+                    ximage.bytes_per_line as usize,
+                    ximage.data as *mut u8,
+                );
+                // Windows with an ARGB visual show the alpha channel of the contents.
+                if surface_info.alpha_mode == SurfaceAlphaMode::Opaque && target.depth == 32 {
+                    fill_alpha(ximage);
+                }
+                self.put_image(&target, image);
+                Ok(())
+            }
+        };
+
+        self.targets.insert(surface_info.id.0, target);
+        result
+    }
+
+    unsafe fn create_image(&mut self, target: &Target, size: &Size2D<i32>) -> Option<Image> {
+        let (width, height) = (size.width.max(1) as c_uint, size.height.max(1) as c_uint);
+
+        if let Some(shm_functions) = self.shm_functions {
+            match create_shm_image(self.display, shm_functions, target, size) {
+                Some(image) => return Some(image),
+                // Don't keep trying to use shared memory with a remote X server.
+                None => self.shm_functions = None,
+            }
+        }
+
+        let ximage = XCreateImage(
+            self.display,
+            target.visual,
+            target.depth as c_uint,
+            ZPixmap,
+            0,
+            ptr::null_mut(),
+            width,
+            height,
+            BITS_PER_PIXEL,
+            0,
+        );
+        if ximage.is_null() {
+            return None;
+        }
+        if (*ximage).bits_per_pixel != BITS_PER_PIXEL {
+            XDestroyImage(ximage);
+            return None;
+        }
+
+        // `XDestroyImage()` frees this with `free()`. Xlib converts to the server's byte order
+        // when sending the image.
+        (*ximage).data =
+            libc::malloc(((*ximage).bytes_per_line as u32 * height) as usize) as *mut c_char;
+        (*ximage).byte_order = native_byte_order();
+        Some(Image {
+            ximage,
+            size: *size,
+            shm_segment: None,
+        })
+    }
+
+    unsafe fn destroy_image(&self, image: Image) {
+        match image.shm_segment {
+            None => {
+                XDestroyImage(image.ximage);
+            }
+            Some(mut shm_segment) => {
+                let shm_functions = XSHM_FUNCTIONS.as_ref().unwrap();
+                (shm_functions.Detach)(self.display, &mut *shm_segment);
+                XSync(self.display, False);
+                XDestroyImage(image.ximage);
+                libc::shmdt(shm_segment.shmaddr as *const c_void);
+            }
+        }
+    }
+
+    unsafe fn put_image(&self, target: &Target, image: &Image) {
+        let (width, height) = (
+            (*image.ximage).width as c_uint,
+            (*image.ximage).height as c_uint,
+        );
+        match image.shm_segment {
+            Some(_) => {
+                let shm_functions = XSHM_FUNCTIONS.as_ref().unwrap();
+                (shm_functions.PutImage)(
+                    self.display,
+                    target.window,
+                    target.gc,
+                    image.ximage,
+                    0,
+                    0,
+                    0,
+                    0,
+                    width,
+                    height,
+                    False,
+                );
+            }
+            None => {
+                XPutImage(
+                    self.display,
+                    target.window,
+                    target.gc,
+                    image.ximage,
+                    0,
+                    0,
+                    0,
+                    0,
+                    width,
+                    height,
+                );
+            }
+        }
+
+        // Wait for the X server to finish reading the image, so that the next frame can reuse it.
+        XSync(self.display, False);
+    }
+}
+
+impl Drop for ShmPresenter {
+    fn drop(&mut self) {
+        unsafe {
+            let surface_ids: Vec<u64> = self.targets.keys().cloned().collect();
+            for surface_id in surface_ids {
+                self.destroy_target(surface_id);
+            }
+            XCloseDisplay(self.display);
+        }
+    }
+}
+
+unsafe fn create_shm_image(
+    display: *mut Display,
+    shm_functions: &XShmFunctions,
+    target: &Target,
+    size: &Size2D<i32>,
+) -> Option<Image> {
+    let (width, height) = (size.width.max(1) as c_uint, size.height.max(1) as c_uint);
+    let mut shm_segment: Box<XShmSegmentInfo> = Box::new(mem::zeroed());
+    let ximage = (shm_functions.CreateImage)(
+        display,
+        target.visual,
+        target.depth as c_uint,
+        ZPixmap,
+        ptr::null_mut(),
+        &mut *shm_segment,
+        width,
+        height,
+    );
+    if ximage.is_null() {
+        return None;
+    }
+    if (*ximage).bits_per_pixel != BITS_PER_PIXEL {
+        XDestroyImage(ximage);
+        return None;
+    }
+
+    let byte_size = (*ximage).bytes_per_line as usize * height as usize;
+    shm_segment.shmid = libc::shmget(libc::IPC_PRIVATE, byte_size, libc::IPC_CREAT | 0o600);
+    if shm_segment.shmid < 0 {
+        XDestroyImage(ximage);
+        return None;
+    }
+    shm_segment.shmaddr = libc::shmat(shm_segment.shmid, ptr::null(), 0) as *mut c_char;
+    // The segment is freed once both we and the X server have detached from it.
+    let attached = shm_segment.shmaddr as isize != -1
+        && trap_x_errors(display, || {
+            (shm_functions.Attach)(display, &mut *shm_segment)
+        })
+        .map_or(false, |ok| ok != 0);
+    libc::shmctl(shm_segment.shmid, libc::IPC_RMID, ptr::null_mut());
+    if !attached {
+        if shm_segment.shmaddr as isize != -1 {
+            libc::shmdt(shm_segment.shmaddr as *const c_void);
+        }
+        XDestroyImage(ximage);
+        return None;
+    }
+
+    (*ximage).data = shm_segment.shmaddr;
+    Some(Image {
+        ximage,
+        size: *size,
+        shm_segment: Some(shm_segment),
+    })
+}
+
+// Makes every pixel of the image fully opaque. The pixels are native-endian 32-bit ARGB words.
+unsafe fn fill_alpha(ximage: &XImage) {
+    for y in 0..(ximage.height as usize) {
+        let row = ximage.data.add(y * ximage.bytes_per_line as usize) as *mut u32;
+        for pixel in slice::from_raw_parts_mut(row, ximage.width as usize) {
+            *pixel |= 0xff00_0000;
+        }
+    }
+}
+
+fn native_byte_order() -> c_int {
+    if cfg!(target_endian = "little") {
+        LSBFirst
+    } else {
+        MSBFirst
+    }
+}
@@ -0,0 +1,330 @@
+// surfman/surfman/src/platform/unix/glx/connection.rs
+//
+//! A wrapper for X11 server connections (`DISPLAY` variables), for use with GLX.
+
+use super::device::{Device, NativeDevice};
+use super::ffi::{glx_has_extension, GLX_FUNCTIONS};
+use super::surface::NativeWidget;
+use crate::context::ContextIDAllocator;
+use crate::error::Error;
+use crate::info::GLApi;
+use crate::overrides;
+use crate::platform::unix::generic::device::Adapter;
+use crate::platform::unix::x11::connection::{DisplayGuard, X_THREADS_INIT};
+use crate::platform::unix::xrandr;
+use crate::{DisplayInfo, IpcDescriptor, NativeDisplayHandle, NativeWidgetHandle};
+
+use euclid::default::Size2D;
+
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+use std::sync::Arc;
+use x11::xlib::{Display, XCloseDisplay, XDefaultScreen, XOpenDisplay};
+
+#[cfg(feature = "sm-winit")]
+use winit::platform::unix::WindowExtUnix;
+#[cfg(feature = "sm-winit")]
+use winit::window::Window;
+
+/// A connection to the X11 display server.
+#[derive(Clone)]
+pub struct Connection {
+    pub(crate) native_connection: Arc<NativeConnectionWrapper>,
+    pub(crate) context_ids: ContextIDAllocator,
+}
+
+unsafe impl Send for Connection {}
+
+pub(crate) struct NativeConnectionWrapper {
+    x11_display: *mut Display,
+    x11_display_is_owned: bool,
+    pub(crate) screen: c_int,
+    pub(crate) create_context_supported: bool,
+    pub(crate) texture_from_pixmap_supported: bool,
+}
+
+/// Wrapper for an X11 display.
+#[derive(Clone)]
+pub struct NativeConnection {
+    /// The Xlib Display. This must be present; do not pass NULL.
+    pub x11_display: *mut Display,
+}
+
+impl Drop for NativeConnectionWrapper {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe {
+            if self.x11_display_is_owned {
+                XCloseDisplay(self.x11_display);
+            }
+            self.x11_display = ptr::null_mut();
+        }
+    }
+}
+
+impl Connection {
+    /// Connects to the default display.
+    ///
+    /// This fails if `libGL` can't be loaded or the X server doesn't support GLX 1.3.
+    #[inline]
+    pub fn new() -> Result<Connection, Error> {
+        unsafe {
+            *X_THREADS_INIT;
+
+            let x11_display = XOpenDisplay(ptr::null());
+            if x11_display.is_null() {
+                return Err(Error::ConnectionFailed);
+            }
+
+            Connection::from_x11_display(x11_display, true)
+        }
+    }
+
+    /// Wraps an existing X11 `Display` in a `Connection`.
+    ///
+    /// # Safety
+    ///
+    /// Important: Before calling this function, X11 must have be initialized in a thread-safe
+    /// manner by using `XInitThreads()`. Otherwise, it will not be safe to use `surfman` from
+    /// multiple threads.
+    ///
+    /// The display is not retained, as there is no way to do that in the X11 API. Therefore, it is
+    /// the caller's responsibility to ensure that the display connection is not closed before this
+    /// `Connection` object is disposed of.
+    #[inline]
+    pub unsafe fn from_native_connection(
+        native_connection: NativeConnection,
+    ) -> Result<Connection, Error> {
+        Connection::from_x11_display(native_connection.x11_display, false)
+    }
+
+    /// Returns `UnsupportedOnThisPlatform`, because Xlib can't connect through a socket that
+    /// another process opened.
+    ///
+    /// # Safety
+    ///
+    /// The descriptor is never used, so there are no requirements on it.
+    #[inline]
+    pub unsafe fn from_ipc_descriptor(_: IpcDescriptor) -> Result<Connection, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    pub(crate) fn from_x11_display(
+        x11_display: *mut Display,
+        is_owned: bool,
+    ) -> Result<Connection, Error> {
+        unsafe {
+            let mut native_connection = NativeConnectionWrapper {
+                x11_display,
+                x11_display_is_owned: is_owned,
+                screen: XDefaultScreen(x11_display),
+                create_context_supported: false,
+                texture_from_pixmap_supported: false,
+            };
+
+            // Dropping the wrapper closes the display if we own it.
+            let glx = GLX_FUNCTIONS.as_ref().ok_or(Error::ConnectionFailed)?;
+            let (mut error_base, mut event_base) = (0, 0);
+            if (glx.QueryExtension)(x11_display, &mut error_base, &mut event_base) == 0 {
+                return Err(Error::ConnectionFailed);
+            }
+            let (mut major_version, mut minor_version) = (0, 0);
+            if (glx.QueryVersion)(x11_display, &mut major_version, &mut minor_version) == 0
+                || (major_version, minor_version) < (1, 3)
+            {
+                return Err(Error::ConnectionFailed);
+            }
+
+            let screen = native_connection.screen;
+            native_connection.create_context_supported =
+                glx_has_extension(glx, x11_display, screen, b"GLX_ARB_create_context")
+                    && glx_has_extension(
+                        glx,
+                        x11_display,
+                        screen,
+                        b"GLX_ARB_create_context_profile",
+                    );
+            native_connection.texture_from_pixmap_supported =
+                glx_has_extension(glx, x11_display, screen, b"GLX_EXT_texture_from_pixmap");
+
+            Ok(Connection {
+                native_connection: Arc::new(native_connection),
+                context_ids: ContextIDAllocator::new()?,
+            })
+        }
+    }
+
+    /// Returns the underlying native connection.
+    #[inline]
+    pub fn native_connection(&self) -> NativeConnection {
+        NativeConnection {
+            x11_display: self.native_connection.x11_display,
+        }
+    }
+
+    /// Returns the raw display connection underneath this connection: its Xlib display.
+    #[inline]
+    pub fn native_display(&self) -> NativeDisplayHandle {
+        NativeDisplayHandle::Xlib {
+            display: self.native_connection.x11_display as *mut c_void,
+            egl_display: ptr::null_mut(),
+        }
+    }
+
+    /// Returns the OpenGL API flavor that this connection supports (OpenGL or OpenGL ES).
+    #[inline]
+    pub fn gl_api(&self) -> GLApi {
+        GLApi::GL
+    }
+
+    /// Returns the displays connected to the system.
+    ///
+    /// Displays are enumerated through the RandR extension. If `libXrandr` can't be loaded, this
+    /// returns `UnsupportedOnThisPlatform`.
+    pub fn displays(&self) -> Result<Vec<DisplayInfo>, Error> {
+        let display_guard = self.native_connection.lock_display();
+        unsafe { xrandr::displays(display_guard.display()) }
+    }
+
+    /// Returns the "best" adapter on this system, preferring high-performance hardware adapters.
+    ///
+    /// This is an alias for `Connection::create_hardware_adapter()`.
+    #[inline]
+    pub fn create_adapter(&self) -> Result<Adapter, Error> {
+        self.create_hardware_adapter()
+    }
+
+    /// Returns the "best" adapter on this system, preferring high-performance hardware adapters.
+    #[inline]
+    pub fn create_hardware_adapter(&self) -> Result<Adapter, Error> {
+        if overrides::software_rendering_forced() {
+            return self.create_software_adapter();
+        }
+        Ok(Adapter::hardware())
+    }
+
+    /// Returns the "best" adapter on this system, preferring low-power hardware adapters.
+    #[inline]
+    pub fn create_low_power_adapter(&self) -> Result<Adapter, Error> {
+        if overrides::software_rendering_forced() {
+            return self.create_software_adapter();
+        }
+        Ok(Adapter::low_power())
+    }
+
+    /// Returns the "best" adapter on this system, preferring software adapters.
+    #[inline]
+    pub fn create_software_adapter(&self) -> Result<Adapter, Error> {
+        Ok(Adapter::software())
+    }
+
+    /// Returns the adapter for the GPU that drives the given display.
+    ///
+    /// The GPU is found by matching the display's name against the DRM connectors in sysfs, and
+    /// selected by pointing Mesa's `DRI_PRIME` variable at its PCI slot.
+    #[inline]
+    pub fn create_adapter_for_display(&self, display: &DisplayInfo) -> Result<Adapter, Error> {
+        Ok(Adapter::for_display(display)?)
+    }
+
+    /// Opens the hardware device corresponding to the given adapter.
+    ///
+    /// Device handles can be cloned and sent to other threads.
+    #[inline]
+    pub(crate) fn create_device_impl(&self, adapter: &Adapter) -> Result<Device, Error> {
+        Device::new(self, adapter)
+    }
+
+    /// Opens the hardware device corresponding to the adapter wrapped in the given native
+    /// device.
+    ///
+    /// This is present for compatibility with other backends.
+    #[inline]
+    pub(crate) unsafe fn create_device_from_native_device_impl(
+        &self,
+        native_device: NativeDevice,
+    ) -> Result<Device, Error> {
+        Device::new(self, &native_device.adapter)
+    }
+
+    /// Opens the display connection corresponding to the given `winit` window.
+    #[cfg(feature = "sm-winit")]
+    pub fn from_winit_window(window: &Window) -> Result<Connection, Error> {
+        if let Some(display) = window.xlib_display() {
+            Connection::from_x11_display(display as *mut Display, false)
+        } else {
+            Err(Error::IncompatibleWinitWindow)
+        }
+    }
+
+    /// Creates a native widget type from the given `winit` window.
+    ///
+    /// This type can be later used to create surfaces that render to the window.
+    #[cfg(feature = "sm-winit")]
+    pub fn create_native_widget_from_winit_window(
+        &self,
+        window: &Window,
+    ) -> Result<NativeWidget, Error> {
+        match window.xlib_window() {
+            Some(window) => Ok(NativeWidget { window }),
+            None => Err(Error::IncompatibleNativeWidget),
+        }
+    }
+
+    /// Create a native widget from a raw pointer
+    ///
+    /// # Safety
+    ///
+    /// The pointer must hold the ID of an X11 window on this connection's display, which must
+    /// outlive the native widget and any surfaces created from it.
+    pub unsafe fn create_native_widget_from_ptr(
+        &self,
+        raw: *mut c_void,
+        _size: Size2D<i32>,
+    ) -> NativeWidget {
+        NativeWidget {
+            window: raw as usize as x11::xlib::Window,
+        }
+    }
+
+    /// Creates a native widget from a handle to a native window or view.
+    ///
+    /// Only X11 window handles are accepted.
+    ///
+    /// # Safety
+    ///
+    /// The handle must refer to a live window or view, which must outlive the native widget and any
+    /// surfaces created from it.
+    pub unsafe fn create_native_widget_from_handle(
+        &self,
+        handle: NativeWidgetHandle,
+    ) -> Result<NativeWidget, Error> {
+        match handle {
+            NativeWidgetHandle::X11Window(window) => Ok(NativeWidget { window }),
+            _ => Err(Error::IncompatibleNativeWidget),
+        }
+    }
+
+    /// Create a native widget type from the given `raw_window_handle::HasRawWindowHandle`.
+    #[cfg(feature = "sm-raw-window-handle")]
+    pub fn create_native_widget_from_rwh(
+        &self,
+        raw_handle: raw_window_handle::RawWindowHandle,
+    ) -> Result<NativeWidget, Error> {
+        use raw_window_handle::RawWindowHandle::Xlib;
+
+        match raw_handle {
+            Xlib(handle) => Ok(NativeWidget {
+                window: handle.window,
+            }),
+            _ => Err(Error::IncompatibleNativeWidget),
+        }
+    }
+}
+
+impl NativeConnectionWrapper {
+    #[inline]
+    pub(crate) fn lock_display(&self) -> DisplayGuard<'_> {
+        unsafe { DisplayGuard::new(self.x11_display) }
+    }
+}
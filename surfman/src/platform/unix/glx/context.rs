@@ -0,0 +1,1068 @@
+// surfman/surfman/src/platform/unix/glx/context.rs
+//
+//! OpenGL rendering contexts on X11 via GLX.
+
+use super::connection::Connection;
+use super::device::Device;
+use super::ffi::GLX_CONTEXT_PROFILE_MASK_ARB;
+use super::ffi::{glx, GLXFunctions};
+use super::ffi::{GLX_BIND_TO_TEXTURE_RGBA_EXT, GLX_BIND_TO_TEXTURE_RGB_EXT};
+use super::ffi::{GLX_BIND_TO_TEXTURE_TARGETS_EXT, GLX_TEXTURE_2D_BIT_EXT};
+use super::ffi::{GLX_CONTEXT_COMPATIBILITY_PROFILE_BIT_ARB, GLX_CONTEXT_CORE_PROFILE_BIT_ARB};
+use super::ffi::{GLX_CONTEXT_DEBUG_BIT_ARB, GLX_CONTEXT_FLAGS_ARB};
+use super::ffi::{GLX_CONTEXT_MAJOR_VERSION_ARB, GLX_CONTEXT_MINOR_VERSION_ARB};
+use super::surface::{self, Surface, SurfaceDrawable};
+use crate::context;
+use crate::gl::types::GLuint;
+use crate::gl_utils;
+#[cfg(feature = "sm-opencl")]
+use crate::opencl::{GLSharingDisplay, GLSharingProperties};
+use crate::overrides;
+use crate::platform::unix::x11::connection::trap_x_errors;
+use crate::surface::{ExternalFramebuffer, Framebuffer};
+use crate::{
+    ContextAttributeFlags, ContextAttributes, ContextDescriptorID, ContextID, Error, GLVersion, Gl,
+};
+use crate::{GLApi, SurfaceInfo, WindowingApiError};
+
+use euclid::default::Size2D;
+use std::ffi::CString;
+use std::mem;
+use std::os::raw::{c_int, c_uchar, c_void};
+use std::ptr;
+use std::slice;
+use std::thread;
+use x11::glx::{GLXContext, GLXDrawable, GLXFBConfig, GLX_ALPHA_SIZE, GLX_BLUE_SIZE};
+use x11::glx::{GLX_DEPTH_SIZE, GLX_DONT_CARE, GLX_DOUBLEBUFFER, GLX_DRAWABLE_TYPE};
+use x11::glx::{GLX_FBCONFIG_ID, GLX_GREEN_SIZE, GLX_PIXMAP_BIT, GLX_RED_SIZE, GLX_RENDER_TYPE};
+use x11::glx::{GLX_RGBA_BIT, GLX_RGBA_TYPE, GLX_STENCIL_SIZE, GLX_WINDOW_BIT, GLX_X_RENDERABLE};
+use x11::glx::{GLX_SAMPLES, GLX_SAMPLE_BUFFERS, GLX_STEREO};
+use x11::xlib::{Display, False, True, XFree};
+
+const DUMMY_PIXMAP_SIZE: i32 = 16;
+const RGB_CHANNEL_BIT_DEPTH: c_int = 8;
+
+thread_local! {
+    #[doc(hidden)]
+    pub static GL_FUNCTIONS: Gl = Gl::load_with(get_proc_address);
+}
+
+/// Represents an OpenGL rendering context.
+///
+/// A context allows you to issue rendering commands to a surface. When initially created, a
+/// context has no attached surface, so rendering commands will fail or be ignored. Typically, you
+/// attach a surface to the context before rendering.
+///
+/// Contexts take ownership of the surfaces attached to them. In order to mutate a surface in any
+/// way other than rendering to it (e.g. presenting it to a window, which causes a buffer swap), it
+/// must first be detached from its context. Each surface is associated with a single context upon
+/// creation and may not be rendered to from any other context. However, you can wrap a surface in
+/// a surface texture, which allows the surface to be read from another context.
+///
+/// OpenGL objects may not be shared across contexts directly, but surface textures effectively
+/// allow for sharing of texture data. Contexts are local to a single thread and device.
+///
+/// A context must be explicitly destroyed with `destroy_context()`, or a panic will occur.
+pub struct Context {
+    pub(crate) glx_context: GLXContext,
+    pub(crate) id: ContextID,
+    pub(crate) fb_config_id: c_int,
+    // GLX has no notion of a context without a drawable, so the context is made current on this
+    // pixmap when no surface is bound.
+    dummy_drawable: Option<SurfaceDrawable>,
+    pub(crate) framebuffer: Framebuffer<Surface, ExternalGLXDrawables>,
+    status: ContextStatus,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ContextStatus {
+    Owned,
+    Referenced,
+    Destroyed,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct ExternalGLXDrawables {
+    draw: GLXDrawable,
+    read: GLXDrawable,
+}
+
+/// Wrapper for a native `GLXContext`.
+#[derive(Clone, Copy)]
+pub struct NativeContext {
+    /// The GLX context.
+    pub glx_context: GLXContext,
+    /// The GLX drawable that is to be attached to that context for reading.
+    pub glx_read_drawable: GLXDrawable,
+    /// The GLX drawable that is to be attached to that context for drawing.
+    pub glx_draw_drawable: GLXDrawable,
+}
+
+/// Information needed to create a context. Some APIs call this a "config" or a "pixel format".
+///
+/// These are local to a device. Descriptors compare equal when they name the same GLX
+/// framebuffer config and request the same version, profile, and extensions.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ContextDescriptor {
+    pub(crate) fb_config_id: c_int,
+    pub(crate) gl_version: GLVersion,
+    // The highest version to try when creating contexts, which is `gl_version` unless a range was
+    // asked for.
+    pub(crate) max_gl_version: GLVersion,
+    pub(crate) compatibility_profile: bool,
+    pub(crate) debug: bool,
+    pub(crate) required_extensions: Vec<String>,
+}
+
+impl ContextDescriptor {
+    /// Returns the `GLX_FBCONFIG_ID` of the GLX framebuffer config that contexts created from
+    /// this descriptor use.
+    ///
+    /// Libraries that create their own contexts can look the config up on the same display with
+    /// `glXChooseFBConfig()` and a `GLX_FBCONFIG_ID` attribute, to match surfman's configuration.
+    #[inline]
+    pub fn fb_config_id(&self) -> c_int {
+        self.fb_config_id
+    }
+}
+
+#[must_use]
+pub(crate) struct CurrentContextGuard {
+    display: *mut Display,
+    old_draw_drawable: GLXDrawable,
+    old_read_drawable: GLXDrawable,
+    old_glx_context: GLXContext,
+}
+
+impl Drop for Context {
+    #[inline]
+    fn drop(&mut self) {
+        if self.status != ContextStatus::Destroyed && !thread::panicking() {
+            panic!("Contexts must be destroyed explicitly with `destroy_context`!")
+        }
+    }
+}
+
+impl Drop for CurrentContextGuard {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.display.is_null() {
+                (glx().MakeContextCurrent)(
+                    self.display,
+                    self.old_draw_drawable,
+                    self.old_read_drawable,
+                    self.old_glx_context,
+                );
+            }
+        }
+    }
+}
+
+impl Device {
+    /// Creates a context descriptor with the given attributes.
+    ///
+    /// Context descriptors are local to this device.
+    ///
+    /// Descriptors are cached by this device, so asking again for the same attributes returns a
+    /// copy of the earlier descriptor without choosing a GLX framebuffer config again.
+    #[inline]
+    pub(crate) fn create_context_descriptor_impl(
+        &self,
+        attributes: &ContextAttributes,
+    ) -> Result<ContextDescriptor, Error> {
+        let attributes = &overrides::context_attributes(attributes);
+        self.context_descriptors
+            .get_or_insert_with(attributes, || self.choose_context_descriptor(attributes))
+    }
+
+    /// Returns a small ID for the given context descriptor, which is the same for every equal
+    /// descriptor.
+    ///
+    /// The IDs are shared with clones of this device.
+    #[inline]
+    pub fn intern_context_descriptor(
+        &self,
+        context_descriptor: &ContextDescriptor,
+    ) -> ContextDescriptorID {
+        self.context_descriptors.intern(context_descriptor)
+    }
+
+    fn choose_context_descriptor(
+        &self,
+        attributes: &ContextAttributes,
+    ) -> Result<ContextDescriptor, Error> {
+        // Set environment variables as appropriate.
+        self.adapter.set_environment_variables();
+
+        let flags = attributes.flags;
+        let alpha_size = if flags.contains(ContextAttributeFlags::ALPHA) {
+            8
+        } else {
+            0
+        };
+        let depth_size = if flags.contains(ContextAttributeFlags::DEPTH) {
+            24
+        } else {
+            0
+        };
+        let stencil_size = if flags.contains(ContextAttributeFlags::STENCIL) {
+            8
+        } else {
+            0
+        };
+
+        let compatibility_profile = flags.contains(ContextAttributeFlags::COMPATIBILITY_PROFILE);
+
+        // Mesa doesn't support the OpenGL compatibility profile post version 3.0. Take that into
+        // account.
+        if compatibility_profile
+            && (attributes.version.major > 3
+                || attributes.version.major == 3 && attributes.version.minor > 0)
+        {
+            return Err(Error::UnsupportedGLProfile);
+        }
+
+        // Floating-point configs can't back the pixmaps that generic surfaces are made of.
+        if flags.contains(ContextAttributeFlags::FLOAT_COLOR) {
+            return Err(Error::UnsupportedOnThisPlatform);
+        }
+
+        // As with EGL, we check the color channel sizes separately, because
+        // `glXChooseFBConfig()` on its own might give us 32-bit color when 24-bit color is
+        // requested.
+        let required_config_attributes = [
+            GLX_RED_SIZE,
+            RGB_CHANNEL_BIT_DEPTH,
+            GLX_GREEN_SIZE,
+            RGB_CHANNEL_BIT_DEPTH,
+            GLX_BLUE_SIZE,
+            RGB_CHANNEL_BIT_DEPTH,
+        ];
+
+        // Generic surfaces are pixmaps and widget surfaces are windows, so the config must
+        // support both. Pixmaps are single-buffered, so don't insist on double-buffering.
+        //
+        // Stereo configs are only offered for double-buffered windows, so contexts that ask for
+        // stereo can only render to widget surfaces.
+        let stereo = flags.contains(ContextAttributeFlags::STEREO);
+        let (drawable_type, double_buffer) = if stereo {
+            (GLX_WINDOW_BIT, True)
+        } else {
+            (GLX_WINDOW_BIT | GLX_PIXMAP_BIT, GLX_DONT_CARE)
+        };
+        let glx = glx();
+        let display_guard = self.native_connection.lock_display();
+        let display = display_guard.display();
+
+        // Try each sample count in turn, settling for fewer samples if the requested count isn't
+        // available.
+        let mut fb_config_id = None;
+        for samples in attributes.sample_counts() {
+            let mut required_config_attributes = required_config_attributes.to_vec();
+            if samples > 1 {
+                required_config_attributes.extend_from_slice(&[
+                    GLX_SAMPLE_BUFFERS,
+                    1,
+                    GLX_SAMPLES,
+                    samples as c_int,
+                ]);
+            }
+
+            let mut requested_config_attributes = required_config_attributes.clone();
+            requested_config_attributes.extend_from_slice(&[
+                GLX_ALPHA_SIZE,
+                alpha_size,
+                GLX_DEPTH_SIZE,
+                depth_size,
+                GLX_STENCIL_SIZE,
+                stencil_size,
+                GLX_X_RENDERABLE,
+                True,
+                GLX_DRAWABLE_TYPE,
+                drawable_type,
+                GLX_RENDER_TYPE,
+                GLX_RGBA_BIT,
+                GLX_DOUBLEBUFFER,
+                double_buffer,
+                GLX_STEREO,
+                if stereo { True } else { False },
+            ]);
+            if self.native_connection.texture_from_pixmap_supported && !stereo {
+                let bind_to_texture = if alpha_size != 0 {
+                    GLX_BIND_TO_TEXTURE_RGBA_EXT
+                } else {
+                    GLX_BIND_TO_TEXTURE_RGB_EXT
+                };
+                requested_config_attributes.extend_from_slice(&[
+                    bind_to_texture,
+                    True,
+                    GLX_BIND_TO_TEXTURE_TARGETS_EXT,
+                    GLX_TEXTURE_2D_BIT_EXT,
+                ]);
+            }
+            requested_config_attributes.push(0);
+
+            unsafe {
+                let mut config_count = 0;
+                let configs = (glx.ChooseFBConfig)(
+                    display,
+                    self.native_connection.screen,
+                    requested_config_attributes.as_ptr(),
+                    &mut config_count,
+                );
+                if configs.is_null() {
+                    continue;
+                }
+
+                // Sanitize configs.
+                let fb_config = slice::from_raw_parts(configs, config_count as usize)
+                    .iter()
+                    .cloned()
+                    .find(|&fb_config| {
+                        required_config_attributes.chunks(2).all(|pair| {
+                            get_fb_config_attr(glx, display, fb_config, pair[0]) == pair[1]
+                        })
+                    });
+                fb_config_id = fb_config
+                    .map(|fb_config| get_fb_config_attr(glx, display, fb_config, GLX_FBCONFIG_ID));
+                XFree(configs as *mut c_void);
+            }
+            if fb_config_id.is_some() {
+                break;
+            }
+        }
+
+        match fb_config_id {
+            None => Err(Error::NoPixelFormatFound),
+            Some(fb_config_id) => Ok(ContextDescriptor {
+                fb_config_id,
+                gl_version: attributes.version,
+                max_gl_version: attributes.highest_version(),
+                compatibility_profile,
+                debug: flags.contains(ContextAttributeFlags::DEBUG),
+                required_extensions: attributes.required_extensions.clone(),
+            }),
+        }
+    }
+
+    /// Creates a new OpenGL context.
+    ///
+    /// The context initially has no surface attached. Until a surface is bound to it, rendering
+    /// commands will fail or have no effect.
+    ///
+    /// Without `GLX_ARB_create_context`, only OpenGL 2.x contexts can be created.
+    pub(crate) fn create_context_impl(
+        &mut self,
+        descriptor: &ContextDescriptor,
+        share_with: Option<&Context>,
+    ) -> Result<Context, Error> {
+        let glx = glx();
+        let display_guard = self.native_connection.lock_display();
+        let display = display_guard.display();
+        unsafe {
+            let fb_config = fb_config_from_id(
+                glx,
+                display,
+                self.native_connection.screen,
+                descriptor.fb_config_id,
+            )?;
+            let share_context = share_with.map_or(ptr::null_mut(), |context| context.glx_context);
+
+            let glx_context = match glx.CreateContextAttribsARB {
+                Some(create_context_attribs) if self.native_connection.create_context_supported => {
+                    let profile_mask = if descriptor.compatibility_profile {
+                        GLX_CONTEXT_COMPATIBILITY_PROFILE_BIT_ARB
+                    } else {
+                        GLX_CONTEXT_CORE_PROFILE_BIT_ARB
+                    };
+                    // Try the highest version first. Versions that the driver doesn't support fail
+                    // with an X error.
+                    let gl_versions = context::gl_versions(
+                        GLApi::GL,
+                        descriptor.gl_version,
+                        descriptor.max_gl_version,
+                    );
+                    gl_versions.into_iter().find_map(|gl_version| {
+                        let glx_context_attributes = [
+                            GLX_CONTEXT_MAJOR_VERSION_ARB,
+                            gl_version.major as c_int,
+                            GLX_CONTEXT_MINOR_VERSION_ARB,
+                            gl_version.minor as c_int,
+                            GLX_CONTEXT_PROFILE_MASK_ARB,
+                            profile_mask,
+                            GLX_CONTEXT_FLAGS_ARB,
+                            if descriptor.debug {
+                                GLX_CONTEXT_DEBUG_BIT_ARB
+                            } else {
+                                0
+                            },
+                            0,
+                        ];
+                        trap_x_errors(display, || {
+                            create_context_attribs(
+                                display,
+                                fb_config,
+                                share_context,
+                                True,
+                                glx_context_attributes.as_ptr(),
+                            )
+                        })
+                        .filter(|glx_context| !glx_context.is_null())
+                    })
+                }
+                _ if descriptor.gl_version.major < 3 => trap_x_errors(display, || {
+                    (glx.CreateNewContext)(display, fb_config, GLX_RGBA_TYPE, share_context, True)
+                }),
+                _ => return Err(Error::RequiredExtensionUnavailable),
+            };
+            let glx_context = match glx_context {
+                Some(glx_context) if !glx_context.is_null() => glx_context,
+                _ => return Err(Error::ContextCreationFailed(WindowingApiError::Failed)),
+            };
+
+            let dummy_size = Size2D::new(DUMMY_PIXMAP_SIZE, DUMMY_PIXMAP_SIZE);
+            let dummy_drawable = match surface::create_pixmap_drawable(
+                glx,
+                display,
+                self.native_connection.screen,
+                fb_config,
+                &dummy_size,
+                None,
+            ) {
+                Ok(dummy_drawable) => dummy_drawable,
+                Err(err) => {
+                    (glx.DestroyContext)(display, glx_context);
+                    return Err(err);
+                }
+            };
+
+            let context = Context {
+                glx_context,
+                id: self.context_ids.next(),
+                fb_config_id: descriptor.fb_config_id,
+                dummy_drawable: Some(dummy_drawable),
+                framebuffer: Framebuffer::None,
+                status: ContextStatus::Owned,
+            };
+            Ok(context)
+        }
+    }
+
+    /// Wraps a `GLXContext` in a native context and returns it.
+    ///
+    /// The context is not retained, as there is no way to do this in the GLX API. Therefore, it
+    /// is the caller's responsibility to ensure that the returned `Context` object remains alive
+    /// as long as the `GLXContext` is.
+    pub(crate) unsafe fn create_context_from_native_context_impl(
+        &self,
+        native_context: NativeContext,
+    ) -> Result<Context, Error> {
+        let display_guard = self.native_connection.lock_display();
+        let mut fb_config_id = 0;
+        (glx().QueryContext)(
+            display_guard.display(),
+            native_context.glx_context,
+            GLX_FBCONFIG_ID,
+            &mut fb_config_id,
+        );
+
+        let context = Context {
+            glx_context: native_context.glx_context,
+            id: self.context_ids.next(),
+            fb_config_id,
+            dummy_drawable: None,
+            framebuffer: Framebuffer::External(ExternalGLXDrawables {
+                draw: native_context.glx_draw_drawable,
+                read: native_context.glx_read_drawable,
+            }),
+            status: ContextStatus::Referenced,
+        };
+        Ok(context)
+    }
+
+    /// Wraps the GLX context that is current on this thread, along with its Xlib display, in a new
+    /// device and context.
+    ///
+    /// If no context is current, this returns a `NoCurrentContext` error.
+    ///
+    /// # Safety
+    ///
+    /// Neither is retained, so the caller must keep both alive as long as the device and context
+    /// are.
+    pub unsafe fn from_current_context() -> Result<(Device, Context), Error> {
+        let native_context = NativeContext::current()?;
+        let connection = Connection::from_x11_display((glx().GetCurrentDisplay)(), false)?;
+        let device = connection.create_device(&connection.create_adapter()?)?;
+        let context = device.create_context_from_native_context(native_context)?;
+        Ok((device, context))
+    }
+
+    /// Destroys a context.
+    ///
+    /// The context must have been created on this device.
+    pub(crate) fn destroy_context_impl(&self, context: &mut Context) -> Result<(), Error> {
+        if context.status == ContextStatus::Destroyed {
+            return Ok(());
+        }
+
+        if let Ok(Some(mut surface)) = self.unbind_surface_from_context_impl(context) {
+            self.destroy_surface(context, &mut surface)?;
+        }
+
+        let glx = glx();
+        let display_guard = self.native_connection.lock_display();
+        let display = display_guard.display();
+        unsafe {
+            if (glx.GetCurrentContext)() == context.glx_context {
+                (glx.MakeContextCurrent)(display, 0, 0, ptr::null_mut());
+            }
+
+            if let Some(mut dummy_drawable) = context.dummy_drawable.take() {
+                surface::destroy_drawable(glx, display, &mut dummy_drawable);
+            }
+
+            if context.status == ContextStatus::Owned {
+                (glx.DestroyContext)(display, context.glx_context);
+            }
+        }
+
+        context.glx_context = ptr::null_mut();
+        context.status = ContextStatus::Destroyed;
+        Ok(())
+    }
+
+    /// Given a context, returns its underlying GLX context and attached drawables.
+    pub fn native_context(&self, context: &Context) -> NativeContext {
+        let (glx_draw_drawable, glx_read_drawable) = self.context_drawables(context);
+        NativeContext {
+            glx_context: context.glx_context,
+            glx_draw_drawable,
+            glx_read_drawable,
+        }
+    }
+
+    /// Returns the descriptor that this context was created with.
+    pub fn context_descriptor(&self, context: &Context) -> ContextDescriptor {
+        GL_FUNCTIONS.with(|gl| {
+            let _guard = self.temporarily_make_context_current(context);
+            let gl_version = GLVersion::current(gl);
+            let compatibility_profile = context::current_context_uses_compatibility_profile(gl);
+            let debug = context::current_context_is_debug(gl);
+
+            ContextDescriptor {
+                fb_config_id: context.fb_config_id,
+                gl_version,
+                max_gl_version: gl_version,
+                compatibility_profile,
+                debug,
+                required_extensions: vec![],
+            }
+        })
+    }
+
+    /// Makes the context the current OpenGL context for this thread.
+    ///
+    /// After calling this function, it is valid to use OpenGL rendering commands.
+    pub(crate) fn make_context_current_impl(&self, context: &Context) -> Result<(), Error> {
+        let (draw_drawable, read_drawable) = self.context_drawables(context);
+        let display_guard = self.native_connection.lock_display();
+        unsafe {
+            let ok = (glx().MakeContextCurrent)(
+                display_guard.display(),
+                draw_drawable,
+                read_drawable,
+                context.glx_context,
+            );
+            if ok != 0 {
+                Ok(())
+            } else {
+                Err(Error::MakeCurrentFailed(WindowingApiError::Failed))
+            }
+        }
+    }
+
+    /// Makes the context the current OpenGL context for this thread, rendering to the given
+    /// surface instead of any surface bound to it.
+    ///
+    /// The surface must have been created with this context, or an `IncompatibleSurface` error is
+    /// returned.
+    pub(crate) fn make_context_current_with_surface_impl(
+        &self,
+        context: &Context,
+        surface: &Surface,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        let drawable = surface.drawable.glx_drawable();
+        let display_guard = self.native_connection.lock_display();
+        unsafe {
+            let ok = (glx().MakeContextCurrent)(
+                display_guard.display(),
+                drawable,
+                drawable,
+                context.glx_context,
+            );
+            if ok != 0 {
+                Ok(())
+            } else {
+                Err(Error::MakeCurrentFailed(WindowingApiError::Failed))
+            }
+        }
+    }
+
+    /// Removes the current OpenGL context from this thread.
+    ///
+    /// After calling this function, OpenGL rendering commands will fail until a new context is
+    /// made current.
+    pub fn make_no_context_current(&self) -> Result<(), Error> {
+        let display_guard = self.native_connection.lock_display();
+        unsafe {
+            let ok = (glx().MakeContextCurrent)(display_guard.display(), 0, 0, ptr::null_mut());
+            if ok != 0 {
+                Ok(())
+            } else {
+                Err(Error::MakeCurrentFailed(WindowingApiError::Failed))
+            }
+        }
+    }
+
+    #[inline]
+    pub(crate) fn temporarily_make_context_current(
+        &self,
+        context: &Context,
+    ) -> Result<CurrentContextGuard, Error> {
+        let guard = CurrentContextGuard::new();
+        self.make_context_current_impl(context)?;
+        Ok(guard)
+    }
+
+    // Calls the given function with the OpenGL functions, which are loaded once per thread and
+    // shared by all contexts.
+    #[inline]
+    pub(crate) fn with_gl_functions<F, R>(&self, _: &Context, callback: F) -> R
+    where
+        F: FnOnce(&Gl) -> R,
+    {
+        GL_FUNCTIONS.with(|gl| callback(gl))
+    }
+
+    fn context_drawables(&self, context: &Context) -> (GLXDrawable, GLXDrawable) {
+        match context.framebuffer {
+            Framebuffer::Surface(ref surface) => {
+                let drawable = surface.drawable.glx_drawable();
+                (drawable, drawable)
+            }
+            Framebuffer::External(ref drawables) => (drawables.draw, drawables.read),
+            Framebuffer::None | Framebuffer::ExternalFramebuffer(_) => match context.dummy_drawable
+            {
+                Some(ref dummy_drawable) => {
+                    let drawable = dummy_drawable.glx_drawable();
+                    (drawable, drawable)
+                }
+                None => (0, 0),
+            },
+        }
+    }
+
+    /// Returns the attributes that the context descriptor was created with.
+    pub fn context_descriptor_attributes(
+        &self,
+        context_descriptor: &ContextDescriptor,
+    ) -> ContextAttributes {
+        let glx = glx();
+        let display_guard = self.native_connection.lock_display();
+        let display = display_guard.display();
+        unsafe {
+            let fb_config = fb_config_from_id(
+                glx,
+                display,
+                self.native_connection.screen,
+                context_descriptor.fb_config_id,
+            )
+            .expect("How did you make a context descriptor with an invalid config?");
+
+            let alpha_size = get_fb_config_attr(glx, display, fb_config, GLX_ALPHA_SIZE);
+            let depth_size = get_fb_config_attr(glx, display, fb_config, GLX_DEPTH_SIZE);
+            let stencil_size = get_fb_config_attr(glx, display, fb_config, GLX_STENCIL_SIZE);
+            let stereo = get_fb_config_attr(glx, display, fb_config, GLX_STEREO);
+            let samples = get_fb_config_samples(glx, display, fb_config);
+
+            // Convert to `surfman` context attribute flags.
+            let mut attribute_flags = ContextAttributeFlags::empty();
+            attribute_flags.set(ContextAttributeFlags::ALPHA, alpha_size != 0);
+            attribute_flags.set(ContextAttributeFlags::DEPTH, depth_size != 0);
+            attribute_flags.set(ContextAttributeFlags::STENCIL, stencil_size != 0);
+            attribute_flags.set(ContextAttributeFlags::STEREO, stereo != 0);
+
+            attribute_flags.set(
+                ContextAttributeFlags::COMPATIBILITY_PROFILE,
+                context_descriptor.compatibility_profile,
+            );
+            attribute_flags.set(ContextAttributeFlags::DEBUG, context_descriptor.debug);
+
+            // Create appropriate context attributes.
+            ContextAttributes {
+                flags: attribute_flags,
+                version: context_descriptor.gl_version,
+                max_version: Some(context_descriptor.max_gl_version)
+                    .filter(|&version| version > context_descriptor.gl_version),
+                required_extensions: context_descriptor.required_extensions.clone(),
+                samples,
+            }
+        }
+    }
+
+    /// Fetches the address of an OpenGL function associated with this context.
+    ///
+    /// OpenGL functions are local to a context. You should not use OpenGL functions on one context
+    /// with any other context.
+    ///
+    /// This method is typically used with a function like `gl::load_with()` from the `gl` crate to
+    /// load OpenGL function pointers.
+    #[inline]
+    pub fn get_proc_address(&self, _: &Context, symbol_name: &str) -> *const c_void {
+        get_proc_address(symbol_name)
+    }
+
+    /// Attaches a surface to a context for rendering.
+    ///
+    /// This function takes ownership of the surface. The surface must have been created with this
+    /// context, or an `IncompatibleSurface` error is returned.
+    ///
+    /// If this function is called with a surface already bound, a `SurfaceAlreadyBound` error is
+    /// returned. To avoid this error, first unbind the existing surface with
+    /// `unbind_surface_from_context`.
+    ///
+    /// If an error is returned, the surface is returned alongside it.
+    pub(crate) fn bind_surface_to_context_impl(
+        &self,
+        context: &mut Context,
+        surface: Surface,
+    ) -> Result<(), (Error, Surface)> {
+        if context.id != surface.context_id {
+            return Err((Error::IncompatibleSurface, surface));
+        }
+
+        match context.framebuffer {
+            Framebuffer::None => {}
+            Framebuffer::External(_) | Framebuffer::ExternalFramebuffer(_) => {
+                return Err((Error::ExternalRenderTarget, surface))
+            }
+            Framebuffer::Surface(_) => return Err((Error::SurfaceAlreadyBound, surface)),
+        }
+
+        let is_current = self.context_is_current(context);
+        context.framebuffer = Framebuffer::Surface(surface);
+
+        if is_current {
+            // We need to make ourselves current again, because the surface changed.
+            drop(self.make_context_current_impl(context));
+        }
+
+        Ok(())
+    }
+
+    /// Removes and returns any attached surface from this context.
+    ///
+    /// Any pending OpenGL commands targeting this surface will be automatically flushed, so the
+    /// surface is safe to read from immediately when this function returns.
+    pub(crate) fn unbind_surface_from_context_impl(
+        &self,
+        context: &mut Context,
+    ) -> Result<Option<Surface>, Error> {
+        match context.framebuffer {
+            Framebuffer::None => return Ok(None),
+            Framebuffer::Surface(_) => {}
+            Framebuffer::External(_) => return Err(Error::ExternalRenderTarget),
+            Framebuffer::ExternalFramebuffer(_) => {
+                context.framebuffer = Framebuffer::None;
+                return Ok(None);
+            }
+        }
+
+        let is_current = self.context_is_current(context);
+        if is_current {
+            GL_FUNCTIONS.with(|gl| unsafe { gl.Flush() });
+        }
+
+        let surface = match mem::replace(&mut context.framebuffer, Framebuffer::None) {
+            Framebuffer::Surface(surface) => surface,
+            Framebuffer::None | Framebuffer::External(_) | Framebuffer::ExternalFramebuffer(_) => {
+                unreachable!()
+            }
+        };
+
+        // If we're current, we stay current, but on the dummy pixmap.
+        if is_current {
+            drop(self.make_context_current_impl(context));
+        }
+
+        Ok(Some(surface))
+    }
+
+    /// Makes a context render to a framebuffer object that the caller created and owns, in place
+    /// of a surface.
+    ///
+    /// The framebuffer object must stay alive until it's detached again with
+    /// `unbind_surface_from_context()`.
+    pub fn bind_external_framebuffer(
+        &self,
+        context: &mut Context,
+        framebuffer_object: GLuint,
+        size: Size2D<i32>,
+    ) -> Result<(), Error> {
+        match context.framebuffer {
+            Framebuffer::None => {}
+            Framebuffer::External(_) | Framebuffer::ExternalFramebuffer(_) => {
+                return Err(Error::ExternalRenderTarget)
+            }
+            Framebuffer::Surface(_) => return Err(Error::SurfaceAlreadyBound),
+        }
+
+        // The context stays current on its dummy pixmap, as it does when nothing is bound.
+        context.framebuffer =
+            Framebuffer::ExternalFramebuffer(ExternalFramebuffer::new(framebuffer_object, size));
+        Ok(())
+    }
+
+    /// Makes the rendering submitted so far to a surface visible to everything else that reads
+    /// it.
+    ///
+    /// This flushes the context and waits on a fence for the rendering to finish, so that readers
+    /// that aren't implicitly synchronized with it, such as other APIs importing the surface's
+    /// memory, see all of it.
+    ///
+    /// The surface must have been created with the given context, or an `IncompatibleSurface`
+    /// error is returned.
+    pub(crate) fn flush_context_impl(
+        &self,
+        context: &Context,
+        surface: &Surface,
+    ) -> Result<(), Error> {
+        if surface.context_id != context.id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        let _guard = self.temporarily_make_context_current(context)?;
+        GL_FUNCTIONS.with(gl_utils::finish_with_fence);
+        Ok(())
+    }
+
+    #[inline]
+    fn context_is_current(&self, context: &Context) -> bool {
+        unsafe { (glx().GetCurrentContext)() == context.glx_context }
+    }
+
+    /// Returns a unique ID representing a context.
+    ///
+    /// This ID is unique to all currently-allocated contexts. If you destroy a context and create
+    /// a new one, the new context might have the same ID as the destroyed one.
+    #[inline]
+    pub fn context_id(&self, context: &Context) -> ContextID {
+        context.id
+    }
+
+    /// Returns various information about the surface attached to a context.
+    ///
+    /// This includes, most notably, the OpenGL framebuffer object needed to render to the surface.
+    pub fn context_surface_info(&self, context: &Context) -> Result<Option<SurfaceInfo>, Error> {
+        match context.framebuffer {
+            Framebuffer::None => Ok(None),
+            Framebuffer::External(_) => Err(Error::ExternalRenderTarget),
+            Framebuffer::ExternalFramebuffer(ref framebuffer) => {
+                Ok(Some(framebuffer.info(context.id)))
+            }
+            Framebuffer::Surface(ref surface) => Ok(Some(SurfaceInfo {
+                bound: true,
+                ..self.surface_info(surface)
+            })),
+        }
+    }
+}
+
+impl NativeContext {
+    /// Returns the current GLX context and drawables, if applicable.
+    ///
+    /// If there is no current GLX context, this returns a `NoCurrentContext` error.
+    pub fn current() -> Result<NativeContext, Error> {
+        let glx = glx();
+        unsafe {
+            let glx_context = (glx.GetCurrentContext)();
+            if glx_context.is_null() {
+                Err(Error::NoCurrentContext)
+            } else {
+                Ok(NativeContext {
+                    glx_context,
+                    glx_read_drawable: (glx.GetCurrentReadDrawable)(),
+                    glx_draw_drawable: (glx.GetCurrentDrawable)(),
+                })
+            }
+        }
+    }
+}
+
+impl CurrentContextGuard {
+    fn new() -> CurrentContextGuard {
+        let glx = glx();
+        unsafe {
+            CurrentContextGuard {
+                display: (glx.GetCurrentDisplay)(),
+                old_draw_drawable: (glx.GetCurrentDrawable)(),
+                old_read_drawable: (glx.GetCurrentReadDrawable)(),
+                old_glx_context: (glx.GetCurrentContext)(),
+            }
+        }
+    }
+}
+
+pub(crate) unsafe fn fb_config_from_id(
+    glx: &GLXFunctions,
+    display: *mut Display,
+    screen: c_int,
+    fb_config_id: c_int,
+) -> Result<GLXFBConfig, Error> {
+    // All other attributes are ignored when a config ID is given.
+    let config_attributes = [GLX_FBCONFIG_ID, fb_config_id, 0];
+
+    let mut config_count = 0;
+    let configs = (glx.ChooseFBConfig)(
+        display,
+        screen,
+        config_attributes.as_ptr(),
+        &mut config_count,
+    );
+    if configs.is_null() {
+        return Err(Error::IncompatibleContextDescriptor);
+    }
+    let fb_config = if config_count > 0 {
+        Ok(*configs)
+    } else {
+        Err(Error::IncompatibleContextDescriptor)
+    };
+    XFree(configs as *mut c_void);
+    fb_config
+}
+
+pub(crate) unsafe fn get_fb_config_attr(
+    glx: &GLXFunctions,
+    display: *mut Display,
+    fb_config: GLXFBConfig,
+    attr: c_int,
+) -> c_int {
+    let mut value = 0;
+    let result = (glx.GetFBConfigAttrib)(display, fb_config, attr, &mut value);
+    debug_assert_eq!(result, 0);
+    value
+}
+
+// Returns the number of samples per pixel of drawables created with the given config.
+// Single-sampled configs have 0 samples, which we report as 1.
+pub(crate) unsafe fn get_fb_config_samples(
+    glx: &GLXFunctions,
+    display: *mut Display,
+    fb_config: GLXFBConfig,
+) -> u32 {
+    get_fb_config_attr(glx, display, fb_config, GLX_SAMPLES).max(1) as u32
+}
+
+// Returns a single-sampled config with the same color, depth, and stencil sizes as the given one
+// that can back pixmaps, or the given config itself if it isn't multisampled. Generic surfaces are
+// allocated from it, so that their contents can be bound to textures without a multisample
+// resolve.
+pub(crate) unsafe fn single_sampled_fb_config(
+    glx: &GLXFunctions,
+    display: *mut Display,
+    screen: c_int,
+    fb_config: GLXFBConfig,
+    texture_from_pixmap_supported: bool,
+) -> Result<GLXFBConfig, Error> {
+    if get_fb_config_samples(glx, display, fb_config) == 1 {
+        return Ok(fb_config);
+    }
+
+    let mut required_config_attributes = vec![];
+    for &attr in &[
+        GLX_RED_SIZE,
+        GLX_GREEN_SIZE,
+        GLX_BLUE_SIZE,
+        GLX_ALPHA_SIZE,
+        GLX_DEPTH_SIZE,
+        GLX_STENCIL_SIZE,
+    ] {
+        required_config_attributes
+            .extend_from_slice(&[attr, get_fb_config_attr(glx, display, fb_config, attr)]);
+    }
+    required_config_attributes.extend_from_slice(&[GLX_SAMPLE_BUFFERS, 0]);
+
+    let mut requested_config_attributes = required_config_attributes.clone();
+    requested_config_attributes.extend_from_slice(&[
+        GLX_X_RENDERABLE,
+        True,
+        GLX_DRAWABLE_TYPE,
+        GLX_PIXMAP_BIT,
+        GLX_RENDER_TYPE,
+        GLX_RGBA_BIT,
+    ]);
+    if texture_from_pixmap_supported {
+        let bind_to_texture = if get_fb_config_attr(glx, display, fb_config, GLX_ALPHA_SIZE) != 0 {
+            GLX_BIND_TO_TEXTURE_RGBA_EXT
+        } else {
+            GLX_BIND_TO_TEXTURE_RGB_EXT
+        };
+        requested_config_attributes.extend_from_slice(&[
+            bind_to_texture,
+            True,
+            GLX_BIND_TO_TEXTURE_TARGETS_EXT,
+            GLX_TEXTURE_2D_BIT_EXT,
+        ]);
+    }
+    requested_config_attributes.push(0);
+
+    let mut config_count = 0;
+    let configs = (glx.ChooseFBConfig)(
+        display,
+        screen,
+        requested_config_attributes.as_ptr(),
+        &mut config_count,
+    );
+    if configs.is_null() {
+        return Err(Error::NoPixelFormatFound);
+    }
+    let single_sampled_fb_config = slice::from_raw_parts(configs, config_count as usize)
+        .iter()
+        .cloned()
+        .find(|&fb_config| {
+            required_config_attributes
+                .chunks(2)
+                .all(|pair| get_fb_config_attr(glx, display, fb_config, pair[0]) == pair[1])
+        });
+    XFree(configs as *mut c_void);
+    single_sampled_fb_config.ok_or(Error::NoPixelFormatFound)
+}
+
+fn get_proc_address(symbol_name: &str) -> *const c_void {
+    unsafe {
+        let symbol_name: CString = CString::new(symbol_name).unwrap();
+        let symbol_ptr = symbol_name.as_ptr() as *const c_uchar;
+        match (glx().GetProcAddressARB)(symbol_ptr) {
+            Some(function) => function as *const c_void,
+            None => ptr::null(),
+        }
+    }
+}
+
+#[cfg(feature = "sm-opencl")]
+impl Device {
+    /// Returns what OpenCL needs to share objects with the given context through
+    /// `cl_khr_gl_sharing`.
+    pub fn gl_sharing_properties(&self, context: &Context) -> Result<GLSharingProperties, Error> {
+        Ok(GLSharingProperties {
+            gl_context: context.glx_context as *mut c_void,
+            display: GLSharingDisplay::GLX(
+                self.native_connection.lock_display().display() as *mut c_void
+            ),
+        })
+    }
+}
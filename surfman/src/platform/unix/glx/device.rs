@@ -0,0 +1,157 @@
+// surfman/surfman/src/platform/unix/glx/device.rs
+//
+//! A wrapper around X11 displays, for use with GLX.
+
+use super::connection::{Connection, NativeConnectionWrapper};
+use super::context::ContextDescriptor;
+use crate::context::{ContextDescriptorCache, ContextIDAllocator};
+use crate::device_events::{self, DeviceEventDispatcher};
+use crate::timings::{CreationTimings, CreationTimingsRecorder};
+use crate::{DeviceEventHandler, Error, GLApi, LifecycleObserver, NativeDeviceHandle};
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+pub use crate::platform::unix::generic::device::Adapter;
+
+/// A handle to a device.
+///
+/// Devices contain most of the relevant surface management methods. Clones share the device's
+/// display connection and can be sent to other threads, so each rendering thread can own a handle.
+#[derive(Clone)]
+pub struct Device {
+    pub(crate) native_connection: Arc<NativeConnectionWrapper>,
+    pub(crate) adapter: Adapter,
+    pub(crate) lifecycle_observer: Option<Arc<dyn LifecycleObserver>>,
+    pub(crate) device_event_dispatcher: DeviceEventDispatcher,
+    pub(crate) creation_timings: CreationTimingsRecorder,
+    pub(crate) memory_usage: Arc<AtomicUsize>,
+    pub(crate) context_ids: ContextIDAllocator,
+    pub(crate) context_descriptors: ContextDescriptorCache<ContextDescriptor>,
+}
+
+// Like the connection, the device can be used from any thread.
+unsafe impl Send for Device {}
+
+/// Wraps an adapter.
+///
+/// On X11, devices and adapters are essentially identical types.
+#[derive(Clone)]
+pub struct NativeDevice {
+    /// The hardware adapter corresponding to this device.
+    pub adapter: Adapter,
+}
+
+impl Device {
+    #[inline]
+    pub(crate) fn new(connection: &Connection, adapter: &Adapter) -> Result<Device, Error> {
+        Ok(Device {
+            native_connection: connection.native_connection.clone(),
+            adapter: (*adapter).clone(),
+            lifecycle_observer: None,
+            device_event_dispatcher: DeviceEventDispatcher::default(),
+            creation_timings: CreationTimingsRecorder::default(),
+            memory_usage: Arc::new(AtomicUsize::new(0)),
+            context_ids: connection.context_ids.clone(),
+            context_descriptors: ContextDescriptorCache::new(),
+        })
+    }
+
+    /// Returns the native device corresponding to this device.
+    ///
+    /// This method is essentially an alias for the `adapter()` method, since there is no
+    /// explicit concept of a device on this backend.
+    #[inline]
+    pub fn native_device(&self) -> NativeDevice {
+        NativeDevice {
+            adapter: self.adapter(),
+        }
+    }
+
+    /// Returns the raw display or GPU device underneath this device.
+    ///
+    /// GLX has no device object, so this returns `NativeDeviceHandle::None`. The Xlib display is
+    /// returned by `Connection::native_display()`.
+    #[inline]
+    pub fn native_device_handle(&self) -> NativeDeviceHandle {
+        NativeDeviceHandle::None
+    }
+
+    /// Returns the display server connection that this device was created with.
+    #[inline]
+    pub fn connection(&self) -> Connection {
+        Connection {
+            native_connection: self.native_connection.clone(),
+            context_ids: self.context_ids.clone(),
+        }
+    }
+
+    /// Returns the adapter that this device was created with.
+    #[inline]
+    pub fn adapter(&self) -> Adapter {
+        self.adapter.clone()
+    }
+
+    /// Returns the OpenGL API flavor that this device supports (OpenGL or OpenGL ES).
+    #[inline]
+    pub fn gl_api(&self) -> GLApi {
+        GLApi::GL
+    }
+
+    /// Installs an observer that is notified when contexts and surfaces of this device are
+    /// created, destroyed, resized, or presented, replacing any previous one.
+    #[inline]
+    pub fn set_lifecycle_observer(&mut self, observer: Option<Arc<dyn LifecycleObserver>>) {
+        self.lifecycle_observer = observer;
+    }
+
+    /// Returns the lifecycle observer installed on this device, if any.
+    #[inline]
+    pub fn lifecycle_observer(&self) -> Option<Arc<dyn LifecycleObserver>> {
+        self.lifecycle_observer.clone()
+    }
+
+    /// Installs a handler that is told when the GPU of this device is removed or reset, or the
+    /// displays are reconfigured, replacing any previous one.
+    ///
+    /// Display changes are found by checking `Connection::displays()` once a second.
+    pub fn set_device_event_handler(&mut self, handler: Option<DeviceEventHandler>) {
+        let connection = self.connection();
+        let poll = device_events::display_poller(move || connection.displays());
+        self.device_event_dispatcher.set_handler(handler, poll);
+    }
+
+    /// Returns the device event handler installed on this device, if any.
+    #[inline]
+    pub fn device_event_handler(&self) -> Option<DeviceEventHandler> {
+        self.device_event_dispatcher.handler()
+    }
+
+    #[inline]
+    pub(crate) fn device_event_dispatcher(&self) -> &DeviceEventDispatcher {
+        &self.device_event_dispatcher
+    }
+
+    /// Returns the GPU memory, in bytes, held by the surfaces of this device.
+    #[inline]
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub(crate) fn memory_usage_counter(&self) -> &AtomicUsize {
+        &self.memory_usage
+    }
+
+    /// Returns how long opening this device, and creating its context descriptors and contexts,
+    /// took.
+    #[inline]
+    pub fn creation_timings(&self) -> CreationTimings {
+        self.creation_timings.get()
+    }
+
+    #[inline]
+    pub(crate) fn creation_timings_recorder(&self) -> &CreationTimingsRecorder {
+        &self.creation_timings
+    }
+}
@@ -0,0 +1,250 @@
+// surfman/surfman/src/platform/unix/glx/ffi.rs
+//
+//! GLX entry points, loaded at runtime.
+//!
+//! `libGL` is opened with `dlopen()` rather than linked, so that a system without GLX simply
+//! can't create connections on this backend instead of failing to start.
+
+use std::ffi::CStr;
+use std::mem;
+use std::os::raw::{c_char, c_int, c_uchar, c_void};
+use x11::glx::{GLXContext, GLXDrawable, GLXFBConfig, GLXPixmap, GLXWindow};
+use x11::xlib::XVisualInfo;
+use x11::xlib::{Bool, Display, Pixmap, Window};
+
+pub(crate) const GLX_CONTEXT_MAJOR_VERSION_ARB: c_int = 0x2091;
+pub(crate) const GLX_CONTEXT_MINOR_VERSION_ARB: c_int = 0x2092;
+pub(crate) const GLX_CONTEXT_PROFILE_MASK_ARB: c_int = 0x9126;
+pub(crate) const GLX_CONTEXT_FLAGS_ARB: c_int = 0x2094;
+pub(crate) const GLX_CONTEXT_DEBUG_BIT_ARB: c_int = 0x0001;
+pub(crate) const GLX_CONTEXT_CORE_PROFILE_BIT_ARB: c_int = 0x0001;
+pub(crate) const GLX_CONTEXT_COMPATIBILITY_PROFILE_BIT_ARB: c_int = 0x0002;
+
+pub(crate) const GLX_BIND_TO_TEXTURE_RGB_EXT: c_int = 0x20d0;
+pub(crate) const GLX_BIND_TO_TEXTURE_RGBA_EXT: c_int = 0x20d1;
+pub(crate) const GLX_BIND_TO_TEXTURE_TARGETS_EXT: c_int = 0x20d3;
+pub(crate) const GLX_TEXTURE_FORMAT_EXT: c_int = 0x20d5;
+pub(crate) const GLX_TEXTURE_TARGET_EXT: c_int = 0x20d6;
+pub(crate) const GLX_TEXTURE_FORMAT_RGB_EXT: c_int = 0x20d9;
+pub(crate) const GLX_TEXTURE_FORMAT_RGBA_EXT: c_int = 0x20da;
+pub(crate) const GLX_TEXTURE_2D_EXT: c_int = 0x20dc;
+pub(crate) const GLX_FRONT_LEFT_EXT: c_int = 0x20de;
+pub(crate) const GLX_TEXTURE_2D_BIT_EXT: c_int = 0x0002;
+
+type QueryExtensionFn =
+    unsafe extern "C" fn(dpy: *mut Display, errorb: *mut c_int, event: *mut c_int) -> Bool;
+type QueryVersionFn =
+    unsafe extern "C" fn(dpy: *mut Display, maj: *mut c_int, min: *mut c_int) -> Bool;
+type QueryExtensionsStringFn =
+    unsafe extern "C" fn(dpy: *mut Display, screen: c_int) -> *const c_char;
+type ChooseFBConfigFn = unsafe extern "C" fn(
+    dpy: *mut Display,
+    screen: c_int,
+    attrib_list: *const c_int,
+    nelements: *mut c_int,
+) -> *mut GLXFBConfig;
+type GetFBConfigAttribFn = unsafe extern "C" fn(
+    dpy: *mut Display,
+    config: GLXFBConfig,
+    attribute: c_int,
+    value: *mut c_int,
+) -> c_int;
+type GetVisualFromFBConfigFn =
+    unsafe extern "C" fn(dpy: *mut Display, config: GLXFBConfig) -> *mut XVisualInfo;
+type CreateNewContextFn = unsafe extern "C" fn(
+    dpy: *mut Display,
+    config: GLXFBConfig,
+    render_type: c_int,
+    share_list: GLXContext,
+    direct: Bool,
+) -> GLXContext;
+type DestroyContextFn = unsafe extern "C" fn(dpy: *mut Display, ctx: GLXContext);
+type QueryContextFn = unsafe extern "C" fn(
+    dpy: *mut Display,
+    ctx: GLXContext,
+    attribute: c_int,
+    value: *mut c_int,
+) -> c_int;
+type MakeContextCurrentFn = unsafe extern "C" fn(
+    dpy: *mut Display,
+    draw: GLXDrawable,
+    read: GLXDrawable,
+    ctx: GLXContext,
+) -> Bool;
+type GetCurrentContextFn = unsafe extern "C" fn() -> GLXContext;
+type GetCurrentDisplayFn = unsafe extern "C" fn() -> *mut Display;
+type GetCurrentDrawableFn = unsafe extern "C" fn() -> GLXDrawable;
+type GetCurrentReadDrawableFn = unsafe extern "C" fn() -> GLXDrawable;
+type CreatePixmapFn = unsafe extern "C" fn(
+    dpy: *mut Display,
+    config: GLXFBConfig,
+    pixmap: Pixmap,
+    attrib_list: *const c_int,
+) -> GLXPixmap;
+type DestroyPixmapFn = unsafe extern "C" fn(dpy: *mut Display, pixmap: GLXPixmap);
+type CreateWindowFn = unsafe extern "C" fn(
+    dpy: *mut Display,
+    config: GLXFBConfig,
+    win: Window,
+    attrib_list: *const c_int,
+) -> GLXWindow;
+type DestroyWindowFn = unsafe extern "C" fn(dpy: *mut Display, win: GLXWindow);
+type SwapBuffersFn = unsafe extern "C" fn(dpy: *mut Display, drawable: GLXDrawable);
+type GetProcAddressARBFn =
+    unsafe extern "C" fn(proc_name: *const c_uchar) -> Option<unsafe extern "C" fn()>;
+type CreateContextAttribsARBFn = unsafe extern "C" fn(
+    dpy: *mut Display,
+    config: GLXFBConfig,
+    share_context: GLXContext,
+    direct: Bool,
+    attrib_list: *const c_int,
+) -> GLXContext;
+type BindTexImageEXTFn = unsafe extern "C" fn(
+    dpy: *mut Display,
+    drawable: GLXDrawable,
+    buffer: c_int,
+    attrib_list: *const c_int,
+);
+type ReleaseTexImageEXTFn =
+    unsafe extern "C" fn(dpy: *mut Display, drawable: GLXDrawable, buffer: c_int);
+
+#[allow(non_snake_case)]
+pub(crate) struct GLXFunctions {
+    pub(crate) QueryExtension: QueryExtensionFn,
+    pub(crate) QueryVersion: QueryVersionFn,
+    pub(crate) QueryExtensionsString: QueryExtensionsStringFn,
+    pub(crate) ChooseFBConfig: ChooseFBConfigFn,
+    pub(crate) GetFBConfigAttrib: GetFBConfigAttribFn,
+    pub(crate) GetVisualFromFBConfig: GetVisualFromFBConfigFn,
+    pub(crate) CreateNewContext: CreateNewContextFn,
+    pub(crate) DestroyContext: DestroyContextFn,
+    pub(crate) QueryContext: QueryContextFn,
+    pub(crate) MakeContextCurrent: MakeContextCurrentFn,
+    pub(crate) GetCurrentContext: GetCurrentContextFn,
+    pub(crate) GetCurrentDisplay: GetCurrentDisplayFn,
+    pub(crate) GetCurrentDrawable: GetCurrentDrawableFn,
+    pub(crate) GetCurrentReadDrawable: GetCurrentReadDrawableFn,
+    pub(crate) CreatePixmap: CreatePixmapFn,
+    pub(crate) DestroyPixmap: DestroyPixmapFn,
+    pub(crate) CreateWindow: CreateWindowFn,
+    pub(crate) DestroyWindow: DestroyWindowFn,
+    pub(crate) SwapBuffers: SwapBuffersFn,
+    pub(crate) GetProcAddressARB: GetProcAddressARBFn,
+
+    // Extension functions. Check the extension string before calling these, since
+    // `glXGetProcAddressARB()` returns a stub for any function name.
+    pub(crate) CreateContextAttribsARB: Option<CreateContextAttribsARBFn>,
+    pub(crate) BindTexImageEXT: Option<BindTexImageEXTFn>,
+    pub(crate) ReleaseTexImageEXT: Option<ReleaseTexImageEXTFn>,
+}
+
+lazy_static! {
+    pub(crate) static ref GLX_FUNCTIONS: Option<GLXFunctions> = unsafe { load_glx_functions() };
+}
+
+macro_rules! load_symbol {
+    ($library:expr, $name:expr, $ty:ty) => {{
+        let symbol = libc::dlsym($library, concat!($name, "\0").as_ptr() as *const c_char);
+        if symbol.is_null() {
+            return None;
+        }
+        mem::transmute::<*mut c_void, $ty>(symbol)
+    }};
+}
+
+macro_rules! load_extension {
+    ($get_proc_address:expr, $name:expr, $ty:ty) => {
+        mem::transmute::<Option<unsafe extern "C" fn()>, $ty>($get_proc_address(
+            concat!($name, "\0").as_ptr(),
+        ))
+    };
+}
+
+unsafe fn load_glx_functions() -> Option<GLXFunctions> {
+    // OpenBSD versions its libraries differently, but its `dlopen()` resolves unversioned names.
+    let library_names: [&[u8]; 2] = [b"libGL.so.1\0", b"libGL.so\0"];
+    let library = library_names
+        .iter()
+        .map(|name| libc::dlopen(name.as_ptr() as *const c_char, libc::RTLD_LAZY))
+        .find(|library| !library.is_null())?;
+
+    let mut functions = GLXFunctions {
+        QueryExtension: load_symbol!(library, "glXQueryExtension", QueryExtensionFn),
+        QueryVersion: load_symbol!(library, "glXQueryVersion", QueryVersionFn),
+        QueryExtensionsString: load_symbol!(
+            library,
+            "glXQueryExtensionsString",
+            QueryExtensionsStringFn
+        ),
+        ChooseFBConfig: load_symbol!(library, "glXChooseFBConfig", ChooseFBConfigFn),
+        GetFBConfigAttrib: load_symbol!(library, "glXGetFBConfigAttrib", GetFBConfigAttribFn),
+        GetVisualFromFBConfig: load_symbol!(
+            library,
+            "glXGetVisualFromFBConfig",
+            GetVisualFromFBConfigFn
+        ),
+        CreateNewContext: load_symbol!(library, "glXCreateNewContext", CreateNewContextFn),
+        DestroyContext: load_symbol!(library, "glXDestroyContext", DestroyContextFn),
+        QueryContext: load_symbol!(library, "glXQueryContext", QueryContextFn),
+        MakeContextCurrent: load_symbol!(library, "glXMakeContextCurrent", MakeContextCurrentFn),
+        GetCurrentContext: load_symbol!(library, "glXGetCurrentContext", GetCurrentContextFn),
+        GetCurrentDisplay: load_symbol!(library, "glXGetCurrentDisplay", GetCurrentDisplayFn),
+        GetCurrentDrawable: load_symbol!(library, "glXGetCurrentDrawable", GetCurrentDrawableFn),
+        GetCurrentReadDrawable: load_symbol!(
+            library,
+            "glXGetCurrentReadDrawable",
+            GetCurrentReadDrawableFn
+        ),
+        CreatePixmap: load_symbol!(library, "glXCreatePixmap", CreatePixmapFn),
+        DestroyPixmap: load_symbol!(library, "glXDestroyPixmap", DestroyPixmapFn),
+        CreateWindow: load_symbol!(library, "glXCreateWindow", CreateWindowFn),
+        DestroyWindow: load_symbol!(library, "glXDestroyWindow", DestroyWindowFn),
+        SwapBuffers: load_symbol!(library, "glXSwapBuffers", SwapBuffersFn),
+        GetProcAddressARB: load_symbol!(library, "glXGetProcAddressARB", GetProcAddressARBFn),
+        CreateContextAttribsARB: None,
+        BindTexImageEXT: None,
+        ReleaseTexImageEXT: None,
+    };
+
+    let get_proc_address = functions.GetProcAddressARB;
+    functions.CreateContextAttribsARB = load_extension!(
+        get_proc_address,
+        "glXCreateContextAttribsARB",
+        Option<CreateContextAttribsARBFn>
+    );
+    functions.BindTexImageEXT = load_extension!(
+        get_proc_address,
+        "glXBindTexImageEXT",
+        Option<BindTexImageEXTFn>
+    );
+    functions.ReleaseTexImageEXT = load_extension!(
+        get_proc_address,
+        "glXReleaseTexImageEXT",
+        Option<ReleaseTexImageEXTFn>
+    );
+    Some(functions)
+}
+
+// Returns the loaded GLX entry points. Connections can't be created without them, so this never
+// fails once a connection exists.
+#[inline]
+pub(crate) fn glx() -> &'static GLXFunctions {
+    GLX_FUNCTIONS
+        .as_ref()
+        .expect("GLX functions should have been loaded when the connection was created!")
+}
+
+// Returns true if the GLX implementation supports the named extension on the given screen.
+pub(crate) unsafe fn glx_has_extension(
+    functions: &GLXFunctions,
+    display: *mut Display,
+    screen: c_int,
+    extension: &[u8],
+) -> bool {
+    let extensions = (functions.QueryExtensionsString)(display, screen);
+    !extensions.is_null()
+        && CStr::from_ptr(extensions)
+            .to_bytes()
+            .split(|&byte| byte == b' ')
+            .any(|name| name == extension)
+}
@@ -0,0 +1,20 @@
+// surfman/surfman/src/platform/unix/glx/mod.rs
+//
+//! Bindings to GLX via Xlib.
+//!
+//! This backend is for systems whose drivers have solid GLX support but broken or missing EGL.
+//! Generic surfaces are X pixmaps, which other contexts read via `GLX_EXT_texture_from_pixmap`.
+
+pub mod connection;
+pub mod context;
+pub mod device;
+pub mod surface;
+
+mod ffi;
+
+#[path = "../../../implementation/mod.rs"]
+mod implementation;
+
+#[cfg(test)]
+#[path = "../../../tests.rs"]
+mod tests;
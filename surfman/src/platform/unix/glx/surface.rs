@@ -0,0 +1,972 @@
+// surfman/surfman/src/platform/unix/glx/surface.rs
+//
+//! Surface management for X11 using GLX pixmaps and windows.
+
+use super::context::{self, Context, GL_FUNCTIONS};
+use super::device::Device;
+use super::ffi::{glx, GLXFunctions, GLX_FRONT_LEFT_EXT, GLX_TEXTURE_2D_EXT};
+use super::ffi::{GLX_TEXTURE_FORMAT_EXT, GLX_TEXTURE_FORMAT_RGBA_EXT};
+use super::ffi::{GLX_TEXTURE_FORMAT_RGB_EXT, GLX_TEXTURE_TARGET_EXT};
+use crate::gl;
+use crate::gl::types::{GLenum, GLint, GLuint};
+use crate::gl_utils;
+#[cfg(feature = "sm-opencl")]
+use crate::opencl::SharedEGLImage;
+use crate::platform::unix::x11::connection::trap_x_errors;
+use crate::platform::unix::xrandr;
+use crate::surface::{estimate_memory_usage, SurfaceLayoutRequirements};
+use crate::{
+    Colorspace, CreateSurfacesResult, Error, PresentationStatistics, SurfaceAccess,
+    SurfaceAlphaMode, SurfaceFormat, SurfaceID, SurfaceInfo, SurfaceOrigin, SurfaceTransform,
+    SurfaceType, SurfaceUsage, SurfaceVisibility,
+};
+use crate::{ContextID, WidgetOutputInfo, WindowingApiError};
+
+use euclid::default::{Rect, Size2D};
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+use std::mem;
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+use std::thread;
+use x11::glx::{GLXFBConfig, GLXPixmap, GLXWindow, GLX_ALPHA_SIZE};
+use x11::xlib::{Display, Drawable, Pixmap, Window, XCreatePixmap, XFree, XFreePixmap};
+use x11::xlib::{XGetGeometry, XRootWindow};
+
+const SURFACE_GL_TEXTURE_TARGET: GLenum = gl::TEXTURE_2D;
+
+/// Represents a hardware buffer of pixels that can be rendered to via the CPU or GPU and either
+/// displayed in a native widget or bound to a texture for reading.
+///
+/// Surfaces come in two varieties: generic and widget surfaces. Generic surfaces can be bound to a
+/// texture but cannot be displayed in a widget (without using other APIs such as Core Animation,
+/// DirectComposition, or XPRESENT). Widget surfaces are the opposite: they can be displayed in a
+/// widget but not bound to a texture.
+///
+/// On this backend, generic surfaces are GLX pixmaps and widget surfaces are GLX windows.
+///
+/// Surfaces are specific to a given context and cannot be rendered to from any context other than
+/// the one they were created with. However, they can be *read* from any context on any thread (as
+/// long as that context shares the same adapter and connection), by wrapping them in a
+/// `SurfaceTexture`.
+///
+/// Depending on the platform, each surface may be internally double-buffered.
+///
+/// Surfaces must be destroyed with the `destroy_surface()` method, or a panic will occur.
+pub struct Surface {
+    pub(crate) id: SurfaceID,
+    pub(crate) size: Size2D<i32>,
+    pub(crate) context_id: ContextID,
+    pub(crate) drawable: SurfaceDrawable,
+    // Which way up the contents are. See `SurfaceInfo::origin`.
+    pub(crate) origin: SurfaceOrigin,
+    // How the alpha channel is interpreted. See `SurfaceInfo::alpha_mode`.
+    pub(crate) alpha_mode: SurfaceAlphaMode,
+    // The color space of the contents. See `SurfaceInfo::colorspace`.
+    pub(crate) colorspace: Colorspace,
+    // The number of samples per pixel. See `SurfaceInfo::samples`.
+    pub(crate) samples: u32,
+    pub(crate) destroyed: bool,
+}
+
+pub(crate) enum SurfaceDrawable {
+    Pixmap {
+        pixmap: Pixmap,
+        glx_pixmap: GLXPixmap,
+    },
+    Window {
+        window: Window,
+        glx_window: GLXWindow,
+    },
+}
+
+/// Represents an OpenGL texture that wraps a surface.
+///
+/// Reading from the associated OpenGL texture reads from the surface. It is undefined behavior to
+/// write to such a texture (e.g. by binding it to a framebuffer and rendering to that
+/// framebuffer).
+///
+/// Surface textures are local to a context, but that context does not have to be the same context
+/// as that associated with the underlying surface. The texture must be destroyed with the
+/// `destroy_surface_texture()` method, or a panic will occur.
+pub struct SurfaceTexture {
+    pub(crate) surface: Surface,
+    pub(crate) texture_object: GLuint,
+    pub(crate) phantom: PhantomData<*const ()>,
+}
+
+/// A wrapper for an X11 window.
+#[derive(Clone)]
+pub struct NativeWidget {
+    pub(crate) window: Window,
+}
+
+unsafe impl Send for Surface {}
+
+impl Debug for Surface {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "Surface({:x})", self.drawable.glx_drawable())
+    }
+}
+
+impl Debug for SurfaceTexture {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "SurfaceTexture({:?})", self.surface)
+    }
+}
+
+/// The native resources of a surface given up with `Device::surface_into_destroy_token()`, which
+/// can be destroyed later without the surface's context.
+///
+/// Tokens can be sent to other threads, and must be destroyed with `Device::destroy_token()`, or
+/// their resources leak.
+pub struct SurfaceDestroyToken {
+    drawable: SurfaceDrawable,
+}
+
+impl Surface {
+    /// Gives up this surface, returning a token that can be destroyed later with
+    /// `Device::destroy_token()`, on any thread and without the surface's context.
+    ///
+    /// On this backend, the token holds the surface's GLX drawable, which doesn't belong to any
+    /// context.
+    pub(crate) fn into_destroy_token(mut self) -> SurfaceDestroyToken {
+        self.destroyed = true;
+        let placeholder = SurfaceDrawable::Window {
+            window: 0,
+            glx_window: 0,
+        };
+        SurfaceDestroyToken {
+            drawable: mem::replace(&mut self.drawable, placeholder),
+        }
+    }
+}
+
+impl Drop for Surface {
+    fn drop(&mut self) {
+        if !self.destroyed && !thread::panicking() {
+            panic!("Should have destroyed the surface first with `destroy_surface()`!")
+        }
+    }
+}
+
+impl SurfaceDrawable {
+    #[inline]
+    pub(crate) fn glx_drawable(&self) -> Drawable {
+        match *self {
+            SurfaceDrawable::Pixmap { glx_pixmap, .. } => glx_pixmap,
+            SurfaceDrawable::Window { glx_window, .. } => glx_window,
+        }
+    }
+}
+
+impl Device {
+    /// Creates either a generic or a widget surface, depending on the supplied surface type.
+    ///
+    /// Only the given context may ever render to the surface, but generic surfaces can be wrapped
+    /// up in a `SurfaceTexture` for reading by other contexts.
+    ///
+    /// The window backing a widget surface must have been created with a visual compatible with
+    /// the context's framebuffer configuration. That visual also decides whether the window shows
+    /// the alpha channel of its surface, whatever the alpha mode.
+    pub(crate) fn create_surface_impl(
+        &mut self,
+        context: &Context,
+        _: SurfaceAccess,
+        _: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
+        surface_type: SurfaceType<NativeWidget>,
+    ) -> Result<Surface, Error> {
+        let mut surface = match surface_type {
+            SurfaceType::Generic { size } => self.create_generic_surface(context, &size)?,
+            // Window systems only composite premultiplied contents.
+            SurfaceType::Widget { .. } if surface_alpha_mode == SurfaceAlphaMode::Straight => {
+                return Err(Error::UnsupportedOnThisPlatform)
+            }
+            SurfaceType::Widget { native_widget } => unsafe {
+                self.create_window_surface(context, native_widget.window)?
+            },
+        };
+        surface.alpha_mode = surface_alpha_mode;
+        Ok(surface)
+    }
+
+    /// Creates `count` generic surfaces of the given size.
+    ///
+    /// The display is locked, and the context's framebuffer configuration looked up, once for the
+    /// whole batch. If one of the surfaces fails to be created, the error is returned along with
+    /// the surfaces that were created before it.
+    pub(crate) fn create_surfaces_impl(
+        &mut self,
+        context: &Context,
+        _: SurfaceAccess,
+        _: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
+        size: &Size2D<i32>,
+        count: usize,
+    ) -> CreateSurfacesResult<Surface> {
+        let display_guard = self.native_connection.lock_display();
+        let display = display_guard.display();
+        unsafe {
+            let fb_config = match self.generic_surface_fb_config(display, context) {
+                Ok(fb_config) => fb_config,
+                Err(err) => return Err((err, vec![])),
+            };
+            let texture_format = self.pixmap_texture_format(display, fb_config);
+
+            let mut surfaces = Vec::with_capacity(count);
+            for _ in 0..count {
+                match self.create_pixmap_surface(context, display, fb_config, texture_format, size)
+                {
+                    Ok(mut surface) => {
+                        surface.alpha_mode = surface_alpha_mode;
+                        surfaces.push(surface);
+                    }
+                    Err(err) => return Err((err, surfaces)),
+                }
+            }
+            Ok(surfaces)
+        }
+    }
+
+    fn create_generic_surface(
+        &mut self,
+        context: &Context,
+        size: &Size2D<i32>,
+    ) -> Result<Surface, Error> {
+        let display_guard = self.native_connection.lock_display();
+        let display = display_guard.display();
+        unsafe {
+            let fb_config = self.generic_surface_fb_config(display, context)?;
+            let texture_format = self.pixmap_texture_format(display, fb_config);
+            self.create_pixmap_surface(context, display, fb_config, texture_format, size)
+        }
+    }
+
+    // Returns the config that the pixmaps of the context's generic surfaces are created with.
+    //
+    // Only widget surfaces are multisampled, so that surface textures always hold resolved
+    // pixels. This is the context's own config unless that config is multisampled.
+    unsafe fn generic_surface_fb_config(
+        &self,
+        display: *mut Display,
+        context: &Context,
+    ) -> Result<GLXFBConfig, Error> {
+        let glx = glx();
+        let fb_config = context::fb_config_from_id(
+            glx,
+            display,
+            self.native_connection.screen,
+            context.fb_config_id,
+        )?;
+        context::single_sampled_fb_config(
+            glx,
+            display,
+            self.native_connection.screen,
+            fb_config,
+            self.native_connection.texture_from_pixmap_supported,
+        )
+    }
+
+    unsafe fn create_pixmap_surface(
+        &self,
+        context: &Context,
+        display: *mut Display,
+        fb_config: GLXFBConfig,
+        texture_format: Option<c_int>,
+        size: &Size2D<i32>,
+    ) -> Result<Surface, Error> {
+        let drawable = create_pixmap_drawable(
+            glx(),
+            display,
+            self.native_connection.screen,
+            fb_config,
+            size,
+            texture_format,
+        )?;
+        Ok(Surface {
+            id: SurfaceID::next(),
+            size: *size,
+            context_id: context.id,
+            drawable,
+            origin: SurfaceOrigin::BottomLeft,
+            alpha_mode: SurfaceAlphaMode::Premultiplied,
+            colorspace: Colorspace::SRGB,
+            samples: context::get_fb_config_samples(glx(), display, fb_config),
+            destroyed: false,
+        })
+    }
+
+    unsafe fn create_window_surface(
+        &mut self,
+        context: &Context,
+        x11_window: Window,
+    ) -> Result<Surface, Error> {
+        let glx = glx();
+        let display_guard = self.native_connection.lock_display();
+        let display = display_guard.display();
+
+        let fb_config = context::fb_config_from_id(
+            glx,
+            display,
+            self.native_connection.screen,
+            context.fb_config_id,
+        )?;
+        let glx_window = trap_x_errors(display, || {
+            (glx.CreateWindow)(display, fb_config, x11_window, ptr::null())
+        });
+        let glx_window = match glx_window {
+            Some(glx_window) if glx_window != 0 => glx_window,
+            _ => {
+                return Err(Error::SurfaceCreationFailed(
+                    WindowingApiError::BadNativeWindow,
+                ))
+            }
+        };
+
+        Ok(Surface {
+            id: SurfaceID::next(),
+            size: drawable_size(display, x11_window),
+            context_id: context.id,
+            drawable: SurfaceDrawable::Window {
+                window: x11_window,
+                glx_window,
+            },
+            origin: SurfaceOrigin::BottomLeft,
+            alpha_mode: SurfaceAlphaMode::Premultiplied,
+            colorspace: Colorspace::SRGB,
+            samples: context::get_fb_config_samples(glx, display, fb_config),
+            destroyed: false,
+        })
+    }
+
+    // Pixmaps can only be bound to textures if they were created with a texture format.
+    unsafe fn pixmap_texture_format(
+        &self,
+        display: *mut Display,
+        fb_config: GLXFBConfig,
+    ) -> Option<c_int> {
+        if !self.native_connection.texture_from_pixmap_supported {
+            None
+        } else if context::get_fb_config_attr(glx(), display, fb_config, GLX_ALPHA_SIZE) != 0 {
+            Some(GLX_TEXTURE_FORMAT_RGBA_EXT)
+        } else {
+            Some(GLX_TEXTURE_FORMAT_RGB_EXT)
+        }
+    }
+
+    /// Creates a generic surface with the given number of layers.
+    ///
+    /// Layered surfaces aren't supported on GLX, so this always returns
+    /// `UnsupportedOnThisPlatform`.
+    #[inline]
+    pub fn create_layered_surface(
+        &mut self,
+        _: &Context,
+        _: SurfaceAccess,
+        _: SurfaceUsage,
+        _: &Size2D<i32>,
+        _: u32,
+    ) -> Result<Surface, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Chooses the layer of a layered surface that rendering to the surface draws into.
+    ///
+    /// Layered surfaces aren't supported on GLX, so this always returns
+    /// `UnsupportedOnThisPlatform`.
+    #[inline]
+    pub fn set_surface_layer(&self, _: &Context, _: &mut Surface, _: u32) -> Result<(), Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Returns the number of layers of a surface, which is always 1 on GLX.
+    #[inline]
+    pub fn surface_layers(&self, _: &Surface) -> u32 {
+        1
+    }
+
+    /// Creates a new generic surface with a copy of the contents of the given one, stored with
+    /// its color channels in the given order.
+    ///
+    /// This is not yet supported on GLX, so it always returns `UnsupportedOnThisPlatform`.
+    #[inline]
+    pub(crate) fn convert_surface_format_impl(
+        &mut self,
+        _: &mut Context,
+        _: &Surface,
+        _: SurfaceFormat,
+    ) -> Result<Surface, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Wraps an existing OpenGL texture of the given context in a new generic surface.
+    ///
+    /// Generic surfaces on this backend are X pixmaps, which can't be made from a texture, so this always returns an `UnsupportedOnThisPlatform` error.
+    #[inline]
+    pub(crate) fn create_surface_from_texture_impl(
+        &mut self,
+        _: &mut Context,
+        _: &Size2D<i32>,
+        _: GLuint,
+    ) -> Result<Surface, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Creates a surface texture from an existing generic surface for use with the given context.
+    ///
+    /// The surface texture is local to the supplied context and takes ownership of the surface.
+    /// Destroying the surface texture allows you to retrieve the surface again.
+    ///
+    /// *The supplied context does not have to be the same context that the surface is associated
+    /// with.* This allows you to render to a surface in one context and sample from that surface
+    /// in another context.
+    ///
+    /// Pixmaps are stored top-down, so on some drivers the texture is upside down relative to
+    /// other backends. This requires the `GLX_EXT_texture_from_pixmap` extension.
+    ///
+    /// Calling this method on a widget surface returns a `WidgetAttached` error.
+    pub fn create_surface_texture(
+        &self,
+        context: &mut Context,
+        surface: Surface,
+    ) -> Result<SurfaceTexture, (Error, Surface)> {
+        let glx_pixmap = match surface.drawable {
+            SurfaceDrawable::Window { .. } => return Err((Error::WidgetAttached, surface)),
+            SurfaceDrawable::Pixmap { glx_pixmap, .. } => glx_pixmap,
+        };
+        let bind_tex_image = match glx().BindTexImageEXT {
+            Some(bind_tex_image) if self.native_connection.texture_from_pixmap_supported => {
+                bind_tex_image
+            }
+            _ => return Err((Error::RequiredExtensionUnavailable, surface)),
+        };
+
+        let _guard = match self.temporarily_make_context_current(context) {
+            Ok(guard) => guard,
+            Err(err) => return Err((err, surface)),
+        };
+
+        GL_FUNCTIONS.with(|gl| unsafe {
+            let mut bindings =
+                gl_utils::BindingsGuard::for_texture(gl, gl::TEXTURE_2D, gl::TEXTURE_BINDING_2D);
+
+            let mut texture_object = 0;
+            gl.GenTextures(1, &mut texture_object);
+            bindings.bind_texture(texture_object);
+
+            let display_guard = self.native_connection.lock_display();
+            bind_tex_image(
+                display_guard.display(),
+                glx_pixmap,
+                GLX_FRONT_LEFT_EXT,
+                ptr::null(),
+            );
+
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
+            gl.TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_WRAP_S,
+                gl::CLAMP_TO_EDGE as GLint,
+            );
+            gl.TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_WRAP_T,
+                gl::CLAMP_TO_EDGE as GLint,
+            );
+
+            drop(bindings);
+
+            Ok(SurfaceTexture {
+                surface,
+                texture_object,
+                phantom: PhantomData,
+            })
+        })
+    }
+
+    /// Destroys a surface.
+    ///
+    /// The supplied context must be the context the surface is associated with, or this returns
+    /// an `IncompatibleSurface` error.
+    ///
+    /// You must explicitly call this method to dispose of a surface. Otherwise, a panic occurs in
+    /// the `drop` method.
+    pub(crate) fn destroy_surface_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if surface.destroyed {
+            return Ok(());
+        }
+
+        let display_guard = self.native_connection.lock_display();
+        unsafe {
+            destroy_drawable(glx(), display_guard.display(), &mut surface.drawable);
+        }
+        surface.destroyed = true;
+        Ok(())
+    }
+
+    /// Destroys a surface texture and returns the underlying surface.
+    ///
+    /// The supplied context must be the same context the surface texture was created with, or an
+    /// `IncompatibleSurfaceTexture` error is returned.
+    ///
+    /// All surface textures must be explicitly destroyed with this function, or a panic will
+    /// occur.
+    pub fn destroy_surface_texture(
+        &self,
+        context: &mut Context,
+        mut surface_texture: SurfaceTexture,
+    ) -> Result<Surface, (Error, SurfaceTexture)> {
+        let _guard = match self.temporarily_make_context_current(context) {
+            Ok(guard) => guard,
+            Err(err) => return Err((err, surface_texture)),
+        };
+
+        GL_FUNCTIONS.with(|gl| unsafe {
+            if let SurfaceDrawable::Pixmap { glx_pixmap, .. } = surface_texture.surface.drawable {
+                if let Some(release_tex_image) = glx().ReleaseTexImageEXT {
+                    let display_guard = self.native_connection.lock_display();
+                    release_tex_image(display_guard.display(), glx_pixmap, GLX_FRONT_LEFT_EXT);
+                }
+            }
+            gl.DeleteTextures(1, &surface_texture.texture_object);
+            surface_texture.texture_object = 0;
+        });
+
+        Ok(surface_texture.surface)
+    }
+
+    /// Destroys the native resources of a surface given up with `surface_into_destroy_token()`.
+    ///
+    /// Unlike `destroy_surface()`, this doesn't need the context that the surface belonged to.
+    pub fn destroy_token(&self, mut token: SurfaceDestroyToken) -> Result<(), Error> {
+        let display_guard = self.native_connection.lock_display();
+        unsafe {
+            destroy_drawable(glx(), display_guard.display(), &mut token.drawable);
+        }
+        Ok(())
+    }
+
+    /// Displays the contents of a widget surface on screen.
+    ///
+    /// Widget surfaces are internally double-buffered, so changes to them don't show up in their
+    /// associated widgets until this method is called.
+    ///
+    /// The supplied context must match the context the surface was created with, or an
+    /// `IncompatibleSurface` error is returned.
+    pub(crate) fn present_surface_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        match surface.drawable {
+            SurfaceDrawable::Pixmap { .. } => Err(Error::NoWidgetAttached),
+            SurfaceDrawable::Window { glx_window, .. } => {
+                let display_guard = self.native_connection.lock_display();
+                unsafe {
+                    (glx().SwapBuffers)(display_guard.display(), glx_window);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Displays the contents of a widget surface on screen, hinting that only the given
+    /// rectangles have changed since the last present.
+    ///
+    /// `glXSwapBuffers()` always presents the whole window, so the damage is ignored.
+    #[inline]
+    pub(crate) fn present_surface_with_damage_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        _: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        self.present_surface_impl(context, surface)
+    }
+
+    /// Displays the `source` rectangle of a widget surface in the `destination` rectangle of its
+    /// widget, scaling it to fit.
+    ///
+    /// This is not yet supported on GLX, so it always returns `UnsupportedOnThisPlatform`.
+    #[inline]
+    pub(crate) fn present_surface_with_viewport_impl(
+        &self,
+        _: &Context,
+        _: &mut Surface,
+        _: &Rect<i32>,
+        _: &Rect<i32>,
+    ) -> Result<(), Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Declares which parts of a widget surface will be redrawn before it is next presented.
+    ///
+    /// GLX has no notion of partial updates, so this does nothing.
+    #[inline]
+    pub fn set_surface_damage_region(
+        &self,
+        _: &Context,
+        _: &mut Surface,
+        _: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Returns the number of presents ago that the current back buffer of a widget surface was
+    /// displayed, or 0 if its contents are undefined.
+    ///
+    /// `GLX_EXT_buffer_age` isn't queried on this backend, so this always returns 0.
+    #[inline]
+    pub fn surface_buffer_age(&self, _: &Context, _: &Surface) -> Result<u32, Error> {
+        Ok(0)
+    }
+
+    /// Sets whether presenting a widget surface preserves its contents.
+    ///
+    /// Widget surfaces are created without preservation, so their contents are undefined after
+    /// `present_surface()`. With it, applications that only repaint the dirty regions of each
+    /// frame can draw over the previous one, at the cost of a copy per present on some hardware.
+    ///
+    /// GLX has no way to change the swap behavior of a window, so this returns
+    /// `Error::UnsupportedOnThisPlatform`.
+    pub fn set_surface_preserve_buffer(
+        &self,
+        _: &Context,
+        _: &mut Surface,
+        _: bool,
+    ) -> Result<(), Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Sets which way up the contents of a surface are.
+    ///
+    /// Widget surfaces can only be bottom-left, and setting `SurfaceOrigin::TopLeft` on them
+    /// returns `Error::UnsupportedOnThisPlatform`.
+    pub fn set_surface_origin(
+        &self,
+        surface: &mut Surface,
+        origin: SurfaceOrigin,
+    ) -> Result<(), Error> {
+        if let SurfaceDrawable::Window { .. } = surface.drawable {
+            if origin == SurfaceOrigin::TopLeft {
+                return Err(Error::UnsupportedOnThisPlatform);
+            }
+        }
+        surface.origin = origin;
+        Ok(())
+    }
+
+    /// Sets the color space that the contents of a surface are in.
+    ///
+    /// X11 has no way to tag the contents of a window with a color space, so widget surfaces can
+    /// only be sRGB, and setting any other color space on them returns
+    /// `Error::UnsupportedOnThisPlatform`.
+    pub fn set_surface_colorspace(
+        &self,
+        surface: &mut Surface,
+        colorspace: Colorspace,
+    ) -> Result<(), Error> {
+        if let SurfaceDrawable::Window { .. } = surface.drawable {
+            if colorspace != Colorspace::SRGB {
+                return Err(Error::UnsupportedOnThisPlatform);
+            }
+        }
+        surface.colorspace = colorspace;
+        Ok(())
+    }
+
+    /// Resizes a widget surface.
+    ///
+    /// Resizing a generic surface reallocates its pixmap, discarding its contents.
+    pub(crate) fn resize_surface_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        size: Size2D<i32>,
+    ) -> Result<(), Error> {
+        if surface.size == size {
+            return Ok(());
+        }
+        if let SurfaceDrawable::Window { .. } = surface.drawable {
+            // The X server resizes the window's buffers for us.
+            surface.size = size;
+            return Ok(());
+        }
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        let glx = glx();
+        let display_guard = self.native_connection.lock_display();
+        let display = display_guard.display();
+        unsafe {
+            let fb_config = context::fb_config_from_id(
+                glx,
+                display,
+                self.native_connection.screen,
+                context.fb_config_id,
+            )?;
+            let mut drawable = create_pixmap_drawable(
+                glx,
+                display,
+                self.native_connection.screen,
+                fb_config,
+                &size,
+                self.pixmap_texture_format(display, fb_config),
+            )?;
+            mem::swap(&mut surface.drawable, &mut drawable);
+            destroy_drawable(glx, display, &mut drawable);
+        }
+        surface.size = size;
+        Ok(())
+    }
+
+    /// Returns a pointer to the underlying surface data for reading or writing by the CPU.
+    #[inline]
+    pub fn lock_surface_data<'s>(&self, _: &'s mut Surface) -> Result<SurfaceDataGuard<'s>, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Returns the OpenGL texture target needed to read from this surface texture.
+    ///
+    /// This will be `GL_TEXTURE_2D` or `GL_TEXTURE_RECTANGLE`, depending on platform.
+    #[inline]
+    pub fn surface_gl_texture_target(&self) -> GLenum {
+        SURFACE_GL_TEXTURE_TARGET
+    }
+
+    /// Returns the OpenGL texture target that the given surface texture is bound to, which is
+    /// always `surface_gl_texture_target()` on GLX.
+    #[inline]
+    pub fn surface_texture_binding_target(&self, _: &SurfaceTexture) -> GLenum {
+        SURFACE_GL_TEXTURE_TARGET
+    }
+
+    /// Returns the number of layers of the surface that a surface texture wraps, which is always
+    /// 1 on GLX.
+    #[inline]
+    pub fn surface_texture_layers(&self, _: &SurfaceTexture) -> u32 {
+        1
+    }
+
+    /// Fills in the mip chain of a surface texture from the current contents of its surface.
+    ///
+    /// Surface textures on this backend are bound to pixmaps without mip levels, so this always returns an `UnsupportedOnThisPlatform` error.
+    #[inline]
+    pub fn generate_surface_mipmaps(
+        &self,
+        _: &mut Context,
+        _: &SurfaceTexture,
+    ) -> Result<(), Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Returns various information about the surface, including the framebuffer object needed to
+    /// render to this surface.
+    ///
+    /// Before rendering to a surface attached to a context, you must call `glBindFramebuffer()`
+    /// on the framebuffer object returned by this function. This framebuffer object may or not be
+    /// 0, the default framebuffer, depending on platform.
+    pub fn surface_info(&self, surface: &Surface) -> SurfaceInfo {
+        SurfaceInfo {
+            size: surface.size,
+            id: surface.id,
+            context_id: surface.context_id,
+            framebuffer_object: 0,
+            scale_factor: 1.0,
+            transform: SurfaceTransform::Identity,
+            origin: surface.origin,
+            // Pixmaps and windows of depth 24 or 32 store pixels as BGRA.
+            format: SurfaceFormat::BGRA8,
+            alpha_mode: surface.alpha_mode,
+            colorspace: surface.colorspace,
+            samples: surface.samples,
+            bound: false,
+        }
+    }
+
+    /// Returns the GPU memory, in bytes, held by the given surface.
+    ///
+    /// GLX doesn't report the size of the underlying allocation, so this is an estimate that counts
+    /// four bytes per pixel of color storage.
+    #[inline]
+    pub fn surface_memory_usage(&self, surface: &Surface) -> usize {
+        estimate_memory_usage(&self.surface_info(surface).size)
+    }
+
+    /// Returns `Error::UnsupportedOnThisPlatform`, as GLX pixmaps have no modification counter.
+    #[inline]
+    pub fn surface_seed(&self, _: &Surface) -> Result<u32, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Returns the stride, row alignment, and total size of the memory that a generic surface of
+    /// the given size would be allocated with, without creating one.
+    ///
+    /// GLX pixmaps can't be mapped for CPU access, so this is the tightly packed layout that
+    /// readbacks use.
+    #[inline]
+    pub fn surface_layout_requirements(
+        &self,
+        size: &Size2D<i32>,
+        _: SurfaceFormat,
+        _: SurfaceAccess,
+    ) -> Result<SurfaceLayoutRequirements, Error> {
+        Ok(SurfaceLayoutRequirements::packed(size))
+    }
+
+    /// Returns timing information about the most recently displayed frame of a widget surface.
+    ///
+    /// GLX doesn't report presentation timing, so this always returns `None`.
+    #[inline]
+    pub fn surface_presentation_statistics(&self, _: &Surface) -> Option<PresentationStatistics> {
+        None
+    }
+
+    /// Returns whether a widget surface can currently be seen on screen.
+    ///
+    /// GLX doesn't report visibility, so this always returns `Unknown`.
+    #[inline]
+    pub fn surface_visibility(&self, _: &Surface) -> SurfaceVisibility {
+        SurfaceVisibility::Unknown
+    }
+
+    /// Returns the RandR output that a widget surface is shown on, and the bit depth of its
+    /// window.
+    ///
+    /// X11 doesn't report the color space or HDR capability of outputs, so these are always
+    /// reported as sRGB without HDR.
+    pub fn widget_output_info(&self, surface: &Surface) -> Result<WidgetOutputInfo, Error> {
+        match surface.drawable {
+            SurfaceDrawable::Pixmap { .. } => Err(Error::NoWidgetAttached),
+            SurfaceDrawable::Window { window, .. } => {
+                let display_guard = self.native_connection.lock_display();
+                unsafe { Ok(xrandr::window_output_info(display_guard.display(), window)) }
+            }
+        }
+    }
+
+    /// Returns the OpenGL texture object containing the contents of this surface.
+    ///
+    /// It is only legal to read from, not write to, this texture object.
+    #[inline]
+    pub fn surface_texture_object(&self, surface_texture: &SurfaceTexture) -> GLuint {
+        surface_texture.texture_object
+    }
+}
+
+// Creates an X pixmap and a GLX pixmap wrapping it. If `texture_format` is supplied, the pixmap
+// can be bound to a 2D texture with `glXBindTexImageEXT()`.
+pub(crate) unsafe fn create_pixmap_drawable(
+    glx: &GLXFunctions,
+    display: *mut Display,
+    screen: c_int,
+    fb_config: GLXFBConfig,
+    size: &Size2D<i32>,
+    texture_format: Option<c_int>,
+) -> Result<SurfaceDrawable, Error> {
+    let visual_info = (glx.GetVisualFromFBConfig)(display, fb_config);
+    if visual_info.is_null() {
+        return Err(Error::SurfaceCreationFailed(WindowingApiError::BadConfig));
+    }
+    let depth = (*visual_info).depth;
+    XFree(visual_info as *mut c_void);
+
+    let pixmap = XCreatePixmap(
+        display,
+        XRootWindow(display, screen),
+        size.width.max(1) as u32,
+        size.height.max(1) as u32,
+        depth as u32,
+    );
+
+    let mut pixmap_attributes = vec![];
+    if let Some(texture_format) = texture_format {
+        pixmap_attributes.extend_from_slice(&[
+            GLX_TEXTURE_TARGET_EXT,
+            GLX_TEXTURE_2D_EXT,
+            GLX_TEXTURE_FORMAT_EXT,
+            texture_format,
+        ]);
+    }
+    pixmap_attributes.push(0);
+
+    let glx_pixmap = trap_x_errors(display, || {
+        (glx.CreatePixmap)(display, fb_config, pixmap, pixmap_attributes.as_ptr())
+    });
+    match glx_pixmap {
+        Some(glx_pixmap) if glx_pixmap != 0 => Ok(SurfaceDrawable::Pixmap { pixmap, glx_pixmap }),
+        _ => {
+            XFreePixmap(display, pixmap);
+            Err(Error::SurfaceCreationFailed(WindowingApiError::Failed))
+        }
+    }
+}
+
+pub(crate) unsafe fn destroy_drawable(
+    glx: &GLXFunctions,
+    display: *mut Display,
+    drawable: &mut SurfaceDrawable,
+) {
+    match *drawable {
+        SurfaceDrawable::Pixmap {
+            ref mut pixmap,
+            ref mut glx_pixmap,
+        } => {
+            (glx.DestroyPixmap)(display, *glx_pixmap);
+            XFreePixmap(display, *pixmap);
+            *glx_pixmap = 0;
+            *pixmap = 0;
+        }
+        SurfaceDrawable::Window {
+            ref mut glx_window, ..
+        } => {
+            (glx.DestroyWindow)(display, *glx_window);
+            *glx_window = 0;
+        }
+    }
+}
+
+unsafe fn drawable_size(display: *mut Display, drawable: Drawable) -> Size2D<i32> {
+    let (mut root_window, mut x, mut y, mut width, mut height) = (0, 0, 0, 0, 0);
+    let (mut border_width, mut depth) = (0, 0);
+    XGetGeometry(
+        display,
+        drawable,
+        &mut root_window,
+        &mut x,
+        &mut y,
+        &mut width,
+        &mut height,
+        &mut border_width,
+        &mut depth,
+    );
+    Size2D::new(width as i32, height as i32)
+}
+
+/// Represents the CPU view of the pixel data of this surface.
+pub struct SurfaceDataGuard<'a> {
+    phantom: PhantomData<&'a ()>,
+}
+
+#[cfg(feature = "sm-opencl")]
+impl Device {
+    /// Returns `Error::UnsupportedOnThisPlatform`, as GLX surfaces have no EGL image.
+    #[inline]
+    pub fn surface_egl_image(&self, _: &Surface) -> Result<SharedEGLImage, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+}
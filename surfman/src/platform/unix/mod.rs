@@ -1,6 +1,11 @@
 // surfman/surfman/src/platform/unix/mod.rs
 //
 //! Backends specific to Unix-like systems, particularly Linux.
+//!
+//! These also build on FreeBSD and OpenBSD, whose Mesa ports provide the same EGL extensions. On
+//! those systems the DRM device is found through `EGL_EXT_device_drm` just as on Linux, and
+//! features that their DRM ports lack, such as syncobj timelines, are detected and skipped at
+//! runtime.
 
 // The default when x11 is enabled
 #[cfg(x11)]
@@ -13,7 +18,12 @@ pub use wayland as default;
 #[cfg(linux)]
 pub mod generic;
 
+#[cfg(x11)]
+pub mod glx;
+
 #[cfg(linux)]
 pub mod wayland;
 #[cfg(x11)]
 pub mod x11;
+#[cfg(x11)]
+mod xrandr;
@@ -0,0 +1,315 @@
+// surfman/surfman/src/platform/unix/wayland/buffer.rs
+//
+//! Wrapping the contents of generic surfaces in `wl_buffer`s via `zwp_linux_dmabuf_v1`.
+
+use super::connection::{destroy_proxy, Globals};
+use super::ffi::zwp_linux_buffer_params_v1_interface;
+use super::ffi::zwp_linux_buffer_params_v1_listener;
+use super::ffi::zwp_linux_dmabuf_feedback_v1_interface;
+use super::ffi::zwp_linux_dmabuf_feedback_v1_listener;
+use super::ffi::zwp_linux_dmabuf_v1_listener;
+use super::ffi::ZWP_LINUX_BUFFER_PARAMS_V1_CREATE_IMMED_SINCE_VERSION;
+use super::ffi::ZWP_LINUX_DMABUF_V1_CREATE_PARAMS;
+use super::ffi::ZWP_LINUX_DMABUF_V1_GET_DEFAULT_FEEDBACK;
+use super::ffi::ZWP_LINUX_DMABUF_V1_GET_DEFAULT_FEEDBACK_SINCE_VERSION;
+use super::ffi::ZWP_LINUX_DMABUF_V1_MODIFIER_SINCE_VERSION;
+use super::ffi::{wl_buffer_interface, ZWP_LINUX_BUFFER_PARAMS_V1_FLAGS_Y_INVERT};
+use super::ffi::{ZWP_LINUX_BUFFER_PARAMS_V1_ADD, ZWP_LINUX_BUFFER_PARAMS_V1_CREATE_IMMED};
+use crate::platform::generic::egl::surface::EGLDmaBuf;
+use crate::Error;
+
+use euclid::default::Size2D;
+use std::mem;
+use std::os::raw::c_void;
+use std::ptr;
+use std::slice;
+use wayland_sys::client::{wl_display, wl_proxy, WAYLAND_CLIENT_HANDLE};
+use wayland_sys::common::{wl_argument, wl_array};
+
+// The size of each entry in the `zwp_linux_dmabuf_feedback_v1` format table: a 32-bit fourcc, 32
+// bits of padding, and a 64-bit modifier.
+const FORMAT_TABLE_ENTRY_SIZE: usize = 16;
+
+// Creates a `wl_buffer` on the application's default event queue that refers to the given
+// DMA-BUF.
+pub(crate) unsafe fn create_dma_buf_buffer(
+    wayland_display: *mut wl_display,
+    globals: &Globals,
+    dma_buf: &EGLDmaBuf,
+    size: &Size2D<i32>,
+) -> Result<*mut wl_proxy, Error> {
+    if globals.linux_dmabuf.is_null()
+        || (WAYLAND_CLIENT_HANDLE.wl_proxy_get_version)(globals.linux_dmabuf)
+            < ZWP_LINUX_BUFFER_PARAMS_V1_CREATE_IMMED_SINCE_VERSION
+    {
+        return Err(Error::RequiredExtensionUnavailable);
+    }
+
+    let mut args = [wl_argument { n: 0 }];
+    let params = (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array_constructor)(
+        globals.linux_dmabuf,
+        ZWP_LINUX_DMABUF_V1_CREATE_PARAMS,
+        args.as_mut_ptr(),
+        &zwp_linux_buffer_params_v1_interface.0,
+    );
+    if params.is_null() {
+        return Err(Error::Failed);
+    }
+
+    let mut failed = false;
+    (WAYLAND_CLIENT_HANDLE.wl_proxy_add_listener)(
+        params,
+        &BUFFER_PARAMS_LISTENER as *const zwp_linux_buffer_params_v1_listener
+            as *mut extern "C" fn(),
+        &mut failed as *mut bool as *mut c_void,
+    );
+
+    for (plane_index, plane) in dma_buf.planes.iter().enumerate() {
+        let mut args = [
+            wl_argument { h: plane.fd },
+            wl_argument {
+                u: plane_index as u32,
+            },
+            wl_argument { u: plane.offset },
+            wl_argument { u: plane.stride },
+            wl_argument {
+                u: (dma_buf.modifier >> 32) as u32,
+            },
+            wl_argument {
+                u: dma_buf.modifier as u32,
+            },
+        ];
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array)(
+            params,
+            ZWP_LINUX_BUFFER_PARAMS_V1_ADD,
+            args.as_mut_ptr(),
+        );
+    }
+
+    // OpenGL renders the surface bottom-up, while Wayland buffers are read top-down.
+    let mut args = [
+        wl_argument { n: 0 },
+        wl_argument { i: size.width },
+        wl_argument { i: size.height },
+        wl_argument { u: dma_buf.fourcc },
+        wl_argument {
+            u: ZWP_LINUX_BUFFER_PARAMS_V1_FLAGS_Y_INVERT,
+        },
+    ];
+    let buffer = (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array_constructor)(
+        params,
+        ZWP_LINUX_BUFFER_PARAMS_V1_CREATE_IMMED,
+        args.as_mut_ptr(),
+        &wl_buffer_interface.0,
+    );
+
+    // The buffer belongs to the application, so hand it over to the default queue before any
+    // events can arrive for it. Then wait to hear whether the compositor accepted it.
+    if !buffer.is_null() {
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_set_queue)(buffer, ptr::null_mut());
+    }
+    (WAYLAND_CLIENT_HANDLE.wl_display_roundtrip_queue)(wayland_display, globals.event_queue);
+    destroy_proxy(params);
+
+    if buffer.is_null() {
+        return Err(Error::Failed);
+    }
+    if failed {
+        destroy_proxy(buffer);
+        return Err(Error::Failed);
+    }
+    Ok(buffer)
+}
+
+static BUFFER_PARAMS_LISTENER: zwp_linux_buffer_params_v1_listener =
+    zwp_linux_buffer_params_v1_listener {
+        created: buffer_params_created,
+        failed: buffer_params_failed,
+    };
+
+// Only sent in response to `create`, which we don't use.
+unsafe extern "C" fn buffer_params_created(_: *mut c_void, _: *mut wl_proxy, _: *mut wl_proxy) {}
+
+unsafe extern "C" fn buffer_params_failed(data: *mut c_void, _: *mut wl_proxy) {
+    *(data as *mut bool) = true;
+}
+
+// Collects the fourcc/modifier pairs that the compositor can import into `globals.dma_buf_formats`.
+//
+// Version 4 of `zwp_linux_dmabuf_v1` reports these via the default feedback object; version 3
+// sends them as `modifier` events right after binding. Older versions only advertise fourccs,
+// which tells us nothing about modifiers, so the list is left empty.
+pub(crate) unsafe fn query_dma_buf_formats(
+    wayland_display: *mut wl_display,
+    globals: &mut Globals,
+) {
+    if globals.linux_dmabuf.is_null() {
+        return;
+    }
+
+    let version = (WAYLAND_CLIENT_HANDLE.wl_proxy_get_version)(globals.linux_dmabuf);
+    if version >= ZWP_LINUX_DMABUF_V1_GET_DEFAULT_FEEDBACK_SINCE_VERSION {
+        let mut args = [wl_argument { n: 0 }];
+        let feedback = (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array_constructor)(
+            globals.linux_dmabuf,
+            ZWP_LINUX_DMABUF_V1_GET_DEFAULT_FEEDBACK,
+            args.as_mut_ptr(),
+            &zwp_linux_dmabuf_feedback_v1_interface.0,
+        );
+        if feedback.is_null() {
+            return;
+        }
+
+        let mut feedback_state = FeedbackState {
+            format_table: ptr::null_mut(),
+            format_table_size: 0,
+            formats: mem::replace(&mut globals.dma_buf_formats, vec![]),
+        };
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_add_listener)(
+            feedback,
+            &DMA_BUF_FEEDBACK_LISTENER as *const zwp_linux_dmabuf_feedback_v1_listener
+                as *mut extern "C" fn(),
+            &mut feedback_state as *mut FeedbackState as *mut c_void,
+        );
+        (WAYLAND_CLIENT_HANDLE.wl_display_roundtrip_queue)(wayland_display, globals.event_queue);
+        destroy_proxy(feedback);
+
+        if !feedback_state.format_table.is_null() {
+            libc::munmap(
+                feedback_state.format_table,
+                feedback_state.format_table_size,
+            );
+        }
+        globals.dma_buf_formats = feedback_state.formats;
+    } else if version >= ZWP_LINUX_DMABUF_V1_MODIFIER_SINCE_VERSION {
+        // The events are already on their way, so we only need to listen and wait for them.
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_add_listener)(
+            globals.linux_dmabuf,
+            &DMA_BUF_LISTENER as *const zwp_linux_dmabuf_v1_listener as *mut extern "C" fn(),
+            &mut globals.dma_buf_formats as *mut Vec<(u32, u64)> as *mut c_void,
+        );
+        (WAYLAND_CLIENT_HANDLE.wl_display_roundtrip_queue)(wayland_display, globals.event_queue);
+    }
+}
+
+fn add_dma_buf_format(formats: &mut Vec<(u32, u64)>, fourcc: u32, modifier: u64) {
+    if !formats.contains(&(fourcc, modifier)) {
+        formats.push((fourcc, modifier));
+    }
+}
+
+static DMA_BUF_LISTENER: zwp_linux_dmabuf_v1_listener = zwp_linux_dmabuf_v1_listener {
+    format: dma_buf_format,
+    modifier: dma_buf_modifier,
+};
+
+// Superseded by `modifier` events, which carry the same fourccs.
+unsafe extern "C" fn dma_buf_format(_: *mut c_void, _: *mut wl_proxy, _: u32) {}
+
+unsafe extern "C" fn dma_buf_modifier(
+    data: *mut c_void,
+    _: *mut wl_proxy,
+    fourcc: u32,
+    modifier_hi: u32,
+    modifier_lo: u32,
+) {
+    let formats = &mut *(data as *mut Vec<(u32, u64)>);
+    add_dma_buf_format(
+        formats,
+        fourcc,
+        ((modifier_hi as u64) << 32) | modifier_lo as u64,
+    );
+}
+
+struct FeedbackState {
+    format_table: *mut c_void,
+    format_table_size: usize,
+    formats: Vec<(u32, u64)>,
+}
+
+static DMA_BUF_FEEDBACK_LISTENER: zwp_linux_dmabuf_feedback_v1_listener =
+    zwp_linux_dmabuf_feedback_v1_listener {
+        done: dma_buf_feedback_ignore,
+        format_table: dma_buf_feedback_format_table,
+        main_device: dma_buf_feedback_ignore_device,
+        tranche_done: dma_buf_feedback_ignore,
+        tranche_target_device: dma_buf_feedback_ignore_device,
+        tranche_formats: dma_buf_feedback_tranche_formats,
+        tranche_flags: dma_buf_feedback_ignore_flags,
+    };
+
+unsafe extern "C" fn dma_buf_feedback_ignore(_: *mut c_void, _: *mut wl_proxy) {}
+
+unsafe extern "C" fn dma_buf_feedback_ignore_device(
+    _: *mut c_void,
+    _: *mut wl_proxy,
+    _: *mut wl_array,
+) {
+}
+
+unsafe extern "C" fn dma_buf_feedback_ignore_flags(_: *mut c_void, _: *mut wl_proxy, _: u32) {}
+
+unsafe extern "C" fn dma_buf_feedback_format_table(
+    data: *mut c_void,
+    _: *mut wl_proxy,
+    fd: i32,
+    size: u32,
+) {
+    let feedback_state = &mut *(data as *mut FeedbackState);
+    let format_table = libc::mmap(
+        ptr::null_mut(),
+        size as usize,
+        libc::PROT_READ,
+        libc::MAP_PRIVATE,
+        fd,
+        0,
+    );
+    libc::close(fd);
+    if format_table == libc::MAP_FAILED {
+        return;
+    }
+
+    if !feedback_state.format_table.is_null() {
+        libc::munmap(
+            feedback_state.format_table,
+            feedback_state.format_table_size,
+        );
+    }
+    feedback_state.format_table = format_table;
+    feedback_state.format_table_size = size as usize;
+}
+
+unsafe extern "C" fn dma_buf_feedback_tranche_formats(
+    data: *mut c_void,
+    _: *mut wl_proxy,
+    indices: *mut wl_array,
+) {
+    let feedback_state = &mut *(data as *mut FeedbackState);
+    if feedback_state.format_table.is_null() || (*indices).data.is_null() {
+        return;
+    }
+
+    let format_table = slice::from_raw_parts(
+        feedback_state.format_table as *const u8,
+        feedback_state.format_table_size,
+    );
+    let indices = slice::from_raw_parts(
+        (*indices).data as *const u16,
+        (*indices).size / mem::size_of::<u16>(),
+    );
+    for &index in indices {
+        let offset = index as usize * FORMAT_TABLE_ENTRY_SIZE;
+        let entry = match format_table.get(offset..(offset + FORMAT_TABLE_ENTRY_SIZE)) {
+            Some(entry) => entry,
+            None => continue,
+        };
+        let fourcc = u32::from_ne_bytes([entry[0], entry[1], entry[2], entry[3]]);
+        let mut modifier = [0; 8];
+        modifier.copy_from_slice(&entry[8..16]);
+        add_dma_buf_format(
+            &mut feedback_state.formats,
+            fourcc,
+            u64::from_ne_bytes(modifier),
+        );
+    }
+}
@@ -0,0 +1,302 @@
+// surfman/surfman/src/platform/unix/wayland/color.rs
+//
+//! Tagging of widget surfaces with their color space, via `wp_color_manager_v1`.
+
+use super::connection::{bind, destroy_proxy};
+use super::ffi::wp_image_description_creator_params_v1_interface;
+use super::ffi::WP_COLOR_MANAGEMENT_SURFACE_V1_SET_IMAGE_DESCRIPTION as SET_IMAGE_DESCRIPTION;
+use super::ffi::WP_COLOR_MANAGEMENT_SURFACE_V1_UNSET_IMAGE_DESCRIPTION as UNSET_IMAGE_DESCRIPTION;
+use super::ffi::WP_COLOR_MANAGER_V1_TRANSFER_FUNCTION_SRGB as TRANSFER_FUNCTION_SRGB;
+use super::ffi::WP_COLOR_MANAGER_V1_TRANSFER_FUNCTION_ST2084_PQ as TRANSFER_FUNCTION_PQ;
+use super::ffi::WP_IMAGE_DESCRIPTION_CREATOR_PARAMS_V1_CREATE as CREATE_IMAGE_DESCRIPTION;
+use super::ffi::WP_IMAGE_DESCRIPTION_CREATOR_PARAMS_V1_SET_PRIMARIES_NAMED as SET_PRIMARIES_NAMED;
+use super::ffi::WP_IMAGE_DESCRIPTION_CREATOR_PARAMS_V1_SET_TF_NAMED as SET_TF_NAMED;
+use super::ffi::{wp_color_management_surface_v1_interface, wp_color_manager_v1_interface};
+use super::ffi::{wp_color_manager_v1_listener, wp_image_description_v1_interface};
+use super::ffi::{wp_image_description_v1_listener, WP_COLOR_MANAGER_V1_FEATURE_PARAMETRIC};
+use super::ffi::{WP_COLOR_MANAGER_V1_CREATE_PARAMETRIC_CREATOR, WP_COLOR_MANAGER_V1_GET_SURFACE};
+use super::ffi::{WP_COLOR_MANAGER_V1_PRIMARIES_BT2020, WP_COLOR_MANAGER_V1_PRIMARIES_DISPLAY_P3};
+use super::ffi::{
+    WP_COLOR_MANAGER_V1_PRIMARIES_SRGB, WP_COLOR_MANAGER_V1_RENDER_INTENT_PERCEPTUAL,
+};
+use crate::Colorspace;
+
+use std::os::raw::{c_char, c_void};
+use std::ptr;
+use wayland_sys::client::{wl_display, wl_event_queue, wl_proxy, WAYLAND_CLIENT_HANDLE};
+use wayland_sys::common::wl_argument;
+
+// The `wp_color_manager_v1` global, along with what the compositor has told us it supports.
+//
+// Boxed, so that its address can serve as the user data of the listener.
+pub(crate) struct ColorManager {
+    proxy: *mut wl_proxy,
+    // Bitmasks, indexed by the values of the corresponding protocol enums.
+    features: u32,
+    primaries: u32,
+    transfer_functions: u32,
+}
+
+// The `wp_color_management_surface_v1` object attached to a widget surface.
+pub(crate) struct ColorManagementSurface(*mut wl_proxy);
+
+static COLOR_MANAGER_LISTENER: wp_color_manager_v1_listener = wp_color_manager_v1_listener {
+    supported_intent: color_manager_supported_intent,
+    supported_feature: color_manager_supported_feature,
+    supported_tf_named: color_manager_supported_tf_named,
+    supported_primaries_named: color_manager_supported_primaries_named,
+    done: color_manager_done,
+};
+
+static IMAGE_DESCRIPTION_LISTENER: wp_image_description_v1_listener =
+    wp_image_description_v1_listener {
+        failed: image_description_failed,
+        ready: image_description_ready,
+    };
+
+impl ColorManager {
+    // Binds the `wp_color_manager_v1` global and starts listening for what it supports.
+    pub(crate) unsafe fn bind(
+        registry: *mut wl_proxy,
+        name: u32,
+        advertised_version: u32,
+    ) -> Option<Box<ColorManager>> {
+        let proxy = bind(
+            registry,
+            name,
+            &wp_color_manager_v1_interface.0,
+            advertised_version,
+        );
+        if proxy.is_null() {
+            return None;
+        }
+
+        let mut color_manager = Box::new(ColorManager {
+            proxy,
+            features: 0,
+            primaries: 0,
+            transfer_functions: 0,
+        });
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_add_listener)(
+            proxy,
+            &COLOR_MANAGER_LISTENER as *const wp_color_manager_v1_listener
+                as *mut extern "C" fn(),
+            &mut *color_manager as *mut ColorManager as *mut c_void,
+        );
+        Some(color_manager)
+    }
+
+    // Returns true if the compositor can build an image description for the color space.
+    pub(crate) fn supports(&self, colorspace: Colorspace) -> bool {
+        let (primaries, transfer_function) = named_parameters(colorspace);
+        self.features & (1 << WP_COLOR_MANAGER_V1_FEATURE_PARAMETRIC) != 0
+            && self.primaries & (1 << primaries) != 0
+            && self.transfer_functions & (1 << transfer_function) != 0
+    }
+
+    pub(crate) unsafe fn get_surface(
+        &self,
+        wayland_surface: *mut wl_proxy,
+    ) -> Option<ColorManagementSurface> {
+        let mut args = [
+            wl_argument { n: 0 },
+            wl_argument {
+                o: wayland_surface as *const c_void,
+            },
+        ];
+        let proxy = (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array_constructor)(
+            self.proxy,
+            WP_COLOR_MANAGER_V1_GET_SURFACE,
+            args.as_mut_ptr(),
+            &wp_color_management_surface_v1_interface.0,
+        );
+        if proxy.is_null() {
+            None
+        } else {
+            Some(ColorManagementSurface(proxy))
+        }
+    }
+
+    // Builds an image description for the color space and waits for the compositor to accept it.
+    // Returns null if the compositor rejects it.
+    //
+    // The image description inherits our private event queue from the color manager, so the
+    // round trip never dispatches events that belong to the application.
+    unsafe fn create_image_description(
+        &self,
+        wayland_display: *mut wl_display,
+        event_queue: *mut wl_event_queue,
+        colorspace: Colorspace,
+    ) -> *mut wl_proxy {
+        let mut args = [wl_argument { n: 0 }];
+        let creator = (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array_constructor)(
+            self.proxy,
+            WP_COLOR_MANAGER_V1_CREATE_PARAMETRIC_CREATOR,
+            args.as_mut_ptr(),
+            &wp_image_description_creator_params_v1_interface.0,
+        );
+        if creator.is_null() {
+            return ptr::null_mut();
+        }
+
+        let (primaries, transfer_function) = named_parameters(colorspace);
+        let mut args = [wl_argument {
+            u: transfer_function,
+        }];
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array)(creator, SET_TF_NAMED, args.as_mut_ptr());
+        let mut args = [wl_argument { u: primaries }];
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array)(
+            creator,
+            SET_PRIMARIES_NAMED,
+            args.as_mut_ptr(),
+        );
+
+        // `create` destroys the creator on the compositor's side.
+        let mut args = [wl_argument { n: 0 }];
+        let image_description = (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array_constructor)(
+            creator,
+            CREATE_IMAGE_DESCRIPTION,
+            args.as_mut_ptr(),
+            &wp_image_description_v1_interface.0,
+        );
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_destroy)(creator);
+        if image_description.is_null() {
+            return ptr::null_mut();
+        }
+
+        let mut ready: Option<bool> = None;
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_add_listener)(
+            image_description,
+            &IMAGE_DESCRIPTION_LISTENER as *const wp_image_description_v1_listener
+                as *mut extern "C" fn(),
+            &mut ready as *mut Option<bool> as *mut c_void,
+        );
+        (WAYLAND_CLIENT_HANDLE.wl_display_roundtrip_queue)(wayland_display, event_queue);
+        if ready == Some(true) {
+            return image_description;
+        }
+
+        destroy_proxy(image_description);
+        ptr::null_mut()
+    }
+}
+
+impl Drop for ColorManager {
+    fn drop(&mut self) {
+        unsafe {
+            destroy_proxy(self.proxy);
+        }
+    }
+}
+
+impl ColorManagementSurface {
+    // Tags the surface with the color space. Returns false if the compositor rejects it.
+    //
+    // Like the buffer transform, the tag takes effect on the next commit of the surface, which
+    // happens in `eglSwapBuffers()`.
+    pub(crate) unsafe fn set_colorspace(
+        &self,
+        color_manager: &ColorManager,
+        wayland_display: *mut wl_display,
+        event_queue: *mut wl_event_queue,
+        colorspace: Colorspace,
+    ) -> bool {
+        // Untagged surfaces are taken to be sRGB.
+        if colorspace == Colorspace::SRGB {
+            (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array)(
+                self.0,
+                UNSET_IMAGE_DESCRIPTION,
+                ptr::null_mut(),
+            );
+            return true;
+        }
+
+        let image_description =
+            color_manager.create_image_description(wayland_display, event_queue, colorspace);
+        if image_description.is_null() {
+            return false;
+        }
+
+        let mut args = [
+            wl_argument {
+                o: image_description as *const c_void,
+            },
+            wl_argument {
+                u: WP_COLOR_MANAGER_V1_RENDER_INTENT_PERCEPTUAL,
+            },
+        ];
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array)(
+            self.0,
+            SET_IMAGE_DESCRIPTION,
+            args.as_mut_ptr(),
+        );
+
+        // The surface holds on to the image description for as long as it needs it.
+        destroy_proxy(image_description);
+        true
+    }
+}
+
+impl Drop for ColorManagementSurface {
+    fn drop(&mut self) {
+        unsafe {
+            destroy_proxy(self.0);
+        }
+    }
+}
+
+// Returns the named primaries and transfer function of a color space.
+fn named_parameters(colorspace: Colorspace) -> (u32, u32) {
+    match colorspace {
+        Colorspace::SRGB => (WP_COLOR_MANAGER_V1_PRIMARIES_SRGB, TRANSFER_FUNCTION_SRGB),
+        Colorspace::DisplayP3 => (
+            WP_COLOR_MANAGER_V1_PRIMARIES_DISPLAY_P3,
+            TRANSFER_FUNCTION_SRGB,
+        ),
+        Colorspace::BT2020 => (WP_COLOR_MANAGER_V1_PRIMARIES_BT2020, TRANSFER_FUNCTION_PQ),
+    }
+}
+
+unsafe extern "C" fn color_manager_supported_intent(_: *mut c_void, _: *mut wl_proxy, _: u32) {}
+
+unsafe extern "C" fn color_manager_supported_feature(
+    data: *mut c_void,
+    _: *mut wl_proxy,
+    feature: u32,
+) {
+    let color_manager = &mut *(data as *mut ColorManager);
+    color_manager.features |= 1u32.checked_shl(feature).unwrap_or(0);
+}
+
+unsafe extern "C" fn color_manager_supported_tf_named(
+    data: *mut c_void,
+    _: *mut wl_proxy,
+    transfer_function: u32,
+) {
+    let color_manager = &mut *(data as *mut ColorManager);
+    color_manager.transfer_functions |= 1u32.checked_shl(transfer_function).unwrap_or(0);
+}
+
+unsafe extern "C" fn color_manager_supported_primaries_named(
+    data: *mut c_void,
+    _: *mut wl_proxy,
+    primaries: u32,
+) {
+    let color_manager = &mut *(data as *mut ColorManager);
+    color_manager.primaries |= 1u32.checked_shl(primaries).unwrap_or(0);
+}
+
+unsafe extern "C" fn color_manager_done(_: *mut c_void, _: *mut wl_proxy) {}
+
+unsafe extern "C" fn image_description_failed(
+    data: *mut c_void,
+    _: *mut wl_proxy,
+    _: u32,
+    _: *const c_char,
+) {
+    *(data as *mut Option<bool>) = Some(false);
+}
+
+unsafe extern "C" fn image_description_ready(data: *mut c_void, _: *mut wl_proxy, _: u32) {
+    *(data as *mut Option<bool>) = Some(true);
+}
@@ -2,20 +2,39 @@
 //
 //! A wrapper for Wayland connections (displays).
 
+use super::buffer;
+use super::color::ColorManager;
 use super::device::{Adapter, Device, NativeDevice};
+use super::ffi::wp_linux_drm_syncobj_manager_v1_interface;
+use super::ffi::wp_viewporter_interface;
+use super::ffi::zwp_linux_dmabuf_v1_interface;
+use super::ffi::zwp_linux_explicit_synchronization_v1_interface;
+use super::ffi::WL_REGISTRY_BIND;
+use super::ffi::{wl_registry_interface, wl_registry_listener, DESTROY, WL_DISPLAY_GET_REGISTRY};
+use super::ffi::{wp_fractional_scale_manager_v1_interface, wp_presentation_interface};
+use super::gbm::GbmDevice;
+use super::output::{self, OutputState};
 use super::surface::NativeWidget;
+use super::sync;
+use super::widget::WidgetState;
+use crate::context::ContextIDAllocator;
 use crate::egl;
 use crate::egl::types::{EGLAttrib, EGLDisplay};
 use crate::info::GLApi;
-use crate::platform::generic::egl::device::EGL_FUNCTIONS;
+use crate::overrides;
+use crate::platform::generic::egl::device::{query_display_vendor, EGL_FUNCTIONS};
 use crate::platform::generic::egl::ffi::EGL_PLATFORM_WAYLAND_KHR;
-use crate::Error;
+use crate::{DisplayInfo, Error, IpcDescriptor, NativeDisplayHandle, NativeWidgetHandle};
 
 use euclid::default::Size2D;
-use std::os::raw::c_void;
+use std::cmp;
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_void};
 use std::ptr;
-use std::sync::Arc;
-use wayland_sys::client::{wl_display, wl_proxy, WAYLAND_CLIENT_HANDLE};
+use std::sync::{Arc, Mutex};
+use wayland_sys::client::{wl_display, wl_event_queue, wl_proxy, WAYLAND_CLIENT_HANDLE};
+use wayland_sys::common::{wl_argument, wl_interface};
 
 #[cfg(feature = "sm-winit")]
 use winit::platform::unix::WindowExtUnix;
@@ -26,11 +45,38 @@ use winit::window::Window;
 #[derive(Clone)]
 pub struct Connection {
     pub(crate) native_connection: Arc<NativeConnectionWrapper>,
+    pub(crate) context_ids: ContextIDAllocator,
 }
 
 pub(crate) struct NativeConnectionWrapper {
     pub(crate) egl_display: EGLDisplay,
-    wayland_display: Option<*mut wl_display>,
+    pub(crate) wayland_display: Option<*mut wl_display>,
+    owns_wayland_display: bool,
+    pub(crate) globals: Option<Box<Globals>>,
+    // Only opened if the compositor tells us which format modifiers it accepts.
+    pub(crate) gbm_device: Option<Mutex<GbmDevice>>,
+    // Keyed by the address of the `wl_egl_window` of each widget surface.
+    pub(crate) widgets: Mutex<HashMap<usize, WidgetState>>,
+}
+
+// Protocol extension globals advertised by the compositor.
+//
+// These live on a private event queue, so that we never dispatch events that belong to the
+// application.
+pub(crate) struct Globals {
+    pub(crate) event_queue: *mut wl_event_queue,
+    registry: *mut wl_proxy,
+    pub(crate) viewporter: *mut wl_proxy,
+    pub(crate) fractional_scale_manager: *mut wl_proxy,
+    pub(crate) presentation: *mut wl_proxy,
+    pub(crate) syncobj_manager: *mut wl_proxy,
+    pub(crate) explicit_synchronization: *mut wl_proxy,
+    pub(crate) linux_dmabuf: *mut wl_proxy,
+    pub(crate) color_manager: Option<Box<ColorManager>>,
+    // The fourcc/modifier pairs that the compositor advertises through `linux_dmabuf`.
+    pub(crate) dma_buf_formats: Vec<(u32, u64)>,
+    // One entry per `wl_output` global, in the order the compositor advertised them.
+    pub(crate) outputs: Vec<Box<OutputState>>,
 }
 
 /// An EGL display wrapping a Wayland display.
@@ -50,13 +96,34 @@ impl Connection {
 
     /// Wraps an existing EGL display in a `Connection`.
     ///
+    /// # Safety
+    ///
     /// The display is not retained, as there is no way to do this in the EGL API. Therefore, it is
     /// the caller's responsibility to ensure that the EGL display remains alive as long as the
     /// connection is.
     pub unsafe fn from_native_connection(
         native_connection: NativeConnection,
     ) -> Result<Connection, Error> {
-        Connection::from_egl_display(native_connection.0, None)
+        Connection::from_egl_display(native_connection.0, None, false)
+    }
+
+    /// Connects through a handle that another process opened and passed over IPC.
+    ///
+    /// Only `IpcDescriptor::WaylandSocket` is accepted. The socket is closed when the connection
+    /// is dropped, or right away if connecting fails.
+    ///
+    /// # Safety
+    ///
+    /// The socket must be an open file descriptor connected to a Wayland compositor, and nothing
+    /// else may use or close it.
+    pub unsafe fn from_ipc_descriptor(descriptor: IpcDescriptor) -> Result<Connection, Error> {
+        match descriptor {
+            IpcDescriptor::WaylandSocket(fd) => {
+                let wayland_display = (WAYLAND_CLIENT_HANDLE.wl_display_connect_to_fd)(fd);
+                Connection::from_wayland_display(wayland_display, true)
+            }
+            _ => Err(Error::UnsupportedOnThisPlatform),
+        }
     }
 
     /// Returns the underlying native connection.
@@ -65,12 +132,41 @@ impl Connection {
         NativeConnection(self.native_connection.egl_display)
     }
 
+    /// Returns the raw display connection underneath this connection: its Wayland display, if
+    /// any, and the EGL display created on it.
+    #[inline]
+    pub fn native_display(&self) -> NativeDisplayHandle {
+        NativeDisplayHandle::Wayland {
+            display: self
+                .native_connection
+                .wayland_display
+                .map_or(ptr::null_mut(), |wayland_display| {
+                    wayland_display as *mut c_void
+                }),
+            egl_display: self.native_connection.egl_display as *mut c_void,
+        }
+    }
+
     /// Returns the OpenGL API flavor that this connection supports (OpenGL or OpenGL ES).
     #[inline]
     pub fn gl_api(&self) -> GLApi {
         GLApi::GL
     }
 
+    /// Returns the displays connected to the system.
+    ///
+    /// Displays are the compositor's `wl_output` globals. Hotplugged outputs show up once the
+    /// application's event loop has read the compositor's announcement of them. If this connection
+    /// wraps a bare EGL display, there is no Wayland display to ask, and this returns
+    /// `UnsupportedOnThisPlatform`.
+    pub fn displays(&self) -> Result<Vec<DisplayInfo>, Error> {
+        self.native_connection.dispatch_pending_events();
+        match self.native_connection.globals {
+            Some(ref globals) => Ok(globals.outputs.iter().map(|output| output.info()).collect()),
+            None => Err(Error::UnsupportedOnThisPlatform),
+        }
+    }
+
     /// Returns the "best" adapter on this system, preferring high-performance hardware adapters.
     ///
     /// This is an alias for `Connection::create_hardware_adapter()`.
@@ -82,26 +178,46 @@ impl Connection {
     /// Returns the "best" adapter on this system, preferring high-performance hardware adapters.
     #[inline]
     pub fn create_hardware_adapter(&self) -> Result<Adapter, Error> {
-        Ok(Adapter::hardware())
+        if overrides::software_rendering_forced() {
+            return self.create_software_adapter();
+        }
+        Ok(Adapter::hardware().with_vendor(self.vendor()))
     }
 
     /// Returns the "best" adapter on this system, preferring low-power hardware adapters.
     #[inline]
     pub fn create_low_power_adapter(&self) -> Result<Adapter, Error> {
-        Ok(Adapter::low_power())
+        if overrides::software_rendering_forced() {
+            return self.create_software_adapter();
+        }
+        Ok(Adapter::low_power().with_vendor(self.vendor()))
     }
 
     /// Returns the "best" adapter on this system, preferring software adapters.
     #[inline]
     pub fn create_software_adapter(&self) -> Result<Adapter, Error> {
-        Ok(Adapter::software())
+        Ok(Adapter::software().with_vendor(self.vendor()))
+    }
+
+    /// Returns the adapter for the GPU that drives the given display.
+    ///
+    /// The GPU is found by matching the display's name against the DRM connectors in sysfs, and
+    /// selected by pointing Mesa's `DRI_PRIME` variable at its PCI slot.
+    #[inline]
+    pub fn create_adapter_for_display(&self, display: &DisplayInfo) -> Result<Adapter, Error> {
+        Ok(Adapter::for_display(display)?.with_vendor(self.vendor()))
+    }
+
+    // Returns the vendor library that this connection's display is dispatched to.
+    fn vendor(&self) -> Option<String> {
+        unsafe { query_display_vendor(self.native_connection.egl_display) }
     }
 
     /// Opens the hardware device corresponding to the given adapter.
     ///
-    /// Device handles are local to a single thread.
+    /// Device handles can be cloned and sent to other threads.
     #[inline]
-    pub fn create_device(&self, adapter: &Adapter) -> Result<Device, Error> {
+    pub(crate) fn create_device_impl(&self, adapter: &Adapter) -> Result<Device, Error> {
         Device::new(self, adapter)
     }
 
@@ -110,7 +226,7 @@ impl Connection {
     ///
     /// This is present for compatibility with other backends.
     #[inline]
-    pub unsafe fn create_device_from_native_device(
+    pub(crate) unsafe fn create_device_from_native_device_impl(
         &self,
         native_device: NativeDevice,
     ) -> Result<Device, Error> {
@@ -140,24 +256,34 @@ impl Connection {
             let ok = egl.Initialize(egl_display, &mut egl_major_version, &mut egl_minor_version);
             assert_ne!(ok, egl::FALSE);
 
-            let owned_display = if is_owned {
-                Some(wayland_display)
-            } else {
-                None
-            };
-            Connection::from_egl_display(egl_display, owned_display)
+            Connection::from_egl_display(egl_display, Some(wayland_display), is_owned)
         })
     }
 
-    fn from_egl_display(
+    unsafe fn from_egl_display(
         egl_display: EGLDisplay,
         wayland_display: Option<*mut wl_display>,
+        owns_wayland_display: bool,
     ) -> Result<Connection, Error> {
+        let globals = wayland_display.and_then(|wayland_display| Globals::new(wayland_display));
+        let gbm_device = match globals {
+            Some(ref globals) if !globals.dma_buf_formats.is_empty() => {
+                sync::open_drm_device(egl_display)
+                    .and_then(|drm_fd| GbmDevice::new(drm_fd))
+                    .map(Mutex::new)
+            }
+            _ => None,
+        };
         Ok(Connection {
             native_connection: Arc::new(NativeConnectionWrapper {
                 egl_display,
                 wayland_display,
+                owns_wayland_display,
+                globals,
+                gbm_device,
+                widgets: Mutex::new(HashMap::new()),
             }),
+            context_ids: ContextIDAllocator::new()?,
         })
     }
 
@@ -198,10 +324,16 @@ impl Connection {
         Ok(NativeWidget {
             wayland_surface,
             size: window_size,
+            explicit_sync: false,
         })
     }
 
     /// Create a native widget from a raw pointer
+    ///
+    /// # Safety
+    ///
+    /// The pointer must be to a live `wl_surface` on this connection's display, which must outlive
+    /// the native widget and any surfaces created from it.
     pub unsafe fn create_native_widget_from_ptr(
         &self,
         raw: *mut c_void,
@@ -210,6 +342,29 @@ impl Connection {
         NativeWidget {
             wayland_surface: raw as *mut wl_proxy,
             size,
+            explicit_sync: false,
+        }
+    }
+
+    /// Creates a native widget from a handle to a native window or view.
+    ///
+    /// Only Wayland surface handles are accepted.
+    ///
+    /// # Safety
+    ///
+    /// The handle must refer to a live window or view, which must outlive the native widget and any
+    /// surfaces created from it.
+    pub unsafe fn create_native_widget_from_handle(
+        &self,
+        handle: NativeWidgetHandle,
+    ) -> Result<NativeWidget, Error> {
+        match handle {
+            NativeWidgetHandle::WaylandSurface { surface, size, .. } => Ok(NativeWidget {
+                wayland_surface: surface as *mut wl_proxy,
+                size,
+                explicit_sync: false,
+            }),
+            _ => Err(Error::IncompatibleNativeWidget),
         }
     }
 
@@ -232,20 +387,196 @@ impl Connection {
         Ok(NativeWidget {
             wayland_surface,
             size: window_size,
+            explicit_sync: false,
         })
     }
 }
 
 impl Drop for NativeConnectionWrapper {
     fn drop(&mut self) {
+        // Protocol objects must be destroyed before the display they were created on.
+        self.widgets.get_mut().unwrap().clear();
+        drop(self.globals.take());
+
         unsafe {
-            if let Some(wayland_display) = self.wayland_display {
+            if let (Some(wayland_display), true) = (self.wayland_display, self.owns_wayland_display)
+            {
                 (WAYLAND_CLIENT_HANDLE.wl_display_disconnect)(wayland_display);
             }
         }
     }
 }
 
+impl NativeConnectionWrapper {
+    // Processes any events that the compositor has sent to our protocol objects.
+    //
+    // This never reads from the display socket itself; that is left to the application's event
+    // loop, which queues our events as a side effect.
+    pub(crate) fn dispatch_pending_events(&self) {
+        if let (Some(wayland_display), Some(ref globals)) = (self.wayland_display, &self.globals) {
+            unsafe {
+                (WAYLAND_CLIENT_HANDLE.wl_display_dispatch_queue_pending)(
+                    wayland_display,
+                    globals.event_queue,
+                );
+            }
+        }
+    }
+}
+
+static REGISTRY_LISTENER: wl_registry_listener = wl_registry_listener {
+    global: registry_global,
+    global_remove: registry_global_remove,
+};
+
+impl Globals {
+    pub(crate) unsafe fn new(wayland_display: *mut wl_display) -> Option<Box<Globals>> {
+        let event_queue = (WAYLAND_CLIENT_HANDLE.wl_display_create_queue)(wayland_display);
+        if event_queue.is_null() {
+            return None;
+        }
+
+        let display_wrapper =
+            (WAYLAND_CLIENT_HANDLE.wl_proxy_create_wrapper)(wayland_display as *mut wl_proxy);
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_set_queue)(display_wrapper, event_queue);
+        let mut args = [wl_argument { n: 0 }];
+        let registry = (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array_constructor)(
+            display_wrapper,
+            WL_DISPLAY_GET_REGISTRY,
+            args.as_mut_ptr(),
+            &wl_registry_interface.0,
+        );
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_wrapper_destroy)(display_wrapper);
+
+        let mut globals = Box::new(Globals {
+            event_queue,
+            registry,
+            viewporter: ptr::null_mut(),
+            fractional_scale_manager: ptr::null_mut(),
+            presentation: ptr::null_mut(),
+            syncobj_manager: ptr::null_mut(),
+            explicit_synchronization: ptr::null_mut(),
+            linux_dmabuf: ptr::null_mut(),
+            color_manager: None,
+            dma_buf_formats: vec![],
+            outputs: vec![],
+        });
+        if registry.is_null() {
+            return Some(globals);
+        }
+
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_add_listener)(
+            registry,
+            &REGISTRY_LISTENER as *const wl_registry_listener as *mut extern "C" fn(),
+            &mut *globals as *mut Globals as *mut c_void,
+        );
+        (WAYLAND_CLIENT_HANDLE.wl_display_roundtrip_queue)(wayland_display, event_queue);
+        if !globals.outputs.is_empty() || globals.color_manager.is_some() {
+            // Wait for the initial description of each output that we just bound, and for the
+            // color manager to list what it supports.
+            (WAYLAND_CLIENT_HANDLE.wl_display_roundtrip_queue)(wayland_display, event_queue);
+        }
+        buffer::query_dma_buf_formats(wayland_display, &mut globals);
+        Some(globals)
+    }
+}
+
+impl Drop for Globals {
+    fn drop(&mut self) {
+        unsafe {
+            destroy_proxy(self.viewporter);
+            destroy_proxy(self.fractional_scale_manager);
+            destroy_proxy(self.presentation);
+            destroy_proxy(self.syncobj_manager);
+            destroy_proxy(self.explicit_synchronization);
+            destroy_proxy(self.linux_dmabuf);
+            self.color_manager = None;
+            self.outputs.clear();
+            if !self.registry.is_null() {
+                (WAYLAND_CLIENT_HANDLE.wl_proxy_destroy)(self.registry);
+            }
+            (WAYLAND_CLIENT_HANDLE.wl_event_queue_destroy)(self.event_queue);
+        }
+    }
+}
+
+unsafe extern "C" fn registry_global(
+    data: *mut c_void,
+    registry: *mut wl_proxy,
+    name: u32,
+    interface: *const c_char,
+    version: u32,
+) {
+    let globals = &mut *(data as *mut Globals);
+    let interface = CStr::from_ptr(interface).to_bytes();
+    if interface == b"wp_viewporter" && globals.viewporter.is_null() {
+        globals.viewporter = bind(registry, name, &wp_viewporter_interface.0, version);
+    } else if interface == b"wp_fractional_scale_manager_v1"
+        && globals.fractional_scale_manager.is_null()
+    {
+        globals.fractional_scale_manager = bind(
+            registry,
+            name,
+            &wp_fractional_scale_manager_v1_interface.0,
+            version,
+        );
+    } else if interface == b"wp_presentation" && globals.presentation.is_null() {
+        globals.presentation = bind(registry, name, &wp_presentation_interface.0, version);
+    } else if interface == b"wp_linux_drm_syncobj_manager_v1" && globals.syncobj_manager.is_null() {
+        globals.syncobj_manager = bind(
+            registry,
+            name,
+            &wp_linux_drm_syncobj_manager_v1_interface.0,
+            version,
+        );
+    } else if interface == b"zwp_linux_explicit_synchronization_v1"
+        && globals.explicit_synchronization.is_null()
+    {
+        globals.explicit_synchronization = bind(
+            registry,
+            name,
+            &zwp_linux_explicit_synchronization_v1_interface.0,
+            version,
+        );
+    } else if interface == b"zwp_linux_dmabuf_v1" && globals.linux_dmabuf.is_null() {
+        globals.linux_dmabuf = bind(registry, name, &zwp_linux_dmabuf_v1_interface.0, version);
+    } else if interface == b"wp_color_manager_v1" && globals.color_manager.is_none() {
+        globals.color_manager = ColorManager::bind(registry, name, version);
+    } else if interface == b"wl_output" {
+        output::bind_output(globals, registry, name, version);
+    }
+}
+
+unsafe extern "C" fn registry_global_remove(data: *mut c_void, _: *mut wl_proxy, name: u32) {
+    // Of the globals we bind, only outputs come and go.
+    let globals = &mut *(data as *mut Globals);
+    globals
+        .outputs
+        .retain(|output| output.global_name() != name);
+}
+
+pub(crate) unsafe fn bind(
+    registry: *mut wl_proxy,
+    name: u32,
+    interface: &wl_interface,
+    advertised_version: u32,
+) -> *mut wl_proxy {
+    let version = cmp::min(advertised_version, interface.version as u32);
+    let mut args = [
+        wl_argument { u: name },
+        wl_argument { s: interface.name },
+        wl_argument { u: version },
+        wl_argument { n: 0 },
+    ];
+    (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array_constructor_versioned)(
+        registry,
+        WL_REGISTRY_BIND,
+        args.as_mut_ptr(),
+        interface,
+        version,
+    )
+}
+
 impl NativeConnection {
     /// Returns the current native connection, if applicable.
     #[inline]
@@ -262,3 +593,11 @@ impl NativeConnection {
         }
     }
 }
+
+// Sends the `destroy` request of a protocol extension object and frees the proxy.
+pub(crate) unsafe fn destroy_proxy(proxy: *mut wl_proxy) {
+    if !proxy.is_null() {
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array)(proxy, DESTROY, ptr::null_mut());
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_destroy)(proxy);
+    }
+}
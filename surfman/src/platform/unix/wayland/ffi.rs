@@ -0,0 +1,696 @@
+// surfman/surfman/src/platform/unix/wayland/ffi.rs
+//
+//! Hand-written interface descriptions for the Wayland protocol extensions that `surfman` uses.
+//!
+//! `wayland-sys` only exposes `libwayland-client` itself, so the interfaces of the protocol
+//! objects we bind are described here in the same form that `wayland-scanner` would generate.
+
+#![allow(non_upper_case_globals)]
+
+use std::os::raw::{c_char, c_void};
+use std::ptr;
+use wayland_sys::client::wl_proxy;
+use wayland_sys::common::{wl_array, wl_interface, wl_message};
+
+// `wl_display` requests.
+pub(crate) const WL_DISPLAY_GET_REGISTRY: u32 = 1;
+
+// `wl_registry` requests.
+pub(crate) const WL_REGISTRY_BIND: u32 = 0;
+
+// `wl_output` requests.
+pub(crate) const WL_OUTPUT_RELEASE: u32 = 0;
+pub(crate) const WL_OUTPUT_RELEASE_SINCE_VERSION: u32 = 3;
+
+// `wl_output` mode flags.
+pub(crate) const WL_OUTPUT_MODE_CURRENT: u32 = 1;
+
+// `wl_output` transforms.
+pub(crate) const WL_OUTPUT_TRANSFORM_NORMAL: i32 = 0;
+pub(crate) const WL_OUTPUT_TRANSFORM_FLIPPED_180: i32 = 6;
+
+// `wl_surface` requests.
+pub(crate) const WL_SURFACE_ATTACH: u32 = 1;
+pub(crate) const WL_SURFACE_DAMAGE: u32 = 2;
+pub(crate) const WL_SURFACE_COMMIT: u32 = 6;
+pub(crate) const WL_SURFACE_SET_BUFFER_TRANSFORM: u32 = 7;
+pub(crate) const WL_SURFACE_SET_BUFFER_TRANSFORM_SINCE_VERSION: u32 = 2;
+pub(crate) const WL_SURFACE_SET_BUFFER_SCALE: u32 = 8;
+pub(crate) const WL_SURFACE_SET_BUFFER_SCALE_SINCE_VERSION: u32 = 3;
+pub(crate) const WL_SURFACE_DAMAGE_BUFFER: u32 = 9;
+pub(crate) const WL_SURFACE_DAMAGE_BUFFER_SINCE_VERSION: u32 = 4;
+
+// `wl_shm` requests and formats.
+pub(crate) const WL_SHM_CREATE_POOL: u32 = 0;
+pub(crate) const WL_SHM_FORMAT_ARGB8888: u32 = 0;
+pub(crate) const WL_SHM_FORMAT_XRGB8888: u32 = 1;
+
+// `wl_shm_pool` requests. Unlike the extension interfaces, its `destroy` request isn't first.
+pub(crate) const WL_SHM_POOL_CREATE_BUFFER: u32 = 0;
+pub(crate) const WL_SHM_POOL_DESTROY: u32 = 1;
+
+// Requests shared by all of the extension interfaces below.
+pub(crate) const DESTROY: u32 = 0;
+
+// `wp_viewporter` requests.
+pub(crate) const WP_VIEWPORTER_GET_VIEWPORT: u32 = 1;
+
+// `wp_viewport` requests.
+pub(crate) const WP_VIEWPORT_SET_DESTINATION: u32 = 2;
+
+// `wp_fractional_scale_manager_v1` requests.
+pub(crate) const WP_FRACTIONAL_SCALE_MANAGER_V1_GET_FRACTIONAL_SCALE: u32 = 1;
+
+// `wp_presentation` requests.
+pub(crate) const WP_PRESENTATION_FEEDBACK: u32 = 1;
+
+// `wp_color_manager_v1` requests.
+pub(crate) const WP_COLOR_MANAGER_V1_GET_SURFACE: u32 = 2;
+pub(crate) const WP_COLOR_MANAGER_V1_CREATE_PARAMETRIC_CREATOR: u32 = 5;
+
+// `wp_color_manager_v1` enums.
+pub(crate) const WP_COLOR_MANAGER_V1_RENDER_INTENT_PERCEPTUAL: u32 = 0;
+pub(crate) const WP_COLOR_MANAGER_V1_FEATURE_PARAMETRIC: u32 = 1;
+pub(crate) const WP_COLOR_MANAGER_V1_PRIMARIES_SRGB: u32 = 1;
+pub(crate) const WP_COLOR_MANAGER_V1_PRIMARIES_BT2020: u32 = 6;
+pub(crate) const WP_COLOR_MANAGER_V1_PRIMARIES_DISPLAY_P3: u32 = 9;
+pub(crate) const WP_COLOR_MANAGER_V1_TRANSFER_FUNCTION_SRGB: u32 = 9;
+pub(crate) const WP_COLOR_MANAGER_V1_TRANSFER_FUNCTION_ST2084_PQ: u32 = 11;
+
+// `wp_color_management_surface_v1` requests.
+pub(crate) const WP_COLOR_MANAGEMENT_SURFACE_V1_SET_IMAGE_DESCRIPTION: u32 = 1;
+pub(crate) const WP_COLOR_MANAGEMENT_SURFACE_V1_UNSET_IMAGE_DESCRIPTION: u32 = 2;
+
+// `wp_image_description_creator_params_v1` requests.
+pub(crate) const WP_IMAGE_DESCRIPTION_CREATOR_PARAMS_V1_CREATE: u32 = 0;
+pub(crate) const WP_IMAGE_DESCRIPTION_CREATOR_PARAMS_V1_SET_TF_NAMED: u32 = 1;
+pub(crate) const WP_IMAGE_DESCRIPTION_CREATOR_PARAMS_V1_SET_PRIMARIES_NAMED: u32 = 3;
+
+// `wp_linux_drm_syncobj_manager_v1` requests.
+pub(crate) const WP_LINUX_DRM_SYNCOBJ_MANAGER_V1_GET_SURFACE: u32 = 1;
+pub(crate) const WP_LINUX_DRM_SYNCOBJ_MANAGER_V1_IMPORT_TIMELINE: u32 = 2;
+
+// `wp_linux_drm_syncobj_surface_v1` requests.
+pub(crate) const WP_LINUX_DRM_SYNCOBJ_SURFACE_V1_SET_ACQUIRE_POINT: u32 = 1;
+pub(crate) const WP_LINUX_DRM_SYNCOBJ_SURFACE_V1_SET_RELEASE_POINT: u32 = 2;
+
+// `zwp_linux_explicit_synchronization_v1` requests.
+pub(crate) const ZWP_LINUX_EXPLICIT_SYNCHRONIZATION_V1_GET_SYNCHRONIZATION: u32 = 1;
+
+// `zwp_linux_surface_synchronization_v1` requests.
+pub(crate) const ZWP_LINUX_SURFACE_SYNCHRONIZATION_V1_SET_ACQUIRE_FENCE: u32 = 1;
+
+// `zwp_linux_dmabuf_v1` requests.
+pub(crate) const ZWP_LINUX_DMABUF_V1_CREATE_PARAMS: u32 = 1;
+pub(crate) const ZWP_LINUX_DMABUF_V1_GET_DEFAULT_FEEDBACK: u32 = 2;
+pub(crate) const ZWP_LINUX_DMABUF_V1_GET_DEFAULT_FEEDBACK_SINCE_VERSION: u32 = 4;
+pub(crate) const ZWP_LINUX_DMABUF_V1_MODIFIER_SINCE_VERSION: u32 = 3;
+
+// `zwp_linux_buffer_params_v1` requests.
+pub(crate) const ZWP_LINUX_BUFFER_PARAMS_V1_ADD: u32 = 1;
+pub(crate) const ZWP_LINUX_BUFFER_PARAMS_V1_CREATE_IMMED: u32 = 3;
+pub(crate) const ZWP_LINUX_BUFFER_PARAMS_V1_CREATE_IMMED_SINCE_VERSION: u32 = 2;
+pub(crate) const ZWP_LINUX_BUFFER_PARAMS_V1_FLAGS_Y_INVERT: u32 = 1;
+
+// The denominator of the scale reported by `wp_fractional_scale_v1::preferred_scale`.
+pub(crate) const FRACTIONAL_SCALE_DENOMINATOR: u32 = 120;
+
+#[repr(C)]
+pub(crate) struct wl_registry_listener {
+    pub(crate) global: unsafe extern "C" fn(
+        data: *mut c_void,
+        registry: *mut wl_proxy,
+        name: u32,
+        interface: *const c_char,
+        version: u32,
+    ),
+    pub(crate) global_remove:
+        unsafe extern "C" fn(data: *mut c_void, registry: *mut wl_proxy, name: u32),
+}
+
+#[repr(C)]
+pub(crate) struct wl_output_listener {
+    pub(crate) geometry: unsafe extern "C" fn(
+        data: *mut c_void,
+        output: *mut wl_proxy,
+        x: i32,
+        y: i32,
+        physical_width: i32,
+        physical_height: i32,
+        subpixel: i32,
+        make: *const c_char,
+        model: *const c_char,
+        transform: i32,
+    ),
+    pub(crate) mode: unsafe extern "C" fn(
+        data: *mut c_void,
+        output: *mut wl_proxy,
+        flags: u32,
+        width: i32,
+        height: i32,
+        refresh: i32,
+    ),
+    pub(crate) done: unsafe extern "C" fn(data: *mut c_void, output: *mut wl_proxy),
+    pub(crate) scale: unsafe extern "C" fn(data: *mut c_void, output: *mut wl_proxy, factor: i32),
+    pub(crate) name:
+        unsafe extern "C" fn(data: *mut c_void, output: *mut wl_proxy, name: *const c_char),
+    pub(crate) description:
+        unsafe extern "C" fn(data: *mut c_void, output: *mut wl_proxy, description: *const c_char),
+}
+
+#[repr(C)]
+pub(crate) struct wl_buffer_listener {
+    pub(crate) release: unsafe extern "C" fn(data: *mut c_void, buffer: *mut wl_proxy),
+}
+
+#[repr(C)]
+pub(crate) struct wp_fractional_scale_v1_listener {
+    pub(crate) preferred_scale:
+        unsafe extern "C" fn(data: *mut c_void, fractional_scale: *mut wl_proxy, scale: u32),
+}
+
+#[repr(C)]
+pub(crate) struct wp_presentation_feedback_listener {
+    pub(crate) sync_output:
+        unsafe extern "C" fn(data: *mut c_void, feedback: *mut wl_proxy, output: *mut wl_proxy),
+    pub(crate) presented: unsafe extern "C" fn(
+        data: *mut c_void,
+        feedback: *mut wl_proxy,
+        tv_sec_hi: u32,
+        tv_sec_lo: u32,
+        tv_nsec: u32,
+        refresh: u32,
+        seq_hi: u32,
+        seq_lo: u32,
+        flags: u32,
+    ),
+    pub(crate) discarded: unsafe extern "C" fn(data: *mut c_void, feedback: *mut wl_proxy),
+}
+
+#[repr(C)]
+pub(crate) struct wp_color_manager_v1_listener {
+    pub(crate) supported_intent:
+        unsafe extern "C" fn(data: *mut c_void, color_manager: *mut wl_proxy, intent: u32),
+    pub(crate) supported_feature:
+        unsafe extern "C" fn(data: *mut c_void, color_manager: *mut wl_proxy, feature: u32),
+    pub(crate) supported_tf_named:
+        unsafe extern "C" fn(data: *mut c_void, color_manager: *mut wl_proxy, tf: u32),
+    pub(crate) supported_primaries_named:
+        unsafe extern "C" fn(data: *mut c_void, color_manager: *mut wl_proxy, primaries: u32),
+    pub(crate) done: unsafe extern "C" fn(data: *mut c_void, color_manager: *mut wl_proxy),
+}
+
+#[repr(C)]
+pub(crate) struct wp_image_description_v1_listener {
+    pub(crate) failed: unsafe extern "C" fn(
+        data: *mut c_void,
+        image_description: *mut wl_proxy,
+        cause: u32,
+        msg: *const c_char,
+    ),
+    pub(crate) ready:
+        unsafe extern "C" fn(data: *mut c_void, image_description: *mut wl_proxy, identity: u32),
+}
+
+#[repr(C)]
+pub(crate) struct zwp_linux_dmabuf_v1_listener {
+    pub(crate) format: unsafe extern "C" fn(data: *mut c_void, dmabuf: *mut wl_proxy, format: u32),
+    pub(crate) modifier: unsafe extern "C" fn(
+        data: *mut c_void,
+        dmabuf: *mut wl_proxy,
+        format: u32,
+        modifier_hi: u32,
+        modifier_lo: u32,
+    ),
+}
+
+#[repr(C)]
+pub(crate) struct zwp_linux_dmabuf_feedback_v1_listener {
+    pub(crate) done: unsafe extern "C" fn(data: *mut c_void, feedback: *mut wl_proxy),
+    pub(crate) format_table:
+        unsafe extern "C" fn(data: *mut c_void, feedback: *mut wl_proxy, fd: i32, size: u32),
+    pub(crate) main_device:
+        unsafe extern "C" fn(data: *mut c_void, feedback: *mut wl_proxy, device: *mut wl_array),
+    pub(crate) tranche_done: unsafe extern "C" fn(data: *mut c_void, feedback: *mut wl_proxy),
+    pub(crate) tranche_target_device:
+        unsafe extern "C" fn(data: *mut c_void, feedback: *mut wl_proxy, device: *mut wl_array),
+    pub(crate) tranche_formats:
+        unsafe extern "C" fn(data: *mut c_void, feedback: *mut wl_proxy, indices: *mut wl_array),
+    pub(crate) tranche_flags:
+        unsafe extern "C" fn(data: *mut c_void, feedback: *mut wl_proxy, flags: u32),
+}
+
+#[repr(C)]
+pub(crate) struct zwp_linux_buffer_params_v1_listener {
+    pub(crate) created:
+        unsafe extern "C" fn(data: *mut c_void, params: *mut wl_proxy, buffer: *mut wl_proxy),
+    pub(crate) failed: unsafe extern "C" fn(data: *mut c_void, params: *mut wl_proxy),
+}
+
+// The interface tables contain raw pointers, so they need a wrapper in order to live in statics.
+#[repr(transparent)]
+pub(crate) struct Static<T>(pub(crate) T);
+
+unsafe impl<T> Sync for Static<T> {}
+
+// Argument types for messages that create no objects. Object arguments that refer to core
+// interfaces (like `wl_surface`) are left null too, since `libwayland-client` only consults the
+// types of `new_id` arguments when marshaling requests.
+static NULL_TYPES: Static<[*const wl_interface; 8]> = Static([ptr::null(); 8]);
+
+macro_rules! message {
+    ($name:expr, $signature:expr, $types:expr) => {
+        wl_message {
+            name: concat!($name, "\0").as_ptr() as *const c_char,
+            signature: concat!($signature, "\0").as_ptr() as *const c_char,
+            types: $types,
+        }
+    };
+}
+
+static wl_registry_requests: Static<[wl_message; 1]> =
+    Static([message!("bind", "usun", NULL_TYPES.0.as_ptr())]);
+static wl_registry_events: Static<[wl_message; 2]> = Static([
+    message!("global", "usu", NULL_TYPES.0.as_ptr()),
+    message!("global_remove", "u", NULL_TYPES.0.as_ptr()),
+]);
+pub(crate) static wl_registry_interface: Static<wl_interface> = Static(wl_interface {
+    name: b"wl_registry\0".as_ptr() as *const c_char,
+    version: 1,
+    request_count: 1,
+    requests: wl_registry_requests.0.as_ptr(),
+    event_count: 2,
+    events: wl_registry_events.0.as_ptr(),
+});
+
+static get_viewport_types: Static<[*const wl_interface; 2]> =
+    Static([&wp_viewport_interface.0, ptr::null()]);
+static wp_viewporter_requests: Static<[wl_message; 2]> = Static([
+    message!("destroy", "", NULL_TYPES.0.as_ptr()),
+    message!("get_viewport", "no", get_viewport_types.0.as_ptr()),
+]);
+pub(crate) static wp_viewporter_interface: Static<wl_interface> = Static(wl_interface {
+    name: b"wp_viewporter\0".as_ptr() as *const c_char,
+    version: 1,
+    request_count: 2,
+    requests: wp_viewporter_requests.0.as_ptr(),
+    event_count: 0,
+    events: ptr::null(),
+});
+
+static wp_viewport_requests: Static<[wl_message; 3]> = Static([
+    message!("destroy", "", NULL_TYPES.0.as_ptr()),
+    message!("set_source", "ffff", NULL_TYPES.0.as_ptr()),
+    message!("set_destination", "ii", NULL_TYPES.0.as_ptr()),
+]);
+pub(crate) static wp_viewport_interface: Static<wl_interface> = Static(wl_interface {
+    name: b"wp_viewport\0".as_ptr() as *const c_char,
+    version: 1,
+    request_count: 3,
+    requests: wp_viewport_requests.0.as_ptr(),
+    event_count: 0,
+    events: ptr::null(),
+});
+
+static get_fractional_scale_types: Static<[*const wl_interface; 2]> =
+    Static([&wp_fractional_scale_v1_interface.0, ptr::null()]);
+static wp_fractional_scale_manager_v1_requests: Static<[wl_message; 2]> = Static([
+    message!("destroy", "", NULL_TYPES.0.as_ptr()),
+    message!(
+        "get_fractional_scale",
+        "no",
+        get_fractional_scale_types.0.as_ptr()
+    ),
+]);
+pub(crate) static wp_fractional_scale_manager_v1_interface: Static<wl_interface> =
+    Static(wl_interface {
+        name: b"wp_fractional_scale_manager_v1\0".as_ptr() as *const c_char,
+        version: 1,
+        request_count: 2,
+        requests: wp_fractional_scale_manager_v1_requests.0.as_ptr(),
+        event_count: 0,
+        events: ptr::null(),
+    });
+
+static wp_fractional_scale_v1_requests: Static<[wl_message; 1]> =
+    Static([message!("destroy", "", NULL_TYPES.0.as_ptr())]);
+static wp_fractional_scale_v1_events: Static<[wl_message; 1]> =
+    Static([message!("preferred_scale", "u", NULL_TYPES.0.as_ptr())]);
+pub(crate) static wp_fractional_scale_v1_interface: Static<wl_interface> = Static(wl_interface {
+    name: b"wp_fractional_scale_v1\0".as_ptr() as *const c_char,
+    version: 1,
+    request_count: 1,
+    requests: wp_fractional_scale_v1_requests.0.as_ptr(),
+    event_count: 1,
+    events: wp_fractional_scale_v1_events.0.as_ptr(),
+});
+
+static feedback_types: Static<[*const wl_interface; 2]> =
+    Static([ptr::null(), &wp_presentation_feedback_interface.0]);
+static wp_presentation_requests: Static<[wl_message; 2]> = Static([
+    message!("destroy", "", NULL_TYPES.0.as_ptr()),
+    message!("feedback", "on", feedback_types.0.as_ptr()),
+]);
+static wp_presentation_events: Static<[wl_message; 1]> =
+    Static([message!("clock_id", "u", NULL_TYPES.0.as_ptr())]);
+pub(crate) static wp_presentation_interface: Static<wl_interface> = Static(wl_interface {
+    name: b"wp_presentation\0".as_ptr() as *const c_char,
+    version: 1,
+    request_count: 2,
+    requests: wp_presentation_requests.0.as_ptr(),
+    event_count: 1,
+    events: wp_presentation_events.0.as_ptr(),
+});
+
+static wp_presentation_feedback_events: Static<[wl_message; 3]> = Static([
+    message!("sync_output", "o", NULL_TYPES.0.as_ptr()),
+    message!("presented", "uuuuuuu", NULL_TYPES.0.as_ptr()),
+    message!("discarded", "", NULL_TYPES.0.as_ptr()),
+]);
+pub(crate) static wp_presentation_feedback_interface: Static<wl_interface> = Static(wl_interface {
+    name: b"wp_presentation_feedback\0".as_ptr() as *const c_char,
+    version: 1,
+    request_count: 0,
+    requests: ptr::null(),
+    event_count: 3,
+    events: wp_presentation_feedback_events.0.as_ptr(),
+});
+
+// `create_windows_scrgb` is left out, along with the types of the `new_id` arguments of requests
+// that we never send.
+static get_color_management_surface_types: Static<[*const wl_interface; 2]> =
+    Static([&wp_color_management_surface_v1_interface.0, ptr::null()]);
+static create_parametric_creator_types: Static<[*const wl_interface; 1]> =
+    Static([&wp_image_description_creator_params_v1_interface.0]);
+static wp_color_manager_v1_requests: Static<[wl_message; 6]> = Static([
+    message!("destroy", "", NULL_TYPES.0.as_ptr()),
+    message!("get_output", "no", NULL_TYPES.0.as_ptr()),
+    message!(
+        "get_surface",
+        "no",
+        get_color_management_surface_types.0.as_ptr()
+    ),
+    message!("get_surface_feedback", "no", NULL_TYPES.0.as_ptr()),
+    message!("create_icc_creator", "n", NULL_TYPES.0.as_ptr()),
+    message!(
+        "create_parametric_creator",
+        "n",
+        create_parametric_creator_types.0.as_ptr()
+    ),
+]);
+static wp_color_manager_v1_events: Static<[wl_message; 5]> = Static([
+    message!("supported_intent", "u", NULL_TYPES.0.as_ptr()),
+    message!("supported_feature", "u", NULL_TYPES.0.as_ptr()),
+    message!("supported_tf_named", "u", NULL_TYPES.0.as_ptr()),
+    message!("supported_primaries_named", "u", NULL_TYPES.0.as_ptr()),
+    message!("done", "", NULL_TYPES.0.as_ptr()),
+]);
+pub(crate) static wp_color_manager_v1_interface: Static<wl_interface> = Static(wl_interface {
+    name: b"wp_color_manager_v1\0".as_ptr() as *const c_char,
+    version: 1,
+    request_count: 6,
+    requests: wp_color_manager_v1_requests.0.as_ptr(),
+    event_count: 5,
+    events: wp_color_manager_v1_events.0.as_ptr(),
+});
+
+static wp_color_management_surface_v1_requests: Static<[wl_message; 3]> = Static([
+    message!("destroy", "", NULL_TYPES.0.as_ptr()),
+    message!("set_image_description", "ou", NULL_TYPES.0.as_ptr()),
+    message!("unset_image_description", "", NULL_TYPES.0.as_ptr()),
+]);
+pub(crate) static wp_color_management_surface_v1_interface: Static<wl_interface> =
+    Static(wl_interface {
+        name: b"wp_color_management_surface_v1\0".as_ptr() as *const c_char,
+        version: 1,
+        request_count: 3,
+        requests: wp_color_management_surface_v1_requests.0.as_ptr(),
+        event_count: 0,
+        events: ptr::null(),
+    });
+
+// Only the requests up to `set_primaries_named` are described, since we only ever build image
+// descriptions out of named primaries and transfer functions.
+static create_image_description_types: Static<[*const wl_interface; 1]> =
+    Static([&wp_image_description_v1_interface.0]);
+static wp_image_description_creator_params_v1_requests: Static<[wl_message; 4]> = Static([
+    message!("create", "n", create_image_description_types.0.as_ptr()),
+    message!("set_tf_named", "u", NULL_TYPES.0.as_ptr()),
+    message!("set_tf_power", "u", NULL_TYPES.0.as_ptr()),
+    message!("set_primaries_named", "u", NULL_TYPES.0.as_ptr()),
+]);
+pub(crate) static wp_image_description_creator_params_v1_interface: Static<wl_interface> =
+    Static(wl_interface {
+        name: b"wp_image_description_creator_params_v1\0".as_ptr() as *const c_char,
+        version: 1,
+        request_count: 4,
+        requests: wp_image_description_creator_params_v1_requests.0.as_ptr(),
+        event_count: 0,
+        events: ptr::null(),
+    });
+
+// `get_information` is left out, since we never ask for the details of an image description.
+static wp_image_description_v1_requests: Static<[wl_message; 1]> =
+    Static([message!("destroy", "", NULL_TYPES.0.as_ptr())]);
+static wp_image_description_v1_events: Static<[wl_message; 2]> = Static([
+    message!("failed", "us", NULL_TYPES.0.as_ptr()),
+    message!("ready", "u", NULL_TYPES.0.as_ptr()),
+]);
+pub(crate) static wp_image_description_v1_interface: Static<wl_interface> = Static(wl_interface {
+    name: b"wp_image_description_v1\0".as_ptr() as *const c_char,
+    version: 1,
+    request_count: 1,
+    requests: wp_image_description_v1_requests.0.as_ptr(),
+    event_count: 2,
+    events: wp_image_description_v1_events.0.as_ptr(),
+});
+
+static get_surface_types: Static<[*const wl_interface; 2]> =
+    Static([&wp_linux_drm_syncobj_surface_v1_interface.0, ptr::null()]);
+static import_timeline_types: Static<[*const wl_interface; 2]> =
+    Static([&wp_linux_drm_syncobj_timeline_v1_interface.0, ptr::null()]);
+static wp_linux_drm_syncobj_manager_v1_requests: Static<[wl_message; 3]> = Static([
+    message!("destroy", "", NULL_TYPES.0.as_ptr()),
+    message!("get_surface", "no", get_surface_types.0.as_ptr()),
+    message!("import_timeline", "nh", import_timeline_types.0.as_ptr()),
+]);
+pub(crate) static wp_linux_drm_syncobj_manager_v1_interface: Static<wl_interface> =
+    Static(wl_interface {
+        name: b"wp_linux_drm_syncobj_manager_v1\0".as_ptr() as *const c_char,
+        version: 1,
+        request_count: 3,
+        requests: wp_linux_drm_syncobj_manager_v1_requests.0.as_ptr(),
+        event_count: 0,
+        events: ptr::null(),
+    });
+
+static wp_linux_drm_syncobj_timeline_v1_requests: Static<[wl_message; 1]> =
+    Static([message!("destroy", "", NULL_TYPES.0.as_ptr())]);
+pub(crate) static wp_linux_drm_syncobj_timeline_v1_interface: Static<wl_interface> =
+    Static(wl_interface {
+        name: b"wp_linux_drm_syncobj_timeline_v1\0".as_ptr() as *const c_char,
+        version: 1,
+        request_count: 1,
+        requests: wp_linux_drm_syncobj_timeline_v1_requests.0.as_ptr(),
+        event_count: 0,
+        events: ptr::null(),
+    });
+
+static wp_linux_drm_syncobj_surface_v1_requests: Static<[wl_message; 3]> = Static([
+    message!("destroy", "", NULL_TYPES.0.as_ptr()),
+    message!("set_acquire_point", "ouu", NULL_TYPES.0.as_ptr()),
+    message!("set_release_point", "ouu", NULL_TYPES.0.as_ptr()),
+]);
+pub(crate) static wp_linux_drm_syncobj_surface_v1_interface: Static<wl_interface> =
+    Static(wl_interface {
+        name: b"wp_linux_drm_syncobj_surface_v1\0".as_ptr() as *const c_char,
+        version: 1,
+        request_count: 3,
+        requests: wp_linux_drm_syncobj_surface_v1_requests.0.as_ptr(),
+        event_count: 0,
+        events: ptr::null(),
+    });
+
+static get_synchronization_types: Static<[*const wl_interface; 2]> = Static([
+    &zwp_linux_surface_synchronization_v1_interface.0,
+    ptr::null(),
+]);
+static zwp_linux_explicit_synchronization_v1_requests: Static<[wl_message; 2]> = Static([
+    message!("destroy", "", NULL_TYPES.0.as_ptr()),
+    message!(
+        "get_synchronization",
+        "no",
+        get_synchronization_types.0.as_ptr()
+    ),
+]);
+pub(crate) static zwp_linux_explicit_synchronization_v1_interface: Static<wl_interface> =
+    Static(wl_interface {
+        name: b"zwp_linux_explicit_synchronization_v1\0".as_ptr() as *const c_char,
+        version: 1,
+        request_count: 2,
+        requests: zwp_linux_explicit_synchronization_v1_requests.0.as_ptr(),
+        event_count: 0,
+        events: ptr::null(),
+    });
+
+// `get_release` is left out, since we never ask for release fences through this protocol: doing so
+// would suppress the `wl_buffer.release` events that EGL relies on to recycle its buffers.
+static zwp_linux_surface_synchronization_v1_requests: Static<[wl_message; 2]> = Static([
+    message!("destroy", "", NULL_TYPES.0.as_ptr()),
+    message!("set_acquire_fence", "h", NULL_TYPES.0.as_ptr()),
+]);
+pub(crate) static zwp_linux_surface_synchronization_v1_interface: Static<wl_interface> =
+    Static(wl_interface {
+        name: b"zwp_linux_surface_synchronization_v1\0".as_ptr() as *const c_char,
+        version: 1,
+        request_count: 2,
+        requests: zwp_linux_surface_synchronization_v1_requests.0.as_ptr(),
+        event_count: 0,
+        events: ptr::null(),
+    });
+
+// `wl_buffer` is a core interface, but `wayland-sys` doesn't export its description, and we need
+// one in order to create buffers.
+static wl_buffer_requests: Static<[wl_message; 1]> =
+    Static([message!("destroy", "", NULL_TYPES.0.as_ptr())]);
+static wl_buffer_events: Static<[wl_message; 1]> =
+    Static([message!("release", "", NULL_TYPES.0.as_ptr())]);
+pub(crate) static wl_buffer_interface: Static<wl_interface> = Static(wl_interface {
+    name: b"wl_buffer\0".as_ptr() as *const c_char,
+    version: 1,
+    request_count: 1,
+    requests: wl_buffer_requests.0.as_ptr(),
+    event_count: 1,
+    events: wl_buffer_events.0.as_ptr(),
+});
+
+// Likewise for `wl_output`, which we bind ourselves in order to enumerate displays.
+static wl_output_requests: Static<[wl_message; 1]> =
+    Static([message!("release", "3", NULL_TYPES.0.as_ptr())]);
+static wl_output_events: Static<[wl_message; 6]> = Static([
+    message!("geometry", "iiiiissi", NULL_TYPES.0.as_ptr()),
+    message!("mode", "uiii", NULL_TYPES.0.as_ptr()),
+    message!("done", "2", NULL_TYPES.0.as_ptr()),
+    message!("scale", "2i", NULL_TYPES.0.as_ptr()),
+    message!("name", "4s", NULL_TYPES.0.as_ptr()),
+    message!("description", "4s", NULL_TYPES.0.as_ptr()),
+]);
+pub(crate) static wl_output_interface: Static<wl_interface> = Static(wl_interface {
+    name: b"wl_output\0".as_ptr() as *const c_char,
+    version: 4,
+    request_count: 1,
+    requests: wl_output_requests.0.as_ptr(),
+    event_count: 6,
+    events: wl_output_events.0.as_ptr(),
+});
+
+// Likewise for `wl_shm` and `wl_shm_pool`, which share memory with the compositor when
+// presenting without a GPU.
+static create_pool_types: Static<[*const wl_interface; 3]> =
+    Static([&wl_shm_pool_interface.0, ptr::null(), ptr::null()]);
+static wl_shm_requests: Static<[wl_message; 1]> =
+    Static([message!("create_pool", "nhi", create_pool_types.0.as_ptr())]);
+static wl_shm_events: Static<[wl_message; 1]> =
+    Static([message!("format", "u", NULL_TYPES.0.as_ptr())]);
+pub(crate) static wl_shm_interface: Static<wl_interface> = Static(wl_interface {
+    name: b"wl_shm\0".as_ptr() as *const c_char,
+    version: 1,
+    request_count: 1,
+    requests: wl_shm_requests.0.as_ptr(),
+    event_count: 1,
+    events: wl_shm_events.0.as_ptr(),
+});
+
+static create_buffer_types: Static<[*const wl_interface; 6]> = Static([
+    &wl_buffer_interface.0,
+    ptr::null(),
+    ptr::null(),
+    ptr::null(),
+    ptr::null(),
+    ptr::null(),
+]);
+static wl_shm_pool_requests: Static<[wl_message; 3]> = Static([
+    message!("create_buffer", "niiiiu", create_buffer_types.0.as_ptr()),
+    message!("destroy", "", NULL_TYPES.0.as_ptr()),
+    message!("resize", "i", NULL_TYPES.0.as_ptr()),
+]);
+pub(crate) static wl_shm_pool_interface: Static<wl_interface> = Static(wl_interface {
+    name: b"wl_shm_pool\0".as_ptr() as *const c_char,
+    version: 1,
+    request_count: 3,
+    requests: wl_shm_pool_requests.0.as_ptr(),
+    event_count: 0,
+    events: ptr::null(),
+});
+
+static create_params_types: Static<[*const wl_interface; 1]> =
+    Static([&zwp_linux_buffer_params_v1_interface.0]);
+static get_feedback_types: Static<[*const wl_interface; 2]> =
+    Static([&zwp_linux_dmabuf_feedback_v1_interface.0, ptr::null()]);
+static zwp_linux_dmabuf_v1_requests: Static<[wl_message; 4]> = Static([
+    message!("destroy", "", NULL_TYPES.0.as_ptr()),
+    message!("create_params", "n", create_params_types.0.as_ptr()),
+    message!("get_default_feedback", "4n", get_feedback_types.0.as_ptr()),
+    message!("get_surface_feedback", "4no", get_feedback_types.0.as_ptr()),
+]);
+static zwp_linux_dmabuf_v1_events: Static<[wl_message; 2]> = Static([
+    message!("format", "u", NULL_TYPES.0.as_ptr()),
+    message!("modifier", "3uuu", NULL_TYPES.0.as_ptr()),
+]);
+pub(crate) static zwp_linux_dmabuf_v1_interface: Static<wl_interface> = Static(wl_interface {
+    name: b"zwp_linux_dmabuf_v1\0".as_ptr() as *const c_char,
+    version: 4,
+    request_count: 4,
+    requests: zwp_linux_dmabuf_v1_requests.0.as_ptr(),
+    event_count: 2,
+    events: zwp_linux_dmabuf_v1_events.0.as_ptr(),
+});
+
+static zwp_linux_dmabuf_feedback_v1_requests: Static<[wl_message; 1]> =
+    Static([message!("destroy", "", NULL_TYPES.0.as_ptr())]);
+static zwp_linux_dmabuf_feedback_v1_events: Static<[wl_message; 7]> = Static([
+    message!("done", "", NULL_TYPES.0.as_ptr()),
+    message!("format_table", "hu", NULL_TYPES.0.as_ptr()),
+    message!("main_device", "a", NULL_TYPES.0.as_ptr()),
+    message!("tranche_done", "", NULL_TYPES.0.as_ptr()),
+    message!("tranche_target_device", "a", NULL_TYPES.0.as_ptr()),
+    message!("tranche_formats", "a", NULL_TYPES.0.as_ptr()),
+    message!("tranche_flags", "u", NULL_TYPES.0.as_ptr()),
+]);
+pub(crate) static zwp_linux_dmabuf_feedback_v1_interface: Static<wl_interface> =
+    Static(wl_interface {
+        name: b"zwp_linux_dmabuf_feedback_v1\0".as_ptr() as *const c_char,
+        version: 4,
+        request_count: 1,
+        requests: zwp_linux_dmabuf_feedback_v1_requests.0.as_ptr(),
+        event_count: 7,
+        events: zwp_linux_dmabuf_feedback_v1_events.0.as_ptr(),
+    });
+
+static create_immed_types: Static<[*const wl_interface; 5]> = Static([
+    &wl_buffer_interface.0,
+    ptr::null(),
+    ptr::null(),
+    ptr::null(),
+    ptr::null(),
+]);
+static created_types: Static<[*const wl_interface; 1]> = Static([&wl_buffer_interface.0]);
+static zwp_linux_buffer_params_v1_requests: Static<[wl_message; 4]> = Static([
+    message!("destroy", "", NULL_TYPES.0.as_ptr()),
+    message!("add", "huuuuu", NULL_TYPES.0.as_ptr()),
+    message!("create", "iiuu", NULL_TYPES.0.as_ptr()),
+    message!("create_immed", "2niiuu", create_immed_types.0.as_ptr()),
+]);
+static zwp_linux_buffer_params_v1_events: Static<[wl_message; 2]> = Static([
+    message!("created", "n", created_types.0.as_ptr()),
+    message!("failed", "", NULL_TYPES.0.as_ptr()),
+]);
+pub(crate) static zwp_linux_buffer_params_v1_interface: Static<wl_interface> =
+    Static(wl_interface {
+        name: b"zwp_linux_buffer_params_v1\0".as_ptr() as *const c_char,
+        version: 4,
+        request_count: 4,
+        requests: zwp_linux_buffer_params_v1_requests.0.as_ptr(),
+        event_count: 2,
+        events: zwp_linux_buffer_params_v1_events.0.as_ptr(),
+    });
@@ -0,0 +1,227 @@
+// surfman/surfman/src/platform/unix/wayland/gbm.rs
+//
+//! Allocation of generic surface images with GBM, so that their layout can be negotiated.
+//!
+//! Images that EGL allocates for textures use whatever layout the driver picks, which is usually
+//! linear when the image is meant to be shared. Allocating them with GBM instead lets us choose a
+//! format modifier that both the compositor and EGL understand, so tiled and compressed layouts
+//! can be used all the way to the screen.
+
+use crate::platform::generic::egl::surface::{EGLDmaBuf, EGLDmaBufPlane};
+
+use euclid::default::Size2D;
+use std::mem;
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+use std::os::unix::io::RawFd;
+
+// The fourcc of the images that back generic surfaces, matching the byte order of `GL_RGBA`.
+pub(crate) const DRM_FORMAT_ABGR8888: u32 = 0x3432_4241;
+
+// The vendors in the top byte of DRM format modifiers, from `drm_fourcc.h`.
+const DRM_FORMAT_MOD_VENDOR_INTEL: u64 = 0x01;
+const DRM_FORMAT_MOD_VENDOR_AMD: u64 = 0x02;
+const DRM_FORMAT_MOD_VENDOR_NVIDIA: u64 = 0x03;
+const DRM_FORMAT_MOD_VENDOR_QCOM: u64 = 0x05;
+const DRM_FORMAT_MOD_VENDOR_ARM: u64 = 0x08;
+
+type CreateDeviceFn = unsafe extern "C" fn(fd: c_int) -> *mut c_void;
+type DeviceDestroyFn = unsafe extern "C" fn(device: *mut c_void);
+type BoCreateWithModifiersFn = unsafe extern "C" fn(
+    device: *mut c_void,
+    width: u32,
+    height: u32,
+    format: u32,
+    modifiers: *const u64,
+    count: c_uint,
+) -> *mut c_void;
+type BoGetPlaneCountFn = unsafe extern "C" fn(bo: *mut c_void) -> c_int;
+type BoGetFdFn = unsafe extern "C" fn(bo: *mut c_void) -> c_int;
+type BoGetFdForPlaneFn = unsafe extern "C" fn(bo: *mut c_void, plane: c_int) -> c_int;
+type BoGetStrideForPlaneFn = unsafe extern "C" fn(bo: *mut c_void, plane: c_int) -> u32;
+type BoGetOffsetFn = unsafe extern "C" fn(bo: *mut c_void, plane: c_int) -> u32;
+type BoGetModifierFn = unsafe extern "C" fn(bo: *mut c_void) -> u64;
+type BoDestroyFn = unsafe extern "C" fn(bo: *mut c_void);
+
+// `libgbm` is loaded at runtime, so that its absence just means falling back to EGL's own
+// allocations.
+#[allow(non_snake_case)]
+struct GbmFunctions {
+    CreateDevice: CreateDeviceFn,
+    DeviceDestroy: DeviceDestroyFn,
+    BoCreateWithModifiers: BoCreateWithModifiersFn,
+    BoGetPlaneCount: BoGetPlaneCountFn,
+    BoGetFd: BoGetFdFn,
+    // Only available in Mesa 21 and later.
+    BoGetFdForPlane: Option<BoGetFdForPlaneFn>,
+    BoGetStrideForPlane: BoGetStrideForPlaneFn,
+    BoGetOffset: BoGetOffsetFn,
+    BoGetModifier: BoGetModifierFn,
+    BoDestroy: BoDestroyFn,
+}
+
+lazy_static! {
+    static ref GBM_FUNCTIONS: Option<GbmFunctions> = unsafe {
+        let library_names: [&[u8]; 2] = [b"libgbm.so.1\0", b"libgbm.so\0"];
+        let library = library_names
+            .iter()
+            .map(|name| libc::dlopen(name.as_ptr() as *const c_char, libc::RTLD_LAZY))
+            .find(|library| !library.is_null())?;
+        let get = |name: &'static [u8]| libc::dlsym(library, name.as_ptr() as *const c_char);
+        let symbols = [
+            get(b"gbm_create_device\0"),
+            get(b"gbm_device_destroy\0"),
+            get(b"gbm_bo_create_with_modifiers\0"),
+            get(b"gbm_bo_get_plane_count\0"),
+            get(b"gbm_bo_get_fd\0"),
+            get(b"gbm_bo_get_stride_for_plane\0"),
+            get(b"gbm_bo_get_offset\0"),
+            get(b"gbm_bo_get_modifier\0"),
+            get(b"gbm_bo_destroy\0"),
+        ];
+        if symbols.iter().any(|symbol| symbol.is_null()) {
+            return None;
+        }
+        let get_fd_for_plane = get(b"gbm_bo_get_fd_for_plane\0");
+        Some(GbmFunctions {
+            CreateDevice: mem::transmute::<*mut c_void, CreateDeviceFn>(symbols[0]),
+            DeviceDestroy: mem::transmute::<*mut c_void, DeviceDestroyFn>(symbols[1]),
+            BoCreateWithModifiers: mem::transmute::<*mut c_void, BoCreateWithModifiersFn>(
+                symbols[2],
+            ),
+            BoGetPlaneCount: mem::transmute::<*mut c_void, BoGetPlaneCountFn>(symbols[3]),
+            BoGetFd: mem::transmute::<*mut c_void, BoGetFdFn>(symbols[4]),
+            BoGetFdForPlane: if get_fd_for_plane.is_null() {
+                None
+            } else {
+                Some(mem::transmute::<*mut c_void, BoGetFdForPlaneFn>(
+                    get_fd_for_plane,
+                ))
+            },
+            BoGetStrideForPlane: mem::transmute::<*mut c_void, BoGetStrideForPlaneFn>(symbols[5]),
+            BoGetOffset: mem::transmute::<*mut c_void, BoGetOffsetFn>(symbols[6]),
+            BoGetModifier: mem::transmute::<*mut c_void, BoGetModifierFn>(symbols[7]),
+            BoDestroy: mem::transmute::<*mut c_void, BoDestroyFn>(symbols[8]),
+        })
+    };
+}
+
+pub(crate) struct GbmDevice {
+    gbm_functions: &'static GbmFunctions,
+    device: *mut c_void,
+    drm_fd: RawFd,
+}
+
+unsafe impl Send for GbmDevice {}
+
+impl GbmDevice {
+    // Wraps the given DRM device, which the GBM device takes ownership of.
+    pub(crate) unsafe fn new(drm_fd: RawFd) -> Option<GbmDevice> {
+        let gbm_functions = match *GBM_FUNCTIONS {
+            Some(ref gbm_functions) => gbm_functions,
+            None => {
+                libc::close(drm_fd);
+                return None;
+            }
+        };
+
+        let device = (gbm_functions.CreateDevice)(drm_fd);
+        if device.is_null() {
+            libc::close(drm_fd);
+            return None;
+        }
+        Some(GbmDevice {
+            gbm_functions,
+            device,
+            drm_fd,
+        })
+    }
+
+    // Returns the `gbm_device`, for use as the native display of an EGL display.
+    pub(crate) fn as_ptr(&self) -> *mut c_void {
+        self.device
+    }
+
+    // Allocates an image with one of the given modifiers, letting the driver pick the best one.
+    //
+    // The buffer object is released right away; the returned DMA-BUF keeps the memory alive.
+    pub(crate) unsafe fn allocate(
+        &self,
+        size: &Size2D<i32>,
+        fourcc: u32,
+        modifiers: &[u64],
+    ) -> Option<EGLDmaBuf> {
+        if modifiers.is_empty() || size.width <= 0 || size.height <= 0 {
+            return None;
+        }
+
+        let gbm = self.gbm_functions;
+        let bo = (gbm.BoCreateWithModifiers)(
+            self.device,
+            size.width as u32,
+            size.height as u32,
+            fourcc,
+            modifiers.as_ptr(),
+            modifiers.len() as c_uint,
+        );
+        if bo.is_null() {
+            return None;
+        }
+
+        let mut dma_buf = EGLDmaBuf {
+            fourcc,
+            modifier: (gbm.BoGetModifier)(bo),
+            planes: vec![],
+        };
+        let plane_count = (gbm.BoGetPlaneCount)(bo);
+        for plane_index in 0..plane_count {
+            let fd = match gbm.BoGetFdForPlane {
+                Some(get_fd_for_plane) => get_fd_for_plane(bo, plane_index),
+                None if plane_count == 1 => (gbm.BoGetFd)(bo),
+                None => -1,
+            };
+            if fd < 0 {
+                (gbm.BoDestroy)(bo);
+                return None;
+            }
+            dma_buf.planes.push(EGLDmaBufPlane {
+                fd,
+                offset: (gbm.BoGetOffset)(bo, plane_index),
+                stride: (gbm.BoGetStrideForPlane)(bo, plane_index),
+            });
+        }
+
+        (gbm.BoDestroy)(bo);
+        if dma_buf.planes.is_empty() {
+            return None;
+        }
+        Some(dma_buf)
+    }
+}
+
+// Returns true if images with the given modifier are stored compressed (AFBC or AFRC on Arm, CCS on
+// Intel, DCC on AMD, and so on), which consumers that map or import them must understand.
+pub(crate) fn modifier_is_compressed(modifier: u64) -> bool {
+    let value = modifier & 0x00ff_ffff_ffff_ffff;
+    match modifier >> 56 {
+        // The `I915_FORMAT_MOD_*_CCS*` modifiers.
+        DRM_FORMAT_MOD_VENDOR_INTEL => matches!(value, 4..=8 | 10..=17),
+        // `AMD_FMT_MOD_DCC`.
+        DRM_FORMAT_MOD_VENDOR_AMD => value & (1 << 13) != 0,
+        // The compression field of `DRM_FORMAT_MOD_NVIDIA_BLOCK_LINEAR_2D`.
+        DRM_FORMAT_MOD_VENDOR_NVIDIA => value & 0x10 != 0 && (value >> 23) & 0x7 != 0,
+        // `DRM_FORMAT_MOD_QCOM_COMPRESSED`.
+        DRM_FORMAT_MOD_VENDOR_QCOM => value == 1,
+        // The AFBC and AFRC modifier types.
+        DRM_FORMAT_MOD_VENDOR_ARM => matches!(value >> 52, 0x0 | 0x2),
+        _ => false,
+    }
+}
+
+impl Drop for GbmDevice {
+    fn drop(&mut self) {
+        unsafe {
+            (self.gbm_functions.DeviceDestroy)(self.device);
+            libc::close(self.drm_fd);
+        }
+    }
+}
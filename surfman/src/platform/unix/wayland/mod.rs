@@ -7,6 +7,14 @@ pub mod context;
 pub mod device;
 pub mod surface;
 
+mod buffer;
+mod color;
+pub(crate) mod ffi;
+pub(crate) mod gbm;
+mod output;
+mod sync;
+mod widget;
+
 #[path = "../../../implementation/mod.rs"]
 mod implementation;
 
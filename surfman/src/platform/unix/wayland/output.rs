@@ -0,0 +1,168 @@
+// surfman/surfman/src/platform/unix/wayland/output.rs
+//
+//! Tracking of the compositor's `wl_output` globals, for display enumeration.
+
+use super::connection::{bind, Globals};
+use super::ffi::{wl_output_interface, wl_output_listener, WL_OUTPUT_MODE_CURRENT};
+use super::ffi::{WL_OUTPUT_RELEASE, WL_OUTPUT_RELEASE_SINCE_VERSION};
+use crate::DisplayInfo;
+
+use euclid::default::{Point2D, Size2D};
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_void};
+use std::ptr;
+use wayland_sys::client::{wl_proxy, WAYLAND_CLIENT_HANDLE};
+
+// What the compositor has told us about one output so far.
+//
+// Boxed, so that its address can serve as the user data of the listener.
+pub(crate) struct OutputState {
+    proxy: *mut wl_proxy,
+    global_name: u32,
+    name: String,
+    origin: Point2D<i32>,
+    size: Size2D<i32>,
+    // In millihertz, as sent by the compositor; zero if unknown.
+    refresh: i32,
+    scale: i32,
+    transform: i32,
+}
+
+static OUTPUT_LISTENER: wl_output_listener = wl_output_listener {
+    geometry: output_geometry,
+    mode: output_mode,
+    done: output_done,
+    scale: output_scale,
+    name: output_name,
+    description: output_description,
+};
+
+impl OutputState {
+    pub(crate) fn global_name(&self) -> u32 {
+        self.global_name
+    }
+
+    pub(crate) fn proxy(&self) -> *mut wl_proxy {
+        self.proxy
+    }
+
+    pub(crate) fn info(&self) -> DisplayInfo {
+        // Rotated outputs show the mode sideways.
+        let size = if self.transform % 2 == 1 {
+            Size2D::new(self.size.height, self.size.width)
+        } else {
+            self.size
+        };
+        DisplayInfo {
+            id: self.global_name as u64,
+            name: self.name.clone(),
+            origin: self.origin,
+            size,
+            refresh_rate: if self.refresh > 0 {
+                Some(self.refresh as f64 / 1000.0)
+            } else {
+                None
+            },
+            scale_factor: self.scale as f64,
+            colorspace: None,
+        }
+    }
+}
+
+impl Drop for OutputState {
+    fn drop(&mut self) {
+        unsafe {
+            let version = (WAYLAND_CLIENT_HANDLE.wl_proxy_get_version)(self.proxy);
+            if version >= WL_OUTPUT_RELEASE_SINCE_VERSION {
+                (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array)(
+                    self.proxy,
+                    WL_OUTPUT_RELEASE,
+                    ptr::null_mut(),
+                );
+            }
+            (WAYLAND_CLIENT_HANDLE.wl_proxy_destroy)(self.proxy);
+        }
+    }
+}
+
+// Binds a `wl_output` global and starts listening for its description.
+pub(crate) unsafe fn bind_output(
+    globals: &mut Globals,
+    registry: *mut wl_proxy,
+    global_name: u32,
+    advertised_version: u32,
+) {
+    let proxy = bind(
+        registry,
+        global_name,
+        &wl_output_interface.0,
+        advertised_version,
+    );
+    if proxy.is_null() {
+        return;
+    }
+
+    let mut output = Box::new(OutputState {
+        proxy,
+        global_name,
+        name: String::new(),
+        origin: Point2D::zero(),
+        size: Size2D::zero(),
+        refresh: 0,
+        scale: 1,
+        transform: 0,
+    });
+    (WAYLAND_CLIENT_HANDLE.wl_proxy_add_listener)(
+        proxy,
+        &OUTPUT_LISTENER as *const wl_output_listener as *mut extern "C" fn(),
+        &mut *output as *mut OutputState as *mut c_void,
+    );
+    globals.outputs.push(output);
+}
+
+unsafe extern "C" fn output_geometry(
+    data: *mut c_void,
+    _: *mut wl_proxy,
+    x: i32,
+    y: i32,
+    _: i32,
+    _: i32,
+    _: i32,
+    _: *const c_char,
+    _: *const c_char,
+    transform: i32,
+) {
+    let output = &mut *(data as *mut OutputState);
+    output.origin = Point2D::new(x, y);
+    output.transform = transform;
+}
+
+unsafe extern "C" fn output_mode(
+    data: *mut c_void,
+    _: *mut wl_proxy,
+    flags: u32,
+    width: i32,
+    height: i32,
+    refresh: i32,
+) {
+    if flags & WL_OUTPUT_MODE_CURRENT == 0 {
+        return;
+    }
+    let output = &mut *(data as *mut OutputState);
+    output.size = Size2D::new(width, height);
+    output.refresh = refresh;
+}
+
+unsafe extern "C" fn output_done(_: *mut c_void, _: *mut wl_proxy) {}
+
+unsafe extern "C" fn output_scale(data: *mut c_void, _: *mut wl_proxy, factor: i32) {
+    let output = &mut *(data as *mut OutputState);
+    output.scale = factor;
+}
+
+unsafe extern "C" fn output_name(data: *mut c_void, _: *mut wl_proxy, name: *const c_char) {
+    let output = &mut *(data as *mut OutputState);
+    output.name = CStr::from_ptr(name).to_string_lossy().into_owned();
+}
+
+unsafe extern "C" fn output_description(_: *mut c_void, _: *mut wl_proxy, _: *const c_char) {}
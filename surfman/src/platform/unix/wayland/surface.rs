@@ -2,18 +2,36 @@
 //
 //! A surface implementation using Wayland surfaces backed by TextureImage.
 
+use super::buffer;
+use super::connection::Globals;
 use super::context::{Context, GL_FUNCTIONS};
 use super::device::Device;
+use super::gbm::{self, DRM_FORMAT_ABGR8888};
+use super::widget::WidgetState;
 use crate::gl;
 use crate::gl::types::{GLenum, GLuint};
+#[cfg(feature = "sm-opencl")]
+use crate::opencl::SharedEGLImage;
 use crate::platform::generic::egl::context;
-use crate::platform::generic::egl::surface::{EGLBackedSurface, EGLSurfaceTexture};
-use crate::{Error, SurfaceAccess, SurfaceInfo, SurfaceType};
+use crate::platform::generic::egl::device;
+use crate::platform::generic::egl::ffi::{DRM_FORMAT_MOD_INVALID, DRM_FORMAT_MOD_LINEAR};
+use crate::platform::generic::egl::surface::EGLSurfaceTexture;
+use crate::platform::generic::egl::surface::{self, EGLBackedSurface, EGLSurfaceDestroyToken};
+use crate::surface::{estimate_memory_usage, SurfaceLayoutRequirements};
+use crate::{Colorspace, SurfaceType, SurfaceVisibility, WidgetOutputInfo};
+use crate::{ContextAttributes, Error, Gl, PresentationStatistics, SurfaceAccess, SurfaceUsage};
+use crate::{
+    CreateSurfacesResult, SurfaceAlphaMode, SurfaceFormat, SurfaceInfo, SurfaceOrigin,
+    SurfaceTransform,
+};
 
-use euclid::default::Size2D;
+pub use crate::platform::generic::egl::surface::EGLDmaBuf as DmaBuf;
+pub use crate::platform::generic::egl::surface::EGLDmaBufPlane as DmaBufPlane;
+
+use euclid::default::{Rect, Size2D};
 use std::marker::PhantomData;
 use std::os::raw::c_void;
-use wayland_sys::client::wl_proxy;
+use wayland_sys::client::{wl_display, wl_proxy};
 use wayland_sys::egl::{wl_egl_window, WAYLAND_EGL_HANDLE};
 
 // FIXME(pcwalton): Is this right, or should it be `TEXTURE_EXTERNAL_OES`?
@@ -55,63 +73,248 @@ pub struct SurfaceTexture(pub(crate) EGLSurfaceTexture);
 pub struct NativeWidget {
     pub(crate) wayland_surface: *mut wl_proxy,
     pub(crate) size: Size2D<i32>,
+    pub(crate) explicit_sync: bool,
 }
 
+/// The native resources of a surface given up with `Device::surface_into_destroy_token()`, which
+/// can be destroyed later without the surface's context.
+///
+/// Tokens can be sent to other threads, and must be destroyed with `Device::destroy_token()`, or
+/// their resources leak.
+pub struct SurfaceDestroyToken(pub(crate) EGLSurfaceDestroyToken);
+
 unsafe impl Send for Surface {}
 
+impl Surface {
+    /// Gives up this surface, returning a token that can be destroyed later with
+    /// `Device::destroy_token()`, on any thread and without the surface's context.
+    ///
+    /// The OpenGL objects that the surface owns in its context, such as its framebuffer, aren't
+    /// part of the token. They're freed when the context is destroyed.
+    #[inline]
+    pub(crate) fn into_destroy_token(self) -> SurfaceDestroyToken {
+        SurfaceDestroyToken(self.0.into_destroy_token())
+    }
+}
+
+impl NativeWidget {
+    /// Requests explicit synchronization with the compositor for widget surfaces created from
+    /// this native widget.
+    ///
+    /// When enabled, and the compositor supports `linux-drm-syncobj-v1` (or, failing that,
+    /// `zwp_linux_explicit_synchronization_v1`), each present carries explicit fences instead of
+    /// relying on implicit synchronization of the buffers.
+    ///
+    /// This is off by default, because a Wayland surface can only have one synchronization object
+    /// attached to it. Leave it off if the EGL implementation attaches one itself, as NVIDIA's
+    /// does; otherwise the compositor will raise a protocol error.
+    #[inline]
+    pub fn with_explicit_sync(mut self, enabled: bool) -> NativeWidget {
+        self.explicit_sync = enabled;
+        self
+    }
+}
+
 impl Device {
     /// Creates either a generic or a widget surface, depending on the supplied surface type.
     ///
     /// Only the given context may ever render to the surface, but generic surfaces can be wrapped
     /// up in a `SurfaceTexture` for reading by other contexts.
-    pub fn create_surface(
+    ///
+    /// The compositor blends widget surfaces as premultiplied. Opaque widget surfaces are shown
+    /// opaque if EGL supports `EGL_EXT_present_opaque`; otherwise, their alpha channel should be
+    /// kept at 1.
+    pub(crate) fn create_surface_impl(
         &mut self,
         context: &Context,
         _: SurfaceAccess,
+        surface_usage: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
         surface_type: SurfaceType<NativeWidget>,
     ) -> Result<Surface, Error> {
-        match surface_type {
-            SurfaceType::Generic { size } => self.create_generic_surface(context, &size),
+        let mut surface = match surface_type {
+            SurfaceType::Generic { size } => {
+                self.create_generic_surface(context, surface_usage, &size)?
+            }
+            // Window systems only composite premultiplied contents.
+            SurfaceType::Widget { .. } if surface_alpha_mode == SurfaceAlphaMode::Straight => {
+                return Err(Error::UnsupportedOnThisPlatform)
+            }
             SurfaceType::Widget { native_widget } => unsafe {
-                self.create_window_surface(
-                    context,
-                    native_widget.wayland_surface,
-                    &native_widget.size,
-                )
+                self.create_window_surface(context, &native_widget, surface_alpha_mode)?
             },
-        }
+        };
+        surface.0.alpha_mode = surface_alpha_mode;
+        Ok(surface)
+    }
+
+    /// Creates `count` generic surfaces of the given size.
+    ///
+    /// The context is made current, and its attributes looked up, once for the whole batch. If
+    /// one of the surfaces fails to be created, the error is returned along with the surfaces that
+    /// were created before it.
+    pub(crate) fn create_surfaces_impl(
+        &mut self,
+        context: &Context,
+        _: SurfaceAccess,
+        surface_usage: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
+        size: &Size2D<i32>,
+        count: usize,
+    ) -> CreateSurfacesResult<Surface> {
+        let _guard = match self.temporarily_make_context_current(context) {
+            Ok(guard) => guard,
+            Err(err) => return Err((err, vec![])),
+        };
+        let context_descriptor = self.context_descriptor(context);
+        let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+        GL_FUNCTIONS.with(|gl| {
+            Ok((0..count)
+                .map(|_| {
+                    let mut surface = self.create_generic_surface_with_attributes(
+                        gl,
+                        context,
+                        &context_attributes,
+                        surface_usage,
+                        size,
+                    );
+                    surface.0.alpha_mode = surface_alpha_mode;
+                    surface
+                })
+                .collect())
+        })
     }
 
     fn create_generic_surface(
         &mut self,
         context: &Context,
+        surface_usage: SurfaceUsage,
         size: &Size2D<i32>,
     ) -> Result<Surface, Error> {
         let _guard = self.temporarily_make_context_current(context)?;
         let context_descriptor = self.context_descriptor(context);
         let context_attributes = self.context_descriptor_attributes(&context_descriptor);
         GL_FUNCTIONS.with(|gl| {
-            Ok(Surface(EGLBackedSurface::new_generic(
+            Ok(self.create_generic_surface_with_attributes(
                 gl,
-                self.native_connection.egl_display,
-                context.0.egl_context,
-                context.0.id,
+                context,
                 &context_attributes,
+                surface_usage,
                 size,
-            )))
+            ))
         })
     }
 
+    // The context must be current.
+    fn create_generic_surface_with_attributes(
+        &self,
+        gl: &Gl,
+        context: &Context,
+        context_attributes: &ContextAttributes,
+        surface_usage: SurfaceUsage,
+        size: &Size2D<i32>,
+    ) -> Surface {
+        let surface = unsafe {
+            self.create_negotiated_generic_surface(
+                gl,
+                context,
+                context_attributes,
+                surface_usage,
+                size,
+            )
+        };
+        if let Some(surface) = surface {
+            return Surface(surface);
+        }
+
+        Surface(EGLBackedSurface::new_generic(
+            gl,
+            self.native_connection.egl_display,
+            context.0.egl_context,
+            context.0.id,
+            context_attributes,
+            size,
+        ))
+    }
+
+    // Allocates the image of a generic surface with a format modifier that both the compositor
+    // and EGL accept, so that sharing it doesn't force a linear layout.
+    //
+    // Linear surfaces are restricted to `DRM_FORMAT_MOD_LINEAR`, and uncompressed ones to modifiers
+    // without compression.
+    //
+    // Returns `None` if there's nothing to negotiate, in which case EGL allocates the image.
+    unsafe fn create_negotiated_generic_surface(
+        &self,
+        gl: &Gl,
+        context: &Context,
+        context_attributes: &ContextAttributes,
+        surface_usage: SurfaceUsage,
+        size: &Size2D<i32>,
+    ) -> Option<EGLBackedSurface> {
+        let (globals, gbm_device) = match (
+            &self.native_connection.globals,
+            &self.native_connection.gbm_device,
+        ) {
+            (&Some(ref globals), &Some(ref gbm_device)) => (globals, gbm_device),
+            _ => return None,
+        };
+
+        let egl_display = self.native_connection.egl_display;
+        let importable_modifiers =
+            device::query_dma_buf_modifiers(egl_display, DRM_FORMAT_ABGR8888);
+        let modifiers: Vec<u64> = globals
+            .dma_buf_formats
+            .iter()
+            .filter(|&&(fourcc, modifier)| {
+                fourcc == DRM_FORMAT_ABGR8888
+                    && modifier != DRM_FORMAT_MOD_INVALID
+                    && importable_modifiers.contains(&modifier)
+                    && (modifier == DRM_FORMAT_MOD_LINEAR
+                        || !surface_usage.contains(SurfaceUsage::LINEAR))
+                    && !(surface_usage.contains(SurfaceUsage::UNCOMPRESSED)
+                        && gbm::modifier_is_compressed(modifier))
+            })
+            .map(|&(_, modifier)| modifier)
+            .collect();
+
+        let dma_buf = gbm_device
+            .lock()
+            .unwrap()
+            .allocate(size, DRM_FORMAT_ABGR8888, &modifiers)?;
+        let egl_image = surface::create_egl_image_from_dma_buf(egl_display, &dma_buf, size).ok()?;
+        Some(EGLBackedSurface::new_from_egl_image(
+            gl,
+            egl_image,
+            SurfaceFormat::RGBA8,
+            context.0.id,
+            context_attributes,
+            size,
+        ))
+    }
+
     unsafe fn create_window_surface(
         &mut self,
         context: &Context,
-        wayland_surface: *mut wl_proxy,
-        size: &Size2D<i32>,
+        native_widget: &NativeWidget,
+        alpha_mode: SurfaceAlphaMode,
     ) -> Result<Surface, Error> {
+        let (wayland_surface, size) = (native_widget.wayland_surface, &native_widget.size);
         let egl_window =
             (WAYLAND_EGL_HANDLE.wl_egl_window_create)(wayland_surface, size.width, size.height);
         assert!(!egl_window.is_null());
 
+        let widget_state = WidgetState::new(
+            self.native_connection.globals.as_deref(),
+            self.native_connection.egl_display,
+            native_widget,
+        );
+        self.native_connection
+            .widgets
+            .lock()
+            .unwrap()
+            .insert(egl_window as usize, widget_state);
+
         let context_descriptor = self.context_descriptor(context);
         let egl_config = context::egl_config_from_id(
             self.native_connection.egl_display,
@@ -124,9 +327,161 @@ impl Device {
             egl_window as *mut c_void,
             context.0.id,
             size,
+            alpha_mode,
         )))
     }
 
+    pub(crate) fn egl_surface_info(&self, surface: &EGLBackedSurface) -> SurfaceInfo {
+        SurfaceInfo {
+            scale_factor: self.surface_scale_factor(surface),
+            transform: SurfaceTransform::Identity,
+            ..surface.info()
+        }
+    }
+
+    fn surface_scale_factor(&self, surface: &EGLBackedSurface) -> f64 {
+        let wayland_egl_window = match surface.native_window() {
+            Ok(wayland_egl_window) => wayland_egl_window,
+            Err(_) => return 1.0,
+        };
+
+        let widgets = self.native_connection.widgets.lock().unwrap();
+        match widgets.get(&(wayland_egl_window as usize)) {
+            Some(widget_state) => widget_state.scale_factor(),
+            None => 1.0,
+        }
+    }
+
+    /// Creates a generic surface with the given number of layers, each of the given size.
+    ///
+    /// Surface textures of a layered surface are bound to `GL_TEXTURE_2D_ARRAY`. Rendering to the
+    /// surface draws into the layer chosen with `set_surface_layer()`, initially the first.
+    ///
+    /// EGL can only share single images, so each layer is an image of its own, and a surface
+    /// texture of a layered surface holds a copy of the layers, made when it's created. As the
+    /// surface texture owns the surface, the copy always matches the surface's contents. `layers`
+    /// must be at least 1, or this returns `Error::InvalidSurfaceLayer`.
+    pub fn create_layered_surface(
+        &mut self,
+        context: &Context,
+        _: SurfaceAccess,
+        _: SurfaceUsage,
+        size: &Size2D<i32>,
+        layers: u32,
+    ) -> Result<Surface, Error> {
+        if layers == 0 {
+            return Err(Error::InvalidSurfaceLayer);
+        }
+        let _guard = self.temporarily_make_context_current(context)?;
+        let context_descriptor = self.context_descriptor(context);
+        let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+        let surface = GL_FUNCTIONS.with(|gl| {
+            Surface(EGLBackedSurface::new_generic_layered(
+                gl,
+                self.native_connection.egl_display,
+                context.0.egl_context,
+                context.0.id,
+                &context_attributes,
+                size,
+                layers,
+            ))
+        });
+        self.note_surface_created(&surface);
+        Ok(surface)
+    }
+
+    /// Chooses the layer of a layered surface that rendering to the surface draws into.
+    ///
+    /// The supplied context must be the context the surface is associated with, or this returns
+    /// an `IncompatibleSurface` error. Surfaces not created with `create_layered_surface()` have
+    /// only the first layer, and asking for a layer past the last returns
+    /// `Error::InvalidSurfaceLayer`.
+    pub fn set_surface_layer(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        layer: u32,
+    ) -> Result<(), Error> {
+        if surface.0.context_id != context.0.id {
+            return Err(Error::IncompatibleSurface);
+        }
+        let _guard = self.temporarily_make_context_current(context)?;
+        GL_FUNCTIONS.with(|gl| surface.0.set_layer(gl, layer))
+    }
+
+    /// Returns the number of layers of a generic surface.
+    ///
+    /// This is 1 for all surfaces except those created with `create_layered_surface()`.
+    #[inline]
+    pub fn surface_layers(&self, surface: &Surface) -> u32 {
+        surface.0.layers()
+    }
+
+    /// Creates a new generic surface with a copy of the contents of the given one, stored with
+    /// its color channels in the given order.
+    ///
+    /// Generic surfaces on this backend are RGBA in memory. Calling this method on a widget surface
+    /// returns a `WidgetAttached` error.
+    pub(crate) fn convert_surface_format_impl(
+        &mut self,
+        context: &mut Context,
+        surface: &Surface,
+        format: SurfaceFormat,
+    ) -> Result<Surface, Error> {
+        if surface.0.context_id != context.0.id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        let mut new_surface =
+            self.create_generic_surface(context, SurfaceUsage::default(), &surface.0.size)?;
+        new_surface.0.format = format;
+        new_surface.0.origin = surface.0.origin;
+        new_surface.0.alpha_mode = surface.0.alpha_mode;
+        new_surface.0.colorspace = surface.0.colorspace;
+
+        let result = match self.temporarily_make_context_current(context) {
+            Ok(_guard) => GL_FUNCTIONS.with(|gl| surface.0.copy_contents_to(gl, &new_surface.0)),
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(()) => Ok(new_surface),
+            Err(err) => {
+                self.destroy_surface_impl(context, &mut new_surface)?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Wraps an existing OpenGL texture of the given context in a new generic surface, so that
+    /// textures produced outside surfman can be shared or presented like any other surface.
+    ///
+    /// The texture must be a complete `GL_TEXTURE_2D` of the given size. It stays owned by the
+    /// caller, but must not be redefined while the surface is alive.
+    ///
+    /// This requires the `EGL_KHR_gl_texture_2D_image` extension.
+    pub(crate) fn create_surface_from_texture_impl(
+        &mut self,
+        context: &mut Context,
+        size: &Size2D<i32>,
+        texture_object: GLuint,
+    ) -> Result<Surface, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        let context_descriptor = self.context_descriptor(context);
+        let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+        GL_FUNCTIONS.with(|gl| {
+            EGLBackedSurface::new_from_texture(
+                gl,
+                self.native_connection.egl_display,
+                context.0.egl_context,
+                context.0.id,
+                &context_attributes,
+                size,
+                texture_object,
+            )
+            .map(Surface)
+        })
+    }
+
     /// Creates a surface texture from an existing generic surface for use with the given context.
     ///
     /// The surface texture is local to the supplied context and takes ownership of the surface.
@@ -153,6 +508,57 @@ impl Device {
         })
     }
 
+    /// Creates a surface texture that samples from a DMA-BUF image, such as a video frame decoded
+    /// with VA-API and exported through FFmpeg's DRM PRIME mapping.
+    ///
+    /// The image must be in a format that the driver can sample as a `GL_TEXTURE_2D`; formats that
+    /// need `GL_TEXTURE_EXTERNAL_OES`, such as most YUV formats, fail to import. The image holds
+    /// its own references to the buffers, so the caller keeps ownership of the file descriptors.
+    /// Destroying the surface texture returns a surface wrapping the image, which must then be
+    /// destroyed with `destroy_surface()`.
+    ///
+    /// This requires the `EGL_EXT_image_dma_buf_import` extension.
+    ///
+    /// # Safety
+    ///
+    /// The file descriptors in `dma_buf` must be open.
+    pub unsafe fn create_surface_texture_from_dma_buf(
+        &mut self,
+        context: &mut Context,
+        dma_buf: &DmaBuf,
+        size: &Size2D<i32>,
+    ) -> Result<SurfaceTexture, Error> {
+        let egl_display = self.native_connection.egl_display;
+        if !device::display_has_extension(egl_display, b"EGL_EXT_image_dma_buf_import") {
+            return Err(Error::RequiredExtensionUnavailable);
+        }
+
+        let _guard = self.temporarily_make_context_current(context)?;
+        let context_descriptor = self.context_descriptor(context);
+        let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+        GL_FUNCTIONS.with(|gl| {
+            let surface = EGLBackedSurface::new_from_dma_buf(
+                gl,
+                egl_display,
+                dma_buf,
+                context.0.id,
+                &context_attributes,
+                size,
+            )
+            .map_err(|err| match err {
+                Error::SurfaceCreationFailed(err) => Error::SurfaceTextureCreationFailed(err),
+                err => err,
+            })?;
+            match surface.to_surface_texture(gl) {
+                Ok(surface_texture) => Ok(SurfaceTexture(surface_texture)),
+                Err((err, mut surface)) => {
+                    let _ = surface.destroy(gl, egl_display, context.0.id);
+                    Err(err)
+                }
+            }
+        })
+    }
+
     /// Destroys a surface.
     ///
     /// The supplied context must be the context the surface is associated with, or this returns
@@ -160,14 +566,20 @@ impl Device {
     ///
     /// You must explicitly call this method to dispose of a surface. Otherwise, a panic occurs in
     /// the `drop` method.
-    pub fn destroy_surface(
+    pub(crate) fn destroy_surface_impl(
         &self,
-        context: &mut Context,
+        context: &Context,
         surface: &mut Surface,
     ) -> Result<(), Error> {
         GL_FUNCTIONS.with(|gl| {
             let egl_display = self.native_connection.egl_display;
             if let Some(wayland_egl_window) = surface.0.destroy(gl, egl_display, context.0.id)? {
+                self.native_connection
+                    .widgets
+                    .lock()
+                    .unwrap()
+                    .remove(&(wayland_egl_window as usize));
+
                 unsafe {
                     let wayland_egl_window = wayland_egl_window as *mut wl_egl_window;
                     (WAYLAND_EGL_HANDLE.wl_egl_window_destroy)(wayland_egl_window);
@@ -195,6 +607,25 @@ impl Device {
         }
     }
 
+    /// Destroys the native resources of a surface given up with `surface_into_destroy_token()`.
+    ///
+    /// Unlike `destroy_surface()`, this doesn't need the context that the surface belonged to.
+    pub fn destroy_token(&self, token: SurfaceDestroyToken) -> Result<(), Error> {
+        if let Some(wayland_egl_window) = token.0.destroy(self.native_connection.egl_display) {
+            self.native_connection
+                .widgets
+                .lock()
+                .unwrap()
+                .remove(&(wayland_egl_window as usize));
+
+            unsafe {
+                let wayland_egl_window = wayland_egl_window as *mut wl_egl_window;
+                (WAYLAND_EGL_HANDLE.wl_egl_window_destroy)(wayland_egl_window);
+            }
+        }
+        Ok(())
+    }
+
     /// Displays the contents of a widget surface on screen.
     ///
     /// Widget surfaces are internally double-buffered, so changes to them don't show up in their
@@ -202,14 +633,236 @@ impl Device {
     ///
     /// The supplied context must match the context the surface was created with, or an
     /// `IncompatibleSurface` error is returned.
-    pub fn present_surface(&self, context: &Context, surface: &mut Surface) -> Result<(), Error> {
+    ///
+    /// If the compositor supports fractional scaling, any change to the preferred scale of the
+    /// widget is applied to the surface as part of presenting it. Consult the `scale_factor`
+    /// field of `SurfaceInfo` to find the size that the surface should be rendered at.
+    #[inline]
+    pub(crate) fn present_surface_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        self.present_surface_with_damage_impl(context, surface, &[])
+    }
+
+    /// Displays the contents of a widget surface on screen, hinting that only the given
+    /// rectangles have changed since the last present.
+    ///
+    /// The rectangles are in device pixels, with the origin at the lower left corner of the
+    /// surface. An empty slice means that the whole surface changed. With
+    /// `EGL_KHR_swap_buffers_with_damage`, they become the buffer damage of the commit, so the
+    /// compositor only has to recomposite those parts of the screen.
+    pub(crate) fn present_surface_with_damage_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        let wayland_egl_window = surface.0.native_window()?;
+
+        let egl_display = self.native_connection.egl_display;
+
+        self.native_connection.dispatch_pending_events();
+        let mut widgets = self.native_connection.widgets.lock().unwrap();
+        let mut widget_state = widgets.get_mut(&(wayland_egl_window as usize));
+        if let Some(ref mut widget_state) = widget_state {
+            unsafe {
+                widget_state.update_scale(&surface.0.size);
+                if let Some(ref globals) = self.native_connection.globals {
+                    widget_state.request_presentation_feedback(globals);
+                }
+                widget_state.begin_present(egl_display);
+            }
+        }
+
+        let result = surface
+            .0
+            .present(egl_display, context.0.egl_context, damage);
+
+        if let Some(widget_state) = widget_state {
+            unsafe { widget_state.end_present() }
+        }
+        result
+    }
+
+    /// Displays the `source` rectangle of a widget surface in the `destination` rectangle of its
+    /// widget, scaling it to fit.
+    ///
+    /// The rectangles are in device pixels, with the origin at the lower left corner. The back
+    /// buffer is rescaled in place before it is swapped, so the whole surface is committed as
+    /// damage.
+    pub(crate) fn present_surface_with_viewport_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        source: &Rect<i32>,
+        destination: &Rect<i32>,
+    ) -> Result<(), Error> {
+        if surface.0.context_id != context.0.id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        let wayland_egl_window = surface.0.native_window()?;
+
+        let egl_display = self.native_connection.egl_display;
+
+        self.native_connection.dispatch_pending_events();
+        let mut widgets = self.native_connection.widgets.lock().unwrap();
+        let mut widget_state = widgets.get_mut(&(wayland_egl_window as usize));
+        if let Some(ref mut widget_state) = widget_state {
+            unsafe {
+                widget_state.update_scale(&surface.0.size);
+                if let Some(ref globals) = self.native_connection.globals {
+                    widget_state.request_presentation_feedback(globals);
+                }
+                widget_state.begin_present(egl_display);
+            }
+        }
+
+        let result = GL_FUNCTIONS.with(|gl| {
+            surface.0.present_with_viewport(
+                gl,
+                egl_display,
+                context.0.egl_context,
+                source,
+                destination,
+            )
+        });
+
+        if let Some(widget_state) = widget_state {
+            unsafe { widget_state.end_present() }
+        }
+        result
+    }
+
+    /// Declares which parts of a widget surface will be redrawn before it is next presented.
+    ///
+    /// This must be called before any rendering to the frame. With `EGL_KHR_partial_update`,
+    /// the contents of the surface outside the region are undefined afterward; otherwise, this
+    /// does nothing. An empty slice means that the whole surface will be redrawn.
+    pub fn set_surface_damage_region(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        region: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        if surface.0.context_id != context.0.id {
+            return Err(Error::IncompatibleSurface);
+        }
+        surface.0.set_damage_region(
+            self.native_connection.egl_display,
+            context.0.egl_context,
+            region,
+        )
+    }
+
+    /// Returns the number of presents ago that the current back buffer of a widget surface was
+    /// displayed, or 0 if its contents are undefined.
+    ///
+    /// This requires `EGL_EXT_buffer_age` or `EGL_KHR_partial_update`; without them, it always
+    /// returns 0.
+    pub fn surface_buffer_age(&self, context: &Context, surface: &Surface) -> Result<u32, Error> {
+        if surface.0.context_id != context.0.id {
+            return Err(Error::IncompatibleSurface);
+        }
+        surface
+            .0
+            .buffer_age(self.native_connection.egl_display, context.0.egl_context)
+    }
+
+    /// Sets whether presenting a widget surface preserves its contents.
+    ///
+    /// Widget surfaces are created without preservation, so their contents are undefined after
+    /// `present_surface()`. With it, applications that only repaint the dirty regions of each
+    /// frame can draw over the previous one, at the cost of a copy per present on some hardware.
+    ///
+    /// If the config of the context doesn't allow preservation, this returns
+    /// `Error::UnsupportedOnThisPlatform`.
+    pub fn set_surface_preserve_buffer(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        preserve: bool,
+    ) -> Result<(), Error> {
+        if surface.0.context_id != context.0.id {
+            return Err(Error::IncompatibleSurface);
+        }
         surface
             .0
-            .present(self.native_connection.egl_display, context.0.egl_context)
+            .set_preserve_buffer(self.native_connection.egl_display, preserve)
+    }
+
+    /// Sets which way up the contents of a surface are.
+    ///
+    /// Widget surfaces are flipped by the compositor, through the buffer transform of their
+    /// Wayland surface.
+    pub fn set_surface_origin(
+        &self,
+        surface: &mut Surface,
+        origin: SurfaceOrigin,
+    ) -> Result<(), Error> {
+        let wayland_egl_window = match surface.0.native_window() {
+            Ok(wayland_egl_window) => wayland_egl_window,
+            Err(_) => return surface.0.set_origin(origin),
+        };
+
+        let widgets = self.native_connection.widgets.lock().unwrap();
+        let widget_state = match widgets.get(&(wayland_egl_window as usize)) {
+            Some(widget_state) => widget_state,
+            None => return surface.0.set_origin(origin),
+        };
+        unsafe {
+            if !widget_state.set_flipped(origin == SurfaceOrigin::TopLeft) {
+                return Err(Error::UnsupportedOnThisPlatform);
+            }
+        }
+        surface.0.origin = origin;
+        Ok(())
+    }
+
+    /// Sets the color space that the contents of a surface are in.
+    ///
+    /// Widget surfaces are tagged through the `wp_color_manager_v1` protocol, so that the
+    /// compositor converts their contents for the display. If the compositor doesn't support that
+    /// protocol or can't describe the color space, this returns
+    /// `Error::UnsupportedOnThisPlatform`.
+    pub fn set_surface_colorspace(
+        &self,
+        surface: &mut Surface,
+        colorspace: Colorspace,
+    ) -> Result<(), Error> {
+        let wayland_egl_window = match surface.0.native_window() {
+            Ok(wayland_egl_window) => wayland_egl_window,
+            Err(_) => return surface.0.set_colorspace(colorspace),
+        };
+
+        let mut widgets = self.native_connection.widgets.lock().unwrap();
+        let widget_state = match widgets.get_mut(&(wayland_egl_window as usize)) {
+            Some(widget_state) => widget_state,
+            None => return surface.0.set_colorspace(colorspace),
+        };
+        let tagged = match (
+            self.native_connection.wayland_display,
+            &self.native_connection.globals,
+        ) {
+            (Some(wayland_display), &Some(ref globals)) => unsafe {
+                widget_state.set_colorspace(wayland_display, globals, colorspace)
+            },
+            _ => colorspace == Colorspace::SRGB,
+        };
+        if !tagged {
+            return Err(Error::UnsupportedOnThisPlatform);
+        }
+        surface.0.colorspace = colorspace;
+        Ok(())
     }
 
     /// Resizes a widget surface.
-    pub fn resize_surface(
+    ///
+    /// The size is given in device pixels. When fractional scaling is in effect, the surface is
+    /// displayed at this size divided by the `scale_factor` reported in `SurfaceInfo`.
+    pub(crate) fn resize_surface_impl(
         &self,
         _context: &Context,
         surface: &mut Surface,
@@ -226,6 +879,12 @@ impl Device {
             )
         };
         surface.0.size = size;
+
+        self.native_connection.dispatch_pending_events();
+        let mut widgets = self.native_connection.widgets.lock().unwrap();
+        if let Some(widget_state) = widgets.get_mut(&(wayland_egl_window as usize)) {
+            unsafe { widget_state.resize(&size) }
+        }
         Ok(())
     }
 
@@ -243,6 +902,38 @@ impl Device {
         SURFACE_GL_TEXTURE_TARGET
     }
 
+    /// Returns the OpenGL texture target that the given surface texture is bound to.
+    ///
+    /// This is `GL_TEXTURE_2D_ARRAY` for surface textures of layered surfaces, and
+    /// `surface_gl_texture_target()` otherwise.
+    #[inline]
+    pub fn surface_texture_binding_target(&self, surface_texture: &SurfaceTexture) -> GLenum {
+        surface_texture.0.texture_target
+    }
+
+    /// Returns the number of layers of the surface that a surface texture wraps.
+    ///
+    /// This is 1 for all surface textures except those of surfaces created with
+    /// `create_layered_surface()`.
+    #[inline]
+    pub fn surface_texture_layers(&self, surface_texture: &SurfaceTexture) -> u32 {
+        surface_texture.0.surface.layers()
+    }
+
+    /// Fills in the mip chain of a surface texture from the current contents of its surface, so
+    /// that it can be sampled with mipmapping at scales well below its size.
+    ///
+    /// Surface textures on this backend sample the surface's EGL image, which only has a base
+    /// level, so this always returns an `UnsupportedOnThisPlatform` error.
+    #[inline]
+    pub fn generate_surface_mipmaps(
+        &self,
+        _: &mut Context,
+        _: &SurfaceTexture,
+    ) -> Result<(), Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
     /// Returns various information about the surface, including the framebuffer object needed to
     /// render to this surface.
     ///
@@ -250,7 +941,145 @@ impl Device {
     /// on the framebuffer object returned by this function. This framebuffer object may or not be
     /// 0, the default framebuffer, depending on platform.
     pub fn surface_info(&self, surface: &Surface) -> SurfaceInfo {
-        surface.0.info()
+        self.egl_surface_info(&surface.0)
+    }
+
+    /// Returns the GPU memory, in bytes, held by the given surface.
+    ///
+    /// EGL doesn't report the size of the underlying allocation, so this is an estimate that counts
+    /// four bytes per pixel of color storage in each layer.
+    #[inline]
+    pub fn surface_memory_usage(&self, surface: &Surface) -> usize {
+        estimate_memory_usage(&self.surface_info(surface).size) * surface.0.layers() as usize
+    }
+
+    /// Returns `Error::UnsupportedOnThisPlatform`, as EGL surfaces have no modification counter.
+    #[inline]
+    pub fn surface_seed(&self, _: &Surface) -> Result<u32, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Returns the stride, row alignment, and total size of the memory that a generic surface of
+    /// the given size would be allocated with, without creating one.
+    ///
+    /// Surfaces on this backend can't be mapped for CPU access, so this is the tightly packed
+    /// layout that readbacks use.
+    #[inline]
+    pub fn surface_layout_requirements(
+        &self,
+        size: &Size2D<i32>,
+        _: SurfaceFormat,
+        _: SurfaceAccess,
+    ) -> Result<SurfaceLayoutRequirements, Error> {
+        Ok(SurfaceLayoutRequirements::packed(size))
+    }
+
+    /// Returns timing information about the most recently displayed frame of a widget surface.
+    ///
+    /// On Wayland, this requires the compositor to support the `wp_presentation` protocol.
+    /// Presentation events are delivered when the application dispatches events on its display, so
+    /// the statistics lag behind `present_surface()` by at least a frame.
+    pub fn surface_presentation_statistics(
+        &self,
+        surface: &Surface,
+    ) -> Option<PresentationStatistics> {
+        let wayland_egl_window = surface.0.native_window().ok()?;
+
+        self.native_connection.dispatch_pending_events();
+        let widgets = self.native_connection.widgets.lock().unwrap();
+        widgets
+            .get(&(wayland_egl_window as usize))
+            .and_then(|widget_state| widget_state.presentation_statistics())
+    }
+
+    /// Returns whether a widget surface can currently be seen on screen.
+    ///
+    /// Wayland has no direct way to ask this. Instead, this reports whether the compositor
+    /// displayed or discarded the most recently presented frame, which requires the compositor to
+    /// support the `wp_presentation` protocol. Like presentation statistics, this lags behind
+    /// `present_surface()` by at least a frame.
+    pub fn surface_visibility(&self, surface: &Surface) -> SurfaceVisibility {
+        let wayland_egl_window = match surface.0.native_window() {
+            Ok(wayland_egl_window) => wayland_egl_window,
+            Err(_) => return SurfaceVisibility::Unknown,
+        };
+
+        self.native_connection.dispatch_pending_events();
+        let widgets = self.native_connection.widgets.lock().unwrap();
+        match widgets.get(&(wayland_egl_window as usize)) {
+            Some(widget_state) => widget_state.visibility(),
+            None => SurfaceVisibility::Unknown,
+        }
+    }
+
+    /// Returns the output that a widget surface is shown on.
+    ///
+    /// Wayland only says which output a frame was displayed on in presentation feedback, so this
+    /// requires the compositor to support the `wp_presentation` protocol, and lags behind
+    /// `present_surface()` by at least a frame. The color space, bit depth, and HDR capability of
+    /// outputs aren't reported, so these are always reported as 8-bit sRGB without HDR.
+    pub fn widget_output_info(&self, surface: &Surface) -> Result<WidgetOutputInfo, Error> {
+        let wayland_egl_window = surface.0.native_window()?;
+
+        self.native_connection.dispatch_pending_events();
+        let widgets = self.native_connection.widgets.lock().unwrap();
+        let outputs = match widgets.get(&(wayland_egl_window as usize)) {
+            Some(widget_state) => widget_state.outputs(),
+            None => vec![],
+        };
+        let display_id = match self.native_connection.globals {
+            Some(ref globals) => globals
+                .outputs
+                .iter()
+                .find(|output| outputs.contains(&output.proxy()))
+                .map(|output| output.global_name() as u64),
+            None => None,
+        };
+        Ok(WidgetOutputInfo::unknown(display_id))
+    }
+
+    /// Creates a `wl_buffer` that displays the contents of a generic surface, for attaching to a
+    /// Wayland surface that the application manages itself.
+    ///
+    /// The buffer is created on the default event queue of the given display. It shares storage
+    /// with the surface rather than copying it, so the application must flush any rendering to the
+    /// surface before committing the buffer.
+    ///
+    /// This requires the `EGL_MESA_image_dma_buf_export` extension and version 2 or later of the
+    /// `zwp_linux_dmabuf_v1` protocol. Widget surfaces can't be exported; this returns
+    /// `Error::WidgetAttached` for them.
+    ///
+    /// # Safety
+    ///
+    /// The display must be a live connection to the same compositor that this device renders for,
+    /// and the buffer must be destroyed (with `wl_buffer.destroy`) before the surface is.
+    pub unsafe fn export_wayland_buffer(
+        &self,
+        surface: &Surface,
+        wayland_display: *mut wl_display,
+    ) -> Result<*mut wl_proxy, Error> {
+        let dma_buf = surface
+            .0
+            .export_dma_buf(self.native_connection.egl_display)?;
+
+        // Use our own globals if this is our display; otherwise, bind them just for this buffer.
+        let temporary_globals;
+        let globals = match (
+            self.native_connection.wayland_display,
+            &self.native_connection.globals,
+        ) {
+            (Some(our_wayland_display), &Some(ref globals))
+                if our_wayland_display == wayland_display =>
+            {
+                &**globals
+            }
+            _ => {
+                temporary_globals = Globals::new(wayland_display).ok_or(Error::ConnectionFailed)?;
+                &*temporary_globals
+            }
+        };
+
+        buffer::create_dma_buf_buffer(wayland_display, globals, &dma_buf, &surface.0.size)
     }
 
     /// Returns the OpenGL texture object containing the contents of this surface.
@@ -260,9 +1089,38 @@ impl Device {
     pub fn surface_texture_object(&self, surface_texture: &SurfaceTexture) -> GLuint {
         surface_texture.0.texture_object
     }
+
+    /// Exports the contents of a generic surface as DMA-BUF file descriptors, for sharing with
+    /// other processes or APIs (e.g. Vulkan, a video encoder, or a screen-capture pipeline).
+    ///
+    /// The buffers share storage with the surface rather than copying it, so rendering to the
+    /// surface must be flushed before the consumer reads them. They remain valid after the
+    /// surface is destroyed, as long as the file descriptors are open.
+    ///
+    /// This requires the `EGL_MESA_image_dma_buf_export` extension. Widget surfaces can't be
+    /// exported; this returns `Error::WidgetAttached` for them.
+    #[inline]
+    pub fn export_dma_buf(&self, surface: &Surface) -> Result<DmaBuf, Error> {
+        surface.0.export_dma_buf(self.native_connection.egl_display)
+    }
 }
 
 /// Represents the CPU view of the pixel data of this surface.
 pub struct SurfaceDataGuard<'a> {
     phantom: PhantomData<&'a ()>,
 }
+
+#[cfg(feature = "sm-opencl")]
+impl Device {
+    /// Returns the EGL image holding the contents of the given surface, for OpenCL to wrap with
+    /// `cl_khr_egl_image`.
+    ///
+    /// The image is owned by the surface and is destroyed along with it. Widget surfaces have no
+    /// EGL image; this returns `Error::WidgetAttached` for them.
+    pub fn surface_egl_image(&self, surface: &Surface) -> Result<SharedEGLImage, Error> {
+        Ok(SharedEGLImage {
+            egl_display: self.native_connection.egl_display,
+            egl_image: surface.0.egl_image()? as *mut c_void,
+        })
+    }
+}
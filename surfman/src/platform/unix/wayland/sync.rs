@@ -0,0 +1,468 @@
+// surfman/surfman/src/platform/unix/wayland/sync.rs
+//
+//! Explicit synchronization between widget surface rendering and the compositor.
+//!
+//! With `linux-drm-syncobj-v1`, each commit carries an acquire point that the compositor waits on
+//! before reading the buffer and a release point that it signals when it's done with it. The
+//! older `zwp_linux_explicit_synchronization_v1` protocol is used as a fallback, and only carries
+//! acquire fences.
+
+use super::connection::{destroy_proxy, Globals};
+use super::ffi::wp_linux_drm_syncobj_surface_v1_interface;
+use super::ffi::wp_linux_drm_syncobj_timeline_v1_interface;
+use super::ffi::zwp_linux_surface_synchronization_v1_interface;
+use super::ffi::WP_LINUX_DRM_SYNCOBJ_MANAGER_V1_GET_SURFACE;
+use super::ffi::WP_LINUX_DRM_SYNCOBJ_MANAGER_V1_IMPORT_TIMELINE;
+use super::ffi::WP_LINUX_DRM_SYNCOBJ_SURFACE_V1_SET_ACQUIRE_POINT;
+use super::ffi::WP_LINUX_DRM_SYNCOBJ_SURFACE_V1_SET_RELEASE_POINT;
+use super::ffi::ZWP_LINUX_EXPLICIT_SYNCHRONIZATION_V1_GET_SYNCHRONIZATION;
+use super::ffi::ZWP_LINUX_SURFACE_SYNCHRONIZATION_V1_SET_ACQUIRE_FENCE;
+use crate::egl;
+use crate::egl::types::{EGLAttrib, EGLDisplay, EGLint};
+use crate::platform::generic::egl::device::supports_native_fences;
+use crate::platform::generic::egl::device::{create_native_fence, finish_rendering};
+use crate::platform::generic::egl::ffi::{EGL_DEVICE_EXT, EGL_DRM_DEVICE_FILE_EXT};
+use crate::platform::generic::egl::ffi::{EGL_DRM_RENDER_NODE_FILE_EXT, EGL_EXTENSION_FUNCTIONS};
+
+use std::io;
+use std::mem;
+use std::os::raw::{c_ulong, c_void};
+use std::os::unix::io::RawFd;
+use std::ptr;
+use wayland_sys::client::{wl_proxy, WAYLAND_CLIENT_HANDLE};
+use wayland_sys::common::wl_argument;
+
+// How long to wait for the compositor to release the previous frame before giving up. This
+// bounds the stall when the compositor holds on to buffers, e.g. while the window is hidden.
+const RELEASE_TIMEOUT_NS: i64 = 100_000_000;
+
+// DRM syncobj ioctls, from `drm.h`.
+const DRM_IOCTL_SYNCOBJ_CREATE: c_ulong = drm_iowr(0xbf, mem::size_of::<drm_syncobj_create>());
+const DRM_IOCTL_SYNCOBJ_DESTROY: c_ulong = drm_iowr(0xc0, mem::size_of::<drm_syncobj_destroy>());
+const DRM_IOCTL_SYNCOBJ_HANDLE_TO_FD: c_ulong =
+    drm_iowr(0xc1, mem::size_of::<drm_syncobj_handle>());
+const DRM_IOCTL_SYNCOBJ_FD_TO_HANDLE: c_ulong =
+    drm_iowr(0xc2, mem::size_of::<drm_syncobj_handle>());
+const DRM_IOCTL_SYNCOBJ_TIMELINE_WAIT: c_ulong =
+    drm_iowr(0xca, mem::size_of::<drm_syncobj_timeline_wait>());
+const DRM_IOCTL_SYNCOBJ_TRANSFER: c_ulong = drm_iowr(0xcc, mem::size_of::<drm_syncobj_transfer>());
+const DRM_IOCTL_SYNCOBJ_TIMELINE_SIGNAL: c_ulong =
+    drm_iowr(0xcd, mem::size_of::<drm_syncobj_timeline_array>());
+
+const DRM_SYNCOBJ_FD_TO_HANDLE_FLAGS_IMPORT_SYNC_FILE: u32 = 1 << 0;
+const DRM_SYNCOBJ_WAIT_FLAGS_WAIT_FOR_SUBMIT: u32 = 1 << 1;
+
+// The BSDs encode `_IOWR()` the same way as Linux for arguments this small, so the same request
+// numbers work with their DRM ports.
+const fn drm_iowr(nr: c_ulong, size: usize) -> c_ulong {
+    (3 << 30) | ((size as c_ulong) << 16) | ((b'd' as c_ulong) << 8) | nr
+}
+
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct drm_syncobj_create {
+    handle: u32,
+    flags: u32,
+}
+
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct drm_syncobj_destroy {
+    handle: u32,
+    pad: u32,
+}
+
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct drm_syncobj_handle {
+    handle: u32,
+    flags: u32,
+    fd: i32,
+    pad: u32,
+    point: u64,
+}
+
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct drm_syncobj_transfer {
+    src_handle: u32,
+    dst_handle: u32,
+    src_point: u64,
+    dst_point: u64,
+    flags: u32,
+    pad: u32,
+}
+
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct drm_syncobj_timeline_wait {
+    handles: u64,
+    points: u64,
+    timeout_nsec: i64,
+    count_handles: u32,
+    flags: u32,
+    first_signaled: u32,
+    pad: u32,
+}
+
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct drm_syncobj_timeline_array {
+    handles: u64,
+    points: u64,
+    count_handles: u32,
+    flags: u32,
+}
+
+pub(crate) enum ExplicitSync {
+    Syncobj(SyncobjSurface),
+    Fence {
+        surface_synchronization: *mut wl_proxy,
+        native_fences: bool,
+    },
+}
+
+// A `wp_linux_drm_syncobj_surface_v1` and the timeline that carries its points. Acquire points
+// are odd and release points are even, so each frame advances the timeline by two.
+pub(crate) struct SyncobjSurface {
+    drm_fd: RawFd,
+    timeline_handle: u32,
+    timeline: *mut wl_proxy,
+    syncobj_surface: *mut wl_proxy,
+    point: u64,
+    native_fences: bool,
+}
+
+impl ExplicitSync {
+    pub(crate) unsafe fn new(
+        globals: &Globals,
+        egl_display: EGLDisplay,
+        wayland_surface: *mut wl_proxy,
+    ) -> Option<ExplicitSync> {
+        let native_fences = supports_native_fences(egl_display);
+        if !globals.syncobj_manager.is_null() {
+            if let Some(syncobj_surface) =
+                SyncobjSurface::new(globals, egl_display, wayland_surface, native_fences)
+            {
+                return Some(ExplicitSync::Syncobj(syncobj_surface));
+            }
+        }
+
+        // Without native fences there's nothing to send over the older protocol, and implicit
+        // synchronization stays in effect.
+        if globals.explicit_synchronization.is_null() || !native_fences {
+            return None;
+        }
+        let mut args = [
+            wl_argument { n: 0 },
+            wl_argument {
+                o: wayland_surface as *const c_void,
+            },
+        ];
+        let surface_synchronization = (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array_constructor)(
+            globals.explicit_synchronization,
+            ZWP_LINUX_EXPLICIT_SYNCHRONIZATION_V1_GET_SYNCHRONIZATION,
+            args.as_mut_ptr(),
+            &zwp_linux_surface_synchronization_v1_interface.0,
+        );
+        if surface_synchronization.is_null() {
+            return None;
+        }
+        Some(ExplicitSync::Fence {
+            surface_synchronization,
+            native_fences,
+        })
+    }
+
+    // Attaches synchronization points for the commit that `eglSwapBuffers()` is about to make.
+    //
+    // This must be called with the context of the surface current, after all rendering to the
+    // frame has been submitted.
+    pub(crate) unsafe fn begin_present(&mut self, egl_display: EGLDisplay) {
+        match *self {
+            ExplicitSync::Syncobj(ref mut syncobj_surface) => {
+                syncobj_surface.set_points(egl_display)
+            }
+            ExplicitSync::Fence {
+                surface_synchronization,
+                native_fences,
+            } => {
+                let fence_fd = match create_native_fence(egl_display, native_fences) {
+                    Some(fence_fd) => fence_fd,
+                    None => return,
+                };
+                let mut args = [wl_argument { h: fence_fd }];
+                (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array)(
+                    surface_synchronization,
+                    ZWP_LINUX_SURFACE_SYNCHRONIZATION_V1_SET_ACQUIRE_FENCE,
+                    args.as_mut_ptr(),
+                );
+                libc::close(fence_fd);
+            }
+        }
+    }
+
+    // Waits for the compositor to finish with the buffers that EGL may reuse for the next frame.
+    //
+    // EGL recycles a buffer as soon as it sees `wl_buffer.release`, but under
+    // `linux-drm-syncobj-v1` the compositor may send that before the release point is signaled.
+    // Once the compositor has released the previous frame, every buffer other than the one just
+    // committed is safe to render into again.
+    pub(crate) unsafe fn end_present(&mut self) {
+        if let ExplicitSync::Syncobj(ref syncobj_surface) = *self {
+            syncobj_surface.wait_for_previous_release();
+        }
+    }
+}
+
+impl Drop for ExplicitSync {
+    fn drop(&mut self) {
+        unsafe {
+            if let ExplicitSync::Fence {
+                surface_synchronization,
+                ..
+            } = *self
+            {
+                destroy_proxy(surface_synchronization);
+            }
+        }
+    }
+}
+
+impl SyncobjSurface {
+    unsafe fn new(
+        globals: &Globals,
+        egl_display: EGLDisplay,
+        wayland_surface: *mut wl_proxy,
+        native_fences: bool,
+    ) -> Option<SyncobjSurface> {
+        let drm_fd = open_drm_device(egl_display)?;
+
+        let mut create = drm_syncobj_create {
+            handle: 0,
+            flags: 0,
+        };
+        if drm_ioctl(drm_fd, DRM_IOCTL_SYNCOBJ_CREATE, &mut create).is_err() {
+            libc::close(drm_fd);
+            return None;
+        }
+
+        let mut syncobj_surface = SyncobjSurface {
+            drm_fd,
+            timeline_handle: create.handle,
+            timeline: ptr::null_mut(),
+            syncobj_surface: ptr::null_mut(),
+            point: 0,
+            native_fences,
+        };
+
+        let mut export = drm_syncobj_handle {
+            handle: create.handle,
+            flags: 0,
+            fd: -1,
+            pad: 0,
+            point: 0,
+        };
+        if drm_ioctl(drm_fd, DRM_IOCTL_SYNCOBJ_HANDLE_TO_FD, &mut export).is_err() {
+            return None;
+        }
+        let mut args = [wl_argument { n: 0 }, wl_argument { h: export.fd }];
+        syncobj_surface.timeline = (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array_constructor)(
+            globals.syncobj_manager,
+            WP_LINUX_DRM_SYNCOBJ_MANAGER_V1_IMPORT_TIMELINE,
+            args.as_mut_ptr(),
+            &wp_linux_drm_syncobj_timeline_v1_interface.0,
+        );
+        libc::close(export.fd);
+
+        let mut args = [
+            wl_argument { n: 0 },
+            wl_argument {
+                o: wayland_surface as *const c_void,
+            },
+        ];
+        syncobj_surface.syncobj_surface = (WAYLAND_CLIENT_HANDLE
+            .wl_proxy_marshal_array_constructor)(
+            globals.syncobj_manager,
+            WP_LINUX_DRM_SYNCOBJ_MANAGER_V1_GET_SURFACE,
+            args.as_mut_ptr(),
+            &wp_linux_drm_syncobj_surface_v1_interface.0,
+        );
+
+        if syncobj_surface.timeline.is_null() || syncobj_surface.syncobj_surface.is_null() {
+            return None;
+        }
+        Some(syncobj_surface)
+    }
+
+    unsafe fn set_points(&mut self, egl_display: EGLDisplay) {
+        let acquire_point = self.point + 1;
+        let release_point = self.point + 2;
+        self.point = release_point;
+
+        // Every commit with a buffer needs an acquire point, so if we couldn't get a fence for
+        // the rendering, wait for it here and signal the point ourselves.
+        let imported = match create_native_fence(egl_display, self.native_fences) {
+            Some(fence_fd) => {
+                let imported = self.import_fence(fence_fd, acquire_point);
+                libc::close(fence_fd);
+                imported
+            }
+            None => false,
+        };
+        if !imported {
+            finish_rendering(egl_display);
+            let mut signal = drm_syncobj_timeline_array {
+                handles: &self.timeline_handle as *const u32 as u64,
+                points: &acquire_point as *const u64 as u64,
+                count_handles: 1,
+                flags: 0,
+            };
+            let _ = drm_ioctl(self.drm_fd, DRM_IOCTL_SYNCOBJ_TIMELINE_SIGNAL, &mut signal);
+        }
+
+        self.send_point(
+            WP_LINUX_DRM_SYNCOBJ_SURFACE_V1_SET_ACQUIRE_POINT,
+            acquire_point,
+        );
+        self.send_point(
+            WP_LINUX_DRM_SYNCOBJ_SURFACE_V1_SET_RELEASE_POINT,
+            release_point,
+        );
+    }
+
+    // Moves the fence of a sync file onto the given point of the timeline.
+    unsafe fn import_fence(&self, fence_fd: RawFd, point: u64) -> bool {
+        let mut create = drm_syncobj_create {
+            handle: 0,
+            flags: 0,
+        };
+        if drm_ioctl(self.drm_fd, DRM_IOCTL_SYNCOBJ_CREATE, &mut create).is_err() {
+            return false;
+        }
+
+        let mut import = drm_syncobj_handle {
+            handle: create.handle,
+            flags: DRM_SYNCOBJ_FD_TO_HANDLE_FLAGS_IMPORT_SYNC_FILE,
+            fd: fence_fd,
+            pad: 0,
+            point: 0,
+        };
+        let mut transfer = drm_syncobj_transfer {
+            src_handle: create.handle,
+            dst_handle: self.timeline_handle,
+            src_point: 0,
+            dst_point: point,
+            flags: 0,
+            pad: 0,
+        };
+        let ok = drm_ioctl(self.drm_fd, DRM_IOCTL_SYNCOBJ_FD_TO_HANDLE, &mut import).is_ok()
+            && drm_ioctl(self.drm_fd, DRM_IOCTL_SYNCOBJ_TRANSFER, &mut transfer).is_ok();
+
+        let mut destroy = drm_syncobj_destroy {
+            handle: create.handle,
+            pad: 0,
+        };
+        let _ = drm_ioctl(self.drm_fd, DRM_IOCTL_SYNCOBJ_DESTROY, &mut destroy);
+        ok
+    }
+
+    unsafe fn send_point(&self, opcode: u32, point: u64) {
+        let mut args = [
+            wl_argument {
+                o: self.timeline as *const c_void,
+            },
+            wl_argument {
+                u: (point >> 32) as u32,
+            },
+            wl_argument { u: point as u32 },
+        ];
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array)(
+            self.syncobj_surface,
+            opcode,
+            args.as_mut_ptr(),
+        );
+    }
+
+    unsafe fn wait_for_previous_release(&self) {
+        // The release point of the frame before the one just committed.
+        if self.point < 4 {
+            return;
+        }
+        let point = self.point - 2;
+
+        let mut now: libc::timespec = mem::zeroed();
+        libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now);
+        let now = now.tv_sec as i64 * 1_000_000_000 + now.tv_nsec as i64;
+
+        let mut wait = drm_syncobj_timeline_wait {
+            handles: &self.timeline_handle as *const u32 as u64,
+            points: &point as *const u64 as u64,
+            timeout_nsec: now + RELEASE_TIMEOUT_NS,
+            count_handles: 1,
+            flags: DRM_SYNCOBJ_WAIT_FLAGS_WAIT_FOR_SUBMIT,
+            first_signaled: 0,
+            pad: 0,
+        };
+        let _ = drm_ioctl(self.drm_fd, DRM_IOCTL_SYNCOBJ_TIMELINE_WAIT, &mut wait);
+    }
+}
+
+impl Drop for SyncobjSurface {
+    fn drop(&mut self) {
+        unsafe {
+            destroy_proxy(self.syncobj_surface);
+            destroy_proxy(self.timeline);
+            let mut destroy = drm_syncobj_destroy {
+                handle: self.timeline_handle,
+                pad: 0,
+            };
+            let _ = drm_ioctl(self.drm_fd, DRM_IOCTL_SYNCOBJ_DESTROY, &mut destroy);
+            libc::close(self.drm_fd);
+        }
+    }
+}
+
+unsafe fn drm_ioctl<T>(fd: RawFd, request: c_ulong, arg: &mut T) -> Result<(), ()> {
+    loop {
+        if libc::ioctl(fd, request as _, arg as *mut T as *mut c_void) == 0 {
+            return Ok(());
+        }
+        match io::Error::last_os_error().raw_os_error() {
+            Some(libc::EINTR) | Some(libc::EAGAIN) => continue,
+            _ => return Err(()),
+        }
+    }
+}
+
+// Opens the DRM device that the EGL display renders with, preferring its render node.
+pub(crate) unsafe fn open_drm_device(egl_display: EGLDisplay) -> Option<RawFd> {
+    let (query_display_attrib, query_device_string) = match (
+        EGL_EXTENSION_FUNCTIONS.QueryDisplayAttribEXT,
+        EGL_EXTENSION_FUNCTIONS.QueryDeviceStringEXT,
+    ) {
+        (Some(query_display_attrib), Some(query_device_string)) => {
+            (query_display_attrib, query_device_string)
+        }
+        _ => return None,
+    };
+
+    let mut device: EGLAttrib = 0;
+    if query_display_attrib(egl_display, EGL_DEVICE_EXT as EGLint, &mut device) == egl::FALSE {
+        return None;
+    }
+
+    let mut path = query_device_string(device as _, EGL_DRM_RENDER_NODE_FILE_EXT as EGLint);
+    if path.is_null() {
+        path = query_device_string(device as _, EGL_DRM_DEVICE_FILE_EXT as EGLint);
+    }
+    if path.is_null() {
+        return None;
+    }
+
+    let drm_fd = libc::open(path, libc::O_RDWR | libc::O_CLOEXEC);
+    if drm_fd < 0 {
+        return None;
+    }
+    Some(drm_fd)
+}
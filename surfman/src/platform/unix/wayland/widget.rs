@@ -0,0 +1,438 @@
+// surfman/surfman/src/platform/unix/wayland/widget.rs
+//
+//! Protocol state that `surfman` attaches to the Wayland surfaces of widget surfaces.
+
+use super::color::ColorManagementSurface;
+use super::connection::{destroy_proxy, Globals};
+use super::ffi::WP_VIEWPORT_SET_DESTINATION;
+use super::ffi::{wp_fractional_scale_v1_interface, wp_fractional_scale_v1_listener};
+use super::ffi::{wp_presentation_feedback_interface, wp_presentation_feedback_listener};
+use super::ffi::{wp_viewport_interface, FRACTIONAL_SCALE_DENOMINATOR, WP_PRESENTATION_FEEDBACK};
+use super::ffi::{WL_OUTPUT_TRANSFORM_FLIPPED_180, WL_OUTPUT_TRANSFORM_NORMAL};
+use super::ffi::{WL_SURFACE_SET_BUFFER_SCALE, WL_SURFACE_SET_BUFFER_SCALE_SINCE_VERSION};
+use super::ffi::{WL_SURFACE_SET_BUFFER_TRANSFORM, WL_SURFACE_SET_BUFFER_TRANSFORM_SINCE_VERSION};
+use super::ffi::{WP_FRACTIONAL_SCALE_MANAGER_V1_GET_FRACTIONAL_SCALE, WP_VIEWPORTER_GET_VIEWPORT};
+use super::surface::NativeWidget;
+use super::sync::ExplicitSync;
+use crate::egl::types::EGLDisplay;
+use crate::{Colorspace, PresentationStatistics, SurfaceVisibility};
+
+use euclid::default::Size2D;
+use std::cmp;
+use std::mem;
+use std::os::raw::c_void;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use wayland_sys::client::{wl_display, wl_proxy, WAYLAND_CLIENT_HANDLE};
+use wayland_sys::common::wl_argument;
+
+pub(crate) struct WidgetState {
+    wayland_surface: *mut wl_proxy,
+    scale: Option<FractionalScale>,
+    // Boxed so that its address stays put while feedback objects point to it.
+    presentation: Box<Mutex<PresentationFeedback>>,
+    explicit_sync: Option<ExplicitSync>,
+    // Created the first time the surface is tagged with a color space.
+    color_management: Option<ColorManagementSurface>,
+}
+
+// The `wp_viewport` and `wp_fractional_scale_v1` objects attached to a widget surface.
+//
+// When the compositor supports fractional scaling, the buffer is rendered at the true fractional
+// scale with a buffer scale of 1, and the viewport maps it back onto the logical size of the
+// surface.
+struct FractionalScale {
+    viewport: *mut wl_proxy,
+    fractional_scale: *mut wl_proxy,
+    // The scale most recently reported by the compositor, in 120ths.
+    preferred_scale: Box<AtomicU32>,
+    // The scale that the viewport destination was last computed with, in 120ths.
+    applied_scale: u32,
+}
+
+// The `wp_presentation_feedback` objects that are waiting for their frames to be displayed, the
+// statistics of the most recently displayed frame, and whether the most recent frame the
+// compositor made a decision about was displayed.
+//
+// The compositor names the outputs that a frame was synchronized to just before reporting it as
+// presented, so those are collected in `sync_outputs` until then.
+struct PresentationFeedback {
+    frame_count: u64,
+    pending: Vec<(*mut wl_proxy, u64)>,
+    latest: Option<PresentationStatistics>,
+    visibility: SurfaceVisibility,
+    sync_outputs: Vec<*mut wl_proxy>,
+    latest_outputs: Vec<*mut wl_proxy>,
+}
+
+unsafe impl Send for PresentationFeedback {}
+
+impl WidgetState {
+    pub(crate) unsafe fn new(
+        globals: Option<&Globals>,
+        egl_display: EGLDisplay,
+        native_widget: &NativeWidget,
+    ) -> WidgetState {
+        let (wayland_surface, size) = (native_widget.wayland_surface, &native_widget.size);
+        WidgetState {
+            wayland_surface,
+            scale: globals.and_then(|globals| FractionalScale::new(globals, wayland_surface, size)),
+            presentation: Box::new(Mutex::new(PresentationFeedback {
+                frame_count: 0,
+                pending: vec![],
+                latest: None,
+                visibility: SurfaceVisibility::Unknown,
+                sync_outputs: vec![],
+                latest_outputs: vec![],
+            })),
+            explicit_sync: match globals {
+                Some(globals) if native_widget.explicit_sync => {
+                    ExplicitSync::new(globals, egl_display, wayland_surface)
+                }
+                _ => None,
+            },
+            color_management: None,
+        }
+    }
+
+    pub(crate) fn scale_factor(&self) -> f64 {
+        match self.scale {
+            Some(ref scale) => scale.preferred_scale() as f64 / FRACTIONAL_SCALE_DENOMINATOR as f64,
+            None => 1.0,
+        }
+    }
+
+    // Picks up any new preferred scale from the compositor and updates the viewport to match.
+    //
+    // The new viewport destination takes effect on the next commit of the surface, which happens
+    // in `eglSwapBuffers()`.
+    pub(crate) unsafe fn update_scale(&mut self, size: &Size2D<i32>) {
+        if let Some(ref mut scale) = self.scale {
+            if scale.applied_scale != scale.preferred_scale() {
+                scale.set_viewport_destination(size);
+            }
+        }
+    }
+
+    pub(crate) unsafe fn resize(&mut self, size: &Size2D<i32>) {
+        if let Some(ref mut scale) = self.scale {
+            scale.set_viewport_destination(size);
+        }
+    }
+
+    // Asks the compositor to flip the buffers of the surface vertically, or not. Returns false if
+    // the surface is too old to have a buffer transform.
+    //
+    // Like the viewport destination, the transform takes effect on the next commit.
+    pub(crate) unsafe fn set_flipped(&self, flipped: bool) -> bool {
+        let surface_version = (WAYLAND_CLIENT_HANDLE.wl_proxy_get_version)(self.wayland_surface);
+        if surface_version < WL_SURFACE_SET_BUFFER_TRANSFORM_SINCE_VERSION {
+            return false;
+        }
+
+        // A flip about the vertical axis followed by a half turn is a flip about the horizontal
+        // axis.
+        let transform = if flipped {
+            WL_OUTPUT_TRANSFORM_FLIPPED_180
+        } else {
+            WL_OUTPUT_TRANSFORM_NORMAL
+        };
+        let mut args = [wl_argument { i: transform }];
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array)(
+            self.wayland_surface,
+            WL_SURFACE_SET_BUFFER_TRANSFORM,
+            args.as_mut_ptr(),
+        );
+        true
+    }
+
+    // Tags the surface with the color space of its contents. Returns false if the compositor
+    // doesn't support color management or can't describe the color space.
+    //
+    // Like the buffer transform, the tag takes effect on the next commit.
+    pub(crate) unsafe fn set_colorspace(
+        &mut self,
+        wayland_display: *mut wl_display,
+        globals: &Globals,
+        colorspace: Colorspace,
+    ) -> bool {
+        let color_manager = match globals.color_manager {
+            Some(ref color_manager) => color_manager,
+            None => return colorspace == Colorspace::SRGB,
+        };
+        if colorspace != Colorspace::SRGB && !color_manager.supports(colorspace) {
+            return false;
+        }
+
+        if self.color_management.is_none() {
+            // Surfaces that have never been tagged are already sRGB.
+            if colorspace == Colorspace::SRGB {
+                return true;
+            }
+            self.color_management = color_manager.get_surface(self.wayland_surface);
+        }
+        match self.color_management {
+            Some(ref color_management) => color_management.set_colorspace(
+                color_manager,
+                wayland_display,
+                globals.event_queue,
+                colorspace,
+            ),
+            None => false,
+        }
+    }
+
+    // Asks the compositor to tell us when the next commit of the surface is displayed.
+    pub(crate) unsafe fn request_presentation_feedback(&mut self, globals: &Globals) {
+        if globals.presentation.is_null() {
+            return;
+        }
+
+        let mut args = [
+            wl_argument {
+                o: self.wayland_surface as *const c_void,
+            },
+            wl_argument { n: 0 },
+        ];
+        let feedback = (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array_constructor)(
+            globals.presentation,
+            WP_PRESENTATION_FEEDBACK,
+            args.as_mut_ptr(),
+            &wp_presentation_feedback_interface.0,
+        );
+        if feedback.is_null() {
+            return;
+        }
+
+        let mut presentation = self.presentation.lock().unwrap();
+        presentation.frame_count += 1;
+        let frame = presentation.frame_count;
+        presentation.pending.push((feedback, frame));
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_add_listener)(
+            feedback,
+            &PRESENTATION_FEEDBACK_LISTENER as *const wp_presentation_feedback_listener
+                as *mut extern "C" fn(),
+            &*self.presentation as *const Mutex<PresentationFeedback> as *mut c_void,
+        );
+    }
+
+    pub(crate) fn presentation_statistics(&self) -> Option<PresentationStatistics> {
+        self.presentation.lock().unwrap().latest
+    }
+
+    pub(crate) fn visibility(&self) -> SurfaceVisibility {
+        self.presentation.lock().unwrap().visibility
+    }
+
+    // Returns the `wl_output` proxies that the most recently displayed frame was synchronized to.
+    pub(crate) fn outputs(&self) -> Vec<*mut wl_proxy> {
+        self.presentation.lock().unwrap().latest_outputs.clone()
+    }
+
+    // Attaches explicit synchronization to the commit that `eglSwapBuffers()` is about to make,
+    // if it was requested for this widget.
+    pub(crate) unsafe fn begin_present(&mut self, egl_display: EGLDisplay) {
+        if let Some(ref mut explicit_sync) = self.explicit_sync {
+            explicit_sync.begin_present(egl_display);
+        }
+    }
+
+    pub(crate) unsafe fn end_present(&mut self) {
+        if let Some(ref mut explicit_sync) = self.explicit_sync {
+            explicit_sync.end_present();
+        }
+    }
+}
+
+impl Drop for WidgetState {
+    fn drop(&mut self) {
+        unsafe {
+            // Feedback objects have no destructor request; the proxies just have to be freed.
+            let mut presentation = self.presentation.lock().unwrap();
+            for (feedback, _) in presentation.pending.drain(..) {
+                (WAYLAND_CLIENT_HANDLE.wl_proxy_destroy)(feedback);
+            }
+        }
+    }
+}
+
+impl FractionalScale {
+    unsafe fn new(
+        globals: &Globals,
+        wayland_surface: *mut wl_proxy,
+        size: &Size2D<i32>,
+    ) -> Option<FractionalScale> {
+        if globals.viewporter.is_null() || globals.fractional_scale_manager.is_null() {
+            return None;
+        }
+
+        let mut args = [
+            wl_argument { n: 0 },
+            wl_argument {
+                o: wayland_surface as *const c_void,
+            },
+        ];
+        let viewport = (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array_constructor)(
+            globals.viewporter,
+            WP_VIEWPORTER_GET_VIEWPORT,
+            args.as_mut_ptr(),
+            &wp_viewport_interface.0,
+        );
+        let fractional_scale = (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array_constructor)(
+            globals.fractional_scale_manager,
+            WP_FRACTIONAL_SCALE_MANAGER_V1_GET_FRACTIONAL_SCALE,
+            args.as_mut_ptr(),
+            &wp_fractional_scale_v1_interface.0,
+        );
+        if viewport.is_null() || fractional_scale.is_null() {
+            destroy_proxy(viewport);
+            destroy_proxy(fractional_scale);
+            return None;
+        }
+
+        // The viewport takes over the job of the buffer scale, which must be left at 1.
+        let surface_version = (WAYLAND_CLIENT_HANDLE.wl_proxy_get_version)(wayland_surface);
+        if surface_version >= WL_SURFACE_SET_BUFFER_SCALE_SINCE_VERSION {
+            let mut args = [wl_argument { i: 1 }];
+            (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array)(
+                wayland_surface,
+                WL_SURFACE_SET_BUFFER_SCALE,
+                args.as_mut_ptr(),
+            );
+        }
+
+        let mut scale = FractionalScale {
+            viewport,
+            fractional_scale,
+            preferred_scale: Box::new(AtomicU32::new(FRACTIONAL_SCALE_DENOMINATOR)),
+            applied_scale: FRACTIONAL_SCALE_DENOMINATOR,
+        };
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_add_listener)(
+            fractional_scale,
+            &FRACTIONAL_SCALE_LISTENER as *const wp_fractional_scale_v1_listener
+                as *mut extern "C" fn(),
+            &*scale.preferred_scale as *const AtomicU32 as *mut c_void,
+        );
+        scale.set_viewport_destination(size);
+        Some(scale)
+    }
+
+    #[inline]
+    fn preferred_scale(&self) -> u32 {
+        self.preferred_scale.load(Ordering::Relaxed)
+    }
+
+    unsafe fn set_viewport_destination(&mut self, size: &Size2D<i32>) {
+        let scale = self.preferred_scale();
+        let logical_size = size.to_f64() * FRACTIONAL_SCALE_DENOMINATOR as f64 / scale as f64;
+        let mut args = [
+            wl_argument {
+                i: cmp::max(logical_size.width.round() as i32, 1),
+            },
+            wl_argument {
+                i: cmp::max(logical_size.height.round() as i32, 1),
+            },
+        ];
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array)(
+            self.viewport,
+            WP_VIEWPORT_SET_DESTINATION,
+            args.as_mut_ptr(),
+        );
+        self.applied_scale = scale;
+    }
+}
+
+impl Drop for FractionalScale {
+    fn drop(&mut self) {
+        unsafe {
+            destroy_proxy(self.fractional_scale);
+            destroy_proxy(self.viewport);
+        }
+    }
+}
+
+static FRACTIONAL_SCALE_LISTENER: wp_fractional_scale_v1_listener =
+    wp_fractional_scale_v1_listener {
+        preferred_scale: fractional_scale_preferred_scale,
+    };
+
+unsafe extern "C" fn fractional_scale_preferred_scale(
+    data: *mut c_void,
+    _: *mut wl_proxy,
+    scale: u32,
+) {
+    (*(data as *const AtomicU32)).store(scale, Ordering::Relaxed);
+}
+
+static PRESENTATION_FEEDBACK_LISTENER: wp_presentation_feedback_listener =
+    wp_presentation_feedback_listener {
+        sync_output: presentation_feedback_sync_output,
+        presented: presentation_feedback_presented,
+        discarded: presentation_feedback_discarded,
+    };
+
+unsafe extern "C" fn presentation_feedback_sync_output(
+    data: *mut c_void,
+    _: *mut wl_proxy,
+    output: *mut wl_proxy,
+) {
+    let presentation = &*(data as *const Mutex<PresentationFeedback>);
+    presentation.lock().unwrap().sync_outputs.push(output);
+}
+
+unsafe extern "C" fn presentation_feedback_presented(
+    data: *mut c_void,
+    feedback: *mut wl_proxy,
+    tv_sec_hi: u32,
+    tv_sec_lo: u32,
+    tv_nsec: u32,
+    refresh: u32,
+    seq_hi: u32,
+    seq_lo: u32,
+    _: u32,
+) {
+    let presentation = &*(data as *const Mutex<PresentationFeedback>);
+    let mut presentation = presentation.lock().unwrap();
+    if let Some(frame) = presentation.finish(feedback) {
+        presentation.visibility = SurfaceVisibility::Visible;
+        presentation.latest_outputs = mem::replace(&mut presentation.sync_outputs, vec![]);
+        let seconds = ((tv_sec_hi as u64) << 32) | tv_sec_lo as u64;
+        let sequence = ((seq_hi as u64) << 32) | seq_lo as u64;
+        presentation.latest = Some(PresentationStatistics {
+            frame,
+            presentation_time: Duration::new(seconds, tv_nsec),
+            refresh_interval: if refresh != 0 {
+                Some(Duration::from_nanos(refresh as u64))
+            } else {
+                None
+            },
+            // The compositor reports a sequence of zero if the output has no vblank counter.
+            sequence: if sequence != 0 { Some(sequence) } else { None },
+        });
+    }
+}
+
+unsafe extern "C" fn presentation_feedback_discarded(data: *mut c_void, feedback: *mut wl_proxy) {
+    let presentation = &*(data as *const Mutex<PresentationFeedback>);
+    let mut presentation = presentation.lock().unwrap();
+    // Compositors discard the frames of surfaces that aren't on any output, such as those of
+    // minimized windows or windows on other workspaces.
+    if presentation.finish(feedback).is_some() {
+        presentation.visibility = SurfaceVisibility::Occluded;
+        presentation.sync_outputs.clear();
+    }
+}
+
+impl PresentationFeedback {
+    // Frees a feedback object that has received its final event and returns its frame number.
+    unsafe fn finish(&mut self, feedback: *mut wl_proxy) -> Option<u64> {
+        let index = self
+            .pending
+            .iter()
+            .position(|&(pending_feedback, _)| pending_feedback == feedback)?;
+        let (_, frame) = self.pending.remove(index);
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_destroy)(feedback);
+        Some(frame)
+    }
+}
@@ -3,22 +3,29 @@
 //! A wrapper for X11 server connections (`DISPLAY` variables).
 
 use super::device::{Device, NativeDevice};
+use super::present::Presenter;
 use super::surface::NativeWidget;
+use crate::context::ContextIDAllocator;
 use crate::egl;
 use crate::egl::types::{EGLAttrib, EGLDisplay};
 use crate::error::Error;
 use crate::info::GLApi;
-use crate::platform::generic::egl::device::EGL_FUNCTIONS;
+use crate::overrides;
+use crate::platform::generic::egl::device::{query_display_vendor, EGL_FUNCTIONS};
 use crate::platform::generic::egl::ffi::EGL_PLATFORM_X11_KHR;
 use crate::platform::unix::generic::device::Adapter;
+use crate::platform::unix::xrandr;
+use crate::{DisplayInfo, IpcDescriptor, NativeDisplayHandle, NativeWidgetHandle};
 
 use euclid::default::Size2D;
 
 use std::marker::PhantomData;
-use std::os::raw::c_void;
+use std::os::raw::{c_int, c_void};
 use std::ptr;
-use std::sync::Arc;
-use x11::xlib::{Display, XCloseDisplay, XInitThreads, XLockDisplay, XOpenDisplay, XUnlockDisplay};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use x11::xlib::{Display, False, XCloseDisplay, XErrorEvent, XInitThreads, XLockDisplay};
+use x11::xlib::{XOpenDisplay, XSetErrorHandler, XSync, XUnlockDisplay};
 
 #[cfg(feature = "sm-winit")]
 use winit::platform::unix::WindowExtUnix;
@@ -26,17 +33,21 @@ use winit::platform::unix::WindowExtUnix;
 use winit::window::Window;
 
 lazy_static! {
-    static ref X_THREADS_INIT: () = {
+    pub(crate) static ref X_THREADS_INIT: () = {
         unsafe {
             XInitThreads();
         }
     };
+    static ref X_ERROR_TRAP_MUTEX: Mutex<()> = Mutex::new(());
 }
 
+static X_ERROR_OCCURRED: AtomicBool = AtomicBool::new(false);
+
 /// A connection to the X11 display server.
 #[derive(Clone)]
 pub struct Connection {
     pub(crate) native_connection: Arc<NativeConnectionWrapper>,
+    pub(crate) context_ids: ContextIDAllocator,
 }
 
 unsafe impl Send for Connection {}
@@ -45,6 +56,8 @@ pub(crate) struct NativeConnectionWrapper {
     pub(crate) egl_display: EGLDisplay,
     x11_display: *mut Display,
     x11_display_is_owned: bool,
+    // Presents widget surfaces via the Present extension, if the X server and EGL support it.
+    pub(crate) presenter: Option<Mutex<Presenter>>,
 }
 
 /// Wrapper for an X11 and EGL display.
@@ -84,20 +97,30 @@ impl Connection {
                 return Err(Error::ConnectionFailed);
             }
 
-            let egl_display = create_egl_display(x11_display);
+            let egl_display = match create_egl_display(x11_display) {
+                Ok(egl_display) => egl_display,
+                Err(err) => {
+                    XCloseDisplay(x11_display);
+                    return Err(err);
+                }
+            };
 
             Ok(Connection {
                 native_connection: Arc::new(NativeConnectionWrapper {
                     x11_display,
                     x11_display_is_owned: true,
                     egl_display,
+                    presenter: open_presenter(x11_display, egl_display),
                 }),
+                context_ids: ContextIDAllocator::new()?,
             })
         }
     }
 
     /// Wraps an existing X11 `Display` in a `Connection`.
     ///
+    /// # Safety
+    ///
     /// Important: Before calling this function, X11 must have be initialized in a thread-safe
     /// manner by using `XInitThreads()`. Otherwise, it will not be safe to use `surfman` from
     /// multiple threads.
@@ -114,19 +137,63 @@ impl Connection {
                 egl_display: native_connection.egl_display,
                 x11_display: native_connection.x11_display,
                 x11_display_is_owned: false,
+                presenter: open_presenter(
+                    native_connection.x11_display,
+                    native_connection.egl_display,
+                ),
+            }),
+            context_ids: ContextIDAllocator::new()?,
+        })
+    }
+
+    /// Returns `UnsupportedOnThisPlatform`, because Xlib can't connect through a socket that
+    /// another process opened.
+    ///
+    /// # Safety
+    ///
+    /// The descriptor is never used, so there are no requirements on it.
+    #[inline]
+    pub unsafe fn from_ipc_descriptor(_: IpcDescriptor) -> Result<Connection, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    // Wraps the current EGL display in a connection. There is no way to get back to the Xlib
+    // display that it was created on, so we open a display of our own to present with; windows
+    // are shared between clients of the X server, so the application's windows work with it.
+    pub(crate) unsafe fn from_current_egl_display() -> Result<Connection, Error> {
+        *X_THREADS_INIT;
+
+        let egl_display = EGL_FUNCTIONS.with(|egl| egl.GetCurrentDisplay());
+        if egl_display == egl::NO_DISPLAY {
+            return Err(Error::NoCurrentConnection);
+        }
+        let x11_display = XOpenDisplay(ptr::null());
+        if x11_display.is_null() {
+            return Err(Error::ConnectionFailed);
+        }
+
+        Ok(Connection {
+            native_connection: Arc::new(NativeConnectionWrapper {
+                egl_display,
+                x11_display,
+                x11_display_is_owned: true,
+                presenter: open_presenter(x11_display, egl_display),
             }),
+            context_ids: ContextIDAllocator::new()?,
         })
     }
 
     fn from_x11_display(x11_display: *mut Display, is_owned: bool) -> Result<Connection, Error> {
         unsafe {
-            let egl_display = create_egl_display(x11_display);
+            let egl_display = create_egl_display(x11_display)?;
             Ok(Connection {
                 native_connection: Arc::new(NativeConnectionWrapper {
                     egl_display,
                     x11_display,
                     x11_display_is_owned: is_owned,
+                    presenter: open_presenter(x11_display, egl_display),
                 }),
+                context_ids: ContextIDAllocator::new()?,
             })
         }
     }
@@ -140,12 +207,31 @@ impl Connection {
         }
     }
 
+    /// Returns the raw display connection underneath this connection: its Xlib display and the
+    /// EGL display created on it.
+    #[inline]
+    pub fn native_display(&self) -> NativeDisplayHandle {
+        NativeDisplayHandle::Xlib {
+            display: self.native_connection.x11_display as *mut c_void,
+            egl_display: self.native_connection.egl_display as *mut c_void,
+        }
+    }
+
     /// Returns the OpenGL API flavor that this connection supports (OpenGL or OpenGL ES).
     #[inline]
     pub fn gl_api(&self) -> GLApi {
         GLApi::GL
     }
 
+    /// Returns the displays connected to the system.
+    ///
+    /// Displays are enumerated through the RandR extension. If `libXrandr` can't be loaded, this
+    /// returns `UnsupportedOnThisPlatform`.
+    pub fn displays(&self) -> Result<Vec<DisplayInfo>, Error> {
+        let display_guard = self.native_connection.lock_display();
+        unsafe { xrandr::displays(display_guard.display()) }
+    }
+
     /// Returns the "best" adapter on this system, preferring high-performance hardware adapters.
     ///
     /// This is an alias for `Connection::create_hardware_adapter()`.
@@ -157,26 +243,46 @@ impl Connection {
     /// Returns the "best" adapter on this system, preferring high-performance hardware adapters.
     #[inline]
     pub fn create_hardware_adapter(&self) -> Result<Adapter, Error> {
-        Ok(Adapter::hardware())
+        if overrides::software_rendering_forced() {
+            return self.create_software_adapter();
+        }
+        Ok(Adapter::hardware().with_vendor(self.vendor()))
     }
 
     /// Returns the "best" adapter on this system, preferring low-power hardware adapters.
     #[inline]
     pub fn create_low_power_adapter(&self) -> Result<Adapter, Error> {
-        Ok(Adapter::low_power())
+        if overrides::software_rendering_forced() {
+            return self.create_software_adapter();
+        }
+        Ok(Adapter::low_power().with_vendor(self.vendor()))
     }
 
     /// Returns the "best" adapter on this system, preferring software adapters.
     #[inline]
     pub fn create_software_adapter(&self) -> Result<Adapter, Error> {
-        Ok(Adapter::software())
+        Ok(Adapter::software().with_vendor(self.vendor()))
+    }
+
+    /// Returns the adapter for the GPU that drives the given display.
+    ///
+    /// The GPU is found by matching the display's name against the DRM connectors in sysfs, and
+    /// selected by pointing Mesa's `DRI_PRIME` variable at its PCI slot.
+    #[inline]
+    pub fn create_adapter_for_display(&self, display: &DisplayInfo) -> Result<Adapter, Error> {
+        Ok(Adapter::for_display(display)?.with_vendor(self.vendor()))
+    }
+
+    // Returns the vendor library that this connection's display is dispatched to.
+    fn vendor(&self) -> Option<String> {
+        unsafe { query_display_vendor(self.native_connection.egl_display) }
     }
 
     /// Opens the hardware device corresponding to the given adapter.
     ///
-    /// Device handles are local to a single thread.
+    /// Device handles can be cloned and sent to other threads.
     #[inline]
-    pub fn create_device(&self, adapter: &Adapter) -> Result<Device, Error> {
+    pub(crate) fn create_device_impl(&self, adapter: &Adapter) -> Result<Device, Error> {
         Device::new(self, adapter)
     }
 
@@ -185,7 +291,7 @@ impl Connection {
     ///
     /// This is present for compatibility with other backends.
     #[inline]
-    pub unsafe fn create_device_from_native_device(
+    pub(crate) unsafe fn create_device_from_native_device_impl(
         &self,
         native_device: NativeDevice,
     ) -> Result<Device, Error> {
@@ -217,6 +323,11 @@ impl Connection {
     }
 
     /// Create a native widget from a raw pointer
+    ///
+    /// # Safety
+    ///
+    /// The pointer must hold the ID of an X11 window on this connection's display, which must
+    /// outlive the native widget and any surfaces created from it.
     pub unsafe fn create_native_widget_from_ptr(
         &self,
         raw: *mut c_void,
@@ -227,6 +338,24 @@ impl Connection {
         }
     }
 
+    /// Creates a native widget from a handle to a native window or view.
+    ///
+    /// Only X11 window handles are accepted.
+    ///
+    /// # Safety
+    ///
+    /// The handle must refer to a live window or view, which must outlive the native widget and any
+    /// surfaces created from it.
+    pub unsafe fn create_native_widget_from_handle(
+        &self,
+        handle: NativeWidgetHandle,
+    ) -> Result<NativeWidget, Error> {
+        match handle {
+            NativeWidgetHandle::X11Window(window) => Ok(NativeWidget { window }),
+            _ => Err(Error::IncompatibleNativeWidget),
+        }
+    }
+
     /// Create a native widget type from the given `raw_window_handle::HasRawWindowHandle`.
     #[cfg(feature = "sm-raw-window-handle")]
     pub fn create_native_widget_from_rwh(
@@ -247,14 +376,7 @@ impl Connection {
 impl NativeConnectionWrapper {
     #[inline]
     pub(crate) fn lock_display(&self) -> DisplayGuard {
-        unsafe {
-            let display = self.x11_display;
-            XLockDisplay(display);
-            DisplayGuard {
-                display,
-                phantom: PhantomData,
-            }
-        }
+        unsafe { DisplayGuard::new(self.x11_display) }
     }
 }
 
@@ -272,13 +394,54 @@ impl<'a> Drop for DisplayGuard<'a> {
 }
 
 impl<'a> DisplayGuard<'a> {
+    // The display must outlive the guard.
+    pub(crate) unsafe fn new(display: *mut Display) -> DisplayGuard<'a> {
+        XLockDisplay(display);
+        DisplayGuard {
+            display,
+            phantom: PhantomData,
+        }
+    }
+
     #[inline]
     pub(crate) fn display(&self) -> *mut Display {
         self.display
     }
 }
 
-unsafe fn create_egl_display(display: *mut Display) -> EGLDisplay {
+// Runs the given function, catching any X errors that it causes instead of letting the default
+// Xlib error handler exit the process. Returns `None` if an error occurred.
+//
+// Many GLX and MIT-SHM calls report failure only through X errors: for example, `glXCreateContextAttribsARB()`
+// with an unsupported version, or `glXCreatePixmap()` with an incompatible config.
+pub(crate) unsafe fn trap_x_errors<F, T>(display: *mut Display, function: F) -> Option<T>
+where
+    F: FnOnce() -> T,
+{
+    let _lock = X_ERROR_TRAP_MUTEX.lock().unwrap();
+    XSync(display, False);
+    X_ERROR_OCCURRED.store(false, Ordering::SeqCst);
+    let previous_error_handler = XSetErrorHandler(Some(record_x_error));
+
+    let result = function();
+
+    XSync(display, False);
+    XSetErrorHandler(previous_error_handler);
+    if X_ERROR_OCCURRED.load(Ordering::SeqCst) {
+        None
+    } else {
+        Some(result)
+    }
+}
+
+unsafe extern "C" fn record_x_error(_: *mut Display, _: *mut XErrorEvent) -> c_int {
+    X_ERROR_OCCURRED.store(true, Ordering::SeqCst);
+    0
+}
+
+// Fails if the driver has no usable EGL implementation, in which case the GLX backend may still
+// work.
+unsafe fn create_egl_display(display: *mut Display) -> Result<EGLDisplay, Error> {
     EGL_FUNCTIONS.with(|egl| {
         let display_attributes = [egl::NONE as EGLAttrib];
         let egl_display = egl.GetPlatformDisplay(
@@ -288,9 +451,20 @@ unsafe fn create_egl_display(display: *mut Display) -> EGLDisplay {
         );
 
         let (mut egl_major_version, mut egl_minor_version) = (0, 0);
-        let ok = egl.Initialize(egl_display, &mut egl_major_version, &mut egl_minor_version);
-        assert_ne!(ok, egl::FALSE);
+        if egl_display == egl::NO_DISPLAY
+            || egl.Initialize(egl_display, &mut egl_major_version, &mut egl_minor_version)
+                == egl::FALSE
+        {
+            return Err(Error::ConnectionFailed);
+        }
 
-        egl_display
+        Ok(egl_display)
     })
 }
+
+unsafe fn open_presenter(
+    display: *mut Display,
+    egl_display: EGLDisplay,
+) -> Option<Mutex<Presenter>> {
+    Presenter::open(display, egl_display).map(Mutex::new)
+}
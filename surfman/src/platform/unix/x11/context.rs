@@ -2,13 +2,20 @@
 //
 //! OpenGL rendering contexts on X11 via EGL.
 
+use super::connection::Connection;
 use super::device::Device;
 use super::surface::Surface;
 use crate::context::ContextID;
 use crate::egl;
 use crate::egl::types::EGLint;
+use crate::gl::types::GLuint;
+use crate::gl_utils;
+#[cfg(feature = "sm-opencl")]
+use crate::opencl::{GLSharingDisplay, GLSharingProperties};
+use crate::overrides;
 use crate::platform::generic::egl::context::{self, CurrentContextGuard, EGLBackedContext};
-use crate::{ContextAttributes, Error, Gl, SurfaceInfo};
+use crate::{ContextAttributes, ContextDescriptorID, Error, Gl, SurfaceInfo};
+use euclid::default::Size2D;
 
 use std::os::raw::c_void;
 
@@ -41,8 +48,32 @@ impl Device {
     /// Creates a context descriptor with the given attributes.
     ///
     /// Context descriptors are local to this device.
+    ///
+    /// Descriptors are cached by this device, so asking again for the same attributes returns a
+    /// copy of the earlier descriptor without choosing an EGL config again.
     #[inline]
-    pub fn create_context_descriptor(
+    pub(crate) fn create_context_descriptor_impl(
+        &self,
+        attributes: &ContextAttributes,
+    ) -> Result<ContextDescriptor, Error> {
+        let attributes = &overrides::context_attributes(attributes);
+        self.context_descriptors
+            .get_or_insert_with(attributes, || self.choose_context_descriptor(attributes))
+    }
+
+    /// Returns a small ID for the given context descriptor, which is the same for every equal
+    /// descriptor.
+    ///
+    /// The IDs are shared with clones of this device.
+    #[inline]
+    pub fn intern_context_descriptor(
+        &self,
+        context_descriptor: &ContextDescriptor,
+    ) -> ContextDescriptorID {
+        self.context_descriptors.intern(context_descriptor)
+    }
+
+    fn choose_context_descriptor(
         &self,
         attributes: &ContextAttributes,
     ) -> Result<ContextDescriptor, Error> {
@@ -68,7 +99,7 @@ impl Device {
     /// The context initially has no surface attached. Until a surface is bound to it, rendering
     /// commands will fail or have no effect.
     #[inline]
-    pub fn create_context(
+    pub(crate) fn create_context_impl(
         &mut self,
         descriptor: &ContextDescriptor,
         share_with: Option<&Context>,
@@ -79,6 +110,7 @@ impl Device {
                 descriptor,
                 share_with.map(|ctx| &ctx.0),
                 self.gl_api(),
+                &self.context_ids,
             )
             .map(Context)
         }
@@ -90,20 +122,41 @@ impl Device {
     /// it is the caller's responsibility to ensure that the returned `Context` object remains
     /// alive as long as the `EGLContext` is.
     #[inline]
-    pub unsafe fn create_context_from_native_context(
+    pub(crate) unsafe fn create_context_from_native_context_impl(
         &self,
         native_context: NativeContext,
     ) -> Result<Context, Error> {
         Ok(Context(EGLBackedContext::from_native_context(
             native_context,
+            &self.context_ids,
         )))
     }
 
+    /// Wraps the EGL context that is current on this thread, along with its display, in a new
+    /// device and context.
+    ///
+    /// The device presents through an Xlib display connection of its own, since the one that the
+    /// EGL display was created on can't be recovered.
+    ///
+    /// If no context is current, this returns a `NoCurrentContext` error.
+    ///
+    /// # Safety
+    ///
+    /// Neither is retained, so the caller must keep both alive as long as the device and context
+    /// are.
+    pub unsafe fn from_current_context() -> Result<(Device, Context), Error> {
+        let native_context = NativeContext::current()?;
+        let connection = Connection::from_current_egl_display()?;
+        let device = connection.create_device(&connection.create_adapter()?)?;
+        let context = device.create_context_from_native_context(native_context)?;
+        Ok((device, context))
+    }
+
     /// Destroys a context.
     ///
     /// The context must have been created on this device.
-    pub fn destroy_context(&self, context: &mut Context) -> Result<(), Error> {
-        if let Ok(Some(mut surface)) = self.unbind_surface_from_context(context) {
+    pub(crate) fn destroy_context_impl(&self, context: &mut Context) -> Result<(), Error> {
+        if let Ok(Some(mut surface)) = self.unbind_surface_from_context_impl(context) {
             self.destroy_surface(context, &mut surface)?;
         }
 
@@ -135,10 +188,28 @@ impl Device {
     ///
     /// After calling this function, it is valid to use OpenGL rendering commands.
     #[inline]
-    pub fn make_context_current(&self, context: &Context) -> Result<(), Error> {
+    pub(crate) fn make_context_current_impl(&self, context: &Context) -> Result<(), Error> {
         unsafe { context.0.make_current(self.native_connection.egl_display) }
     }
 
+    /// Makes the context the current OpenGL context for this thread, rendering to the given
+    /// surface instead of any surface bound to it.
+    ///
+    /// The surface must have been created with this context, or an `IncompatibleSurface` error is
+    /// returned.
+    #[inline]
+    pub(crate) fn make_context_current_with_surface_impl(
+        &self,
+        context: &Context,
+        surface: &Surface,
+    ) -> Result<(), Error> {
+        unsafe {
+            context
+                .0
+                .make_current_with_surface(self.native_connection.egl_display, &surface.0)
+        }
+    }
+
     /// Removes the current OpenGL context from this thread.
     ///
     /// After calling this function, OpenGL rendering commands will fail until a new context is
@@ -154,10 +225,20 @@ impl Device {
         context: &Context,
     ) -> Result<CurrentContextGuard, Error> {
         let guard = CurrentContextGuard::new();
-        self.make_context_current(context)?;
+        self.make_context_current_impl(context)?;
         Ok(guard)
     }
 
+    // Calls the given function with the OpenGL functions, which are loaded once per thread and
+    // shared by all contexts.
+    #[inline]
+    pub(crate) fn with_gl_functions<F, R>(&self, _: &Context, callback: F) -> R
+    where
+        F: FnOnce(&Gl) -> R,
+    {
+        GL_FUNCTIONS.with(|gl| callback(gl))
+    }
+
     /// Returns the attributes that the context descriptor was created with.
     #[inline]
     pub fn context_descriptor_attributes(
@@ -190,7 +271,7 @@ impl Device {
     ///
     /// If an error is returned, the surface is returned alongside it.
     #[inline]
-    pub fn bind_surface_to_context(
+    pub(crate) fn bind_surface_to_context_impl(
         &self,
         context: &mut Context,
         surface: Surface,
@@ -207,7 +288,7 @@ impl Device {
     ///
     /// Any pending OpenGL commands targeting this surface will be automatically flushed, so the
     /// surface is safe to read from immediately when this function returns.
-    pub fn unbind_surface_from_context(
+    pub(crate) fn unbind_surface_from_context_impl(
         &self,
         context: &mut Context,
     ) -> Result<Option<Surface>, Error> {
@@ -224,6 +305,45 @@ impl Device {
         })
     }
 
+    /// Makes a context render to a framebuffer object that the caller created and owns, in place
+    /// of a surface.
+    ///
+    /// The framebuffer object must stay alive until it's detached again with
+    /// `unbind_surface_from_context()`.
+    pub fn bind_external_framebuffer(
+        &self,
+        context: &mut Context,
+        framebuffer_object: GLuint,
+        size: Size2D<i32>,
+    ) -> Result<(), Error> {
+        context
+            .0
+            .bind_external_framebuffer(framebuffer_object, size)
+    }
+
+    /// Makes the rendering submitted so far to a surface visible to everything else that reads
+    /// it.
+    ///
+    /// This flushes the context and waits on a fence for the rendering to finish, so that readers
+    /// that aren't implicitly synchronized with it, such as other APIs importing the surface's
+    /// memory, see all of it.
+    ///
+    /// The surface must have been created with the given context, or an `IncompatibleSurface`
+    /// error is returned.
+    pub(crate) fn flush_context_impl(
+        &self,
+        context: &Context,
+        surface: &Surface,
+    ) -> Result<(), Error> {
+        if surface.0.context_id != context.0.id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        let _guard = self.temporarily_make_context_current(context)?;
+        GL_FUNCTIONS.with(gl_utils::finish_with_fence);
+        Ok(())
+    }
+
     /// Returns a unique ID representing a context.
     ///
     /// This ID is unique to all currently-allocated contexts. If you destroy a context and create
@@ -241,3 +361,15 @@ impl Device {
         context.0.surface_info()
     }
 }
+
+#[cfg(feature = "sm-opencl")]
+impl Device {
+    /// Returns what OpenCL needs to share objects with the given context through
+    /// `cl_khr_gl_sharing`.
+    pub fn gl_sharing_properties(&self, context: &Context) -> Result<GLSharingProperties, Error> {
+        Ok(GLSharingProperties {
+            gl_context: context.0.egl_context as *mut c_void,
+            display: GLSharingDisplay::EGL(self.native_connection.egl_display),
+        })
+    }
+}
@@ -7,6 +7,8 @@ pub mod context;
 pub mod device;
 pub mod surface;
 
+mod present;
+
 #[path = "../../../implementation/mod.rs"]
 mod implementation;
 
@@ -0,0 +1,690 @@
+// surfman/surfman/src/platform/unix/x11/present.rs
+//
+//! Presentation of widget surfaces through the X11 Present extension.
+//!
+//! Widget surfaces that use Present are rendered offscreen, just like generic surfaces. Presenting
+//! one copies its contents into an idle pixmap from a small swapchain and hands that pixmap to
+//! the X server with `PresentPixmap`, which displays it at the next vblank without tearing.
+//!
+//! Present events are selected on a private display connection, so that they never show up in
+//! the application's event queue.
+
+use super::surface::create_egl_image_from_pixmap;
+use crate::egl::types::EGLDisplay;
+use crate::gl;
+use crate::gl::types::GLuint;
+use crate::gl_utils;
+use crate::platform::generic::egl::device::display_has_extension;
+use crate::platform::generic::egl::ffi::{EGLImageKHR, EGL_EXTENSION_FUNCTIONS, EGL_NO_IMAGE_KHR};
+use crate::platform::generic::egl::surface::{self, EGLBackedSurface};
+use crate::SurfaceVisibility;
+use crate::WindowingApiError;
+use crate::{Error, Gl, PresentationStatistics, SurfaceAlphaMode, SurfaceID, SurfaceOrigin};
+
+use euclid::default::Size2D;
+use std::collections::HashMap;
+use std::mem;
+use std::os::raw::{c_char, c_int, c_uint, c_ulong, c_void};
+use std::ptr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use x11::xlib::{Bool, Display, Pixmap, Window, XCheckTypedEvent, XCloseDisplay};
+use x11::xlib::{GenericEvent, XSync, XWindowAttributes, XID};
+use x11::xlib::{IsViewable, XFreePixmap, XGetEventData};
+use x11::xlib::{XConnectionNumber, XDisplayString, XErrorEvent, XEvent, XFreeEventData};
+use x11::xlib::{XCreatePixmap, XFlush, XGetWindowAttributes, XOpenDisplay, XSetErrorHandler};
+
+// Three pixmaps let us render a frame while one is on screen and another is queued.
+const SWAPCHAIN_LENGTH: usize = 3;
+
+// How long to wait for the X server to give back a pixmap before reusing one anyway. The server
+// holds on to pixmaps indefinitely if the window is unmapped, for instance.
+const IDLE_TIMEOUT: Duration = Duration::from_millis(500);
+
+const PRESENT_COMPLETE_NOTIFY: c_int = 1;
+const PRESENT_IDLE_NOTIFY: c_int = 2;
+const PRESENT_COMPLETE_NOTIFY_MASK: c_uint = 2;
+const PRESENT_IDLE_NOTIFY_MASK: c_uint = 4;
+const PRESENT_COMPLETE_KIND_PIXMAP: u8 = 0;
+const PRESENT_COMPLETE_MODE_SKIP: u8 = 2;
+const PRESENT_OPTION_NONE: u32 = 0;
+
+type QueryExtensionFn = unsafe extern "C" fn(
+    display: *mut Display,
+    major_opcode: *mut c_int,
+    event_base: *mut c_int,
+    error_base: *mut c_int,
+) -> Bool;
+type PixmapFn = unsafe extern "C" fn(
+    display: *mut Display,
+    window: Window,
+    pixmap: Pixmap,
+    serial: u32,
+    valid: XID,
+    update: XID,
+    x_off: c_int,
+    y_off: c_int,
+    target_crtc: XID,
+    wait_fence: XID,
+    idle_fence: XID,
+    options: u32,
+    target_msc: u64,
+    divisor: u64,
+    remainder: u64,
+    notifies: *mut c_void,
+    nnotifies: c_int,
+);
+type SelectInputFn =
+    unsafe extern "C" fn(display: *mut Display, window: Window, event_mask: c_uint) -> XID;
+
+// `libXpresent` is loaded at runtime, so that its absence just means falling back to
+// `eglSwapBuffers()`.
+#[allow(non_snake_case)]
+struct XPresentFunctions {
+    QueryExtension: QueryExtensionFn,
+    Pixmap: PixmapFn,
+    SelectInput: SelectInputFn,
+}
+
+lazy_static! {
+    static ref XPRESENT_FUNCTIONS: Option<XPresentFunctions> = unsafe {
+        let library_names: [&[u8]; 2] = [b"libXpresent.so.1\0", b"libXpresent.so\0"];
+        let library = library_names
+            .iter()
+            .map(|name| libc::dlopen(name.as_ptr() as *const c_char, libc::RTLD_LAZY))
+            .find(|library| !library.is_null())?;
+        let get = |name: &'static [u8]| libc::dlsym(library, name.as_ptr() as *const c_char);
+        let symbols = [
+            get(b"XPresentQueryExtension\0"),
+            get(b"XPresentPixmap\0"),
+            get(b"XPresentSelectInput\0"),
+        ];
+        if symbols.iter().any(|symbol| symbol.is_null()) {
+            return None;
+        }
+        Some(XPresentFunctions {
+            QueryExtension: mem::transmute::<*mut c_void, QueryExtensionFn>(symbols[0]),
+            Pixmap: mem::transmute::<*mut c_void, PixmapFn>(symbols[1]),
+            SelectInput: mem::transmute::<*mut c_void, SelectInputFn>(symbols[2]),
+        })
+    };
+
+    // The private displays that Present requests are made on, and the error handler that was
+    // installed before ours.
+    static ref PRIVATE_DISPLAYS: Mutex<Vec<usize>> = Mutex::new(vec![]);
+    static ref PREVIOUS_ERROR_HANDLER: Mutex<Option<ErrorHandler>> = Mutex::new(None);
+}
+
+type ErrorHandler = unsafe extern "C" fn(*mut Display, *mut XErrorEvent) -> c_int;
+
+#[repr(C)]
+struct XPresentCompleteNotifyEvent {
+    type_: c_int,
+    serial: c_ulong,
+    send_event: Bool,
+    display: *mut Display,
+    extension: c_int,
+    evtype: c_int,
+    eid: u32,
+    window: Window,
+    serial_number: u32,
+    ust: u64,
+    msc: u64,
+    kind: u8,
+    mode: u8,
+}
+
+#[repr(C)]
+struct XPresentIdleNotifyEvent {
+    type_: c_int,
+    serial: c_ulong,
+    send_event: Bool,
+    display: *mut Display,
+    extension: c_int,
+    evtype: c_int,
+    eid: u32,
+    window: Window,
+    serial_number: u32,
+    pixmap: Pixmap,
+    idle_fence: XID,
+}
+
+pub(crate) struct Presenter {
+    display: *mut Display,
+    opcode: c_int,
+    // Keyed by surface ID.
+    swapchains: HashMap<u64, Swapchain>,
+}
+
+unsafe impl Send for Presenter {}
+
+struct Swapchain {
+    window: Window,
+    depth: c_uint,
+    buffers: Vec<Buffer>,
+    next_serial: u32,
+    frame_count: u64,
+    // The serial of each presentation that hasn't completed yet, with its frame number.
+    pending: Vec<(u32, u64)>,
+    // The UST and MSC of the most recently completed presentation.
+    last_complete: Option<(u64, u64)>,
+    statistics: Option<PresentationStatistics>,
+}
+
+struct Buffer {
+    pixmap: Pixmap,
+    size: Size2D<i32>,
+    egl_image: EGLImageKHR,
+    texture_object: GLuint,
+    framebuffer_object: GLuint,
+    idle: bool,
+    // The serial this buffer was last presented with, for picking the least recently used one.
+    serial: u32,
+}
+
+impl Presenter {
+    // Opens a private connection to the same X server, if it supports Present and EGL can wrap
+    // pixmaps in images.
+    pub(crate) unsafe fn open(
+        x11_display: *mut Display,
+        egl_display: EGLDisplay,
+    ) -> Option<Presenter> {
+        let functions = XPRESENT_FUNCTIONS.as_ref()?;
+        if !display_has_extension(egl_display, b"EGL_KHR_image_pixmap") {
+            return None;
+        }
+
+        let display = XOpenDisplay(XDisplayString(x11_display));
+        if display.is_null() {
+            return None;
+        }
+        let (mut opcode, mut event_base, mut error_base) = (0, 0, 0);
+        if (functions.QueryExtension)(display, &mut opcode, &mut event_base, &mut error_base) == 0 {
+            XCloseDisplay(display);
+            return None;
+        }
+
+        install_error_handler(display);
+        Some(Presenter {
+            display,
+            opcode,
+            swapchains: HashMap::new(),
+        })
+    }
+
+    #[inline]
+    pub(crate) fn has_swapchain(&self, surface_id: SurfaceID) -> bool {
+        self.swapchains.contains_key(&surface_id.0)
+    }
+
+    #[inline]
+    pub(crate) fn window(&self, surface_id: SurfaceID) -> Option<Window> {
+        self.swapchains
+            .get(&surface_id.0)
+            .map(|swapchain| swapchain.window)
+    }
+
+    // Starts presenting the given surface to a window. Returns false if the window can't be
+    // presented to, in which case the caller should fall back to an EGL window surface.
+    pub(crate) unsafe fn create_swapchain(
+        &mut self,
+        surface_id: SurfaceID,
+        window: Window,
+    ) -> bool {
+        let functions = XPRESENT_FUNCTIONS.as_ref().unwrap();
+
+        let mut window_attributes: XWindowAttributes = mem::zeroed();
+        if XGetWindowAttributes(self.display, window, &mut window_attributes) == 0 {
+            return false;
+        }
+
+        (functions.SelectInput)(
+            self.display,
+            window,
+            PRESENT_COMPLETE_NOTIFY_MASK | PRESENT_IDLE_NOTIFY_MASK,
+        );
+        XFlush(self.display);
+
+        self.swapchains.insert(
+            surface_id.0,
+            Swapchain {
+                window,
+                depth: window_attributes.depth as c_uint,
+                buffers: vec![],
+                next_serial: 1,
+                frame_count: 0,
+                pending: vec![],
+                last_complete: None,
+                statistics: None,
+            },
+        );
+        true
+    }
+
+    // The context that the surface belongs to must be current.
+    pub(crate) unsafe fn destroy_swapchain(
+        &mut self,
+        gl: &Gl,
+        egl_display: EGLDisplay,
+        surface_id: SurfaceID,
+    ) {
+        // There's no need to deselect Present events: we ignore events for windows we don't know
+        // about, and the selection goes away along with the window.
+        if let Some(mut swapchain) = self.swapchains.remove(&surface_id.0) {
+            for buffer in swapchain.buffers.drain(..) {
+                buffer.destroy(gl, egl_display, self.display);
+            }
+            XFlush(self.display);
+        }
+    }
+
+    // Like `destroy_swapchain()`, for surfaces whose context isn't available. The buffers'
+    // framebuffers and textures are left for the context to free when it's destroyed.
+    pub(crate) unsafe fn abandon_swapchain(
+        &mut self,
+        egl_display: EGLDisplay,
+        surface_id: SurfaceID,
+    ) {
+        if let Some(mut swapchain) = self.swapchains.remove(&surface_id.0) {
+            for buffer in swapchain.buffers.drain(..) {
+                buffer.release(egl_display, self.display);
+            }
+            XFlush(self.display);
+        }
+    }
+
+    // Copies the contents of a surface into an idle pixmap and queues that pixmap for display at
+    // the next vblank.
+    //
+    // The context that the surface belongs to must be current.
+    pub(crate) unsafe fn present(
+        &mut self,
+        gl: &Gl,
+        egl_display: EGLDisplay,
+        surface: &EGLBackedSurface,
+    ) -> Result<(), Error> {
+        let functions = XPRESENT_FUNCTIONS.as_ref().unwrap();
+        let surface_id = surface.id();
+        let size = surface.size;
+
+        self.dispatch_events();
+        let buffer_index = match self.acquire_buffer(gl, egl_display, surface_id, &size) {
+            Some(buffer_index) => buffer_index,
+            None => return Err(Error::PresentFailed(WindowingApiError::Failed)),
+        };
+
+        let display = self.display;
+        let swapchain = self.swapchains.get_mut(&surface_id.0).unwrap();
+        let buffer = &mut swapchain.buffers[buffer_index];
+        let info = surface.info();
+        // Pixmaps take the depth of the window, so windows with an ARGB visual show the alpha
+        // channel of the contents unless the surface is opaque.
+        let fill_alpha = info.alpha_mode == SurfaceAlphaMode::Opaque && swapchain.depth == 32;
+        copy_top_down(gl, info.framebuffer_object, info.origin, fill_alpha, buffer);
+
+        let serial = swapchain.next_serial;
+        swapchain.next_serial = swapchain.next_serial.wrapping_add(1);
+        swapchain.frame_count += 1;
+        swapchain.pending.push((serial, swapchain.frame_count));
+        buffer.idle = false;
+        buffer.serial = serial;
+
+        // A target MSC of zero with a divisor of zero means the next vblank.
+        (functions.Pixmap)(
+            display,
+            swapchain.window,
+            buffer.pixmap,
+            serial,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            PRESENT_OPTION_NONE,
+            0,
+            0,
+            0,
+            ptr::null_mut(),
+            0,
+        );
+        XFlush(display);
+        Ok(())
+    }
+
+    pub(crate) fn presentation_statistics(
+        &mut self,
+        surface_id: SurfaceID,
+    ) -> Option<PresentationStatistics> {
+        unsafe {
+            self.dispatch_events();
+        }
+        self.swapchains
+            .get(&surface_id.0)
+            .and_then(|swapchain| swapchain.statistics)
+    }
+
+    // X11 doesn't track occlusion, but windows that are minimized or on another workspace are
+    // unmapped, and so aren't viewable.
+    pub(crate) fn visibility(&self, surface_id: SurfaceID) -> SurfaceVisibility {
+        let swapchain = match self.swapchains.get(&surface_id.0) {
+            Some(swapchain) => swapchain,
+            None => return SurfaceVisibility::Unknown,
+        };
+        unsafe {
+            let mut window_attributes: XWindowAttributes = mem::zeroed();
+            if XGetWindowAttributes(self.display, swapchain.window, &mut window_attributes) == 0 {
+                return SurfaceVisibility::Unknown;
+            }
+            if window_attributes.map_state == IsViewable {
+                SurfaceVisibility::Visible
+            } else {
+                SurfaceVisibility::Occluded
+            }
+        }
+    }
+
+    // Returns the index of a buffer of the right size that is ready to be rendered into, waiting
+    // for the X server to release one if necessary.
+    unsafe fn acquire_buffer(
+        &mut self,
+        gl: &Gl,
+        egl_display: EGLDisplay,
+        surface_id: SurfaceID,
+        size: &Size2D<i32>,
+    ) -> Option<usize> {
+        let deadline = Instant::now() + IDLE_TIMEOUT;
+        loop {
+            let display = self.display;
+            let swapchain = self.swapchains.get_mut(&surface_id.0)?;
+
+            // Buffers left over from before a resize are thrown away once they're idle.
+            let mut index = 0;
+            while index < swapchain.buffers.len() {
+                let buffer = &swapchain.buffers[index];
+                if buffer.idle && buffer.size != *size {
+                    swapchain
+                        .buffers
+                        .remove(index)
+                        .destroy(gl, egl_display, display);
+                } else {
+                    index += 1;
+                }
+            }
+
+            if let Some(index) = swapchain.buffers.iter().position(|buffer| buffer.idle) {
+                return Some(index);
+            }
+            if swapchain.buffers.len() < SWAPCHAIN_LENGTH {
+                let buffer = Buffer::new(gl, egl_display, display, swapchain, size)?;
+                swapchain.buffers.push(buffer);
+                return Some(swapchain.buffers.len() - 1);
+            }
+
+            let now = Instant::now();
+            if now >= deadline || !self.wait_for_events(deadline - now) {
+                // Give up on the server and take the least recently presented pixmap.
+                let swapchain = self.swapchains.get_mut(&surface_id.0)?;
+                let next_serial = swapchain.next_serial;
+                let (index, buffer) = swapchain
+                    .buffers
+                    .iter_mut()
+                    .enumerate()
+                    .min_by_key(|&(_, ref buffer)| buffer.serial.wrapping_sub(next_serial))?;
+                buffer.idle = true;
+                if buffer.size == *size {
+                    return Some(index);
+                }
+                continue;
+            }
+            self.dispatch_events();
+        }
+    }
+
+    // Blocks until events arrive on our display, or the timeout elapses.
+    unsafe fn wait_for_events(&self, timeout: Duration) -> bool {
+        let mut poll_fd = libc::pollfd {
+            fd: XConnectionNumber(self.display),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        libc::poll(&mut poll_fd, 1, timeout.as_millis() as c_int) > 0
+    }
+
+    // Processes all the Present events that have arrived on our display.
+    unsafe fn dispatch_events(&mut self) {
+        let mut event: XEvent = mem::zeroed();
+        while XCheckTypedEvent(self.display, GenericEvent, &mut event) != 0 {
+            let mut cookie = event.generic_event_cookie;
+            if cookie.extension != self.opcode || XGetEventData(self.display, &mut cookie) == 0 {
+                continue;
+            }
+            match cookie.evtype {
+                PRESENT_COMPLETE_NOTIFY => {
+                    let event = &*(cookie.data as *const XPresentCompleteNotifyEvent);
+                    self.handle_complete_notify(event);
+                }
+                PRESENT_IDLE_NOTIFY => {
+                    let event = &*(cookie.data as *const XPresentIdleNotifyEvent);
+                    self.handle_idle_notify(event);
+                }
+                _ => {}
+            }
+            XFreeEventData(self.display, &mut cookie);
+        }
+    }
+
+    fn handle_complete_notify(&mut self, event: &XPresentCompleteNotifyEvent) {
+        if event.kind != PRESENT_COMPLETE_KIND_PIXMAP {
+            return;
+        }
+        let swapchain = match self
+            .swapchains
+            .values_mut()
+            .find(|swapchain| swapchain.window == event.window)
+        {
+            Some(swapchain) => swapchain,
+            None => return,
+        };
+        let index = match swapchain
+            .pending
+            .iter()
+            .position(|&(serial, _)| serial == event.serial_number)
+        {
+            Some(index) => index,
+            None => return,
+        };
+        let (_, frame) = swapchain.pending.remove(index);
+        if event.mode == PRESENT_COMPLETE_MODE_SKIP {
+            return;
+        }
+
+        // The refresh interval is only known once two consecutive vblanks have been seen.
+        let refresh_interval = match swapchain.last_complete {
+            Some((last_ust, last_msc)) if event.msc > last_msc && event.ust > last_ust => Some(
+                Duration::from_micros((event.ust - last_ust) / (event.msc - last_msc)),
+            ),
+            _ => None,
+        };
+        swapchain.last_complete = Some((event.ust, event.msc));
+        swapchain.statistics = Some(PresentationStatistics {
+            frame,
+            presentation_time: Duration::from_micros(event.ust),
+            refresh_interval,
+            sequence: Some(event.msc),
+        });
+    }
+
+    fn handle_idle_notify(&mut self, event: &XPresentIdleNotifyEvent) {
+        for swapchain in self.swapchains.values_mut() {
+            if swapchain.window != event.window {
+                continue;
+            }
+            for buffer in &mut swapchain.buffers {
+                if buffer.pixmap == event.pixmap {
+                    buffer.idle = true;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Presenter {
+    fn drop(&mut self) {
+        // Closing the display frees all of the pixmaps that were created on it.
+        unsafe {
+            PRIVATE_DISPLAYS
+                .lock()
+                .unwrap()
+                .retain(|&display| display != self.display as usize);
+            XCloseDisplay(self.display);
+        }
+    }
+}
+
+impl Buffer {
+    unsafe fn new(
+        gl: &Gl,
+        egl_display: EGLDisplay,
+        display: *mut Display,
+        swapchain: &Swapchain,
+        size: &Size2D<i32>,
+    ) -> Option<Buffer> {
+        let pixmap = XCreatePixmap(
+            display,
+            swapchain.window,
+            size.width as c_uint,
+            size.height as c_uint,
+            swapchain.depth,
+        );
+        // The pixmap has to exist on the server before EGL, on its own connection, can find it.
+        XSync(display, 0);
+
+        let egl_image = create_egl_image_from_pixmap(egl_display, pixmap);
+        if egl_image == EGL_NO_IMAGE_KHR {
+            XFreePixmap(display, pixmap);
+            return None;
+        }
+
+        let mut bindings = gl_utils::BindingsGuard::new(gl);
+        let texture_object = surface::bind_egl_image_to_gl_texture(gl, egl_image);
+        let framebuffer_object =
+            bindings.create_and_bind_framebuffer(gl::TEXTURE_2D, texture_object);
+        drop(bindings);
+
+        Some(Buffer {
+            pixmap,
+            size: *size,
+            egl_image,
+            texture_object,
+            framebuffer_object,
+            idle: true,
+            serial: 0,
+        })
+    }
+
+    unsafe fn destroy(self, gl: &Gl, egl_display: EGLDisplay, display: *mut Display) {
+        gl_utils::destroy_framebuffer(gl, self.framebuffer_object);
+        gl.DeleteTextures(1, &self.texture_object);
+        self.release(egl_display, display);
+    }
+
+    // Frees the parts of the buffer that don't belong to the context.
+    unsafe fn release(self, egl_display: EGLDisplay, display: *mut Display) {
+        (EGL_EXTENSION_FUNCTIONS.DestroyImageKHR)(egl_display, self.egl_image);
+        XFreePixmap(display, self.pixmap);
+    }
+}
+
+// Copies the source framebuffer into the buffer, turning OpenGL's bottom-up rows into the
+// top-down rows of the pixmap unless the source is already top-down. If `fill_alpha` is set, the
+// alpha channel of the buffer is then made fully opaque.
+unsafe fn copy_top_down(
+    gl: &Gl,
+    source_framebuffer_object: GLuint,
+    source_origin: SurfaceOrigin,
+    fill_alpha: bool,
+    buffer: &Buffer,
+) {
+    let (mut old_read_framebuffer, mut old_draw_framebuffer) = (0, 0);
+    gl.GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut old_read_framebuffer);
+    gl.GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut old_draw_framebuffer);
+    let scissor_test_was_enabled = gl.IsEnabled(gl::SCISSOR_TEST) != gl::FALSE;
+
+    gl.Disable(gl::SCISSOR_TEST);
+    gl.BindFramebuffer(gl::READ_FRAMEBUFFER, source_framebuffer_object);
+    gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, buffer.framebuffer_object);
+    let (width, height) = (buffer.size.width, buffer.size.height);
+    let (dest_y0, dest_y1) = match source_origin {
+        SurfaceOrigin::BottomLeft => (height, 0),
+        SurfaceOrigin::TopLeft => (0, height),
+    };
+    gl.BlitFramebuffer(
+        0,
+        0,
+        width,
+        height,
+        0,
+        dest_y0,
+        width,
+        dest_y1,
+        gl::COLOR_BUFFER_BIT,
+        gl::NEAREST,
+    );
+    if fill_alpha {
+        let mut old_color_mask = [gl::FALSE; 4];
+        let mut old_clear_color = [0.0; 4];
+        gl.GetBooleanv(gl::COLOR_WRITEMASK, old_color_mask.as_mut_ptr());
+        gl.GetFloatv(gl::COLOR_CLEAR_VALUE, old_clear_color.as_mut_ptr());
+        gl.ColorMask(gl::FALSE, gl::FALSE, gl::FALSE, gl::TRUE);
+        gl.ClearColor(0.0, 0.0, 0.0, 1.0);
+        gl.Clear(gl::COLOR_BUFFER_BIT);
+        gl.ColorMask(
+            old_color_mask[0],
+            old_color_mask[1],
+            old_color_mask[2],
+            old_color_mask[3],
+        );
+        gl.ClearColor(
+            old_clear_color[0],
+            old_clear_color[1],
+            old_clear_color[2],
+            old_clear_color[3],
+        );
+    }
+
+    gl.BindFramebuffer(gl::READ_FRAMEBUFFER, old_read_framebuffer as GLuint);
+    gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, old_draw_framebuffer as GLuint);
+    if scissor_test_was_enabled {
+        gl.Enable(gl::SCISSOR_TEST);
+    }
+
+    // The X server picks up the pixmap on its own, so make sure it sees the rendering.
+    gl.Flush();
+}
+
+// Errors on our private displays (for example, presenting to a window that the application has
+// already destroyed) mustn't take down the process, which is what the default Xlib error handler
+// does. So we install a handler that swallows those and passes any others along.
+unsafe fn install_error_handler(display: *mut Display) {
+    let mut private_displays = PRIVATE_DISPLAYS.lock().unwrap();
+    if private_displays.is_empty() {
+        *PREVIOUS_ERROR_HANDLER.lock().unwrap() = XSetErrorHandler(Some(handle_x_error));
+    }
+    private_displays.push(display as usize);
+}
+
+unsafe extern "C" fn handle_x_error(display: *mut Display, event: *mut XErrorEvent) -> c_int {
+    if PRIVATE_DISPLAYS
+        .lock()
+        .unwrap()
+        .contains(&(display as usize))
+    {
+        return 0;
+    }
+    match *PREVIOUS_ERROR_HANDLER.lock().unwrap() {
+        Some(previous_error_handler) => previous_error_handler(display, event),
+        None => 0,
+    }
+}
@@ -5,17 +5,38 @@
 use super::context::{Context, GL_FUNCTIONS};
 use super::device::Device;
 use crate::egl;
-use crate::egl::types::EGLint;
+use crate::egl::types::{EGLDisplay, EGLint};
 use crate::gl;
 use crate::gl::types::{GLenum, GLuint};
+#[cfg(feature = "sm-opencl")]
+use crate::opencl::SharedEGLImage;
 use crate::platform::generic::egl::context;
-use crate::platform::generic::egl::surface::{EGLBackedSurface, EGLSurfaceTexture};
-use crate::{Error, SurfaceAccess, SurfaceInfo, SurfaceType};
+use crate::platform::generic::egl::device::{display_has_extension, EGL_FUNCTIONS};
+use crate::platform::generic::egl::error::ToWindowingApiError;
+use crate::platform::generic::egl::ffi::EGL_NO_IMAGE_KHR;
+use crate::platform::generic::egl::ffi::{EGLClientBuffer, EGLImageKHR, EGL_EXTENSION_FUNCTIONS};
+use crate::platform::generic::egl::ffi::{EGL_IMAGE_PRESERVED_KHR, EGL_NATIVE_PIXMAP_KHR};
+use crate::platform::generic::egl::surface::EGLSurfaceObjects;
+use crate::platform::generic::egl::surface::EGLSurfaceTexture;
+use crate::platform::generic::egl::surface::{EGLBackedSurface, EGLSurfaceDestroyToken};
+use crate::platform::unix::xrandr;
+use crate::surface::{estimate_memory_usage, SurfaceLayoutRequirements};
+use crate::SurfaceInfo;
+use crate::WidgetOutputInfo;
+use crate::{Colorspace, SurfaceOrigin, SurfaceType, SurfaceUsage, SurfaceVisibility};
+use crate::{
+    CreateSurfacesResult, Error, PresentationStatistics, SurfaceAccess, SurfaceAlphaMode,
+    SurfaceFormat,
+};
 
-use euclid::default::Size2D;
+pub use crate::platform::generic::egl::surface::EGLDmaBuf as DmaBuf;
+pub use crate::platform::generic::egl::surface::EGLDmaBufPlane as DmaBufPlane;
+
+use euclid::default::{Rect, Size2D};
 use std::marker::PhantomData;
+use std::mem;
 use std::os::raw::c_void;
-use x11::xlib::{Window, XGetGeometry};
+use x11::xlib::{Drawable, Pixmap, Window, XGetGeometry};
 
 // FIXME(pcwalton): Is this right, or should it be `TEXTURE_EXTERNAL_OES`?
 const SURFACE_GL_TEXTURE_TARGET: GLenum = gl::TEXTURE_2D;
@@ -57,25 +78,95 @@ pub struct NativeWidget {
     pub(crate) window: Window,
 }
 
+/// The native resources of a surface given up with `Device::surface_into_destroy_token()`, which
+/// can be destroyed later without the surface's context.
+///
+/// Tokens can be sent to other threads, and must be destroyed with `Device::destroy_token()`, or
+/// their resources leak.
+pub struct SurfaceDestroyToken(pub(crate) EGLSurfaceDestroyToken);
+
 unsafe impl Send for Surface {}
 
+impl Surface {
+    /// Gives up this surface, returning a token that can be destroyed later with
+    /// `Device::destroy_token()`, on any thread and without the surface's context.
+    ///
+    /// The OpenGL objects that the surface owns in its context, such as its framebuffer, aren't
+    /// part of the token. They're freed when the context is destroyed.
+    #[inline]
+    pub(crate) fn into_destroy_token(self) -> SurfaceDestroyToken {
+        SurfaceDestroyToken(self.0.into_destroy_token())
+    }
+}
+
 impl Device {
     /// Creates either a generic or a widget surface, depending on the supplied surface type.
     ///
     /// Only the given context may ever render to the surface, but generic surfaces can be wrapped
     /// up in a `SurfaceTexture` for reading by other contexts.
-    pub fn create_surface(
+    ///
+    /// Windows with a 32-bit ARGB visual blend premultiplied surfaces with what is behind them
+    /// under a compositing manager, when surfaces are presented with the Present extension.
+    /// Opaque surfaces are shown opaque. Windows with a visual of depth 24 show their surfaces
+    /// opaque whatever the alpha mode.
+    pub(crate) fn create_surface_impl(
         &mut self,
         context: &Context,
         _: SurfaceAccess,
+        _: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
         surface_type: SurfaceType<NativeWidget>,
     ) -> Result<Surface, Error> {
-        match surface_type {
-            SurfaceType::Generic { size } => self.create_generic_surface(context, &size),
+        let mut surface = match surface_type {
+            SurfaceType::Generic { size } => self.create_generic_surface(context, &size)?,
+            // Window systems only composite premultiplied contents.
+            SurfaceType::Widget { .. } if surface_alpha_mode == SurfaceAlphaMode::Straight => {
+                return Err(Error::UnsupportedOnThisPlatform)
+            }
             SurfaceType::Widget { native_widget } => unsafe {
-                self.create_window_surface(context, native_widget.window)
+                self.create_window_surface(context, native_widget.window, surface_alpha_mode)?
             },
-        }
+        };
+        surface.0.alpha_mode = surface_alpha_mode;
+        Ok(surface)
+    }
+
+    /// Creates `count` generic surfaces of the given size.
+    ///
+    /// The context is made current, and its attributes looked up, once for the whole batch. If
+    /// one of the surfaces fails to be created, the error is returned along with the surfaces that
+    /// were created before it.
+    pub(crate) fn create_surfaces_impl(
+        &mut self,
+        context: &Context,
+        _: SurfaceAccess,
+        _: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
+        size: &Size2D<i32>,
+        count: usize,
+    ) -> CreateSurfacesResult<Surface> {
+        let _guard = match self.temporarily_make_context_current(context) {
+            Ok(guard) => guard,
+            Err(err) => return Err((err, vec![])),
+        };
+        let context_descriptor = self.context_descriptor(context);
+        let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+        GL_FUNCTIONS.with(|gl| {
+            Ok((0..count)
+                .map(|_| {
+                    let mut surface = EGLBackedSurface::new_generic(
+                        gl,
+                        self.native_connection.egl_display,
+                        context.0.egl_context,
+                        context.0.id,
+                        &context_attributes,
+                        size,
+                    );
+                    surface.alpha_mode = surface_alpha_mode;
+                    Surface(surface)
+                })
+                .collect())
+        })
     }
 
     fn create_generic_surface(
@@ -99,9 +190,42 @@ impl Device {
     }
 
     unsafe fn create_window_surface(
+        &mut self,
+        context: &Context,
+        x11_window: Window,
+        alpha_mode: SurfaceAlphaMode,
+    ) -> Result<Surface, Error> {
+        let size = self.drawable_size(x11_window);
+
+        // Prefer rendering offscreen and presenting with the Present extension, which syncs to
+        // vblank without tearing. If that isn't available, render to the window directly.
+        if self.native_connection.presenter.is_some() {
+            let mut surface = self.create_generic_surface(context, &size)?;
+            let presenter = self.native_connection.presenter.as_ref().unwrap();
+            if presenter
+                .lock()
+                .unwrap()
+                .create_swapchain(surface.0.id(), x11_window)
+            {
+                return Ok(surface);
+            }
+            let _guard = self.temporarily_make_context_current(context)?;
+            GL_FUNCTIONS.with(|gl| {
+                surface
+                    .0
+                    .destroy(gl, self.native_connection.egl_display, context.0.id)
+            })?;
+        }
+
+        self.create_egl_window_surface(context, x11_window, &size, alpha_mode)
+    }
+
+    unsafe fn create_egl_window_surface(
         &mut self,
         context: &Context,
         mut x11_window: Window,
+        size: &Size2D<i32>,
+        alpha_mode: SurfaceAlphaMode,
     ) -> Result<Surface, Error> {
         let egl_config_id = context::get_context_attr(
             self.native_connection.egl_display,
@@ -111,12 +235,34 @@ impl Device {
         let egl_config =
             context::egl_config_from_id(self.native_connection.egl_display, egl_config_id);
 
+        let mut surface = EGLBackedSurface::new_window(
+            self.native_connection.egl_display,
+            egl_config,
+            &mut x11_window as *mut Window as *mut c_void,
+            context.0.id,
+            size,
+            alpha_mode,
+        );
+
+        // EGL only reads the window ID through the pointer while creating the surface. Keep the
+        // ID itself, so that the window can be found again.
+        if let EGLSurfaceObjects::Window {
+            ref mut native_window,
+            ..
+        } = surface.objects
+        {
+            *native_window = x11_window as *const c_void;
+        }
+        Ok(Surface(surface))
+    }
+
+    unsafe fn drawable_size(&self, drawable: Drawable) -> Size2D<i32> {
         let display_guard = self.native_connection.lock_display();
         let (mut root_window, mut x, mut y, mut width, mut height) = (0, 0, 0, 0, 0);
         let (mut border_width, mut depth) = (0, 0);
         XGetGeometry(
             display_guard.display(),
-            x11_window,
+            drawable,
             &mut root_window,
             &mut x,
             &mut y,
@@ -125,15 +271,148 @@ impl Device {
             &mut border_width,
             &mut depth,
         );
-        let size = Size2D::new(width as i32, height as i32);
+        Size2D::new(width as i32, height as i32)
+    }
 
-        Ok(Surface(EGLBackedSurface::new_window(
-            self.native_connection.egl_display,
-            egl_config,
-            &mut x11_window as *mut Window as *mut c_void,
-            context.0.id,
-            &size,
-        )))
+    // Returns true if the surface is a widget surface that is presented via the Present
+    // extension. Such surfaces are generic surfaces under the hood.
+    fn surface_has_swapchain(&self, surface: &Surface) -> bool {
+        match self.native_connection.presenter {
+            Some(ref presenter) => presenter.lock().unwrap().has_swapchain(surface.0.id()),
+            None => false,
+        }
+    }
+
+    /// Creates a generic surface with the given number of layers, each of the given size.
+    ///
+    /// Surface textures of a layered surface are bound to `GL_TEXTURE_2D_ARRAY`. Rendering to the
+    /// surface draws into the layer chosen with `set_surface_layer()`, initially the first.
+    ///
+    /// EGL can only share single images, so each layer is an image of its own, and a surface
+    /// texture of a layered surface holds a copy of the layers, made when it's created. As the
+    /// surface texture owns the surface, the copy always matches the surface's contents. `layers`
+    /// must be at least 1, or this returns `Error::InvalidSurfaceLayer`.
+    pub fn create_layered_surface(
+        &mut self,
+        context: &Context,
+        _: SurfaceAccess,
+        _: SurfaceUsage,
+        size: &Size2D<i32>,
+        layers: u32,
+    ) -> Result<Surface, Error> {
+        if layers == 0 {
+            return Err(Error::InvalidSurfaceLayer);
+        }
+        let _guard = self.temporarily_make_context_current(context)?;
+        let context_descriptor = self.context_descriptor(context);
+        let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+        let surface = GL_FUNCTIONS.with(|gl| {
+            Surface(EGLBackedSurface::new_generic_layered(
+                gl,
+                self.native_connection.egl_display,
+                context.0.egl_context,
+                context.0.id,
+                &context_attributes,
+                size,
+                layers,
+            ))
+        });
+        self.note_surface_created(&surface);
+        Ok(surface)
+    }
+
+    /// Chooses the layer of a layered surface that rendering to the surface draws into.
+    ///
+    /// The supplied context must be the context the surface is associated with, or this returns
+    /// an `IncompatibleSurface` error. Surfaces not created with `create_layered_surface()` have
+    /// only the first layer, and asking for a layer past the last returns
+    /// `Error::InvalidSurfaceLayer`.
+    pub fn set_surface_layer(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        layer: u32,
+    ) -> Result<(), Error> {
+        if surface.0.context_id != context.0.id {
+            return Err(Error::IncompatibleSurface);
+        }
+        let _guard = self.temporarily_make_context_current(context)?;
+        GL_FUNCTIONS.with(|gl| surface.0.set_layer(gl, layer))
+    }
+
+    /// Returns the number of layers of a generic surface.
+    ///
+    /// This is 1 for all surfaces except those created with `create_layered_surface()`.
+    #[inline]
+    pub fn surface_layers(&self, surface: &Surface) -> u32 {
+        surface.0.layers()
+    }
+
+    /// Creates a new generic surface with a copy of the contents of the given one, stored with
+    /// its color channels in the given order.
+    ///
+    /// Generic surfaces on this backend are RGBA in memory. Calling this method on a widget surface
+    /// returns a `WidgetAttached` error.
+    pub(crate) fn convert_surface_format_impl(
+        &mut self,
+        context: &mut Context,
+        surface: &Surface,
+        format: SurfaceFormat,
+    ) -> Result<Surface, Error> {
+        if surface.0.context_id != context.0.id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if self.surface_has_swapchain(surface) {
+            return Err(Error::WidgetAttached);
+        }
+
+        let mut new_surface = self.create_generic_surface(context, &surface.0.size)?;
+        new_surface.0.format = format;
+        new_surface.0.origin = surface.0.origin;
+        new_surface.0.alpha_mode = surface.0.alpha_mode;
+        new_surface.0.colorspace = surface.0.colorspace;
+
+        let result = match self.temporarily_make_context_current(context) {
+            Ok(_guard) => GL_FUNCTIONS.with(|gl| surface.0.copy_contents_to(gl, &new_surface.0)),
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(()) => Ok(new_surface),
+            Err(err) => {
+                self.destroy_surface_impl(context, &mut new_surface)?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Wraps an existing OpenGL texture of the given context in a new generic surface, so that
+    /// textures produced outside surfman can be shared or presented like any other surface.
+    ///
+    /// The texture must be a complete `GL_TEXTURE_2D` of the given size. It stays owned by the
+    /// caller, but must not be redefined while the surface is alive.
+    ///
+    /// This requires the `EGL_KHR_gl_texture_2D_image` extension.
+    pub(crate) fn create_surface_from_texture_impl(
+        &mut self,
+        context: &mut Context,
+        size: &Size2D<i32>,
+        texture_object: GLuint,
+    ) -> Result<Surface, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        let context_descriptor = self.context_descriptor(context);
+        let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+        GL_FUNCTIONS.with(|gl| {
+            EGLBackedSurface::new_from_texture(
+                gl,
+                self.native_connection.egl_display,
+                context.0.egl_context,
+                context.0.id,
+                &context_attributes,
+                size,
+                texture_object,
+            )
+            .map(Surface)
+        })
     }
 
     /// Creates a surface texture from an existing generic surface for use with the given context.
@@ -151,6 +430,10 @@ impl Device {
         context: &mut Context,
         surface: Surface,
     ) -> Result<SurfaceTexture, (Error, Surface)> {
+        if self.surface_has_swapchain(&surface) {
+            return Err((Error::WidgetAttached, surface));
+        }
+
         let _guard = match self.temporarily_make_context_current(context) {
             Ok(guard) => guard,
             Err(err) => return Err((err, surface)),
@@ -162,6 +445,116 @@ impl Device {
         })
     }
 
+    /// Creates a surface texture that samples from an existing X11 pixmap, which may belong to
+    /// another client.
+    ///
+    /// To sample from another client's window, redirect it with the Composite extension and pass
+    /// the pixmap returned by `XCompositeNameWindowPixmap()`. The texture reflects later changes
+    /// to the pixmap's contents. Pixmaps are stored top-down, so the texture is upside down
+    /// relative to the textures of surfman surfaces.
+    ///
+    /// The pixmap must have a depth and visual compatible with the context's configuration.
+    /// Destroying the surface texture returns a surface wrapping the pixmap, which must then be
+    /// destroyed with `destroy_surface()`. This does not free the pixmap itself.
+    ///
+    /// This requires the `EGL_KHR_image_pixmap` extension.
+    ///
+    /// # Safety
+    ///
+    /// The pixmap must stay alive until the surface texture is destroyed.
+    pub unsafe fn create_surface_texture_from_pixmap(
+        &mut self,
+        context: &mut Context,
+        pixmap: Pixmap,
+    ) -> Result<SurfaceTexture, Error> {
+        let egl_display = self.native_connection.egl_display;
+        if !display_has_extension(egl_display, b"EGL_KHR_image_pixmap") {
+            return Err(Error::RequiredExtensionUnavailable);
+        }
+
+        let size = self.drawable_size(pixmap);
+        let _guard = self.temporarily_make_context_current(context)?;
+
+        let egl_image = create_egl_image_from_pixmap(egl_display, pixmap);
+        if egl_image == EGL_NO_IMAGE_KHR {
+            let windowing_api_error = EGL_FUNCTIONS.with(|egl| egl.GetError());
+            return Err(Error::SurfaceTextureCreationFailed(
+                windowing_api_error.to_windowing_api_error(),
+            ));
+        }
+
+        let context_descriptor = self.context_descriptor(context);
+        let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+        GL_FUNCTIONS.with(|gl| {
+            let surface = EGLBackedSurface::new_from_egl_image(
+                gl,
+                egl_image,
+                SurfaceFormat::BGRA8,
+                context.0.id,
+                &context_attributes,
+                &size,
+            );
+            match surface.to_surface_texture(gl) {
+                Ok(surface_texture) => Ok(SurfaceTexture(surface_texture)),
+                Err((err, mut surface)) => {
+                    let _ = surface.destroy(gl, egl_display, context.0.id);
+                    Err(err)
+                }
+            }
+        })
+    }
+
+    /// Creates a surface texture that samples from a DMA-BUF image, such as a video frame decoded
+    /// with VA-API and exported through FFmpeg's DRM PRIME mapping.
+    ///
+    /// The image must be in a format that the driver can sample as a `GL_TEXTURE_2D`; formats that
+    /// need `GL_TEXTURE_EXTERNAL_OES`, such as most YUV formats, fail to import. The image holds
+    /// its own references to the buffers, so the caller keeps ownership of the file descriptors.
+    /// Destroying the surface texture returns a surface wrapping the image, which must then be
+    /// destroyed with `destroy_surface()`.
+    ///
+    /// This requires the `EGL_EXT_image_dma_buf_import` extension.
+    ///
+    /// # Safety
+    ///
+    /// The file descriptors in `dma_buf` must be open.
+    pub unsafe fn create_surface_texture_from_dma_buf(
+        &mut self,
+        context: &mut Context,
+        dma_buf: &DmaBuf,
+        size: &Size2D<i32>,
+    ) -> Result<SurfaceTexture, Error> {
+        let egl_display = self.native_connection.egl_display;
+        if !display_has_extension(egl_display, b"EGL_EXT_image_dma_buf_import") {
+            return Err(Error::RequiredExtensionUnavailable);
+        }
+
+        let _guard = self.temporarily_make_context_current(context)?;
+        let context_descriptor = self.context_descriptor(context);
+        let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+        GL_FUNCTIONS.with(|gl| {
+            let surface = EGLBackedSurface::new_from_dma_buf(
+                gl,
+                egl_display,
+                dma_buf,
+                context.0.id,
+                &context_attributes,
+                size,
+            )
+            .map_err(|err| match err {
+                Error::SurfaceCreationFailed(err) => Error::SurfaceTextureCreationFailed(err),
+                err => err,
+            })?;
+            match surface.to_surface_texture(gl) {
+                Ok(surface_texture) => Ok(SurfaceTexture(surface_texture)),
+                Err((err, mut surface)) => {
+                    let _ = surface.destroy(gl, egl_display, context.0.id);
+                    Err(err)
+                }
+            }
+        })
+    }
+
     /// Destroys a surface.
     ///
     /// The supplied context must be the context the surface is associated with, or this returns
@@ -169,13 +562,25 @@ impl Device {
     ///
     /// You must explicitly call this method to dispose of a surface. Otherwise, a panic occurs in
     /// the `drop` method.
-    pub fn destroy_surface(
+    pub(crate) fn destroy_surface_impl(
         &self,
-        context: &mut Context,
+        context: &Context,
         surface: &mut Surface,
     ) -> Result<(), Error> {
         GL_FUNCTIONS.with(|gl| {
             let egl_display = self.native_connection.egl_display;
+            if self.surface_has_swapchain(surface) && surface.0.context_id == context.0.id {
+                let _guard = self.temporarily_make_context_current(context)?;
+                if let Some(ref presenter) = self.native_connection.presenter {
+                    unsafe {
+                        presenter.lock().unwrap().destroy_swapchain(
+                            gl,
+                            egl_display,
+                            surface.0.id(),
+                        );
+                    }
+                }
+            }
             surface.0.destroy(gl, egl_display, context.0.id)?;
             Ok(())
         })
@@ -199,6 +604,23 @@ impl Device {
         }
     }
 
+    /// Destroys the native resources of a surface given up with `surface_into_destroy_token()`.
+    ///
+    /// Unlike `destroy_surface()`, this doesn't need the context that the surface belonged to.
+    pub fn destroy_token(&self, token: SurfaceDestroyToken) -> Result<(), Error> {
+        let egl_display = self.native_connection.egl_display;
+        if let Some(ref presenter) = self.native_connection.presenter {
+            unsafe {
+                presenter
+                    .lock()
+                    .unwrap()
+                    .abandon_swapchain(egl_display, token.0.id);
+            }
+        }
+        token.0.destroy(egl_display);
+        Ok(())
+    }
+
     /// Displays the contents of a widget surface on screen.
     ///
     /// Widget surfaces are internally double-buffered, so changes to them don't show up in their
@@ -206,21 +628,213 @@ impl Device {
     ///
     /// The supplied context must match the context the surface was created with, or an
     /// `IncompatibleSurface` error is returned.
-    pub fn present_surface(&self, context: &Context, surface: &mut Surface) -> Result<(), Error> {
+    #[inline]
+    pub(crate) fn present_surface_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        self.present_surface_with_damage_impl(context, surface, &[])
+    }
+
+    /// Displays the contents of a widget surface on screen, hinting that only the given
+    /// rectangles have changed since the last present.
+    ///
+    /// The rectangles are in device pixels, with the origin at the lower left corner of the
+    /// surface. An empty slice means that the whole surface changed. The damage is passed to
+    /// `eglSwapBuffersWithDamageKHR()` where available; surfaces presented through the Present
+    /// extension are always copied in full.
+    pub(crate) fn present_surface_with_damage_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        if !self.surface_has_swapchain(surface) {
+            return surface.0.present(
+                self.native_connection.egl_display,
+                context.0.egl_context,
+                damage,
+            );
+        }
+        if surface.0.context_id != context.0.id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        let _guard = self.temporarily_make_context_current(context)?;
+        let presenter = self.native_connection.presenter.as_ref().unwrap();
+        GL_FUNCTIONS.with(|gl| unsafe {
+            presenter
+                .lock()
+                .unwrap()
+                .present(gl, self.native_connection.egl_display, &surface.0)
+        })
+    }
+
+    /// Displays the `source` rectangle of a widget surface in the `destination` rectangle of its
+    /// widget, scaling it to fit.
+    ///
+    /// The rectangles are in device pixels, with the origin at the lower left corner. The back
+    /// buffer of window surfaces is rescaled in place before it is swapped. Surfaces presented
+    /// through the Present extension return `Error::UnsupportedOnThisPlatform`.
+    pub(crate) fn present_surface_with_viewport_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        source: &Rect<i32>,
+        destination: &Rect<i32>,
+    ) -> Result<(), Error> {
+        if surface.0.context_id != context.0.id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if self.surface_has_swapchain(surface) {
+            return Err(Error::UnsupportedOnThisPlatform);
+        }
+
+        GL_FUNCTIONS.with(|gl| {
+            surface.0.present_with_viewport(
+                gl,
+                self.native_connection.egl_display,
+                context.0.egl_context,
+                source,
+                destination,
+            )
+        })
+    }
+
+    /// Declares which parts of a widget surface will be redrawn before it is next presented.
+    ///
+    /// This must be called before any rendering to the frame. With `EGL_KHR_partial_update`,
+    /// the contents of the surface outside the region are undefined afterward; otherwise, this
+    /// does nothing. An empty slice means that the whole surface will be redrawn.
+    pub fn set_surface_damage_region(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        region: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        if surface.0.context_id != context.0.id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if self.surface_has_swapchain(surface) {
+            // Rendering goes to a generic surface, which always keeps its contents.
+            return Ok(());
+        }
+        surface.0.set_damage_region(
+            self.native_connection.egl_display,
+            context.0.egl_context,
+            region,
+        )
+    }
+
+    /// Returns the number of presents ago that the current back buffer of a widget surface was
+    /// displayed, or 0 if its contents are undefined.
+    ///
+    /// Surfaces presented through the Present extension are rendered offscreen and keep their
+    /// contents across presents, so this always returns 1 for them.
+    pub fn surface_buffer_age(&self, context: &Context, surface: &Surface) -> Result<u32, Error> {
+        if surface.0.context_id != context.0.id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if self.surface_has_swapchain(surface) {
+            return Ok(1);
+        }
         surface
             .0
-            .present(self.native_connection.egl_display, context.0.egl_context)
+            .buffer_age(self.native_connection.egl_display, context.0.egl_context)
+    }
+
+    /// Sets whether presenting a widget surface preserves its contents.
+    ///
+    /// Widget surfaces are created without preservation, so their contents are undefined after
+    /// `present_surface()`. With it, applications that only repaint the dirty regions of each
+    /// frame can draw over the previous one, at the cost of a copy per present on some hardware.
+    ///
+    /// Surfaces shown through the swapchain of the X11 presenter always keep their contents. For
+    /// the others, if the config of the context doesn't allow preservation, this returns
+    /// `Error::UnsupportedOnThisPlatform`.
+    pub fn set_surface_preserve_buffer(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        preserve: bool,
+    ) -> Result<(), Error> {
+        if surface.0.context_id != context.0.id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if self.surface_has_swapchain(surface) {
+            return Ok(());
+        }
+        surface
+            .0
+            .set_preserve_buffer(self.native_connection.egl_display, preserve)
+    }
+
+    /// Sets which way up the contents of a surface are.
+    ///
+    /// Surfaces shown through the swapchain of the X11 presenter are flipped when they are copied
+    /// to their window. Other widget surfaces can only be bottom-left, and setting
+    /// `SurfaceOrigin::TopLeft` on them returns `Error::UnsupportedOnThisPlatform`.
+    pub fn set_surface_origin(
+        &self,
+        surface: &mut Surface,
+        origin: SurfaceOrigin,
+    ) -> Result<(), Error> {
+        surface.0.set_origin(origin)
+    }
+
+    /// Sets the color space that the contents of a surface are in.
+    ///
+    /// X11 has no way to tag the contents of a window with a color space, so widget surfaces can
+    /// only be sRGB, and setting any other color space on them returns
+    /// `Error::UnsupportedOnThisPlatform`.
+    pub fn set_surface_colorspace(
+        &self,
+        surface: &mut Surface,
+        colorspace: Colorspace,
+    ) -> Result<(), Error> {
+        if self.surface_has_swapchain(surface) && colorspace != Colorspace::SRGB {
+            return Err(Error::UnsupportedOnThisPlatform);
+        }
+        surface.0.set_colorspace(colorspace)
     }
 
     /// Resizes a widget surface.
-    pub fn resize_surface(
+    pub(crate) fn resize_surface_impl(
         &self,
-        _context: &Context,
+        context: &Context,
         surface: &mut Surface,
         size: Size2D<i32>,
     ) -> Result<(), Error> {
-        surface.0.size = size;
-        Ok(())
+        if !self.surface_has_swapchain(surface) || surface.0.size == size {
+            surface.0.size = size;
+            return Ok(());
+        }
+        if surface.0.context_id != context.0.id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        // Surfaces presented via the Present extension are rendered offscreen, so their storage
+        // has to be reallocated. The swapchain replaces its pixmaps as they become idle.
+        let _guard = self.temporarily_make_context_current(context)?;
+        let context_descriptor = self.context_descriptor(context);
+        let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+        let egl_display = self.native_connection.egl_display;
+        GL_FUNCTIONS.with(|gl| {
+            let mut new_surface = EGLBackedSurface::new_generic(
+                gl,
+                egl_display,
+                context.0.egl_context,
+                context.0.id,
+                &context_attributes,
+                &size,
+            );
+            // Keep the ID, so that the surface stays keyed to its swapchain.
+            new_surface.id = surface.0.id;
+            mem::swap(&mut surface.0, &mut new_surface);
+            new_surface.destroy(gl, egl_display, context.0.id)?;
+            Ok(())
+        })
     }
 
     /// Returns a pointer to the underlying surface data for reading or writing by the CPU.
@@ -237,6 +851,38 @@ impl Device {
         SURFACE_GL_TEXTURE_TARGET
     }
 
+    /// Returns the OpenGL texture target that the given surface texture is bound to.
+    ///
+    /// This is `GL_TEXTURE_2D_ARRAY` for surface textures of layered surfaces, and
+    /// `surface_gl_texture_target()` otherwise.
+    #[inline]
+    pub fn surface_texture_binding_target(&self, surface_texture: &SurfaceTexture) -> GLenum {
+        surface_texture.0.texture_target
+    }
+
+    /// Returns the number of layers of the surface that a surface texture wraps.
+    ///
+    /// This is 1 for all surface textures except those of surfaces created with
+    /// `create_layered_surface()`.
+    #[inline]
+    pub fn surface_texture_layers(&self, surface_texture: &SurfaceTexture) -> u32 {
+        surface_texture.0.surface.layers()
+    }
+
+    /// Fills in the mip chain of a surface texture from the current contents of its surface, so
+    /// that it can be sampled with mipmapping at scales well below its size.
+    ///
+    /// Surface textures on this backend sample the surface's EGL image, which only has a base
+    /// level, so this always returns an `UnsupportedOnThisPlatform` error.
+    #[inline]
+    pub fn generate_surface_mipmaps(
+        &self,
+        _: &mut Context,
+        _: &SurfaceTexture,
+    ) -> Result<(), Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
     /// Returns various information about the surface, including the framebuffer object needed to
     /// render to this surface.
     ///
@@ -247,6 +893,82 @@ impl Device {
         surface.0.info()
     }
 
+    /// Returns the GPU memory, in bytes, held by the given surface.
+    ///
+    /// EGL doesn't report the size of the underlying allocation, so this is an estimate that counts
+    /// four bytes per pixel of color storage in each layer.
+    #[inline]
+    pub fn surface_memory_usage(&self, surface: &Surface) -> usize {
+        estimate_memory_usage(&self.surface_info(surface).size) * surface.0.layers() as usize
+    }
+
+    /// Returns `Error::UnsupportedOnThisPlatform`, as EGL surfaces have no modification counter.
+    #[inline]
+    pub fn surface_seed(&self, _: &Surface) -> Result<u32, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Returns the stride, row alignment, and total size of the memory that a generic surface of
+    /// the given size would be allocated with, without creating one.
+    ///
+    /// Surfaces on this backend can't be mapped for CPU access, so this is the tightly packed
+    /// layout that readbacks use.
+    #[inline]
+    pub fn surface_layout_requirements(
+        &self,
+        size: &Size2D<i32>,
+        _: SurfaceFormat,
+        _: SurfaceAccess,
+    ) -> Result<SurfaceLayoutRequirements, Error> {
+        Ok(SurfaceLayoutRequirements::packed(size))
+    }
+
+    /// Returns timing information about the most recently displayed frame of a widget surface.
+    ///
+    /// This is only available for widget surfaces presented via the X11 Present extension, and
+    /// returns `None` until the X server has reported a completed presentation.
+    pub fn surface_presentation_statistics(
+        &self,
+        surface: &Surface,
+    ) -> Option<PresentationStatistics> {
+        let presenter = self.native_connection.presenter.as_ref()?;
+        presenter
+            .lock()
+            .unwrap()
+            .presentation_statistics(surface.0.id())
+    }
+
+    /// Returns whether a widget surface can currently be seen on screen.
+    ///
+    /// X11 doesn't report whether a window is covered by other windows, so this only reports
+    /// `Occluded` for windows that aren't mapped, such as minimized ones. It is only available for
+    /// widget surfaces presented via the X11 Present extension, and returns `Unknown` otherwise.
+    pub fn surface_visibility(&self, surface: &Surface) -> SurfaceVisibility {
+        match self.native_connection.presenter {
+            Some(ref presenter) => presenter.lock().unwrap().visibility(surface.0.id()),
+            None => SurfaceVisibility::Unknown,
+        }
+    }
+
+    /// Returns the RandR output that a widget surface is shown on, and the bit depth of its
+    /// window.
+    ///
+    /// X11 doesn't report the color space or HDR capability of outputs, so these are always
+    /// reported as sRGB without HDR.
+    pub fn widget_output_info(&self, surface: &Surface) -> Result<WidgetOutputInfo, Error> {
+        let swapchain_window = match self.native_connection.presenter {
+            Some(ref presenter) => presenter.lock().unwrap().window(surface.0.id()),
+            None => None,
+        };
+        let window = match swapchain_window {
+            Some(window) => window,
+            None => surface.0.native_window()? as Window,
+        };
+
+        let display_guard = self.native_connection.lock_display();
+        unsafe { Ok(xrandr::window_output_info(display_guard.display(), window)) }
+    }
+
     /// Returns the OpenGL texture object containing the contents of this surface.
     ///
     /// It is only legal to read from, not write to, this texture object.
@@ -254,9 +976,59 @@ impl Device {
     pub fn surface_texture_object(&self, surface_texture: &SurfaceTexture) -> GLuint {
         surface_texture.0.texture_object
     }
+
+    /// Exports the contents of a generic surface as DMA-BUF file descriptors, for sharing with
+    /// other processes or APIs (e.g. Vulkan, a video encoder, or a screen-capture pipeline).
+    ///
+    /// The buffers share storage with the surface rather than copying it, so rendering to the
+    /// surface must be flushed before the consumer reads them. They remain valid after the
+    /// surface is destroyed, as long as the file descriptors are open.
+    ///
+    /// This requires the `EGL_MESA_image_dma_buf_export` extension. Widget surfaces can't be
+    /// exported; this returns `Error::WidgetAttached` for them.
+    #[inline]
+    pub fn export_dma_buf(&self, surface: &Surface) -> Result<DmaBuf, Error> {
+        surface.0.export_dma_buf(self.native_connection.egl_display)
+    }
+}
+
+// Wraps an X11 pixmap in an EGL image. Returns `EGL_NO_IMAGE_KHR` on failure.
+//
+// This requires the `EGL_KHR_image_pixmap` extension.
+pub(crate) unsafe fn create_egl_image_from_pixmap(
+    egl_display: EGLDisplay,
+    pixmap: Pixmap,
+) -> EGLImageKHR {
+    let egl_image_attributes = [
+        EGL_IMAGE_PRESERVED_KHR as EGLint,
+        egl::TRUE as EGLint,
+        egl::NONE as EGLint,
+    ];
+    (EGL_EXTENSION_FUNCTIONS.CreateImageKHR)(
+        egl_display,
+        egl::NO_CONTEXT,
+        EGL_NATIVE_PIXMAP_KHR,
+        pixmap as usize as EGLClientBuffer,
+        egl_image_attributes.as_ptr(),
+    )
 }
 
 /// Represents the CPU view of the pixel data of this surface.
 pub struct SurfaceDataGuard<'a> {
     phantom: PhantomData<&'a ()>,
 }
+
+#[cfg(feature = "sm-opencl")]
+impl Device {
+    /// Returns the EGL image holding the contents of the given surface, for OpenCL to wrap with
+    /// `cl_khr_egl_image`.
+    ///
+    /// The image is owned by the surface and is destroyed along with it. Widget surfaces have no
+    /// EGL image; this returns `Error::WidgetAttached` for them.
+    pub fn surface_egl_image(&self, surface: &Surface) -> Result<SharedEGLImage, Error> {
+        Ok(SharedEGLImage {
+            egl_display: self.native_connection.egl_display,
+            egl_image: surface.0.egl_image()? as *mut c_void,
+        })
+    }
+}
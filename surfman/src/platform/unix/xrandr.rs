@@ -0,0 +1,185 @@
+// surfman/surfman/src/platform/unix/xrandr.rs
+//
+//! Display enumeration on X11 through the RandR extension, shared by the EGL and GLX backends.
+
+use crate::{DisplayInfo, Error, WidgetOutputInfo};
+
+use euclid::default::{Point2D, Rect, Size2D};
+use std::ffi::CStr;
+use std::mem;
+use std::os::raw::{c_char, c_ulong, c_void};
+use std::slice;
+use x11::xlib::XWindowAttributes;
+use x11::xlib::{Display, Window, XDefaultRootWindow, XGetWindowAttributes, XTranslateCoordinates};
+use x11::xrandr::{RRCrtc, RROutput, XRRCrtcInfo, XRRModeInfo, XRROutputInfo, XRRScreenResources};
+use x11::xrandr::{RR_Connected, RR_DoubleScan, RR_Interlace};
+
+type GetScreenResourcesCurrentFn =
+    unsafe extern "C" fn(display: *mut Display, window: Window) -> *mut XRRScreenResources;
+type GetOutputInfoFn = unsafe extern "C" fn(
+    display: *mut Display,
+    resources: *mut XRRScreenResources,
+    output: RROutput,
+) -> *mut XRROutputInfo;
+type GetCrtcInfoFn = unsafe extern "C" fn(
+    display: *mut Display,
+    resources: *mut XRRScreenResources,
+    crtc: RRCrtc,
+) -> *mut XRRCrtcInfo;
+type FreeScreenResourcesFn = unsafe extern "C" fn(resources: *mut XRRScreenResources);
+type FreeOutputInfoFn = unsafe extern "C" fn(output_info: *mut XRROutputInfo);
+type FreeCrtcInfoFn = unsafe extern "C" fn(crtc_info: *mut XRRCrtcInfo);
+
+// `libXrandr` is loaded at runtime, like `libXpresent`, so that surfman doesn't gain a link-time
+// dependency on it just to list displays.
+#[allow(non_snake_case)]
+struct XRandRFunctions {
+    GetScreenResourcesCurrent: GetScreenResourcesCurrentFn,
+    GetOutputInfo: GetOutputInfoFn,
+    GetCrtcInfo: GetCrtcInfoFn,
+    FreeScreenResources: FreeScreenResourcesFn,
+    FreeOutputInfo: FreeOutputInfoFn,
+    FreeCrtcInfo: FreeCrtcInfoFn,
+}
+
+lazy_static! {
+    static ref XRANDR_FUNCTIONS: Option<XRandRFunctions> = unsafe {
+        let library_names: [&[u8]; 2] = [b"libXrandr.so.2\0", b"libXrandr.so\0"];
+        let library = library_names
+            .iter()
+            .map(|name| libc::dlopen(name.as_ptr() as *const c_char, libc::RTLD_LAZY))
+            .find(|library| !library.is_null())?;
+        let get = |name: &'static [u8]| libc::dlsym(library, name.as_ptr() as *const c_char);
+        let symbols: [*mut c_void; 6] = [
+            get(b"XRRGetScreenResourcesCurrent\0"),
+            get(b"XRRGetOutputInfo\0"),
+            get(b"XRRGetCrtcInfo\0"),
+            get(b"XRRFreeScreenResources\0"),
+            get(b"XRRFreeOutputInfo\0"),
+            get(b"XRRFreeCrtcInfo\0"),
+        ];
+        if symbols.iter().any(|symbol| symbol.is_null()) {
+            return None;
+        }
+        Some(XRandRFunctions {
+            GetScreenResourcesCurrent: mem::transmute::<*mut c_void, GetScreenResourcesCurrentFn>(
+                symbols[0],
+            ),
+            GetOutputInfo: mem::transmute::<*mut c_void, GetOutputInfoFn>(symbols[1]),
+            GetCrtcInfo: mem::transmute::<*mut c_void, GetCrtcInfoFn>(symbols[2]),
+            FreeScreenResources: mem::transmute::<*mut c_void, FreeScreenResourcesFn>(symbols[3]),
+            FreeOutputInfo: mem::transmute::<*mut c_void, FreeOutputInfoFn>(symbols[4]),
+            FreeCrtcInfo: mem::transmute::<*mut c_void, FreeCrtcInfoFn>(symbols[5]),
+        })
+    };
+}
+
+/// Lists the connected outputs of the default screen that are driven by a CRTC.
+///
+/// X11 has no notion of a per-output scale factor or color space, so those are reported as `1.0`
+/// and `None` respectively.
+///
+/// The display must be locked.
+pub(crate) unsafe fn displays(display: *mut Display) -> Result<Vec<DisplayInfo>, Error> {
+    let functions = match *XRANDR_FUNCTIONS {
+        Some(ref functions) => functions,
+        None => return Err(Error::UnsupportedOnThisPlatform),
+    };
+
+    let resources = (functions.GetScreenResourcesCurrent)(display, XDefaultRootWindow(display));
+    if resources.is_null() {
+        return Err(Error::UnsupportedOnThisPlatform);
+    }
+
+    let outputs = slice::from_raw_parts((*resources).outputs, (*resources).noutput as usize);
+    let modes = slice::from_raw_parts((*resources).modes, (*resources).nmode as usize);
+
+    let mut displays = vec![];
+    for &output in outputs {
+        let output_info = (functions.GetOutputInfo)(display, resources, output);
+        if output_info.is_null() {
+            continue;
+        }
+        if (*output_info).connection as i32 == RR_Connected && (*output_info).crtc != 0 {
+            let crtc_info = (functions.GetCrtcInfo)(display, resources, (*output_info).crtc);
+            if !crtc_info.is_null() {
+                let name = CStr::from_ptr((*output_info).name)
+                    .to_string_lossy()
+                    .into_owned();
+                let refresh_rate = modes
+                    .iter()
+                    .find(|mode| mode.id == (*crtc_info).mode)
+                    .and_then(refresh_rate_of_mode);
+                displays.push(DisplayInfo {
+                    id: output,
+                    name,
+                    origin: Point2D::new((*crtc_info).x, (*crtc_info).y),
+                    size: Size2D::new((*crtc_info).width as i32, (*crtc_info).height as i32),
+                    refresh_rate,
+                    scale_factor: 1.0,
+                    colorspace: None,
+                });
+                (functions.FreeCrtcInfo)(crtc_info);
+            }
+        }
+        (functions.FreeOutputInfo)(output_info);
+    }
+
+    (functions.FreeScreenResources)(resources);
+    Ok(displays)
+}
+
+/// Returns what an X11 window is shown on: the output that contains the center of the window, and
+/// the bit depth of the window's visual.
+///
+/// X11 doesn't report the color space or HDR capability of outputs, so every output is described
+/// as sRGB without HDR.
+///
+/// The display must be locked.
+pub(crate) unsafe fn window_output_info(display: *mut Display, window: Window) -> WidgetOutputInfo {
+    let mut window_attributes: XWindowAttributes = mem::zeroed();
+    if XGetWindowAttributes(display, window, &mut window_attributes) == 0 {
+        return WidgetOutputInfo::unknown(None);
+    }
+
+    let (mut center_x, mut center_y, mut child) = (0, 0, 0);
+    XTranslateCoordinates(
+        display,
+        window,
+        window_attributes.root,
+        window_attributes.width / 2,
+        window_attributes.height / 2,
+        &mut center_x,
+        &mut center_y,
+        &mut child,
+    );
+    let center = Point2D::new(center_x, center_y);
+    let display_id = displays(display).ok().and_then(|displays| {
+        displays
+            .into_iter()
+            .find(|info| Rect::new(info.origin, info.size).contains(center))
+            .map(|info| info.id)
+    });
+
+    let mut output_info = WidgetOutputInfo::unknown(display_id);
+    // Deep color visuals have a depth of 30; 24- and 32-bit visuals have 8 bits per channel.
+    if window_attributes.depth == 30 {
+        output_info.bits_per_component = 10;
+    }
+    output_info
+}
+
+fn refresh_rate_of_mode(mode: &XRRModeInfo) -> Option<f64> {
+    let mut lines = mode.vTotal as f64;
+    if mode.modeFlags & RR_DoubleScan as c_ulong != 0 {
+        lines *= 2.0;
+    }
+    if mode.modeFlags & RR_Interlace as c_ulong != 0 {
+        lines /= 2.0;
+    }
+    let pixels_per_frame = mode.hTotal as f64 * lines;
+    if mode.dotClock == 0 || pixels_per_frame == 0.0 {
+        return None;
+    }
+    Some(mode.dotClock as f64 / pixels_per_frame)
+}
@@ -9,15 +9,19 @@
 
 use super::device::{Adapter, Device, NativeDevice, VendorPreference};
 use super::surface::NativeWidget;
+use crate::context::ContextIDAllocator;
 use crate::egl::types::{EGLDisplay, EGLNativeWindowType};
-use crate::Error;
+use crate::overrides;
+use crate::platform::windows::monitors;
 use crate::GLApi;
+use crate::{DisplayInfo, Error, IpcDescriptor, NativeDisplayHandle, NativeWidgetHandle};
 
 use euclid::default::Size2D;
 
 use std::os::raw::c_void;
 
 use winapi::shared::minwindef::UINT;
+use winapi::shared::windef::HMONITOR;
 use winapi::um::d3dcommon::{D3D_DRIVER_TYPE_UNKNOWN, D3D_DRIVER_TYPE_WARP};
 
 #[cfg(all(feature = "sm-winit", not(target_vendor = "uwp")))]
@@ -34,7 +38,11 @@ const INTEL_PCI_ID: UINT = 0x8086;
 /// thread-safe. So we need to use the DXGI/Direct3D concept of a connection instead. These are
 /// implicit in the Win32 API, and as such this type is a no-op.
 #[derive(Clone)]
-pub struct Connection;
+pub struct Connection {
+    pub(crate) context_ids: ContextIDAllocator,
+    // The adapter that an `IpcDescriptor` pinned hardware rendering to, if any.
+    pub(crate) adapter_luid: Option<u64>,
+}
 
 /// An empty placeholder for native connections.
 ///
@@ -49,10 +57,35 @@ impl Connection {
     /// Connects to the default display.
     #[inline]
     pub fn new() -> Result<Connection, Error> {
-        Ok(Connection)
+        Ok(Connection {
+            context_ids: ContextIDAllocator::new()?,
+            adapter_luid: None,
+        })
+    }
+
+    /// Connects through a handle that another process opened and passed over IPC.
+    ///
+    /// Only `IpcDescriptor::DXGIAdapterLuid` is accepted. The hardware and low-power adapters of
+    /// the connection are then always that adapter. If no adapter has the identifier, this returns
+    /// `NoAdapterFound`.
+    ///
+    /// # Safety
+    ///
+    /// A LUID only identifies an adapter, so there are no requirements on the descriptor.
+    pub unsafe fn from_ipc_descriptor(descriptor: IpcDescriptor) -> Result<Connection, Error> {
+        let IpcDescriptor::DXGIAdapterLuid(luid) = descriptor;
+        Adapter::for_luid(luid)?;
+        Ok(Connection {
+            context_ids: ContextIDAllocator::new()?,
+            adapter_luid: Some(luid),
+        })
     }
 
     /// An alias for `Connection::new()`, present for consistency with other backends.
+    ///
+    /// # Safety
+    ///
+    /// The native connection carries no handles, so there are no requirements on it.
     #[inline]
     pub unsafe fn from_native_connection(_: NativeConnection) -> Result<Connection, Error> {
         Connection::new()
@@ -64,12 +97,27 @@ impl Connection {
         NativeConnection
     }
 
+    /// Returns the raw display connection underneath this connection.
+    ///
+    /// Windows has no display connection, so this returns `NativeDisplayHandle::None`. The
+    /// device's EGL display is returned by `Device::native_device_handle()`.
+    #[inline]
+    pub fn native_display(&self) -> NativeDisplayHandle {
+        NativeDisplayHandle::None
+    }
+
     /// Returns the OpenGL API flavor that this connection supports (OpenGL or OpenGL ES).
     #[inline]
     pub fn gl_api(&self) -> GLApi {
         GLApi::GLES
     }
 
+    /// Returns the monitors attached to the desktop.
+    #[inline]
+    pub fn displays(&self) -> Result<Vec<DisplayInfo>, Error> {
+        monitors::displays()
+    }
+
     /// Returns the "best" adapter on this system, preferring high-performance hardware adapters.
     ///
     /// This is an alias for `Connection::create_hardware_adapter()`.
@@ -81,6 +129,12 @@ impl Connection {
     /// Returns the "best" adapter on this system, preferring high-performance hardware adapters.
     #[inline]
     pub fn create_hardware_adapter(&self) -> Result<Adapter, Error> {
+        if overrides::software_rendering_forced() {
+            return self.create_software_adapter();
+        }
+        if let Some(luid) = self.adapter_luid {
+            return Adapter::for_luid(luid);
+        }
         Adapter::new(
             D3D_DRIVER_TYPE_UNKNOWN,
             VendorPreference::Avoid(INTEL_PCI_ID),
@@ -90,6 +144,12 @@ impl Connection {
     /// Returns the "best" adapter on this system, preferring low-power hardware adapters.
     #[inline]
     pub fn create_low_power_adapter(&self) -> Result<Adapter, Error> {
+        if overrides::software_rendering_forced() {
+            return self.create_software_adapter();
+        }
+        if let Some(luid) = self.adapter_luid {
+            return Adapter::for_luid(luid);
+        }
         Adapter::new(
             D3D_DRIVER_TYPE_UNKNOWN,
             VendorPreference::Prefer(INTEL_PCI_ID),
@@ -102,26 +162,36 @@ impl Connection {
         Adapter::new(D3D_DRIVER_TYPE_WARP, VendorPreference::None)
     }
 
+    /// Returns the adapter for the GPU that drives the given display.
+    ///
+    /// This is the DXGI adapter that has the display's monitor among its outputs.
+    #[inline]
+    pub fn create_adapter_for_display(&self, display: &DisplayInfo) -> Result<Adapter, Error> {
+        Adapter::for_monitor(display.id as usize as HMONITOR)
+    }
+
     /// Opens the hardware device corresponding to the given adapter.
     ///
-    /// Device handles are local to a single thread.
+    /// Device handles can be cloned and sent to other threads.
     #[inline]
-    pub fn create_device(&self, adapter: &Adapter) -> Result<Device, Error> {
-        Device::new(adapter)
+    pub(crate) fn create_device_impl(&self, adapter: &Adapter) -> Result<Device, Error> {
+        Device::new(self, adapter)
     }
 
     /// Wraps a `NativeDevice` in a `Device` and returns it.
     #[inline]
-    pub unsafe fn create_device_from_native_device(
+    pub(crate) unsafe fn create_device_from_native_device_impl(
         &self,
         native_device: NativeDevice,
     ) -> Result<Device, Error> {
-        Device::from_native_device(native_device)
+        Device::from_native_device(self, native_device)
     }
 
     /// Wraps an ANGLE `EGLDisplay`, along with the associated Direct3D device, in a `Device` and
     /// returns it.
     ///
+    /// # Safety
+    ///
     /// The underlying `EGLDisplay` is not retained, as there is no way to do this in the EGL API.
     /// Therefore, it is the caller's responsibility to keep it alive as long as this `Device`
     /// remains alive. This function does, however, call `AddRef` on the Direct3D device.
@@ -130,7 +200,7 @@ impl Connection {
         &self,
         egl_display: EGLDisplay,
     ) -> Result<Device, Error> {
-        Device::from_egl_display(egl_display)
+        Device::from_egl_display(self, egl_display)
     }
 
     /// Opens the display connection corresponding to the given `winit` window.
@@ -170,6 +240,11 @@ impl Connection {
     }
 
     /// Create a native widget from a raw pointer
+    ///
+    /// # Safety
+    ///
+    /// The pointer must be a live `HWND`, which must outlive the native widget and any surfaces
+    /// created from it.
     pub unsafe fn create_native_widget_from_ptr(
         &self,
         raw: *mut c_void,
@@ -180,6 +255,26 @@ impl Connection {
         }
     }
 
+    /// Creates a native widget from a handle to a native window or view.
+    ///
+    /// Only `HWND` handles are accepted.
+    ///
+    /// # Safety
+    ///
+    /// The handle must refer to a live window or view, which must outlive the native widget and any
+    /// surfaces created from it.
+    pub unsafe fn create_native_widget_from_handle(
+        &self,
+        handle: NativeWidgetHandle,
+    ) -> Result<NativeWidget, Error> {
+        match handle {
+            NativeWidgetHandle::HWND(window_handle) => Ok(NativeWidget {
+                egl_native_window: window_handle as EGLNativeWindowType,
+            }),
+            _ => Err(Error::IncompatibleNativeWidget),
+        }
+    }
+
     /// Create a native widget type from the given `raw_window_handle::RawWindowHandle`.
     #[cfg(feature = "sm-raw-window-handle")]
     #[inline]
@@ -2,18 +2,25 @@
 //
 //! Wrapper for EGL contexts managed by ANGLE using Direct3D 11 as a backend on Windows.
 
+use super::connection::Connection;
 use super::device::Device;
 use super::surface::{Surface, Synchronization, Win32Objects};
-use crate::context::{ContextID, CREATE_CONTEXT_MUTEX};
+use crate::context::ContextID;
 use crate::egl;
 use crate::egl::types::{EGLConfig, EGLContext, EGLint};
+use crate::gl::types::GLuint;
+use crate::gl_utils;
+#[cfg(feature = "sm-opencl")]
+use crate::opencl::{GLSharingDisplay, GLSharingProperties};
+use crate::overrides;
 use crate::platform::generic::egl::context::{self, CurrentContextGuard};
 use crate::platform::generic::egl::device::EGL_FUNCTIONS;
 use crate::platform::generic::egl::error::ToWindowingApiError;
 use crate::platform::generic::egl::surface::ExternalEGLSurfaces;
-use crate::surface::Framebuffer;
-use crate::{ContextAttributes, Error, Gl, SurfaceInfo};
+use crate::surface::{ExternalFramebuffer, Framebuffer};
+use crate::{ContextAttributes, ContextDescriptorID, Error, Gl, SurfaceInfo};
 
+use euclid::default::Size2D;
 use std::mem;
 use std::os::raw::c_void;
 use std::thread;
@@ -22,6 +29,16 @@ use winapi::um::winbase::INFINITE;
 
 pub use crate::platform::generic::egl::context::{ContextDescriptor, NativeContext};
 
+// Generic surfaces are pbuffers that are bound to textures, so configs must support both.
+pub(crate) const PBUFFER_CONFIG_ATTRIBUTES: [EGLint; 6] = [
+    egl::BIND_TO_TEXTURE_RGBA as EGLint,
+    1 as EGLint,
+    egl::SURFACE_TYPE as EGLint,
+    egl::PBUFFER_BIT as EGLint,
+    egl::RENDERABLE_TYPE as EGLint,
+    egl::OPENGL_ES2_BIT as EGLint,
+];
+
 thread_local! {
     #[doc(hidden)]
     pub static GL_FUNCTIONS: Gl = Gl::load_with(context::get_proc_address);
@@ -63,37 +80,47 @@ impl Device {
     /// Creates a context descriptor with the given attributes.
     ///
     /// Context descriptors are local to this device.
+    ///
+    /// Descriptors are cached by this device, so asking again for the same attributes returns a
+    /// copy of the earlier descriptor without choosing an EGL config again.
     #[inline]
-    pub fn create_context_descriptor(
+    pub(crate) fn create_context_descriptor_impl(
         &self,
         attributes: &ContextAttributes,
     ) -> Result<ContextDescriptor, Error> {
-        unsafe {
-            ContextDescriptor::new(
-                self.egl_display,
-                attributes,
-                &[
-                    egl::BIND_TO_TEXTURE_RGBA as EGLint,
-                    1 as EGLint,
-                    egl::SURFACE_TYPE as EGLint,
-                    egl::PBUFFER_BIT as EGLint,
-                    egl::RENDERABLE_TYPE as EGLint,
-                    egl::OPENGL_ES2_BIT as EGLint,
-                ],
-            )
-        }
+        let attributes = &overrides::context_attributes(attributes);
+        self.context_descriptors
+            .get_or_insert_with(attributes, || self.choose_context_descriptor(attributes))
+    }
+
+    /// Returns a small ID for the given context descriptor, which is the same for every equal
+    /// descriptor.
+    ///
+    /// The IDs are shared with clones of this device.
+    #[inline]
+    pub fn intern_context_descriptor(
+        &self,
+        context_descriptor: &ContextDescriptor,
+    ) -> ContextDescriptorID {
+        self.context_descriptors.intern(context_descriptor)
+    }
+
+    fn choose_context_descriptor(
+        &self,
+        attributes: &ContextAttributes,
+    ) -> Result<ContextDescriptor, Error> {
+        unsafe { ContextDescriptor::new(self.egl_display, attributes, &PBUFFER_CONFIG_ATTRIBUTES) }
     }
 
     /// Creates a new OpenGL context.
     ///
     /// The context initially has no surface attached. Until a surface is bound to it, rendering
     /// commands will fail or have no effect.
-    pub fn create_context(
+    pub(crate) fn create_context_impl(
         &mut self,
         descriptor: &ContextDescriptor,
         share_with: Option<&Context>,
     ) -> Result<Context, Error> {
-        let mut next_context_id = CREATE_CONTEXT_MUTEX.lock().unwrap();
         unsafe {
             let egl_context = context::create_context(
                 self.egl_display,
@@ -104,11 +131,10 @@ impl Device {
 
             let context = Context {
                 egl_context,
-                id: *next_context_id,
+                id: self.context_ids.next(),
                 framebuffer: Framebuffer::None,
                 context_is_owned: true,
             };
-            next_context_id.0 += 1;
             Ok(context)
         }
     }
@@ -118,36 +144,51 @@ impl Device {
     /// The underlying `EGLContext` is not retained, as there is no way to do this in the EGL API.
     /// Therefore, it is the caller's responsibility to keep it alive as long as this `Context`
     /// remains alive.
-    pub unsafe fn create_context_from_native_context(
+    pub(crate) unsafe fn create_context_from_native_context_impl(
         &self,
         native_context: NativeContext,
     ) -> Result<Context, Error> {
-        let mut next_context_id = CREATE_CONTEXT_MUTEX.lock().unwrap();
-
         // Create the context.
         let context = Context {
             egl_context: native_context.egl_context,
-            id: *next_context_id,
+            id: self.context_ids.next(),
             framebuffer: Framebuffer::External(ExternalEGLSurfaces {
                 draw: native_context.egl_draw_surface,
                 read: native_context.egl_read_surface,
             }),
             context_is_owned: false,
         };
-        next_context_id.0 += 1;
 
         Ok(context)
     }
 
+    /// Wraps the ANGLE context that is current on this thread, along with its display and the
+    /// Direct3D 11 device under it, in a new device and context.
+    ///
+    /// If no context is current, this returns a `NoCurrentContext` error.
+    ///
+    /// # Safety
+    ///
+    /// Neither the context nor the display is retained, so the caller must keep both alive as long
+    /// as the device and context are. The Direct3D device is retained.
+    pub unsafe fn from_current_context() -> Result<(Device, Context), Error> {
+        let native_context = NativeContext::current()?;
+        let egl_display = EGL_FUNCTIONS.with(|egl| egl.GetCurrentDisplay());
+        let connection = Connection::new()?;
+        let device = connection.create_device_from_egl_display(egl_display)?;
+        let context = device.create_context_from_native_context(native_context)?;
+        Ok((device, context))
+    }
+
     /// Destroys a context.
     ///
     /// The context must have been created on this device.
-    pub fn destroy_context(&self, context: &mut Context) -> Result<(), Error> {
+    pub(crate) fn destroy_context_impl(&self, context: &mut Context) -> Result<(), Error> {
         if context.egl_context == egl::NO_CONTEXT {
             return Ok(());
         }
 
-        if let Ok(Some(mut surface)) = self.unbind_surface_from_context(context) {
+        if let Ok(Some(mut surface)) = self.unbind_surface_from_context_impl(context) {
             self.destroy_surface(context, &mut surface)?;
         }
 
@@ -183,11 +224,13 @@ impl Device {
     /// Makes the context the current OpenGL context for this thread.
     ///
     /// After calling this function, it is valid to use OpenGL rendering commands.
-    pub fn make_context_current(&self, context: &Context) -> Result<(), Error> {
+    pub(crate) fn make_context_current_impl(&self, context: &Context) -> Result<(), Error> {
         unsafe {
             let (egl_draw_surface, egl_read_surface) = match context.framebuffer {
                 Framebuffer::Surface(ref surface) => (surface.egl_surface, surface.egl_surface),
-                Framebuffer::None => (egl::NO_SURFACE, egl::NO_SURFACE),
+                Framebuffer::None | Framebuffer::ExternalFramebuffer(_) => {
+                    (egl::NO_SURFACE, egl::NO_SURFACE)
+                }
                 Framebuffer::External(ref surfaces) => (surfaces.draw, surfaces.read),
             };
 
@@ -207,6 +250,37 @@ impl Device {
         }
     }
 
+    /// Makes the context the current OpenGL context for this thread, rendering to the given
+    /// surface instead of any surface bound to it.
+    ///
+    /// The surface must have been created with this context, or an `IncompatibleSurface` error is
+    /// returned.
+    pub(crate) fn make_context_current_with_surface_impl(
+        &self,
+        context: &Context,
+        surface: &Surface,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        unsafe {
+            EGL_FUNCTIONS.with(|egl| {
+                let result = egl.MakeCurrent(
+                    self.egl_display,
+                    surface.egl_surface,
+                    surface.egl_surface,
+                    context.egl_context,
+                );
+                if result == egl::FALSE {
+                    let err = egl.GetError().to_windowing_api_error();
+                    return Err(Error::MakeCurrentFailed(err));
+                }
+                Ok(())
+            })
+        }
+    }
+
     /// Removes the current OpenGL context from this thread.
     ///
     /// After calling this function, OpenGL rendering commands will fail until a new context is
@@ -220,10 +294,20 @@ impl Device {
         context: &Context,
     ) -> Result<CurrentContextGuard, Error> {
         let guard = CurrentContextGuard::new();
-        self.make_context_current(context)?;
+        self.make_context_current_impl(context)?;
         Ok(guard)
     }
 
+    // Calls the given function with the OpenGL functions, which are loaded once per thread and
+    // shared by all contexts.
+    #[inline]
+    pub(crate) fn with_gl_functions<F, R>(&self, _: &Context, callback: F) -> R
+    where
+        F: FnOnce(&Gl) -> R,
+    {
+        GL_FUNCTIONS.with(|gl| callback(gl))
+    }
+
     pub(crate) fn context_is_current(&self, context: &Context) -> bool {
         EGL_FUNCTIONS.with(|egl| unsafe { egl.GetCurrentContext() == context.egl_context })
     }
@@ -267,7 +351,7 @@ impl Device {
     /// `unbind_surface_from_context`.
     ///
     /// If an error is returned, the surface is returned alongside it.
-    pub fn bind_surface_to_context(
+    pub(crate) fn bind_surface_to_context_impl(
         &self,
         context: &mut Context,
         surface: Surface,
@@ -278,7 +362,9 @@ impl Device {
 
         match context.framebuffer {
             Framebuffer::None => {}
-            Framebuffer::External(_) => return Err((Error::ExternalRenderTarget, surface)),
+            Framebuffer::External(_) | Framebuffer::ExternalFramebuffer(_) => {
+                return Err((Error::ExternalRenderTarget, surface))
+            }
             Framebuffer::Surface(_) => return Err((Error::SurfaceAlreadyBound, surface)),
         }
 
@@ -309,7 +395,7 @@ impl Device {
 
         if is_current {
             // We need to make ourselves current again, because the surface changed.
-            drop(self.make_context_current(context));
+            drop(self.make_context_current_impl(context));
         }
 
         Ok(())
@@ -319,19 +405,25 @@ impl Device {
     ///
     /// Any pending OpenGL commands targeting this surface will be automatically flushed, so the
     /// surface is safe to read from immediately when this function returns.
-    pub fn unbind_surface_from_context(
+    pub(crate) fn unbind_surface_from_context_impl(
         &self,
         context: &mut Context,
     ) -> Result<Option<Surface>, Error> {
         match context.framebuffer {
             Framebuffer::None => return Ok(None),
             Framebuffer::External(_) => return Err(Error::ExternalRenderTarget),
+            Framebuffer::ExternalFramebuffer(_) => {
+                context.framebuffer = Framebuffer::None;
+                return Ok(None);
+            }
             Framebuffer::Surface(_) => {}
         }
 
         let surface = match mem::replace(&mut context.framebuffer, Framebuffer::None) {
             Framebuffer::Surface(surface) => surface,
-            Framebuffer::None | Framebuffer::External(_) => unreachable!(),
+            Framebuffer::None | Framebuffer::External(_) | Framebuffer::ExternalFramebuffer(_) => {
+                unreachable!()
+            }
         };
 
         match surface.win32_objects {
@@ -348,6 +440,52 @@ impl Device {
         Ok(Some(surface))
     }
 
+    /// Makes a context render to a framebuffer object that the caller created and owns, in place
+    /// of a surface.
+    ///
+    /// The framebuffer object must stay alive until it's detached again with
+    /// `unbind_surface_from_context()`.
+    pub fn bind_external_framebuffer(
+        &self,
+        context: &mut Context,
+        framebuffer_object: GLuint,
+        size: Size2D<i32>,
+    ) -> Result<(), Error> {
+        match context.framebuffer {
+            Framebuffer::None => {}
+            Framebuffer::External(_) | Framebuffer::ExternalFramebuffer(_) => {
+                return Err(Error::ExternalRenderTarget)
+            }
+            Framebuffer::Surface(_) => return Err(Error::SurfaceAlreadyBound),
+        }
+
+        // The context stays current without a surface, as it does when nothing is bound.
+        context.framebuffer =
+            Framebuffer::ExternalFramebuffer(ExternalFramebuffer::new(framebuffer_object, size));
+        Ok(())
+    }
+
+    /// Makes the rendering submitted so far to a surface visible to everything else that reads
+    /// it.
+    ///
+    /// This flushes the context and waits on a fence for the rendering to finish.
+    ///
+    /// The surface must have been created with the given context, or an `IncompatibleSurface`
+    /// error is returned.
+    pub(crate) fn flush_context_impl(
+        &self,
+        context: &Context,
+        surface: &Surface,
+    ) -> Result<(), Error> {
+        if surface.context_id != context.id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        let _guard = self.temporarily_make_context_current(context)?;
+        GL_FUNCTIONS.with(gl_utils::finish_with_fence);
+        Ok(())
+    }
+
     /// Returns a unique ID representing a context.
     ///
     /// This ID is unique to all currently-allocated contexts. If you destroy a context and create
@@ -364,7 +502,13 @@ impl Device {
         match context.framebuffer {
             Framebuffer::None => Ok(None),
             Framebuffer::External(_) => Err(Error::ExternalRenderTarget),
-            Framebuffer::Surface(ref surface) => Ok(Some(self.surface_info(surface))),
+            Framebuffer::ExternalFramebuffer(ref framebuffer) => {
+                Ok(Some(framebuffer.info(context.id)))
+            }
+            Framebuffer::Surface(ref surface) => Ok(Some(SurfaceInfo {
+                bound: true,
+                ..self.surface_info(surface)
+            })),
         }
     }
 
@@ -373,7 +517,9 @@ impl Device {
         let (egl_draw_surface, egl_read_surface) = match context.framebuffer {
             Framebuffer::Surface(Surface { egl_surface, .. }) => (egl_surface, egl_surface),
             Framebuffer::External(ExternalEGLSurfaces { draw, read }) => (draw, read),
-            Framebuffer::None => (egl::NO_SURFACE, egl::NO_SURFACE),
+            Framebuffer::None | Framebuffer::ExternalFramebuffer(_) => {
+                (egl::NO_SURFACE, egl::NO_SURFACE)
+            }
         };
 
         NativeContext {
@@ -383,3 +529,15 @@ impl Device {
         }
     }
 }
+
+#[cfg(feature = "sm-opencl")]
+impl Device {
+    /// Returns what OpenCL needs to share objects with the given context through
+    /// `cl_khr_gl_sharing`.
+    pub fn gl_sharing_properties(&self, context: &Context) -> Result<GLSharingProperties, Error> {
+        Ok(GLSharingProperties {
+            gl_context: context.egl_context as *mut c_void,
+            display: GLSharingDisplay::EGL(self.egl_display),
+        })
+    }
+}
@@ -1,22 +1,29 @@
 // surfman/surfman/src/platform/windows/angle/device.rs
 //
-//! A thread-local handle to the device.
+//! A handle to the device.
 
 use super::connection::Connection;
+use super::context::ContextDescriptor;
+use crate::context::{ContextDescriptorCache, ContextIDAllocator};
+use crate::device_events::{self, DeviceEventDispatcher};
 use crate::egl;
 use crate::egl::types::{EGLAttrib, EGLDisplay, EGLint, EGLDeviceEXT};
-use crate::platform::generic::egl::device::EGL_FUNCTIONS;
+use crate::platform::generic::egl::device::{OwnedEGLDisplay, EGL_FUNCTIONS};
 use crate::platform::generic::egl::ffi::{EGL_D3D11_DEVICE_ANGLE, EGL_EXTENSION_FUNCTIONS};
 use crate::platform::generic::egl::ffi::{EGL_NO_DEVICE_EXT, EGL_PLATFORM_DEVICE_EXT};
 use crate::platform::generic::egl::ffi::{EGL_DEVICE_EXT};
-use crate::{Error, GLApi};
+use crate::timings::{CreationTimings, CreationTimingsRecorder};
+use crate::{DeviceEvent, DeviceEventHandler, Error, GLApi, LifecycleObserver, NativeDeviceHandle};
 
 use std::cell::{RefCell, RefMut};
 use std::mem;
 use std::os::raw::c_void;
 use std::ptr;
-use winapi::shared::dxgi::{self, IDXGIAdapter, IDXGIDevice, IDXGIFactory1};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use winapi::shared::dxgi::{self, IDXGIAdapter, IDXGIDevice, IDXGIFactory1, IDXGIOutput};
 use winapi::shared::minwindef::UINT;
+use winapi::shared::windef::HMONITOR;
 use winapi::shared::winerror::{self, S_OK};
 use winapi::um::d3d11::{D3D11CreateDevice, ID3D11Device, D3D11_SDK_VERSION};
 use winapi::um::d3dcommon::{D3D_DRIVER_TYPE, D3D_DRIVER_TYPE_UNKNOWN, D3D_FEATURE_LEVEL_9_3};
@@ -29,7 +36,7 @@ thread_local! {
 
 /// Represents a hardware display adapter that can be used for rendering (including the CPU).
 ///
-/// Adapters can be sent between threads. To render with an adapter, open a thread-local `Device`.
+/// Adapters can be sent between threads. To render with an adapter, open a `Device`.
 #[derive(Clone)]
 pub struct Adapter {
     pub(crate) dxgi_adapter: ComPtr<IDXGIAdapter>,
@@ -38,16 +45,34 @@ pub struct Adapter {
 
 unsafe impl Send for Adapter {}
 
-/// A thread-local handle to a device.
+/// A handle to a device.
 ///
-/// Devices contain most of the relevant surface management methods.
+/// Devices contain most of the relevant surface management methods. Clones share the device's
+/// EGL display and Direct3D 11 device, and can be sent to other threads, so each rendering thread
+/// can own a handle.
+#[derive(Clone)]
 pub struct Device {
     pub(crate) egl_display: EGLDisplay,
     pub(crate) d3d11_device: ComPtr<ID3D11Device>,
     pub(crate) d3d_driver_type: D3D_DRIVER_TYPE,
-    pub(crate) display_is_owned: bool,
+    // Present if the device initialized the display and must terminate it.
+    pub(crate) _display_owner: Option<Arc<OwnedEGLDisplay>>,
+    pub(crate) lifecycle_observer: Option<Arc<dyn LifecycleObserver>>,
+    pub(crate) device_event_dispatcher: DeviceEventDispatcher,
+    pub(crate) creation_timings: CreationTimingsRecorder,
+    pub(crate) memory_usage: Arc<AtomicUsize>,
+    pub(crate) context_ids: ContextIDAllocator,
+    pub(crate) context_descriptors: ContextDescriptorCache<ContextDescriptor>,
 }
 
+// EGL displays are process-wide, and Direct3D 11 devices are free-threaded.
+unsafe impl Send for Device {}
+
+// Lets the device event thread check whether the Direct3D 11 device was lost.
+struct SendableD3D11Device(ComPtr<ID3D11Device>);
+
+unsafe impl Send for SendableD3D11Device {}
+
 pub(crate) enum VendorPreference {
     None,
     Prefer(UINT),
@@ -65,29 +90,34 @@ pub struct NativeDevice {
     pub d3d_driver_type: D3D_DRIVER_TYPE,
 }
 
+// Returns this thread's DXGI factory, creating it if necessary.
+unsafe fn dxgi_factory() -> Result<ComPtr<IDXGIFactory1>, Error> {
+    DXGI_FACTORY.with(|dxgi_factory_slot| {
+        let mut dxgi_factory_slot: RefMut<Option<ComPtr<IDXGIFactory1>>> =
+            dxgi_factory_slot.borrow_mut();
+        if dxgi_factory_slot.is_none() {
+            let mut dxgi_factory: *mut IDXGIFactory1 = ptr::null_mut();
+            let result = dxgi::CreateDXGIFactory1(
+                &IDXGIFactory1::uuidof(),
+                &mut dxgi_factory as *mut *mut IDXGIFactory1 as *mut *mut c_void,
+            );
+            if !winerror::SUCCEEDED(result) {
+                return Err(Error::Failed);
+            }
+            assert!(!dxgi_factory.is_null());
+            *dxgi_factory_slot = Some(ComPtr::from_raw(dxgi_factory));
+        }
+        Ok((*dxgi_factory_slot).clone().unwrap())
+    })
+}
+
 impl Adapter {
     pub(crate) fn new(
         d3d_driver_type: D3D_DRIVER_TYPE,
         vendor_preference: VendorPreference,
     ) -> Result<Adapter, Error> {
         unsafe {
-            let dxgi_factory = DXGI_FACTORY.with(|dxgi_factory_slot| {
-                let mut dxgi_factory_slot: RefMut<Option<ComPtr<IDXGIFactory1>>> =
-                    dxgi_factory_slot.borrow_mut();
-                if dxgi_factory_slot.is_none() {
-                    let mut dxgi_factory: *mut IDXGIFactory1 = ptr::null_mut();
-                    let result = dxgi::CreateDXGIFactory1(
-                        &IDXGIFactory1::uuidof(),
-                        &mut dxgi_factory as *mut *mut IDXGIFactory1 as *mut *mut c_void,
-                    );
-                    if !winerror::SUCCEEDED(result) {
-                        return Err(Error::Failed);
-                    }
-                    assert!(!dxgi_factory.is_null());
-                    *dxgi_factory_slot = Some(ComPtr::from_raw(dxgi_factory));
-                }
-                Ok((*dxgi_factory_slot).clone().unwrap())
-            })?;
+            let dxgi_factory = dxgi_factory()?;
 
             // Find the first adapter that matches the vendor preference.
             let mut adapter_index = 0;
@@ -151,6 +181,93 @@ impl Adapter {
         }
     }
 
+    // Returns the hardware adapter that has the given monitor among its outputs.
+    pub(crate) fn for_monitor(monitor: HMONITOR) -> Result<Adapter, Error> {
+        unsafe {
+            let dxgi_factory = dxgi_factory()?;
+
+            let mut adapter_index = 0;
+            loop {
+                let mut dxgi_adapter_1 = ptr::null_mut();
+                let result = (*dxgi_factory).EnumAdapters1(adapter_index, &mut dxgi_adapter_1);
+                if !winerror::SUCCEEDED(result) {
+                    return Err(Error::NoAdapterFound);
+                }
+                assert!(!dxgi_adapter_1.is_null());
+                let dxgi_adapter_1 = ComPtr::from_raw(dxgi_adapter_1);
+
+                let mut output_index = 0;
+                loop {
+                    let mut dxgi_output = ptr::null_mut();
+                    let result = (*dxgi_adapter_1).EnumOutputs(output_index, &mut dxgi_output);
+                    if !winerror::SUCCEEDED(result) {
+                        break;
+                    }
+                    assert!(!dxgi_output.is_null());
+                    let dxgi_output: ComPtr<IDXGIOutput> = ComPtr::from_raw(dxgi_output);
+
+                    let mut output_desc = mem::zeroed();
+                    let result = (*dxgi_output).GetDesc(&mut output_desc);
+                    if winerror::SUCCEEDED(result) && output_desc.Monitor == monitor {
+                        let mut dxgi_adapter: *mut IDXGIAdapter = ptr::null_mut();
+                        let result = (*dxgi_adapter_1).QueryInterface(
+                            &IDXGIAdapter::uuidof(),
+                            &mut dxgi_adapter as *mut *mut IDXGIAdapter as *mut *mut c_void,
+                        );
+                        assert_eq!(result, S_OK);
+                        return Ok(Adapter {
+                            dxgi_adapter: ComPtr::from_raw(dxgi_adapter),
+                            d3d_driver_type: D3D_DRIVER_TYPE_UNKNOWN,
+                        });
+                    }
+
+                    output_index += 1;
+                }
+
+                adapter_index += 1;
+            }
+        }
+    }
+
+    // Returns the hardware adapter with the given locally unique identifier.
+    pub(crate) fn for_luid(luid: u64) -> Result<Adapter, Error> {
+        unsafe {
+            let dxgi_factory = dxgi_factory()?;
+
+            let mut adapter_index = 0;
+            loop {
+                let mut dxgi_adapter_1 = ptr::null_mut();
+                let result = (*dxgi_factory).EnumAdapters1(adapter_index, &mut dxgi_adapter_1);
+                if !winerror::SUCCEEDED(result) {
+                    return Err(Error::NoAdapterFound);
+                }
+                assert!(!dxgi_adapter_1.is_null());
+                let dxgi_adapter_1 = ComPtr::from_raw(dxgi_adapter_1);
+
+                let mut adapter_desc = mem::zeroed();
+                let result = (*dxgi_adapter_1).GetDesc1(&mut adapter_desc);
+                assert_eq!(result, S_OK);
+
+                let adapter_luid = ((adapter_desc.AdapterLuid.HighPart as u32 as u64) << 32)
+                    | adapter_desc.AdapterLuid.LowPart as u64;
+                if adapter_luid == luid {
+                    let mut dxgi_adapter: *mut IDXGIAdapter = ptr::null_mut();
+                    let result = (*dxgi_adapter_1).QueryInterface(
+                        &IDXGIAdapter::uuidof(),
+                        &mut dxgi_adapter as *mut *mut IDXGIAdapter as *mut *mut c_void,
+                    );
+                    assert_eq!(result, S_OK);
+                    return Ok(Adapter {
+                        dxgi_adapter: ComPtr::from_raw(dxgi_adapter),
+                        d3d_driver_type: D3D_DRIVER_TYPE_UNKNOWN,
+                    });
+                }
+
+                adapter_index += 1;
+            }
+        }
+    }
+
     /// Create an Adapter instance wrapping an existing DXGI adapter.
     pub fn from_dxgi_adapter(adapter: ComPtr<IDXGIAdapter>) -> Adapter {
         Adapter {
@@ -162,7 +279,7 @@ impl Adapter {
 
 impl Device {
     #[allow(non_snake_case)]
-    pub(crate) fn new(adapter: &Adapter) -> Result<Device, Error> {
+    pub(crate) fn new(connection: &Connection, adapter: &Adapter) -> Result<Device, Error> {
         let d3d_driver_type = adapter.d3d_driver_type;
         unsafe {
             let mut d3d11_device = ptr::null_mut();
@@ -214,26 +331,44 @@ impl Device {
                     egl_display,
                     d3d11_device,
                     d3d_driver_type,
-                    display_is_owned: true,
+                    _display_owner: Some(Arc::new(OwnedEGLDisplay(egl_display))),
+                    lifecycle_observer: None,
+                    device_event_dispatcher: DeviceEventDispatcher::default(),
+                    creation_timings: CreationTimingsRecorder::default(),
+                    memory_usage: Arc::new(AtomicUsize::new(0)),
+                    context_ids: connection.context_ids.clone(),
+                    context_descriptors: ContextDescriptorCache::new(),
                 })
             })
         }
     }
 
-    pub(crate) fn from_native_device(native_device: NativeDevice) -> Result<Device, Error> {
+    pub(crate) fn from_native_device(
+        connection: &Connection,
+        native_device: NativeDevice,
+    ) -> Result<Device, Error> {
         unsafe {
             (*native_device.d3d11_device).AddRef();
             Ok(Device {
                 egl_display: native_device.egl_display,
                 d3d11_device: ComPtr::from_raw(native_device.d3d11_device),
                 d3d_driver_type: native_device.d3d_driver_type,
-                display_is_owned: false,
+                _display_owner: None,
+                lifecycle_observer: None,
+                device_event_dispatcher: DeviceEventDispatcher::default(),
+                creation_timings: CreationTimingsRecorder::default(),
+                memory_usage: Arc::new(AtomicUsize::new(0)),
+                context_ids: connection.context_ids.clone(),
+                context_descriptors: ContextDescriptorCache::new(),
             })
         }
     }
 
     #[allow(non_snake_case)]
-    pub(crate) fn from_egl_display(egl_display: EGLDisplay) -> Result<Device, Error> {
+    pub(crate) fn from_egl_display(
+        connection: &Connection,
+        egl_display: EGLDisplay,
+    ) -> Result<Device, Error> {
 
         let eglQueryDisplayAttribEXT = EGL_EXTENSION_FUNCTIONS.QueryDisplayAttribEXT.expect(
             "Where's the `EGL_EXT_device_query` extension?"
@@ -267,7 +402,13 @@ impl Device {
                 egl_display: egl_display,
                 d3d11_device: ComPtr::from_raw(d3d11_device),
                 d3d_driver_type: D3D_DRIVER_TYPE_UNKNOWN,
-                display_is_owned: false,
+                _display_owner: None,
+                lifecycle_observer: None,
+                device_event_dispatcher: DeviceEventDispatcher::default(),
+                creation_timings: CreationTimingsRecorder::default(),
+                memory_usage: Arc::new(AtomicUsize::new(0)),
+                context_ids: connection.context_ids.clone(),
+                context_descriptors: ContextDescriptorCache::new(),
             })
         }
     }
@@ -275,7 +416,10 @@ impl Device {
     /// Returns the display server connection that this device was created with.
     #[inline]
     pub fn connection(&self) -> Connection {
-        Connection
+        Connection {
+            context_ids: self.context_ids.clone(),
+            adapter_luid: None,
+        }
     }
 
     /// Returns the adapter that this device was created with.
@@ -313,23 +457,96 @@ impl Device {
         }
     }
 
+    /// Returns the raw GPU device underneath this device: its Direct3D 11 device and the EGL
+    /// display that ANGLE created on it.
+    ///
+    /// Unlike `native_device()`, this doesn't add a reference to the Direct3D 11 device.
+    #[inline]
+    pub fn native_device_handle(&self) -> NativeDeviceHandle {
+        NativeDeviceHandle::D3D11Device {
+            device: self.d3d11_device.as_raw() as *mut c_void,
+            egl_display: self.egl_display as *mut c_void,
+        }
+    }
+
     /// Returns the OpenGL API flavor that this device supports (OpenGL or OpenGL ES).
     #[inline]
     pub fn gl_api(&self) -> GLApi {
         GLApi::GLES
     }
-}
 
-impl Drop for Device {
-    fn drop(&mut self) {
-        unsafe {
-            if self.display_is_owned {
-                EGL_FUNCTIONS.with(|egl| {
-                    let result = egl.Terminate(self.egl_display);
-                    assert_ne!(result, egl::FALSE);
-                    self.egl_display = egl::NO_DISPLAY;
-                })
+    /// Installs an observer that is notified when contexts and surfaces of this device are
+    /// created, destroyed, resized, or presented, replacing any previous one.
+    #[inline]
+    pub fn set_lifecycle_observer(&mut self, observer: Option<Arc<dyn LifecycleObserver>>) {
+        self.lifecycle_observer = observer;
+    }
+
+    /// Returns the lifecycle observer installed on this device, if any.
+    #[inline]
+    pub fn lifecycle_observer(&self) -> Option<Arc<dyn LifecycleObserver>> {
+        self.lifecycle_observer.clone()
+    }
+
+    /// Installs a handler that is told when the GPU of this device is removed or reset, or the
+    /// displays are reconfigured, replacing any previous one.
+    ///
+    /// The Direct3D 11 device's removal reason and `Connection::displays()` are checked once a
+    /// second. A hung or reset Direct3D 11 device is reported as `Reset`, but like a removed one,
+    /// the device has to be opened again.
+    pub fn set_device_event_handler(&mut self, handler: Option<DeviceEventHandler>) {
+        let connection = self.connection();
+        let mut poll_displays = device_events::display_poller(move || connection.displays());
+        let d3d11_device = SendableD3D11Device(self.d3d11_device.clone());
+        let mut lost = false;
+        let poll = move || {
+            if !lost {
+                let reason = unsafe { d3d11_device.0.GetDeviceRemovedReason() };
+                if reason != S_OK {
+                    // The reason doesn't change once the device is lost, so only report it once.
+                    lost = true;
+                    if reason == winerror::DXGI_ERROR_DEVICE_REMOVED {
+                        return Some(DeviceEvent::Removed);
+                    }
+                    return Some(DeviceEvent::Reset);
+                }
             }
-        }
+            poll_displays()
+        };
+        self.device_event_dispatcher.set_handler(handler, poll);
+    }
+
+    /// Returns the device event handler installed on this device, if any.
+    #[inline]
+    pub fn device_event_handler(&self) -> Option<DeviceEventHandler> {
+        self.device_event_dispatcher.handler()
+    }
+
+    #[inline]
+    pub(crate) fn device_event_dispatcher(&self) -> &DeviceEventDispatcher {
+        &self.device_event_dispatcher
+    }
+
+    /// Returns the GPU memory, in bytes, held by the surfaces of this device.
+    #[inline]
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub(crate) fn memory_usage_counter(&self) -> &AtomicUsize {
+        &self.memory_usage
+    }
+
+    /// Returns how long opening this device, and creating its context descriptors and contexts,
+    /// took.
+    #[inline]
+    pub fn creation_timings(&self) -> CreationTimings {
+        self.creation_timings.get()
+    }
+
+    #[inline]
+    pub(crate) fn creation_timings_recorder(&self) -> &CreationTimingsRecorder {
+        &self.creation_timings
     }
 }
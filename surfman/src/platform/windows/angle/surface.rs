@@ -2,7 +2,7 @@
 //
 //! Surface management for Direct3D 11 on Windows using the ANGLE library as a frontend.
 
-use super::context::{Context, ContextDescriptor, GL_FUNCTIONS};
+use super::context::{Context, ContextDescriptor, GL_FUNCTIONS, PBUFFER_CONFIG_ATTRIBUTES};
 use super::device::Device;
 use crate::context::ContextID;
 use crate::egl::types::EGLNativeWindowType;
@@ -10,26 +10,53 @@ use crate::egl::types::EGLSurface;
 use crate::egl::{self, EGLint};
 use crate::gl;
 use crate::gl::types::{GLenum, GLint, GLuint};
-use crate::platform::generic::egl::device::EGL_FUNCTIONS;
+use crate::gl_utils;
+#[cfg(feature = "sm-opencl")]
+use crate::opencl::SharedEGLImage;
+use crate::platform::generic::egl::context::{self, get_config_samples};
+use crate::platform::generic::egl::device::{display_has_extension, EGL_FUNCTIONS};
 use crate::platform::generic::egl::error::ToWindowingApiError;
+use crate::platform::generic::egl::ffi::EGLImageKHR;
+use crate::platform::generic::egl::ffi::EGL_D3D11_TEXTURE_ANGLE;
 use crate::platform::generic::egl::ffi::EGL_D3D_TEXTURE_2D_SHARE_HANDLE_ANGLE;
 use crate::platform::generic::egl::ffi::EGL_D3D_TEXTURE_ANGLE;
+use crate::platform::generic::egl::ffi::EGL_DIRECT_COMPOSITION_ANGLE;
 use crate::platform::generic::egl::ffi::EGL_DXGI_KEYED_MUTEX_ANGLE;
 use crate::platform::generic::egl::ffi::EGL_EXTENSION_FUNCTIONS;
-use crate::{Error, SurfaceAccess, SurfaceID, SurfaceInfo, SurfaceType};
+use crate::platform::generic::egl::ffi::{EGL_IMAGE_PRESERVED_KHR, EGL_NO_IMAGE_KHR};
+use crate::platform::generic::egl::surface::bind_egl_image_to_gl_texture;
+use crate::platform::generic::egl::surface::set_swap_behavior;
+use crate::platform::generic::egl::surface::swap_buffers_with_damage;
+use crate::platform::generic::egl::surface::swap_buffers_with_viewport;
+use crate::platform::generic::egl::surface::window_surface_attributes;
+use crate::platform::generic::egl::surface::{query_buffer_age, set_damage_region};
+use crate::platform::windows::monitors;
+use crate::surface::{estimate_memory_usage, SurfaceLayoutRequirements};
+use crate::{
+    Colorspace, ContextAttributeFlags, CreateSurfacesResult, Error, PresentationStatistics,
+    SurfaceAccess, SurfaceAlphaMode, SurfaceFormat, SurfaceID, SurfaceInfo, SurfaceOrigin,
+    SurfaceTransform, SurfaceType, SurfaceUsage, SurfaceVisibility, WidgetOutputInfo,
+};
 
-use euclid::default::Size2D;
+use euclid::default::{Rect, Size2D};
 use std::fmt::{self, Debug, Formatter};
 use std::marker::PhantomData;
+use std::mem;
 use std::os::raw::c_void;
 use std::ptr;
 use std::thread;
 use winapi::shared::dxgi::IDXGIKeyedMutex;
+use winapi::shared::minwindef::{FALSE, UINT};
+use winapi::shared::windef::HWND;
+use winapi::shared::winerror;
 use winapi::shared::winerror::S_OK;
 use winapi::um::d3d11;
+use winapi::um::d3dcommon::WKPDID_D3DDebugObjectName;
 use winapi::um::handleapi::INVALID_HANDLE_VALUE;
 use winapi::um::winbase::INFINITE;
 use winapi::um::winnt::HANDLE;
+use winapi::um::winuser;
+use winapi::Interface;
 use wio::com::ComPtr;
 
 const SURFACE_GL_TEXTURE_TARGET: GLenum = gl::TEXTURE_2D;
@@ -51,11 +78,20 @@ const SURFACE_GL_TEXTURE_TARGET: GLenum = gl::TEXTURE_2D;
 ///
 /// Surfaces must be destroyed with the `destroy_surface()` method, or a panic will occur.
 pub struct Surface {
+    pub(crate) id: SurfaceID,
     pub(crate) egl_surface: EGLSurface,
     pub(crate) size: Size2D<i32>,
     pub(crate) context_id: ContextID,
     pub(crate) context_descriptor: ContextDescriptor,
     pub(crate) win32_objects: Win32Objects,
+    // Which way up the contents are. See `SurfaceInfo::origin`.
+    pub(crate) origin: SurfaceOrigin,
+    // How the alpha channel is interpreted. See `SurfaceInfo::alpha_mode`.
+    pub(crate) alpha_mode: SurfaceAlphaMode,
+    // The color space of the contents. See `SurfaceInfo::colorspace`.
+    pub(crate) colorspace: Colorspace,
+    // The number of samples per pixel. See `SurfaceInfo::samples`.
+    pub(crate) samples: u32,
 }
 
 /// Represents an OpenGL texture that wraps a surface.
@@ -70,6 +106,7 @@ pub struct Surface {
 pub struct SurfaceTexture {
     pub(crate) surface: Surface,
     pub(crate) local_egl_surface: EGLSurface,
+    pub(crate) local_egl_image: EGLImageKHR,
     pub(crate) local_keyed_mutex: Option<ComPtr<IDXGIKeyedMutex>>,
     pub(crate) gl_texture: GLuint,
     pub(crate) phantom: PhantomData<*const ()>,
@@ -98,7 +135,9 @@ impl Debug for SurfaceTexture {
 }
 
 pub(crate) enum Win32Objects {
-    Window,
+    Window {
+        window_handle: HWND,
+    },
     Pbuffer {
         share_handle: HANDLE,
         synchronization: Synchronization,
@@ -127,18 +166,62 @@ impl Device {
     ///
     /// Only the given context may ever render to the surface, but generic surfaces can be wrapped
     /// up in a `SurfaceTexture` for reading by other contexts.
-    pub fn create_surface(
+    ///
+    /// Opaque widget surfaces ask DWM to ignore their alpha channel where ANGLE supports
+    /// `EGL_EXT_present_opaque`. Widget surfaces with straight alpha are unsupported.
+    ///
+    /// Premultiplied widget surfaces of contexts with an alpha channel are presented through
+    /// DirectComposition where ANGLE supports it, so that translucent pixels show what is behind
+    /// the window. The window must be created with `WS_EX_NOREDIRECTIONBITMAP` for that to be the
+    /// desktop rather than the window's own background.
+    pub(crate) fn create_surface_impl(
         &mut self,
         context: &Context,
         _: SurfaceAccess,
+        _: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
         surface_type: SurfaceType<NativeWidget>,
     ) -> Result<Surface, Error> {
-        match surface_type {
-            SurfaceType::Generic { ref size } => self.create_pbuffer_surface(context, size, None),
+        let mut surface = match surface_type {
+            SurfaceType::Generic { ref size } => {
+                self.create_pbuffer_surface(context, size, None)?
+            }
+            // DWM only composites premultiplied contents.
+            SurfaceType::Widget { .. } if surface_alpha_mode == SurfaceAlphaMode::Straight => {
+                return Err(Error::UnsupportedOnThisPlatform)
+            }
             SurfaceType::Widget { ref native_widget } => {
-                self.create_window_surface(context, native_widget)
+                self.create_window_surface(context, native_widget, surface_alpha_mode)?
+            }
+        };
+        surface.alpha_mode = surface_alpha_mode;
+        Ok(surface)
+    }
+
+    /// Creates `count` generic surfaces of the given size.
+    ///
+    /// If one of the surfaces fails to be created, the error is returned along with the surfaces
+    /// that were created before it.
+    pub(crate) fn create_surfaces_impl(
+        &mut self,
+        context: &Context,
+        _: SurfaceAccess,
+        _: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
+        size: &Size2D<i32>,
+        count: usize,
+    ) -> CreateSurfacesResult<Surface> {
+        let mut surfaces = Vec::with_capacity(count);
+        for _ in 0..count {
+            match self.create_pbuffer_surface(context, size, None) {
+                Ok(mut surface) => {
+                    surface.alpha_mode = surface_alpha_mode;
+                    surfaces.push(surface)
+                }
+                Err(err) => return Err((err, surfaces)),
             }
         }
+        Ok(surfaces)
     }
 
     #[allow(non_snake_case)]
@@ -152,6 +235,14 @@ impl Device {
         let egl_config = self.context_descriptor_to_egl_config(&context_descriptor);
 
         unsafe {
+            // Only widget surfaces are multisampled, so that surface textures and share handles
+            // always hold resolved pixels.
+            let egl_config = context::single_sampled_config(
+                self.egl_display,
+                egl_config,
+                &PBUFFER_CONFIG_ATTRIBUTES,
+            )?;
+
             let attributes = [
                 egl::WIDTH as EGLint,
                 size.width as EGLint,
@@ -221,6 +312,7 @@ impl Device {
                 };
 
                 Ok(Surface {
+                    id: SurfaceID::next(),
                     egl_surface,
                     size: *size,
                     context_id: context.id,
@@ -230,6 +322,10 @@ impl Device {
                         synchronization,
                         texture,
                     },
+                    origin: SurfaceOrigin::BottomLeft,
+                    alpha_mode: SurfaceAlphaMode::Premultiplied,
+                    colorspace: Colorspace::SRGB,
+                    samples: get_config_samples(self.egl_display, egl_config),
                 })
             })
         }
@@ -237,26 +333,51 @@ impl Device {
 
     /// Given a D3D11 texture, create a surface that wraps that texture. This method is unsafe
     /// in that the resulting surface is only valid on the current thread.
-    pub unsafe fn create_surface_from_texture(
+    ///
+    /// This used to be called `create_surface_from_texture()`. That name now belongs to the
+    /// method that wraps an OpenGL texture, as it does on every backend, so it can't be kept as
+    /// an alias.
+    #[doc(alias = "create_surface_from_texture")]
+    pub unsafe fn create_surface_from_d3d11_texture(
         &mut self,
         context: &Context,
         size: &Size2D<i32>,
         texture: ComPtr<d3d11::ID3D11Texture2D>,
     ) -> Result<Surface, Error> {
-        self.create_pbuffer_surface(context, size, Some(texture))
+        let surface = self.create_pbuffer_surface(context, size, Some(texture))?;
+        self.note_surface_created(&surface);
+        Ok(surface)
     }
 
     fn create_window_surface(
         &mut self,
         context: &Context,
         native_widget: &NativeWidget,
+        alpha_mode: SurfaceAlphaMode,
     ) -> Result<Surface, Error> {
         let context_descriptor = self.context_descriptor(context);
         let egl_config = self.context_descriptor_to_egl_config(&context_descriptor);
 
+        let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+
         unsafe {
+            // DirectComposition blends the swap chain with what is behind the window, while DWM
+            // treats a window's redirection surface as opaque.
+            let mut attributes = vec![];
+            if alpha_mode == SurfaceAlphaMode::Premultiplied
+                && context_attributes
+                    .flags
+                    .contains(ContextAttributeFlags::ALPHA)
+                && display_has_extension(self.egl_display, b"EGL_ANGLE_direct_composition")
+            {
+                attributes.extend_from_slice(&[
+                    EGL_DIRECT_COMPOSITION_ANGLE as EGLint,
+                    egl::TRUE as EGLint,
+                ]);
+            }
+            attributes.extend(window_surface_attributes(self.egl_display, alpha_mode));
+
             EGL_FUNCTIONS.with(|egl| {
-                let attributes = [egl::NONE as EGLint];
                 let egl_surface = egl.CreateWindowSurface(
                     self.egl_display,
                     egl_config,
@@ -283,16 +404,51 @@ impl Device {
                 assert_ne!(height, 0);
 
                 Ok(Surface {
+                    id: SurfaceID::next(),
                     egl_surface,
                     size: Size2D::new(width, height),
                     context_id: context.id,
                     context_descriptor,
-                    win32_objects: Win32Objects::Window,
+                    win32_objects: Win32Objects::Window {
+                        window_handle: native_widget.egl_native_window as HWND,
+                    },
+                    origin: SurfaceOrigin::BottomLeft,
+                    alpha_mode,
+                    colorspace: Colorspace::SRGB,
+                    samples: get_config_samples(self.egl_display, egl_config),
                 })
             })
         }
     }
 
+    /// Creates a new generic surface with a copy of the contents of the given one, stored with
+    /// its color channels in the given order.
+    ///
+    /// This is not yet supported on ANGLE, so it always returns `UnsupportedOnThisPlatform`.
+    #[inline]
+    pub(crate) fn convert_surface_format_impl(
+        &mut self,
+        _: &mut Context,
+        _: &Surface,
+        _: SurfaceFormat,
+    ) -> Result<Surface, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Wraps an existing OpenGL texture of the given context in a new generic surface.
+    ///
+    /// Generic surfaces on this backend are pbuffers, which can't be made from a texture, so this
+    /// always returns an `UnsupportedOnThisPlatform` error.
+    #[inline]
+    pub(crate) fn create_surface_from_texture_impl(
+        &mut self,
+        _: &mut Context,
+        _: &Size2D<i32>,
+        _: GLuint,
+    ) -> Result<Surface, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
     /// Creates a surface texture from an existing generic surface for use with the given context.
     ///
     /// The surface texture is local to the supplied context and takes ownership of the surface.
@@ -302,6 +458,10 @@ impl Device {
     /// with.* This allows you to render to a surface in one context and sample from that surface
     /// in another context.
     ///
+    /// If ANGLE supports `EGL_ANGLE_image_d3d11_texture`, the surface's Direct3D 11 texture is
+    /// wrapped in an EGL image and the surface texture is bound to that image. Otherwise, the
+    /// surface is imported through its share handle as a pbuffer local to the context.
+    ///
     /// Calling this method on a widget surface returns a `WidgetAttached` error.
     #[allow(non_snake_case)]
     pub fn create_surface_texture(
@@ -310,10 +470,16 @@ impl Device {
         surface: Surface,
     ) -> Result<SurfaceTexture, (Error, Surface)> {
         let share_handle = match surface.win32_objects {
-            Win32Objects::Window => return Err((Error::WidgetAttached, surface)),
+            Win32Objects::Window { .. } => return Err((Error::WidgetAttached, surface)),
             Win32Objects::Pbuffer { share_handle, .. } => share_handle,
         };
 
+        unsafe {
+            if display_has_extension(self.egl_display, b"EGL_ANGLE_image_d3d11_texture") {
+                return self.create_surface_texture_from_egl_image(context, surface);
+            }
+        }
+
         let local_egl_config = self.context_descriptor_to_egl_config(&surface.context_descriptor);
         EGL_FUNCTIONS.with(|egl| {
             unsafe {
@@ -375,6 +541,70 @@ impl Device {
         })
     }
 
+    // Binds a new texture to an EGL image wrapping the surface's Direct3D 11 texture. This doesn't
+    // depend on pbuffers, so it works the same way whichever renderer ANGLE is using.
+    unsafe fn create_surface_texture_from_egl_image(
+        &self,
+        context: &Context,
+        surface: Surface,
+    ) -> Result<SurfaceTexture, (Error, Surface)> {
+        let d3d11_texture = match self.open_surface_d3d11_texture(&surface) {
+            Ok(d3d11_texture) => d3d11_texture,
+            Err(err) => return Err((err, surface)),
+        };
+
+        // Surfaces that are shared through a keyed mutex must be read with the mutex held, just
+        // as on the pbuffer path.
+        let local_keyed_mutex = match surface.win32_objects {
+            Win32Objects::Pbuffer {
+                synchronization: Synchronization::KeyedMutex(_),
+                ..
+            } => match d3d11_texture.cast::<IDXGIKeyedMutex>() {
+                Ok(local_keyed_mutex) => {
+                    let result = local_keyed_mutex.AcquireSync(0, INFINITE);
+                    assert_eq!(result, S_OK);
+                    Some(local_keyed_mutex)
+                }
+                Err(_) => None,
+            },
+            _ => None,
+        };
+
+        let egl_image_attributes = [
+            EGL_IMAGE_PRESERVED_KHR as EGLint,
+            egl::TRUE as EGLint,
+            egl::NONE as EGLint,
+            0,
+        ];
+        let egl_image = (EGL_EXTENSION_FUNCTIONS.CreateImageKHR)(
+            self.egl_display,
+            egl::NO_CONTEXT,
+            EGL_D3D11_TEXTURE_ANGLE,
+            d3d11_texture.as_raw() as *mut _,
+            egl_image_attributes.as_ptr(),
+        );
+        if egl_image == EGL_NO_IMAGE_KHR {
+            let windowing_api_error =
+                EGL_FUNCTIONS.with(|egl| egl.GetError().to_windowing_api_error());
+            if let Some(ref local_keyed_mutex) = local_keyed_mutex {
+                local_keyed_mutex.ReleaseSync(0);
+            }
+            return Err((Error::SurfaceImportFailed(windowing_api_error), surface));
+        }
+
+        let _guard = self.temporarily_make_context_current(context);
+        let gl_texture = GL_FUNCTIONS.with(|gl| bind_egl_image_to_gl_texture(gl, egl_image));
+
+        Ok(SurfaceTexture {
+            surface,
+            local_egl_surface: egl::NO_SURFACE,
+            local_egl_image: egl_image,
+            local_keyed_mutex,
+            gl_texture,
+            phantom: PhantomData,
+        })
+    }
+
     fn create_surface_texture_from_local_surface(
         &self,
         context: &Context,
@@ -392,7 +622,12 @@ impl Device {
                     gl.GenTextures(1, &mut texture);
                     debug_assert_ne!(texture, 0);
 
-                    gl.BindTexture(gl::TEXTURE_2D, texture);
+                    let mut bindings = gl_utils::BindingsGuard::for_texture(
+                        gl,
+                        gl::TEXTURE_2D,
+                        gl::TEXTURE_BINDING_2D,
+                    );
+                    bindings.bind_texture(texture);
                     if egl.BindTexImage(
                         self.egl_display,
                         local_egl_surface,
@@ -420,12 +655,12 @@ impl Device {
                         gl::CLAMP_TO_EDGE as GLint,
                     );
 
-                    gl.BindTexture(gl::TEXTURE_2D, 0);
                     debug_assert_eq!(gl.GetError(), gl::NO_ERROR);
 
                     Ok(SurfaceTexture {
                         surface,
                         local_egl_surface,
+                        local_egl_image: EGL_NO_IMAGE_KHR,
                         local_keyed_mutex,
                         gl_texture: texture,
                         phantom: PhantomData,
@@ -448,11 +683,56 @@ impl Device {
         let local_egl_surface = surface.egl_surface;
         self.create_surface_texture_from_local_surface(context, surface, local_egl_surface, None)
             .map_err(|(err, mut surface)| {
-                let _ = self.destroy_surface(context, &mut surface);
+                let _ = self.destroy_surface_impl(context, &mut surface);
                 err
             })
     }
 
+    fn open_surface_d3d11_texture(
+        &self,
+        surface: &Surface,
+    ) -> Result<ComPtr<d3d11::ID3D11Texture2D>, Error> {
+        let share_handle = match surface.win32_objects {
+            Win32Objects::Pbuffer {
+                texture: Some(ref texture),
+                ..
+            } => return Ok(texture.clone()),
+            Win32Objects::Pbuffer { share_handle, .. } => share_handle,
+            Win32Objects::Window { .. } => return Err(Error::WidgetAttached),
+        };
+        if share_handle == INVALID_HANDLE_VALUE || share_handle.is_null() {
+            return Err(Error::Failed);
+        }
+
+        unsafe {
+            let mut texture = ptr::null_mut();
+            let result = self.d3d11_device.OpenSharedResource(
+                share_handle,
+                &d3d11::ID3D11Texture2D::uuidof(),
+                &mut texture,
+            );
+            if !winerror::SUCCEEDED(result) || texture.is_null() {
+                return Err(Error::Failed);
+            }
+            Ok(ComPtr::from_raw(texture as *mut d3d11::ID3D11Texture2D))
+        }
+    }
+
+    // Names the share texture behind a generic surface with `WKPDID_D3DDebugObjectName`, so that
+    // Direct3D captures show the surface's label on it. Widget surfaces have no texture of their
+    // own.
+    pub(crate) fn set_surface_native_label(&self, surface: &Surface, label: &str) {
+        if let Ok(texture) = self.open_surface_d3d11_texture(surface) {
+            unsafe {
+                texture.SetPrivateData(
+                    &WKPDID_D3DDebugObjectName,
+                    label.len() as UINT,
+                    label.as_ptr() as *const c_void,
+                );
+            }
+        }
+    }
+
     /// Destroys a surface.
     ///
     /// The supplied context must be the context the surface is associated with, or this returns
@@ -460,9 +740,9 @@ impl Device {
     ///
     /// You must explicitly call this method to dispose of a surface. Otherwise, a panic occurs in
     /// the `drop` method.
-    pub fn destroy_surface(
+    pub(crate) fn destroy_surface_impl(
         &self,
-        context: &mut Context,
+        context: &Context,
         surface: &mut Surface,
     ) -> Result<(), Error> {
         if context.id != surface.context_id {
@@ -512,14 +792,35 @@ impl Device {
                 assert_eq!(result, S_OK);
             }
 
-            EGL_FUNCTIONS.with(|egl| {
-                egl.DestroySurface(self.egl_display, surface_texture.local_egl_surface);
-            })
+            if surface_texture.local_egl_image != EGL_NO_IMAGE_KHR {
+                (EGL_EXTENSION_FUNCTIONS.DestroyImageKHR)(
+                    self.egl_display,
+                    surface_texture.local_egl_image,
+                );
+                surface_texture.local_egl_image = EGL_NO_IMAGE_KHR;
+            }
+
+            if surface_texture.local_egl_surface != egl::NO_SURFACE {
+                EGL_FUNCTIONS.with(|egl| {
+                    egl.DestroySurface(self.egl_display, surface_texture.local_egl_surface);
+                })
+            }
         }
 
         Ok(surface_texture.surface)
     }
 
+    /// Destroys the native resources of a surface given up with `surface_into_destroy_token()`.
+    ///
+    /// Unlike `destroy_surface()`, this doesn't need the context that the surface belonged to.
+    pub fn destroy_token(&self, token: SurfaceDestroyToken) -> Result<(), Error> {
+        EGL_FUNCTIONS.with(|egl| unsafe {
+            // A surface that's still current is destroyed once it's made non-current.
+            egl.DestroySurface(self.egl_display, token.egl_surface);
+        });
+        Ok(())
+    }
+
     /// Returns the OpenGL texture target needed to read from this surface texture.
     ///
     /// This will be `GL_TEXTURE_2D` or `GL_TEXTURE_RECTANGLE`, depending on platform.
@@ -528,6 +829,18 @@ impl Device {
         SURFACE_GL_TEXTURE_TARGET
     }
 
+    /// Fills in the mip chain of a surface texture from the current contents of its surface.
+    ///
+    /// Surface textures on this backend are bound to pbuffers without mip levels, so this always returns an `UnsupportedOnThisPlatform` error.
+    #[inline]
+    pub fn generate_surface_mipmaps(
+        &self,
+        _: &mut Context,
+        _: &SurfaceTexture,
+    ) -> Result<(), Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
     /// Returns a pointer to the underlying surface data for reading or writing by the CPU.
     #[inline]
     pub fn lock_surface_data<'s>(
@@ -544,21 +857,163 @@ impl Device {
     ///
     /// The supplied context must match the context the surface was created with, or an
     /// `IncompatibleSurface` error is returned.
-    pub fn present_surface(&self, _: &Context, surface: &mut Surface) -> Result<(), Error> {
+    #[inline]
+    pub(crate) fn present_surface_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        self.present_surface_with_damage_impl(context, surface, &[])
+    }
+
+    /// Displays the contents of a widget surface on screen, hinting that only the given
+    /// rectangles have changed since the last present.
+    ///
+    /// The rectangles are in device pixels, with the origin at the lower left corner of the
+    /// surface. An empty slice means that the whole surface changed. ANGLE forwards the damage to
+    /// `IDXGISwapChain1::Present1()` if it supports `EGL_KHR_swap_buffers_with_damage`.
+    pub(crate) fn present_surface_with_damage_impl(
+        &self,
+        _: &Context,
+        surface: &mut Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
         match surface.win32_objects {
             Win32Objects::Window { .. } => {}
             _ => return Err(Error::NoWidgetAttached),
         }
 
-        EGL_FUNCTIONS.with(|egl| unsafe {
-            let ok = egl.SwapBuffers(self.egl_display, surface.egl_surface);
+        unsafe {
+            let ok = swap_buffers_with_damage(self.egl_display, surface.egl_surface, damage);
             assert_ne!(ok, egl::FALSE);
             Ok(())
+        }
+    }
+
+    /// Displays the `source` rectangle of a widget surface in the `destination` rectangle of its
+    /// widget, scaling it to fit.
+    ///
+    /// The rectangles are in device pixels, with the origin at the lower left corner. The back
+    /// buffer is rescaled in place before it is swapped.
+    pub(crate) fn present_surface_with_viewport_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        source: &Rect<i32>,
+        destination: &Rect<i32>,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        match surface.win32_objects {
+            Win32Objects::Window { .. } => {}
+            _ => return Err(Error::NoWidgetAttached),
+        }
+
+        GL_FUNCTIONS.with(|gl| unsafe {
+            swap_buffers_with_viewport(
+                gl,
+                self.egl_display,
+                surface.egl_surface,
+                context.egl_context,
+                source,
+                destination,
+            )
         })
     }
 
+    /// Declares which parts of a widget surface will be redrawn before it is next presented.
+    ///
+    /// This must be called while the surface is bound to the current context, before any
+    /// rendering to the frame. It does nothing unless ANGLE supports `EGL_KHR_partial_update`.
+    pub fn set_surface_damage_region(
+        &self,
+        _: &Context,
+        surface: &mut Surface,
+        region: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        match surface.win32_objects {
+            Win32Objects::Window { .. } => unsafe {
+                set_damage_region(self.egl_display, surface.egl_surface, region)
+            },
+            _ => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Returns the number of presents ago that the current back buffer of a widget surface was
+    /// displayed, or 0 if its contents are undefined.
+    ///
+    /// The surface must be bound to the current context.
+    pub fn surface_buffer_age(&self, _: &Context, surface: &Surface) -> Result<u32, Error> {
+        match surface.win32_objects {
+            Win32Objects::Window { .. } => unsafe {
+                Ok(query_buffer_age(self.egl_display, surface.egl_surface))
+            },
+            _ => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Sets whether presenting a widget surface preserves its contents.
+    ///
+    /// Widget surfaces are created without preservation, so their contents are undefined after
+    /// `present_surface()`. With it, applications that only repaint the dirty regions of each
+    /// frame can draw over the previous one, at the cost of a copy per present on some hardware.
+    ///
+    /// If the config of the context doesn't allow preservation, this returns
+    /// `Error::UnsupportedOnThisPlatform`.
+    pub fn set_surface_preserve_buffer(
+        &self,
+        _: &Context,
+        surface: &mut Surface,
+        preserve: bool,
+    ) -> Result<(), Error> {
+        match surface.win32_objects {
+            Win32Objects::Window { .. } => unsafe {
+                set_swap_behavior(self.egl_display, surface.egl_surface, preserve)
+            },
+            _ => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Sets which way up the contents of a surface are.
+    ///
+    /// Widget surfaces can only be bottom-left, and setting `SurfaceOrigin::TopLeft` on them
+    /// returns `Error::UnsupportedOnThisPlatform`.
+    pub fn set_surface_origin(
+        &self,
+        surface: &mut Surface,
+        origin: SurfaceOrigin,
+    ) -> Result<(), Error> {
+        if let Win32Objects::Window { .. } = surface.win32_objects {
+            if origin == SurfaceOrigin::TopLeft {
+                return Err(Error::UnsupportedOnThisPlatform);
+            }
+        }
+        surface.origin = origin;
+        Ok(())
+    }
+
+    /// Sets the color space that the contents of a surface are in.
+    ///
+    /// ANGLE doesn't expose the DXGI swap chain of a window, so its color space can't be changed,
+    /// and setting any color space but `SRGB` on a widget surface returns
+    /// `Error::UnsupportedOnThisPlatform`.
+    pub fn set_surface_colorspace(
+        &self,
+        surface: &mut Surface,
+        colorspace: Colorspace,
+    ) -> Result<(), Error> {
+        if let Win32Objects::Window { .. } = surface.win32_objects {
+            if colorspace != Colorspace::SRGB {
+                return Err(Error::UnsupportedOnThisPlatform);
+            }
+        }
+        surface.colorspace = colorspace;
+        Ok(())
+    }
+
     /// Resizes a widget surface.
-    pub fn resize_surface(
+    pub(crate) fn resize_surface_impl(
         &self,
         _context: &Context,
         surface: &mut Surface,
@@ -581,6 +1036,84 @@ impl Device {
             id: surface.id(),
             context_id: surface.context_id,
             framebuffer_object: 0,
+            scale_factor: 1.0,
+            transform: SurfaceTransform::Identity,
+            origin: surface.origin,
+            // ANGLE backs both pbuffers and windows with B8G8R8A8 Direct3D textures.
+            format: SurfaceFormat::BGRA8,
+            alpha_mode: surface.alpha_mode,
+            colorspace: surface.colorspace,
+            samples: surface.samples,
+            bound: false,
+        }
+    }
+
+    /// Returns the GPU memory, in bytes, held by the given surface.
+    ///
+    /// Direct3D doesn't report the size of the underlying texture allocation, so this is an
+    /// estimate that counts four bytes per pixel of color storage.
+    #[inline]
+    pub fn surface_memory_usage(&self, surface: &Surface) -> usize {
+        estimate_memory_usage(&self.surface_info(surface).size)
+    }
+
+    /// Returns `Error::UnsupportedOnThisPlatform`, as Direct3D textures have no modification
+    /// counter.
+    #[inline]
+    pub fn surface_seed(&self, _: &Surface) -> Result<u32, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Returns the stride, row alignment, and total size of the memory that a generic surface of
+    /// the given size would be allocated with, without creating one.
+    ///
+    /// Direct3D keeps the row pitch of its textures to itself until they're mapped, so this is
+    /// the tightly packed layout that readbacks use.
+    #[inline]
+    pub fn surface_layout_requirements(
+        &self,
+        size: &Size2D<i32>,
+        _: SurfaceFormat,
+        _: SurfaceAccess,
+    ) -> Result<SurfaceLayoutRequirements, Error> {
+        Ok(SurfaceLayoutRequirements::packed(size))
+    }
+
+    /// Returns timing information about the most recently displayed frame of a widget surface.
+    ///
+    /// Presentation timing isn't available on this backend, so this always returns `None`.
+    #[inline]
+    pub fn surface_presentation_statistics(&self, _: &Surface) -> Option<PresentationStatistics> {
+        None
+    }
+
+    /// Returns whether a widget surface can currently be seen on screen.
+    ///
+    /// ANGLE doesn't expose the occlusion status of its DXGI swap chain, so this only reports
+    /// `Occluded` for windows that are hidden or minimized.
+    pub fn surface_visibility(&self, surface: &Surface) -> SurfaceVisibility {
+        match surface.win32_objects {
+            Win32Objects::Window { window_handle } => unsafe {
+                if winuser::IsWindowVisible(window_handle) == FALSE
+                    || winuser::IsIconic(window_handle) != FALSE
+                {
+                    SurfaceVisibility::Occluded
+                } else {
+                    SurfaceVisibility::Visible
+                }
+            },
+            Win32Objects::Pbuffer { .. } => SurfaceVisibility::Unknown,
+        }
+    }
+
+    /// Returns the monitor that a widget surface is shown on, and its bit depth and color space
+    /// as reported by DXGI.
+    pub fn widget_output_info(&self, surface: &Surface) -> Result<WidgetOutputInfo, Error> {
+        match surface.win32_objects {
+            Win32Objects::Window { window_handle } => {
+                Ok(monitors::window_output_info(window_handle))
+            }
+            Win32Objects::Pbuffer { .. } => Err(Error::NoWidgetAttached),
         }
     }
 
@@ -596,7 +1129,22 @@ impl Device {
 impl Surface {
     #[inline]
     fn id(&self) -> SurfaceID {
-        SurfaceID(self.egl_surface as usize)
+        self.id
+    }
+
+    /// Gives up this surface, returning a token that can be destroyed later with
+    /// `Device::destroy_token()`, on any thread and without the surface's context.
+    ///
+    /// On this backend, surfaces are EGL surfaces, which don't belong to any context, so nothing
+    /// is left behind.
+    pub(crate) fn into_destroy_token(mut self) -> SurfaceDestroyToken {
+        let placeholder = Win32Objects::Window {
+            window_handle: ptr::null_mut(),
+        };
+        SurfaceDestroyToken {
+            egl_surface: mem::replace(&mut self.egl_surface, egl::NO_SURFACE),
+            _win32_objects: mem::replace(&mut self.win32_objects, placeholder),
+        }
     }
 
     #[inline]
@@ -611,7 +1159,46 @@ impl Surface {
     }
 }
 
+/// The native resources of a surface given up with `Device::surface_into_destroy_token()`, which
+/// can be destroyed later without the surface's context.
+///
+/// Tokens can be sent to other threads, and must be destroyed with `Device::destroy_token()`, or
+/// their resources leak.
+pub struct SurfaceDestroyToken {
+    egl_surface: EGLSurface,
+    // Keeps the Direct3D texture of a generic surface alive until the token is destroyed.
+    _win32_objects: Win32Objects,
+}
+
+unsafe impl Send for SurfaceDestroyToken {}
+
 /// Represents the CPU view of the pixel data of this surface.
 pub struct SurfaceDataGuard<'a> {
     phantom: PhantomData<&'a ()>,
 }
+
+#[cfg(feature = "sm-opencl")]
+impl Device {
+    /// Returns `Error::UnsupportedOnThisPlatform`, as ANGLE surfaces have no EGL image.
+    #[inline]
+    pub fn surface_egl_image(&self, _: &Surface) -> Result<SharedEGLImage, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+}
+
+#[cfg(feature = "sm-ffmpeg")]
+impl Device {
+    /// Returns the Direct3D 11 texture backing the given surface, for wrapping in an FFmpeg
+    /// D3D11VA frame.
+    ///
+    /// The texture belongs to the Direct3D device that ANGLE renders with, which FFmpeg's D3D11VA
+    /// device context must be created with. Widget surfaces have no texture; this returns
+    /// `Error::WidgetAttached` for them.
+    #[inline]
+    pub fn surface_d3d11_texture(
+        &self,
+        surface: &Surface,
+    ) -> Result<ComPtr<d3d11::ID3D11Texture2D>, Error> {
+        self.open_surface_d3d11_texture(surface)
+    }
+}
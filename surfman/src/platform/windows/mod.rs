@@ -7,3 +7,6 @@ pub mod angle;
 
 #[cfg(not(feature = "sm-no-wgl"))]
 pub mod wgl;
+
+#[cfg(any(feature = "sm-angle", not(feature = "sm-no-wgl")))]
+mod monitors;
@@ -0,0 +1,165 @@
+// surfman/surfman/src/platform/windows/monitors.rs
+//
+//! Display enumeration through the Win32 monitor APIs, shared by the WGL and ANGLE backends.
+
+use crate::{Colorspace, DisplayInfo, Error, WidgetOutputInfo};
+
+use euclid::default::{Point2D, Size2D};
+use std::mem;
+use std::os::raw::c_void;
+use std::ptr;
+use winapi::shared::dxgi::{self, IDXGIFactory1};
+use winapi::shared::dxgi1_6::{IDXGIOutput6, DXGI_OUTPUT_DESC1};
+use winapi::shared::dxgitype::DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020;
+use winapi::shared::minwindef::{BOOL, LPARAM, TRUE};
+use winapi::shared::windef::{HDC, HMONITOR, HWND, LPRECT};
+use winapi::shared::winerror;
+use winapi::um::wingdi::DEVMODEW;
+use winapi::um::winuser::{self, ENUM_CURRENT_SETTINGS, MONITORINFOEXW, MONITOR_DEFAULTTONEAREST};
+use winapi::Interface;
+use wio::com::ComPtr;
+
+/// Lists the monitors attached to the desktop.
+///
+/// Windows scales content per window rather than per monitor, and doesn't report a monitor's
+/// color space through these APIs, so those are reported as `1.0` and `None` respectively.
+pub(crate) fn displays() -> Result<Vec<DisplayInfo>, Error> {
+    let mut monitors: Vec<HMONITOR> = vec![];
+    unsafe {
+        let ok = winuser::EnumDisplayMonitors(
+            ptr::null_mut(),
+            ptr::null(),
+            Some(collect_monitor),
+            &mut monitors as *mut Vec<HMONITOR> as LPARAM,
+        );
+        if ok == 0 {
+            return Err(Error::Failed);
+        }
+    }
+
+    Ok(monitors.into_iter().filter_map(monitor_info).collect())
+}
+
+/// Returns what a window is shown on: the monitor that holds most of it, and what DXGI reports
+/// about that monitor.
+///
+/// The bit depth and color space come from `IDXGIOutput6`, which is available from Windows 10
+/// version 1703 on. On older systems, or for monitors that no DXGI adapter drives, the monitor is
+/// described as 8-bit sRGB without HDR. DXGI only reports a wide-gamut color space while HDR is
+/// turned on for the monitor, so HDR monitors are reported as `BT2020`.
+pub(crate) fn window_output_info(window_handle: HWND) -> WidgetOutputInfo {
+    unsafe {
+        let monitor = winuser::MonitorFromWindow(window_handle, MONITOR_DEFAULTTONEAREST);
+        if monitor.is_null() {
+            return WidgetOutputInfo::unknown(None);
+        }
+
+        let mut output_info = WidgetOutputInfo::unknown(Some(monitor as usize as u64));
+        if let Some(output_desc) = dxgi_output_desc(monitor) {
+            if output_desc.BitsPerColor > 0 {
+                output_info.bits_per_component = output_desc.BitsPerColor;
+            }
+            if output_desc.ColorSpace == DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020 {
+                output_info.colorspace = Colorspace::BT2020;
+                output_info.hdr = true;
+            }
+        }
+        output_info
+    }
+}
+
+// Finds the DXGI output that drives a monitor, and describes it.
+unsafe fn dxgi_output_desc(monitor: HMONITOR) -> Option<DXGI_OUTPUT_DESC1> {
+    let mut dxgi_factory: *mut IDXGIFactory1 = ptr::null_mut();
+    let result = dxgi::CreateDXGIFactory1(
+        &IDXGIFactory1::uuidof(),
+        &mut dxgi_factory as *mut *mut IDXGIFactory1 as *mut *mut c_void,
+    );
+    if !winerror::SUCCEEDED(result) {
+        return None;
+    }
+    let dxgi_factory = ComPtr::from_raw(dxgi_factory);
+
+    let mut adapter_index = 0;
+    loop {
+        let mut dxgi_adapter = ptr::null_mut();
+        if !winerror::SUCCEEDED(dxgi_factory.EnumAdapters1(adapter_index, &mut dxgi_adapter)) {
+            return None;
+        }
+        let dxgi_adapter = ComPtr::from_raw(dxgi_adapter);
+
+        let mut output_index = 0;
+        loop {
+            let mut dxgi_output = ptr::null_mut();
+            if !winerror::SUCCEEDED(dxgi_adapter.EnumOutputs(output_index, &mut dxgi_output)) {
+                break;
+            }
+            let dxgi_output = ComPtr::from_raw(dxgi_output);
+            if let Ok(dxgi_output_6) = dxgi_output.cast::<IDXGIOutput6>() {
+                let mut output_desc: DXGI_OUTPUT_DESC1 = mem::zeroed();
+                if winerror::SUCCEEDED(dxgi_output_6.GetDesc1(&mut output_desc))
+                    && output_desc.Monitor == monitor
+                {
+                    return Some(output_desc);
+                }
+            }
+            output_index += 1;
+        }
+        adapter_index += 1;
+    }
+}
+
+unsafe extern "system" fn collect_monitor(
+    monitor: HMONITOR,
+    _: HDC,
+    _: LPRECT,
+    data: LPARAM,
+) -> BOOL {
+    (*(data as *mut Vec<HMONITOR>)).push(monitor);
+    TRUE
+}
+
+fn monitor_info(monitor: HMONITOR) -> Option<DisplayInfo> {
+    unsafe {
+        let mut monitor_info: MONITORINFOEXW = mem::zeroed();
+        monitor_info.cbSize = mem::size_of::<MONITORINFOEXW>() as u32;
+        if winuser::GetMonitorInfoW(monitor, &mut monitor_info as *mut MONITORINFOEXW as *mut _)
+            == 0
+        {
+            return None;
+        }
+
+        let name_length = monitor_info
+            .szDevice
+            .iter()
+            .position(|&character| character == 0)
+            .unwrap_or(monitor_info.szDevice.len());
+        let name = String::from_utf16_lossy(&monitor_info.szDevice[..name_length]);
+
+        let mut dev_mode: DEVMODEW = mem::zeroed();
+        dev_mode.dmSize = mem::size_of::<DEVMODEW>() as u16;
+        let refresh_rate = if winuser::EnumDisplaySettingsW(
+            monitor_info.szDevice.as_ptr(),
+            ENUM_CURRENT_SETTINGS,
+            &mut dev_mode,
+        ) != 0
+            && dev_mode.dmDisplayFrequency > 1
+        {
+            // Frequencies of 0 and 1 mean "the hardware's default".
+            Some(dev_mode.dmDisplayFrequency as f64)
+        } else {
+            None
+        };
+
+        let rect = monitor_info.rcMonitor;
+        Some(DisplayInfo {
+            id: monitor as usize as u64,
+            name,
+            origin: Point2D::new(rect.left, rect.top),
+            size: Size2D::new(rect.right - rect.left, rect.bottom - rect.top),
+            refresh_rate,
+            scale_factor: 1.0,
+            colorspace: None,
+        })
+    }
+}
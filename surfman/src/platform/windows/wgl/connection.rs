@@ -2,12 +2,15 @@
 //
 //! A connection to the window server.
 //!
-//! Window server connections are implicit in the Win32 API, so this is a zero-sized type.
+//! Window server connections are implicit in the Win32 API, so connections only hold surfman's own
+//! state.
 
 use super::device::{Adapter, Device, NativeDevice};
 use super::surface::NativeWidget;
-use crate::Error;
+use crate::context::ContextIDAllocator;
+use crate::platform::windows::monitors;
 use crate::GLApi;
+use crate::{DisplayInfo, Error, IpcDescriptor, NativeDisplayHandle, NativeWidgetHandle};
 
 use euclid::default::Size2D;
 
@@ -22,9 +25,12 @@ use winit::window::Window;
 
 /// Represents a connection to the display server.
 ///
-/// Window server connections are implicit in the Win32 API, so this is a zero-sized type.
+/// Window server connections are implicit in the Win32 API, so this only holds surfman's own
+/// state, such as the counter that context IDs are allocated from.
 #[derive(Clone)]
-pub struct Connection;
+pub struct Connection {
+    pub(crate) context_ids: ContextIDAllocator,
+}
 
 /// An empty placeholder for native connections.
 ///
@@ -36,10 +42,27 @@ impl Connection {
     /// Connects to the default display.
     #[inline]
     pub fn new() -> Result<Connection, Error> {
-        Ok(Connection)
+        Ok(Connection {
+            context_ids: ContextIDAllocator::new()?,
+        })
+    }
+
+    /// Returns `UnsupportedOnThisPlatform`, because WGL renders on whichever adapter the
+    /// graphics driver picks for the process.
+    ///
+    /// # Safety
+    ///
+    /// The descriptor is never used, so there are no requirements on it.
+    #[inline]
+    pub unsafe fn from_ipc_descriptor(_: IpcDescriptor) -> Result<Connection, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
     }
 
     /// An alias for `Connection::new()`, present for consistency with other backends.
+    ///
+    /// # Safety
+    ///
+    /// The native connection carries no handles, so there are no requirements on it.
     #[inline]
     pub unsafe fn from_native_connection(_: NativeConnection) -> Result<Connection, Error> {
         Connection::new()
@@ -51,12 +74,26 @@ impl Connection {
         NativeConnection
     }
 
+    /// Returns the raw display connection underneath this connection.
+    ///
+    /// Windows has no display connection, so this returns `NativeDisplayHandle::None`.
+    #[inline]
+    pub fn native_display(&self) -> NativeDisplayHandle {
+        NativeDisplayHandle::None
+    }
+
     /// Returns the OpenGL API flavor that this connection supports (OpenGL or OpenGL ES).
     #[inline]
     pub fn gl_api(&self) -> GLApi {
         GLApi::GL
     }
 
+    /// Returns the monitors attached to the desktop.
+    #[inline]
+    pub fn displays(&self) -> Result<Vec<DisplayInfo>, Error> {
+        monitors::displays()
+    }
+
     /// Returns the "best" adapter on this system, preferring high-performance hardware adapters.
     ///
     /// This is an alias for `Connection::create_hardware_adapter()`.
@@ -83,10 +120,20 @@ impl Connection {
         self.create_low_power_adapter()
     }
 
+    /// Returns the adapter for the GPU that drives the given display.
+    ///
+    /// WGL offers no portable way to tie a context to a GPU: `WGL_AMD_gpu_association` can't tell
+    /// which GPU drives a display, and `WGL_NV_gpu_affinity` is limited to workstation cards. So
+    /// this always returns `UnsupportedOnThisPlatform`; use ANGLE to pin rendering to a display.
+    #[inline]
+    pub fn create_adapter_for_display(&self, _: &DisplayInfo) -> Result<Adapter, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
     /// Opens a device.
     #[inline]
-    pub fn create_device(&self, adapter: &Adapter) -> Result<Device, Error> {
-        Device::new(adapter)
+    pub(crate) fn create_device_impl(&self, adapter: &Adapter) -> Result<Device, Error> {
+        Device::new(self, adapter)
     }
 
     /// Creates a `Device` from a Direct3D 11 device and associated GL/DX interop handle.
@@ -97,11 +144,11 @@ impl Connection {
     /// This method increases the reference count on the Direct3D 11 device and takes ownership of
     /// the GL/DX interop handle.
     #[inline]
-    pub unsafe fn create_device_from_native_device(
+    pub(crate) unsafe fn create_device_from_native_device_impl(
         &self,
         native_device: NativeDevice,
     ) -> Result<Device, Error> {
-        Device::from_native_device(native_device)
+        Device::from_native_device(self, native_device)
     }
 
     /// Opens the display connection corresponding to the given `winit` window.
@@ -130,6 +177,11 @@ impl Connection {
     }
 
     /// Create a native widget from a raw pointer
+    ///
+    /// # Safety
+    ///
+    /// The pointer must be a live `HWND`, which must outlive the native widget and any surfaces
+    /// created from it.
     pub unsafe fn create_native_widget_from_ptr(
         &self,
         raw: *mut c_void,
@@ -140,6 +192,26 @@ impl Connection {
         }
     }
 
+    /// Creates a native widget from a handle to a native window or view.
+    ///
+    /// Only `HWND` handles are accepted.
+    ///
+    /// # Safety
+    ///
+    /// The handle must refer to a live window or view, which must outlive the native widget and any
+    /// surfaces created from it.
+    pub unsafe fn create_native_widget_from_handle(
+        &self,
+        handle: NativeWidgetHandle,
+    ) -> Result<NativeWidget, Error> {
+        match handle {
+            NativeWidgetHandle::HWND(window_handle) => Ok(NativeWidget {
+                window_handle: window_handle as HWND,
+            }),
+            _ => Err(Error::IncompatibleNativeWidget),
+        }
+    }
+
     /// Create a native widget type from the given `raw_window_handle::HasRawWindowHandle`.
     #[cfg(feature = "sm-raw-window-handle")]
     pub fn create_native_widget_from_rwh(
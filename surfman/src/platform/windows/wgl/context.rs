@@ -2,16 +2,24 @@
 //
 //! Wrapper for WGL contexts on Windows.
 
+use super::connection::Connection;
 use super::device::{DCGuard, Device, HiddenWindow};
 use super::surface::{Surface, Win32Objects};
-use crate::context::{self, CREATE_CONTEXT_MUTEX};
-use crate::surface::Framebuffer;
-use crate::{ContextAttributeFlags, ContextAttributes, ContextID, Error, GLVersion};
-use crate::{SurfaceInfo, WindowingApiError};
+use crate::context;
+use crate::overrides;
+use crate::surface::{ExternalFramebuffer, Framebuffer};
+use crate::{
+    ContextAttributeFlags, ContextAttributes, ContextDescriptorID, ContextID, Error, GLVersion,
+};
+use crate::{GLApi, SurfaceInfo, WindowingApiError};
 
 use crate::gl;
 use crate::gl::types::{GLenum, GLint, GLuint};
+use crate::gl_utils;
+#[cfg(feature = "sm-opencl")]
+use crate::opencl::{GLSharingDisplay, GLSharingProperties};
 use crate::Gl;
+use euclid::default::Size2D;
 use std::borrow::Cow;
 use std::ffi::{CStr, CString};
 use std::mem;
@@ -34,6 +42,7 @@ const WGL_DRAW_TO_WINDOW_ARB: GLenum = 0x2001;
 const WGL_ACCELERATION_ARB: GLenum = 0x2003;
 const WGL_SUPPORT_OPENGL_ARB: GLenum = 0x2010;
 const WGL_DOUBLE_BUFFER_ARB: GLenum = 0x2011;
+const WGL_STEREO_ARB: GLenum = 0x2012;
 const WGL_PIXEL_TYPE_ARB: GLenum = 0x2013;
 const WGL_COLOR_BITS_ARB: GLenum = 0x2014;
 const WGL_ALPHA_BITS_ARB: GLenum = 0x201b;
@@ -41,13 +50,19 @@ const WGL_DEPTH_BITS_ARB: GLenum = 0x2022;
 const WGL_STENCIL_BITS_ARB: GLenum = 0x2023;
 const WGL_FULL_ACCELERATION_ARB: GLenum = 0x2027;
 const WGL_TYPE_RGBA_ARB: GLenum = 0x202b;
+const WGL_TYPE_RGBA_FLOAT_ARB: GLenum = 0x21a0;
+const WGL_SAMPLE_BUFFERS_ARB: GLenum = 0x2041;
+const WGL_SAMPLES_ARB: GLenum = 0x2042;
 const WGL_CONTEXT_MAJOR_VERSION_ARB: GLenum = 0x2091;
 const WGL_CONTEXT_MINOR_VERSION_ARB: GLenum = 0x2092;
 const WGL_CONTEXT_PROFILE_MASK_ARB: GLenum = 0x9126;
+const WGL_CONTEXT_FLAGS_ARB: GLenum = 0x2094;
 
 const WGL_CONTEXT_CORE_PROFILE_BIT_ARB: GLenum = 0x00000001;
 const WGL_CONTEXT_COMPATIBILITY_PROFILE_BIT_ARB: GLenum = 0x00000002;
 
+const WGL_CONTEXT_DEBUG_BIT_ARB: GLenum = 0x00000001;
+
 #[allow(non_snake_case)]
 #[derive(Default)]
 pub(crate) struct WGLExtensionFunctions {
@@ -57,6 +72,8 @@ pub(crate) struct WGLExtensionFunctions {
     GetExtensionsStringARB: Option<unsafe extern "C" fn(hdc: HDC) -> *const c_char>,
     pub(crate) pixel_format_functions: Option<WGLPixelFormatExtensionFunctions>,
     pub(crate) dx_interop_functions: Option<WGLDXInteropExtensionFunctions>,
+    pixel_format_float: bool,
+    multisample: bool,
 }
 
 #[allow(non_snake_case)]
@@ -101,12 +118,29 @@ pub(crate) struct WGLDXInteropExtensionFunctions {
 
 /// Information needed to create a context. Some APIs call this a "config" or a "pixel format".
 ///
-/// These are local to a device.
-#[derive(Clone)]
+/// These are local to a device. Descriptors compare equal when they name the same pixel format and
+/// request the same version, profile, and extensions.
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct ContextDescriptor {
     pixel_format: c_int,
     gl_version: GLVersion,
+    // The highest version to try when creating contexts, which is `gl_version` unless a range was
+    // asked for.
+    max_gl_version: GLVersion,
     compatibility_profile: bool,
+    debug: bool,
+    required_extensions: Vec<String>,
+}
+
+impl ContextDescriptor {
+    /// Returns the index of the pixel format that contexts created from this descriptor use.
+    ///
+    /// Libraries that create their own contexts can pass it to `SetPixelFormat()` on their device
+    /// context, to match surfman's configuration.
+    #[inline]
+    pub fn pixel_format(&self) -> c_int {
+        self.pixel_format
+    }
 }
 
 /// Represents an OpenGL rendering context.
@@ -162,14 +196,48 @@ impl Device {
     /// Creates a context descriptor with the given attributes.
     ///
     /// Context descriptors are local to this device.
+    ///
+    /// Descriptors are cached by this device, so asking again for the same attributes returns a
+    /// copy of the earlier descriptor without choosing a pixel format again.
+    #[inline]
+    pub(crate) fn create_context_descriptor_impl(
+        &self,
+        attributes: &ContextAttributes,
+    ) -> Result<ContextDescriptor, Error> {
+        let attributes = &overrides::context_attributes(attributes);
+        self.context_descriptors
+            .get_or_insert_with(attributes, || self.choose_context_descriptor(attributes))
+    }
+
+    /// Returns a small ID for the given context descriptor, which is the same for every equal
+    /// descriptor.
+    ///
+    /// The IDs are shared with clones of this device.
+    #[inline]
+    pub fn intern_context_descriptor(
+        &self,
+        context_descriptor: &ContextDescriptor,
+    ) -> ContextDescriptorID {
+        self.context_descriptors.intern(context_descriptor)
+    }
+
     #[allow(non_snake_case)]
-    pub fn create_context_descriptor(
+    fn choose_context_descriptor(
         &self,
         attributes: &ContextAttributes,
     ) -> Result<ContextDescriptor, Error> {
         let flags = attributes.flags;
+        let float_color = flags.contains(ContextAttributeFlags::FLOAT_COLOR);
+        if float_color && !WGL_EXTENSION_FUNCTIONS.pixel_format_float {
+            return Err(Error::RequiredExtensionUnavailable);
+        }
+        let (pixel_type, channel_bits) = if float_color {
+            (WGL_TYPE_RGBA_FLOAT_ARB, 16)
+        } else {
+            (WGL_TYPE_RGBA_ARB, 8)
+        };
         let alpha_bits = if flags.contains(ContextAttributeFlags::ALPHA) {
-            8
+            channel_bits
         } else {
             0
         };
@@ -185,7 +253,7 @@ impl Device {
         };
         let compatibility_profile = flags.contains(ContextAttributeFlags::COMPATIBILITY_PROFILE);
 
-        let attrib_i_list = [
+        let mut attrib_i_list = vec![
             WGL_DRAW_TO_WINDOW_ARB as c_int,
             gl::TRUE as c_int,
             WGL_SUPPORT_OPENGL_ARB as c_int,
@@ -193,49 +261,73 @@ impl Device {
             WGL_DOUBLE_BUFFER_ARB as c_int,
             gl::TRUE as c_int,
             WGL_PIXEL_TYPE_ARB as c_int,
-            WGL_TYPE_RGBA_ARB as c_int,
+            pixel_type as c_int,
             WGL_ACCELERATION_ARB as c_int,
             WGL_FULL_ACCELERATION_ARB as c_int,
             WGL_COLOR_BITS_ARB as c_int,
-            32,
+            channel_bits * 4,
             WGL_ALPHA_BITS_ARB as c_int,
             alpha_bits,
             WGL_DEPTH_BITS_ARB as c_int,
             depth_bits,
             WGL_STENCIL_BITS_ARB as c_int,
             stencil_bits,
-            0,
         ];
+        if flags.contains(ContextAttributeFlags::STEREO) {
+            attrib_i_list.extend_from_slice(&[WGL_STEREO_ARB as c_int, gl::TRUE as c_int]);
+        }
 
         let wglChoosePixelFormatARB = match WGL_EXTENSION_FUNCTIONS.pixel_format_functions {
             None => return Err(Error::RequiredExtensionUnavailable),
             Some(ref pixel_format_functions) => pixel_format_functions.ChoosePixelFormatARB,
         };
 
+        // Try each sample count in turn, settling for fewer samples if the requested count isn't
+        // available. Without `WGL_ARB_multisample`, only single-sampled formats are available.
         let hidden_window_dc = self.hidden_window.get_dc();
-        unsafe {
-            let (mut pixel_format, mut pixel_format_count) = (0, 0);
-            let ok = wglChoosePixelFormatARB(
-                hidden_window_dc.dc,
-                attrib_i_list.as_ptr(),
-                ptr::null(),
-                1,
-                &mut pixel_format,
-                &mut pixel_format_count,
-            );
-            if ok == FALSE {
-                return Err(Error::PixelFormatSelectionFailed(WindowingApiError::Failed));
-            }
-            if pixel_format_count == 0 {
-                return Err(Error::NoPixelFormatFound);
+        for samples in attributes.sample_counts() {
+            let mut attrib_i_list = attrib_i_list.clone();
+            if samples > 1 {
+                if !WGL_EXTENSION_FUNCTIONS.multisample {
+                    continue;
+                }
+                attrib_i_list.extend_from_slice(&[
+                    WGL_SAMPLE_BUFFERS_ARB as c_int,
+                    1,
+                    WGL_SAMPLES_ARB as c_int,
+                    samples as c_int,
+                ]);
             }
+            attrib_i_list.push(0);
+
+            unsafe {
+                let (mut pixel_format, mut pixel_format_count) = (0, 0);
+                let ok = wglChoosePixelFormatARB(
+                    hidden_window_dc.dc,
+                    attrib_i_list.as_ptr(),
+                    ptr::null(),
+                    1,
+                    &mut pixel_format,
+                    &mut pixel_format_count,
+                );
+                if ok == FALSE {
+                    return Err(Error::PixelFormatSelectionFailed(WindowingApiError::Failed));
+                }
+                if pixel_format_count == 0 {
+                    continue;
+                }
 
-            Ok(ContextDescriptor {
-                pixel_format,
-                gl_version: attributes.version,
-                compatibility_profile,
-            })
+                return Ok(ContextDescriptor {
+                    pixel_format,
+                    gl_version: attributes.version,
+                    max_gl_version: attributes.highest_version(),
+                    compatibility_profile,
+                    debug: flags.contains(ContextAttributeFlags::DEBUG),
+                    required_extensions: attributes.required_extensions.clone(),
+                });
+            }
         }
+        Err(Error::NoPixelFormatFound)
     }
 
     /// Creates a new OpenGL context.
@@ -243,7 +335,7 @@ impl Device {
     /// The context initially has no surface attached. Until a surface is bound to it, rendering
     /// commands will fail or have no effect.
     #[allow(non_snake_case)]
-    pub fn create_context(
+    pub(crate) fn create_context_impl(
         &mut self,
         descriptor: &ContextDescriptor,
         share_with: Option<&Context>,
@@ -253,7 +345,6 @@ impl Device {
             Some(wglCreateContextAttribsARB) => wglCreateContextAttribsARB,
         };
 
-        let mut next_context_id = CREATE_CONTEXT_MUTEX.lock().unwrap();
         unsafe {
             let (glrc, gl);
 
@@ -272,20 +363,39 @@ impl Device {
                 } else {
                     WGL_CONTEXT_CORE_PROFILE_BIT_ARB
                 };
-                let wgl_attributes = [
-                    WGL_CONTEXT_MAJOR_VERSION_ARB as c_int,
-                    descriptor.gl_version.major as c_int,
-                    WGL_CONTEXT_MINOR_VERSION_ARB as c_int,
-                    descriptor.gl_version.minor as c_int,
-                    WGL_CONTEXT_PROFILE_MASK_ARB as c_int,
-                    profile_mask as c_int,
-                    0,
-                ];
-                glrc = wglCreateContextAttribsARB(
-                    dc,
-                    share_with.map_or(ptr::null_mut(), |ctx| ctx.glrc),
-                    wgl_attributes.as_ptr(),
+                // Try the highest version first. Versions that the driver doesn't support fail
+                // with `ERROR_INVALID_VERSION_ARB`.
+                let gl_versions = context::gl_versions(
+                    GLApi::GL,
+                    descriptor.gl_version,
+                    descriptor.max_gl_version,
                 );
+                glrc = gl_versions
+                    .into_iter()
+                    .map(|gl_version| {
+                        let wgl_attributes = [
+                            WGL_CONTEXT_MAJOR_VERSION_ARB as c_int,
+                            gl_version.major as c_int,
+                            WGL_CONTEXT_MINOR_VERSION_ARB as c_int,
+                            gl_version.minor as c_int,
+                            WGL_CONTEXT_PROFILE_MASK_ARB as c_int,
+                            profile_mask as c_int,
+                            WGL_CONTEXT_FLAGS_ARB as c_int,
+                            if descriptor.debug {
+                                WGL_CONTEXT_DEBUG_BIT_ARB as c_int
+                            } else {
+                                0
+                            },
+                            0,
+                        ];
+                        wglCreateContextAttribsARB(
+                            dc,
+                            share_with.map_or(ptr::null_mut(), |ctx| ctx.glrc),
+                            wgl_attributes.as_ptr(),
+                        )
+                    })
+                    .find(|glrc| !glrc.is_null())
+                    .unwrap_or(ptr::null_mut());
                 if glrc.is_null() {
                     return Err(Error::ContextCreationFailed(WindowingApiError::Failed));
                 }
@@ -302,13 +412,12 @@ impl Device {
             // Create the initial context.
             let context = Context {
                 glrc,
-                id: *next_context_id,
+                id: self.context_ids.next(),
                 gl,
                 hidden_window: Some(hidden_window),
                 framebuffer: Framebuffer::None,
                 status: ContextStatus::Owned,
             };
-            next_context_id.0 += 1;
             Ok(context)
         }
     }
@@ -318,11 +427,10 @@ impl Device {
     /// The `HGLRC` is not retained, as there is no way to do this in the Win32 API. Therefore, it
     /// is the caller's responsibility to make sure the OpenGL context is not destroyed before this
     /// `Context` is.
-    pub unsafe fn create_context_from_native_context(
+    pub(crate) unsafe fn create_context_from_native_context_impl(
         &self,
         native_context: NativeContext,
     ) -> Result<Context, Error> {
-        let mut next_context_id = CREATE_CONTEXT_MUTEX.lock().unwrap();
         let hidden_window = HiddenWindow::new();
 
         // Load the GL functions.
@@ -337,25 +445,41 @@ impl Device {
 
         let context = Context {
             glrc: native_context.0,
-            id: *next_context_id,
+            id: self.context_ids.next(),
             gl,
             hidden_window: Some(hidden_window),
             framebuffer: Framebuffer::External(()),
             status: ContextStatus::Referenced,
         };
-        next_context_id.0 += 1;
         Ok(context)
     }
 
+    /// Wraps the WGL context that is current on this thread in a context on a new device, opened
+    /// on the default adapter.
+    ///
+    /// If no context is current, this returns a `NoCurrentContext` error.
+    ///
+    /// # Safety
+    ///
+    /// As with `create_context_from_native_context()`, the caller must keep the `HGLRC` alive as
+    /// long as the returned context is.
+    pub unsafe fn from_current_context() -> Result<(Device, Context), Error> {
+        let native_context = NativeContext::current()?;
+        let connection = Connection::new()?;
+        let device = connection.create_device(&connection.create_adapter()?)?;
+        let context = device.create_context_from_native_context(native_context)?;
+        Ok((device, context))
+    }
+
     /// Destroys a context.
     ///
     /// The context must have been created on this device.
-    pub fn destroy_context(&self, context: &mut Context) -> Result<(), Error> {
+    pub(crate) fn destroy_context_impl(&self, context: &mut Context) -> Result<(), Error> {
         if context.status == ContextStatus::Destroyed {
             return Ok(());
         }
 
-        if let Ok(Some(mut surface)) = self.unbind_surface_from_context(context) {
+        if let Ok(Some(mut surface)) = self.unbind_surface_from_context_impl(context) {
             self.destroy_surface(context, &mut surface)?;
         }
 
@@ -385,11 +509,15 @@ impl Device {
             let gl_version = GLVersion::current(&context.gl);
             let compatibility_profile =
                 context::current_context_uses_compatibility_profile(&context.gl);
+            let debug = context::current_context_is_debug(&context.gl);
 
             ContextDescriptor {
                 pixel_format,
                 gl_version,
+                max_gl_version: gl_version,
                 compatibility_profile,
+                debug,
+                required_extensions: vec![],
             }
         }
     }
@@ -416,8 +544,10 @@ impl Device {
                 WGL_ALPHA_BITS_ARB as c_int,
                 WGL_DEPTH_BITS_ARB as c_int,
                 WGL_STENCIL_BITS_ARB as c_int,
+                WGL_STEREO_ARB as c_int,
+                WGL_PIXEL_TYPE_ARB as c_int,
             ];
-            let mut attrib_value_i_list = [0; 3];
+            let mut attrib_value_i_list = [0; 5];
             let ok = wglGetPixelFormatAttribivARB(
                 dc_guard.dc,
                 context_descriptor.pixel_format,
@@ -427,15 +557,35 @@ impl Device {
                 attrib_value_i_list.as_mut_ptr(),
             );
             assert_ne!(ok, FALSE);
-            let (alpha_bits, depth_bits, stencil_bits) = (
+            let (alpha_bits, depth_bits, stencil_bits, stereo, pixel_type) = (
                 attrib_value_i_list[0],
                 attrib_value_i_list[1],
                 attrib_value_i_list[2],
+                attrib_value_i_list[3],
+                attrib_value_i_list[4],
             );
 
+            // Without `WGL_ARB_multisample`, every pixel format is single-sampled.
+            let mut samples = 0;
+            if WGL_EXTENSION_FUNCTIONS.multisample {
+                let ok = wglGetPixelFormatAttribivARB(
+                    dc_guard.dc,
+                    context_descriptor.pixel_format,
+                    0,
+                    1,
+                    &(WGL_SAMPLES_ARB as c_int),
+                    &mut samples,
+                );
+                assert_ne!(ok, FALSE);
+            }
+
             let mut attributes = ContextAttributes {
                 version: context_descriptor.gl_version,
+                max_version: Some(context_descriptor.max_gl_version)
+                    .filter(|&version| version > context_descriptor.gl_version),
                 flags: ContextAttributeFlags::empty(),
+                required_extensions: context_descriptor.required_extensions.clone(),
+                samples: samples.max(1) as u32,
             };
             if alpha_bits > 0 {
                 attributes.flags.insert(ContextAttributeFlags::ALPHA);
@@ -446,6 +596,15 @@ impl Device {
             if stencil_bits > 0 {
                 attributes.flags.insert(ContextAttributeFlags::STENCIL);
             }
+            if stereo != 0 {
+                attributes.flags.insert(ContextAttributeFlags::STEREO);
+            }
+            if pixel_type == WGL_TYPE_RGBA_FLOAT_ARB as c_int {
+                attributes.flags.insert(ContextAttributeFlags::FLOAT_COLOR);
+            }
+            if context_descriptor.debug {
+                attributes.flags.insert(ContextAttributeFlags::DEBUG);
+            }
 
             attributes
         }
@@ -468,14 +627,24 @@ impl Device {
         context: &Context,
     ) -> Result<CurrentContextGuard, Error> {
         let guard = CurrentContextGuard::new();
-        self.make_context_current(context)?;
+        self.make_context_current_impl(context)?;
         Ok(guard)
     }
 
+    // Calls the given function with the OpenGL functions of the context, which WGL loads for each
+    // context.
+    #[inline]
+    pub(crate) fn with_gl_functions<F, R>(&self, context: &Context, callback: F) -> R
+    where
+        F: FnOnce(&Gl) -> R,
+    {
+        callback(&context.gl)
+    }
+
     /// Makes the context the current OpenGL context for this thread.
     ///
     /// After calling this function, it is valid to use OpenGL rendering commands.
-    pub fn make_context_current(&self, context: &Context) -> Result<(), Error> {
+    pub(crate) fn make_context_current_impl(&self, context: &Context) -> Result<(), Error> {
         unsafe {
             let dc_guard = self.get_context_dc(context);
             let ok = wglMakeCurrent(dc_guard.dc, context.glrc);
@@ -487,6 +656,39 @@ impl Device {
         }
     }
 
+    /// Makes the context the current OpenGL context for this thread, rendering to the given
+    /// surface instead of any surface bound to it.
+    ///
+    /// The surface must have been created with this context, or an `IncompatibleSurface` error is
+    /// returned.
+    ///
+    /// Generic surfaces on this backend are only available to OpenGL while they're bound, so this
+    /// returns a `NoWidgetAttached` error for them.
+    pub(crate) fn make_context_current_with_surface_impl(
+        &self,
+        context: &Context,
+        surface: &Surface,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        let window_handle = match surface.win32_objects {
+            Win32Objects::Widget { window_handle } => window_handle,
+            Win32Objects::Texture { .. } => return Err(Error::NoWidgetAttached),
+        };
+
+        unsafe {
+            let dc_guard = DCGuard::new(winuser::GetDC(window_handle), Some(window_handle));
+            let ok = wglMakeCurrent(dc_guard.dc, context.glrc);
+            if ok != FALSE {
+                Ok(())
+            } else {
+                Err(Error::MakeCurrentFailed(WindowingApiError::Failed))
+            }
+        }
+    }
+
     /// Removes the current OpenGL context from this thread.
     ///
     /// After calling this function, OpenGL rendering commands will fail until a new context is
@@ -530,7 +732,7 @@ impl Device {
     /// `unbind_surface_from_context`.
     ///
     /// If an error is returned, the surface is returned alongside it.
-    pub fn bind_surface_to_context(
+    pub(crate) fn bind_surface_to_context_impl(
         &self,
         context: &mut Context,
         surface: Surface,
@@ -541,7 +743,9 @@ impl Device {
 
         match context.framebuffer {
             Framebuffer::None => {}
-            Framebuffer::External(()) => return Err((Error::ExternalRenderTarget, surface)),
+            Framebuffer::External(()) | Framebuffer::ExternalFramebuffer(_) => {
+                return Err((Error::ExternalRenderTarget, surface))
+            }
             Framebuffer::Surface(_) => return Err((Error::SurfaceAlreadyBound, surface)),
         }
 
@@ -552,7 +756,7 @@ impl Device {
 
         if is_current {
             // We need to make ourselves current again, because the surface changed.
-            drop(self.make_context_current(context));
+            drop(self.make_context_current_impl(context));
         }
 
         Ok(())
@@ -562,7 +766,7 @@ impl Device {
     ///
     /// Any pending OpenGL commands targeting this surface will be automatically flushed, so the
     /// surface is safe to read from immediately when this function returns.
-    pub fn unbind_surface_from_context(
+    pub(crate) fn unbind_surface_from_context_impl(
         &self,
         context: &mut Context,
     ) -> Result<Option<Surface>, Error> {
@@ -572,8 +776,55 @@ impl Device {
                 Ok(Some(surface))
             }
             Framebuffer::External(()) => Err(Error::ExternalRenderTarget),
-            Framebuffer::None => Ok(None),
+            Framebuffer::None | Framebuffer::ExternalFramebuffer(_) => Ok(None),
+        }
+    }
+
+    /// Makes a context render to a framebuffer object that the caller created and owns, in place
+    /// of a surface.
+    ///
+    /// The framebuffer object must stay alive until it's detached again with
+    /// `unbind_surface_from_context()`.
+    pub fn bind_external_framebuffer(
+        &self,
+        context: &mut Context,
+        framebuffer_object: GLuint,
+        size: Size2D<i32>,
+    ) -> Result<(), Error> {
+        match context.framebuffer {
+            Framebuffer::None => {}
+            Framebuffer::External(()) | Framebuffer::ExternalFramebuffer(_) => {
+                return Err(Error::ExternalRenderTarget)
+            }
+            Framebuffer::Surface(_) => return Err(Error::SurfaceAlreadyBound),
+        }
+
+        // The context stays current on its hidden window, as it does when nothing is bound.
+        context.framebuffer =
+            Framebuffer::ExternalFramebuffer(ExternalFramebuffer::new(framebuffer_object, size));
+        Ok(())
+    }
+
+    /// Makes the rendering submitted so far to a surface visible to everything else that reads
+    /// it.
+    ///
+    /// This waits on a fence for the rendering to finish. Direct3D sees it once the surface's
+    /// texture is unlocked, which unbound surfaces already are.
+    ///
+    /// The surface must have been created with the given context, or an `IncompatibleSurface`
+    /// error is returned.
+    pub(crate) fn flush_context_impl(
+        &self,
+        context: &Context,
+        surface: &Surface,
+    ) -> Result<(), Error> {
+        if surface.context_id != context.id {
+            return Err(Error::IncompatibleSurface);
         }
+
+        let _guard = self.temporarily_make_context_current(context)?;
+        gl_utils::finish_with_fence(&context.gl);
+        Ok(())
     }
 
     pub(crate) fn get_context_dc<'a>(&self, context: &'a Context) -> DCGuard<'a> {
@@ -588,6 +839,7 @@ impl Device {
                     ..
                 })
                 | Framebuffer::External(())
+                | Framebuffer::ExternalFramebuffer(_)
                 | Framebuffer::None => context.hidden_window.as_ref().unwrap().get_dc(),
             }
         }
@@ -609,7 +861,13 @@ impl Device {
         match context.framebuffer {
             Framebuffer::None => Ok(None),
             Framebuffer::External(()) => Err(Error::ExternalRenderTarget),
-            Framebuffer::Surface(ref surface) => Ok(Some(self.surface_info(surface))),
+            Framebuffer::ExternalFramebuffer(ref framebuffer) => {
+                Ok(Some(framebuffer.info(context.id)))
+            }
+            Framebuffer::Surface(ref surface) => Ok(Some(SurfaceInfo {
+                bound: true,
+                ..self.surface_info(surface)
+            })),
         }
     }
 
@@ -790,6 +1048,14 @@ extern "system" fn extension_loader_window_proc(
                             });
                         continue;
                     }
+                    if extension == "WGL_ARB_pixel_format_float" {
+                        (*wgl_extension_functions).pixel_format_float = true;
+                        continue;
+                    }
+                    if extension == "WGL_ARB_multisample" {
+                        (*wgl_extension_functions).multisample = true;
+                        continue;
+                    }
                 }
 
                 wglDeleteContext(gl_context);
@@ -897,3 +1163,18 @@ pub(crate) fn set_dc_pixel_format(dc: HDC, pixel_format: c_int) {
         assert_ne!(ok, FALSE);
     }
 }
+
+#[cfg(feature = "sm-opencl")]
+impl Device {
+    /// Returns what OpenCL needs to share objects with the given context through
+    /// `cl_khr_gl_sharing`.
+    ///
+    /// The device context is that of the surface currently bound to the context, so this must be
+    /// called again after binding a different widget surface.
+    pub fn gl_sharing_properties(&self, context: &Context) -> Result<GLSharingProperties, Error> {
+        Ok(GLSharingProperties {
+            gl_context: context.glrc as *mut c_void,
+            display: GLSharingDisplay::WGL(self.get_context_dc(context).dc as *mut c_void),
+        })
+    }
+}
@@ -3,14 +3,19 @@
 //! An implementation of the GPU device for Windows using the WGL API.
 
 use super::connection::Connection;
-use super::context::WGL_EXTENSION_FUNCTIONS;
-use crate::{Error, GLApi};
+use super::context::{ContextDescriptor, WGL_EXTENSION_FUNCTIONS};
+use crate::context::{ContextDescriptorCache, ContextIDAllocator};
+use crate::device_events::{self, DeviceEventDispatcher};
+use crate::timings::{CreationTimings, CreationTimingsRecorder};
+use crate::{DeviceEventHandler, Error, GLApi, LifecycleObserver, NativeDeviceHandle};
 
 use std::marker::PhantomData;
 use std::mem;
 use std::os::raw::{c_int, c_void};
 use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::thread::{self, JoinHandle};
 use winapi::shared::dxgi::{IDXGIAdapter, IDXGIDevice};
 use winapi::shared::minwindef::{self, FALSE, UINT};
@@ -33,7 +38,7 @@ static AMD_GPU_SELECT_SYMBOL: &[u8] = b"AmdPowerXpressRequestHighPerformance\0";
 
 /// Represents a hardware display adapter that can be used for rendering (including the CPU).
 ///
-/// Adapters can be sent between threads. To render with an adapter, open a thread-local `Device`.
+/// Adapters can be sent between threads. To render with an adapter, open a `Device`.
 #[derive(Clone, Debug)]
 pub enum Adapter {
     #[doc(hidden)]
@@ -46,18 +51,45 @@ struct SendableHWND(HWND);
 
 unsafe impl Send for SendableHWND {}
 
-/// A thread-local handle to a device.
+/// A handle to a device.
 ///
-/// Devices contain most of the relevant surface management methods.
+/// Devices contain most of the relevant surface management methods. Clones share the device's
+/// Direct3D 11 device, GL/DX interop device, and hidden window, and can be sent to other threads,
+/// so each rendering thread can own a handle.
 #[allow(dead_code)]
+#[derive(Clone)]
 pub struct Device {
     pub(crate) adapter: Adapter,
     pub(crate) d3d11_device: ComPtr<ID3D11Device>,
     pub(crate) d3d11_device_context: ComPtr<ID3D11DeviceContext>,
     pub(crate) gl_dx_interop_device: HANDLE,
-    pub(crate) hidden_window: HiddenWindow,
+    // Closes `gl_dx_interop_device` once the last clone of the device is dropped.
+    gl_dx_interop_device_owner: Arc<GLDXInteropDevice>,
+    pub(crate) hidden_window: Arc<HiddenWindow>,
+    pub(crate) lifecycle_observer: Option<Arc<dyn LifecycleObserver>>,
+    pub(crate) device_event_dispatcher: DeviceEventDispatcher,
+    pub(crate) creation_timings: CreationTimingsRecorder,
+    pub(crate) memory_usage: Arc<AtomicUsize>,
+    pub(crate) context_ids: ContextIDAllocator,
+    pub(crate) context_descriptors: ContextDescriptorCache<ContextDescriptor>,
 }
 
+// Nothing the device holds is tied to the thread that opened it:
+//
+// * The hidden window belongs to its own thread, which pumps its messages. The device only calls
+//   `GetDC()`, `ReleaseDC()`, and `PostMessageA()` on it, which any thread may do. Its class has
+//   `CS_OWNDC`, so every clone gets the same DC; `HiddenWindow::get_dc()` serializes its use.
+// * The device holds no `HGLRC`. Rendering contexts are owned by `Context`s, which aren't `Send`.
+// * Direct3D 11 devices are free-threaded. The immediate context isn't, but the device only keeps
+//   a reference to it and never issues commands on it.
+// * `wglDXCloseDeviceNV()` doesn't have to be called on the thread that opened the interop device,
+//   so the last clone may be dropped anywhere.
+unsafe impl Send for Device {}
+
+// A GL/DX interop device, closed with `wglDXCloseDeviceNV()` when the last clone of the device
+// that opened or adopted it is dropped.
+struct GLDXInteropDevice(HANDLE);
+
 /// Wraps a Direct3D 11 device and its associated GL/DX interop device.
 #[derive(Clone)]
 pub struct NativeDevice {
@@ -111,20 +143,20 @@ impl Adapter {
     }
 }
 
-impl Drop for Device {
+impl Drop for GLDXInteropDevice {
     fn drop(&mut self) {
         let dx_interop_functions = WGL_EXTENSION_FUNCTIONS
             .dx_interop_functions
             .as_ref()
             .unwrap();
         unsafe {
-            (dx_interop_functions.DXCloseDeviceNV)(self.gl_dx_interop_device);
+            (dx_interop_functions.DXCloseDeviceNV)(self.0);
         }
     }
 }
 
 impl Device {
-    pub(crate) fn new(adapter: &Adapter) -> Result<Device, Error> {
+    pub(crate) fn new(connection: &Connection, adapter: &Adapter) -> Result<Device, Error> {
         adapter.set_exported_variables();
 
         let dx_interop_functions = match WGL_EXTENSION_FUNCTIONS.dx_interop_functions {
@@ -165,12 +197,22 @@ impl Device {
                 d3d11_device,
                 d3d11_device_context,
                 gl_dx_interop_device,
-                hidden_window,
+                gl_dx_interop_device_owner: Arc::new(GLDXInteropDevice(gl_dx_interop_device)),
+                hidden_window: Arc::new(hidden_window),
+                lifecycle_observer: None,
+                device_event_dispatcher: DeviceEventDispatcher::default(),
+                creation_timings: CreationTimingsRecorder::default(),
+                memory_usage: Arc::new(AtomicUsize::new(0)),
+                context_ids: connection.context_ids.clone(),
+                context_descriptors: ContextDescriptorCache::new(),
             })
         }
     }
 
-    pub(crate) fn from_native_device(native_device: NativeDevice) -> Result<Device, Error> {
+    pub(crate) fn from_native_device(
+        connection: &Connection,
+        native_device: NativeDevice,
+    ) -> Result<Device, Error> {
         unsafe {
             (*native_device.d3d11_device).AddRef();
             let d3d11_device = ComPtr::from_raw(native_device.d3d11_device);
@@ -200,7 +242,14 @@ impl Device {
                 d3d11_device,
                 d3d11_device_context,
                 gl_dx_interop_device,
-                hidden_window,
+                gl_dx_interop_device_owner: Arc::new(GLDXInteropDevice(gl_dx_interop_device)),
+                hidden_window: Arc::new(hidden_window),
+                lifecycle_observer: None,
+                device_event_dispatcher: DeviceEventDispatcher::default(),
+                creation_timings: CreationTimingsRecorder::default(),
+                memory_usage: Arc::new(AtomicUsize::new(0)),
+                context_ids: connection.context_ids.clone(),
+                context_descriptors: ContextDescriptorCache::new(),
             })
         }
     }
@@ -220,10 +269,23 @@ impl Device {
         }
     }
 
+    /// Returns the raw GPU device underneath this device: its Direct3D 11 device.
+    ///
+    /// Unlike `native_device()`, this doesn't add a reference to the Direct3D 11 device.
+    #[inline]
+    pub fn native_device_handle(&self) -> NativeDeviceHandle {
+        NativeDeviceHandle::D3D11Device {
+            device: self.d3d11_device.as_raw() as *mut c_void,
+            egl_display: ptr::null_mut(),
+        }
+    }
+
     /// Returns the display server connection that this device was created with.
     #[inline]
     pub fn connection(&self) -> Connection {
-        Connection
+        Connection {
+            context_ids: self.context_ids.clone(),
+        }
     }
 
     /// Returns the adapter that this device was created with.
@@ -237,6 +299,63 @@ impl Device {
     pub fn gl_api(&self) -> GLApi {
         GLApi::GL
     }
+
+    /// Installs an observer that is notified when contexts and surfaces of this device are
+    /// created, destroyed, resized, or presented, replacing any previous one.
+    #[inline]
+    pub fn set_lifecycle_observer(&mut self, observer: Option<Arc<dyn LifecycleObserver>>) {
+        self.lifecycle_observer = observer;
+    }
+
+    /// Returns the lifecycle observer installed on this device, if any.
+    #[inline]
+    pub fn lifecycle_observer(&self) -> Option<Arc<dyn LifecycleObserver>> {
+        self.lifecycle_observer.clone()
+    }
+
+    /// Installs a handler that is told when the GPU of this device is removed or reset, or the
+    /// displays are reconfigured, replacing any previous one.
+    ///
+    /// Display changes are found by checking `Connection::displays()` once a second.
+    pub fn set_device_event_handler(&mut self, handler: Option<DeviceEventHandler>) {
+        let connection = self.connection();
+        let poll = device_events::display_poller(move || connection.displays());
+        self.device_event_dispatcher.set_handler(handler, poll);
+    }
+
+    /// Returns the device event handler installed on this device, if any.
+    #[inline]
+    pub fn device_event_handler(&self) -> Option<DeviceEventHandler> {
+        self.device_event_dispatcher.handler()
+    }
+
+    #[inline]
+    pub(crate) fn device_event_dispatcher(&self) -> &DeviceEventDispatcher {
+        &self.device_event_dispatcher
+    }
+
+    /// Returns the GPU memory, in bytes, held by the surfaces of this device.
+    #[inline]
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub(crate) fn memory_usage_counter(&self) -> &AtomicUsize {
+        &self.memory_usage
+    }
+
+    /// Returns how long opening this device, and creating its context descriptors and contexts,
+    /// took.
+    #[inline]
+    pub fn creation_timings(&self) -> CreationTimings {
+        self.creation_timings.get()
+    }
+
+    #[inline]
+    pub(crate) fn creation_timings_recorder(&self) -> &CreationTimingsRecorder {
+        &self.creation_timings
+    }
 }
 
 impl Adapter {
@@ -258,11 +377,14 @@ impl Adapter {
 pub(crate) struct HiddenWindow {
     window: HWND,
     join_handle: Option<JoinHandle<()>>,
+    // Held while the window's DC is in use, since clones of a device share it across threads.
+    dc_lock: Mutex<()>,
 }
 
 pub(crate) struct DCGuard<'a> {
     pub(crate) dc: HDC,
     window: Option<HWND>,
+    _dc_lock: Option<MutexGuard<'a, ()>>,
     phantom: PhantomData<&'a HWND>,
 }
 
@@ -296,12 +418,16 @@ impl HiddenWindow {
         HiddenWindow {
             window,
             join_handle: Some(join_handle),
+            dc_lock: Mutex::new(()),
         }
     }
 
     #[inline]
     pub(crate) fn get_dc(&self) -> DCGuard {
-        unsafe { DCGuard::new(winuser::GetDC(self.window), Some(self.window)) }
+        let dc_lock = self.dc_lock.lock().unwrap();
+        let mut dc_guard = unsafe { DCGuard::new(winuser::GetDC(self.window), Some(self.window)) };
+        dc_guard._dc_lock = Some(dc_lock);
+        dc_guard
     }
 
     // The thread that creates the window for off-screen contexts.
@@ -361,6 +487,7 @@ impl<'a> DCGuard<'a> {
         DCGuard {
             dc,
             window,
+            _dc_lock: None,
             phantom: PhantomData,
         }
     }
@@ -5,13 +5,22 @@
 use super::context::{self, Context, WGL_EXTENSION_FUNCTIONS};
 use super::device::Device;
 use crate::error::WindowingApiError;
+use crate::platform::windows::monitors;
 use crate::renderbuffers::Renderbuffers;
-use crate::{ContextID, Error, SurfaceAccess, SurfaceID, SurfaceInfo, SurfaceType};
+use crate::surface::{estimate_memory_usage, SurfaceLayoutRequirements};
+use crate::{
+    Colorspace, ContextAttributeFlags, ContextID, CreateSurfacesResult, Error,
+    PresentationStatistics, SurfaceAccess, SurfaceAlphaMode, SurfaceFormat, SurfaceID, SurfaceInfo,
+    SurfaceOrigin, SurfaceTransform, SurfaceType, SurfaceUsage, SurfaceVisibility,
+    WidgetOutputInfo,
+};
 
 use crate::gl;
 use crate::gl::types::{GLenum, GLint, GLuint};
 use crate::gl_utils;
-use euclid::default::Size2D;
+#[cfg(feature = "sm-opencl")]
+use crate::opencl::SharedEGLImage;
+use euclid::default::{Rect, Size2D};
 use std::fmt::{self, Debug, Formatter};
 use std::marker::PhantomData;
 use std::mem;
@@ -21,13 +30,15 @@ use std::thread;
 use winapi::shared::dxgi::IDXGIResource;
 use winapi::shared::dxgiformat::DXGI_FORMAT_R8G8B8A8_UNORM;
 use winapi::shared::dxgitype::DXGI_SAMPLE_DESC;
-use winapi::shared::minwindef::{FALSE, UINT};
+use winapi::shared::minwindef::{FALSE, TRUE, UINT};
 use winapi::shared::ntdef::HANDLE;
-use winapi::shared::windef::HWND;
+use winapi::shared::windef::{HGDIOBJ, HWND};
 use winapi::shared::winerror;
 use winapi::um::d3d11::{ID3D11Texture2D, D3D11_USAGE_DEFAULT};
 use winapi::um::d3d11::{D3D11_BIND_RENDER_TARGET, D3D11_BIND_SHADER_RESOURCE};
 use winapi::um::d3d11::{D3D11_RESOURCE_MISC_SHARED_KEYEDMUTEX, D3D11_TEXTURE2D_DESC};
+use winapi::um::d3dcommon::WKPDID_D3DDebugObjectName;
+use winapi::um::dwmapi::{self, DWM_BB_BLURREGION, DWM_BB_ENABLE, DWM_BLURBEHIND};
 use winapi::um::handleapi::INVALID_HANDLE_VALUE;
 use winapi::um::wingdi;
 use winapi::um::winuser;
@@ -56,9 +67,18 @@ const WGL_ACCESS_READ_WRITE_NV: GLenum = 0x0001;
 ///
 /// Surfaces must be destroyed with the `destroy_surface()` method, or a panic will occur.
 pub struct Surface {
+    pub(crate) id: SurfaceID,
     pub(crate) size: Size2D<i32>,
     pub(crate) context_id: ContextID,
     pub(crate) win32_objects: Win32Objects,
+    // Which way up the contents are. See `SurfaceInfo::origin`.
+    pub(crate) origin: SurfaceOrigin,
+    // How the alpha channel is interpreted. See `SurfaceInfo::alpha_mode`.
+    pub(crate) alpha_mode: SurfaceAlphaMode,
+    // The color space of the contents. See `SurfaceInfo::colorspace`.
+    pub(crate) colorspace: Colorspace,
+    // The number of samples per pixel. See `SurfaceInfo::samples`.
+    pub(crate) samples: u32,
     pub(crate) destroyed: bool,
 }
 
@@ -129,18 +149,60 @@ impl Device {
     ///
     /// Only the given context may ever render to the surface, but generic surfaces can be wrapped
     /// up in a `SurfaceTexture` for reading by other contexts.
-    pub fn create_surface(
+    ///
+    /// The alpha mode is recorded in the surface's `SurfaceInfo`. Premultiplied widget surfaces of
+    /// contexts with an alpha channel are blended by DWM with what is behind the window, and
+    /// widget surfaces with straight alpha are unsupported.
+    pub(crate) fn create_surface_impl(
         &mut self,
         context: &Context,
         _: SurfaceAccess,
+        _: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
         surface_type: SurfaceType<NativeWidget>,
     ) -> Result<Surface, Error> {
-        match surface_type {
-            SurfaceType::Generic { size } => self.create_generic_surface(context, &size),
+        let mut surface = match surface_type {
+            SurfaceType::Generic { size } => self.create_generic_surface(context, &size)?,
+            SurfaceType::Widget { .. } if surface_alpha_mode == SurfaceAlphaMode::Straight => {
+                return Err(Error::UnsupportedOnThisPlatform)
+            }
             SurfaceType::Widget { native_widget } => {
-                self.create_widget_surface(context, native_widget)
+                self.create_widget_surface(context, native_widget, surface_alpha_mode)?
+            }
+        };
+        surface.alpha_mode = surface_alpha_mode;
+        Ok(surface)
+    }
+
+    /// Creates `count` generic surfaces of the given size.
+    ///
+    /// The context is made current once for the whole batch. If one of the surfaces fails to be
+    /// created, the error is returned along with the surfaces that were created before it.
+    pub(crate) fn create_surfaces_impl(
+        &mut self,
+        context: &Context,
+        _: SurfaceAccess,
+        _: SurfaceUsage,
+        surface_alpha_mode: SurfaceAlphaMode,
+        size: &Size2D<i32>,
+        count: usize,
+    ) -> CreateSurfacesResult<Surface> {
+        let _guard = match self.temporarily_make_context_current(context) {
+            Ok(guard) => guard,
+            Err(err) => return Err((err, vec![])),
+        };
+
+        let mut surfaces = Vec::with_capacity(count);
+        for _ in 0..count {
+            match self.create_generic_surface(context, size) {
+                Ok(mut surface) => {
+                    surface.alpha_mode = surface_alpha_mode;
+                    surfaces.push(surface)
+                }
+                Err(err) => return Err((err, surfaces)),
             }
         }
+        Ok(surfaces)
     }
 
     fn create_generic_surface(
@@ -254,6 +316,7 @@ impl Device {
             // that?
 
             Ok(Surface {
+                id: SurfaceID::next(),
                 size: *size,
                 context_id: context.id,
                 win32_objects: Win32Objects::Texture {
@@ -264,6 +327,10 @@ impl Device {
                     gl_framebuffer,
                     renderbuffers,
                 },
+                origin: SurfaceOrigin::BottomLeft,
+                alpha_mode: SurfaceAlphaMode::Premultiplied,
+                colorspace: Colorspace::SRGB,
+                samples: 1,
                 destroyed: false,
             })
         }
@@ -273,6 +340,7 @@ impl Device {
         &mut self,
         context: &Context,
         native_widget: NativeWidget,
+        alpha_mode: SurfaceAlphaMode,
     ) -> Result<Surface, Error> {
         unsafe {
             // Get the bounds of the native HWND.
@@ -290,7 +358,18 @@ impl Device {
                 context::set_dc_pixel_format(window_dc, pixel_format);
             }
 
+            let context_descriptor = self.context_descriptor(context);
+            let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+            if alpha_mode == SurfaceAlphaMode::Premultiplied
+                && context_attributes
+                    .flags
+                    .contains(ContextAttributeFlags::ALPHA)
+            {
+                enable_window_transparency(native_widget.window_handle);
+            }
+
             Ok(Surface {
+                id: SurfaceID::next(),
                 size: Size2D::new(
                     widget_rect.right - widget_rect.left,
                     widget_rect.bottom - widget_rect.top,
@@ -299,6 +378,10 @@ impl Device {
                 win32_objects: Win32Objects::Widget {
                     window_handle: native_widget.window_handle,
                 },
+                origin: SurfaceOrigin::BottomLeft,
+                alpha_mode: SurfaceAlphaMode::Premultiplied,
+                colorspace: Colorspace::SRGB,
+                samples: context_attributes.samples,
                 destroyed: false,
             })
         }
@@ -311,9 +394,9 @@ impl Device {
     ///
     /// You must explicitly call this method to dispose of a surface. Otherwise, a panic occurs in
     /// the `drop` method.
-    pub fn destroy_surface(
+    pub(crate) fn destroy_surface_impl(
         &self,
-        context: &mut Context,
+        context: &Context,
         surface: &mut Surface,
     ) -> Result<(), Error> {
         let dx_interop_functions = WGL_EXTENSION_FUNCTIONS
@@ -361,6 +444,33 @@ impl Device {
         Ok(())
     }
 
+    /// Creates a new generic surface with a copy of the contents of the given one, stored with
+    /// its color channels in the given order.
+    ///
+    /// This is not yet supported on WGL, so it always returns `UnsupportedOnThisPlatform`.
+    #[inline]
+    pub(crate) fn convert_surface_format_impl(
+        &mut self,
+        _: &mut Context,
+        _: &Surface,
+        _: SurfaceFormat,
+    ) -> Result<Surface, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Wraps an existing OpenGL texture of the given context in a new generic surface.
+    ///
+    /// Generic surfaces on this backend are Direct3D textures, which can't be made from an OpenGL texture, so this always returns an `UnsupportedOnThisPlatform` error.
+    #[inline]
+    pub(crate) fn create_surface_from_texture_impl(
+        &mut self,
+        _: &mut Context,
+        _: &Size2D<i32>,
+        _: GLuint,
+    ) -> Result<Surface, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
     /// Creates a surface texture from an existing generic surface for use with the given context.
     ///
     /// The surface texture is local to the supplied context and takes ownership of the surface.
@@ -437,9 +547,13 @@ impl Device {
             );
             assert_ne!(ok, FALSE);
 
-            // Initialize the texture, for convenience.
-            // FIXME(pcwalton): We should probably reset the bound texture after this.
-            context.gl.BindTexture(gl::TEXTURE_2D, gl_texture);
+            // Initialize the texture, for convenience, leaving the caller's binding as it was.
+            let mut bindings = gl_utils::BindingsGuard::for_texture(
+                &context.gl,
+                gl::TEXTURE_2D,
+                gl::TEXTURE_BINDING_2D,
+            );
+            bindings.bind_texture(gl_texture);
             context
                 .gl
                 .TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
@@ -515,6 +629,30 @@ impl Device {
         Ok(surface_texture.surface)
     }
 
+    /// Destroys the native resources of a surface given up with `surface_into_destroy_token()`.
+    ///
+    /// Unlike `destroy_surface()`, this doesn't need the context that the surface belonged to.
+    pub fn destroy_token(&self, token: SurfaceDestroyToken) -> Result<(), Error> {
+        if let Win32Objects::Texture {
+            gl_dx_interop_object,
+            ..
+        } = token.win32_objects
+        {
+            let dx_interop_functions = WGL_EXTENSION_FUNCTIONS
+                .dx_interop_functions
+                .as_ref()
+                .expect("How did you make a surface without DX interop?");
+            unsafe {
+                let ok = (dx_interop_functions.DXUnregisterObjectNV)(
+                    self.gl_dx_interop_device,
+                    gl_dx_interop_object,
+                );
+                assert_ne!(ok, FALSE);
+            }
+        }
+        Ok(())
+    }
+
     pub(crate) fn lock_surface(&self, surface: &Surface) {
         let mut gl_dx_interop_object = match surface.win32_objects {
             Win32Objects::Widget { .. } => return,
@@ -563,6 +701,24 @@ impl Device {
         }
     }
 
+    // Names the Direct3D texture behind a generic surface with `WKPDID_D3DDebugObjectName`, so
+    // that Direct3D captures show the surface's label on it. Widget surfaces have no texture of
+    // their own.
+    pub(crate) fn set_surface_native_label(&self, surface: &Surface, label: &str) {
+        if let Win32Objects::Texture {
+            ref d3d11_texture, ..
+        } = surface.win32_objects
+        {
+            unsafe {
+                d3d11_texture.SetPrivateData(
+                    &WKPDID_D3DDebugObjectName,
+                    label.len() as UINT,
+                    label.as_ptr() as *const c_void,
+                );
+            }
+        }
+    }
+
     /// Returns a pointer to the underlying surface data for reading or writing by the CPU.
     #[inline]
     pub fn lock_surface_data<'s>(
@@ -580,6 +736,18 @@ impl Device {
         gl::TEXTURE_2D
     }
 
+    /// Fills in the mip chain of a surface texture from the current contents of its surface.
+    ///
+    /// Surface textures on this backend share Direct3D textures that have no mip levels, so this always returns an `UnsupportedOnThisPlatform` error.
+    #[inline]
+    pub fn generate_surface_mipmaps(
+        &self,
+        _: &mut Context,
+        _: &SurfaceTexture,
+    ) -> Result<(), Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
     /// Displays the contents of a widget surface on screen.
     ///
     /// Widget surfaces are internally double-buffered, so changes to them don't show up in their
@@ -587,7 +755,11 @@ impl Device {
     ///
     /// The supplied context must match the context the surface was created with, or an
     /// `IncompatibleSurface` error is returned.
-    pub fn present_surface(&self, _: &Context, surface: &mut Surface) -> Result<(), Error> {
+    pub(crate) fn present_surface_impl(
+        &self,
+        _: &Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
         let window_handle = match surface.win32_objects {
             Win32Objects::Widget { window_handle } => window_handle,
             _ => return Err(Error::NoWidgetAttached),
@@ -602,8 +774,113 @@ impl Device {
         }
     }
 
+    /// Displays the contents of a widget surface on screen, hinting that only the given
+    /// rectangles have changed since the last present.
+    ///
+    /// `SwapBuffers()` always presents the whole window, so the damage is ignored.
+    #[inline]
+    pub(crate) fn present_surface_with_damage_impl(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        _: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        self.present_surface_impl(context, surface)
+    }
+
+    /// Displays the `source` rectangle of a widget surface in the `destination` rectangle of its
+    /// widget, scaling it to fit.
+    ///
+    /// This is not yet supported on WGL, so it always returns `UnsupportedOnThisPlatform`.
+    #[inline]
+    pub(crate) fn present_surface_with_viewport_impl(
+        &self,
+        _: &Context,
+        _: &mut Surface,
+        _: &Rect<i32>,
+        _: &Rect<i32>,
+    ) -> Result<(), Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Declares which parts of a widget surface will be redrawn before it is next presented.
+    ///
+    /// WGL has no notion of partial updates, so this does nothing.
+    #[inline]
+    pub fn set_surface_damage_region(
+        &self,
+        _: &Context,
+        _: &mut Surface,
+        _: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Returns the number of presents ago that the current back buffer of a widget surface was
+    /// displayed, or 0 if its contents are undefined.
+    ///
+    /// WGL doesn't report buffer ages, so this always returns 0.
+    #[inline]
+    pub fn surface_buffer_age(&self, _: &Context, _: &Surface) -> Result<u32, Error> {
+        Ok(0)
+    }
+
+    /// Sets whether presenting a widget surface preserves its contents.
+    ///
+    /// Widget surfaces are created without preservation, so their contents are undefined after
+    /// `present_surface()`. With it, applications that only repaint the dirty regions of each
+    /// frame can draw over the previous one, at the cost of a copy per present on some hardware.
+    ///
+    /// WGL has no way to change the swap behavior of a window once its pixel format is set, so this returns
+    /// `Error::UnsupportedOnThisPlatform`.
+    pub fn set_surface_preserve_buffer(
+        &self,
+        _: &Context,
+        _: &mut Surface,
+        _: bool,
+    ) -> Result<(), Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Sets which way up the contents of a surface are.
+    ///
+    /// Widget surfaces can only be bottom-left, and setting `SurfaceOrigin::TopLeft` on them
+    /// returns `Error::UnsupportedOnThisPlatform`.
+    pub fn set_surface_origin(
+        &self,
+        surface: &mut Surface,
+        origin: SurfaceOrigin,
+    ) -> Result<(), Error> {
+        if let Win32Objects::Widget { .. } = surface.win32_objects {
+            if origin == SurfaceOrigin::TopLeft {
+                return Err(Error::UnsupportedOnThisPlatform);
+            }
+        }
+        surface.origin = origin;
+        Ok(())
+    }
+
+    /// Sets the color space that the contents of a surface are in.
+    ///
+    /// Windows presented through WGL have no color space of their own, so widget surfaces can only
+    /// be sRGB, and setting any other color space on them returns
+    /// `Error::UnsupportedOnThisPlatform`.
+    pub fn set_surface_colorspace(
+        &self,
+        surface: &mut Surface,
+        colorspace: Colorspace,
+    ) -> Result<(), Error> {
+        if let Win32Objects::Widget { .. } = surface.win32_objects {
+            if colorspace != Colorspace::SRGB {
+                return Err(Error::UnsupportedOnThisPlatform);
+            }
+        }
+        surface.colorspace = colorspace;
+        Ok(())
+    }
+
     /// Resizes a widget surface.
-    pub fn resize_surface(
+    pub(crate) fn resize_surface_impl(
         &self,
         _scontext: &Context,
         surface: &mut Surface,
@@ -629,6 +906,86 @@ impl Device {
                 Win32Objects::Texture { gl_framebuffer, .. } => gl_framebuffer,
                 Win32Objects::Widget { .. } => 0,
             },
+            scale_factor: 1.0,
+            transform: SurfaceTransform::Identity,
+            origin: surface.origin,
+            format: match surface.win32_objects {
+                Win32Objects::Texture { .. } => SurfaceFormat::RGBA8,
+                Win32Objects::Widget { .. } => SurfaceFormat::BGRA8,
+            },
+            alpha_mode: surface.alpha_mode,
+            colorspace: surface.colorspace,
+            samples: surface.samples,
+            bound: false,
+        }
+    }
+
+    /// Returns the GPU memory, in bytes, held by the given surface.
+    ///
+    /// Direct3D doesn't report the size of the underlying texture allocation, so this is an
+    /// estimate that counts four bytes per pixel of color storage.
+    #[inline]
+    pub fn surface_memory_usage(&self, surface: &Surface) -> usize {
+        estimate_memory_usage(&self.surface_info(surface).size)
+    }
+
+    /// Returns `Error::UnsupportedOnThisPlatform`, as Direct3D textures have no modification
+    /// counter.
+    #[inline]
+    pub fn surface_seed(&self, _: &Surface) -> Result<u32, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Returns the stride, row alignment, and total size of the memory that a generic surface of
+    /// the given size would be allocated with, without creating one.
+    ///
+    /// Direct3D keeps the row pitch of its textures to itself until they're mapped, so this is
+    /// the tightly packed layout that readbacks use.
+    #[inline]
+    pub fn surface_layout_requirements(
+        &self,
+        size: &Size2D<i32>,
+        _: SurfaceFormat,
+        _: SurfaceAccess,
+    ) -> Result<SurfaceLayoutRequirements, Error> {
+        Ok(SurfaceLayoutRequirements::packed(size))
+    }
+
+    /// Returns timing information about the most recently displayed frame of a widget surface.
+    ///
+    /// Presentation timing isn't available on this backend, so this always returns `None`.
+    #[inline]
+    pub fn surface_presentation_statistics(&self, _: &Surface) -> Option<PresentationStatistics> {
+        None
+    }
+
+    /// Returns whether a widget surface can currently be seen on screen.
+    ///
+    /// Windows doesn't report whether a window is covered by other windows, so this only reports
+    /// `Occluded` for windows that are hidden or minimized.
+    pub fn surface_visibility(&self, surface: &Surface) -> SurfaceVisibility {
+        match surface.win32_objects {
+            Win32Objects::Widget { window_handle } => unsafe {
+                if winuser::IsWindowVisible(window_handle) == FALSE
+                    || winuser::IsIconic(window_handle) != FALSE
+                {
+                    SurfaceVisibility::Occluded
+                } else {
+                    SurfaceVisibility::Visible
+                }
+            },
+            Win32Objects::Texture { .. } => SurfaceVisibility::Unknown,
+        }
+    }
+
+    /// Returns the monitor that a widget surface is shown on, and its bit depth and color space
+    /// as reported by DXGI.
+    pub fn widget_output_info(&self, surface: &Surface) -> Result<WidgetOutputInfo, Error> {
+        match surface.win32_objects {
+            Win32Objects::Widget { window_handle } => {
+                Ok(monitors::window_output_info(window_handle))
+            }
+            Win32Objects::Texture { .. } => Err(Error::NoWidgetAttached),
         }
     }
 
@@ -643,16 +1000,98 @@ impl Device {
 
 impl Surface {
     pub(crate) fn id(&self) -> SurfaceID {
-        match self.win32_objects {
-            Win32Objects::Texture {
-                ref d3d11_texture, ..
-            } => SurfaceID((*d3d11_texture).as_raw() as usize),
-            Win32Objects::Widget { window_handle } => SurfaceID(window_handle as usize),
+        self.id
+    }
+
+    /// Gives up this surface, returning a token that can be destroyed later with
+    /// `Device::destroy_token()`, on any thread and without the surface's context.
+    ///
+    /// The OpenGL objects that the surface owns in its context, such as its framebuffer, aren't
+    /// part of the token. They're freed when the context is destroyed.
+    pub(crate) fn into_destroy_token(mut self) -> SurfaceDestroyToken {
+        self.destroyed = true;
+        let placeholder = Win32Objects::Widget {
+            window_handle: ptr::null_mut(),
+        };
+        let mut win32_objects = mem::replace(&mut self.win32_objects, placeholder);
+        if let Win32Objects::Texture {
+            ref mut gl_texture,
+            ref mut gl_framebuffer,
+            ref mut renderbuffers,
+            ..
+        } = win32_objects
+        {
+            *gl_texture = 0;
+            *gl_framebuffer = 0;
+            renderbuffers.abandon();
         }
+        SurfaceDestroyToken { win32_objects }
     }
 }
 
+// Has DWM blend the window with what is behind it, using the alpha channel of its contents. DWM
+// ignores the alpha of OpenGL windows otherwise; the empty blur region turns on blending without
+// blurring anything. This does nothing if desktop composition is off.
+unsafe fn enable_window_transparency(window_handle: HWND) {
+    let mut composition_enabled = FALSE;
+    if dwmapi::DwmIsCompositionEnabled(&mut composition_enabled) != winerror::S_OK
+        || composition_enabled == FALSE
+    {
+        return;
+    }
+    let region = wingdi::CreateRectRgn(0, 0, -1, -1);
+    let blur_behind = DWM_BLURBEHIND {
+        dwFlags: DWM_BB_ENABLE | DWM_BB_BLURREGION,
+        fEnable: TRUE,
+        hRgnBlur: region,
+        fTransitionOnMaximized: FALSE,
+    };
+    dwmapi::DwmEnableBlurBehindWindow(window_handle, &blur_behind);
+    wingdi::DeleteObject(region as HGDIOBJ);
+}
+
+/// The native resources of a surface given up with `Device::surface_into_destroy_token()`, which
+/// can be destroyed later without the surface's context.
+///
+/// Tokens can be sent to other threads, and must be destroyed with `Device::destroy_token()`, or
+/// their resources leak.
+pub struct SurfaceDestroyToken {
+    win32_objects: Win32Objects,
+}
+
+unsafe impl Send for SurfaceDestroyToken {}
+
 /// Represents the CPU view of the pixel data of this surface.
 pub struct SurfaceDataGuard<'a> {
     phantom: PhantomData<&'a ()>,
 }
+
+#[cfg(feature = "sm-opencl")]
+impl Device {
+    /// Returns `Error::UnsupportedOnThisPlatform`, as WGL surfaces have no EGL image.
+    #[inline]
+    pub fn surface_egl_image(&self, _: &Surface) -> Result<SharedEGLImage, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+}
+
+#[cfg(feature = "sm-ffmpeg")]
+impl Device {
+    /// Returns the Direct3D 11 texture backing the given surface, for wrapping in an FFmpeg
+    /// D3D11VA frame.
+    ///
+    /// The texture belongs to the Direct3D device of this device, which FFmpeg's D3D11VA device
+    /// context must be created with. Widget surfaces have no texture; this returns
+    /// `Error::WidgetAttached` for them.
+    pub fn surface_d3d11_texture(
+        &self,
+        surface: &Surface,
+    ) -> Result<ComPtr<ID3D11Texture2D>, Error> {
+        match surface.win32_objects {
+            Win32Objects::Texture {
+                ref d3d11_texture, ..
+            } => Ok(d3d11_texture.clone()),
+            Win32Objects::Widget { .. } => Err(Error::WidgetAttached),
+        }
+    }
+}
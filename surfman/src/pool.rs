@@ -0,0 +1,224 @@
+// surfman/surfman/src/pool.rs
+//
+//! A pool of idle generic surfaces that are reused instead of reallocated, kept within a soft
+//! memory budget.
+//!
+//! Long-lived compositors that recycle surfaces as content comes and goes can otherwise
+//! accumulate idle surfaces without bound.
+
+use crate::context::ContextID;
+use crate::device::Device;
+use crate::{Error, SurfaceAccess, SurfaceAlphaMode, SurfaceInfo, SurfaceType, SurfaceUsage};
+
+use euclid::default::Size2D;
+use std::collections::VecDeque;
+
+type EvictionCallback = Box<dyn FnMut(&SurfaceInfo, usize)>;
+
+/// Idle generic surfaces of one context, available for reuse.
+///
+/// When a soft memory budget is set with `set_memory_budget()` and the memory usage of the device
+/// exceeds it, the pool destroys its least recently released surfaces until the device is back
+/// within budget or the pool is empty, and reports each one to the eviction callback. Surfaces
+/// that are in use are never evicted, so the budget can still be exceeded.
+///
+/// Memory usage is measured with `Device::memory_usage()`. The pool must be emptied with
+/// `destroy()` before it's dropped, as each of its surfaces must be destroyed.
+pub struct SurfacePool<D>
+where
+    D: Device,
+{
+    context_id: ContextID,
+    // Least recently released first.
+    idle_surfaces: VecDeque<D::Surface>,
+    memory_budget: Option<usize>,
+    eviction_callback: Option<EvictionCallback>,
+}
+
+impl<D> SurfacePool<D>
+where
+    D: Device,
+{
+    /// Creates an empty pool for surfaces of the given context, with no memory budget.
+    pub fn new(device: &D, context: &D::Context) -> SurfacePool<D> {
+        SurfacePool {
+            context_id: device.context_id(context),
+            idle_surfaces: VecDeque::new(),
+            memory_budget: None,
+            eviction_callback: None,
+        }
+    }
+
+    /// Sets the soft budget, in bytes, for the memory usage of the device, or removes it if
+    /// `None`.
+    ///
+    /// The budget is enforced the next time a surface is released or `evict()` is called.
+    #[inline]
+    pub fn set_memory_budget(&mut self, memory_budget: Option<usize>) {
+        self.memory_budget = memory_budget;
+    }
+
+    /// Returns the soft budget, in bytes, for the memory usage of the device, if any.
+    #[inline]
+    pub fn memory_budget(&self) -> Option<usize> {
+        self.memory_budget
+    }
+
+    /// Sets a callback that is called with the information and memory usage of each surface that
+    /// the pool evicts, right after it's destroyed.
+    pub fn set_eviction_callback<F>(&mut self, callback: F)
+    where
+        F: FnMut(&SurfaceInfo, usize) + 'static,
+    {
+        self.eviction_callback = Some(Box::new(callback));
+    }
+
+    /// Returns the number of idle surfaces in the pool.
+    #[inline]
+    pub fn idle_surface_count(&self) -> usize {
+        self.idle_surfaces.len()
+    }
+
+    /// Takes an idle surface of the given size out of the pool, or creates a new GPU-only generic
+    /// surface if there is none.
+    ///
+    /// The given context must be the one the pool was created for, or an `IncompatibleContext`
+    /// error is returned. Reused surfaces keep whatever contents they had.
+    pub fn acquire(
+        &mut self,
+        device: &mut D,
+        context: &D::Context,
+        size: &Size2D<i32>,
+    ) -> Result<D::Surface, Error> {
+        if device.context_id(context) != self.context_id {
+            return Err(Error::IncompatibleContext);
+        }
+
+        let index = self
+            .idle_surfaces
+            .iter()
+            .position(|surface| device.surface_info(surface).size == *size);
+        if let Some(surface) = index.and_then(|index| self.idle_surfaces.remove(index)) {
+            return Ok(surface);
+        }
+
+        device.create_surface(
+            context,
+            SurfaceAccess::GPUOnly,
+            SurfaceUsage::default(),
+            SurfaceAlphaMode::default(),
+            SurfaceType::Generic { size: *size },
+        )
+    }
+
+    /// Fills the pool with `count` new GPU-only generic surfaces of the given size, created in one
+    /// batch with `Device::create_surfaces()`.
+    ///
+    /// The given context must be the one the pool was created for, or an `IncompatibleContext`
+    /// error is returned. If a surface fails to be created, the ones created before it are still
+    /// added to the pool. The memory budget isn't enforced until the next release or `evict()`.
+    pub fn prewarm(
+        &mut self,
+        device: &mut D,
+        context: &D::Context,
+        size: &Size2D<i32>,
+        count: usize,
+    ) -> Result<(), Error> {
+        if device.context_id(context) != self.context_id {
+            return Err(Error::IncompatibleContext);
+        }
+
+        match device.create_surfaces(
+            context,
+            SurfaceAccess::GPUOnly,
+            SurfaceUsage::default(),
+            SurfaceAlphaMode::default(),
+            size,
+            count,
+        ) {
+            Ok(surfaces) => {
+                self.idle_surfaces.extend(surfaces);
+                Ok(())
+            }
+            Err((err, surfaces)) => {
+                self.idle_surfaces.extend(surfaces);
+                Err(err)
+            }
+        }
+    }
+
+    /// Puts a generic surface back into the pool for reuse, then evicts idle surfaces as needed
+    /// to honor the memory budget.
+    ///
+    /// The surface must belong to the context the pool was created for, which must be the given
+    /// one; otherwise, it's handed back along with an `IncompatibleSurface` error. If an evicted
+    /// surface fails to be destroyed, the error is logged and the surface stays in the pool.
+    pub fn release(
+        &mut self,
+        device: &D,
+        context: &mut D::Context,
+        surface: D::Surface,
+    ) -> Result<(), (Error, D::Surface)> {
+        if device.surface_info(&surface).context_id != self.context_id
+            || device.context_id(context) != self.context_id
+        {
+            return Err((Error::IncompatibleSurface, surface));
+        }
+
+        self.idle_surfaces.push_back(surface);
+        if let Err(err) = self.evict(device, context) {
+            error!("Failed to evict a surface from the pool: {:?}", err);
+        }
+        Ok(())
+    }
+
+    /// Destroys the least recently released idle surfaces until the memory usage of the device is
+    /// within the budget or the pool is empty.
+    ///
+    /// This does nothing if there's no budget. The given context must be the one the pool was
+    /// created for, or an `IncompatibleContext` error is returned.
+    pub fn evict(&mut self, device: &D, context: &mut D::Context) -> Result<(), Error> {
+        if device.context_id(context) != self.context_id {
+            return Err(Error::IncompatibleContext);
+        }
+
+        let memory_budget = match self.memory_budget {
+            None => return Ok(()),
+            Some(memory_budget) => memory_budget,
+        };
+        while device.memory_usage() > memory_budget {
+            let mut surface = match self.idle_surfaces.pop_front() {
+                None => break,
+                Some(surface) => surface,
+            };
+            let surface_info = device.surface_info(&surface);
+            let memory_usage = device.surface_memory_usage(&surface);
+            if let Err(err) = device.destroy_surface(context, &mut surface) {
+                self.idle_surfaces.push_front(surface);
+                return Err(err);
+            }
+            if let Some(ref mut eviction_callback) = self.eviction_callback {
+                eviction_callback(&surface_info, memory_usage);
+            }
+        }
+        Ok(())
+    }
+
+    /// Destroys all idle surfaces in the pool, without reporting them to the eviction callback.
+    ///
+    /// The given context must be the one the pool was created for, or an `IncompatibleContext`
+    /// error is returned.
+    pub fn destroy(&mut self, device: &D, context: &mut D::Context) -> Result<(), Error> {
+        if device.context_id(context) != self.context_id {
+            return Err(Error::IncompatibleContext);
+        }
+
+        while let Some(mut surface) = self.idle_surfaces.pop_front() {
+            if let Err(err) = device.destroy_surface(context, &mut surface) {
+                self.idle_surfaces.push_front(surface);
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+}
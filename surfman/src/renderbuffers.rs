@@ -28,13 +28,18 @@ impl Drop for Renderbuffers {
 }
 
 impl Renderbuffers {
+    // Creates the depth and stencil renderbuffers that the attributes ask for. The renderbuffer
+    // binding is left as it was.
     pub(crate) fn new(
         gl: &Gl,
         size: &Size2D<i32>,
         attributes: &ContextAttributes,
     ) -> Renderbuffers {
         unsafe {
-            if attributes
+            let mut old_renderbuffer = 0;
+            gl.GetIntegerv(gl::RENDERBUFFER_BINDING, &mut old_renderbuffer);
+
+            let renderbuffers = if attributes
                 .flags
                 .contains(ContextAttributeFlags::DEPTH | ContextAttributeFlags::STENCIL)
             {
@@ -47,37 +52,37 @@ impl Renderbuffers {
                     size.width,
                     size.height,
                 );
-                gl.BindRenderbuffer(gl::RENDERBUFFER, 0);
-                return Renderbuffers::CombinedDepthStencil(renderbuffer);
-            }
-
-            let (mut depth_renderbuffer, mut stencil_renderbuffer) = (0, 0);
-            if attributes.flags.contains(ContextAttributeFlags::DEPTH) {
-                gl.GenRenderbuffers(1, &mut depth_renderbuffer);
-                gl.BindRenderbuffer(gl::RENDERBUFFER, depth_renderbuffer);
-                gl.RenderbufferStorage(
-                    gl::RENDERBUFFER,
-                    gl::DEPTH_COMPONENT24,
-                    size.width,
-                    size.height,
-                );
-            }
-            if attributes.flags.contains(ContextAttributeFlags::STENCIL) {
-                gl.GenRenderbuffers(1, &mut stencil_renderbuffer);
-                gl.BindRenderbuffer(gl::RENDERBUFFER, stencil_renderbuffer);
-                gl.RenderbufferStorage(
-                    gl::RENDERBUFFER,
-                    gl::STENCIL_INDEX8,
-                    size.width,
-                    size.height,
-                );
-            }
-            gl.BindRenderbuffer(gl::RENDERBUFFER, 0);
+                Renderbuffers::CombinedDepthStencil(renderbuffer)
+            } else {
+                let (mut depth_renderbuffer, mut stencil_renderbuffer) = (0, 0);
+                if attributes.flags.contains(ContextAttributeFlags::DEPTH) {
+                    gl.GenRenderbuffers(1, &mut depth_renderbuffer);
+                    gl.BindRenderbuffer(gl::RENDERBUFFER, depth_renderbuffer);
+                    gl.RenderbufferStorage(
+                        gl::RENDERBUFFER,
+                        gl::DEPTH_COMPONENT24,
+                        size.width,
+                        size.height,
+                    );
+                }
+                if attributes.flags.contains(ContextAttributeFlags::STENCIL) {
+                    gl.GenRenderbuffers(1, &mut stencil_renderbuffer);
+                    gl.BindRenderbuffer(gl::RENDERBUFFER, stencil_renderbuffer);
+                    gl.RenderbufferStorage(
+                        gl::RENDERBUFFER,
+                        gl::STENCIL_INDEX8,
+                        size.width,
+                        size.height,
+                    );
+                }
+                Renderbuffers::IndividualDepthStencil {
+                    depth: depth_renderbuffer,
+                    stencil: stencil_renderbuffer,
+                }
+            };
 
-            Renderbuffers::IndividualDepthStencil {
-                depth: depth_renderbuffer,
-                stencil: stencil_renderbuffer,
-            }
+            gl.BindRenderbuffer(gl::RENDERBUFFER, old_renderbuffer as GLuint);
+            renderbuffers
         }
     }
 
@@ -121,8 +126,6 @@ impl Renderbuffers {
 
     pub(crate) fn destroy(&mut self, gl: &Gl) {
         unsafe {
-            gl.BindRenderbuffer(gl::RENDERBUFFER, 0);
-
             match *self {
                 Renderbuffers::CombinedDepthStencil(ref mut renderbuffer) => {
                     if *renderbuffer != 0 {
@@ -146,4 +149,19 @@ impl Renderbuffers {
             }
         }
     }
+
+    // Forgets the renderbuffers without deleting them, for when their context isn't available.
+    // They're freed along with the context.
+    pub(crate) fn abandon(&mut self) {
+        match *self {
+            Renderbuffers::CombinedDepthStencil(ref mut renderbuffer) => *renderbuffer = 0,
+            Renderbuffers::IndividualDepthStencil {
+                ref mut depth,
+                ref mut stencil,
+            } => {
+                *depth = 0;
+                *stencil = 0;
+            }
+        }
+    }
 }
@@ -0,0 +1,118 @@
+// surfman/surfman/src/sendable.rs
+//
+//! Handing surface textures off to other threads.
+
+use crate::context::ContextID;
+use crate::device::Device;
+use crate::gl;
+use crate::gl::types::GLsync;
+use crate::{Error, Gl};
+
+use std::ptr;
+
+/// A surface texture on its way to another thread.
+///
+/// Surface textures aren't `Send`, because they belong to the context they were created in, and
+/// the commands that fill them in may still be in flight when they change hands. This wrapper
+/// captures a fence after those commands when it's created, and waits for that fence when it's
+/// unwrapped with `into_surface_texture()` on the destination thread.
+///
+/// The destination thread must unwrap it with the original context, or with a context that shares
+/// objects with it (see the `share_with` argument of `Device::create_context()`). Like the surface
+/// texture itself, the wrapper must be unwrapped and the texture destroyed, or a panic will occur.
+pub struct SendableSurfaceTexture<D>
+where
+    D: Device,
+{
+    surface_texture: D::SurfaceTexture,
+    context_id: ContextID,
+    // Null if the context has no fences, in which case the commands were finished instead.
+    fence: GLsync,
+}
+
+// The surface texture holds the surface it wraps, so that must be `Send` for its native buffer to
+// change threads. The texture object itself is only used again after `into_surface_texture()` has
+// checked that the context it's unwrapped with can see it.
+unsafe impl<D> Send for SendableSurfaceTexture<D>
+where
+    D: Device,
+    D::Surface: Send,
+{
+}
+
+impl<D> SendableSurfaceTexture<D>
+where
+    D: Device,
+{
+    /// Wraps a surface texture for sending to another thread.
+    ///
+    /// The context must be the one that the surface texture was created in. It's made current,
+    /// and a fence is inserted after the commands submitted to it so far. Contexts without
+    /// `glFenceSync()` finish those commands instead.
+    pub fn new(
+        device: &D,
+        context: &D::Context,
+        surface_texture: D::SurfaceTexture,
+    ) -> Result<SendableSurfaceTexture<D>, (Error, D::SurfaceTexture)> {
+        if let Err(err) = device.make_context_current(context) {
+            return Err((err, surface_texture));
+        }
+
+        let gl = Gl::load_with(|symbol| device.get_proc_address(context, symbol));
+        let fence = unsafe {
+            let fence = if gl.FenceSync.is_loaded() {
+                gl.FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0)
+            } else {
+                ptr::null()
+            };
+            if fence.is_null() {
+                gl.Finish();
+            } else {
+                // Other contexts can only wait for the fence once it has been flushed.
+                gl.Flush();
+            }
+            fence
+        };
+
+        Ok(SendableSurfaceTexture {
+            surface_texture,
+            context_id: device.context_id(context),
+            fence,
+        })
+    }
+
+    /// Returns the ID of the context that the surface texture was created in.
+    #[inline]
+    pub fn context_id(&self) -> ContextID {
+        self.context_id
+    }
+
+    /// Unwraps the surface texture on the destination thread, once the commands before the fence
+    /// have finished.
+    ///
+    /// The context is made current, and must be the original context or share objects with it.
+    /// The fence is the object whose visibility is checked: if the context can't see it, this
+    /// returns an `IncompatibleSurfaceTexture` error along with the untouched wrapper.
+    pub fn into_surface_texture(
+        self,
+        device: &D,
+        context: &D::Context,
+    ) -> Result<D::SurfaceTexture, (Error, SendableSurfaceTexture<D>)> {
+        if let Err(err) = device.make_context_current(context) {
+            return Err((err, self));
+        }
+        if self.fence.is_null() {
+            return Ok(self.surface_texture);
+        }
+
+        let gl = Gl::load_with(|symbol| device.get_proc_address(context, symbol));
+        unsafe {
+            if gl.IsSync(self.fence) == gl::FALSE {
+                return Err((Error::IncompatibleSurfaceTexture, self));
+            }
+            gl.ClientWaitSync(self.fence, 0, gl::TIMEOUT_IGNORED);
+            gl.DeleteSync(self.fence);
+        }
+        Ok(self.surface_texture)
+    }
+}
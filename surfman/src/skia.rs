@@ -0,0 +1,126 @@
+// surfman/surfman/src/skia.rs
+//
+//! Descriptions of surfaces in the terms that Skia's OpenGL backend wraps textures and
+//! framebuffers in.
+//!
+//! These carry what Skia's `GrGLTextureInfo` and `GrGLFramebufferInfo` need, plus the size,
+//! sample count, and stencil bits that go into a `GrBackendTexture` or `GrBackendRenderTarget`,
+//! so that Skia-based UIs can draw into and sample from surfman surfaces without knowing which
+//! texture target or format each platform uses. Surfman surfaces have their origin at the lower
+//! left corner, as in OpenGL, so they should be wrapped with `kBottomLeft_GrSurfaceOrigin`.
+
+use crate::device::Device;
+use crate::gl;
+use crate::gl::types::{GLenum, GLuint};
+use crate::{ContextAttributeFlags, Error};
+
+use euclid::default::Size2D;
+
+// Not in the OpenGL ES bindings, though no backend that uses them has rectangle textures.
+const GL_TEXTURE_RECTANGLE: GLenum = 0x84f5;
+const GL_TEXTURE_EXTERNAL_OES: GLenum = 0x8d65;
+
+/// What Skia needs to wrap a surface texture in a `GrBackendTexture`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BackendTexture {
+    /// The size of the texture, in pixels.
+    pub size: Size2D<i32>,
+    /// The OpenGL texture target: `GL_TEXTURE_2D`, `GL_TEXTURE_RECTANGLE`, or
+    /// `GL_TEXTURE_EXTERNAL_OES`. Skia can only sample from external textures, not render to them.
+    ///
+    /// This is the `fTarget` of `GrGLTextureInfo`.
+    pub target: GLenum,
+    /// The OpenGL texture object.
+    ///
+    /// This is the `fID` of `GrGLTextureInfo`.
+    pub texture_object: GLuint,
+    /// The sized internal format of the texture, which is always `GL_RGBA8`.
+    ///
+    /// This is the `fFormat` of `GrGLTextureInfo`. Shaders see the contents of surfaces as RGBA
+    /// whatever the order of their native storage, so Skia must too.
+    pub format: GLenum,
+}
+
+/// What Skia needs to wrap the surface bound to a context in a `GrBackendRenderTarget`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BackendRenderTarget {
+    /// The size of the surface, in device pixels.
+    pub size: Size2D<i32>,
+    /// The number of samples per pixel, which is always 0, as surfman surfaces aren't
+    /// multisampled.
+    pub sample_count: usize,
+    /// The number of bits in the stencil buffer: 8 if the context was created with the `STENCIL`
+    /// flag, and 0 otherwise.
+    pub stencil_bits: usize,
+    /// The OpenGL framebuffer object that renders to the surface.
+    ///
+    /// This is the `fFBOID` of `GrGLFramebufferInfo`.
+    pub framebuffer_object: GLuint,
+    /// The sized internal format of the surface, which is always `GL_RGBA8`.
+    ///
+    /// This is the `fFormat` of `GrGLFramebufferInfo`.
+    pub format: GLenum,
+}
+
+/// Describes a surface texture for Skia.
+///
+/// `size` is that of the surface that the texture was created from. Skia has no array textures,
+/// so surface textures of layered surfaces return `Error::IncompatibleSurfaceTexture`.
+pub fn backend_texture<D>(
+    device: &D,
+    surface_texture: &D::SurfaceTexture,
+    size: &Size2D<i32>,
+) -> Result<BackendTexture, Error>
+where
+    D: Device,
+{
+    let target = device.surface_texture_binding_target(surface_texture);
+    match target {
+        gl::TEXTURE_2D | GL_TEXTURE_RECTANGLE | GL_TEXTURE_EXTERNAL_OES => {}
+        _ => return Err(Error::IncompatibleSurfaceTexture),
+    }
+
+    Ok(BackendTexture {
+        size: *size,
+        target,
+        texture_object: device.surface_texture_object(surface_texture),
+        format: gl::RGBA8,
+    })
+}
+
+/// Describes the surface bound to the given context for Skia, or returns `None` if no surface is
+/// bound.
+///
+/// As with `Device::context_surface_info()`, the framebuffer object is only valid while the
+/// surface stays bound to the context.
+pub fn backend_render_target<D>(
+    device: &D,
+    context: &D::Context,
+) -> Result<Option<BackendRenderTarget>, Error>
+where
+    D: Device,
+{
+    let surface_info = match device.context_surface_info(context)? {
+        None => return Ok(None),
+        Some(surface_info) => surface_info,
+    };
+
+    let context_descriptor = device.context_descriptor(context);
+    let context_attributes = device.context_descriptor_attributes(&context_descriptor);
+    let stencil_bits = if context_attributes
+        .flags
+        .contains(ContextAttributeFlags::STENCIL)
+    {
+        8
+    } else {
+        0
+    };
+
+    Ok(Some(BackendRenderTarget {
+        size: surface_info.size,
+        sample_count: 0,
+        stencil_bits,
+        framebuffer_object: surface_info.framebuffer_object,
+        format: gl::RGBA8,
+    }))
+}
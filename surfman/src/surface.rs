@@ -3,16 +3,19 @@
 //! Information related to hardware surfaces.
 
 use crate::context::ContextID;
+use crate::{Colorspace, Error};
 
 use crate::gl::types::GLuint;
 use euclid::default::Size2D;
 use std::fmt::{self, Display, Formatter};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 
 /// Various data about the surface.
 pub struct SystemSurfaceInfo {
     /// The surface's size, in device pixels.
     pub size: Size2D<i32>,
-    /// The ID of the surface. This should be globally unique for each currently-allocated surface.
+    /// The ID of the surface, which no other surface in this process has had or will have.
     pub id: SurfaceID,
 }
 
@@ -20,7 +23,7 @@ pub struct SystemSurfaceInfo {
 pub struct SurfaceInfo {
     /// The surface's size, in device pixels.
     pub size: Size2D<i32>,
-    /// The ID of the surface. This should be globally unique for each currently-allocated surface.
+    /// The ID of the surface, which no other surface in this process has had or will have.
     pub id: SurfaceID,
     /// The ID of the context that this surface belongs to.
     pub context_id: ContextID,
@@ -28,6 +31,164 @@ pub struct SurfaceInfo {
     ///
     /// This is only valid when the surface is actually attached to a context.
     pub framebuffer_object: GLuint,
+    /// The ratio of device pixels to logical pixels that the surface is displayed at.
+    ///
+    /// This is 1.0 unless the windowing system reports a preferred scale for the widget that the
+    /// surface is attached to. On Wayland compositors that support fractional scaling, this may
+    /// be a non-integer value like 1.5, in which case rendering at this scale avoids blurriness.
+    pub scale_factor: f64,
+    /// The rotation that rendering to the surface must apply for its contents to appear upright.
+    ///
+    /// This is `Identity` unless the surface is a widget surface that was asked to pre-rotate
+    /// its contents to match the orientation of the display, which spares the compositor from
+    /// rotating every frame. When it is `Rotate90` or `Rotate270`, `size` is that of the rotated
+    /// buffer, so its width and height are swapped relative to the widget.
+    pub transform: SurfaceTransform,
+    /// Which way up the contents of the surface are.
+    ///
+    /// Widget surfaces are displayed accordingly, and consumers of the surface's texture or
+    /// native storage should sample it accordingly. This is `BottomLeft`, OpenGL's convention,
+    /// unless it was changed with `set_surface_origin()`.
+    pub origin: SurfaceOrigin,
+    /// The order of the color channels of the surface's pixels in memory.
+    ///
+    /// Shaders always see the contents as RGBA, but this is the order that consumers of the
+    /// native storage of the surface, such as exported buffers or `lock_surface_data()`, see.
+    /// Use `convert_surface_format()` to get a copy of a surface in a different order.
+    pub format: SurfaceFormat,
+    /// How the alpha channel of the surface is to be interpreted, as given when it was created.
+    ///
+    /// Consumers of the surface's texture or native storage should blend its contents
+    /// accordingly.
+    pub alpha_mode: SurfaceAlphaMode,
+    /// The color space that the contents of the surface are in.
+    ///
+    /// This is `SRGB` unless it was changed with `set_surface_colorspace()`. Widget surfaces are
+    /// tagged with it, so that the compositor doesn't reinterpret wide-gamut contents as sRGB.
+    pub colorspace: Colorspace,
+    /// The number of samples per pixel of the surface's framebuffer.
+    ///
+    /// This is the count negotiated from `ContextAttributes::samples` for widget surfaces whose
+    /// framebuffer is provided by the window system, and 1 for all others, including every
+    /// generic surface.
+    pub samples: u32,
+    /// Whether the surface is bound to its context.
+    ///
+    /// A bound surface is owned by its context, so this is only true of the information returned
+    /// by `context_surface_info()`.
+    pub bound: bool,
+}
+
+/// The order of the color channels of the pixels of a surface in memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SurfaceFormat {
+    /// Red, green, blue, and alpha, one byte each.
+    RGBA8,
+    /// Blue, green, red, and alpha, one byte each.
+    BGRA8,
+}
+
+/// How the alpha channel of a surface is interpreted when its contents are presented or sampled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SurfaceAlphaMode {
+    /// The alpha channel is ignored, and the surface is treated as fully opaque.
+    Opaque,
+    /// The color channels have already been multiplied by alpha. This is what blending with
+    /// `glBlendFunc(GL_ONE, GL_ONE_MINUS_SRC_ALPHA)` produces, and what window systems composite.
+    Premultiplied,
+    /// The color channels have not been multiplied by alpha.
+    ///
+    /// Window systems only composite premultiplied contents, so widget surfaces can't be created
+    /// with this mode.
+    Straight,
+}
+
+impl Default for SurfaceAlphaMode {
+    #[inline]
+    fn default() -> SurfaceAlphaMode {
+        SurfaceAlphaMode::Premultiplied
+    }
+}
+
+/// A clockwise rotation of the contents of a surface.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SurfaceTransform {
+    /// No rotation.
+    Identity,
+    /// A rotation by 90 degrees clockwise.
+    Rotate90,
+    /// A rotation by 180 degrees.
+    Rotate180,
+    /// A rotation by 270 degrees clockwise.
+    Rotate270,
+}
+
+impl SurfaceTransform {
+    /// Returns the rotation that undoes this one.
+    #[inline]
+    pub fn inverse(self) -> SurfaceTransform {
+        match self {
+            SurfaceTransform::Identity => SurfaceTransform::Identity,
+            SurfaceTransform::Rotate90 => SurfaceTransform::Rotate270,
+            SurfaceTransform::Rotate180 => SurfaceTransform::Rotate180,
+            SurfaceTransform::Rotate270 => SurfaceTransform::Rotate90,
+        }
+    }
+
+    /// Returns true if this rotation swaps the width and height of the surface.
+    #[inline]
+    pub fn swaps_dimensions(self) -> bool {
+        match self {
+            SurfaceTransform::Rotate90 | SurfaceTransform::Rotate270 => true,
+            SurfaceTransform::Identity | SurfaceTransform::Rotate180 => false,
+        }
+    }
+}
+
+/// Which way up the contents of a surface are.
+///
+/// OpenGL puts the origin of window coordinates at the lower left, so the first row of a
+/// framebuffer is the bottom of the image. Renderers that put the origin at the upper left, as
+/// Direct3D, Metal, and Vulkan do, produce images that are upside down by that convention.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SurfaceOrigin {
+    /// The first row of the surface is the bottom of the image.
+    BottomLeft,
+    /// The first row of the surface is the top of the image.
+    TopLeft,
+}
+
+/// Timing information about a frame of a widget surface that was displayed on screen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PresentationStatistics {
+    /// The number of the frame, counting from 1 for the first present of the surface.
+    pub frame: u64,
+    /// The time at which the frame started to be scanned out.
+    ///
+    /// This is measured on the presentation clock of the platform, which is usually the system
+    /// monotonic clock (`CLOCK_MONOTONIC` on Unix).
+    pub presentation_time: Duration,
+    /// The predicted interval until the next refresh of the output the frame was displayed on.
+    ///
+    /// Adding this to `presentation_time` gives the earliest time the next frame can be shown.
+    /// This is `None` if the output has a variable refresh rate or the platform doesn't know.
+    pub refresh_interval: Option<Duration>,
+    /// The value of the output's vertical retrace counter when the frame was displayed, if known.
+    pub sequence: Option<u64>,
+}
+
+/// Whether a widget surface can currently be seen on screen.
+///
+/// Renderers can use this to throttle or skip frames while their window is hidden.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SurfaceVisibility {
+    /// At least part of the surface may be visible.
+    Visible,
+    /// The surface is known not to be visible, because its window is minimized, unmapped, or
+    /// completely covered by other windows.
+    Occluded,
+    /// The platform doesn't report visibility, or the surface is not a widget surface.
+    Unknown,
 }
 
 // The default framebuffer for a context.
@@ -37,15 +198,61 @@ pub(crate) enum Framebuffer<S, E> {
     None,
     // The context is externally-managed.
     External(E),
+    // The context renders to a framebuffer object that the caller owns.
+    ExternalFramebuffer(ExternalFramebuffer),
     // The context renders to a surface.
     Surface(S),
 }
 
+// A framebuffer object, created and owned by the caller, that a context renders to.
+pub(crate) struct ExternalFramebuffer {
+    pub(crate) id: SurfaceID,
+    pub(crate) framebuffer_object: GLuint,
+    pub(crate) size: Size2D<i32>,
+}
+
+impl ExternalFramebuffer {
+    pub(crate) fn new(framebuffer_object: GLuint, size: Size2D<i32>) -> ExternalFramebuffer {
+        ExternalFramebuffer {
+            id: SurfaceID::next(),
+            framebuffer_object,
+            size,
+        }
+    }
+
+    pub(crate) fn info(&self, context_id: ContextID) -> SurfaceInfo {
+        SurfaceInfo {
+            size: self.size,
+            id: self.id,
+            context_id,
+            framebuffer_object: self.framebuffer_object,
+            scale_factor: 1.0,
+            transform: SurfaceTransform::Identity,
+            origin: SurfaceOrigin::BottomLeft,
+            format: SurfaceFormat::RGBA8,
+            alpha_mode: SurfaceAlphaMode::Premultiplied,
+            colorspace: Colorspace::SRGB,
+            samples: 1,
+            bound: true,
+        }
+    }
+}
+
 /// A unique ID per allocated surface.
 ///
-/// If you destroy a surface and then create a new one, the ID may be reused.
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub struct SurfaceID(pub usize);
+/// IDs are never reused within a process, so they can key caches that outlive the surfaces in
+/// them. A surface keeps its ID for its whole lifetime, including across resizes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SurfaceID(pub u64);
+
+static NEXT_SURFACE_ID: AtomicU64 = AtomicU64::new(1);
+
+impl SurfaceID {
+    // Allocates the ID of a newly-created surface.
+    pub(crate) fn next() -> SurfaceID {
+        SurfaceID(NEXT_SURFACE_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
 
 impl Display for SurfaceID {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
@@ -92,6 +299,62 @@ pub enum SurfaceAccess {
     GPUCPUWriteCombined,
 }
 
+bitflags! {
+    /// How a surface will be used, which lets the backend pick the placement and layout of its
+    /// memory to suit.
+    ///
+    /// These are hints. Backends act on the flags that they can and ignore the rest, and a
+    /// surface may still be used in ways that weren't declared, just less efficiently. Whether the
+    /// CPU may access the surface directly is still governed by `SurfaceAccess`.
+    ///
+    /// The default usage, `RENDER_TARGET | TEXTURE_SOURCE`, is what surfman allocated for every
+    /// surface before these flags existed.
+    pub struct SurfaceUsage: u16 {
+        /// The surface will be rendered to by the GPU.
+        const RENDER_TARGET  = 0x01;
+        /// The surface will be sampled by the GPU through a surface texture.
+        const TEXTURE_SOURCE = 0x02;
+        /// The CPU will read the contents of the surface.
+        const CPU_READ       = 0x04;
+        /// The CPU will write the contents of the surface.
+        const CPU_WRITE      = 0x08;
+        /// The surface will be handed to the system compositor, which may scan it out directly.
+        const SCANOUT        = 0x10;
+        /// The surface will be written by a hardware video decoder.
+        const VIDEO_DECODE   = 0x20;
+        /// The surface will be sampled at varying scales, so its storage should have room for a
+        /// full mip chain. See `Device::generate_surface_mipmaps()`.
+        const MIPMAPPED      = 0x40;
+        /// The pixels of the surface should be laid out row by row instead of in the tiled
+        /// layout the GPU prefers.
+        ///
+        /// A linear layout makes CPU access through `lock_surface_data()` much faster, since the
+        /// pixels don't have to be detiled on the way, at some cost to the speed of GPU sampling.
+        /// Without this flag the backend picks the layout the GPU prefers. On macOS surfaces are
+        /// `IOSurface`s, which are always linear. On Android the hardware buffer is allocated with
+        /// CPU usage, which makes the allocator pick a linear layout. On Wayland the image is
+        /// allocated with `DRM_FORMAT_MOD_LINEAR` instead of a negotiated modifier. Elsewhere the
+        /// driver chooses the layout of the renderbuffers and textures behind surfaces, and the
+        /// flag is ignored.
+        const LINEAR         = 0x80;
+        /// The surface must not be stored in a compressed layout, such as Arm's AFBC.
+        ///
+        /// Compressed layouts save memory bandwidth, but they can't be mapped by the CPU, and
+        /// consumers that the surface is shared with can only read them if they know the
+        /// compression scheme. On Android the hardware buffer is allocated with CPU usage, which
+        /// makes the allocator turn compression off. On Wayland compressed format modifiers are
+        /// left out of the negotiation. Elsewhere the flag is ignored.
+        const UNCOMPRESSED   = 0x100;
+    }
+}
+
+impl Default for SurfaceUsage {
+    #[inline]
+    fn default() -> SurfaceUsage {
+        SurfaceUsage::RENDER_TARGET | SurfaceUsage::TEXTURE_SOURCE
+    }
+}
+
 /// Information specific to the type of surface: generic or widget.
 pub enum SurfaceType<NativeWidget> {
     /// An off-screen surface that has a pixel size. Generic surfaces can sometimes be shown on
@@ -125,3 +388,40 @@ impl SurfaceAccess {
         }
     }
 }
+
+/// How the pixels of a surface are laid out in memory, as reported by
+/// `Device::surface_layout_requirements()` before the surface is created.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SurfaceLayoutRequirements {
+    /// The number of bytes from the start of one row of pixels to the start of the next.
+    pub stride: usize,
+    /// The alignment, in bytes, of the start of each row.
+    pub alignment: usize,
+    /// The total number of bytes allocated for the pixels, including any row padding.
+    pub size: usize,
+}
+
+impl SurfaceLayoutRequirements {
+    // The layout of surfaces whose rows are packed tightly at four bytes per pixel, for backends
+    // that don't let the platform choose.
+    #[cfg(any(linux, ohos, redox, windows))]
+    pub(crate) fn packed(size: &Size2D<i32>) -> SurfaceLayoutRequirements {
+        let stride = size.width.max(0) as usize * 4;
+        SurfaceLayoutRequirements {
+            stride,
+            alignment: 4,
+            size: stride * size.height.max(0) as usize,
+        }
+    }
+}
+
+/// The result of `Device::create_surfaces()`: either all of the surfaces, or the error that
+/// stopped it along with the surfaces created before that, which the caller must destroy.
+pub type CreateSurfacesResult<S> = Result<Vec<S>, (Error, Vec<S>)>;
+
+// Estimates the memory held by a surface of the given size from its color storage, for backends
+// that can't ask the platform.
+#[cfg(any(android, linux, ohos, redox, windows))]
+pub(crate) fn estimate_memory_usage(size: &Size2D<i32>) -> usize {
+    size.width as usize * size.height as usize * 4
+}
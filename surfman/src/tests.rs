@@ -12,15 +12,31 @@ use super::connection::Connection;
 use super::context::{Context, ContextDescriptor, NativeContext};
 use super::device::{Adapter, Device};
 use super::surface::Surface;
+use crate::compositor::{Compositor, CompositorLayer};
+use crate::connection::Connection as ConnectionAPI;
+use crate::device::Device as DeviceAPI;
 use crate::gl;
-use crate::gl::types::{GLenum, GLuint};
-use crate::{ContextAttributeFlags, ContextAttributes, Error, GLApi, GLVersion, Gl, SurfaceAccess};
-use crate::{SurfaceType, WindowingApiError};
-
-use euclid::default::Size2D;
+use crate::gl::types::{GLenum, GLint, GLuint};
+use crate::platform::generic::multi::context::Context as MultiContext;
+use crate::platform::generic::multi::device::Device as MultiDevice;
+use crate::pool::SurfacePool;
+use crate::sendable::SendableSurfaceTexture;
+use crate::{Colorspace, DeviceEvent, DeviceEventHandler, SurfaceVisibility, WindowingApiError};
+use crate::{ContextAttributeFlags, ContextAttributes, CreationTimings, Error, GLApi, GLVersion};
+use crate::{Gl, SurfaceAccess};
+use crate::{InitOptions, LifecycleEvent, LifecycleObserver, Metric, MetricsSink};
+use crate::{NativeDeviceHandle, NativeDisplayHandle, PresentTimeout, PresentWatchdog};
+use crate::{SurfaceAlphaMode, SurfaceFormat, SurfaceOrigin, SurfaceType, SurfaceUsage};
+
+use euclid::default::{Point2D, Rect, Size2D, Transform2D};
+use std::cell::RefCell;
+use std::collections::HashSet;
 use std::os::raw::c_void;
+use std::rc::Rc;
 use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
 
 static GL_VERSIONS: [GLVersion; 6] = [
     GLVersion { major: 2, minor: 0 },
@@ -105,13 +121,33 @@ pub fn test_context_creation() {
     for &version in versions {
         for flag_bits in 0..(ContextAttributeFlags::all().bits() + 1) {
             let flags = ContextAttributeFlags::from_bits_truncate(flag_bits);
-            let attributes = ContextAttributes { version, flags };
+            let attributes = ContextAttributes {
+                version,
+                max_version: None,
+                flags,
+                required_extensions: vec![],
+                samples: 0,
+            };
             let descriptor = match device.create_context_descriptor(&attributes) {
                 Ok(descriptor) => descriptor,
                 Err(Error::UnsupportedGLProfile) | Err(Error::UnsupportedGLVersion) => {
                     // Nothing we can do about this. Go on to the next one.
                     continue;
                 }
+                Err(Error::UnsupportedOnThisPlatform) | Err(Error::NoPixelFormatFound)
+                    if flags.contains(ContextAttributeFlags::STEREO) =>
+                {
+                    // Few drivers offer stereo pixel formats.
+                    continue;
+                }
+                Err(Error::UnsupportedOnThisPlatform)
+                | Err(Error::NoPixelFormatFound)
+                | Err(Error::RequiredExtensionUnavailable)
+                    if flags.contains(ContextAttributeFlags::FLOAT_COLOR) =>
+                {
+                    // Floating-point pixel formats need an extension that many drivers lack.
+                    continue;
+                }
                 Err(err) => panic!("Context descriptor creation failed: {:?}", err),
             };
 
@@ -178,7 +214,10 @@ pub fn test_newly_created_contexts_are_not_current() {
     let context_descriptor = device
         .create_context_descriptor(&ContextAttributes {
             version: GLVersion::new(3, 0),
+            max_version: None,
             flags: ContextAttributeFlags::empty(),
+            required_extensions: vec![],
+            samples: 0,
         })
         .unwrap();
 
@@ -246,7 +285,10 @@ pub fn test_context_sharing() {
     let context_descriptor = device
         .create_context_descriptor(&ContextAttributes {
             version: GLVersion::new(3, 0),
+            max_version: None,
             flags: ContextAttributeFlags::empty(),
+            required_extensions: vec![],
+            samples: 0,
         })
         .unwrap();
 
@@ -286,7 +328,10 @@ pub fn test_generic_surface_creation() {
     let descriptor = device
         .create_context_descriptor(&ContextAttributes {
             version: GLVersion::new(3, 0),
+            max_version: None,
             flags: ContextAttributeFlags::empty(),
+            required_extensions: vec![],
+            samples: 0,
         })
         .unwrap();
 
@@ -304,6 +349,8 @@ pub fn test_generic_surface_creation() {
             .create_surface(
                 &context,
                 access,
+                SurfaceUsage::default(),
+                SurfaceAlphaMode::default(),
                 SurfaceType::Generic {
                     size: Size2D::new(640, 480),
                 },
@@ -316,117 +363,1869 @@ pub fn test_generic_surface_creation() {
     })
     .collect();
 
-    // Make sure all IDs for extant surfaces are distinct.
-    for (surface_index, surface) in surfaces.iter().enumerate() {
-        for (other_surface_index, other_surface) in surfaces.iter().enumerate() {
-            if surface_index != other_surface_index {
-                assert_ne!(
-                    device.surface_info(surface).id,
-                    device.surface_info(other_surface).id
-                );
-            }
+    // Make sure all IDs for extant surfaces are distinct.
+    for (surface_index, surface) in surfaces.iter().enumerate() {
+        for (other_surface_index, other_surface) in surfaces.iter().enumerate() {
+            if surface_index != other_surface_index {
+                assert_ne!(
+                    device.surface_info(surface).id,
+                    device.surface_info(other_surface).id
+                );
+            }
+        }
+    }
+
+    let surface_ids: Vec<_> = surfaces
+        .iter()
+        .map(|surface| device.surface_info(surface).id)
+        .collect();
+    for mut surface in surfaces.into_iter() {
+        device.destroy_surface(&mut context, &mut surface).unwrap();
+    }
+
+    // Make sure IDs aren't reused once their surfaces are gone.
+    let mut surface = make_surface(&mut device, &context);
+    assert!(!surface_ids.contains(&device.surface_info(&surface).id));
+    device.destroy_surface(&mut context, &mut surface).unwrap();
+
+    device.destroy_context(&mut context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_cleared_surface_creation() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    clear(&env.gl, &[255, 0, 0, 255]);
+
+    let cleared_surface = env
+        .device
+        .create_cleared_surface(
+            &mut env.context,
+            SurfaceAccess::GPUOnly,
+            SurfaceUsage::default(),
+            SurfaceAlphaMode::default(),
+            SurfaceType::Generic {
+                size: Size2D::new(640, 480),
+            },
+            [0.0, 0.0, 1.0, 1.0],
+        )
+        .unwrap();
+
+    // The original surface should still be attached, with its contents intact.
+    bind_context_fbo(&env.gl, &env.device, &env.context);
+    assert_eq!(get_pixel_from_bottom_row(&env.gl), [255, 0, 0, 255]);
+
+    let mut old_surface = env
+        .device
+        .unbind_surface_from_context(&mut env.context)
+        .unwrap()
+        .unwrap();
+    env.device
+        .bind_surface_to_context(&mut env.context, cleared_surface)
+        .unwrap();
+    bind_context_fbo(&env.gl, &env.device, &env.context);
+    assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 0, 255, 255]);
+
+    env.device
+        .destroy_surface(&mut env.context, &mut old_surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_surface_format_conversion() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    clear(&env.gl, &[255, 0, 0, 255]);
+
+    let mut surface = env
+        .device
+        .unbind_surface_from_context(&mut env.context)
+        .unwrap()
+        .unwrap();
+    let format = match env.device.surface_info(&surface).format {
+        SurfaceFormat::RGBA8 => SurfaceFormat::BGRA8,
+        SurfaceFormat::BGRA8 => SurfaceFormat::RGBA8,
+    };
+    let result = env
+        .device
+        .convert_surface_format(&mut env.context, &surface, format);
+    let converted_surface = match result {
+        Ok(converted_surface) => converted_surface,
+        Err(Error::UnsupportedOnThisPlatform) => {
+            env.device
+                .destroy_surface(&mut env.context, &mut surface)
+                .unwrap();
+            env.device.destroy_context(&mut env.context).unwrap();
+            return;
+        }
+        Err(err) => panic!("Failed to convert surface: {:?}", err),
+    };
+    assert_eq!(env.device.surface_info(&converted_surface).format, format);
+
+    // The red and blue channels are swapped in storage, so OpenGL sees them swapped too.
+    env.device
+        .bind_surface_to_context(&mut env.context, converted_surface)
+        .unwrap();
+    bind_context_fbo(&env.gl, &env.device, &env.context);
+    assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 0, 255, 255]);
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_surface_origin() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let mut surface = env
+        .device
+        .unbind_surface_from_context(&mut env.context)
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        env.device.surface_info(&surface).origin,
+        SurfaceOrigin::BottomLeft
+    );
+
+    // Generic surfaces can always be marked as top-left.
+    env.device
+        .set_surface_origin(&mut surface, SurfaceOrigin::TopLeft)
+        .unwrap();
+    assert_eq!(
+        env.device.surface_info(&surface).origin,
+        SurfaceOrigin::TopLeft
+    );
+
+    env.device
+        .bind_surface_to_context(&mut env.context, surface)
+        .unwrap();
+    let info = env
+        .device
+        .context_surface_info(&env.context)
+        .unwrap()
+        .unwrap();
+    assert_eq!(info.origin, SurfaceOrigin::TopLeft);
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_surface_alpha_mode() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    // Generic surfaces accept every alpha mode and report it back.
+    for &alpha_mode in &[
+        SurfaceAlphaMode::Opaque,
+        SurfaceAlphaMode::Premultiplied,
+        SurfaceAlphaMode::Straight,
+    ] {
+        let mut surface = env
+            .device
+            .create_surface(
+                &env.context,
+                SurfaceAccess::GPUOnly,
+                SurfaceUsage::default(),
+                alpha_mode,
+                SurfaceType::Generic {
+                    size: Size2D::new(640, 480),
+                },
+            )
+            .unwrap();
+        assert_eq!(env.device.surface_info(&surface).alpha_mode, alpha_mode);
+        env.device
+            .destroy_surface(&mut env.context, &mut surface)
+            .unwrap();
+    }
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_surface_colorspace() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let mut surface = make_surface(&mut env.device, &env.context);
+    let colorspace = env.device.surface_info(&surface).colorspace;
+    assert_eq!(colorspace, Colorspace::SRGB);
+
+    // Generic surfaces accept every color space and report it back.
+    for &colorspace in &[Colorspace::DisplayP3, Colorspace::BT2020, Colorspace::SRGB] {
+        env.device
+            .set_surface_colorspace(&mut surface, colorspace)
+            .unwrap();
+        assert_eq!(env.device.surface_info(&surface).colorspace, colorspace);
+    }
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_widget_output_info_of_generic_surface() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    // Generic surfaces aren't shown on any display.
+    let mut surface = make_surface(&mut env.device, &env.context);
+    match env.device.widget_output_info(&surface) {
+        Err(Error::NoWidgetAttached) => {}
+        other => panic!("Expected `NoWidgetAttached`, got {:?}", other),
+    }
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_sample_count_negotiation() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    // No driver offers this many samples, so rather than failing, the count should be negotiated
+    // down to one that is available.
+    let descriptor = env
+        .device
+        .create_context_descriptor(&ContextAttributes {
+            version: GLVersion::new(3, 0),
+            max_version: None,
+            flags: ContextAttributeFlags::empty(),
+            required_extensions: vec![],
+            samples: 256,
+        })
+        .unwrap();
+    let samples = env
+        .device
+        .context_descriptor_attributes(&descriptor)
+        .samples;
+    assert!((1..=8).contains(&samples));
+
+    // Only widget surfaces get the negotiated count. Generic surfaces are single-sampled.
+    let mut context = env.device.create_context(&descriptor, None).unwrap();
+    let mut surface = make_surface(&mut env.device, &context);
+    assert_eq!(env.device.surface_info(&surface).samples, 1);
+
+    env.device
+        .destroy_surface(&mut context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_multisampled_context_surface_texture() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    // A context that asks for multisampling still renders to single-sampled generic surfaces, so
+    // their surface textures can be read without a resolve.
+    let mut attributes = env
+        .device
+        .context_descriptor_attributes(&env.context_descriptor);
+    attributes.samples = 4;
+    let descriptor = env.device.create_context_descriptor(&attributes).unwrap();
+    let mut context = env.device.create_context(&descriptor, None).unwrap();
+
+    unsafe {
+        let surface = make_surface(&mut env.device, &context);
+        assert_eq!(env.device.surface_info(&surface).samples, 1);
+        env.device
+            .bind_surface_to_context(&mut context, surface)
+            .unwrap();
+        env.device.make_context_current(&context).unwrap();
+        bind_context_fbo(&env.gl, &env.device, &context);
+        clear(&env.gl, &[0, 255, 0, 255]);
+        env.gl.Flush();
+
+        let green_surface = env
+            .device
+            .unbind_surface_from_context(&mut context)
+            .unwrap()
+            .unwrap();
+        let green_surface_texture = env
+            .device
+            .create_surface_texture(&mut env.context, green_surface)
+            .unwrap();
+
+        env.device.make_context_current(&env.context).unwrap();
+        let mut green_framebuffer_object = make_fbo(
+            &env.gl,
+            env.device.surface_gl_texture_target(),
+            env.device.surface_texture_object(&green_surface_texture),
+        );
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+
+        env.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        check_gl(&env.gl);
+        env.gl.DeleteFramebuffers(1, &mut green_framebuffer_object);
+
+        let mut green_surface = env
+            .device
+            .destroy_surface_texture(&mut env.context, green_surface_texture)
+            .unwrap();
+        env.device
+            .destroy_surface(&mut context, &mut green_surface)
+            .unwrap();
+    }
+    env.device.destroy_context(&mut context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_present_to_external_framebuffer() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    clear(&env.gl, &[255, 0, 0, 255]);
+
+    // The framebuffer of the context's own surface stands in for one owned by another library.
+    let target_framebuffer_object = env
+        .device
+        .context_surface_info(&env.context)
+        .unwrap()
+        .unwrap()
+        .framebuffer_object;
+    let mut surface = env
+        .device
+        .create_cleared_surface(
+            &mut env.context,
+            SurfaceAccess::GPUOnly,
+            SurfaceUsage::default(),
+            SurfaceAlphaMode::default(),
+            SurfaceType::Generic {
+                size: Size2D::new(64, 64),
+            },
+            [0.0, 0.0, 1.0, 1.0],
+        )
+        .unwrap();
+
+    // Only the left half of the target is covered.
+    let viewport = Rect::new(Point2D::new(0, 0), Size2D::new(320, 480));
+    match env.device.present_to_external_framebuffer(
+        &env.context,
+        &surface,
+        target_framebuffer_object,
+        &viewport,
+    ) {
+        Ok(()) => {
+            bind_context_fbo(&env.gl, &env.device, &env.context);
+            assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 0, 255, 255]);
+            let mut pixel = [0u8; 4];
+            unsafe {
+                env.gl.ReadPixels(
+                    639,
+                    0,
+                    1,
+                    1,
+                    gl::RGBA,
+                    gl::UNSIGNED_BYTE,
+                    pixel.as_mut_ptr() as *mut c_void,
+                );
+            }
+            assert_eq!(pixel, [255, 0, 0, 255]);
+        }
+        Err(Error::UnsupportedOnThisPlatform) => {}
+        Err(err) => panic!("Failed to present to the external framebuffer: {:?}", err),
+    }
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_bind_external_framebuffer() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let size = Size2D::new(64, 64);
+    let (texture_object, framebuffer_object) = unsafe {
+        let mut texture_object = 0;
+        env.gl.GenTextures(1, &mut texture_object);
+        env.gl.BindTexture(gl::TEXTURE_2D, texture_object);
+        env.gl.TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            gl::RGBA as GLint,
+            size.width,
+            size.height,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            std::ptr::null(),
+        );
+        env.gl.BindTexture(gl::TEXTURE_2D, 0);
+        check_gl(&env.gl);
+        (
+            texture_object,
+            make_fbo(&env.gl, gl::TEXTURE_2D, texture_object),
+        )
+    };
+
+    // A context with a surface can't adopt a framebuffer object.
+    match env
+        .device
+        .bind_external_framebuffer(&mut env.context, framebuffer_object, size)
+    {
+        Err(Error::SurfaceAlreadyBound) => {}
+        other => panic!("Expected `SurfaceAlreadyBound`, got {:?}", other),
+    }
+
+    let mut surface = env
+        .device
+        .unbind_surface_from_context(&mut env.context)
+        .unwrap()
+        .unwrap();
+    env.device
+        .bind_external_framebuffer(&mut env.context, framebuffer_object, size)
+        .unwrap();
+
+    let info = env
+        .device
+        .context_surface_info(&env.context)
+        .unwrap()
+        .unwrap();
+    assert_eq!(info.framebuffer_object, framebuffer_object);
+    assert_eq!(info.size, size);
+    assert_eq!(info.context_id, env.device.context_id(&env.context));
+    assert!(info.bound);
+
+    // Rendering through the reported framebuffer object lands in the caller's texture.
+    bind_context_fbo(&env.gl, &env.device, &env.context);
+    clear(&env.gl, &[0, 255, 0, 255]);
+    assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+
+    // Unbinding detaches the framebuffer object without handing back a surface.
+    assert!(env
+        .device
+        .unbind_surface_from_context(&mut env.context)
+        .unwrap()
+        .is_none());
+    assert!(env
+        .device
+        .context_surface_info(&env.context)
+        .unwrap()
+        .is_none());
+
+    unsafe {
+        env.gl.DeleteFramebuffers(1, &framebuffer_object);
+        env.gl.DeleteTextures(1, &texture_object);
+    }
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_surface_from_texture() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    unsafe {
+        let size = Size2D::new(640, 480);
+        let pixels = vec![255u8, 0, 0, 255].repeat((size.width * size.height) as usize);
+        let mut texture_object = 0;
+        env.gl.GenTextures(1, &mut texture_object);
+        env.gl.BindTexture(gl::TEXTURE_2D, texture_object);
+        env.gl.TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            gl::RGBA8 as GLint,
+            size.width,
+            size.height,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            pixels.as_ptr() as *const _,
+        );
+        env.gl
+            .TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
+        env.gl.BindTexture(gl::TEXTURE_2D, 0);
+        check_gl(&env.gl);
+
+        let result =
+            env.device
+                .create_surface_from_texture(&mut env.context, &size, texture_object);
+        match result {
+            Ok(surface) => {
+                // The surface should start out with the contents of the texture.
+                let mut old_surface = env
+                    .device
+                    .unbind_surface_from_context(&mut env.context)
+                    .unwrap()
+                    .unwrap();
+                env.device
+                    .bind_surface_to_context(&mut env.context, surface)
+                    .unwrap();
+                bind_context_fbo(&env.gl, &env.device, &env.context);
+                assert_eq!(get_pixel_from_bottom_row(&env.gl), [255, 0, 0, 255]);
+
+                env.device
+                    .destroy_surface(&mut env.context, &mut old_surface)
+                    .unwrap();
+            }
+            Err(Error::UnsupportedOnThisPlatform) => {}
+            Err(err) => panic!("Failed to create surface from texture: {:?}", err),
+        }
+
+        env.gl.DeleteTextures(1, &texture_object);
+        env.device.destroy_context(&mut env.context).unwrap();
+    }
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_surface_into_pixels() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    clear(&env.gl, &[255, 0, 0, 255]);
+    clear_bottom_row(&env.gl, &[0, 255, 0, 255]);
+
+    let surface = env
+        .device
+        .unbind_surface_from_context(&mut env.context)
+        .unwrap()
+        .unwrap();
+    let (pixels, stride, format) = env
+        .device
+        .surface_into_pixels(&mut env.context, surface)
+        .unwrap();
+    assert_eq!(stride, 640 * 4);
+    assert_eq!(pixels.len(), stride * 480);
+
+    // The bottom row comes last.
+    let (top_pixel, bottom_pixel) = match format {
+        SurfaceFormat::RGBA8 => ([255, 0, 0, 255], [0, 255, 0, 255]),
+        SurfaceFormat::BGRA8 => ([0, 0, 255, 255], [0, 255, 0, 255]),
+    };
+    assert_eq!(pixels[0..4], top_pixel);
+    assert_eq!(pixels[(stride * 479)..(stride * 479 + 4)], bottom_pixel);
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_surface_into_pixels_pack_parameters() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    clear(&env.gl, &[255, 0, 0, 255]);
+    clear_bottom_row(&env.gl, &[0, 255, 0, 255]);
+    unsafe {
+        env.gl.PixelStorei(gl::PACK_ALIGNMENT, 8);
+        env.gl.PixelStorei(gl::PACK_ROW_LENGTH, 700);
+        env.gl.PixelStorei(gl::PACK_SKIP_ROWS, 3);
+    }
+
+    let surface = env
+        .device
+        .unbind_surface_from_context(&mut env.context)
+        .unwrap()
+        .unwrap();
+    let (pixels, stride, _) = env
+        .device
+        .surface_into_pixels(&mut env.context, surface)
+        .unwrap();
+
+    // The rows are still packed tightly, with nothing skipped. Green reads the same in either
+    // channel order.
+    assert_eq!(pixels.len(), stride * 480);
+    let bottom_pixel = [0, 255, 0, 255];
+    assert_eq!(pixels[(stride * 479)..(stride * 479 + 4)], bottom_pixel);
+    assert_ne!(pixels[(stride * 476)..(stride * 476 + 4)], bottom_pixel);
+
+    // The caller's pack parameters are left as they were.
+    unsafe {
+        let (mut alignment, mut row_length, mut skip_rows) = (0, 0, 0);
+        env.gl.GetIntegerv(gl::PACK_ALIGNMENT, &mut alignment);
+        env.gl.GetIntegerv(gl::PACK_ROW_LENGTH, &mut row_length);
+        env.gl.GetIntegerv(gl::PACK_SKIP_ROWS, &mut skip_rows);
+        assert_eq!((alignment, row_length, skip_rows), (8, 700, 3));
+    }
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_debug_labels() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    assert_eq!(env.device.context_label(&env.context), None);
+    env.device
+        .set_context_label(&env.context, "test context")
+        .unwrap();
+    assert_eq!(
+        env.device.context_label(&env.context).as_deref(),
+        Some("test context")
+    );
+
+    let mut surface = make_surface(&mut env.device, &env.context);
+    assert_eq!(env.device.surface_label(&surface), None);
+    env.device
+        .set_surface_label(&env.context, &surface, "test surface")
+        .unwrap();
+    check_gl(&env.gl);
+    assert_eq!(
+        env.device.surface_label(&surface).as_deref(),
+        Some("test surface")
+    );
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_lifecycle_observer() {
+    struct RecordingObserver {
+        events: Mutex<Vec<LifecycleEvent>>,
+    }
+
+    impl LifecycleObserver for RecordingObserver {
+        fn on_event(&self, event: &LifecycleEvent) {
+            self.events.lock().unwrap().push(*event);
+        }
+    }
+
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let observer = Arc::new(RecordingObserver {
+        events: Mutex::new(vec![]),
+    });
+    DeviceAPI::set_lifecycle_observer(&mut env.device, Some(observer.clone()));
+
+    // Calls through the `Device` trait and calls on the device directly are both observed.
+    let mut context =
+        DeviceAPI::create_context(&mut env.device, &env.context_descriptor, None).unwrap();
+    let context_id = env.device.context_id(&context);
+    let mut surface = env
+        .device
+        .create_surface(
+            &context,
+            SurfaceAccess::GPUOnly,
+            SurfaceUsage::default(),
+            SurfaceAlphaMode::default(),
+            SurfaceType::Generic {
+                size: Size2D::new(640, 480),
+            },
+        )
+        .unwrap();
+    let surface_id = env.device.surface_info(&surface).id;
+    env.device
+        .destroy_surface(&mut context, &mut surface)
+        .unwrap();
+    DeviceAPI::destroy_context(&env.device, &mut context).unwrap();
+
+    assert_eq!(
+        *observer.events.lock().unwrap(),
+        [
+            LifecycleEvent::ContextCreated { context_id },
+            LifecycleEvent::SurfaceCreated {
+                surface_id,
+                context_id,
+                size: Size2D::new(640, 480),
+            },
+            LifecycleEvent::SurfaceDestroyed { surface_id },
+            LifecycleEvent::ContextDestroyed { context_id },
+        ]
+    );
+
+    DeviceAPI::set_lifecycle_observer(&mut env.device, None);
+    env.device.destroy_context(&mut env.context).unwrap();
+    assert_eq!(observer.events.lock().unwrap().len(), 4);
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_device_event_handler() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    assert!(DeviceAPI::device_event_handler(&env.device).is_none());
+
+    let events = Arc::new(Mutex::new(vec![]));
+    let handler: DeviceEventHandler = {
+        let events = events.clone();
+        Arc::new(move |event| events.lock().unwrap().push(event))
+    };
+    DeviceAPI::set_device_event_handler(&mut env.device, Some(handler.clone()));
+    assert!(Arc::ptr_eq(
+        &DeviceAPI::device_event_handler(&env.device).unwrap(),
+        &handler
+    ));
+
+    // A healthy context doesn't report a reset.
+    DeviceAPI::make_context_current(&env.device, &env.context).unwrap();
+    DeviceAPI::make_no_context_current(&env.device).unwrap();
+    assert!(!events
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|&event| event == DeviceEvent::Reset || event == DeviceEvent::Removed));
+
+    DeviceAPI::set_device_event_handler(&mut env.device, None);
+    assert!(DeviceAPI::device_event_handler(&env.device).is_none());
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_metrics_sink() {
+    struct RecordingSink {
+        records: Mutex<Vec<(Metric, i64)>>,
+    }
+
+    impl MetricsSink for RecordingSink {
+        fn record(&self, metric: Metric, delta: i64) {
+            self.records.lock().unwrap().push((metric, delta));
+        }
+    }
+
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let sink = Arc::new(RecordingSink {
+        records: Mutex::new(vec![]),
+    });
+    crate::set_metrics_sink(Some(sink.clone()));
+
+    // Other tests may run concurrently, so only check that our own changes were recorded.
+    let mut surface = DeviceAPI::create_surface(
+        &mut env.device,
+        &env.context,
+        SurfaceAccess::GPUOnly,
+        SurfaceUsage::default(),
+        SurfaceAlphaMode::default(),
+        SurfaceType::Generic {
+            size: Size2D::new(640, 480),
+        },
+    )
+    .unwrap();
+    DeviceAPI::make_context_current(&env.device, &env.context).unwrap();
+    DeviceAPI::destroy_surface(&env.device, &mut env.context, &mut surface).unwrap();
+    crate::set_metrics_sink(None);
+
+    let records = sink.records.lock().unwrap();
+    for record in &[
+        (Metric::LiveSurfaces, 1),
+        (Metric::SurfaceBytesAllocated, 640 * 480 * 4),
+        (Metric::MakeCurrentCalls, 1),
+        (Metric::LiveSurfaces, -1),
+        (Metric::SurfaceBytesAllocated, -640 * 480 * 4),
+    ] {
+        assert!(records.contains(record));
+    }
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_memory_usage() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let initial_memory_usage = DeviceAPI::memory_usage(&env.device);
+    let mut surface = DeviceAPI::create_surface(
+        &mut env.device,
+        &env.context,
+        SurfaceAccess::GPUOnly,
+        SurfaceUsage::default(),
+        SurfaceAlphaMode::default(),
+        SurfaceType::Generic {
+            size: Size2D::new(640, 480),
+        },
+    )
+    .unwrap();
+    let surface_memory_usage = env.device.surface_memory_usage(&surface);
+    assert!(surface_memory_usage >= 640 * 480 * 4);
+    assert_eq!(
+        DeviceAPI::memory_usage(&env.device),
+        initial_memory_usage + surface_memory_usage
+    );
+
+    DeviceAPI::destroy_surface(&env.device, &mut env.context, &mut surface).unwrap();
+    assert_eq!(DeviceAPI::memory_usage(&env.device), initial_memory_usage);
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_surface_layout_requirements() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let requirements = env
+        .device
+        .surface_layout_requirements(
+            &Size2D::new(640, 480),
+            SurfaceFormat::RGBA8,
+            SurfaceAccess::GPUCPU,
+        )
+        .unwrap();
+    assert!(requirements.stride >= 640 * 4);
+    assert!(requirements.alignment.is_power_of_two());
+    assert_eq!(requirements.stride % requirements.alignment, 0);
+    assert!(requirements.size >= requirements.stride * 480);
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_surface_seed() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let mut surface = make_surface(&mut env.device, &env.context);
+    match env.device.surface_seed(&surface) {
+        Ok(seed) => {
+            // Nothing touched the surface in between.
+            assert_eq!(env.device.surface_seed(&surface).unwrap(), seed);
+        }
+        Err(Error::UnsupportedOnThisPlatform) => {}
+        Err(err) => panic!("Failed to get the seed of the surface: {:?}", err),
+    }
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_surface_destroy_token() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let initial_memory_usage = DeviceAPI::memory_usage(&env.device);
+    let surface = DeviceAPI::create_surface(
+        &mut env.device,
+        &env.context,
+        SurfaceAccess::GPUOnly,
+        SurfaceUsage::default(),
+        SurfaceAlphaMode::default(),
+        SurfaceType::Generic {
+            size: Size2D::new(640, 480),
+        },
+    )
+    .unwrap();
+    let token = DeviceAPI::surface_into_destroy_token(&env.device, surface);
+    assert_eq!(DeviceAPI::memory_usage(&env.device), initial_memory_usage);
+
+    // Unlike the surface's context, the token can be handed to another thread.
+    let token = thread::spawn(move || token).join().unwrap();
+    DeviceAPI::destroy_token(&env.device, token).unwrap();
+
+    env.device.make_context_current(&env.context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_sendable_surface_texture() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let surface = make_surface(&mut env.device, &env.context);
+    let surface_texture = env
+        .device
+        .create_surface_texture(&mut env.context, surface)
+        .unwrap();
+    let sendable = match SendableSurfaceTexture::new(&env.device, &env.context, surface_texture) {
+        Ok(sendable) => sendable,
+        Err((err, _)) => panic!("Failed to wrap the surface texture: {:?}", err),
+    };
+    assert_eq!(sendable.context_id(), env.device.context_id(&env.context));
+
+    let sendable = thread::spawn(move || sendable).join().unwrap();
+    let surface_texture = match sendable.into_surface_texture(&env.device, &env.context) {
+        Ok(surface_texture) => surface_texture,
+        Err((err, _)) => panic!("Failed to unwrap the surface texture: {:?}", err),
+    };
+
+    let mut surface = env
+        .device
+        .destroy_surface_texture(&mut env.context, surface_texture)
+        .unwrap();
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_device_clone_on_another_thread() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let initial_memory_usage = DeviceAPI::memory_usage(&env.device);
+    let mut surface = DeviceAPI::create_surface(
+        &mut env.device,
+        &env.context,
+        SurfaceAccess::GPUOnly,
+        SurfaceUsage::default(),
+        SurfaceAlphaMode::default(),
+        SurfaceType::Generic {
+            size: Size2D::new(640, 480),
+        },
+    )
+    .unwrap();
+    let memory_usage = DeviceAPI::memory_usage(&env.device);
+    assert!(memory_usage > initial_memory_usage);
+
+    // The clone shares the original's memory usage, and can render on its own thread.
+    let attributes = env
+        .device
+        .context_descriptor_attributes(&env.context_descriptor);
+    let device = env.device.clone();
+    thread::spawn(move || {
+        let mut device = device;
+        assert_eq!(DeviceAPI::memory_usage(&device), memory_usage);
+
+        let context_descriptor = device.create_context_descriptor(&attributes).unwrap();
+        let mut context = device.create_context(&context_descriptor, None).unwrap();
+        let mut other_surface = make_surface(&mut device, &context);
+        device
+            .destroy_surface(&mut context, &mut other_surface)
+            .unwrap();
+        device.destroy_context(&mut context).unwrap();
+    })
+    .join()
+    .unwrap();
+
+    DeviceAPI::destroy_surface(&mut env.device, &mut env.context, &mut surface).unwrap();
+    assert_eq!(DeviceAPI::memory_usage(&env.device), initial_memory_usage);
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg(linux)]
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_connection_from_ipc_descriptor() {
+    use crate::IpcDescriptor;
+
+    // Stands in for a render node that a broker process opened and passed over.
+    let drm_fd = unsafe {
+        libc::open(
+            b"/dev/dri/renderD128\0".as_ptr() as *const _,
+            libc::O_RDWR | libc::O_CLOEXEC,
+        )
+    };
+    if drm_fd < 0 {
+        return;
+    }
+
+    let descriptor = IpcDescriptor::DrmRenderNode(drm_fd);
+    let connection = match unsafe { Connection::from_ipc_descriptor(descriptor) } {
+        Ok(connection) => connection,
+        Err(Error::UnsupportedOnThisPlatform) => {
+            unsafe { libc::close(drm_fd) };
+            return;
+        }
+        // GBM isn't available, and the render node has already been closed.
+        Err(Error::ConnectionFailed) => return,
+        Err(err) => panic!("Failed to connect: {:?}", err),
+    };
+
+    let adapter = connection.create_adapter().unwrap();
+    let mut device = connection.create_device(&adapter).unwrap();
+    let context_descriptor = device
+        .create_context_descriptor(&ContextAttributes {
+            version: GLVersion::new(3, 0),
+            max_version: None,
+            flags: ContextAttributeFlags::empty(),
+            required_extensions: vec![],
+            samples: 0,
+        })
+        .unwrap();
+    let mut context = device.create_context(&context_descriptor, None).unwrap();
+    let mut surface = make_surface(&mut device, &context);
+    device.destroy_surface(&mut context, &mut surface).unwrap();
+    device.destroy_context(&mut context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_surface_pool() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let mut pool = SurfacePool::new(&env.device, &env.context);
+    let evicted = Rc::new(RefCell::new(vec![]));
+    let evicted_in_callback = evicted.clone();
+    pool.set_eviction_callback(move |surface_info, _| {
+        evicted_in_callback.borrow_mut().push(surface_info.id);
+    });
+
+    let size = Size2D::new(640, 480);
+    let surface_a = pool.acquire(&mut env.device, &env.context, &size).unwrap();
+    let surface_b = pool.acquire(&mut env.device, &env.context, &size).unwrap();
+    let (surface_a_id, surface_b_id) = (
+        env.device.surface_info(&surface_a).id,
+        env.device.surface_info(&surface_b).id,
+    );
+
+    // Leave room for only one of the two surfaces.
+    let surface_memory_usage = DeviceAPI::surface_memory_usage(&env.device, &surface_a);
+    pool.set_memory_budget(Some(
+        DeviceAPI::memory_usage(&env.device) - surface_memory_usage,
+    ));
+    pool.release(&env.device, &mut env.context, surface_a)
+        .unwrap();
+    pool.release(&env.device, &mut env.context, surface_b)
+        .unwrap();
+    assert_eq!(*evicted.borrow(), [surface_a_id]);
+    assert_eq!(pool.idle_surface_count(), 1);
+
+    // The remaining surface is reused.
+    let mut surface = pool.acquire(&mut env.device, &env.context, &size).unwrap();
+    assert_eq!(env.device.surface_info(&surface).id, surface_b_id);
+    assert_eq!(pool.idle_surface_count(), 0);
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    pool.destroy(&env.device, &mut env.context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_deferred_surface_destruction() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let initial_memory_usage = DeviceAPI::memory_usage(&env.device);
+    let surface = DeviceAPI::create_surface(
+        &mut env.device,
+        &env.context,
+        SurfaceAccess::GPUOnly,
+        SurfaceUsage::default(),
+        SurfaceAlphaMode::default(),
+        SurfaceType::Generic {
+            size: Size2D::new(640, 480),
+        },
+    )
+    .unwrap();
+    env.device.defer_destroy_surface(surface);
+    assert!(DeviceAPI::memory_usage(&env.device) > initial_memory_usage);
+
+    // Making the context current gives the queued surface a chance to be destroyed.
+    DeviceAPI::make_context_current(&env.device, &env.context).unwrap();
+    assert_eq!(DeviceAPI::memory_usage(&env.device), initial_memory_usage);
+
+    // A multi device queues surfaces with the backend that created them, so that they're still
+    // destroyed when the context is made current.
+    let mut device = MultiDevice::<Device, Device>::Default(env.device);
+    let mut context = MultiContext::<Device, Device>::Default(env.context);
+    let surface = DeviceAPI::create_surface(
+        &mut device,
+        &context,
+        SurfaceAccess::GPUOnly,
+        SurfaceUsage::default(),
+        SurfaceAlphaMode::default(),
+        SurfaceType::Generic {
+            size: Size2D::new(640, 480),
+        },
+    )
+    .unwrap();
+    DeviceAPI::defer_destroy_surface(&device, surface);
+    assert!(DeviceAPI::memory_usage(&device) > initial_memory_usage);
+
+    DeviceAPI::make_context_current(&device, &context).unwrap();
+    assert_eq!(DeviceAPI::memory_usage(&device), initial_memory_usage);
+
+    DeviceAPI::destroy_context(&device, &mut context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_batch_surface_creation() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let initial_memory_usage = DeviceAPI::memory_usage(&env.device);
+    let size = Size2D::new(256, 256);
+    let surfaces = DeviceAPI::create_surfaces(
+        &mut env.device,
+        &env.context,
+        SurfaceAccess::GPUOnly,
+        SurfaceUsage::default(),
+        SurfaceAlphaMode::default(),
+        &size,
+        4,
+    )
+    .unwrap();
+    assert_eq!(surfaces.len(), 4);
+    let memory_usage: usize = surfaces
+        .iter()
+        .map(|surface| DeviceAPI::surface_memory_usage(&env.device, surface))
+        .sum();
+    assert_eq!(
+        DeviceAPI::memory_usage(&env.device),
+        initial_memory_usage + memory_usage
+    );
+
+    for mut surface in surfaces {
+        let surface_info = env.device.surface_info(&surface);
+        assert_eq!(surface_info.size, size);
+        assert_eq!(surface_info.context_id, env.device.context_id(&env.context));
+        DeviceAPI::destroy_surface(&env.device, &mut env.context, &mut surface).unwrap();
+    }
+    assert_eq!(DeviceAPI::memory_usage(&env.device), initial_memory_usage);
+
+    // Prewarmed pools hand out the batch-created surfaces.
+    let mut pool = SurfacePool::new(&env.device, &env.context);
+    pool.prewarm(&mut env.device, &env.context, &size, 2)
+        .unwrap();
+    assert_eq!(pool.idle_surface_count(), 2);
+    let mut surface = pool.acquire(&mut env.device, &env.context, &size).unwrap();
+    assert_eq!(pool.idle_surface_count(), 1);
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    pool.destroy(&env.device, &mut env.context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_context_ids_per_connection() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let mut other_env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    // Contexts of the same connection share its tag, in the upper 16 bits of their IDs.
+    let mut context = env
+        .device
+        .create_context(&env.context_descriptor, None)
+        .unwrap();
+    let (context_id, first_context_id) = (
+        env.device.context_id(&context),
+        env.device.context_id(&env.context),
+    );
+    assert_ne!(context_id, first_context_id);
+    assert_eq!(context_id.0 >> 48, first_context_id.0 >> 48);
+
+    // Contexts of different connections don't.
+    let other_context_id = other_env.device.context_id(&other_env.context);
+    assert_ne!(other_context_id.0 >> 48, first_context_id.0 >> 48);
+
+    env.device.destroy_context(&mut context).unwrap();
+    other_env
+        .device
+        .destroy_context(&mut other_env.context)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_context_descriptor_cache() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    // Asking again for the same attributes hands back an equivalent descriptor, which works
+    // for creating contexts.
+    let attributes = env
+        .device
+        .context_descriptor_attributes(&env.context_descriptor);
+    let context_descriptor = env.device.create_context_descriptor(&attributes).unwrap();
+    assert_eq!(
+        env.device
+            .context_descriptor_attributes(&context_descriptor),
+        attributes
+    );
+    let mut context = env
+        .device
+        .create_context(&context_descriptor, None)
+        .unwrap();
+
+    env.device.destroy_context(&mut context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_context_descriptor_interning() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    // Copies of a descriptor are equal, and get the same ID from clones of the device.
+    let id = env
+        .device
+        .intern_context_descriptor(&env.context_descriptor);
+    let context_descriptor = env.context_descriptor.clone();
+    assert!(context_descriptor == env.context_descriptor);
+    let other_device = env.device.clone();
+    assert_eq!(
+        other_device.intern_context_descriptor(&context_descriptor),
+        id
+    );
+    let mut context_descriptors = HashSet::new();
+    context_descriptors.insert(env.context_descriptor.clone());
+    context_descriptors.insert(context_descriptor);
+    assert_eq!(context_descriptors.len(), 1);
+
+    // Other attributes may or may not end up with the same config, but the IDs agree with
+    // equality either way.
+    let attributes = ContextAttributes {
+        version: GLVersion::new(2, 0),
+        max_version: None,
+        flags: ContextAttributeFlags::DEPTH,
+        required_extensions: vec![],
+        samples: 0,
+    };
+    if let Ok(other_context_descriptor) = env.device.create_context_descriptor(&attributes) {
+        let other_id = env
+            .device
+            .intern_context_descriptor(&other_context_descriptor);
+        assert_eq!(
+            other_id == id,
+            other_context_descriptor == env.context_descriptor
+        );
+    }
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_context_version_range() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    // Whatever version we get must lie in the range we asked for.
+    let max_version = match env.device.gl_api() {
+        GLApi::GL => GLVersion::new(4, 6),
+        GLApi::GLES => GLVersion::new(3, 2),
+    };
+    let attributes = ContextAttributes {
+        version: GLVersion::new(3, 0),
+        max_version: Some(max_version),
+        flags: ContextAttributeFlags::empty(),
+        required_extensions: vec![],
+        samples: 0,
+    };
+    let context_descriptor = env.device.create_context_descriptor(&attributes).unwrap();
+    let mut context = env
+        .device
+        .create_context(&context_descriptor, None)
+        .unwrap();
+    let version = env
+        .device
+        .context_descriptor_attributes(&env.device.context_descriptor(&context))
+        .version;
+    assert!(version >= GLVersion::new(3, 0));
+    assert!(version <= max_version);
+
+    env.device.destroy_context(&mut context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_required_extensions() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    // No driver has this extension, so context creation must fail and say so.
+    let attributes = ContextAttributes {
+        required_extensions: vec!["GL_SURFMAN_nonexistent_extension".to_owned()],
+        ..env
+            .device
+            .context_descriptor_attributes(&env.context_descriptor)
+    };
+    let context_descriptor = env.device.create_context_descriptor(&attributes).unwrap();
+    match env.device.create_context(&context_descriptor, None) {
+        Err(Error::MissingExtensions(missing_extensions)) => {
+            assert_eq!(missing_extensions, vec!["GL_SURFMAN_nonexistent_extension"])
+        }
+        Err(err) => panic!("Unexpected error: {:?}", err),
+        Ok(mut context) => {
+            env.device.destroy_context(&mut context).unwrap();
+            panic!("Created a context without a required extension!");
+        }
+    }
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_native_handles() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    // Whatever handles there are must be live, and EGL displays must agree between the two.
+    let connection_egl_display = match env.connection.native_display() {
+        NativeDisplayHandle::EGLDisplay(egl_display) => {
+            assert!(!egl_display.is_null());
+            Some(egl_display)
+        }
+        NativeDisplayHandle::Xlib {
+            display,
+            egl_display,
+        } => {
+            assert!(!display.is_null());
+            Some(egl_display).filter(|egl_display| !egl_display.is_null())
+        }
+        NativeDisplayHandle::Wayland { egl_display, .. } => {
+            assert!(!egl_display.is_null());
+            Some(egl_display)
+        }
+        NativeDisplayHandle::None => None,
+    };
+    let device_egl_display = match env.device.native_device_handle() {
+        NativeDeviceHandle::EGLDisplay(egl_display) => {
+            assert!(!egl_display.is_null());
+            Some(egl_display)
+        }
+        NativeDeviceHandle::D3D11Device {
+            device,
+            egl_display,
+        } => {
+            assert!(!device.is_null());
+            Some(egl_display).filter(|egl_display| !egl_display.is_null())
+        }
+        NativeDeviceHandle::MTLDevice(device) => {
+            assert!(!device.is_null());
+            None
+        }
+        NativeDeviceHandle::None => None,
+    };
+    if let (Some(connection_egl_display), Some(device_egl_display)) =
+        (connection_egl_display, device_egl_display)
+    {
+        assert_eq!(connection_egl_display, device_egl_display);
+    }
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_creation_timings() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    // Each step is timed, and clones of the device share the timings.
+    let mut device = ConnectionAPI::create_device(&env.connection, &env.adapter).unwrap();
+    let device_open = DeviceAPI::creation_timings(&device).device_open;
+    assert_eq!(
+        DeviceAPI::creation_timings(&device),
+        CreationTimings {
+            device_open,
+            ..CreationTimings::default()
+        }
+    );
+
+    let attributes = DeviceAPI::context_descriptor_attributes(&env.device, &env.context_descriptor);
+    let context_descriptor = DeviceAPI::create_context_descriptor(&device, &attributes).unwrap();
+    let mut context = DeviceAPI::create_context(&mut device, &context_descriptor, None).unwrap();
+    let timings = DeviceAPI::creation_timings(&device.clone());
+    assert_eq!(timings.device_open, device_open);
+    assert_eq!(timings.context_descriptors_created, 1);
+    assert_eq!(timings.contexts_created, 1);
+    assert!(timings.context_creation > Duration::default());
+
+    DeviceAPI::destroy_context(&device, &mut context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that basic GL commands work.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_gl() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    unsafe {
+        // Check basic clear.
+        env.gl.ClearColor(0.0, 1.0, 0.0, 1.0);
+        env.gl.Clear(gl::COLOR_BUFFER_BIT);
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+
+        // Check that GL commands don't work after `make_no_context_current()`.
+        //
+        // The `glGetError()` calls are there to clear any errors.
+        env.device.make_no_context_current().unwrap();
+        env.gl.ClearColor(1.0, 0.0, 0.0, 1.0);
+        env.gl.GetError();
+        env.gl.Clear(gl::COLOR_BUFFER_BIT);
+        env.gl.GetError();
+        env.device.make_context_current(&env.context).unwrap();
+
+        let framebuffer_object = env
+            .device
+            .context_surface_info(&env.context)
+            .unwrap()
+            .unwrap()
+            .framebuffer_object;
+        env.gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+
+        // Make sure GL commands don't work when no surface is attached.
+        //
+        // The `glGetError()` calls are there to clear any errors.
+        let green_surface = env
+            .device
+            .unbind_surface_from_context(&mut env.context)
+            .unwrap()
+            .unwrap();
+        env.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        env.gl.ClearColor(1.0, 0.0, 0.0, 1.0);
+        env.gl.GetError();
+        env.gl.Clear(gl::COLOR_BUFFER_BIT);
+        env.gl.GetError();
+        env.device
+            .bind_surface_to_context(&mut env.context, green_surface)
+            .unwrap();
+        env.gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+
+        // Make sure GL commands go to the right surface.
+        let green_surface = env
+            .device
+            .unbind_surface_from_context(&mut env.context)
+            .unwrap()
+            .unwrap();
+        let red_surface = make_surface(&mut env.device, &env.context);
+        env.device
+            .bind_surface_to_context(&mut env.context, red_surface)
+            .unwrap();
+        let red_framebuffer_object = env
+            .device
+            .context_surface_info(&env.context)
+            .unwrap()
+            .unwrap()
+            .framebuffer_object;
+        env.gl
+            .BindFramebuffer(gl::FRAMEBUFFER, red_framebuffer_object);
+        env.gl.ClearColor(1.0, 0.0, 0.0, 1.0);
+        env.gl.Clear(gl::COLOR_BUFFER_BIT);
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [255, 0, 0, 255]);
+
+        let mut red_surface = env
+            .device
+            .unbind_surface_from_context(&mut env.context)
+            .unwrap()
+            .unwrap();
+        env.device
+            .bind_surface_to_context(&mut env.context, green_surface)
+            .unwrap();
+        env.gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+
+        // Clean up.
+        env.device
+            .destroy_surface(&mut env.context, &mut red_surface)
+            .unwrap();
+    }
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_surface_creation_preserves_bindings() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    unsafe {
+        // Bind the context's framebuffer and a texture of our own.
+        bind_context_fbo(&env.gl, &env.device, &env.context);
+        let mut texture = 0;
+        env.gl.GenTextures(1, &mut texture);
+        env.gl.BindTexture(gl::TEXTURE_2D, texture);
+
+        let framebuffer_object = context_fbo(&env.device, &env.context);
+        let check_bindings = |gl: &Gl| {
+            let (mut draw_framebuffer, mut read_framebuffer, mut texture_binding) = (0, 0, 0);
+            gl.GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut draw_framebuffer);
+            gl.GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut read_framebuffer);
+            gl.GetIntegerv(gl::TEXTURE_BINDING_2D, &mut texture_binding);
+            assert_eq!(draw_framebuffer as GLuint, framebuffer_object);
+            assert_eq!(read_framebuffer as GLuint, framebuffer_object);
+            assert_eq!(texture_binding as GLuint, texture);
+        };
+
+        // Creating, labeling, and destroying surfaces and surface textures leaves them alone.
+        let surface = make_surface(&mut env.device, &env.context);
+        check_bindings(&env.gl);
+        env.device
+            .set_surface_label(&env.context, &surface, "test surface")
+            .unwrap();
+        check_bindings(&env.gl);
+        let surface_texture = env
+            .device
+            .create_surface_texture(&mut env.context, surface)
+            .unwrap();
+        check_bindings(&env.gl);
+        let mut surface = env
+            .device
+            .destroy_surface_texture(&mut env.context, surface_texture)
+            .unwrap();
+        env.device
+            .destroy_surface(&mut env.context, &mut surface)
+            .unwrap();
+        check_bindings(&env.gl);
+
+        env.gl.DeleteTextures(1, &texture);
+        env.device.destroy_context(&mut env.context).unwrap();
+    }
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_flush_context() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let mut surface = make_surface(&mut env.device, &env.context);
+    env.device.flush_context(&env.context, &surface).unwrap();
+    check_gl(&env.gl);
+
+    // Surfaces of other contexts are rejected.
+    let mut other_context = env
+        .device
+        .create_context(&env.context_descriptor, None)
+        .unwrap();
+    match env.device.flush_context(&other_context, &surface) {
+        Err(Error::IncompatibleSurface) => {}
+        result => panic!("Expected `IncompatibleSurface`, got {:?}", result),
+    }
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut other_context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_present_watchdog() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    // The watchdog is process-wide, so the timeout is long enough not to trip other tests.
+    let timeouts = Arc::new(Mutex::new(vec![]));
+    crate::set_present_watchdog(Some(PresentWatchdog {
+        timeout: Duration::from_secs(60),
+        on_timeout: Some({
+            let timeouts = timeouts.clone();
+            Arc::new(move |timeout: &PresentTimeout| timeouts.lock().unwrap().push(timeout.clone()))
+        }),
+    }));
+
+    let mut surface = make_surface(&mut env.device, &env.context);
+    DeviceAPI::flush_context(&env.device, &env.context, &surface).unwrap();
+
+    // Watched calls still report their own errors.
+    let mut other_context = env
+        .device
+        .create_context(&env.context_descriptor, None)
+        .unwrap();
+    match DeviceAPI::flush_context(&env.device, &other_context, &surface) {
+        Err(Error::IncompatibleSurface) => {}
+        result => panic!("Expected `IncompatibleSurface`, got {:?}", result),
+    }
+
+    crate::set_present_watchdog(None);
+    assert!(timeouts.lock().unwrap().is_empty());
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut other_context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_surface_attachment_state() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    // The environment starts out with a surface bound.
+    assert!(env.device.context_has_surface(&env.context));
+    assert!(
+        env.device
+            .context_surface_info(&env.context)
+            .unwrap()
+            .unwrap()
+            .bound
+    );
+
+    let mut surface = env
+        .device
+        .unbind_surface_from_context(&mut env.context)
+        .unwrap()
+        .unwrap();
+    assert!(!env.device.context_has_surface(&env.context));
+    assert!(!env.device.surface_is_bound(&surface));
+    assert!(!env.device.surface_info(&surface).bound);
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_surface_visibility() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    // Generic surfaces are never on screen, so their visibility is unknown.
+    let mut surface = make_surface(&mut env.device, &env.context);
+    assert_eq!(
+        env.device.surface_visibility(&surface),
+        SurfaceVisibility::Unknown
+    );
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_make_context_current_with_surface() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    // Render to a second surface without unbinding the first.
+    let mut surface = make_surface(&mut env.device, &env.context);
+    match env
+        .device
+        .make_context_current_with_surface(&env.context, &surface)
+    {
+        Ok(()) => {}
+        // Some backends can only render to unbound widget surfaces.
+        Err(Error::NoWidgetAttached) => {
+            env.device
+                .destroy_surface(&mut env.context, &mut surface)
+                .unwrap();
+            env.device.destroy_context(&mut env.context).unwrap();
+            return;
         }
+        Err(err) => panic!(
+            "Failed to make the context current with a surface: {:?}",
+            err
+        ),
+    }
+    let surface_fbo = env.device.surface_info(&surface).framebuffer_object;
+    unsafe {
+        env.gl.BindFramebuffer(gl::FRAMEBUFFER, surface_fbo);
     }
+    clear(&env.gl, &[0, 255, 0, 255]);
+    assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
 
-    for mut surface in surfaces.into_iter() {
-        device.destroy_surface(&mut context, &mut surface).unwrap();
+    // Making the context current again switches back to the bound surface.
+    env.device.make_context_current(&env.context).unwrap();
+    bind_context_fbo(&env.gl, &env.device, &env.context);
+    clear(&env.gl, &[255, 0, 0, 255]);
+    assert_eq!(get_pixel_from_bottom_row(&env.gl), [255, 0, 0, 255]);
+
+    env.device
+        .make_context_current_with_surface(&env.context, &surface)
+        .unwrap();
+    unsafe {
+        env.gl.BindFramebuffer(gl::FRAMEBUFFER, surface_fbo);
     }
+    assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+    check_gl(&env.gl);
 
-    device.destroy_context(&mut context).unwrap();
+    // Surfaces of other contexts are rejected.
+    let mut other_context = env
+        .device
+        .create_context(&env.context_descriptor, None)
+        .unwrap();
+    match env
+        .device
+        .make_context_current_with_surface(&other_context, &surface)
+    {
+        Err(Error::IncompatibleSurface) => {}
+        result => panic!("Expected `IncompatibleSurface`, got {:?}", result),
+    }
+
+    env.device.make_context_current(&env.context).unwrap();
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut other_context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
 }
 
-// Tests that basic GL commands work.
 #[cfg_attr(not(feature = "sm-test"), test)]
-pub fn test_gl() {
+pub fn test_displays() {
     let mut env = match BasicEnvironment::new() {
         None => return,
         Some(env) => env,
     };
 
-    unsafe {
-        // Check basic clear.
-        env.gl.ClearColor(0.0, 1.0, 0.0, 1.0);
-        env.gl.Clear(gl::COLOR_BUFFER_BIT);
-        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+    // Headless machines may have no displays at all, so only check that what is reported is sane.
+    match env.connection.displays() {
+        Ok(displays) => {
+            for display in displays {
+                assert!(display.size.width > 0 && display.size.height > 0);
+                assert!(display.scale_factor > 0.0);
+                if let Some(refresh_rate) = display.refresh_rate {
+                    assert!(refresh_rate > 0.0);
+                }
+            }
+        }
+        Err(Error::UnsupportedOnThisPlatform) => {}
+        Err(err) => panic!("Failed to enumerate displays: {:?}", err),
+    }
 
-        // Check that GL commands don't work after `make_no_context_current()`.
-        //
-        // The `glGetError()` calls are there to clear any errors.
-        env.device.make_no_context_current().unwrap();
-        env.gl.ClearColor(1.0, 0.0, 0.0, 1.0);
-        env.gl.GetError();
-        env.gl.Clear(gl::COLOR_BUFFER_BIT);
-        env.gl.GetError();
-        env.device.make_context_current(&env.context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
 
-        let framebuffer_object = env
-            .device
-            .context_surface_info(&env.context)
-            .unwrap()
-            .unwrap()
-            .framebuffer_object;
-        env.gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
-        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_adapter_for_display() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
 
-        // Make sure GL commands don't work when no surface is attached.
-        //
-        // The `glGetError()` calls are there to clear any errors.
-        let green_surface = env
-            .device
-            .unbind_surface_from_context(&mut env.context)
-            .unwrap()
-            .unwrap();
-        env.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
-        env.gl.ClearColor(1.0, 0.0, 0.0, 1.0);
-        env.gl.GetError();
-        env.gl.Clear(gl::COLOR_BUFFER_BIT);
-        env.gl.GetError();
-        env.device
-            .bind_surface_to_context(&mut env.context, green_surface)
-            .unwrap();
-        env.gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
-        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+    let displays = match env.connection.displays() {
+        Ok(displays) => displays,
+        Err(Error::UnsupportedOnThisPlatform) => vec![],
+        Err(err) => panic!("Failed to enumerate displays: {:?}", err),
+    };
 
-        // Make sure GL commands go to the right surface.
-        let green_surface = env
-            .device
-            .unbind_surface_from_context(&mut env.context)
-            .unwrap()
-            .unwrap();
-        let red_surface = make_surface(&mut env.device, &env.context);
-        env.device
-            .bind_surface_to_context(&mut env.context, red_surface)
-            .unwrap();
-        let red_framebuffer_object = env
-            .device
-            .context_surface_info(&env.context)
-            .unwrap()
-            .unwrap()
-            .framebuffer_object;
-        env.gl
-            .BindFramebuffer(gl::FRAMEBUFFER, red_framebuffer_object);
-        env.gl.ClearColor(1.0, 0.0, 0.0, 1.0);
-        env.gl.Clear(gl::COLOR_BUFFER_BIT);
-        assert_eq!(get_pixel_from_bottom_row(&env.gl), [255, 0, 0, 255]);
+    // The GPU behind a display can't always be found, but if it is, it must be usable.
+    for display in displays {
+        match env.connection.create_adapter_for_display(&display) {
+            Ok(adapter) => {
+                let mut device = env.connection.create_device(&adapter).unwrap();
+                let context_descriptor = device
+                    .create_context_descriptor(&ContextAttributes {
+                        version: GLVersion::new(3, 0),
+                        max_version: None,
+                        flags: ContextAttributeFlags::empty(),
+                        required_extensions: vec![],
+                        samples: 0,
+                    })
+                    .unwrap();
+                let mut context = device.create_context(&context_descriptor, None).unwrap();
+                device.destroy_context(&mut context).unwrap();
+            }
+            Err(Error::NoAdapterFound) | Err(Error::UnsupportedOnThisPlatform) => {}
+            Err(err) => panic!("Failed to create an adapter for {:?}: {:?}", display, err),
+        }
+    }
 
-        let mut red_surface = env
-            .device
-            .unbind_surface_from_context(&mut env.context)
-            .unwrap()
-            .unwrap();
-        env.device
-            .bind_surface_to_context(&mut env.context, green_surface)
-            .unwrap();
-        env.gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
-        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+    env.device.destroy_context(&mut env.context).unwrap();
+}
 
-        // Clean up.
-        env.device
-            .destroy_surface(&mut env.context, &mut red_surface)
-            .unwrap();
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_init_with_connection() {
+    let connection = Connection::new().unwrap();
+    let options = InitOptions {
+        surface_size: Some(Size2D::new(640, 480)),
+        ..InitOptions::default()
+    };
+    let (_, device, mut context) = match crate::init_with_connection(connection, options) {
+        Ok(objects) => objects,
+        Err(Error::RequiredExtensionUnavailable) => return,
+        Err(err) => panic!("Failed to initialize: {:?}", err),
+    };
+
+    // The context should come back current, with a surface of the requested size bound to it.
+    let surface_info = device.context_surface_info(&context).unwrap().unwrap();
+    assert_eq!(surface_info.size, Size2D::new(640, 480));
+    let gl = Gl::load_with(|symbol| device.get_proc_address(&context, symbol));
+    unsafe {
+        gl.BindFramebuffer(gl::FRAMEBUFFER, surface_info.framebuffer_object);
+        gl.Viewport(0, 0, 640, 480);
     }
+    clear(&gl, &[255, 0, 0, 255]);
+    assert_eq!(get_pixel_from_bottom_row(&gl), [255, 0, 0, 255]);
 
-    env.device.destroy_context(&mut env.context).unwrap();
+    device.destroy_context(&mut context).unwrap();
 }
 
 #[cfg_attr(not(feature = "sm-test"), test)]
@@ -721,7 +2520,239 @@ pub fn test_surface_texture_right_side_up() {
     }
 }
 
-#[cfg(not(target_os = "android"))]
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_surface_texture_mipmaps() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    unsafe {
+        clear(&env.gl, &[0, 255, 0, 255]);
+
+        let green_surface = env
+            .device
+            .unbind_surface_from_context(&mut env.context)
+            .unwrap()
+            .unwrap();
+        let green_surface_texture = env
+            .device
+            .create_surface_texture(&mut env.context, green_surface)
+            .unwrap();
+
+        match env
+            .device
+            .generate_surface_mipmaps(&mut env.context, &green_surface_texture)
+        {
+            Ok(()) => {
+                // The second mip level should have been filled in from the first.
+                let mut framebuffer_object = 0;
+                env.gl.GenFramebuffers(1, &mut framebuffer_object);
+                env.gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
+                env.gl.FramebufferTexture2D(
+                    gl::FRAMEBUFFER,
+                    gl::COLOR_ATTACHMENT0,
+                    env.device.surface_gl_texture_target(),
+                    env.device.surface_texture_object(&green_surface_texture),
+                    1,
+                );
+                assert_eq!(
+                    env.gl.CheckFramebufferStatus(gl::FRAMEBUFFER),
+                    gl::FRAMEBUFFER_COMPLETE
+                );
+                check_gl(&env.gl);
+                assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+
+                env.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+                env.gl.DeleteFramebuffers(1, &framebuffer_object);
+            }
+            Err(Error::UnsupportedOnThisPlatform) => {}
+            Err(err) => panic!("Failed to generate mipmaps: {:?}", err),
+        }
+
+        let mut green_surface = env
+            .device
+            .destroy_surface_texture(&mut env.context, green_surface_texture)
+            .unwrap();
+        env.device
+            .destroy_surface(&mut env.context, &mut green_surface)
+            .unwrap();
+        env.device.destroy_context(&mut env.context).unwrap();
+    }
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_layered_surfaces() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let size = Size2D::new(16, 16);
+    match env.device.create_layered_surface(
+        &env.context,
+        SurfaceAccess::GPUOnly,
+        SurfaceUsage::empty(),
+        &size,
+        0,
+    ) {
+        Err(Error::InvalidSurfaceLayer) | Err(Error::UnsupportedOnThisPlatform) => {}
+        Ok(_) => panic!("Created a layered surface with no layers!"),
+        Err(err) => panic!(
+            "Unexpected error for a layered surface with no layers: {:?}",
+            err
+        ),
+    }
+
+    let mut surface = match env.device.create_layered_surface(
+        &env.context,
+        SurfaceAccess::GPUOnly,
+        SurfaceUsage::empty(),
+        &size,
+        2,
+    ) {
+        Ok(surface) => surface,
+        Err(Error::UnsupportedOnThisPlatform) => {
+            env.device.destroy_context(&mut env.context).unwrap();
+            return;
+        }
+        Err(err) => panic!("Failed to create a layered surface: {:?}", err),
+    };
+    assert_eq!(env.device.surface_layers(&surface), 2);
+
+    // Clear each layer to a color of its own.
+    let framebuffer_object = env.device.surface_info(&surface).framebuffer_object;
+    for &(layer, color) in &[(0, [255, 0, 0, 255]), (1, [0, 255, 0, 255])] {
+        env.device
+            .set_surface_layer(&env.context, &mut surface, layer)
+            .unwrap();
+        unsafe {
+            env.gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
+        }
+        clear(&env.gl, &color);
+    }
+    match env.device.set_surface_layer(&env.context, &mut surface, 2) {
+        Err(Error::InvalidSurfaceLayer) => {}
+        result => panic!("Chose a layer past the last one: {:?}", result),
+    }
+
+    let surface_texture = env
+        .device
+        .create_surface_texture(&mut env.context, surface)
+        .unwrap();
+    assert_eq!(
+        env.device.surface_texture_binding_target(&surface_texture),
+        gl::TEXTURE_2D_ARRAY
+    );
+    assert_eq!(env.device.surface_texture_layers(&surface_texture), 2);
+
+    // Each layer of the surface texture reads the layer of the surface.
+    let texture_object = env.device.surface_texture_object(&surface_texture);
+    unsafe {
+        let mut framebuffer_object = 0;
+        env.gl.GenFramebuffers(1, &mut framebuffer_object);
+        env.gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
+        for &(layer, color) in &[(0, [255, 0, 0, 255]), (1, [0, 255, 0, 255])] {
+            env.gl.FramebufferTextureLayer(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                texture_object,
+                0,
+                layer,
+            );
+            assert_eq!(get_pixel_from_bottom_row(&env.gl), color);
+        }
+        env.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        env.gl.DeleteFramebuffers(1, &framebuffer_object);
+        check_gl(&env.gl);
+    }
+    let mut surface = env
+        .device
+        .destroy_surface_texture(&mut env.context, surface_texture)
+        .unwrap();
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_compositor() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    clear(&env.gl, &[0, 255, 0, 255]);
+
+    let layer_surface = env
+        .device
+        .unbind_surface_from_context(&mut env.context)
+        .unwrap()
+        .unwrap();
+    let layer_surface_texture = env
+        .device
+        .create_surface_texture(&mut env.context, layer_surface)
+        .unwrap();
+
+    let main_surface = make_surface(&mut env.device, &env.context);
+    env.device
+        .bind_surface_to_context(&mut env.context, main_surface)
+        .unwrap();
+
+    // Draw the layer one pixel up, over red, with state set that drawing has to change.
+    let mut compositor = Compositor::new(&env.device, &env.context).unwrap();
+    unsafe {
+        env.gl.ClearColor(0.0, 0.0, 1.0, 1.0);
+        env.gl.Enable(gl::SCISSOR_TEST);
+        env.gl.Scissor(0, 0, 1, 1);
+    }
+    compositor
+        .composite(
+            &env.device,
+            &env.context,
+            &[CompositorLayer {
+                surface_texture: &layer_surface_texture,
+                size: Size2D::new(640, 480),
+                origin: SurfaceOrigin::BottomLeft,
+                transform: Transform2D::create_translation(0.0, 1.0),
+                opacity: 1.0,
+            }],
+            Some([1.0, 0.0, 0.0, 1.0]),
+        )
+        .unwrap();
+    check_gl(&env.gl);
+
+    unsafe {
+        let mut clear_color = [0.0; 4];
+        env.gl
+            .GetFloatv(gl::COLOR_CLEAR_VALUE, clear_color.as_mut_ptr());
+        assert_eq!(clear_color, [0.0, 0.0, 1.0, 1.0]);
+        assert_eq!(env.gl.IsEnabled(gl::SCISSOR_TEST), gl::TRUE);
+        assert_eq!(env.gl.IsEnabled(gl::BLEND), gl::FALSE);
+        env.gl.Disable(gl::SCISSOR_TEST);
+    }
+
+    bind_context_fbo(&env.gl, &env.device, &env.context);
+    assert_eq!(get_pixel_from_bottom_row(&env.gl), [255, 0, 0, 255]);
+    assert_eq!(
+        get_pixel_from_second_from_bottom_row(&env.gl),
+        [0, 255, 0, 255]
+    );
+
+    // Clean up.
+    compositor.destroy();
+    let mut layer_surface = env
+        .device
+        .destroy_surface_texture(&mut env.context, layer_surface_texture)
+        .unwrap();
+    env.device
+        .destroy_surface(&mut env.context, &mut layer_surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg(not(any(target_os = "android", target_env = "ohos")))]
 #[cfg_attr(not(feature = "sm-test"), test)]
 pub fn test_depth_and_stencil() {
     let connection = Connection::new().unwrap();
@@ -747,7 +2778,10 @@ pub fn test_depth_and_stencil() {
     let depth_context_descriptor = device
         .create_context_descriptor(&ContextAttributes {
             version: GLVersion::new(3, 0),
+            max_version: None,
             flags: ContextAttributeFlags::DEPTH,
+            required_extensions: vec![],
+            samples: 0,
         })
         .unwrap();
 
@@ -794,7 +2828,10 @@ pub fn test_depth_and_stencil() {
     let stencil_context_descriptor = device
         .create_context_descriptor(&ContextAttributes {
             version: GLVersion::new(3, 0),
+            max_version: None,
             flags: ContextAttributeFlags::STENCIL,
+            required_extensions: vec![],
+            samples: 0,
         })
         .unwrap();
 
@@ -865,6 +2902,48 @@ pub fn test_get_native_context() {
     env.device.destroy_context(&mut env.context).unwrap();
 }
 
+// Make sure that whatever context is current can be adopted along with its display, and that the
+// adopted context renders where the original does.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_from_current_context() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    unsafe {
+        env.device.make_no_context_current().unwrap();
+        match <Device as DeviceAPI>::from_current_context().err() {
+            Some(Error::NoCurrentContext) => {}
+            other => panic!("Expected `NoCurrentContext`, got {:?}", other),
+        }
+
+        env.device.make_context_current(&env.context).unwrap();
+        clear(&env.gl, &[0, 255, 0, 255]);
+
+        let (device, mut context) = <Device as DeviceAPI>::from_current_context().unwrap();
+        assert_ne!(
+            device.context_id(&context),
+            env.device.context_id(&env.context)
+        );
+        match device.context_surface_info(&context) {
+            Err(Error::ExternalRenderTarget) => {}
+            Err(err) => panic!("Expected `ExternalRenderTarget`, got {:?}", err),
+            Ok(_) => panic!("Expected `ExternalRenderTarget`, got surface info"),
+        }
+
+        device.make_context_current(&context).unwrap();
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+
+        // Destroying the adopted context leaves the original alone.
+        DeviceAPI::destroy_context(&device, &mut context).unwrap();
+        env.device.make_context_current(&env.context).unwrap();
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+    }
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
 fn bind_context_fbo(gl: &Gl, device: &Device, context: &Context) {
     unsafe {
         gl.BindFramebuffer(gl::FRAMEBUFFER, context_fbo(device, context));
@@ -885,6 +2964,8 @@ fn make_surface(device: &mut Device, context: &Context) -> Surface {
         .create_surface(
             &context,
             SurfaceAccess::GPUOnly,
+            SurfaceUsage::default(),
+            SurfaceAlphaMode::default(),
             SurfaceType::Generic {
                 size: Size2D::new(640, 480),
             },
@@ -964,7 +3045,10 @@ impl BasicEnvironment {
         let context_descriptor = device
             .create_context_descriptor(&ContextAttributes {
                 version: GLVersion::new(3, 0),
+                max_version: None,
                 flags: ContextAttributeFlags::empty(),
+                required_extensions: vec![],
+                samples: 0,
             })
             .unwrap();
 
@@ -0,0 +1,59 @@
+// surfman/surfman/src/timings.rs
+//
+//! How long it took to set up a device and its contexts.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long the steps of setting up a device and its contexts took, as returned by
+/// `Device::creation_timings()`.
+///
+/// Slow startup is usually spent in one of these steps: opening the GPU, choosing a config or
+/// pixel format, or creating the context in the driver. The times of the last two add up over
+/// every call, failed calls included, while the counts only cover calls that succeeded.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CreationTimings {
+    /// How long opening the device on its adapter took, in `Connection::create_device()` or
+    /// `Connection::create_device_from_native_device()`.
+    pub device_open: Duration,
+    /// The total time spent choosing configs or pixel formats in
+    /// `Device::create_context_descriptor()`.
+    ///
+    /// Descriptors are cached, so asking again for the same attributes adds next to nothing.
+    pub config_selection: Duration,
+    /// The number of context descriptors created.
+    pub context_descriptors_created: u32,
+    /// The total time spent creating contexts in `Device::create_context()`, including checking
+    /// for required extensions.
+    pub context_creation: Duration,
+    /// The number of contexts created.
+    pub contexts_created: u32,
+}
+
+// Accumulates the timings of a device. Clones share them, as clones of a device do.
+#[derive(Clone, Default)]
+pub(crate) struct CreationTimingsRecorder(Arc<Mutex<CreationTimings>>);
+
+impl CreationTimingsRecorder {
+    pub(crate) fn get(&self) -> CreationTimings {
+        *self.0.lock().unwrap()
+    }
+
+    // Adds to the timings after a step has run.
+    pub(crate) fn record<F>(&self, update: F)
+    where
+        F: FnOnce(&mut CreationTimings),
+    {
+        update(&mut self.0.lock().unwrap())
+    }
+}
+
+// Runs a step, returning its result along with how long it took.
+pub(crate) fn time<T, F>(step: F) -> (T, Duration)
+where
+    F: FnOnce() -> T,
+{
+    let start = Instant::now();
+    let result = step();
+    (result, start.elapsed())
+}
@@ -0,0 +1,214 @@
+// surfman/surfman/src/watchdog.rs
+//
+//! A watchdog that catches presents and fence waits that never finish, as when the driver
+//! deadlocks or the compositor freezes.
+
+use crate::{Error, SurfaceID};
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Reports presents and fence waits that run past a timeout.
+///
+/// Install one for the whole process with `set_present_watchdog()`. A thread of its own checks on
+/// the watched calls, so `on_timeout` runs while the stuck call is still blocked, and can recover,
+/// by restarting the process for instance, even if the call never returns. If the call does
+/// return, it fails with `Error::PresentTimedOut`, although what it did has taken effect.
+///
+/// Presents are watched, along with the fence waits of `flush_context()`.
+#[derive(Clone)]
+pub struct PresentWatchdog {
+    /// How long a call may block before it counts as stuck.
+    pub timeout: Duration,
+    /// Called, on the watchdog's thread, once for each call that runs past the timeout.
+    pub on_timeout: Option<PresentTimeoutHandler>,
+}
+
+/// A function that the watchdog calls, on its own thread, for each call that runs past its timeout.
+pub type PresentTimeoutHandler = Arc<dyn Fn(&PresentTimeout) + Send + Sync>;
+
+/// Which kind of call the watchdog caught.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchedOperation {
+    /// Presenting a widget surface.
+    Present,
+    /// Waiting on a fence for rendering to a surface to finish.
+    FenceWait,
+}
+
+/// What the watchdog knows about a call that ran past its timeout.
+#[derive(Clone, Debug)]
+pub struct PresentTimeout {
+    /// The kind of call.
+    pub operation: WatchedOperation,
+    /// The surface that was being presented or waited on.
+    pub surface_id: SurfaceID,
+    /// How long the call had been blocked when the watchdog caught it.
+    pub elapsed: Duration,
+    /// The name of the thread that made the call, if it has one.
+    pub thread_name: Option<String>,
+}
+
+// Checked by callers before they watch a call, so that watching costs a single atomic load when
+// no watchdog is installed.
+static WATCHDOG_INSTALLED: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    static ref WATCHDOG: Watchdog = Watchdog {
+        state: Mutex::new(WatchdogState {
+            config: None,
+            watches: vec![],
+            next_watch_id: 0,
+            thread_started: false,
+        }),
+        changed: Condvar::new(),
+    };
+}
+
+struct Watchdog {
+    state: Mutex<WatchdogState>,
+    // Signaled when a watch is added, so that the thread can wait for an earlier deadline.
+    changed: Condvar,
+}
+
+struct WatchdogState {
+    config: Option<PresentWatchdog>,
+    watches: Vec<Watch>,
+    next_watch_id: u64,
+    thread_started: bool,
+}
+
+struct Watch {
+    id: u64,
+    start: Instant,
+    deadline: Instant,
+    operation: WatchedOperation,
+    surface_id: SurfaceID,
+    thread_name: Option<String>,
+    expired: Arc<AtomicBool>,
+}
+
+/// Installs the watchdog for presents and fence waits for the whole process, replacing any
+/// previous one.
+///
+/// Pass `None` to remove the watchdog. By default no watchdog is installed and calls aren't
+/// watched.
+pub fn set_present_watchdog(watchdog: Option<PresentWatchdog>) {
+    let mut state = WATCHDOG.state.lock().unwrap();
+    WATCHDOG_INSTALLED.store(watchdog.is_some(), Ordering::Release);
+    state.config = watchdog;
+}
+
+// Runs a call under the watchdog, if one is installed. A call that runs past the timeout fails
+// with `PresentTimedOut`, unless it failed for another reason.
+pub(crate) fn watch<T, F>(
+    operation: WatchedOperation,
+    surface_id: SurfaceID,
+    call: F,
+) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    if !WATCHDOG_INSTALLED.load(Ordering::Acquire) {
+        return call();
+    }
+
+    let (watch_id, expired) = {
+        let mut state = WATCHDOG.state.lock().unwrap();
+        let timeout = match state.config {
+            Some(ref config) => config.timeout,
+            None => return call(),
+        };
+        let watch_id = state.next_watch_id;
+        state.next_watch_id += 1;
+        let expired = Arc::new(AtomicBool::new(false));
+        let start = Instant::now();
+        state.watches.push(Watch {
+            id: watch_id,
+            start,
+            deadline: start + timeout,
+            operation,
+            surface_id,
+            thread_name: thread::current().name().map(|name| name.to_owned()),
+            expired: expired.clone(),
+        });
+        if !state.thread_started {
+            thread::Builder::new()
+                .name("surfman watchdog".to_owned())
+                .spawn(run)
+                .unwrap();
+            state.thread_started = true;
+        }
+        WATCHDOG.changed.notify_one();
+        (watch_id, expired)
+    };
+
+    let result = call();
+
+    WATCHDOG
+        .state
+        .lock()
+        .unwrap()
+        .watches
+        .retain(|watch| watch.id != watch_id);
+    if expired.load(Ordering::Acquire) {
+        result.and(Err(Error::PresentTimedOut))
+    } else {
+        result
+    }
+}
+
+// The watchdog's thread, which sleeps until the earliest deadline of the calls being watched.
+fn run() {
+    let mut state = WATCHDOG.state.lock().unwrap();
+    loop {
+        let now = Instant::now();
+        let mut timeouts = vec![];
+        for watch in &state.watches {
+            if watch.deadline <= now && !watch.expired.swap(true, Ordering::AcqRel) {
+                timeouts.push(PresentTimeout {
+                    operation: watch.operation,
+                    surface_id: watch.surface_id,
+                    elapsed: now - watch.start,
+                    thread_name: watch.thread_name.clone(),
+                });
+            }
+        }
+
+        if !timeouts.is_empty() {
+            let on_timeout = state
+                .config
+                .as_ref()
+                .and_then(|config| config.on_timeout.clone());
+            // Don't hold the lock while calling out, so that the handler can use surfman.
+            drop(state);
+            for timeout in &timeouts {
+                error!(
+                    "{:?} of surface {:?} timed out!",
+                    timeout.operation, timeout.surface_id
+                );
+                if let Some(ref on_timeout) = on_timeout {
+                    on_timeout(timeout);
+                }
+            }
+            state = WATCHDOG.state.lock().unwrap();
+            continue;
+        }
+
+        let next_deadline = state
+            .watches
+            .iter()
+            .filter(|watch| !watch.expired.load(Ordering::Acquire))
+            .map(|watch| watch.deadline)
+            .min();
+        state = match next_deadline {
+            Some(deadline) => {
+                let timeout = deadline.saturating_duration_since(now);
+                WATCHDOG.changed.wait_timeout(state, timeout).unwrap().0
+            }
+            None => WATCHDOG.changed.wait(state).unwrap(),
+        };
+    }
+}
@@ -0,0 +1,96 @@
+// surfman/surfman/src/wgpu.rs
+//
+//! Sharing surfaces with wgpu, through the OpenGL ES backend of `wgpu-hal` only.
+//!
+//! `expose_adapter()` hands a surfman context to `wgpu-hal` as an external OpenGL ES adapter, so
+//! that wgpu renders with that context and sees its objects. Surface textures of the context can
+//! then be adopted as `wgpu-hal` textures with `texture_from_surface_texture()`, and from there
+//! wrapped in wgpu textures with `wgpu::Device::create_texture_from_hal()`, sharing their storage
+//! with no copies.
+//!
+//! This only works with wgpu's OpenGL ES backend, and only where `wgpu-hal` drives it through EGL:
+//! Linux and Android. wgpu devices on the Vulkan, Metal or Direct3D 12 backends can't adopt
+//! surface textures this way, as nothing here exports surface memory to them. To hand images to
+//! a Vulkan renderer, see the `external_objects` module instead.
+
+use crate::device::Device;
+use crate::Error;
+
+use euclid::default::Size2D;
+use std::num::NonZeroU32;
+use wgpu_hal::api::Gles;
+use wgpu_hal::gles::{Adapter, Device as HalDevice, Texture, TextureInner};
+use wgpu_hal::{ExposedAdapter, MemoryFlags, TextureDescriptor, TextureUses};
+use wgpu_types::{Extent3d, TextureDimension, TextureFormat};
+
+/// Exposes a context to `wgpu-hal` as an OpenGL ES adapter.
+///
+/// Returns `None` if `wgpu-hal` can't use the context, for instance because its OpenGL version is
+/// too old.
+///
+/// # Safety
+///
+/// The context must be current, and must stay current whenever `wgpu-hal` uses the adapter or the
+/// device opened from it. It must outlive both.
+pub unsafe fn expose_adapter<D>(device: &D, context: &D::Context) -> Option<ExposedAdapter<Gles>>
+where
+    D: Device,
+{
+    Adapter::new_external(|symbol_name| device.get_proc_address(context, symbol_name))
+}
+
+/// Adopts a surface texture as a texture of a `wgpu-hal` device, so that wgpu can sample from or
+/// copy out of it.
+///
+/// `size` is that of the surface that the texture was created from. The returned texture has the
+/// `Rgba8Unorm` format, as shaders see the contents of surfaces as RGBA whatever the order of their
+/// native storage, and the usages `RESOURCE` and `COPY_SRC`. It has as many array layers as the
+/// surface, and is bound to the target that `Device::surface_texture_binding_target()` reports.
+/// The wgpu texture that wraps it must be created with a matching descriptor.
+///
+/// Returns `Error::IncompatibleSurfaceTexture` if the surface texture has no OpenGL texture
+/// object that `wgpu-hal` could adopt.
+///
+/// # Safety
+///
+/// The `wgpu-hal` device must have been opened from an adapter exposed with `expose_adapter()`
+/// for the context that the surface texture belongs to. The returned texture doesn't own the
+/// OpenGL texture: the surface texture must outlive it, and must still be destroyed with
+/// `Device::destroy_surface_texture()` once wgpu is done with it.
+pub unsafe fn texture_from_surface_texture<D>(
+    device: &D,
+    hal_device: &HalDevice,
+    surface_texture: &D::SurfaceTexture,
+    size: &Size2D<i32>,
+) -> Result<Texture, Error>
+where
+    D: Device,
+{
+    let descriptor = TextureDescriptor {
+        label: Some("surfman surface texture"),
+        size: Extent3d {
+            width: size.width as u32,
+            height: size.height as u32,
+            depth_or_array_layers: device.surface_texture_layers(surface_texture),
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: TextureFormat::Rgba8Unorm,
+        usage: TextureUses::RESOURCE | TextureUses::COPY_SRC,
+        memory_flags: MemoryFlags::empty(),
+        view_formats: vec![],
+    };
+
+    // A drop guard tells `wgpu-hal` that it doesn't own the texture.
+    let texture_object = NonZeroU32::new(device.surface_texture_object(surface_texture))
+        .ok_or(Error::IncompatibleSurfaceTexture)?;
+    let mut texture = hal_device.texture_from_raw(texture_object, &descriptor, Some(Box::new(())));
+
+    // `wgpu-hal` picks the target from the descriptor, but surface textures may be rectangle or
+    // external textures.
+    if let TextureInner::Texture { ref mut target, .. } = texture.inner {
+        *target = device.surface_texture_binding_target(surface_texture);
+    }
+    Ok(texture)
+}